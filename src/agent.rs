@@ -0,0 +1,232 @@
+//! Agent mode (`--mode agent`): reads the journal, applies the ingest settings and
+//! forwards every entry to a livedata server's `[peer_input]`, without a local
+//! database or web UI. Entries pass through a small on-disk [`Spool`] so a network
+//! outage or a restart loses nothing the spool has room for.
+//!
+//! The journal cursor of the last spooled entry is kept next to the spool, so reading
+//! resumes where it stopped.
+
+use crate::config::Settings;
+use crate::journal_reader::JournalLogReader;
+use crate::log_entry::LogEntry;
+use crate::outputs::{Rejected, Throttled};
+use crate::peer_replication::{PeerSettings, PeerSink};
+use crate::spool::Spool;
+use crate::systemd_notify::SystemdNotifier;
+use anyhow::{Context, Result, anyhow};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const JOURNAL_CURSOR_FILE: &str = "journal_cursor";
+/// Most journal entries read before the spool is written
+const READ_CHUNK: usize = 1000;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(1);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// Where an agent sends its entries (`[agent]` table)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AgentSettings {
+    /// The server's `[peer_input]`, with the same keys as a `peer` output
+    #[serde(flatten)]
+    pub server: PeerSettings,
+
+    /// Spool directory; defaults to `spool` in the data directory
+    #[serde(default)]
+    pub spool_dir: Option<PathBuf>,
+
+    /// Most bytes spooled; beyond that the oldest undelivered entries are dropped
+    #[serde(default = "default_spool_max_bytes")]
+    pub spool_max_bytes: u64,
+
+    /// Most entries sent at once
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+}
+
+fn default_spool_max_bytes() -> u64 {
+    256 * 1024 * 1024
+}
+
+fn default_batch_size() -> usize {
+    500
+}
+
+/// Forwards the journal to the server until SIGINT or SIGTERM
+pub struct Agent {
+    reader: JournalLogReader,
+    spool: Spool,
+    sink: PeerSink,
+    runtime: tokio::runtime::Runtime,
+    batch_size: usize,
+    dropped_fields: HashSet<String>,
+    cursor_path: PathBuf,
+    shutdown_signal: Arc<AtomicBool>,
+}
+
+impl Agent {
+    pub fn new(data_dir: &str, settings: &Settings) -> Result<Self> {
+        let agent = settings
+            .agent
+            .as_ref()
+            .ok_or_else(|| anyhow!("agent mode needs an [agent] table in the config"))?;
+        let spool_dir = agent
+            .spool_dir
+            .clone()
+            .unwrap_or_else(|| Path::new(data_dir).join("spool"));
+        Ok(Self {
+            reader: JournalLogReader::new()?,
+            spool: Spool::open(&spool_dir, agent.spool_max_bytes)?,
+            sink: PeerSink::new(&agent.server, REQUEST_TIMEOUT)
+                .context("Failed to set up the connection to the server")?,
+            runtime: tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()?,
+            batch_size: agent.batch_size.max(1),
+            dropped_fields: settings.dropped_fields.iter().cloned().collect(),
+            cursor_path: spool_dir.join(JOURNAL_CURSOR_FILE),
+            shutdown_signal: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    /// Run until shut down. Without a saved journal cursor, the whole journal is
+    /// forwarded, or with `follow` only what is logged from now on.
+    pub fn run(&mut self, follow: bool) -> Result<()> {
+        signal_hook::flag::register(signal_hook::consts::SIGINT, self.shutdown_signal.clone())?;
+        signal_hook::flag::register(signal_hook::consts::SIGTERM, self.shutdown_signal.clone())?;
+
+        match fs::read_to_string(&self.cursor_path) {
+            Ok(cursor) if !cursor.trim().is_empty() => {
+                self.reader.seek_after_cursor(cursor.trim())?
+            }
+            _ if follow => {
+                self.reader.seek_to_tail()?;
+                self.reader.previous_skip(1)?;
+            }
+            _ => self.reader.seek_to_head()?,
+        }
+        info!("Agent forwarding the journal to the server");
+        let mut systemd = SystemdNotifier::from_env();
+        systemd.ready("Forwarding the journal");
+
+        let mut retry_at: Option<Instant> = None;
+        let mut retry_delay = INITIAL_RETRY_DELAY;
+        while !self.shutdown_signal.load(Ordering::Relaxed) {
+            systemd.watchdog();
+            systemd.status(|| format!("{} bytes spooled", self.spool.size()));
+            let spooled = self.spool_journal()?;
+
+            let mut sent = false;
+            if retry_at.is_none_or(|at| Instant::now() >= at) {
+                let batch: Vec<Arc<LogEntry>> = self
+                    .spool
+                    .read_batch(self.batch_size)?
+                    .into_iter()
+                    .map(Arc::new)
+                    .collect();
+                if !batch.is_empty() {
+                    match self.runtime.block_on(self.sink.send(&batch)) {
+                        Ok(()) => {
+                            self.spool.acknowledge()?;
+                            retry_at = None;
+                            retry_delay = INITIAL_RETRY_DELAY;
+                            sent = true;
+                        }
+                        Err(e) if e.is::<Rejected>() => {
+                            warn!(
+                                "Server rejected {} entries, dropping them: {:#}",
+                                batch.len(),
+                                e
+                            );
+                            self.spool.acknowledge()?;
+                        }
+                        Err(e) => {
+                            let delay = e
+                                .downcast_ref::<Throttled>()
+                                .map_or(retry_delay, |throttled| throttled.retry_after);
+                            warn!(
+                                "Failed to forward {} entries ({:#}), {} bytes spooled, \
+                                 retrying in {:?}",
+                                batch.len(),
+                                e,
+                                self.spool.size(),
+                                delay
+                            );
+                            retry_at = Some(Instant::now() + delay);
+                            retry_delay = (retry_delay * 2).min(MAX_RETRY_DELAY);
+                        }
+                    }
+                }
+            }
+
+            if spooled == 0 && !sent {
+                thread::sleep(Duration::from_millis(100));
+            }
+        }
+
+        systemd.stopping();
+        info!(
+            "Agent stopped with {} bytes spooled ({} entries dropped while the spool was full)",
+            self.spool.size(),
+            self.spool.dropped()
+        );
+        Ok(())
+    }
+
+    /// Move newly logged journal entries into the spool, returning how many
+    fn spool_journal(&mut self) -> Result<usize> {
+        let mut entries = Vec::new();
+        while entries.len() < READ_CHUNK
+            && let Ok(Some(mut entry)) = self.reader.next_log_entry()
+        {
+            entry
+                .fields
+                .retain(|field, _| !self.dropped_fields.contains(field));
+            entries.push(entry);
+        }
+        self.spool.append(&entries)?;
+        // Saved after the entries are spooled, so a crash in between sends them twice
+        // rather than never; the server stores them once
+        if let Some(cursor) = entries.last().and_then(|entry| entry.get_field("__CURSOR")) {
+            fs::write(&self.cursor_path, cursor)?;
+        }
+        Ok(entries.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_agent_settings() {
+        let settings: Settings = toml::from_str(
+            r#"
+log_retention_days = 30
+log_max_size_gb = 1.0
+process_retention_days = 7
+process_max_size_gb = 0.5
+
+[agent]
+url = "https://aggregator:9440"
+cert_file = "/etc/livedata/agent.pem"
+key_file = "/etc/livedata/agent-key.pem"
+ca_file = "/etc/livedata/ca.pem"
+spool_max_bytes = 1048576
+"#,
+        )
+        .unwrap();
+        let agent = settings.agent.unwrap();
+        assert_eq!(agent.server.url, "https://aggregator:9440");
+        assert_eq!(agent.spool_max_bytes, 1 << 20);
+        assert_eq!(agent.batch_size, 500);
+        assert_eq!(agent.spool_dir, None);
+    }
+}
@@ -0,0 +1,1124 @@
+//! Alert rules over the journal: `[[alert_rules]]` from the config file plus rules
+//! stored through the API. Each counts the entries matching a search query and/or SQL
+//! condition over a trailing window, and fires while the count is above its threshold.
+//! A background thread evaluates every rule on its own interval and reloads the stored
+//! rules when they change; state changes are stored in the alerts table, which also
+//! restores which rules were firing after a restart. The same thread evaluates the
+//! `[[heartbeat_rules]]`, which fire when a unit or host goes quiet, and optionally
+//! notifies of systemd unit failures as they are recorded.
+
+use crate::config::{AlertRule, HeartbeatRule, parse_duration};
+use crate::duckdb_buffer::{ALERTS_TABLE, DuckDBBuffer, LOG_COUNTS_TABLE, SqlParam};
+use crate::notifications::{AlertEvent, AlertState, Notifier};
+use crate::read_pool::{PooledReader, ReadPool};
+use crate::search_query::SearchQuery;
+use crate::unit_failures::{UNIT_FAILURE_ALERT_SOURCE, UNIT_FAILURE_LOOKBACK, UnitFailure};
+use anyhow::{Context, Result};
+use chrono::{DateTime, TimeDelta, Utc};
+use log::{error, info, warn};
+use reqwest::Url;
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Source of the alerts raised by log rules
+pub const LOG_ALERT_SOURCE: &str = "log";
+
+/// Source of the alerts raised by heartbeat rules
+pub const HEARTBEAT_ALERT_SOURCE: &str = "heartbeat";
+
+/// Most matching messages sent along with a firing alert
+const ALERT_SAMPLES: usize = 5;
+
+/// Longest window, silence or interval a rule may use
+const MAX_RULE_DURATION: TimeDelta = TimeDelta::days(31);
+
+/// A rule ready to evaluate
+struct CompiledRule {
+    rule: AlertRule,
+    /// Predicate over journal_logs with its bind parameters
+    predicate: String,
+    params: Vec<SqlParam>,
+    window: TimeDelta,
+    interval: TimeDelta,
+    next_due: DateTime<Utc>,
+    firing: bool,
+}
+
+impl CompiledRule {
+    fn new(rule: &AlertRule, schema: &[(String, String)], now: DateTime<Utc>) -> Result<Self> {
+        let query = SearchQuery::parse(&rule.query, schema).map_err(anyhow::Error::msg)?;
+        let mut predicates = Vec::new();
+        let mut params = Vec::new();
+        if let Some((sql, query_params)) = query.to_sql() {
+            predicates.push(sql);
+            params = query_params;
+        }
+        if let Some(sql) = rule.sql.as_ref().filter(|sql| !sql.trim().is_empty()) {
+            predicates.push(format!("({})", sql));
+        }
+        let window = parse_duration(&rule.window).context("window")?;
+        let interval = parse_duration(&rule.interval).context("interval")?;
+        anyhow::ensure!(
+            window > TimeDelta::zero() && interval > TimeDelta::zero(),
+            "window and interval must be positive"
+        );
+        anyhow::ensure!(
+            window <= MAX_RULE_DURATION && interval <= MAX_RULE_DURATION,
+            "window and interval must be at most {} days",
+            MAX_RULE_DURATION.num_days()
+        );
+        Ok(Self {
+            rule: rule.clone(),
+            predicate: if predicates.is_empty() {
+                "TRUE".to_string()
+            } else {
+                predicates.join(" AND ")
+            },
+            params,
+            window,
+            interval,
+            next_due: now,
+            firing: false,
+        })
+    }
+
+    /// Entries matching the rule in the window ending at `now`, with the latest
+    /// messages among them when `samples` is set
+    fn measure(
+        &self,
+        readers: &ReadPool,
+        buffer: &Mutex<DuckDBBuffer>,
+        now: DateTime<Utc>,
+        samples: bool,
+    ) -> Result<(u64, Vec<String>)> {
+        let start = now - self.window;
+        let source = buffer.lock().unwrap().log_source(start, now);
+        let filter = format!(
+            "FROM {} WHERE timestamp >= ? AND timestamp < ? AND {}",
+            source.sql, self.predicate
+        );
+        let mut params = vec![
+            SqlParam::Text(start.to_rfc3339()),
+            SqlParam::Text(now.to_rfc3339()),
+        ];
+        params.extend(self.params.iter().cloned());
+        let count = readers
+            .get()
+            .query_json_rows(
+                &format!("SELECT COUNT(*) {}", filter),
+                &params,
+                &["count".to_string()],
+            )?
+            .first()
+            .and_then(|row| row["count"].as_u64())
+            .unwrap_or(0);
+        if !samples || count == 0 {
+            return Ok((count, Vec::new()));
+        }
+        let sql = format!(
+            "SELECT message {} ORDER BY timestamp DESC LIMIT {}",
+            filter, ALERT_SAMPLES
+        );
+        let samples = readers
+            .get()
+            .query_json_rows(&sql, &params, &["message".to_string()])
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|row| row["message"].as_str().map(str::to_string))
+            .collect();
+        Ok((count, samples))
+    }
+
+    fn event(
+        &self,
+        now: DateTime<Utc>,
+        state: AlertState,
+        message: String,
+        count: u64,
+        samples: Vec<String>,
+        public_url: Option<&str>,
+    ) -> AlertEvent {
+        AlertEvent {
+            timestamp: now,
+            rule: self.rule.name.clone(),
+            source: LOG_ALERT_SOURCE.to_string(),
+            state,
+            value: count as f64,
+            threshold: self.rule.threshold as f64,
+            message,
+            samples,
+            link: public_url
+                .and_then(|base| search_link(base, &self.rule.query, now - self.window, now)),
+            tags: self.rule.tags.clone(),
+            details: BTreeMap::new(),
+        }
+        .with_details(json!({
+            "query": self.rule.query,
+            "sql": self.rule.sql,
+            "window": self.rule.window,
+            "interval": self.rule.interval,
+        }))
+    }
+}
+
+/// A heartbeat rule ready to evaluate
+struct Heartbeat {
+    rule: HeartbeatRule,
+    max_silence: TimeDelta,
+    interval: TimeDelta,
+    /// When watching began; silence before then is not counted
+    since: DateTime<Utc>,
+    next_due: DateTime<Utc>,
+    firing: bool,
+}
+
+impl Heartbeat {
+    fn new(rule: &HeartbeatRule, now: DateTime<Utc>) -> Result<Self> {
+        anyhow::ensure!(
+            rule.unit.is_some() || rule.hostname.is_some(),
+            "unit or hostname is required"
+        );
+        let max_silence = parse_duration(&rule.max_silence).context("max_silence")?;
+        let interval = parse_duration(&rule.interval).context("interval")?;
+        anyhow::ensure!(
+            max_silence > TimeDelta::zero() && interval > TimeDelta::zero(),
+            "max_silence and interval must be positive"
+        );
+        anyhow::ensure!(
+            max_silence <= MAX_RULE_DURATION && interval <= MAX_RULE_DURATION,
+            "max_silence and interval must be at most {} days",
+            MAX_RULE_DURATION.num_days()
+        );
+        Ok(Self {
+            rule: rule.clone(),
+            max_silence,
+            interval,
+            since: now,
+            next_due: now,
+            firing: false,
+        })
+    }
+
+    /// End of the latest minute with entries from the unit and/or host, read from the
+    /// per-minute counts (so it only reaches back over the hot partitions)
+    fn last_seen(&self, readers: &ReadPool) -> Result<Option<DateTime<Utc>>> {
+        let mut conditions = Vec::new();
+        let mut params = Vec::new();
+        if let Some(unit) = &self.rule.unit {
+            conditions.push("_SYSTEMD_UNIT = ?");
+            params.push(SqlParam::Text(unit.clone()));
+        }
+        if let Some(hostname) = &self.rule.hostname {
+            conditions.push("_HOSTNAME = ?");
+            params.push(SqlParam::Text(hostname.clone()));
+        }
+        let sql = format!(
+            "SELECT epoch_us(MAX(timestamp) + INTERVAL 1 MINUTE) AS last_seen FROM {}
+             WHERE {} AND count > 0",
+            LOG_COUNTS_TABLE,
+            conditions.join(" AND ")
+        );
+        Ok(readers
+            .get()
+            .query_json_rows(&sql, &params, &["last_seen".to_string()])?
+            .first()
+            .and_then(|row| row["last_seen"].as_i64())
+            .and_then(DateTime::from_timestamp_micros))
+    }
+
+    /// What the rule watches, e.g. "unit backup.service on db1"
+    fn subject(&self) -> String {
+        match (&self.rule.unit, &self.rule.hostname) {
+            (Some(unit), Some(hostname)) => format!("unit {} on {}", unit, hostname),
+            (Some(unit), None) => format!("unit {}", unit),
+            (None, Some(hostname)) => format!("host {}", hostname),
+            (None, None) => "nothing".to_string(),
+        }
+    }
+
+    /// Search query matching what the rule watches
+    fn query(&self) -> String {
+        let mut terms = Vec::new();
+        if let Some(unit) = &self.rule.unit {
+            terms.push(format!("unit:{}", unit));
+        }
+        if let Some(hostname) = &self.rule.hostname {
+            terms.push(format!("host:{}", hostname));
+        }
+        terms.join(" ")
+    }
+
+    fn event(
+        &self,
+        now: DateTime<Utc>,
+        state: AlertState,
+        message: String,
+        silent: TimeDelta,
+        last_seen: Option<DateTime<Utc>>,
+        public_url: Option<&str>,
+    ) -> AlertEvent {
+        AlertEvent {
+            timestamp: now,
+            rule: self.rule.name.clone(),
+            source: HEARTBEAT_ALERT_SOURCE.to_string(),
+            state,
+            value: silent.num_seconds() as f64 / 60.0,
+            threshold: self.max_silence.num_seconds() as f64 / 60.0,
+            message,
+            samples: Vec::new(),
+            link: public_url
+                .and_then(|base| search_link(base, &self.query(), now - self.max_silence * 2, now)),
+            tags: self.rule.tags.clone(),
+            details: BTreeMap::new(),
+        }
+        .with_details(json!({
+            "unit": self.rule.unit,
+            "hostname": self.rule.hostname,
+            "max_silence": self.rule.max_silence,
+            "last_seen": last_seen,
+        }))
+    }
+}
+
+/// Check that `rule` parses, has usable durations and only a plain SQL condition
+pub fn validate_rule(rule: &AlertRule, reader: &PooledReader<'_>) -> Result<()> {
+    anyhow::ensure!(!rule.name.trim().is_empty(), "name must not be empty");
+    anyhow::ensure!(
+        !rule.tags.iter().any(|tag| tag.contains(',')),
+        "tags must not contain commas"
+    );
+    CompiledRule::new(rule, &reader.get_schema_columns(), Utc::now())?;
+    check_sql_condition(rule, reader)
+}
+
+/// Check that the rule's SQL condition is an expression over the row alone. The
+/// readers share the writer's database, which can read and write files, so subqueries
+/// (and with them table functions such as `read_text`) and further statements are
+/// refused.
+fn check_sql_condition(rule: &AlertRule, reader: &PooledReader<'_>) -> Result<()> {
+    let Some(sql) = rule.sql.as_ref().filter(|sql| !sql.trim().is_empty()) else {
+        return Ok(());
+    };
+    let rows = reader.query_json_rows(
+        "SELECT CAST(json_serialize_sql(CAST(? AS VARCHAR)) AS VARCHAR)",
+        &[SqlParam::Text(format!(
+            "SELECT 1 FROM journal_logs WHERE ({})",
+            sql
+        ))],
+        &["tree".to_string()],
+    )?;
+    let tree: serde_json::Value = rows
+        .first()
+        .and_then(|row| row["tree"].as_str())
+        .map(serde_json::from_str)
+        .transpose()?
+        .unwrap_or_default();
+    if tree["error"].as_bool().unwrap_or(true) {
+        anyhow::bail!(
+            "sql: {}",
+            tree["error_message"]
+                .as_str()
+                .unwrap_or("not a valid condition")
+        );
+    }
+    let statements = tree["statements"].as_array().map_or(&[][..], Vec::as_slice);
+    anyhow::ensure!(
+        statements.len() == 1 && statements[0]["node"]["type"] == "SELECT_NODE",
+        "sql must be a single condition"
+    );
+    anyhow::ensure!(
+        !contains_subquery(&statements[0]["node"]["where_clause"]),
+        "sql must not contain subqueries"
+    );
+    Ok(())
+}
+
+/// Whether a serialized expression has a subquery anywhere below it
+fn contains_subquery(node: &serde_json::Value) -> bool {
+    match node {
+        serde_json::Value::Object(fields) => {
+            fields.get("class").is_some_and(|class| class == "SUBQUERY")
+                || fields.values().any(contains_subquery)
+        }
+        serde_json::Value::Array(items) => items.iter().any(contains_subquery),
+        _ => false,
+    }
+}
+
+/// Evaluate `rule` now and report the result as a firing alert, whether or not it is
+/// over its threshold, for checking a rule and the notifiers it reaches
+pub fn test_alert(
+    rule: &AlertRule,
+    buffer: &Mutex<DuckDBBuffer>,
+    readers: &ReadPool,
+    public_url: Option<&str>,
+) -> Result<AlertEvent> {
+    let now = Utc::now();
+    let schema = buffer.lock().unwrap().get_schema_columns();
+    let compiled = CompiledRule::new(rule, &schema, now)?;
+    let (count, samples) = compiled.measure(readers, buffer, now, true)?;
+    let message = format!(
+        "Test alert: {} entries matched in the last {} (threshold {})",
+        count, rule.window, rule.threshold
+    );
+    Ok(compiled.event(now, AlertState::Firing, message, count, samples, public_url))
+}
+
+/// Evaluates log alert rules, storing and sending the alerts they raise
+pub struct AlertScheduler {
+    /// Rules from the config file; stored rules with the same name are ignored
+    config_rules: Vec<AlertRule>,
+    /// Rules from a reloaded config file, replacing `config_rules` on the next reload
+    replaced_config_rules: Arc<Mutex<Option<Vec<AlertRule>>>>,
+    rules: Vec<CompiledRule>,
+    /// Stored rules version the compiled rules were loaded at
+    rules_version: Option<u64>,
+    readers: ReadPool,
+    buffer: Arc<Mutex<DuckDBBuffer>>,
+    notifier: Notifier,
+    heartbeats: Vec<Heartbeat>,
+    /// Base URL of the web UI that alert links point into
+    public_url: Option<String>,
+    /// Unit failures with a higher id are yet to be notified of (None when unit
+    /// failure alerts are off)
+    unit_failures_after: Option<i64>,
+    /// Unit failure count of the buffer when last checked for new failures
+    unit_failures_seen: u64,
+    /// Unit failures from before this are recorded without notice, such as the ones
+    /// the startup backfill loads
+    unit_failures_since: DateTime<Utc>,
+}
+
+impl AlertScheduler {
+    pub fn new(
+        rules: &[AlertRule],
+        buffer: Arc<Mutex<DuckDBBuffer>>,
+        notifier: Notifier,
+    ) -> Result<Self> {
+        let readers = buffer.lock().unwrap().read_pool(1)?;
+        let mut scheduler = Self {
+            config_rules: rules.to_vec(),
+            replaced_config_rules: Arc::new(Mutex::new(None)),
+            rules: Vec::new(),
+            rules_version: None,
+            readers,
+            buffer,
+            notifier,
+            heartbeats: Vec::new(),
+            public_url: None,
+            unit_failures_after: None,
+            unit_failures_seen: 0,
+            unit_failures_since: Utc::now(),
+        };
+        scheduler.reload_rules()?;
+        Ok(scheduler)
+    }
+
+    pub fn with_public_url(mut self, public_url: Option<String>) -> Self {
+        self.public_url = public_url;
+        self
+    }
+
+    /// Slot the config file's rules can be replaced through while the scheduler runs;
+    /// the rules left in it are compiled within a second
+    pub fn config_rules_slot(&self) -> Arc<Mutex<Option<Vec<AlertRule>>>> {
+        self.replaced_config_rules.clone()
+    }
+
+    /// Evaluate `rules` as well, resuming the ones firing before a restart. Rules that
+    /// are unusable are logged and left out.
+    pub fn with_heartbeat_rules(mut self, rules: &[HeartbeatRule]) -> Result<Self> {
+        let now = Utc::now();
+        let firing = if rules.is_empty() {
+            HashSet::new()
+        } else {
+            firing_rules(&self.readers, HEARTBEAT_ALERT_SOURCE)?
+        };
+        for rule in rules {
+            match Heartbeat::new(rule, now) {
+                Ok(mut heartbeat) => {
+                    heartbeat.firing = firing.contains(&rule.name);
+                    self.heartbeats.push(heartbeat);
+                }
+                Err(e) => warn!("Ignoring heartbeat rule {}: {:#}", rule.name, e),
+            }
+        }
+        Ok(self)
+    }
+
+    /// Notify of unit failures that happen from now on. Failures read afterwards
+    /// from the journal's past, by the startup or history backfill, are not notified
+    /// of.
+    pub fn with_unit_failure_alerts(mut self, enabled: bool) -> Result<Self> {
+        if enabled {
+            let buffer = self.buffer.lock().unwrap();
+            self.unit_failures_seen = buffer.unit_failures_recorded();
+            self.unit_failures_after = Some(buffer.latest_unit_failure_id()?);
+            self.unit_failures_since = Utc::now();
+        }
+        Ok(self)
+    }
+
+    /// Notify of the unit failures recorded since the last call, returning the alerts
+    /// raised. They are not stored as alerts: a failure has no state to resolve, and
+    /// the unit_failures table already records it.
+    pub fn notify_unit_failures(&mut self) -> Result<Vec<AlertEvent>> {
+        let Some(after) = self.unit_failures_after else {
+            return Ok(Vec::new());
+        };
+        let failures = {
+            let mut buffer = self.buffer.lock().unwrap();
+            let recorded = buffer.unit_failures_recorded();
+            if recorded == self.unit_failures_seen {
+                return Ok(Vec::new());
+            }
+            self.unit_failures_seen = recorded;
+            buffer.unit_failures_after(after)?
+        };
+        let Some(last) = failures.last() else {
+            return Ok(Vec::new());
+        };
+        self.unit_failures_after = Some(last.id);
+        let alerts: Vec<AlertEvent> = failures
+            .iter()
+            .filter(|failure| failure.timestamp >= self.unit_failures_since)
+            .map(|failure| unit_failure_alert(failure, self.public_url.as_deref()))
+            .collect();
+        if !alerts.is_empty() {
+            self.notifier.notify(&alerts);
+        }
+        Ok(alerts)
+    }
+
+    /// Recompile the config and stored rules if either changed, keeping the state of
+    /// rules that remain. Rules that fail to parse are logged and left out.
+    fn reload_rules(&mut self) -> Result<()> {
+        let replaced = self.replaced_config_rules.lock().unwrap().take();
+        let config_changed = replaced.is_some();
+        if let Some(rules) = replaced {
+            self.config_rules = rules;
+        }
+        let (version, stored, schema) = {
+            let mut buffer = self.buffer.lock().unwrap();
+            let version = buffer.alert_rules_version();
+            if self.rules_version == Some(version) && !config_changed {
+                return Ok(());
+            }
+            (
+                version,
+                buffer.stored_alert_rules()?,
+                buffer.get_schema_columns(),
+            )
+        };
+        let config_names: HashSet<&str> =
+            self.config_rules.iter().map(|r| r.name.as_str()).collect();
+        let stored = stored.iter().filter(|rule| {
+            let clash = config_names.contains(rule.name.as_str());
+            if clash {
+                warn!(
+                    "Ignoring stored alert rule {}: a configured rule has the same name",
+                    rule.name
+                );
+            }
+            !clash
+        });
+
+        let now = Utc::now();
+        let firing = if self.rules_version.is_none() {
+            firing_rules(&self.readers, LOG_ALERT_SOURCE)?
+        } else {
+            HashSet::new()
+        };
+        let mut previous: HashMap<String, CompiledRule> = self
+            .rules
+            .drain(..)
+            .map(|rule| (rule.rule.name.clone(), rule))
+            .collect();
+        // Stored rules were checked when saved; check them again in case they were
+        // saved by an older version
+        let reader = self.readers.get();
+        let stored = stored.filter(|rule| match check_sql_condition(rule, &reader) {
+            Ok(()) => true,
+            Err(e) => {
+                warn!("Ignoring stored alert rule {}: {:#}", rule.name, e);
+                false
+            }
+        });
+        let stored: Vec<&AlertRule> = stored.collect();
+        drop(reader);
+        for rule in self.config_rules.iter().chain(stored) {
+            let mut compiled = match CompiledRule::new(rule, &schema, now) {
+                Ok(compiled) => compiled,
+                Err(e) => {
+                    warn!("Ignoring alert rule {}: {:#}", rule.name, e);
+                    continue;
+                }
+            };
+            match previous.remove(&rule.name) {
+                Some(old) => {
+                    compiled.firing = old.firing;
+                    compiled.next_due = old.next_due.min(now + compiled.interval);
+                }
+                None => compiled.firing = firing.contains(&rule.name),
+            }
+            self.rules.push(compiled);
+        }
+        if self.rules_version.is_some() {
+            info!("Reloaded alert rules: {} active", self.rules.len());
+        }
+        self.rules_version = Some(version);
+        Ok(())
+    }
+
+    /// Evaluate the log and heartbeat rules due at `now`, returning the alerts raised
+    pub fn evaluate_due(&mut self, now: DateTime<Utc>) -> Vec<AlertEvent> {
+        let mut alerts = Vec::new();
+        for rule in self.rules.iter_mut().filter(|rule| rule.next_due <= now) {
+            rule.next_due = now + rule.interval;
+            let (count, samples) =
+                match rule.measure(&self.readers, &self.buffer, now, !rule.firing) {
+                    Ok(measured) => measured,
+                    Err(e) => {
+                        error!("Failed to evaluate alert rule {}: {}", rule.rule.name, e);
+                        continue;
+                    }
+                };
+
+            let over = count > rule.rule.threshold;
+            if over == rule.firing {
+                continue;
+            }
+            rule.firing = over;
+            let (state, message, samples) = if over {
+                (
+                    AlertState::Firing,
+                    format!(
+                        "{} entries matched in the last {} (threshold {})",
+                        count, rule.rule.window, rule.rule.threshold
+                    ),
+                    samples,
+                )
+            } else {
+                (
+                    AlertState::Resolved,
+                    format!("{} entries matched in the last {}", count, rule.rule.window),
+                    Vec::new(),
+                )
+            };
+            alerts.push(rule.event(
+                now,
+                state,
+                message,
+                count,
+                samples,
+                self.public_url.as_deref(),
+            ));
+        }
+
+        for heartbeat in self.heartbeats.iter_mut().filter(|h| h.next_due <= now) {
+            heartbeat.next_due = now + heartbeat.interval;
+            let last_seen = match heartbeat.last_seen(&self.readers) {
+                Ok(last_seen) => last_seen,
+                Err(e) => {
+                    error!(
+                        "Failed to evaluate heartbeat rule {}: {}",
+                        heartbeat.rule.name, e
+                    );
+                    continue;
+                }
+            };
+            let silent = now - last_seen.unwrap_or(heartbeat.since).max(heartbeat.since);
+            let quiet = silent > heartbeat.max_silence;
+            if quiet == heartbeat.firing {
+                continue;
+            }
+            heartbeat.firing = quiet;
+            let (state, message) = if quiet {
+                let seen = match last_seen {
+                    Some(last_seen) => format!("last seen {}", last_seen.to_rfc3339()),
+                    None => "none seen recently".to_string(),
+                };
+                (
+                    AlertState::Firing,
+                    format!(
+                        "No entries from {} for over {} ({})",
+                        heartbeat.subject(),
+                        heartbeat.rule.max_silence,
+                        seen
+                    ),
+                )
+            } else {
+                (
+                    AlertState::Resolved,
+                    format!("Entries from {} are arriving again", heartbeat.subject()),
+                )
+            };
+            alerts.push(heartbeat.event(
+                now,
+                state,
+                message,
+                silent,
+                last_seen,
+                self.public_url.as_deref(),
+            ));
+        }
+
+        if !alerts.is_empty() {
+            if let Err(e) = self.buffer.lock().unwrap().add_alert_events(&alerts) {
+                error!("Failed to persist alerts: {}", e);
+            }
+            self.notifier.notify(&alerts);
+        }
+        alerts
+    }
+
+    /// Evaluate rules as they fall due until `shutdown_signal` is set
+    pub fn spawn(mut self, shutdown_signal: Arc<AtomicBool>) -> thread::JoinHandle<()> {
+        thread::spawn(move || {
+            info!(
+                "Alert scheduler started with {} rules and {} heartbeat rules",
+                self.rules.len(),
+                self.heartbeats.len()
+            );
+            while !shutdown_signal.load(Ordering::Relaxed) {
+                if let Err(e) = self.reload_rules() {
+                    error!("Failed to reload alert rules: {}", e);
+                }
+                self.evaluate_due(Utc::now());
+                if let Err(e) = self.notify_unit_failures() {
+                    error!("Failed to check for unit failures: {}", e);
+                }
+                thread::sleep(Duration::from_millis(500));
+            }
+            info!("Alert scheduler shutting down");
+        })
+    }
+}
+
+/// Search page of the web UI at `base` showing `query` between `start` and `end`
+fn search_link(
+    base: &str,
+    query: &str,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> Option<String> {
+    let base = format!("{}/", base.trim_end_matches('/'));
+    let mut params = vec![("start", start.to_rfc3339()), ("end", end.to_rfc3339())];
+    if !query.is_empty() {
+        params.insert(0, ("q", query.to_string()));
+    }
+    Url::parse_with_params(&base, &params)
+        .map(String::from)
+        .ok()
+}
+
+/// Alert about a unit failure, with the unit's last lines as samples and a link to
+/// the unit's entries leading up to it
+fn unit_failure_alert(failure: &UnitFailure, public_url: Option<&str>) -> AlertEvent {
+    let link = public_url.and_then(|base| {
+        search_link(
+            base,
+            &format!("unit:{}", failure.unit),
+            failure.timestamp - UNIT_FAILURE_LOOKBACK,
+            failure.timestamp + TimeDelta::minutes(1),
+        )
+    });
+    AlertEvent {
+        timestamp: failure.timestamp,
+        rule: failure.unit.clone(),
+        source: UNIT_FAILURE_ALERT_SOURCE.to_string(),
+        state: AlertState::Firing,
+        value: 1.0,
+        threshold: 0.0,
+        message: format!(
+            "{} failed on {} ({})",
+            failure.unit,
+            failure.hostname.as_deref().unwrap_or("unknown host"),
+            failure.result
+        ),
+        samples: failure
+            .last_lines
+            .iter()
+            .map(|line| line.message.clone())
+            .collect(),
+        link,
+        tags: Vec::new(),
+        details: BTreeMap::new(),
+    }
+    .with_details(json!({
+        "unit": failure.unit,
+        "hostname": failure.hostname,
+        "result": failure.result,
+        "invocation_id": failure.invocation_id,
+    }))
+}
+
+/// Rules of `source` whose latest stored alert is a firing one
+fn firing_rules(readers: &ReadPool, source: &str) -> Result<HashSet<String>> {
+    let sql = format!(
+        "SELECT rule FROM {} WHERE source = ?
+         GROUP BY rule HAVING arg_max(state, timestamp) = ?",
+        ALERTS_TABLE
+    );
+    let rows = readers.get().query_json_rows(
+        &sql,
+        &[
+            SqlParam::Text(source.to_string()),
+            SqlParam::Text(AlertState::Firing.as_str().to_string()),
+        ],
+        &["rule".to_string()],
+    )?;
+    Ok(rows
+        .iter()
+        .filter_map(|row| row["rule"].as_str().map(str::to_string))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::log_entry::LogEntry;
+    use std::collections::HashMap;
+
+    fn entry(timestamp: DateTime<Utc>, unit: &str, priority: i32) -> LogEntry {
+        let mut fields = HashMap::new();
+        fields.insert("MESSAGE".to_string(), "upstream timed out".to_string());
+        fields.insert("_SYSTEMD_UNIT".to_string(), unit.to_string());
+        fields.insert("PRIORITY".to_string(), priority.to_string());
+        LogEntry::new(timestamp, fields)
+    }
+
+    #[test]
+    fn test_rule_fires_and_resolves_and_survives_restart() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let buffer = Arc::new(Mutex::new(DuckDBBuffer::new(temp_dir.path()).unwrap()));
+        let now = Utc::now();
+        {
+            let mut buffer = buffer.lock().unwrap();
+            let entries = (0..3)
+                .map(|i| entry(now - TimeDelta::seconds(10 + i), "nginx.service", 3))
+                .chain([entry(now - TimeDelta::seconds(5), "nginx.service", 6)])
+                .chain([entry(now - TimeDelta::minutes(20), "nginx.service", 3)]);
+            for entry in entries {
+                buffer.add_entry(&entry).unwrap();
+            }
+        }
+        let rules = [
+            AlertRule {
+                name: "nginx errors".to_string(),
+                query: "unit:nginx.service priority<=err".to_string(),
+                threshold: 2,
+                window: "5m".to_string(),
+                interval: "1m".to_string(),
+                ..Default::default()
+            },
+            AlertRule {
+                name: "bad query".to_string(),
+                query: r#""unterminated"#.to_string(),
+                window: "5m".to_string(),
+                interval: "1m".to_string(),
+                ..Default::default()
+            },
+        ];
+
+        let mut scheduler =
+            AlertScheduler::new(&rules, buffer.clone(), Notifier::default()).unwrap();
+        assert_eq!(scheduler.rules.len(), 1);
+        let alerts = scheduler.evaluate_due(now + TimeDelta::seconds(1));
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].state, AlertState::Firing);
+        assert_eq!(alerts[0].value, 3.0);
+        assert_eq!(alerts[0].samples.len(), 3);
+        assert_eq!(alerts[0].link, None);
+        // Not due again until the interval passes
+        assert!(
+            scheduler
+                .evaluate_due(now + TimeDelta::seconds(30))
+                .is_empty()
+        );
+
+        // A restarted scheduler knows the rule is firing and only reports it resolving
+        let mut scheduler = AlertScheduler::new(&rules, buffer.clone(), Notifier::default())
+            .unwrap()
+            .with_public_url(Some("https://logs.example.com/".to_string()));
+        let alerts = scheduler.evaluate_due(now + TimeDelta::minutes(10));
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].state, AlertState::Resolved);
+        assert_eq!(alerts[0].value, 0.0);
+        let link = alerts[0].link.as_deref().unwrap();
+        assert!(link.starts_with(
+            "https://logs.example.com/?q=unit%3Anginx.service+priority%3C%3Derr&start="
+        ));
+    }
+
+    #[test]
+    fn test_stored_rules_are_reloaded() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let buffer = Arc::new(Mutex::new(DuckDBBuffer::new(temp_dir.path()).unwrap()));
+        let now = Utc::now();
+        buffer
+            .lock()
+            .unwrap()
+            .add_entry(&entry(now - TimeDelta::seconds(5), "cron.service", 3))
+            .unwrap();
+        let config_rule = AlertRule {
+            name: "any errors".to_string(),
+            query: "priority<=err".to_string(),
+            threshold: 10,
+            window: "5m".to_string(),
+            interval: "1m".to_string(),
+            ..Default::default()
+        };
+        let mut scheduler = AlertScheduler::new(
+            std::slice::from_ref(&config_rule),
+            buffer.clone(),
+            Notifier::default(),
+        )
+        .unwrap();
+        assert_eq!(scheduler.rules.len(), 1);
+
+        let cron_rule = AlertRule {
+            name: "cron errors".to_string(),
+            query: "unit:cron.service".to_string(),
+            threshold: 0,
+            window: "5m".to_string(),
+            interval: "1m".to_string(),
+            tags: vec!["batch".to_string()],
+            ..Default::default()
+        };
+        {
+            let mut buffer = buffer.lock().unwrap();
+            buffer.save_alert_rule(&cron_rule).unwrap();
+            // Config rules win over stored ones of the same name
+            buffer
+                .save_alert_rule(&AlertRule {
+                    threshold: 0,
+                    ..config_rule.clone()
+                })
+                .unwrap();
+            assert_eq!(buffer.stored_alert_rules().unwrap().len(), 2);
+        }
+        scheduler.reload_rules().unwrap();
+        assert_eq!(scheduler.rules.len(), 2);
+        let alerts = scheduler.evaluate_due(now + TimeDelta::seconds(1));
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].rule, "cron errors");
+        assert_eq!(alerts[0].tags, ["batch"]);
+
+        // Deleting the rule drops it, and deleting it again finds nothing
+        assert!(
+            buffer
+                .lock()
+                .unwrap()
+                .delete_alert_rule("cron errors")
+                .unwrap()
+        );
+        scheduler.reload_rules().unwrap();
+        assert_eq!(scheduler.rules.len(), 1);
+        assert!(
+            !buffer
+                .lock()
+                .unwrap()
+                .delete_alert_rule("cron errors")
+                .unwrap()
+        );
+
+        let readers = buffer.lock().unwrap().read_pool(1).unwrap();
+        let test = test_alert(&cron_rule, &buffer, &readers, None).unwrap();
+        assert_eq!(test.state, AlertState::Firing);
+        assert_eq!(test.value, 1.0);
+        assert!(test.message.starts_with("Test alert"));
+
+        // Without the config rule (a reloaded config dropped it), the stored rule of
+        // the same name takes its place
+        *scheduler.config_rules_slot().lock().unwrap() = Some(Vec::new());
+        scheduler.reload_rules().unwrap();
+        assert_eq!(scheduler.rules.len(), 1);
+        assert_eq!(scheduler.rules[0].rule.threshold, 0);
+    }
+
+    #[test]
+    fn test_heartbeat_fires_when_quiet() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let buffer = Arc::new(Mutex::new(DuckDBBuffer::new(temp_dir.path()).unwrap()));
+        let now = Utc::now();
+        let backup_entry = |timestamp| {
+            let mut entry = entry(timestamp, "backup.service", 6);
+            entry
+                .fields
+                .insert("_HOSTNAME".to_string(), "db1".to_string());
+            entry
+        };
+        {
+            let mut buffer = buffer.lock().unwrap();
+            buffer
+                .add_entry(&backup_entry(now - TimeDelta::hours(2)))
+                .unwrap();
+            buffer.flush_log_counts().unwrap();
+        }
+        let rules = [
+            HeartbeatRule {
+                name: "nightly backup".to_string(),
+                unit: Some("backup.service".to_string()),
+                hostname: Some("db1".to_string()),
+                max_silence: "1h".to_string(),
+                interval: "1m".to_string(),
+                tags: Vec::new(),
+            },
+            HeartbeatRule {
+                name: "web1 quiet".to_string(),
+                hostname: Some("web1".to_string()),
+                max_silence: "1h".to_string(),
+                interval: "1m".to_string(),
+                ..Default::default()
+            },
+            HeartbeatRule {
+                name: "watches nothing".to_string(),
+                max_silence: "1h".to_string(),
+                ..Default::default()
+            },
+        ];
+        let mut scheduler = AlertScheduler::new(&[], buffer.clone(), Notifier::default())
+            .unwrap()
+            .with_heartbeat_rules(&rules)
+            .unwrap();
+        assert_eq!(scheduler.heartbeats.len(), 2);
+
+        // Silence before the scheduler started counts only once it has watched as long
+        assert!(scheduler.evaluate_due(now).is_empty());
+        let alerts = scheduler.evaluate_due(now + TimeDelta::minutes(61));
+        assert_eq!(alerts.len(), 2);
+        assert_eq!(alerts[0].source, HEARTBEAT_ALERT_SOURCE);
+        assert_eq!(alerts[0].state, AlertState::Firing);
+        assert!(
+            alerts[0]
+                .message
+                .starts_with("No entries from unit backup.service on db1 for over 1h (last seen")
+        );
+        assert_eq!(
+            alerts[1].message,
+            "No entries from host web1 for over 1h (none seen recently)"
+        );
+
+        {
+            let mut buffer = buffer.lock().unwrap();
+            buffer
+                .add_entry(&backup_entry(now + TimeDelta::minutes(62)))
+                .unwrap();
+            buffer.flush_log_counts().unwrap();
+        }
+        let alerts = scheduler.evaluate_due(now + TimeDelta::minutes(63));
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].rule, "nightly backup");
+        assert_eq!(alerts[0].state, AlertState::Resolved);
+
+        // Firing heartbeats resume firing after a restart
+        let restarted = AlertScheduler::new(&[], buffer.clone(), Notifier::default())
+            .unwrap()
+            .with_heartbeat_rules(&rules)
+            .unwrap();
+        let firing: Vec<&str> = restarted
+            .heartbeats
+            .iter()
+            .filter(|h| h.firing)
+            .map(|h| h.rule.name.as_str())
+            .collect();
+        assert_eq!(firing, ["web1 quiet"]);
+    }
+
+    #[test]
+    fn test_unit_failures_are_notified() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let buffer = Arc::new(Mutex::new(DuckDBBuffer::new(temp_dir.path()).unwrap()));
+        let journal_entry = |timestamp: DateTime<Utc>, fields: &[(&str, &str)]| {
+            LogEntry::new(
+                timestamp,
+                fields
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect(),
+            )
+        };
+        let failure = |timestamp, invocation| {
+            journal_entry(
+                timestamp,
+                &[
+                    ("_PID", "1"),
+                    ("_HOSTNAME", "web1"),
+                    ("MESSAGE", "nginx.service: Failed with result 'exit-code'."),
+                    ("MESSAGE_ID", crate::unit_failures::UNIT_FAILED_MESSAGE_ID),
+                    ("UNIT", "nginx.service"),
+                    ("UNIT_RESULT", "exit-code"),
+                    ("INVOCATION_ID", invocation),
+                ],
+            )
+        };
+        let before = Utc::now() - TimeDelta::seconds(5);
+        {
+            let mut buffer = buffer.lock().unwrap();
+            buffer.add_entry(&failure(before, "abc")).unwrap();
+            assert_eq!(buffer.unit_failures_recorded(), 1);
+        }
+        let mut scheduler = AlertScheduler::new(&[], buffer.clone(), Notifier::default())
+            .unwrap()
+            .with_public_url(Some("http://logs.example.com".to_string()))
+            .with_unit_failure_alerts(true)
+            .unwrap();
+        // Failures from before the scheduler started are not notified of, including
+        // ones the backfill reads after it started
+        assert!(scheduler.notify_unit_failures().unwrap().is_empty());
+        {
+            let mut buffer = buffer.lock().unwrap();
+            buffer
+                .add_entry(&failure(before + TimeDelta::seconds(1), "older"))
+                .unwrap();
+            assert_eq!(buffer.unit_failures_recorded(), 2);
+        }
+        assert!(scheduler.notify_unit_failures().unwrap().is_empty());
+
+        let now = Utc::now();
+        {
+            let mut buffer = buffer.lock().unwrap();
+            for (millis, message) in [(1, "bind() to 0.0.0.0:80 failed"), (2, "exiting")] {
+                buffer
+                    .add_entry(&journal_entry(
+                        now + TimeDelta::milliseconds(millis),
+                        &[
+                            ("_SYSTEMD_UNIT", "nginx.service"),
+                            ("_SYSTEMD_INVOCATION_ID", "def"),
+                            ("_HOSTNAME", "web1"),
+                            ("MESSAGE", message),
+                        ],
+                    ))
+                    .unwrap();
+            }
+            buffer
+                .add_entry(&failure(now + TimeDelta::milliseconds(3), "def"))
+                .unwrap();
+        }
+        let alerts = scheduler.notify_unit_failures().unwrap();
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].rule, "nginx.service");
+        assert_eq!(alerts[0].source, UNIT_FAILURE_ALERT_SOURCE);
+        assert_eq!(
+            alerts[0].message,
+            "nginx.service failed on web1 (exit-code)"
+        );
+        assert_eq!(
+            alerts[0].samples,
+            ["bind() to 0.0.0.0:80 failed", "exiting"]
+        );
+        assert!(
+            alerts[0]
+                .link
+                .as_ref()
+                .unwrap()
+                .contains("unit%3Anginx.service")
+        );
+        assert!(scheduler.notify_unit_failures().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,302 @@
+//! Client for the web API of a running `livedata web`, used by the command-line tools
+//! (`livedata search`, `livedata tail`, `livedata export`) to read what the collector
+//! stores while it holds the database. Requests go through the same handlers, filters
+//! and access checks as the web UI, with the credentials from the config.
+
+use crate::config::Settings;
+use anyhow::{Context, Result, bail};
+use chrono::NaiveDateTime;
+use reqwest::header::ACCEPT;
+use reqwest::{RequestBuilder, Response, Url};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::io::Write;
+use std::time::Duration;
+
+/// Where `livedata web` listens unless told otherwise
+pub const DEFAULT_PORT: u16 = 3000;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Longest a JSON answer may take, above the web server's default query timeout
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+/// Longest a download or event stream may go without sending anything; event streams
+/// send a keep-alive every 15 seconds
+const READ_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+enum Credentials {
+    Token(String),
+    Basic(String, String),
+}
+
+pub struct ApiClient {
+    base_url: Url,
+    credentials: Option<Credentials>,
+    http: reqwest::Client,
+    runtime: tokio::runtime::Runtime,
+}
+
+impl ApiClient {
+    /// Client for the web server at `url`, by default the one on this machine,
+    /// authenticating with the web token or basic auth user in `settings`
+    pub fn new(url: Option<&str>, settings: &Settings) -> Result<Self> {
+        let base_url = match url {
+            Some(url) => url.to_string(),
+            None => {
+                let scheme = if settings.tls_paths()?.is_some() {
+                    "https"
+                } else {
+                    "http"
+                };
+                format!("{}://127.0.0.1:{}", scheme, DEFAULT_PORT)
+            }
+        };
+        let base_url =
+            Url::parse(&base_url).with_context(|| format!("invalid URL {}", base_url))?;
+        let credentials = match (&settings.web_token, settings.basic_auth()?) {
+            (Some(token), _) => Some(Credentials::Token(token.clone())),
+            (None, Some((user, password))) => {
+                Some(Credentials::Basic(user.to_string(), password.to_string()))
+            }
+            (None, None) => None,
+        };
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        // Downloads and event streams run as long as they keep sending, so only JSON
+        // requests get an overall timeout
+        let http = reqwest::Client::builder()
+            .connect_timeout(CONNECT_TIMEOUT)
+            .read_timeout(READ_IDLE_TIMEOUT)
+            .build()?;
+        Ok(Self {
+            base_url,
+            credentials,
+            http,
+            runtime,
+        })
+    }
+
+    /// GET `path` with `query` and decode the JSON answer
+    pub fn get_json<T: DeserializeOwned>(&self, path: &str, query: &[(&str, String)]) -> Result<T> {
+        let request = self.get(path, query)?.timeout(REQUEST_TIMEOUT);
+        self.runtime
+            .block_on(async { Ok(self.send(request).await?.json().await?) })
+    }
+
+    /// GET `path` with `query` and write the answer to `dest` as it arrives, returning
+    /// the bytes written
+    pub fn download(
+        &self,
+        path: &str,
+        query: &[(&str, String)],
+        dest: &mut impl Write,
+    ) -> Result<u64> {
+        let request = self.get(path, query)?;
+        self.runtime.block_on(async {
+            let mut response = self.send(request).await?;
+            let mut written = 0;
+            while let Some(chunk) = response.chunk().await? {
+                dest.write_all(&chunk)?;
+                written += chunk.len() as u64;
+            }
+            dest.flush()?;
+            Ok(written)
+        })
+    }
+
+    /// GET the server-sent events at `path`, passing each event's name and JSON data
+    /// to `on_event` until the server ends the stream or `on_event` returns false
+    pub fn stream_events(
+        &self,
+        path: &str,
+        query: &[(&str, String)],
+        mut on_event: impl FnMut(&str, Value) -> bool,
+    ) -> Result<()> {
+        let request = self.get(path, query)?.header(ACCEPT, "text/event-stream");
+        self.runtime.block_on(async {
+            let mut response = self.send(request).await?;
+            let mut pending = Vec::new();
+            while let Some(chunk) = response.chunk().await? {
+                pending.extend_from_slice(&chunk);
+                // Events end with a blank line; the rest waits for the next chunk
+                while let Some(end) = pending.windows(2).position(|w| w == b"\n\n") {
+                    let block: Vec<u8> = pending.drain(..end + 2).collect();
+                    let (name, data) = parse_event(&String::from_utf8_lossy(&block));
+                    if data.is_empty() {
+                        continue;
+                    }
+                    let data = serde_json::from_str(&data)
+                        .with_context(|| format!("unreadable {} event", name))?;
+                    if !on_event(&name, data) {
+                        return Ok(());
+                    }
+                }
+            }
+            Ok(())
+        })
+    }
+
+    fn get(&self, path: &str, query: &[(&str, String)]) -> Result<RequestBuilder> {
+        let request = self.http.get(self.base_url.join(path)?).query(query);
+        Ok(match &self.credentials {
+            Some(Credentials::Token(token)) => request.bearer_auth(token),
+            Some(Credentials::Basic(user, password)) => request.basic_auth(user, Some(password)),
+            None => request,
+        })
+    }
+
+    /// Send `request`, turning an error status into an error carrying the server's
+    /// explanation
+    async fn send(&self, request: RequestBuilder) -> Result<Response> {
+        let response = request.send().await.with_context(|| {
+            format!(
+                "connecting to {} (is `livedata web` running?)",
+                self.base_url
+            )
+        })?;
+        let status = response.status();
+        if !status.is_success() {
+            let path = response.url().path().to_string();
+            let body = response.text().await.unwrap_or_default();
+            bail!("{} answered {}: {}", path, status, body.trim());
+        }
+        Ok(response)
+    }
+}
+
+/// Name and data of a server-sent event block; comment and unknown lines are ignored
+fn parse_event(block: &str) -> (String, String) {
+    let mut name = "message".to_string();
+    let mut data = Vec::new();
+    for line in block.lines() {
+        if let Some(value) = line.strip_prefix("event:") {
+            name = value.trim().to_string();
+        } else if let Some(value) = line.strip_prefix("data:") {
+            data.push(value.strip_prefix(' ').unwrap_or(value));
+        }
+    }
+    (name, data.join("\n"))
+}
+
+/// Render a search result row like `journalctl`'s short output: time, host,
+/// identifier[pid]: message. Columns besides those follow as KEY=value.
+pub fn format_short(row: &Value) -> String {
+    let text = |key: &str| match row.get(key) {
+        None | Some(Value::Null) => None,
+        Some(Value::String(s)) => Some(s.clone()),
+        Some(other) => Some(other.to_string()),
+    };
+    let mut line = String::new();
+    if let Some(timestamp) = text("timestamp") {
+        let parsed = NaiveDateTime::parse_from_str(&timestamp, "%Y-%m-%d %H:%M:%S%.f");
+        match parsed {
+            Ok(time) => line.push_str(&time.format("%b %d %H:%M:%S").to_string()),
+            Err(_) => line.push_str(&timestamp),
+        }
+        line.push(' ');
+    }
+    if let Some(hostname) = text("hostname") {
+        line.push_str(&hostname);
+        line.push(' ');
+    }
+    if let Some(identifier) = text("comm").or_else(|| text("unit")) {
+        line.push_str(&identifier);
+        if let Some(pid) = text("pid") {
+            line.push_str(&format!("[{}]", pid));
+        }
+        line.push_str(": ");
+    }
+    line.push_str(&text("message").unwrap_or_default());
+
+    const SHOWN: [&str; 7] = [
+        "timestamp",
+        "hostname",
+        "comm",
+        "unit",
+        "pid",
+        "message",
+        "priority",
+    ];
+    if let Some(row) = row.as_object() {
+        for (key, value) in row.iter().filter(|(key, _)| !SHOWN.contains(&key.as_str())) {
+            if !value.is_null() {
+                let value = value
+                    .as_str()
+                    .map_or_else(|| value.to_string(), str::to_string);
+                line.push_str(&format!(" {}={}", key, value));
+            }
+        }
+    }
+    line
+}
+
+/// `line` in the colours journalctl uses for `priority`: red for errors and worse
+/// (bold from critical up), yellow for warnings, bold for notices, grey for debug
+pub fn colorize(line: &str, priority: Option<u64>) -> String {
+    let code = match priority {
+        Some(0..=2) => "1;31",
+        Some(3) => "31",
+        Some(4) => "33",
+        Some(5) => "1",
+        Some(7) => "90",
+        _ => return line.to_string(),
+    };
+    format!("\x1b[{}m{}\x1b[0m", code, line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_format_short() {
+        let row = json!({
+            "timestamp": "2026-10-17 09:05:01.123456",
+            "hostname": "web1",
+            "unit": "nginx.service",
+            "priority": 6,
+            "pid": "812",
+            "comm": "nginx",
+            "message": "started",
+            "_transport": "journal",
+        });
+        assert_eq!(
+            format_short(&row),
+            "Oct 17 09:05:01 web1 nginx[812]: started _transport=journal"
+        );
+
+        let row = json!({"unit": "cron.service", "pid": null, "message": "tick"});
+        assert_eq!(format_short(&row), "cron.service: tick");
+
+        assert_eq!(colorize("boom", Some(3)), "\x1b[31mboom\x1b[0m");
+        assert_eq!(colorize("fine", Some(6)), "fine");
+        assert_eq!(colorize("fine", None), "fine");
+    }
+
+    #[test]
+    fn test_parse_event() {
+        let block = ": keep-alive\nevent: results\ndata: {\"results\":\ndata: []}\n\n";
+        assert_eq!(
+            parse_event(block),
+            ("results".to_string(), "{\"results\":\n[]}".to_string())
+        );
+        assert_eq!(
+            parse_event("data:{}\n\n"),
+            ("message".to_string(), "{}".to_string())
+        );
+    }
+
+    #[test]
+    fn test_default_url_follows_tls() {
+        let mut settings = Settings::default();
+        let client = ApiClient::new(None, &settings).unwrap();
+        assert_eq!(client.base_url.as_str(), "http://127.0.0.1:3000/");
+
+        settings.tls_cert_path = Some("cert.pem".into());
+        settings.tls_key_path = Some("key.pem".into());
+        let client = ApiClient::new(None, &settings).unwrap();
+        assert_eq!(client.base_url.scheme(), "https");
+        assert!(ApiClient::new(Some("not a url"), &settings).is_err());
+    }
+}
@@ -1,36 +1,134 @@
-use crate::config::Settings;
+use crate::alerting::AlertScheduler;
+use crate::archive::ObjectStoreArchive;
+use crate::config::{AlertRule, RELOADABLE_SETTINGS, RetentionRule, SettingChange, Settings};
+use crate::control::{self, ControlCommand, ControlRequest, start_control_server};
 use crate::duckdb_buffer::DuckDBBuffer;
-use crate::journal_reader::JournalLogReader;
-use crate::log_entry::LogEntry;
-use crate::process_monitor::{ProcessMetricsBatch, ProcessMonitor};
-use anyhow::Result;
-use chrono::{TimeDelta, Utc};
+use crate::entry_workers::{EntryWorkers, READ_BATCH};
+use crate::ingest_queue::IngestQueue;
+use crate::internal_events::{self, InternalEvent, InternalEventKind};
+use crate::journal_reader::{JournalLogReader, JournalRecord};
+use crate::metrics::{BackfillPhase, Metrics};
+use crate::notifications::{AlertEvent, AlertState, Notifier};
+use crate::outputs::{Output, start_outputs};
+use crate::peer_replication::start_peer_input;
+use crate::process_alerts::ProcessAlerts;
+use crate::process_monitor::{
+    ProcessDeltaTracker, ProcessFilter, ProcessMetricsBatch, ProcessMonitor,
+};
+use crate::remote_write::RemoteWriter;
+use crate::supervisor::{Incident, Supervisor};
+use crate::systemd_notify::SystemdNotifier;
+use anyhow::{Context, Result, anyhow, bail};
+use chrono::{DateTime, TimeDelta, Timelike, Utc};
 use gethostname::gethostname;
-use log::{error, info, warn};
-use signal_hook::consts::SIGINT;
+use log::{debug, error, info, warn};
+use serde_json::{Value, json};
+use signal_hook::consts::{SIGHUP, SIGINT};
+use std::collections::{BTreeMap, HashSet};
+use std::path::PathBuf;
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
-use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::{Arc, Mutex, RwLock};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
 
+/// Source of the alerts livedata raises about itself
+pub const SELF_ALERT_SOURCE: &str = "livedata";
+
 #[derive(Default)]
 struct IngestCounters {
-    journal_records_ingested: AtomicU64,
+    /// Counted by the ingest writer as it stores entries
+    journal_records_ingested: Arc<AtomicU64>,
     process_metrics_collected: AtomicU64,
 }
 
+/// Loads the settings again when the config is reloaded
+type SettingsLoader = Box<dyn Fn() -> Result<Settings> + Send>;
+
 pub struct ApplicationController {
     journal_reader: JournalLogReader,
     buffer: Arc<Mutex<DuckDBBuffer>>,
     hostname: String,
     shutdown_signal: Arc<AtomicBool>,
+    /// Set by SIGHUP; the config is reloaded on the next pass of the main loop
+    reload_signal: Arc<AtomicBool>,
+    /// Settings as last loaded, compared against on a reload
+    settings: Settings,
+    load_settings: SettingsLoader,
     process_monitor: Arc<ProcessMonitor>,
     ingest_counters: Arc<IngestCounters>,
+    /// Prepare and store the journal records read by the main loop (None once shut
+    /// down)
+    entry_workers: Option<EntryWorkers>,
+    /// Fields removed from entries by the entry workers, replaced on a reload
+    dropped_fields: Arc<RwLock<HashSet<String>>>,
+    /// Records read while the entry workers were full, queued before reading on
+    held_batch: Option<Vec<JournalRecord>>,
+    /// Whether journal reads are paused for storage to catch up
+    journal_reads_paused: bool,
+    /// Counters exposed on /metrics
+    metrics: Arc<Metrics>,
     process_monitor_handle: Option<thread::JoinHandle<()>>,
     metrics_receiver_handle: Option<thread::JoinHandle<()>>,
+    /// Sends process and system metrics to `[remote_write]`; ends after the metrics
+    /// receiver
+    remote_write_handle: Option<thread::JoinHandle<()>>,
+    /// Hands finished Parquet files to the archive upload thread (None without an
+    /// archive)
+    archive_uploads: Option<Sender<PathBuf>>,
+    archive_upload_handle: Option<thread::JoinHandle<()>>,
+    /// Evaluates configured and stored log alert rules
+    alert_scheduler_handle: Option<thread::JoinHandle<()>>,
+    /// Hands reloaded `alert_rules` to the alert scheduler
+    alert_config_rules: Arc<Mutex<Option<Vec<AlertRule>>>>,
+    /// Which process metrics are persisted, shared with the metrics receiver
+    process_filter: Arc<Mutex<ProcessFilter>>,
+    /// Forward ingested entries to the configured `[[outputs]]`
+    outputs: Vec<Output>,
+    /// Stores entries replicated from other instances (`[peer_input]`)
+    peer_input_handle: Option<thread::JoinHandle<()>>,
     backfill_handle: Option<thread::JoinHandle<()>>,
     max_db_size_bytes: Option<u64>,
+    hot_storage_days: Option<u32>,
+    log_retention_days: u32,
+    log_max_size_gb: f64,
+    process_retention_days: u32,
+    process_max_size_gb: f64,
+    retention_rules: Vec<RetentionRule>,
+    cleanup_interval: TimeDelta,
+    parquet_export: bool,
+    promote_extra_fields_after: Option<u64>,
+    wal_checkpoint_bytes: u64,
+    /// Idle time after which a non-empty WAL is checkpointed (None = size only)
+    idle_checkpoint_after: Option<TimeDelta>,
+    /// When the last journal entry was ingested
+    last_ingest_time: DateTime<Utc>,
+    /// Whether the WAL has been checkpointed since the last ingest
+    checkpointed_since_ingest: bool,
+    last_exported_minute: Option<DateTime<Utc>>,
+    /// Room the database must keep on its filesystem (0 = unchecked)
+    min_free_disk_bytes: u64,
+    /// Set while disk space is short; journal entries stay unread and process metrics
+    /// are discarded until it clears
+    ingest_paused: Arc<AtomicBool>,
+    /// Sends alerts about livedata itself (ingest paused, retention failing)
+    notifier: Notifier,
+    /// Whether the last retention run failed
+    retention_failing: bool,
+    /// Readiness, status and watchdog pings for systemd
+    systemd: SystemdNotifier,
+    /// Commands from the control socket (None when it could not be opened)
+    control_requests: Option<Receiver<ControlRequest>>,
+    control_handle: Option<thread::JoinHandle<()>>,
+    /// How long a shutdown may take to drain and flush pending data
+    shutdown_timeout: Duration,
+    /// Restarts the collection, ingest and metrics threads when they panic, catches
+    /// panics in the cleanup, and notices stalls
+    supervisor: Supervisor,
+    /// Time a supervised thread may spend on one piece of work before it counts as
+    /// stalled (None = never)
+    thread_stall_after: Option<Duration>,
 }
 
 impl ApplicationController {
@@ -43,22 +141,41 @@ impl ApplicationController {
 
         let shutdown_signal = Arc::new(AtomicBool::new(false));
 
-        // Backup database before any migrations
-        Self::backup_database(&data_dir)?;
-
+        // Migrations run (with a backup of an existing database) when the buffer opens
         let mut buffer = DuckDBBuffer::new(&data_dir)?;
+        buffer.set_dropped_fields(&settings.dropped_fields);
+        buffer.set_process_raw_retention_days(settings.process_raw_retention_days);
+        if let Some(archive_settings) = settings.archive.clone() {
+            buffer.set_archive(ObjectStoreArchive::new(archive_settings))?;
+        }
+        for location in &settings.attached_archives {
+            if let Err(e) = buffer.attach_archive(location) {
+                warn!("Failed to attach Parquet archive {}: {}", location, e);
+            }
+        }
+        let metrics = Arc::new(Metrics::default());
         let cleanup_stats = buffer.enforce_retention(
             settings.log_retention_days,
+            &settings.retention_rules,
             settings.log_max_size_gb,
             settings.process_retention_days,
             settings.process_max_size_gb,
         )?;
+        metrics.record_retention_deleted(cleanup_stats.total_deleted());
+        metrics.record_retention_run(Utc::now(), None);
         if cleanup_stats.total_deleted() > 0 {
             info!(
                 "Startup cleanup complete: {} total records deleted",
                 cleanup_stats.total_deleted()
             );
         }
+        if let Some(hot_days) = settings.hot_storage_days {
+            buffer.roll_to_cold_storage(hot_days)?;
+        }
+        let memory_budget = settings.memory_budget();
+        if let Some(budget) = &memory_budget {
+            buffer.set_memory_limit(budget.duckdb_bytes)?;
+        }
         let buffer = Arc::new(Mutex::new(buffer));
         let journal_reader = JournalLogReader::new()?;
         let hostname = gethostname().to_str().unwrap_or("unknown").to_string();
@@ -71,37 +188,149 @@ impl ApplicationController {
             metrics_tx,
             shutdown_signal.clone(),
         ));
-        let process_monitor_handle = process_monitor.start_collection(process_interval);
+        let process_filter = Arc::new(Mutex::new(ProcessFilter::from_settings(&settings)));
+        // Restarts the collection, ingest and metrics threads should they panic
+        let supervisor = Supervisor::default()
+            .with_recovered_lock(&buffer, |buffer: &mut DuckDBBuffer| {
+                if let Err(e) = buffer.recover_after_panic() {
+                    error!("Database connection unhealthy after a panic: {:#}", e);
+                }
+            })
+            .with_shared_lock(&process_filter);
+        let process_monitor_handle = process_monitor.start_collection(
+            process_interval,
+            settings.collect_sensors,
+            settings.collect_gpu,
+            &supervisor,
+        );
         info!(
             "Started process monitoring with {}s interval",
             process_interval
         );
 
         let shared_buffer = buffer.clone();
+        let receiver_filter = process_filter.clone();
         let ingest_counters = Arc::new(IngestCounters::default());
         let counters_for_metrics = ingest_counters.clone();
+        let ingest_queue = IngestQueue::start(
+            buffer.clone(),
+            metrics.clone(),
+            ingest_counters.journal_records_ingested.clone(),
+            settings.ingest_queue_capacity,
+            &supervisor,
+        );
+        let dropped_fields = Arc::new(RwLock::new(
+            settings.dropped_fields.iter().cloned().collect(),
+        ));
+        let entry_workers = EntryWorkers::start(
+            ingest_queue,
+            dropped_fields.clone(),
+            settings.ingest_workers,
+            settings.ingest_queue_capacity,
+            &supervisor,
+        )
+        .with_memory_limit(memory_budget.map(|budget| budget.in_flight_bytes));
+        let ingest_paused = Arc::new(AtomicBool::new(false));
+        let metrics_paused = ingest_paused.clone();
+        let channel_metrics = metrics.clone();
+        let mut process_deltas = ProcessDeltaTracker::from_settings(&settings);
+        let mut process_alerts = ProcessAlerts::new(&settings.process_alert_rules);
+        let notifier = Notifier::from_settings(&settings);
+        notifier.set_silences(buffer.lock().unwrap().silences(Some(Utc::now()))?);
+        let alert_scheduler =
+            AlertScheduler::new(&settings.alert_rules, buffer.clone(), notifier.clone())?
+                .with_public_url(settings.public_url.clone())
+                .with_heartbeat_rules(&settings.heartbeat_rules)?
+                .with_unit_failure_alerts(settings.unit_failure_alerts)?;
+        let process_notifier = notifier.clone();
+        let (remote_writer, remote_write_handle) = match &settings.remote_write {
+            Some(remote_write) => {
+                match RemoteWriter::start(remote_write, &hostname, shutdown_signal.clone()) {
+                    Ok((writer, handle)) => (Some(writer), Some(handle)),
+                    Err(e) => {
+                        error!("Not sending metrics to {}: {:#}", remote_write.url, e);
+                        (None, None)
+                    }
+                }
+            }
+            None => (None, None),
+        };
 
         // Spawn dedicated receiver task in a thread to persist process metrics
-        let metrics_receiver_handle = thread::spawn(move || {
+        let metrics_receiver_handle = supervisor.spawn("metrics receiver", move |heartbeat| {
             // Create tokio runtime for this thread
             let rt = tokio::runtime::Runtime::new().expect("Failed to create tokio runtime");
 
-            rt.block_on(async move {
+            rt.block_on(async {
                 info!("Process metrics receiver task started");
 
-                while let Some(batch) = metrics_rx.recv().await {
-                    let process_count = batch.processes.len();
-                    if batch.processes.is_empty() {
+                loop {
+                    heartbeat.idle();
+                    let Some(batch) = metrics_rx.recv().await else {
+                        break;
+                    };
+                    heartbeat.busy();
+                    channel_metrics.set_process_channel_depth(metrics_rx.len());
+                    if metrics_paused.load(Ordering::Relaxed) {
                         continue;
                     }
+                    if let Some(system) = &batch.system
+                        && let Err(e) = shared_buffer
+                            .lock()
+                            .unwrap()
+                            .add_system_metrics(system, batch.timestamp)
+                    {
+                        error!("Failed to persist system metrics: {}", e);
+                    }
+                    if let Some(gpu) = batch.gpu.as_ref().filter(|gpu| !gpu.is_empty())
+                        && let Err(e) = shared_buffer
+                            .lock()
+                            .unwrap()
+                            .add_gpu_metrics(gpu, batch.timestamp)
+                    {
+                        error!("Failed to persist GPU metrics: {}", e);
+                    }
+                    if !batch.events.is_empty()
+                        && let Err(e) = shared_buffer
+                            .lock()
+                            .unwrap()
+                            .add_process_events(&batch.events, batch.timestamp)
+                    {
+                        error!("Failed to persist process events: {}", e);
+                    }
+                    if !process_alerts.is_empty() {
+                        let alerts = process_alerts.evaluate(&batch.processes, batch.timestamp);
+                        if !alerts.is_empty() {
+                            if let Err(e) = shared_buffer.lock().unwrap().add_alert_events(&alerts)
+                            {
+                                error!("Failed to persist alerts: {}", e);
+                            }
+                            process_notifier.notify(&alerts);
+                        }
+                    }
+                    let processes = receiver_filter.lock().unwrap().apply(batch.processes);
+                    if let Some(writer) = &remote_writer {
+                        writer.push(&processes, batch.system.as_ref(), batch.timestamp);
+                    }
+                    let delta = process_deltas.select(processes, batch.timestamp);
+                    if delta.processes.is_empty() {
+                        if delta.keyframe {
+                            process_deltas.reset();
+                        }
+                        continue;
+                    }
+                    let process_count = delta.processes.len();
 
-                    let result = shared_buffer
-                        .lock()
-                        .unwrap()
-                        .add_process_metrics(batch.processes, batch.timestamp);
+                    let result = shared_buffer.lock().unwrap().add_process_sample(
+                        delta.processes,
+                        batch.timestamp,
+                        delta.keyframe,
+                    );
 
                     if let Err(e) = result {
                         error!("Failed to persist process metrics: {}", e);
+                        // Rows missing from the database can't be carried forward
+                        process_deltas.reset();
                     } else {
                         counters_for_metrics
                             .process_metrics_collected
@@ -117,6 +346,59 @@ impl ApplicationController {
             });
         });
 
+        let archive_uploader = buffer.lock().unwrap().archive_uploader()?;
+        let (archive_uploads, archive_upload_handle) = match archive_uploader {
+            Some(uploader) => {
+                let (uploads, queued) = std::sync::mpsc::channel::<PathBuf>();
+                let archive_buffer = buffer.clone();
+                // Uploads run on their own connection, taking the buffer only to
+                // record each one
+                let handle = supervisor.spawn("archive upload", move |heartbeat| {
+                    while let Ok(path) = queued.recv() {
+                        heartbeat.busy();
+                        match uploader.upload(&path) {
+                            Ok(_) => {
+                                if let Err(e) = archive_buffer.lock().unwrap().archived(&path) {
+                                    warn!("Failed to remove archived {}: {}", path.display(), e);
+                                }
+                            }
+                            Err(e) => warn!("Failed to archive {}: {}", path.display(), e),
+                        }
+                        heartbeat.idle();
+                    }
+                });
+                (Some(uploads), Some(handle))
+            }
+            None => (None, None),
+        };
+
+        let alert_config_rules = alert_scheduler.config_rules_slot();
+        let alert_scheduler_handle = alert_scheduler.spawn(shutdown_signal.clone());
+        let outputs = start_outputs(&settings.outputs, &buffer, &shutdown_signal);
+        let peer_input_handle = match &settings.peer_input {
+            Some(peer_input) => Some(
+                start_peer_input(
+                    peer_input,
+                    buffer.clone(),
+                    metrics.clone(),
+                    shutdown_signal.clone(),
+                )
+                .context("Failed to start the peer input")?,
+            ),
+            None => None,
+        };
+
+        let (control_requests, control_handle) = match start_control_server(
+            &control::socket_path(data_dir.as_ref()),
+            shutdown_signal.clone(),
+        ) {
+            Ok((requests, handle)) => (Some(requests), Some(handle)),
+            Err(e) => {
+                warn!("Control socket unavailable: {:#}", e);
+                (None, None)
+            }
+        };
+
         info!("Application Controller initialized successfully");
         info!(
             "Using on-disk DuckDB at: {}",
@@ -128,31 +410,64 @@ impl ApplicationController {
             buffer,
             hostname,
             shutdown_signal,
+            reload_signal: Arc::new(AtomicBool::new(false)),
+            settings: settings.clone(),
+            load_settings: Box::new(Settings::load),
             process_monitor,
             ingest_counters,
+            entry_workers: Some(entry_workers),
+            dropped_fields,
+            held_batch: None,
+            journal_reads_paused: false,
+            metrics,
             process_monitor_handle: Some(process_monitor_handle),
             metrics_receiver_handle: Some(metrics_receiver_handle),
+            remote_write_handle,
+            archive_uploads,
+            archive_upload_handle,
+            alert_scheduler_handle: Some(alert_scheduler_handle),
+            alert_config_rules,
+            process_filter,
+            outputs,
+            peer_input_handle,
             backfill_handle: None,
             max_db_size_bytes: settings.max_db_size_bytes,
+            hot_storage_days: settings.hot_storage_days,
+            log_retention_days: settings.log_retention_days,
+            log_max_size_gb: settings.log_max_size_gb,
+            process_retention_days: settings.process_retention_days,
+            process_max_size_gb: settings.process_max_size_gb,
+            retention_rules: settings.retention_rules.clone(),
+            cleanup_interval: TimeDelta::minutes(settings.cleanup_interval_minutes as i64),
+            parquet_export: settings.parquet_export,
+            promote_extra_fields_after: settings.promote_extra_fields_after,
+            wal_checkpoint_bytes: settings.wal_checkpoint_bytes,
+            idle_checkpoint_after: (settings.idle_checkpoint_secs > 0)
+                .then(|| TimeDelta::seconds(settings.idle_checkpoint_secs as i64)),
+            last_ingest_time: Utc::now(),
+            checkpointed_since_ingest: false,
+            last_exported_minute: None,
+            min_free_disk_bytes: settings.min_free_disk_bytes,
+            ingest_paused,
+            notifier,
+            retention_failing: false,
+            systemd: SystemdNotifier::from_env(),
+            control_requests,
+            control_handle,
+            shutdown_timeout: Duration::from_secs(settings.shutdown_timeout_secs),
+            supervisor,
+            thread_stall_after: thread_stall_after(&settings),
         })
     }
 
-    /// Backup database file before migrations
-    fn backup_database<P: AsRef<std::path::Path>>(data_dir: P) -> Result<()> {
-        let db_path = data_dir.as_ref().join("livedata.duckdb");
-
-        if !db_path.exists() {
-            // No database to backup yet
-            return Ok(());
-        }
-
-        let backup_path = data_dir.as_ref().join("livedata.duckdb.bak");
-        info!("Backing up database to: {}", backup_path.display());
-
-        std::fs::copy(&db_path, &backup_path)?;
-        info!("Database backup complete");
-
-        Ok(())
+    /// Load the settings with `loader` when the config is reloaded, instead of from
+    /// the config file and environment alone
+    pub fn with_settings_loader(
+        mut self,
+        loader: impl Fn() -> Result<Settings> + Send + 'static,
+    ) -> Self {
+        self.load_settings = Box::new(loader);
+        self
     }
 
     pub fn get_shutdown_signal(&self) -> Arc<AtomicBool> {
@@ -167,9 +482,18 @@ impl ApplicationController {
         self.buffer.clone()
     }
 
+    pub fn get_metrics(&self) -> Arc<Metrics> {
+        self.metrics.clone()
+    }
+
+    pub fn get_notifier(&self) -> Notifier {
+        self.notifier.clone()
+    }
+
     pub fn setup_signal_handler(&self) -> Result<()> {
         signal_hook::flag::register(SIGINT, self.shutdown_signal.clone())?;
         signal_hook::flag::register(signal_hook::consts::SIGTERM, self.shutdown_signal.clone())?;
+        signal_hook::flag::register(SIGHUP, self.reload_signal.clone())?;
 
         Ok(())
     }
@@ -178,9 +502,14 @@ impl ApplicationController {
         info!("Starting journald log collection to DuckDB");
 
         self.setup_signal_handler()?;
+        if let Some(interval) = self.systemd.watchdog_interval() {
+            info!("systemd watchdog enabled, pinging every {:?}", interval);
+        }
 
         // Process historical data from the last hour on startup (unless in follow mode)
         if !follow {
+            self.systemd
+                .status(|| "Loading the last hour of the journal".to_string());
             self.process_startup_historical_data()?;
         } else {
             // In follow mode, just seek to tail for real-time monitoring
@@ -197,22 +526,74 @@ impl ApplicationController {
 
         let mut last_summary_time = Utc::now();
         let summary_interval = TimeDelta::minutes(5);
+        let mut last_cleanup_time = Utc::now();
+        let cleanup_heartbeat = self.supervisor.watch("cleanup");
+        let summaries_interval = TimeDelta::minutes(5);
+        let mut last_summaries_refresh: Option<DateTime<Utc>> = None;
+        let rollups_interval = TimeDelta::minutes(1);
+        let mut last_rollups_refresh: Option<DateTime<Utc>> = None;
+        let wal_check_interval = TimeDelta::seconds(10);
+        let mut last_wal_check = Utc::now();
+        let disk_check_interval = TimeDelta::seconds(30);
+        let mut last_disk_check: Option<DateTime<Utc>> = None;
+        let supervise_interval = TimeDelta::seconds(10);
+        let mut last_supervise = Utc::now();
+        let lag_sample_interval = TimeDelta::minutes(1);
+        let mut last_lag_sample = Utc::now();
 
         info!("Starting main loop");
+        self.systemd.ready("Collecting journal entries");
 
         loop {
+            self.metrics.record_heartbeat(Utc::now());
+            // Pinged from here, so a wedged loop (a stuck lock or query) gets the
+            // service restarted
+            self.systemd.watchdog();
+            self.update_systemd_status();
+
             // Check for shutdown signal
             if self.shutdown_signal.load(Ordering::Relaxed) {
                 info!("Shutdown signal received, initiating graceful shutdown");
                 break;
             }
 
-            // Drain any newly available journal entries.
-            while let Ok(Some(entry)) = self.journal_reader.next_log_entry() {
-                if let Err(e) = self.process_log_entry(entry) {
-                    error!("Failed to process log entry: {}", e);
+            if self.reload_signal.swap(false, Ordering::Relaxed)
+                && let Err(e) = self.reload_settings()
+            {
+                error!("{:#}", e);
+            }
+            self.handle_control_requests();
+
+            if Utc::now() - last_supervise >= supervise_interval {
+                self.supervise();
+                last_supervise = Utc::now();
+            }
+
+            if self.min_free_disk_bytes > 0
+                && last_disk_check.is_none_or(|t| Utc::now() - t >= disk_check_interval)
+            {
+                self.check_disk_space();
+                last_disk_check = Some(Utc::now());
+            }
+
+            // Queue any newly available journal entries. While paused, or while storage
+            // catches up, they stay in the journal and are read once there is room again.
+            match self.read_journal() {
+                Ok(0) => {}
+                Ok(_) => {
+                    self.last_ingest_time = Utc::now();
+                    self.checkpointed_since_ingest = false;
                 }
+                Err(e) => error!("{}", e),
+            }
+            let mut buffer = self.buffer.lock().unwrap();
+            let flush_start = Instant::now();
+            if let Err(e) = buffer.flush_log_counts() {
+                error!("Failed to update log count rollup: {}", e);
             }
+            self.metrics.record_flush(flush_start.elapsed());
+            self.metrics.set_tail_channel_depth(buffer.tail_depth());
+            drop(buffer);
 
             // Log periodic ingestion summary
             let current_time = Utc::now();
@@ -221,6 +602,44 @@ impl ApplicationController {
                 last_summary_time = current_time;
             }
 
+            if self.parquet_export {
+                self.export_completed_minutes(current_time);
+            }
+            self.queue_archive_uploads();
+
+            if current_time - last_lag_sample >= lag_sample_interval {
+                self.sample_ingest_lag(current_time);
+                last_lag_sample = current_time;
+            }
+
+            if current_time - last_wal_check >= wal_check_interval {
+                self.checkpoint_if_needed(current_time);
+                last_wal_check = current_time;
+            }
+
+            if last_summaries_refresh.is_none_or(|t| current_time - t >= summaries_interval) {
+                self.refresh_log_summaries();
+                last_summaries_refresh = Some(current_time);
+            }
+
+            if last_rollups_refresh.is_none_or(|t| current_time - t >= rollups_interval) {
+                self.refresh_process_rollups();
+                last_rollups_refresh = Some(current_time);
+            }
+
+            // Apply retention, then roll aged days into cold storage
+            if current_time - last_cleanup_time >= self.cleanup_interval {
+                // A panic here is reported and the cleanup tried again next interval
+                let supervisor = self.supervisor.clone();
+                supervisor.run_once("cleanup", &cleanup_heartbeat, || {
+                    self.enforce_retention();
+                    self.roll_to_cold_storage();
+                    self.promote_hot_extra_fields();
+                    self.compact_log_counts();
+                });
+                last_cleanup_time = current_time;
+            }
+
             // Small sleep to prevent busy waiting
             thread::sleep(Duration::from_millis(100));
         }
@@ -229,9 +648,171 @@ impl ApplicationController {
         self.graceful_shutdown(checkpoint_on_shutdown)
     }
 
+    /// Load the config again and apply the changes to [`RELOADABLE_SETTINGS`], logging
+    /// every change. A config that fails to load leaves the running one in place.
+    fn reload_settings(&mut self) -> Result<Vec<SettingChange>> {
+        let settings = (self.load_settings)()
+            .context("Failed to reload the config, keeping the current one")?;
+        let changes = self.settings.changes(&settings);
+        if changes.is_empty() {
+            info!("Config reloaded: nothing changed");
+            return Ok(changes);
+        }
+        info!("Config reloaded: {} settings changed", changes.len());
+        for change in &changes {
+            info!("  {}: {} -> {}", change.key, change.old, change.new);
+        }
+        let needs_restart: Vec<&str> = changes
+            .iter()
+            .map(|change| change.key.as_str())
+            .filter(|key| !RELOADABLE_SETTINGS.contains(key))
+            .collect();
+        if !needs_restart.is_empty() {
+            warn!(
+                "Changes to {} take effect after a restart",
+                needs_restart.join(", ")
+            );
+        }
+
+        self.hot_storage_days = settings.hot_storage_days;
+        self.log_retention_days = settings.log_retention_days;
+        self.log_max_size_gb = settings.log_max_size_gb;
+        self.process_retention_days = settings.process_retention_days;
+        self.process_max_size_gb = settings.process_max_size_gb;
+        self.retention_rules = settings.retention_rules.clone();
+        self.cleanup_interval = TimeDelta::minutes(settings.cleanup_interval_minutes as i64);
+        self.parquet_export = settings.parquet_export;
+        self.promote_extra_fields_after = settings.promote_extra_fields_after;
+        self.wal_checkpoint_bytes = settings.wal_checkpoint_bytes;
+        self.idle_checkpoint_after = (settings.idle_checkpoint_secs > 0)
+            .then(|| TimeDelta::seconds(settings.idle_checkpoint_secs as i64));
+        self.min_free_disk_bytes = settings.min_free_disk_bytes;
+        self.shutdown_timeout = Duration::from_secs(settings.shutdown_timeout_secs);
+        self.thread_stall_after = thread_stall_after(&settings);
+        {
+            let mut buffer = self.buffer.lock().unwrap();
+            buffer.set_dropped_fields(&settings.dropped_fields);
+            buffer.set_process_raw_retention_days(settings.process_raw_retention_days);
+        }
+        *self.dropped_fields.write().unwrap() = settings.dropped_fields.iter().cloned().collect();
+        *self.process_filter.lock().unwrap() = ProcessFilter::from_settings(&settings);
+        if settings.alert_rules != self.settings.alert_rules {
+            *self.alert_config_rules.lock().unwrap() = Some(settings.alert_rules.clone());
+        }
+        self.settings = settings;
+
+        // A lowered minimum can end a pause straight away, and tighter retention
+        // should not wait for the next cleanup
+        if self.ingest_paused.load(Ordering::Relaxed) {
+            self.check_disk_space();
+        }
+        let retention_changed = changes
+            .iter()
+            .any(|change| change.key.contains("retention") || change.key.ends_with("max_size_gb"));
+        if retention_changed {
+            self.enforce_retention();
+        }
+        Ok(changes)
+    }
+
+    /// Carry out the commands received on the control socket
+    fn handle_control_requests(&mut self) {
+        let Some(requests) = &self.control_requests else {
+            return;
+        };
+        let requests: Vec<ControlRequest> = requests.try_iter().collect();
+        for request in requests {
+            info!("Control command: {}", request.command);
+            let result = self.control(request.command);
+            request.respond(result);
+        }
+    }
+
+    fn control(&mut self, command: ControlCommand) -> Result<Value> {
+        match command {
+            ControlCommand::Status => {
+                let status = self.get_status()?;
+                let now = Utc::now();
+                Ok(json!({
+                    "hostname": status.hostname,
+                    "total_entries": status.total_entries,
+                    "distinct_minutes": status.distinct_minutes_count,
+                    "oldest_entry_minute": status.oldest_entry_minute,
+                    "newest_entry_minute": status.newest_entry_minute,
+                    "database_size_bytes": status.database_size_bytes,
+                    "wal_size_bytes": self.buffer.lock().unwrap().wal_size(),
+                    "entries_ingested": self.metrics.entries_ingested(),
+                    "ingest_lag_seconds": self.metrics.ingest_lag_seconds(now),
+                    "ingest_paused": self.ingest_paused.load(Ordering::Relaxed),
+                    "ingest_queue_depth": self.entry_workers.as_ref().map_or(0, |w| w.depth()),
+                    "journal_reads_paused": self.journal_reads_paused,
+                    "backfill": self.metrics.backfill_progress(),
+                    "last_retention_run": self.metrics.last_retention_run().map(|run| {
+                        json!({"at": run.at, "error": run.error})
+                    }),
+                }))
+            }
+            ControlCommand::Checkpoint => {
+                let mut buffer = self.buffer.lock().unwrap();
+                let wal_size = buffer.wal_size();
+                buffer
+                    .try_checkpoint()
+                    .context("Checkpoint failed, a query may be running; try again")?;
+                self.checkpointed_since_ingest = true;
+                Ok(json!({"wal_size_bytes": wal_size}))
+            }
+            ControlCommand::Retention => match self.enforce_retention() {
+                Some(deleted) => {
+                    self.roll_to_cold_storage();
+                    Ok(json!({"deleted": deleted}))
+                }
+                None => bail!("Retention failed, see the collector's log"),
+            },
+            ControlCommand::Reload => {
+                let changes = self.reload_settings()?;
+                Ok(json!({
+                    "changed": changes.iter().map(|change| &change.key).collect::<Vec<_>>()
+                }))
+            }
+            ControlCommand::Flush => {
+                self.buffer.lock().unwrap().flush_log_counts()?;
+                self.refresh_log_summaries();
+                self.refresh_process_rollups();
+                Ok(json!({}))
+            }
+            ControlCommand::Backup => {
+                let path = self.buffer.lock().unwrap().stage_backup()?;
+                self.checkpointed_since_ingest = true;
+                Ok(json!({"path": path}))
+            }
+        }
+    }
+
+    /// Report ingest counts and pauses in `systemctl status`
+    fn update_systemd_status(&mut self) {
+        let metrics = &self.metrics;
+        let paused = self.ingest_paused.load(Ordering::Relaxed);
+        self.systemd.status(|| {
+            let entries = metrics.entries_ingested();
+            if paused {
+                format!(
+                    "Ingestion paused for low disk space ({} entries ingested)",
+                    entries
+                )
+            } else {
+                match metrics.ingest_lag_seconds(Utc::now()) {
+                    Some(lag) => format!("{} entries ingested, newest {:.0}s old", entries, lag),
+                    None => format!("{} entries ingested", entries),
+                }
+            }
+        });
+    }
+
     fn spawn_backfill_thread(&mut self, max_db_size_bytes: u64) {
         let buffer = self.buffer.clone();
         let shutdown_signal = self.shutdown_signal.clone();
+        let ingest_paused = self.ingest_paused.clone();
+        let metrics = self.metrics.clone();
 
         let handle = thread::spawn(move || {
             info!(
@@ -252,13 +833,19 @@ impl ApplicationController {
                 return;
             }
 
+            metrics.start_backfill(BackfillPhase::History, Utc::now());
             let mut total_backfilled: u64 = 0;
+            let mut reached = None;
 
             loop {
                 if shutdown_signal.load(Ordering::Relaxed) {
                     info!("Backfill thread: shutdown signal received, stopping");
                     break;
                 }
+                if ingest_paused.load(Ordering::Relaxed) {
+                    thread::sleep(Duration::from_secs(1));
+                    continue;
+                }
 
                 // Process a batch of 1000 entries
                 let mut batch_count = 0;
@@ -277,6 +864,7 @@ impl ApplicationController {
                                 if let Err(e) = buf.add_entry(&entry) {
                                     error!("Backfill: failed to add entry: {}", e);
                                 }
+                                reached = Some(entry.timestamp);
                                 batch_count += 1;
                             }
                             Ok(None) => {
@@ -300,10 +888,17 @@ impl ApplicationController {
                 total_backfilled += batch_count;
 
                 if hit_end {
-                    info!(
+                    let message = format!(
                         "Backfill complete: reached beginning of journal after {} entries",
                         total_backfilled
                     );
+                    info!("{}", message);
+                    let event = InternalEvent::new(
+                        InternalEventKind::Backfill,
+                        message,
+                        Some(total_backfilled as f64),
+                    );
+                    internal_events::record(&buffer.lock().unwrap(), event);
                     break;
                 }
 
@@ -315,11 +910,27 @@ impl ApplicationController {
                         .unwrap_or(0)
                 };
 
+                if let Some(reached) = reached {
+                    metrics.record_backfill_progress(
+                        total_backfilled,
+                        reached,
+                        Some(db_size as f64 / max_db_size_bytes as f64),
+                        Utc::now(),
+                    );
+                }
+
                 if db_size >= max_db_size_bytes {
-                    info!(
+                    let message = format!(
                         "Backfill complete: DB size {} bytes >= max {} bytes after {} entries",
                         db_size, max_db_size_bytes, total_backfilled
                     );
+                    info!("{}", message);
+                    let event = InternalEvent::new(
+                        InternalEventKind::Backfill,
+                        message,
+                        Some(total_backfilled as f64),
+                    );
+                    internal_events::record(&buffer.lock().unwrap(), event);
                     break;
                 }
 
@@ -327,6 +938,7 @@ impl ApplicationController {
                 thread::sleep(Duration::from_millis(10));
             }
 
+            metrics.finish_backfill();
             info!(
                 "Backfill thread exiting, total entries backfilled: {}",
                 total_backfilled
@@ -341,15 +953,43 @@ impl ApplicationController {
         info!("Processing startup historical data - last hour of logs");
 
         // Calculate cutoff time (1 hour ago)
-        let cutoff_time = Utc::now() - TimeDelta::hours(1);
+        let started = Utc::now();
+        let cutoff_time = started - TimeDelta::hours(1);
+        self.metrics.start_backfill(BackfillPhase::Startup, started);
 
         // Process historical entries from the last hour in a single transaction
         // to avoid per-row auto-commit overhead
         let mut buffer = self.buffer.lock().unwrap();
         buffer.begin_transaction()?;
+        let mut stored = 0;
         let result = self
             .journal_reader
-            .process_historical_entries(cutoff_time, |entry| buffer.add_entry(entry));
+            .process_historical_entries(cutoff_time, |entry| {
+                buffer.add_entry(entry)?;
+                stored += 1;
+                if stored % 1000 == 0 {
+                    // Entries come newest first, so the time covered tells how far along
+                    let covered = (started - entry.timestamp).num_seconds() as f64;
+                    self.metrics.record_backfill_progress(
+                        stored,
+                        entry.timestamp,
+                        Some(covered / 3600.0),
+                        Utc::now(),
+                    );
+                    self.systemd.status(|| {
+                        format!(
+                            "Loading the last hour of the journal: {} entries, at {}",
+                            stored,
+                            entry.timestamp.format("%H:%M:%S")
+                        )
+                    });
+                    if let Some(requests) = &self.control_requests {
+                        answer_during_startup(requests, &self.metrics);
+                    }
+                }
+                Ok(())
+            });
+        self.metrics.finish_backfill();
         match result {
             Ok(count) => {
                 buffer.commit_transaction()?;
@@ -376,13 +1016,432 @@ impl ApplicationController {
         Ok(())
     }
 
-    fn process_log_entry(&mut self, entry: LogEntry) -> Result<()> {
-        // Add entry to on-disk DuckDB
-        self.buffer.lock().unwrap().add_entry(&entry)?;
-        self.ingest_counters
-            .journal_records_ingested
-            .fetch_add(1, Ordering::Relaxed);
-        Ok(())
+    /// Hand newly logged journal records to the entry workers in batches until they
+    /// are full, returning how many were queued. Reading stops at a journal error,
+    /// which is counted and returned; the records read before it are queued later.
+    fn read_journal(&mut self) -> Result<usize> {
+        let Some(workers) = &mut self.entry_workers else {
+            return Ok(0);
+        };
+        let mut queued = 0;
+        let mut full = false;
+        let mut read_error = None;
+        while !self.ingest_paused.load(Ordering::Relaxed) {
+            let batch = match self.held_batch.take() {
+                Some(batch) => batch,
+                None => {
+                    let mut batch = Vec::with_capacity(READ_BATCH);
+                    while batch.len() < READ_BATCH {
+                        match self.journal_reader.next_record() {
+                            Ok(Some(record)) => batch.push(record),
+                            Ok(None) => break,
+                            Err(e) => {
+                                self.metrics.record_journal_read_error();
+                                read_error = Some(e);
+                                break;
+                            }
+                        }
+                    }
+                    if read_error.is_some() {
+                        self.held_batch = Some(batch).filter(|batch| !batch.is_empty());
+                        break;
+                    }
+                    batch
+                }
+            };
+            let len = batch.len();
+            if len == 0 {
+                break;
+            }
+            if let Err(batch) = workers.try_push(batch) {
+                self.held_batch = Some(batch);
+                full = true;
+                break;
+            }
+            queued += len;
+            if len < READ_BATCH {
+                // Caught up with the journal
+                break;
+            }
+        }
+
+        let budget_hit = workers.take_budget_hit();
+        if budget_hit {
+            self.metrics.record_memory_budget_hit();
+        }
+        if full && !self.journal_reads_paused {
+            let message = if budget_hit {
+                format!(
+                    "Entries waiting to be stored reached the memory budget ({} bytes), \
+                     pausing journal reads",
+                    workers.bytes()
+                )
+            } else {
+                format!(
+                    "Storage is falling behind ({} entries queued), pausing journal reads",
+                    workers.depth()
+                )
+            };
+            warn!("{}", message);
+            let event = InternalEvent::new(
+                InternalEventKind::Backpressure,
+                message,
+                Some(workers.depth() as f64),
+            );
+            internal_events::record(&self.buffer.lock().unwrap(), event);
+        } else if !full && self.journal_reads_paused {
+            info!("Storage caught up, resuming journal reads");
+        }
+        self.journal_reads_paused = full;
+        self.metrics.set_journal_reads_paused(full);
+        self.metrics.set_ingest_channel_depth(workers.depth());
+        self.metrics.set_in_flight_bytes(workers.bytes());
+        match read_error {
+            Some(e) => Err(e),
+            None => Ok(queued),
+        }
+    }
+
+    /// Report the supervised threads that were restarted, stalled or recovered since
+    /// the last check
+    fn supervise(&mut self) {
+        let stall_after = self.thread_stall_after.unwrap_or(Duration::MAX);
+        for incident in self.supervisor.check(stall_after) {
+            match incident {
+                Incident::Restarted { thread, panic } => {
+                    self.metrics.record_thread_restart(&thread);
+                    let event = InternalEvent::new(
+                        InternalEventKind::ThreadRestarted,
+                        format!("{} thread panicked and was restarted: {}", thread, panic),
+                        None,
+                    );
+                    internal_events::record(&self.buffer.lock().unwrap(), event);
+                }
+                Incident::Stalled { thread, busy_for } => {
+                    let message = format!(
+                        "{} thread has been stuck on one piece of work for {}s",
+                        thread,
+                        busy_for.as_secs()
+                    );
+                    warn!("{}", message);
+                    self.raise_self_alert(
+                        &format!("{} stalled", thread),
+                        AlertState::Firing,
+                        message,
+                    );
+                }
+                Incident::Recovered { thread } => {
+                    let message = format!("{} thread is making progress again", thread);
+                    info!("{}", message);
+                    self.raise_self_alert(
+                        &format!("{} stalled", thread),
+                        AlertState::Resolved,
+                        message,
+                    );
+                }
+            }
+        }
+        self.metrics
+            .set_threads_stalled(self.supervisor.stalled().len());
+    }
+
+    /// Store how far ingestion is behind the journal, so lag can be looked at over time
+    fn sample_ingest_lag(&self, now: DateTime<Utc>) {
+        let Some(lag) = self.metrics.ingest_lag_seconds(now) else {
+            return;
+        };
+        let event = InternalEvent::new(
+            InternalEventKind::IngestLag,
+            format!("Newest stored entry is {:.1}s old", lag),
+            Some(lag),
+        );
+        internal_events::record(&self.buffer.lock().unwrap(), event);
+    }
+
+    /// Export every minute that has closed since the last export to Parquet.
+    /// The first call only records a starting point; exports begin with the minute
+    /// that was in progress at that time.
+    fn export_completed_minutes(&mut self, now: DateTime<Utc>) {
+        let current_minute = now
+            .with_second(0)
+            .and_then(|t| t.with_nanosecond(0))
+            .unwrap_or(now);
+
+        let Some(last) = self.last_exported_minute else {
+            self.last_exported_minute = Some(current_minute - TimeDelta::minutes(1));
+            return;
+        };
+
+        let mut minute = last + TimeDelta::minutes(1);
+        while minute < current_minute {
+            if let Err(e) = self.buffer.lock().unwrap().export_minute_to_parquet(minute) {
+                error!("Failed to export minute {} to Parquet: {}", minute, e);
+                return;
+            }
+            self.last_exported_minute = Some(minute);
+            minute += TimeDelta::minutes(1);
+        }
+    }
+
+    /// Hand the Parquet files written for the archive to the upload thread
+    fn queue_archive_uploads(&mut self) {
+        let Some(uploads) = &self.archive_uploads else {
+            return;
+        };
+        for path in self.buffer.lock().unwrap().take_archive_uploads() {
+            let _ = uploads.send(path);
+        }
+    }
+
+    /// Apply the retention settings, returning how many rows were deleted (None when
+    /// it failed)
+    fn enforce_retention(&mut self) -> Option<usize> {
+        let mut buffer = self.buffer.lock().unwrap();
+        let result = buffer.enforce_retention(
+            self.log_retention_days,
+            &self.retention_rules,
+            self.log_max_size_gb,
+            self.process_retention_days,
+            self.process_max_size_gb,
+        );
+        let event = match &result {
+            Ok(stats) if stats.total_deleted() > 0 => Some(InternalEvent::new(
+                InternalEventKind::Retention,
+                format!("Retention deleted {} records", stats.total_deleted()),
+                Some(stats.total_deleted() as f64),
+            )),
+            Ok(_) => None,
+            Err(e) => Some(InternalEvent::new(
+                InternalEventKind::Retention,
+                format!("Retention failed: {}", e),
+                None,
+            )),
+        };
+        if let Some(event) = event {
+            internal_events::record(&buffer, event);
+        }
+        drop(buffer);
+        match &result {
+            Ok(stats) => {
+                self.metrics.record_retention_deleted(stats.total_deleted());
+                self.metrics.record_retention_run(Utc::now(), None);
+            }
+            Err(e) => self
+                .metrics
+                .record_retention_run(Utc::now(), Some(e.to_string())),
+        }
+        let failed = result.is_err();
+        let deleted = result.as_ref().ok().map(|stats| stats.total_deleted());
+        match result {
+            Ok(stats) if stats.total_deleted() > 0 => {
+                info!(
+                    "Retention cleanup complete: {} total records deleted",
+                    stats.total_deleted()
+                );
+            }
+            Ok(_) => {}
+            Err(e) => {
+                error!("Failed to enforce retention: {}", e);
+                if !self.retention_failing {
+                    self.raise_self_alert(
+                        "retention failing",
+                        AlertState::Firing,
+                        format!("Retention cleanup failed: {}", e),
+                    );
+                }
+            }
+        }
+        if self.retention_failing && !failed {
+            self.raise_self_alert(
+                "retention failing",
+                AlertState::Resolved,
+                "Retention cleanup succeeded again".to_string(),
+            );
+        }
+        self.retention_failing = failed;
+        deleted
+    }
+
+    /// Store and send an alert about livedata itself
+    fn raise_self_alert(&self, rule: &str, state: AlertState, message: String) {
+        raise_self_alert(
+            &mut self.buffer.lock().unwrap(),
+            &self.notifier,
+            rule,
+            state,
+            message,
+        );
+    }
+
+    fn roll_to_cold_storage(&mut self) {
+        let Some(hot_days) = self.hot_storage_days else {
+            return;
+        };
+        if let Err(e) = self.buffer.lock().unwrap().roll_to_cold_storage(hot_days) {
+            error!("Failed to roll logs into cold storage: {}", e);
+        }
+    }
+
+    fn promote_hot_extra_fields(&mut self) {
+        let Some(min_rows) = self.promote_extra_fields_after else {
+            return;
+        };
+        match self
+            .buffer
+            .lock()
+            .unwrap()
+            .promote_hot_extra_fields(min_rows)
+        {
+            Ok(promoted) if !promoted.is_empty() => {
+                info!("Promoted extra fields to columns: {}", promoted.join(", "));
+            }
+            Ok(_) => {}
+            Err(e) => error!("Failed to promote extra fields: {}", e),
+        }
+    }
+
+    /// Checkpoint when the WAL has outgrown `wal_checkpoint_bytes`, or when ingestion
+    /// has been idle for `idle_checkpoint_after` and the WAL is not empty. A large WAL
+    /// is replayed on every startup and holds disk space until it is checkpointed.
+    fn checkpoint_if_needed(&mut self, now: DateTime<Utc>) {
+        let mut buffer = self.buffer.lock().unwrap();
+        let wal_size = buffer.wal_size();
+        let reason = if wal_size >= self.wal_checkpoint_bytes {
+            "WAL size"
+        } else if wal_size > 0
+            && !self.checkpointed_since_ingest
+            && self
+                .idle_checkpoint_after
+                .is_some_and(|idle| now - self.last_ingest_time >= idle)
+        {
+            "idle"
+        } else {
+            return;
+        };
+
+        let started = Instant::now();
+        match buffer.try_checkpoint() {
+            Ok(()) => {
+                self.checkpointed_since_ingest = true;
+                let elapsed = started.elapsed();
+                let message = format!(
+                    "Checkpointed database ({}, WAL was {} KB) in {:.2?}",
+                    reason,
+                    wal_size / 1024,
+                    elapsed
+                );
+                info!("{}", message);
+                let event = InternalEvent::new(
+                    InternalEventKind::Checkpoint,
+                    message,
+                    Some(elapsed.as_secs_f64()),
+                );
+                internal_events::record(&buffer, event);
+            }
+            // Usually a web query holding a transaction open; retried on the next check
+            Err(e) => debug!("Checkpoint ({}) deferred: {}", reason, e),
+        }
+    }
+
+    /// Run an emergency cleanup when the database is short of disk space, pausing
+    /// ingestion if that cannot free enough, and resume once there is room again
+    fn check_disk_space(&mut self) {
+        let mut buffer = self.buffer.lock().unwrap();
+        let Some(room) = buffer.disk_room() else {
+            return;
+        };
+        let paused = self.ingest_paused.load(Ordering::Relaxed);
+        if room >= self.min_free_disk_bytes {
+            if paused {
+                self.ingest_paused.store(false, Ordering::Relaxed);
+                let message = format!(
+                    "Disk space recovered ({} MB free), resuming ingestion",
+                    room / (1024 * 1024)
+                );
+                info!("{}", message);
+                raise_self_alert(
+                    &mut buffer,
+                    &self.notifier,
+                    "ingest paused",
+                    AlertState::Resolved,
+                    message,
+                );
+            }
+            return;
+        }
+
+        warn!(
+            "Only {} MB free for the database (minimum {} MB), running emergency cleanup",
+            room / (1024 * 1024),
+            self.min_free_disk_bytes / (1024 * 1024)
+        );
+        let event = match buffer.emergency_cleanup(self.min_free_disk_bytes) {
+            Ok(deleted) => {
+                self.metrics.record_retention_deleted(deleted);
+                let message = format!("Emergency cleanup deleted {} records", deleted);
+                warn!("{}", message);
+                InternalEvent::new(InternalEventKind::Retention, message, Some(deleted as f64))
+            }
+            Err(e) => {
+                let message = format!("Emergency cleanup failed: {}", e);
+                error!("{}", message);
+                InternalEvent::new(InternalEventKind::Retention, message, None)
+            }
+        };
+        internal_events::record(&buffer, event);
+
+        let room = buffer.disk_room().unwrap_or(0);
+        if room < self.min_free_disk_bytes {
+            if !paused {
+                self.ingest_paused.store(true, Ordering::Relaxed);
+                warn!(
+                    "Pausing ingestion: still only {} MB free for the database in {}. \
+                     Free up disk space or lower min_free_disk_bytes; ingestion resumes \
+                     automatically",
+                    room / (1024 * 1024),
+                    buffer.db_path().display()
+                );
+                let message = format!(
+                    "Ingestion paused: only {} MB free for the database",
+                    room / (1024 * 1024)
+                );
+                raise_self_alert(
+                    &mut buffer,
+                    &self.notifier,
+                    "ingest paused",
+                    AlertState::Firing,
+                    message,
+                );
+            }
+        } else if paused {
+            self.ingest_paused.store(false, Ordering::Relaxed);
+            info!("Emergency cleanup freed enough disk space, resuming ingestion");
+            raise_self_alert(
+                &mut buffer,
+                &self.notifier,
+                "ingest paused",
+                AlertState::Resolved,
+                "Emergency cleanup freed enough disk space, resuming ingestion".to_string(),
+            );
+        }
+    }
+
+    fn refresh_log_summaries(&mut self) {
+        if let Err(e) = self.buffer.lock().unwrap().refresh_log_summaries() {
+            error!("Failed to refresh hourly log summaries: {}", e);
+        }
+    }
+
+    fn refresh_process_rollups(&mut self) {
+        if let Err(e) = self.buffer.lock().unwrap().refresh_process_rollups() {
+            error!("Failed to refresh per-minute process rollups: {}", e);
+        }
+    }
+
+    fn compact_log_counts(&mut self) {
+        if let Err(e) = self.buffer.lock().unwrap().compact_log_counts() {
+            error!("Failed to compact log count rollup: {}", e);
+        }
     }
 
     fn log_ingest_summary(&self) {
@@ -403,30 +1462,52 @@ impl ApplicationController {
 
     fn graceful_shutdown(&mut self, checkpoint_on_shutdown: bool) -> Result<()> {
         info!("Starting graceful shutdown");
+        self.systemd.stopping();
+        let deadline = self.shutdown_deadline();
 
+        // Stops the producers (process monitor, peer input, alert scheduler); what they
+        // already handed over is drained below
         self.shutdown_signal.store(true, Ordering::Relaxed);
 
-        self.process_monitor.shutdown_metrics_channel();
+        self.drain_journal(deadline);
 
-        if let Some(handle) = self.process_monitor_handle.take()
-            && let Err(e) = handle.join()
-        {
-            warn!("Failed to join process monitor thread: {:?}", e);
+        // The receiver stores the batches still queued, then ends once the channel
+        // closes
+        self.process_monitor.shutdown_metrics_channel();
+        if let Some(handle) = self.process_monitor_handle.take() {
+            join_until(handle, "process monitor", deadline);
         }
-
-        if let Some(handle) = self.metrics_receiver_handle.take()
-            && let Err(e) = handle.join()
-        {
-            warn!("Failed to join metrics receiver thread: {:?}", e);
+        if let Some(handle) = self.metrics_receiver_handle.take() {
+            join_until(handle, "metrics receiver", deadline);
+        }
+        if let Some(handle) = self.remote_write_handle.take() {
+            join_until(handle, "remote write", deadline);
+        }
+        if let Some(handle) = self.alert_scheduler_handle.take() {
+            join_until(handle, "alert scheduler", deadline);
+        }
+        // Uploads still queued finish, then the thread ends once the channel closes
+        self.archive_uploads = None;
+        if let Some(handle) = self.archive_upload_handle.take() {
+            join_until(handle, "archive upload", deadline);
+        }
+        for output in self.outputs.drain(..) {
+            output.join_until(deadline);
+        }
+        if let Some(handle) = self.peer_input_handle.take() {
+            join_until(handle, "peer input", deadline);
+        }
+        if let Some(handle) = self.control_handle.take() {
+            join_until(handle, "control socket", deadline);
         }
-
         if let Some(handle) = self.backfill_handle.take() {
             info!("Waiting for backfill thread to finish");
-            if let Err(e) = handle.join() {
-                warn!("Failed to join backfill thread: {:?}", e);
-            }
+            join_until(handle, "backfill", deadline);
         }
 
+        if let Err(e) = self.buffer.lock().unwrap().flush_log_counts() {
+            error!("Failed to update log count rollup: {}", e);
+        }
         if checkpoint_on_shutdown {
             self.checkpoint_database();
         } else {
@@ -440,6 +1521,40 @@ impl ApplicationController {
         Ok(())
     }
 
+    /// When a shutdown starting now has to be done by, `shutdown_timeout_secs` on
+    pub fn shutdown_deadline(&self) -> Instant {
+        Instant::now() + self.shutdown_timeout
+    }
+
+    /// Store the journal entries logged but not yet read and the ones queued, until
+    /// `deadline`
+    fn drain_journal(&mut self, deadline: Instant) {
+        let mut drained = 0;
+        while Instant::now() < deadline {
+            match self.read_journal() {
+                Ok(queued) => drained += queued,
+                Err(e) => {
+                    error!("{}", e);
+                    break;
+                }
+            }
+            if self.held_batch.is_none() || self.ingest_paused.load(Ordering::Relaxed) {
+                break;
+            }
+            // Queue full; wait for the writer
+            thread::sleep(Duration::from_millis(10));
+        }
+        if drained > 0 {
+            info!("Queued {} journal entries pending at shutdown", drained);
+        }
+        if self.held_batch.is_some() && Instant::now() >= deadline {
+            warn!("Shutdown timeout reached while draining the journal");
+        }
+        if let Some(workers) = self.entry_workers.take() {
+            join_until(workers.close(), "ingest writer", deadline);
+        }
+    }
+
     pub fn checkpoint_database(&mut self) {
         if let Err(e) = self.buffer.lock().unwrap().checkpoint() {
             warn!("Failed to checkpoint database during shutdown: {}", e);
@@ -491,6 +1606,50 @@ impl ApplicationController {
     }
 }
 
+/// Answer the control commands sent while the last hour of the journal is loading,
+/// before the main loop takes them: the status with the load's progress, and an
+/// error for the rest
+fn answer_during_startup(requests: &Receiver<ControlRequest>, metrics: &Metrics) {
+    for request in requests.try_iter() {
+        let result = match request.command {
+            ControlCommand::Status => Ok(json!({
+                "starting": true,
+                "backfill": metrics.backfill_progress(),
+            })),
+            command => Err(anyhow!(
+                "Still loading the last hour of the journal; try {} again once it is done",
+                command
+            )),
+        };
+        request.respond(result);
+    }
+}
+
+/// How long a supervised thread may stay busy before it is reported stalled; None
+/// when `thread_stall_secs` is 0
+fn thread_stall_after(settings: &Settings) -> Option<Duration> {
+    (settings.thread_stall_secs > 0).then(|| Duration::from_secs(settings.thread_stall_secs))
+}
+
+/// Join the thread `handle` running `name`, giving up at `deadline` and leaving the
+/// thread behind. Returns whether it ended in time.
+pub fn join_until(handle: thread::JoinHandle<()>, name: &str, deadline: Instant) -> bool {
+    while !handle.is_finished() {
+        if Instant::now() >= deadline {
+            warn!(
+                "The {} thread did not stop within the shutdown timeout, leaving it",
+                name
+            );
+            return false;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    if let Err(e) = handle.join() {
+        warn!("Failed to join {} thread: {:?}", name, e);
+    }
+    true
+}
+
 #[derive(Debug)]
 pub struct ApplicationStatus {
     pub hostname: String,
@@ -501,6 +1660,34 @@ pub struct ApplicationStatus {
     pub database_size_bytes: u64,
 }
 
+/// Store and send an alert about livedata itself. It is sent even when storing fails,
+/// as the database may be what is broken.
+fn raise_self_alert(
+    buffer: &mut DuckDBBuffer,
+    notifier: &Notifier,
+    rule: &str,
+    state: AlertState,
+    message: String,
+) {
+    let alerts = [AlertEvent {
+        timestamp: Utc::now(),
+        rule: rule.to_string(),
+        source: SELF_ALERT_SOURCE.to_string(),
+        state,
+        value: 0.0,
+        threshold: 0.0,
+        message,
+        samples: Vec::new(),
+        link: None,
+        tags: Vec::new(),
+        details: BTreeMap::new(),
+    }];
+    if let Err(e) = buffer.add_alert_events(&alerts) {
+        error!("Failed to persist alerts: {}", e);
+    }
+    notifier.notify(&alerts);
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -527,6 +1714,72 @@ mod tests {
         assert!(!status.hostname.is_empty());
     }
 
+    #[test]
+    fn test_reload_applies_changed_settings() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut controller = ApplicationController::new(temp_dir.path(), 1, Settings::default())
+            .unwrap()
+            .with_settings_loader(|| {
+                Ok(Settings {
+                    log_retention_days: 3,
+                    process_top_n: 5,
+                    alert_rules: vec![AlertRule {
+                        name: "errors".to_string(),
+                        query: "priority<=err".to_string(),
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                })
+            });
+
+        controller.reload_settings().unwrap();
+        assert_eq!(controller.log_retention_days, 3);
+        assert_eq!(controller.process_filter.lock().unwrap().top_n, 5);
+        assert_eq!(controller.settings.alert_rules[0].name, "errors");
+
+        controller.graceful_shutdown(false).unwrap();
+    }
+
+    #[test]
+    fn test_control_commands() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut controller =
+            ApplicationController::new(temp_dir.path(), 1, Settings::default()).unwrap();
+        assert!(temp_dir.path().join(control::SOCKET_FILE).exists());
+
+        let status = controller.control(ControlCommand::Status).unwrap();
+        assert_eq!(status["total_entries"], 0);
+        assert_eq!(status["ingest_paused"], false);
+        assert_eq!(
+            controller.control(ControlCommand::Retention).unwrap()["deleted"],
+            0
+        );
+        controller.control(ControlCommand::Flush).unwrap();
+        controller.control(ControlCommand::Checkpoint).unwrap();
+
+        controller.graceful_shutdown(false).unwrap();
+        assert!(!temp_dir.path().join(control::SOCKET_FILE).exists());
+    }
+
+    #[test]
+    fn test_join_until_gives_up_at_the_deadline() {
+        let quick = thread::spawn(|| {});
+        assert!(join_until(
+            quick,
+            "quick",
+            Instant::now() + Duration::from_secs(5)
+        ));
+
+        let stuck = thread::spawn(|| thread::sleep(Duration::from_secs(10)));
+        let started = Instant::now();
+        assert!(!join_until(
+            stuck,
+            "stuck",
+            started + Duration::from_millis(50)
+        ));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
     #[test]
     fn test_graceful_shutdown_allows_reopen() {
         let temp_dir = TempDir::new().unwrap();
@@ -0,0 +1,224 @@
+use anyhow::{Result, bail};
+use duckdb::{Connection, params};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// S3-compatible object storage settings for archiving Parquet files
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ArchiveSettings {
+    /// Bucket receiving the archived files
+    pub bucket: String,
+
+    /// Key prefix inside the bucket (e.g. "livedata/host-a")
+    #[serde(default)]
+    pub prefix: String,
+
+    /// Bucket region
+    #[serde(default)]
+    pub region: Option<String>,
+
+    /// Custom endpoint for S3-compatible stores (MinIO, R2, ...), host[:port] only
+    #[serde(default)]
+    pub endpoint: Option<String>,
+
+    /// Reach `endpoint` over plain HTTP, e.g. a MinIO on the local network
+    #[serde(default)]
+    pub use_http: bool,
+
+    /// Access key id; when unset the AWS credential chain is used
+    #[serde(default)]
+    pub access_key_id: Option<String>,
+
+    /// Secret access key
+    #[serde(default)]
+    pub secret_access_key: Option<String>,
+
+    /// Delete the local copy once the upload has been verified
+    #[serde(default)]
+    pub delete_local: bool,
+}
+
+/// Archival sink uploading Parquet files to S3-compatible storage.
+///
+/// Uploads go through DuckDB's httpfs extension, so this type only renders the SQL;
+/// [`crate::duckdb_buffer::DuckDBBuffer`] sets the extension up and an
+/// [`ArchiveUploader`] runs the uploads.
+#[derive(Debug, Clone)]
+pub struct ObjectStoreArchive {
+    settings: ArchiveSettings,
+}
+
+impl ObjectStoreArchive {
+    pub fn new(settings: ArchiveSettings) -> Self {
+        Self { settings }
+    }
+
+    pub fn delete_local(&self) -> bool {
+        self.settings.delete_local
+    }
+
+    /// Statements loading httpfs and registering the bucket credentials
+    pub fn setup_sql(&self) -> Vec<String> {
+        let mut options = vec![
+            "TYPE S3".to_string(),
+            format!("SCOPE '{}'", sql_string(&self.bucket_url())),
+        ];
+        match (
+            &self.settings.access_key_id,
+            &self.settings.secret_access_key,
+        ) {
+            (Some(key_id), Some(secret)) => {
+                options.push(format!("KEY_ID '{}'", sql_string(key_id)));
+                options.push(format!("SECRET '{}'", sql_string(secret)));
+            }
+            _ => options.push("PROVIDER credential_chain".to_string()),
+        }
+        if let Some(region) = &self.settings.region {
+            options.push(format!("REGION '{}'", sql_string(region)));
+        }
+        if let Some(endpoint) = &self.settings.endpoint {
+            options.push(format!("ENDPOINT '{}'", sql_string(endpoint)));
+            options.push("URL_STYLE 'path'".to_string());
+            if self.settings.use_http {
+                options.push("USE_SSL false".to_string());
+            }
+        }
+
+        vec![
+            "INSTALL httpfs".to_string(),
+            "LOAD httpfs".to_string(),
+            format!(
+                "CREATE OR REPLACE SECRET livedata_archive ({})",
+                options.join(", ")
+            ),
+        ]
+    }
+
+    /// `s3://bucket` URL covering the whole archive
+    pub fn bucket_url(&self) -> String {
+        format!("s3://{}", self.settings.bucket)
+    }
+
+    /// Object URL for a file, keyed by its path relative to the data directory
+    pub fn object_url(&self, relative: &Path) -> String {
+        let key = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let prefix = self.settings.prefix.trim_matches('/');
+        if prefix.is_empty() {
+            format!("{}/{}", self.bucket_url(), key)
+        } else {
+            format!("{}/{}/{}", self.bucket_url(), prefix, key)
+        }
+    }
+
+    /// COPY statement uploading a local Parquet file to `url`
+    pub fn upload_sql(&self, local: &Path, url: &str) -> String {
+        format!(
+            "COPY (SELECT * FROM read_parquet('{}')) TO '{}' (FORMAT PARQUET, COMPRESSION ZSTD)",
+            sql_string(&local.to_string_lossy()),
+            sql_string(url)
+        )
+    }
+}
+
+/// Uploads finished Parquet files on a connection of its own, so that the transfer
+/// runs without holding the buffer and ingest carries on meanwhile
+pub struct ArchiveUploader {
+    conn: Connection,
+    archive: ObjectStoreArchive,
+    data_dir: PathBuf,
+}
+
+impl ArchiveUploader {
+    /// `conn` is a clone of the buffer's connection, which has httpfs and the bucket
+    /// credentials set up; objects are keyed by their path under `data_dir`
+    pub fn new(conn: Connection, archive: ObjectStoreArchive, data_dir: PathBuf) -> Self {
+        Self {
+            conn,
+            archive,
+            data_dir,
+        }
+    }
+
+    /// Upload `local` and verify the remote copy, returning its object URL. The local
+    /// copy is left for [`crate::duckdb_buffer::DuckDBBuffer::archived`] to remove.
+    pub fn upload(&self, local: &Path) -> Result<String> {
+        let relative = local.strip_prefix(&self.data_dir).unwrap_or(local);
+        let url = self.archive.object_url(relative);
+        self.conn
+            .execute(&self.archive.upload_sql(local, &url), [])?;
+
+        let count_sql = "SELECT COUNT(*) FROM read_parquet(?)";
+        let local_rows: i64 =
+            self.conn
+                .query_row(count_sql, params![local.to_string_lossy()], |row| {
+                    row.get(0)
+                })?;
+        let remote_rows: i64 = self
+            .conn
+            .query_row(count_sql, params![url], |row| row.get(0))?;
+        if local_rows != remote_rows {
+            bail!(
+                "Archive verification failed for {}: {} local rows, {} remote rows",
+                url,
+                local_rows,
+                remote_rows
+            );
+        }
+        info!("Archived {} ({} rows)", url, remote_rows);
+        Ok(url)
+    }
+}
+
+/// Escape a value for use inside a single-quoted SQL string literal
+fn sql_string(s: &str) -> String {
+    s.replace('\'', "''")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn archive(prefix: &str) -> ObjectStoreArchive {
+        ObjectStoreArchive::new(ArchiveSettings {
+            bucket: "logs".to_string(),
+            prefix: prefix.to_string(),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_object_url_joins_prefix_and_relative_path() {
+        let relative = Path::new("cold/journal_logs/20260117.parquet");
+        assert_eq!(
+            archive("/livedata/host-a/").object_url(relative),
+            "s3://logs/livedata/host-a/cold/journal_logs/20260117.parquet"
+        );
+        assert_eq!(
+            archive("").object_url(relative),
+            "s3://logs/cold/journal_logs/20260117.parquet"
+        );
+    }
+
+    #[test]
+    fn test_setup_sql_uses_credential_chain_without_keys() {
+        let sql = archive("").setup_sql();
+        assert!(sql[2].contains("PROVIDER credential_chain"));
+
+        let with_keys = ObjectStoreArchive::new(ArchiveSettings {
+            bucket: "logs".to_string(),
+            access_key_id: Some("AKIA".to_string()),
+            secret_access_key: Some("it's secret".to_string()),
+            endpoint: Some("minio:9000".to_string()),
+            ..Default::default()
+        });
+        let sql = with_keys.setup_sql();
+        assert!(sql[2].contains("SECRET 'it''s secret'"));
+        assert!(sql[2].contains("URL_STYLE 'path'"));
+        assert!(!sql[2].contains("credential_chain"));
+    }
+}
@@ -0,0 +1,210 @@
+use crate::config::Settings;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Web access level; each role includes everything the ones below it may do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Search logs and view processes
+    Viewer,
+    /// Exports, purges, alert rules and silences
+    Operator,
+    /// Schema changes and the SQL the server ran
+    Admin,
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Viewer => "viewer",
+            Self::Operator => "operator",
+            Self::Admin => "admin",
+        })
+    }
+}
+
+/// Web user (`[[web_users]]` tables), authenticating with its bearer `token` or with
+/// basic auth as `name` / `password`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WebUser {
+    pub name: String,
+    pub role: Role,
+    #[serde(default)]
+    pub token: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+}
+
+/// Caller of a web request
+#[derive(Debug, Clone, PartialEq)]
+pub struct Identity {
+    /// Matched user, `None` for anonymous requests
+    pub name: Option<String>,
+    pub role: Role,
+}
+
+impl Identity {
+    pub fn is_authenticated(&self) -> bool {
+        self.name.is_some()
+    }
+}
+
+/// Role of anonymous requests while no web credentials are configured. Routes stay
+/// open as they were before roles existed, except that an `admin_token` keeps the
+/// admin routes to itself; handlers that must never run anonymously (purges) require
+/// a matched user.
+fn anonymous_role(settings: &Settings) -> Role {
+    if settings.admin_token.is_some() {
+        Role::Operator
+    } else {
+        Role::Admin
+    }
+}
+
+/// Every configured credential: `web_users`, plus the single-credential settings
+/// (`web_token` and basic auth as viewers, `admin_token` as admin)
+fn users(settings: &Settings) -> Vec<WebUser> {
+    let mut users = settings.web_users.clone();
+    if let Some(token) = &settings.web_token {
+        users.push(WebUser {
+            name: "web_token".to_string(),
+            role: Role::Viewer,
+            token: Some(token.clone()),
+            password: None,
+        });
+    }
+    if let Ok(Some((name, password))) = settings.basic_auth() {
+        users.push(WebUser {
+            name: name.to_string(),
+            role: Role::Viewer,
+            token: None,
+            password: Some(password.to_string()),
+        });
+    }
+    if let Some(token) = &settings.admin_token {
+        users.push(WebUser {
+            name: "admin_token".to_string(),
+            role: Role::Admin,
+            token: Some(token.clone()),
+            password: None,
+        });
+    }
+    users
+}
+
+/// Identify the caller from the request's `Authorization` header value. `None` means
+/// the request must be rejected: credentials are required and missing, or presented
+/// and wrong. Without web auth, requests without matching credentials are anonymous.
+pub fn authenticate(settings: &Settings, authorization: Option<&str>) -> Option<Identity> {
+    let anonymous = Identity {
+        name: None,
+        role: anonymous_role(settings),
+    };
+    let users = users(settings);
+    let Some(value) = authorization.filter(|_| !users.is_empty()) else {
+        return (!settings.web_auth_enabled()).then_some(anonymous);
+    };
+
+    let user = if let Some(provided) = value.strip_prefix("Bearer ") {
+        users.iter().find(|user| {
+            user.token
+                .as_deref()
+                .is_some_and(|token| constant_time_eq(provided, token))
+        })
+    } else if let Some(encoded) = value.strip_prefix("Basic ")
+        && let Ok(decoded) = BASE64.decode(encoded.trim())
+        && let Ok(decoded) = String::from_utf8(decoded)
+        && let Some((name, provided)) = decoded.split_once(':')
+    {
+        users.iter().find(|user| {
+            // Check both halves so a wrong name takes as long as a wrong password
+            user.password.as_deref().is_some_and(|password| {
+                constant_time_eq(name, &user.name) & constant_time_eq(provided, password)
+            })
+        })
+    } else {
+        None
+    };
+    user.map(|user| Identity {
+        name: Some(user.name.clone()),
+        role: user.role,
+    })
+}
+
+/// Compare every byte so the time taken does not reveal the matching prefix
+fn constant_time_eq(provided: &str, expected: &str) -> bool {
+    provided.len() == expected.len()
+        && provided
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn basic(credentials: &str) -> String {
+        format!("Basic {}", BASE64.encode(credentials))
+    }
+
+    #[test]
+    fn test_authenticate_resolves_roles() {
+        let settings = Settings {
+            web_users: vec![
+                WebUser {
+                    name: "alice".to_string(),
+                    role: Role::Operator,
+                    token: Some("alice-token".to_string()),
+                    password: Some("alice-pw".to_string()),
+                },
+                WebUser {
+                    name: "bob".to_string(),
+                    role: Role::Viewer,
+                    token: None,
+                    password: Some("bob-pw".to_string()),
+                },
+            ],
+            admin_token: Some("root".to_string()),
+            ..Settings::default()
+        };
+        let role = |authorization: &str| {
+            authenticate(&settings, Some(authorization)).map(|identity| identity.role)
+        };
+
+        assert_eq!(role("Bearer alice-token"), Some(Role::Operator));
+        assert_eq!(role(&basic("alice:alice-pw")), Some(Role::Operator));
+        assert_eq!(role(&basic("bob:bob-pw")), Some(Role::Viewer));
+        assert_eq!(role("Bearer root"), Some(Role::Admin));
+        // Passwords only work for their own user
+        assert_eq!(role(&basic("bob:alice-pw")), None);
+        assert_eq!(role("Bearer bob-pw"), None);
+        assert_eq!(authenticate(&settings, None), None);
+    }
+
+    #[test]
+    fn test_anonymous_without_web_auth() {
+        let open = Settings::default();
+        let identity = authenticate(&open, Some("Bearer anything")).unwrap();
+        assert!(!identity.is_authenticated());
+        assert_eq!(identity.role, Role::Admin);
+
+        // An admin token alone keeps reads open but rejects wrong tokens
+        let settings = Settings {
+            admin_token: Some("root".to_string()),
+            ..Settings::default()
+        };
+        let identity = authenticate(&settings, None).unwrap();
+        assert!(!identity.is_authenticated());
+        assert_eq!(identity.role, Role::Operator);
+        assert_eq!(authenticate(&settings, Some("Bearer wrong")), None);
+        assert_eq!(
+            authenticate(&settings, Some("Bearer root")).unwrap().role,
+            Role::Admin
+        );
+    }
+}
@@ -0,0 +1,270 @@
+use crate::control::{self, ControlCommand};
+use crate::sql_trace::trace_sql;
+use anyhow::{Context, Result, anyhow};
+use chrono::Utc;
+use duckdb::Connection;
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use log::{info, warn};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+const SNAPSHOT_PREFIX: &str = "livedata-";
+const SNAPSHOT_SUFFIX: &str = ".duckdb";
+const COMPRESSED_SUFFIX: &str = ".duckdb.gz";
+
+/// Directory under data_dir a running collector copies its database into for
+/// `livedata backup`, which moves the copy into place
+pub const STAGING_DIR: &str = "backup-staging";
+
+#[derive(Debug, Clone, Default)]
+pub struct BackupOptions {
+    /// Gzip the snapshot
+    pub compress: bool,
+    /// Keep only this many snapshots in the destination, deleting the oldest
+    pub keep: Option<usize>,
+}
+
+/// Write a consistent snapshot of the database behind `conn` into `dest_dir`.
+///
+/// The WAL is checkpointed into the database file, which is then copied; the caller
+/// must hold the only writing connection for the duration, so nothing can be committed
+/// between the checkpoint and the copy. Snapshots are named
+/// `livedata-YYYYMMDDTHHMMSSmmmZ.duckdb[.gz]` and are written under a temporary name,
+/// so an interrupted backup never looks complete.
+pub fn snapshot(
+    conn: &Connection,
+    db_path: &Path,
+    dest_dir: &Path,
+    options: &BackupOptions,
+) -> Result<PathBuf> {
+    trace_sql("FORCE CHECKPOINT");
+    conn.execute("FORCE CHECKPOINT", [])?;
+    write_snapshot(db_path, dest_dir, options)
+}
+
+/// Copy the checkpointed database file at `db_path` into `dest_dir` as a snapshot,
+/// see [`snapshot`]
+pub fn write_snapshot(db_path: &Path, dest_dir: &Path, options: &BackupOptions) -> Result<PathBuf> {
+    fs::create_dir_all(dest_dir)?;
+    let suffix = if options.compress {
+        COMPRESSED_SUFFIX
+    } else {
+        SNAPSHOT_SUFFIX
+    };
+    let name = format!(
+        "{}{}{}",
+        SNAPSHOT_PREFIX,
+        Utc::now().format("%Y%m%dT%H%M%S%3fZ"),
+        suffix
+    );
+    let path = dest_dir.join(&name);
+    let partial = dest_dir.join(format!("{}.partial", name));
+    info!("Backing up {} to {}", db_path.display(), path.display());
+
+    let result = if options.compress {
+        compress_file(db_path, &partial)
+    } else {
+        fs::copy(db_path, &partial).map(|_| ()).map_err(Into::into)
+    };
+    if let Err(e) = result {
+        let _ = fs::remove_file(&partial);
+        return Err(e);
+    }
+    fs::rename(&partial, &path)?;
+    info!("Database backup complete: {}", path.display());
+
+    if let Some(keep) = options.keep {
+        prune(dest_dir, keep)?;
+    }
+    Ok(path)
+}
+
+/// Snapshot the database in `data_dir` from a separate process.
+///
+/// DuckDB lets only one process open the file, so a running collector is asked over
+/// the control socket to copy its database into [`STAGING_DIR`] while it holds the
+/// writer lock; the copy is then compressed and moved into `dest_dir` here, without
+/// holding up ingestion. Otherwise the file is opened directly.
+pub fn backup_data_dir(
+    data_dir: &Path,
+    dest_dir: &Path,
+    options: &BackupOptions,
+) -> Result<PathBuf> {
+    let socket = control::socket_path(data_dir);
+    if control::is_listening(&socket) {
+        let result = control::send_command(&socket, ControlCommand::Backup)?;
+        let staged = result["path"]
+            .as_str()
+            .map(PathBuf::from)
+            .ok_or_else(|| anyhow!("the collector did not say where it copied the database"))?;
+        let written = write_snapshot(&staged, dest_dir, options);
+        if let Err(e) = fs::remove_file(&staged) {
+            warn!("Failed to remove {}: {}", staged.display(), e);
+        }
+        return written;
+    }
+
+    let db_path = data_dir.join("livedata.duckdb");
+    if !db_path.exists() {
+        anyhow::bail!("No database found at {}", db_path.display());
+    }
+    // Open directly rather than through DuckDBBuffer: a lock error here must not be
+    // mistaken for corruption and trigger recovery.
+    let conn = Connection::open(&db_path).with_context(|| {
+        format!(
+            "Failed to open {} (is the collector still running?)",
+            db_path.display()
+        )
+    })?;
+    snapshot(&conn, &db_path, dest_dir, options)
+}
+
+/// Snapshots in `dest_dir`, oldest first
+pub fn list(dest_dir: &Path) -> Result<Vec<PathBuf>> {
+    if !dest_dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut snapshots = Vec::new();
+    for entry in fs::read_dir(dest_dir)? {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        if name.starts_with(SNAPSHOT_PREFIX)
+            && (name.ends_with(SNAPSHOT_SUFFIX) || name.ends_with(COMPRESSED_SUFFIX))
+        {
+            snapshots.push(path);
+        }
+    }
+    // The timestamp in the name sorts chronologically
+    snapshots.sort();
+    Ok(snapshots)
+}
+
+/// Delete all but the newest `keep` snapshots in `dest_dir`; returns the removed paths
+pub fn prune(dest_dir: &Path, keep: usize) -> Result<Vec<PathBuf>> {
+    let snapshots = list(dest_dir)?;
+    let excess = snapshots.len().saturating_sub(keep);
+    let mut removed = Vec::with_capacity(excess);
+    for path in snapshots.into_iter().take(excess) {
+        match fs::remove_file(&path) {
+            Ok(()) => {
+                info!("Removed old backup: {}", path.display());
+                removed.push(path);
+            }
+            Err(e) => warn!("Failed to remove old backup {}: {}", path.display(), e),
+        }
+    }
+    Ok(removed)
+}
+
+/// Copy (decompressing if needed) `snapshot` to `db_path`
+pub fn restore(snapshot: &Path, db_path: &Path) -> Result<()> {
+    let compressed = snapshot
+        .to_str()
+        .is_some_and(|s| s.ends_with(COMPRESSED_SUFFIX));
+    if compressed {
+        let mut reader = GzDecoder::new(BufReader::new(File::open(snapshot)?));
+        let mut writer = BufWriter::new(File::create(db_path)?);
+        io::copy(&mut reader, &mut writer)?;
+        writer.flush()?;
+    } else {
+        fs::copy(snapshot, db_path)?;
+    }
+    Ok(())
+}
+
+fn compress_file(src: &Path, dest: &Path) -> Result<()> {
+    let mut reader = BufReader::new(File::open(src)?);
+    let mut encoder = GzEncoder::new(BufWriter::new(File::create(dest)?), Compression::default());
+    io::copy(&mut reader, &mut encoder)?;
+    encoder.finish()?.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn create_db(dir: &Path) -> PathBuf {
+        let db_path = dir.join("livedata.duckdb");
+        let conn = Connection::open(&db_path).unwrap();
+        conn.execute("CREATE TABLE t (v INTEGER)", []).unwrap();
+        conn.execute("INSERT INTO t SELECT * FROM range(1000)", [])
+            .unwrap();
+        db_path
+    }
+
+    fn row_count(db_path: &Path) -> i64 {
+        let conn = Connection::open(db_path).unwrap();
+        conn.query_row("SELECT COUNT(*) FROM t", [], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_snapshot_and_restore_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = create_db(temp_dir.path());
+        let dest = temp_dir.path().join("backups");
+
+        for compress in [false, true] {
+            let options = BackupOptions {
+                compress,
+                keep: None,
+            };
+            let snapshot = backup_data_dir(temp_dir.path(), &dest, &options).unwrap();
+            assert_eq!(
+                snapshot.to_str().unwrap().ends_with(".gz"),
+                compress,
+                "{}",
+                snapshot.display()
+            );
+
+            let restored = temp_dir
+                .path()
+                .join(format!("restored-{}.duckdb", compress));
+            restore(&snapshot, &restored).unwrap();
+            assert_eq!(row_count(&restored), 1000);
+        }
+        assert_eq!(list(&dest).unwrap().len(), 2);
+        // No temporary files left behind
+        assert_eq!(fs::read_dir(&dest).unwrap().count(), 2);
+        // Still usable: the backup only checkpointed it
+        assert_eq!(row_count(&db_path), 1000);
+    }
+
+    #[test]
+    fn test_keep_prunes_oldest_snapshots() {
+        let temp_dir = TempDir::new().unwrap();
+        create_db(temp_dir.path());
+        let dest = temp_dir.path().join("backups");
+        // Files that are not snapshots are left alone
+        fs::create_dir_all(&dest).unwrap();
+        fs::write(dest.join("notes.txt"), "keep me").unwrap();
+
+        let options = BackupOptions {
+            compress: false,
+            keep: Some(2),
+        };
+        let mut taken = Vec::new();
+        for _ in 0..3 {
+            taken.push(backup_data_dir(temp_dir.path(), &dest, &options).unwrap());
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+
+        assert_eq!(list(&dest).unwrap(), taken[1..].to_vec());
+        assert!(dest.join("notes.txt").exists());
+    }
+
+    #[test]
+    fn test_backup_requires_existing_database() {
+        let temp_dir = TempDir::new().unwrap();
+        let dest = temp_dir.path().join("backups");
+        assert!(backup_data_dir(temp_dir.path(), &dest, &BackupOptions::default()).is_err());
+        assert!(list(&dest).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,294 @@
+//! Chat notification channels (Slack, Discord, Telegram). Each `[[chat_notifiers]]`
+//! entry posts into one chat, optionally only for rules carrying one of its tags, and
+//! holds back repeats of the same alert within its `repeat_interval`.
+
+use crate::config::parse_duration;
+use crate::notifications::{
+    AlertEvent, AlertState, NotificationChannel, check_template, render_template,
+};
+use crate::webhook::{Delivery, Poster, WebhookSettings};
+use anyhow::{Context, Result};
+use chrono::{DateTime, TimeDelta, Utc};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChatService {
+    Slack,
+    Discord,
+    Telegram,
+}
+
+impl ChatService {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Slack => "slack",
+            Self::Discord => "discord",
+            Self::Telegram => "telegram",
+        }
+    }
+
+    /// Longest message the service accepts, in characters
+    fn max_message_chars(self) -> usize {
+        match self {
+            Self::Slack => 40_000,
+            Self::Discord => 2_000,
+            Self::Telegram => 4_096,
+        }
+    }
+}
+
+/// A chat alerts are posted into (`[[chat_notifiers]]` table)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatSettings {
+    pub service: ChatService,
+
+    /// Incoming webhook URL (Slack and Discord)
+    #[serde(default)]
+    pub url: Option<String>,
+
+    /// Bot token (Telegram)
+    #[serde(default)]
+    pub bot_token: Option<String>,
+
+    /// Chat the bot posts into (Telegram)
+    #[serde(default)]
+    pub chat_id: Option<String>,
+
+    /// Only post alerts of rules with one of these tags (empty = every alert)
+    #[serde(default)]
+    pub tags: Vec<String>,
+
+    /// Message template, with placeholders or in minijinja syntax; see
+    /// `render_template`
+    #[serde(default = "default_template")]
+    pub template: String,
+
+    /// Repeats of an alert (same rule and state) within this time are held back and
+    /// counted in the next message that is posted
+    #[serde(default = "default_repeat_interval")]
+    pub repeat_interval: String,
+}
+
+fn default_template() -> String {
+    "[{state}] {rule}: {message}\n{link}".to_string()
+}
+
+fn default_repeat_interval() -> String {
+    "15m".to_string()
+}
+
+/// Last post per rule and state, with the repeats held back since
+type PostLog = HashMap<(String, AlertState), (DateTime<Utc>, u32)>;
+
+pub struct ChatChannel {
+    settings: ChatSettings,
+    repeat_interval: TimeDelta,
+    poster: Poster,
+    last_posted: Mutex<PostLog>,
+}
+
+impl ChatChannel {
+    pub fn start(settings: ChatSettings) -> Result<Self> {
+        let url = match settings.service {
+            ChatService::Slack | ChatService::Discord => {
+                settings.url.clone().context("`url` is required")?
+            }
+            ChatService::Telegram => {
+                settings.chat_id.as_ref().context("`chat_id` is required")?;
+                let token = settings
+                    .bot_token
+                    .as_ref()
+                    .context("`bot_token` is required")?;
+                format!("https://api.telegram.org/bot{}/sendMessage", token)
+            }
+        };
+        check_template(&settings.template).context("template")?;
+        let repeat_interval =
+            parse_duration(&settings.repeat_interval).context("repeat_interval")?;
+        let poster = Poster::start(settings.service.as_str(), WebhookSettings::for_url(url))?;
+        Ok(Self {
+            settings,
+            repeat_interval,
+            poster,
+            last_posted: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Whether `alert` is posted, with the number of its repeats held back before it
+    fn admit(&self, alert: &AlertEvent) -> Option<u32> {
+        if !self.settings.tags.is_empty()
+            && !alert
+                .tags
+                .iter()
+                .any(|tag| self.settings.tags.contains(tag))
+        {
+            return None;
+        }
+        let mut last_posted = self.last_posted.lock().unwrap();
+        let key = (alert.rule.clone(), alert.state);
+        if let Some((posted, held_back)) = last_posted.get_mut(&key)
+            && alert.timestamp - *posted < self.repeat_interval
+        {
+            *held_back += 1;
+            debug!(
+                "Holding back repeated alert {} for {}",
+                alert.rule, self.poster.name
+            );
+            return None;
+        }
+        let held_back = last_posted
+            .insert(key, (alert.timestamp, 0))
+            .map_or(0, |(_, held_back)| held_back);
+        Some(held_back)
+    }
+
+    fn payload(&self, alert: &AlertEvent, held_back: u32) -> serde_json::Value {
+        let mut text = render_template(&self.settings.template, alert)
+            .trim_end()
+            .to_string();
+        if held_back > 0 {
+            text.push_str(&format!("\n({} repeats held back)", held_back));
+        }
+        let max_chars = self.settings.service.max_message_chars();
+        if text.chars().count() > max_chars {
+            text = text.chars().take(max_chars - 1).collect();
+            text.push('…');
+        }
+        match self.settings.service {
+            ChatService::Slack => json!({ "text": text }),
+            ChatService::Discord => json!({ "content": text }),
+            ChatService::Telegram => json!({ "chat_id": self.settings.chat_id, "text": text }),
+        }
+    }
+}
+
+impl NotificationChannel for ChatChannel {
+    fn name(&self) -> &str {
+        &self.poster.name
+    }
+
+    fn send(&self, alert: &AlertEvent) -> Result<()> {
+        let Some(held_back) = self.admit(alert) else {
+            return Ok(());
+        };
+        self.poster.post(Delivery {
+            rule: alert.rule.clone(),
+            body: self.payload(alert, held_back),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn alert(rule: &str, state: AlertState, minute: i64, tags: &[&str]) -> AlertEvent {
+        AlertEvent {
+            timestamp: DateTime::<Utc>::UNIX_EPOCH + TimeDelta::minutes(minute),
+            rule: rule.to_string(),
+            source: "log".to_string(),
+            state,
+            value: 12.0,
+            threshold: 10.0,
+            message: "12 entries matched in the last 5m (threshold 10)".to_string(),
+            samples: Vec::new(),
+            link: None,
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            details: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_routing_and_repeats() {
+        let channel = ChatChannel::start(
+            toml::from_str(
+                r#"
+service = "slack"
+url = "https://hooks.slack.com/services/T0/B0/secret"
+tags = ["db"]
+repeat_interval = "10m"
+"#,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(channel.name(), "slack hooks.slack.com");
+
+        assert_eq!(
+            channel.admit(&alert("web", AlertState::Firing, 0, &["web"])),
+            None
+        );
+        assert_eq!(
+            channel.admit(&alert("pg", AlertState::Firing, 0, &["db"])),
+            Some(0)
+        );
+        // Resolving is not a repeat of firing
+        assert_eq!(
+            channel.admit(&alert("pg", AlertState::Resolved, 1, &["db"])),
+            Some(0)
+        );
+        assert_eq!(
+            channel.admit(&alert("pg", AlertState::Firing, 2, &["db"])),
+            None
+        );
+        assert_eq!(
+            channel.admit(&alert("pg", AlertState::Firing, 5, &["db"])),
+            None
+        );
+        assert_eq!(
+            channel.admit(&alert("pg", AlertState::Firing, 10, &["db"])),
+            Some(2)
+        );
+
+        let payload = channel.payload(&alert("pg", AlertState::Firing, 10, &["db"]), 2);
+        assert_eq!(
+            payload["text"],
+            "[firing] pg: 12 entries matched in the last 5m (threshold 10)\n(2 repeats held back)"
+        );
+    }
+
+    #[test]
+    fn test_service_payloads() {
+        let telegram = ChatChannel::start(
+            toml::from_str(
+                r#"
+service = "telegram"
+bot_token = "123:abc"
+chat_id = "-100200"
+template = "{rule} {state}"
+"#,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(telegram.name(), "telegram api.telegram.org");
+        assert_eq!(
+            telegram.payload(&alert("pg", AlertState::Resolved, 0, &[]), 0),
+            json!({ "chat_id": "-100200", "text": "pg resolved" })
+        );
+
+        let discord = ChatChannel::start(ChatSettings {
+            service: ChatService::Discord,
+            url: Some("https://discord.com/api/webhooks/1/secret".to_string()),
+            bot_token: None,
+            chat_id: None,
+            tags: Vec::new(),
+            template: "x".repeat(3_000),
+            repeat_interval: default_repeat_interval(),
+        })
+        .unwrap();
+        let payload = discord.payload(&alert("pg", AlertState::Firing, 0, &[]), 0);
+        assert_eq!(payload["content"].as_str().unwrap().chars().count(), 2_000);
+
+        assert!(
+            ChatChannel::start(toml::from_str("service = \"telegram\"\nchat_id = \"1\"").unwrap())
+                .is_err()
+        );
+    }
+}
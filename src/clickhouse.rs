@@ -0,0 +1,235 @@
+//! ClickHouse output: inserts each batch into a table over ClickHouse's HTTP interface
+//! as `JSONEachRow`, with a configurable mapping from table columns to entry values.
+//! Meant for keeping everything centrally for the long term while livedata keeps
+//! searching its own recent logs.
+
+use crate::log_entry::LogEntry;
+use crate::outputs::check_response;
+use crate::search_query::field_column;
+use anyhow::{Result, bail};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClickHouseSettings {
+    /// HTTP interface, e.g. "http://clickhouse:8123"
+    pub url: String,
+
+    /// Table to insert into, e.g. "logs.journal"
+    pub table: String,
+
+    /// Database `table` is looked up in when it names none
+    #[serde(default)]
+    pub database: Option<String>,
+
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+
+    /// Table columns and what fills them: `timestamp` (microseconds since the epoch,
+    /// which a `DateTime64(6)` column reads as UTC whatever its time zone), `source`, `fields` (all journal fields as a JSON object) or a field by
+    /// its search name (`unit`, `host`, `priority`, `syslog_identifier`, ...). Columns
+    /// of fields an entry lacks get their default.
+    #[serde(default = "default_columns")]
+    pub columns: BTreeMap<String, String>,
+}
+
+fn default_columns() -> BTreeMap<String, String> {
+    ["timestamp", "host", "unit", "priority", "message", "fields"]
+        .map(|column| (column.to_string(), column.to_string()))
+        .into()
+}
+
+/// `name` quoted as a ClickHouse identifier, each part of a dotted name on its own
+fn quote_identifier(name: &str) -> String {
+    name.split('.')
+        .map(|part| format!("`{}`", part.replace('\\', "\\\\").replace('`', "\\`")))
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// Where a column's value comes from
+#[derive(Debug, PartialEq)]
+enum ColumnValue {
+    Timestamp,
+    Source,
+    Fields,
+    /// A journal field, by its name in the journal
+    Field(String),
+}
+
+impl ColumnValue {
+    fn parse(name: &str) -> Self {
+        match name {
+            "timestamp" => Self::Timestamp,
+            "source" => Self::Source,
+            "fields" => Self::Fields,
+            other => Self::Field(field_column(other).to_uppercase()),
+        }
+    }
+
+    fn of(&self, entry: &LogEntry) -> Option<Value> {
+        Some(match self {
+            // A date and time without a zone would be read in the server's time zone
+            Self::Timestamp => Value::from(entry.timestamp.timestamp_micros()),
+            Self::Source => Value::String(entry.source.clone()),
+            Self::Fields => Value::String(
+                serde_json::to_string(&entry.fields.iter().collect::<BTreeMap<_, _>>()).ok()?,
+            ),
+            Self::Field(field) => Value::String(entry.get_field(field)?.clone()),
+        })
+    }
+}
+
+pub struct ClickHouseSink {
+    client: reqwest::Client,
+    url: Url,
+    username: Option<String>,
+    password: Option<String>,
+    /// Column names with where their values come from
+    columns: Vec<(String, ColumnValue)>,
+}
+
+impl ClickHouseSink {
+    pub fn new(settings: &ClickHouseSettings, timeout: Duration) -> Result<Self> {
+        if settings.table.is_empty() {
+            bail!("no table given");
+        }
+        if settings.columns.is_empty() {
+            bail!("at least one column is needed");
+        }
+        let columns: Vec<(String, ColumnValue)> = settings
+            .columns
+            .iter()
+            .map(|(column, value)| (column.clone(), ColumnValue::parse(value)))
+            .collect();
+        let mut url = Url::parse(&settings.url)?;
+        {
+            let mut query = url.query_pairs_mut();
+            query.append_pair("query", &insert_query(&settings.table, &columns));
+            if let Some(database) = &settings.database {
+                query.append_pair("database", database);
+            }
+        }
+        Ok(Self {
+            client: reqwest::Client::builder().timeout(timeout).build()?,
+            url,
+            username: settings.username.clone(),
+            password: settings.password.clone(),
+            columns,
+        })
+    }
+
+    /// `entry` as a `JSONEachRow` row, leaving out the columns it has no value for
+    fn row(&self, entry: &LogEntry) -> Map<String, Value> {
+        self.columns
+            .iter()
+            .filter_map(|(column, value)| Some((column.clone(), value.of(entry)?)))
+            .collect()
+    }
+
+    pub async fn send(&self, batch: &[Arc<LogEntry>]) -> Result<()> {
+        let mut body = Vec::new();
+        for entry in batch {
+            serde_json::to_writer(&mut body, &self.row(entry))?;
+            body.push(b'\n');
+        }
+        let mut request = self.client.post(self.url.clone()).body(body);
+        if let Some(username) = &self.username {
+            request = request.header("X-ClickHouse-User", username);
+        }
+        if let Some(password) = &self.password {
+            request = request.header("X-ClickHouse-Key", password);
+        }
+        check_response(request.send().await).await
+    }
+}
+
+fn insert_query(table: &str, columns: &[(String, ColumnValue)]) -> String {
+    let names: Vec<String> = columns
+        .iter()
+        .map(|(column, _)| quote_identifier(column))
+        .collect();
+    format!(
+        "INSERT INTO {} ({}) FORMAT JSONEachRow",
+        quote_identifier(table),
+        names.join(", ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::outputs::{OutputSettings, SinkSettings};
+    use chrono::DateTime;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_rows_and_insert_query() {
+        let settings: OutputSettings = toml::from_str(
+            r#"
+name = "archive"
+kind = "clickhouse"
+url = "http://clickhouse:8123"
+table = "logs.journal"
+username = "livedata"
+
+[columns]
+ts = "timestamp"
+service = "unit"
+msg = "message"
+request_id = "REQUEST_ID"
+"#,
+        )
+        .unwrap();
+        let SinkSettings::Clickhouse(clickhouse) = &settings.sink else {
+            panic!("not a ClickHouse output: {:?}", settings.sink);
+        };
+        let sink = ClickHouseSink::new(clickhouse, Duration::from_secs(1)).unwrap();
+        let query: HashMap<_, _> = sink.url.query_pairs().into_owned().collect();
+        assert_eq!(
+            query["query"],
+            "INSERT INTO `logs`.`journal` (`msg`, `request_id`, `service`, `ts`) \
+             FORMAT JSONEachRow"
+        );
+
+        let fields: HashMap<String, String> =
+            [("_SYSTEMD_UNIT", "nginx.service"), ("MESSAGE", "GET /")]
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect();
+        let entry = LogEntry::new(
+            DateTime::from_timestamp(1_700_000_000, 1_500_000).unwrap(),
+            fields,
+        );
+        assert_eq!(
+            Value::Object(sink.row(&entry)),
+            serde_json::json!({
+                "msg": "GET /",
+                "service": "nginx.service",
+                "ts": 1_700_000_000_001_500_i64,
+            })
+        );
+
+        let defaults: OutputSettings = toml::from_str(
+            "name = \"ch\"\nkind = \"clickhouse\"\nurl = \"http://ch:8123\"\ntable = \"journal\"",
+        )
+        .unwrap();
+        let SinkSettings::Clickhouse(defaults) = &defaults.sink else {
+            panic!("not a ClickHouse output");
+        };
+        let sink = ClickHouseSink::new(defaults, Duration::from_secs(1)).unwrap();
+        let row = sink.row(&entry);
+        assert_eq!(
+            row["fields"],
+            r#"{"MESSAGE":"GET /","_SYSTEMD_UNIT":"nginx.service"}"#
+        );
+        assert!(!row.contains_key("host"));
+        assert_eq!(quote_identifier("odd`name"), "`odd\\`name`");
+    }
+}
@@ -1,8 +1,91 @@
-use anyhow::{Context, Result};
+use crate::agent::AgentSettings;
+use crate::archive::ArchiveSettings;
+use crate::auth::WebUser;
+use crate::chat::ChatSettings;
+use crate::email::SmtpSettings;
+use crate::oidc::OidcSettings;
+use crate::outputs::OutputSettings;
+use crate::peer_replication::PeerInputSettings;
+use crate::remote_write::RemoteWriteSettings;
+use crate::webhook::WebhookSettings;
+use anyhow::{Context, Result, bail};
+use chrono::TimeDelta;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::fs;
 use std::path::{Path, PathBuf};
 
+/// Settings a running instance applies when its config is reloaded (SIGHUP); changes
+/// to the others take effect after a restart
+pub const RELOADABLE_SETTINGS: &[&str] = &[
+    "log_retention_days",
+    "log_max_size_gb",
+    "process_retention_days",
+    "process_max_size_gb",
+    "process_raw_retention_days",
+    "retention_rules",
+    "cleanup_interval_minutes",
+    "hot_storage_days",
+    "parquet_export",
+    "promote_extra_fields_after",
+    "wal_checkpoint_bytes",
+    "idle_checkpoint_secs",
+    "min_free_disk_bytes",
+    "shutdown_timeout_secs",
+    "thread_stall_secs",
+    "dropped_fields",
+    "process_names",
+    "process_users",
+    "process_min_cpu_percent",
+    "process_min_memory_bytes",
+    "process_top_n",
+    "alert_rules",
+];
+
+/// A setting that differs between two configs, with its values as JSON (secrets
+/// masked)
+#[derive(Debug, Clone, PartialEq)]
+pub struct SettingChange {
+    pub key: String,
+    pub old: String,
+    pub new: String,
+}
+
+/// Words in the keys of settings whose values are credentials. Webhook and chat URLs
+/// embed their token, and `key` covers API keys.
+const SECRET_KEY_WORDS: &[&str] = &["password", "token", "secret", "url", "authorization", "key"];
+
+/// `value` of setting `key` with the strings under keys naming secrets, and every
+/// value of `headers` maps, replaced, for logging
+fn mask_secrets(key: &str, value: Value) -> Value {
+    let key = key.to_ascii_lowercase();
+    match value {
+        Value::String(_) if SECRET_KEY_WORDS.iter().any(|w| key.contains(w)) => {
+            Value::String("***".to_string())
+        }
+        Value::Object(map) if key == "headers" => Value::Object(
+            map.into_iter()
+                .map(|(name, _)| (name, Value::String("***".to_string())))
+                .collect(),
+        ),
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .map(|(key, value)| {
+                    let value = mask_secrets(&key, value);
+                    (key, value)
+                })
+                .collect(),
+        ),
+        Value::Array(values) => Value::Array(
+            values
+                .into_iter()
+                .map(|value| mask_secrets(&key, value))
+                .collect(),
+        ),
+        other => other,
+    }
+}
+
 /// Application configuration with support for multiple sources
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Settings {
@@ -18,10 +101,185 @@ pub struct Settings {
     /// Maximum process metrics database size in GB
     pub process_max_size_gb: f64,
 
+    /// Days raw process samples are kept; after that only their per-minute rollups
+    /// remain, for `process_retention_days` (unset = as long as the rollups)
+    #[serde(default)]
+    pub process_raw_retention_days: Option<u32>,
+
+    /// Only persist metrics of processes with these names (empty = all)
+    #[serde(default)]
+    pub process_names: Vec<String>,
+
+    /// Only persist metrics of processes owned by these users, by login name or uid
+    /// (empty = all)
+    #[serde(default)]
+    pub process_users: Vec<String>,
+
+    /// Processes below both this CPU percentage and `process_min_memory_bytes` are not
+    /// persisted (0 = no CPU threshold)
+    #[serde(default)]
+    pub process_min_cpu_percent: f32,
+
+    /// Processes below both this resident memory and `process_min_cpu_percent` are not
+    /// persisted (0 = no memory threshold)
+    #[serde(default)]
+    pub process_min_memory_bytes: u64,
+
+    /// Persist at most this many processes per sample, the busiest by CPU then memory
+    /// (0 = unlimited)
+    #[serde(default)]
+    pub process_top_n: usize,
+
+    /// Only persist a process when its CPU moved by more than this many percentage
+    /// points, or its memory by more than this percent, since its last stored row
+    /// (unset = store every sample)
+    #[serde(default)]
+    pub process_delta_epsilon: Option<f64>,
+
+    /// Minutes between full process samples while `process_delta_epsilon` is set
+    #[serde(default = "default_process_keyframe_minutes")]
+    pub process_keyframe_minutes: u64,
+
+    /// Also sample temperatures, fan speeds and battery charge into system_metrics
+    #[serde(default)]
+    pub collect_sensors: bool,
+
+    /// Also sample GPU utilization and memory (NVIDIA and amdgpu) into gpu_metrics
+    #[serde(default)]
+    pub collect_gpu: bool,
+
     /// Cleanup interval in minutes (clamped to 5-15 range)
     #[serde(default = "default_cleanup_interval")]
     pub cleanup_interval_minutes: u32,
 
+    /// Days of logs kept in DuckDB before rolling into Parquet cold storage (unset = never)
+    #[serde(default)]
+    pub hot_storage_days: Option<u32>,
+
+    /// Export each completed minute of logs to `<data_dir>/parquet/` as it closes
+    #[serde(default)]
+    pub parquet_export: bool,
+
+    /// DuckDB connections the web server runs its queries on, apart from the writer's
+    #[serde(default = "default_web_read_connections")]
+    pub web_read_connections: usize,
+
+    /// Web search/histogram queries running longer than this are interrupted (504)
+    #[serde(default = "default_query_timeout_ms")]
+    pub query_timeout_ms: u64,
+
+    /// Promote extra_fields keys found in at least this many rows over the last day
+    /// into their own columns (unset = only on request via the web API)
+    #[serde(default)]
+    pub promote_extra_fields_after: Option<u64>,
+
+    /// Journal fields never stored (e.g. `_CMDLINE`, `_SELINUX_CONTEXT`). Their columns
+    /// stay in the schema but are left NULL and hidden from /api/columns.
+    #[serde(default)]
+    pub dropped_fields: Vec<String>,
+
+    /// Checkpoint the database once its write-ahead log grows past this many bytes
+    #[serde(default = "default_wal_checkpoint_bytes")]
+    pub wal_checkpoint_bytes: u64,
+
+    /// Checkpoint a non-empty write-ahead log after this many seconds without new log
+    /// entries (0 = only on size)
+    #[serde(default = "default_idle_checkpoint_secs")]
+    pub idle_checkpoint_secs: u64,
+
+    /// Free space on the data directory's filesystem below which old data is dropped
+    /// and ingestion pauses (0 = never, the default)
+    #[serde(default)]
+    pub min_free_disk_bytes: u64,
+
+    /// /health/ready fails once the newest ingested journal entry is older than this
+    /// many seconds (0 = unchecked, for hosts that can be quiet for long stretches)
+    #[serde(default)]
+    pub ready_max_entry_age_secs: u64,
+
+    /// Requests per minute each client (bearer token, session or IP address) may make
+    /// to /api routes, with bursts up to the same number (0 = unlimited)
+    #[serde(default)]
+    pub api_requests_per_minute: u32,
+
+    /// Seconds that results of /api/filters, /api/columns and repeated searches and
+    /// histograms are reused while no entries are stored or deleted (0 = no caching)
+    #[serde(default = "default_query_cache_ttl_secs")]
+    pub query_cache_ttl_secs: u64,
+
+    /// Also keep the statements shown on /api/debug/queries in the api_query_log table,
+    /// for as long as logs are retained
+    #[serde(default)]
+    pub query_log_table: bool,
+
+    /// Bearer token granting the admin role on the web server. Without any web
+    /// credentials purges are disabled.
+    #[serde(default)]
+    pub admin_token: Option<String>,
+
+    /// Bearer token granting the viewer role. Setting this, basic auth or `web_users`
+    /// requires credentials on every web route except /health.
+    #[serde(default)]
+    pub web_token: Option<String>,
+
+    /// HTTP basic auth user with the viewer role (requires `web_password`)
+    #[serde(default)]
+    pub web_username: Option<String>,
+
+    /// HTTP basic auth password for `web_username`
+    #[serde(default)]
+    pub web_password: Option<String>,
+
+    /// Web users with their roles (`[[web_users]]` tables)
+    #[serde(default)]
+    pub web_users: Vec<WebUser>,
+
+    /// OpenID Connect single sign-on (`[oidc]` table): browser logins with session
+    /// cookies, and provider-issued bearer tokens for the API
+    #[serde(default)]
+    pub oidc: Option<OidcSettings>,
+
+    /// PEM certificate chain the web server terminates TLS with (requires
+    /// `tls_key_path`; unset = plaintext HTTP). Reloaded on SIGHUP.
+    #[serde(default)]
+    pub tls_cert_path: Option<PathBuf>,
+
+    /// PEM private key for `tls_cert_path`
+    #[serde(default)]
+    pub tls_key_path: Option<PathBuf>,
+
+    /// Minutes a finished background export stays downloadable before its file is
+    /// deleted
+    #[serde(default = "default_export_job_ttl_minutes")]
+    pub export_job_ttl_minutes: u64,
+
+    /// Seconds a shutdown waits for pending journal entries, queued process metrics and
+    /// the worker threads to be drained and flushed before the final checkpoint;
+    /// threads still running after that are left behind
+    #[serde(default = "default_shutdown_timeout_secs")]
+    pub shutdown_timeout_secs: u64,
+
+    /// Journal entries read ahead of storage at most; when storage falls behind by
+    /// this many, journal reads pause until it catches up
+    #[serde(default = "default_ingest_queue_capacity")]
+    pub ingest_queue_capacity: usize,
+
+    /// Threads turning journal records into log entries ahead of storage
+    #[serde(default = "default_ingest_workers")]
+    pub ingest_workers: usize,
+
+    /// Memory livedata aims to stay within, in MB (0 = no limit; DuckDB then takes up
+    /// to 80% of RAM). Three quarters go to DuckDB, which spills the rest of a large
+    /// query to disk; the other quarter bounds the journal entries in flight, whose
+    /// reads pause while it is used up.
+    #[serde(default)]
+    pub memory_budget_mb: u64,
+
+    /// Seconds the ingest and metrics threads may spend on one piece of work before
+    /// they are reported stalled and a self-alert fires (0 = never)
+    #[serde(default = "default_thread_stall_secs")]
+    pub thread_stall_secs: u64,
+
     /// Path to the config file
     #[serde(skip)]
     pub config_file: PathBuf,
@@ -29,12 +287,253 @@ pub struct Settings {
     /// Maximum database size for backfill (set via --max-db-size CLI arg)
     #[serde(skip)]
     pub max_db_size_bytes: Option<u64>,
+
+    /// Parquet archives (local directories, s3:// or https:// prefixes) searched for
+    /// time ranges older than the local data
+    #[serde(default)]
+    pub attached_archives: Vec<String>,
+
+    /// Per-unit / per-priority log retention overrides (`[[retention_rules]]` tables).
+    /// The first matching rule decides an entry's retention; unmatched entries use
+    /// `log_retention_days`.
+    #[serde(default)]
+    pub retention_rules: Vec<RetentionRule>,
+
+    /// Alerts on sustained process CPU or memory usage (`[[process_alert_rules]]`
+    /// tables), checked against every process sample
+    #[serde(default)]
+    pub process_alert_rules: Vec<ProcessAlertRule>,
+
+    /// Alerts on the number of journal entries matching a query (`[[alert_rules]]`
+    /// tables), evaluated in the background
+    #[serde(default)]
+    pub alert_rules: Vec<AlertRule>,
+
+    /// Alerts on a unit or host going quiet (`[[heartbeat_rules]]` tables), e.g. a
+    /// backup job that stopped running
+    #[serde(default)]
+    pub heartbeat_rules: Vec<HeartbeatRule>,
+
+    /// Notify when systemd reports a unit as failed (failures are recorded either way)
+    #[serde(default)]
+    pub unit_failure_alerts: bool,
+
+    /// Address the web UI is reached at (e.g. "https://logs.example.com"), used for
+    /// links in alert notifications
+    #[serde(default)]
+    pub public_url: Option<String>,
+
+    /// URLs alerts are POSTed to as JSON (`[[webhooks]]` tables)
+    #[serde(default)]
+    pub webhooks: Vec<WebhookSettings>,
+
+    /// Mail server alerts are emailed through (`[smtp]` table)
+    #[serde(default)]
+    pub smtp: Option<SmtpSettings>,
+
+    /// Slack, Discord and Telegram chats alerts are posted into
+    /// (`[[chat_notifiers]]` tables)
+    #[serde(default)]
+    pub chat_notifiers: Vec<ChatSettings>,
+
+    /// Object storage archive for exported Parquet files (`[archive]` table)
+    #[serde(default)]
+    pub archive: Option<ArchiveSettings>,
+
+    /// Sinks that ingested entries matching a filter are forwarded to
+    /// (`[[outputs]]` tables)
+    #[serde(default)]
+    pub outputs: Vec<OutputSettings>,
+
+    /// Prometheus remote-write receiver for process and system metrics
+    /// (`[remote_write]` table)
+    #[serde(default)]
+    pub remote_write: Option<RemoteWriteSettings>,
+
+    /// Listener other instances replicate their entries to with `peer` outputs
+    /// (`[peer_input]` table)
+    #[serde(default)]
+    pub peer_input: Option<PeerInputSettings>,
+
+    /// Server an instance run with `--mode agent` forwards its journal to
+    /// (`[agent]` table)
+    #[serde(default)]
+    pub agent: Option<AgentSettings>,
+}
+
+/// Log retention override for entries matching a unit and/or priority
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RetentionRule {
+    /// Exact `_SYSTEMD_UNIT` to match (e.g. "nginx.service")
+    #[serde(default)]
+    pub unit: Option<String>,
+
+    /// Match entries with priority at or below this value (0 = emerg .. 7 = debug)
+    #[serde(default)]
+    pub max_priority: Option<u8>,
+
+    /// Number of days to retain matching entries
+    pub days: u32,
+}
+
+/// Alert raised when the processes matching a name and/or unit together use more CPU
+/// or memory than a threshold for `samples` consecutive samples
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProcessAlertRule {
+    /// Shown in alerts and stored with them
+    pub name: String,
+
+    /// Exact process name to match
+    #[serde(default)]
+    pub process_name: Option<String>,
+
+    /// Exact systemd unit to match
+    #[serde(default)]
+    pub unit: Option<String>,
+
+    /// Fire above this summed CPU percent (100 = one core)
+    #[serde(default)]
+    pub cpu_percent: Option<f32>,
+
+    /// Fire above this summed resident memory
+    #[serde(default)]
+    pub memory_bytes: Option<u64>,
+
+    /// Consecutive samples over a threshold before the alert fires
+    #[serde(default = "default_alert_samples")]
+    pub samples: u32,
+
+    /// Labels chat notifiers route alerts on
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+fn default_alert_samples() -> u32 {
+    3
+}
+
+/// Alert raised when more than `threshold` journal entries within the last `window`
+/// match a search query and/or SQL condition, checked every `interval`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AlertRule {
+    /// Shown in alerts and stored with them
+    pub name: String,
+
+    /// Search box query, e.g. `unit:nginx.service priority<=err`
+    #[serde(default)]
+    pub query: String,
+
+    /// SQL condition over journal_logs, ANDed with `query`
+    #[serde(default)]
+    pub sql: Option<String>,
+
+    /// Fire when more entries than this match
+    #[serde(default)]
+    pub threshold: u64,
+
+    /// Time counted back from each evaluation, e.g. "5m"
+    #[serde(default = "default_alert_window")]
+    pub window: String,
+
+    /// Time between evaluations, e.g. "1m"
+    #[serde(default = "default_alert_interval")]
+    pub interval: String,
+
+    /// Labels chat notifiers route alerts on
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// Alert raised when no journal entries from a unit and/or host have been seen for
+/// `max_silence`, checked every `interval`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HeartbeatRule {
+    /// Shown in alerts and stored with them
+    pub name: String,
+
+    /// Exact `_SYSTEMD_UNIT` to watch
+    #[serde(default)]
+    pub unit: Option<String>,
+
+    /// Exact `_HOSTNAME` to watch
+    #[serde(default)]
+    pub hostname: Option<String>,
+
+    /// Fire once nothing was seen for this long, e.g. "25h" for a daily job
+    pub max_silence: String,
+
+    /// Time between evaluations, e.g. "1m"
+    #[serde(default = "default_alert_interval")]
+    pub interval: String,
+
+    /// Labels chat notifiers route alerts on
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+fn default_alert_window() -> String {
+    "5m".to_string()
+}
+
+fn default_alert_interval() -> String {
+    "1m".to_string()
 }
 
 fn default_cleanup_interval() -> u32 {
     10
 }
 
+fn default_process_keyframe_minutes() -> u64 {
+    5
+}
+
+fn default_web_read_connections() -> usize {
+    4
+}
+
+fn default_query_timeout_ms() -> u64 {
+    30_000
+}
+
+fn default_wal_checkpoint_bytes() -> u64 {
+    64 * 1024 * 1024
+}
+
+fn default_idle_checkpoint_secs() -> u64 {
+    60
+}
+
+fn default_query_cache_ttl_secs() -> u64 {
+    5
+}
+
+fn default_export_job_ttl_minutes() -> u64 {
+    24 * 60
+}
+
+fn default_shutdown_timeout_secs() -> u64 {
+    30
+}
+
+fn default_ingest_queue_capacity() -> usize {
+    10_000
+}
+
+fn default_ingest_workers() -> usize {
+    2
+}
+
+fn default_thread_stall_secs() -> u64 {
+    300
+}
+
+/// How `memory_budget_mb` is shared out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryBudget {
+    pub duckdb_bytes: u64,
+    pub in_flight_bytes: usize,
+}
+
 impl Default for Settings {
     fn default() -> Self {
         Self {
@@ -42,13 +541,74 @@ impl Default for Settings {
             log_max_size_gb: 1.0,
             process_retention_days: 7,
             process_max_size_gb: 0.5,
+            process_raw_retention_days: None,
+            process_names: Vec::new(),
+            process_users: Vec::new(),
+            process_min_cpu_percent: 0.0,
+            process_min_memory_bytes: 0,
+            process_top_n: 0,
+            process_delta_epsilon: None,
+            process_keyframe_minutes: default_process_keyframe_minutes(),
+            collect_sensors: false,
+            collect_gpu: false,
             cleanup_interval_minutes: 10,
+            hot_storage_days: None,
+            parquet_export: false,
+            web_read_connections: 4,
+            query_timeout_ms: 30_000,
+            promote_extra_fields_after: None,
+            dropped_fields: Vec::new(),
+            api_requests_per_minute: 0,
+            query_cache_ttl_secs: default_query_cache_ttl_secs(),
+            query_log_table: false,
+            admin_token: None,
+            web_token: None,
+            web_username: None,
+            web_password: None,
+            web_users: Vec::new(),
+            oidc: None,
+            tls_cert_path: None,
+            tls_key_path: None,
+            wal_checkpoint_bytes: default_wal_checkpoint_bytes(),
+            idle_checkpoint_secs: default_idle_checkpoint_secs(),
+            min_free_disk_bytes: 0,
+            ready_max_entry_age_secs: 0,
+            export_job_ttl_minutes: default_export_job_ttl_minutes(),
+            shutdown_timeout_secs: default_shutdown_timeout_secs(),
+            ingest_queue_capacity: default_ingest_queue_capacity(),
+            ingest_workers: default_ingest_workers(),
+            thread_stall_secs: default_thread_stall_secs(),
+            memory_budget_mb: 0,
             config_file: Self::default_config_path(),
             max_db_size_bytes: None,
+            attached_archives: Vec::new(),
+            retention_rules: Vec::new(),
+            process_alert_rules: Vec::new(),
+            alert_rules: Vec::new(),
+            heartbeat_rules: Vec::new(),
+            unit_failure_alerts: false,
+            public_url: None,
+            webhooks: Vec::new(),
+            smtp: None,
+            chat_notifiers: Vec::new(),
+            archive: None,
+            outputs: Vec::new(),
+            remote_write: None,
+            peer_input: None,
+            agent: None,
         }
     }
 }
 
+/// Non-empty entries of a comma-separated environment variable
+fn split_list(val: &str) -> Vec<String> {
+    val.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(String::from)
+        .collect()
+}
+
 /// Parse human-friendly size strings like "5G", "500M", "1T", "1024K", "1024"
 pub fn parse_size(s: &str) -> Result<u64> {
     let s = s.trim();
@@ -67,6 +627,38 @@ pub fn parse_size(s: &str) -> Result<u64> {
     Ok((num * multiplier as f64) as u64)
 }
 
+/// Longest duration [`parse_duration`] accepts
+pub const MAX_DURATION: TimeDelta = TimeDelta::days(3650);
+
+/// Parse durations like "30s", "5m", "2h" or "1d", up to [`MAX_DURATION`] either way
+pub fn parse_duration(s: &str) -> Result<TimeDelta> {
+    let s = s.trim();
+    let Some(unit) = s.chars().last() else {
+        bail!("Empty duration");
+    };
+    let num: i64 = s[..s.len() - unit.len_utf8()]
+        .parse()
+        .with_context(|| format!("Invalid duration '{}'", s))?;
+    let duration = match unit {
+        's' => TimeDelta::try_seconds(num),
+        'm' => TimeDelta::try_minutes(num),
+        'h' => TimeDelta::try_hours(num),
+        'd' => TimeDelta::try_days(num),
+        _ => bail!(
+            "Invalid duration '{}'; use a number followed by s, m, h or d",
+            s
+        ),
+    };
+    match duration {
+        Some(duration) if duration.abs() <= MAX_DURATION => Ok(duration),
+        _ => bail!(
+            "Duration '{}' is too long; the most is {} days",
+            s,
+            MAX_DURATION.num_days()
+        ),
+    }
+}
+
 impl Settings {
     /// Get the default config file path
     pub fn default_config_path() -> PathBuf {
@@ -166,11 +758,301 @@ impl Settings {
             self.process_max_size_gb = size;
         }
 
+        if let Ok(val) = std::env::var("LIVEDATA_PROCESS_RAW_RETENTION_DAYS")
+            && let Ok(days) = val.parse()
+        {
+            self.process_raw_retention_days = Some(days);
+        }
+
+        if let Ok(val) = std::env::var("LIVEDATA_PROCESS_NAMES") {
+            self.process_names = split_list(&val);
+        }
+
+        if let Ok(val) = std::env::var("LIVEDATA_PROCESS_USERS") {
+            self.process_users = split_list(&val);
+        }
+
+        if let Ok(val) = std::env::var("LIVEDATA_PROCESS_MIN_CPU_PERCENT")
+            && let Ok(percent) = val.parse()
+        {
+            self.process_min_cpu_percent = percent;
+        }
+
+        if let Ok(val) = std::env::var("LIVEDATA_PROCESS_MIN_MEMORY")
+            && let Ok(bytes) = parse_size(&val)
+        {
+            self.process_min_memory_bytes = bytes;
+        }
+
+        if let Ok(val) = std::env::var("LIVEDATA_PROCESS_TOP_N")
+            && let Ok(count) = val.parse()
+        {
+            self.process_top_n = count;
+        }
+
+        if let Ok(val) = std::env::var("LIVEDATA_PROCESS_DELTA_EPSILON")
+            && let Ok(epsilon) = val.parse()
+        {
+            self.process_delta_epsilon = Some(epsilon);
+        }
+
+        if let Ok(val) = std::env::var("LIVEDATA_PROCESS_KEYFRAME_MINUTES")
+            && let Ok(minutes) = val.parse()
+        {
+            self.process_keyframe_minutes = minutes;
+        }
+
+        if let Ok(val) = std::env::var("LIVEDATA_COLLECT_SENSORS")
+            && let Ok(enabled) = val.parse()
+        {
+            self.collect_sensors = enabled;
+        }
+
+        if let Ok(val) = std::env::var("LIVEDATA_COLLECT_GPU")
+            && let Ok(enabled) = val.parse()
+        {
+            self.collect_gpu = enabled;
+        }
+
         if let Ok(val) = std::env::var("LIVEDATA_RETENTION_CLEANUP_INTERVAL")
             && let Ok(interval) = val.parse()
         {
             self.cleanup_interval_minutes = Self::clamp_cleanup_interval(interval);
         }
+
+        if let Ok(val) = std::env::var("LIVEDATA_HOT_STORAGE_DAYS")
+            && let Ok(days) = val.parse()
+        {
+            self.hot_storage_days = Some(days);
+        }
+
+        if let Ok(val) = std::env::var("LIVEDATA_PARQUET_EXPORT")
+            && let Ok(enabled) = val.parse()
+        {
+            self.parquet_export = enabled;
+        }
+
+        if let Ok(val) = std::env::var("LIVEDATA_WEB_READ_CONNECTIONS")
+            && let Ok(connections) = val.parse()
+        {
+            self.web_read_connections = connections;
+        }
+
+        if let Ok(val) = std::env::var("LIVEDATA_QUERY_TIMEOUT_MS")
+            && let Ok(timeout) = val.parse()
+        {
+            self.query_timeout_ms = timeout;
+        }
+
+        if let Ok(val) = std::env::var("LIVEDATA_PROMOTE_EXTRA_FIELDS_AFTER")
+            && let Ok(rows) = val.parse()
+        {
+            self.promote_extra_fields_after = Some(rows);
+        }
+
+        if let Ok(val) = std::env::var("LIVEDATA_DROPPED_FIELDS") {
+            self.dropped_fields = split_list(&val);
+        }
+
+        if let Ok(val) = std::env::var("LIVEDATA_WAL_CHECKPOINT_SIZE")
+            && let Ok(bytes) = parse_size(&val)
+        {
+            self.wal_checkpoint_bytes = bytes;
+        }
+
+        if let Ok(val) = std::env::var("LIVEDATA_IDLE_CHECKPOINT_SECS")
+            && let Ok(secs) = val.parse()
+        {
+            self.idle_checkpoint_secs = secs;
+        }
+
+        if let Ok(val) = std::env::var("LIVEDATA_MIN_FREE_DISK")
+            && let Ok(bytes) = parse_size(&val)
+        {
+            self.min_free_disk_bytes = bytes;
+        }
+
+        if let Ok(val) = std::env::var("LIVEDATA_READY_MAX_ENTRY_AGE_SECS")
+            && let Ok(secs) = val.parse()
+        {
+            self.ready_max_entry_age_secs = secs;
+        }
+
+        if let Ok(val) = std::env::var("LIVEDATA_EXPORT_JOB_TTL_MINUTES")
+            && let Ok(minutes) = val.parse()
+        {
+            self.export_job_ttl_minutes = minutes;
+        }
+
+        if let Ok(val) = std::env::var("LIVEDATA_SHUTDOWN_TIMEOUT_SECS")
+            && let Ok(secs) = val.parse()
+        {
+            self.shutdown_timeout_secs = secs;
+        }
+
+        if let Ok(val) = std::env::var("LIVEDATA_INGEST_WORKERS")
+            && let Ok(workers) = val.parse()
+        {
+            self.ingest_workers = workers;
+        }
+
+        if let Ok(val) = std::env::var("LIVEDATA_MEMORY_BUDGET_MB")
+            && let Ok(mb) = val.parse()
+        {
+            self.memory_budget_mb = mb;
+        }
+
+        if let Ok(val) = std::env::var("LIVEDATA_THREAD_STALL_SECS")
+            && let Ok(secs) = val.parse()
+        {
+            self.thread_stall_secs = secs;
+        }
+
+        if let Ok(val) = std::env::var("LIVEDATA_API_REQUESTS_PER_MINUTE")
+            && let Ok(limit) = val.parse()
+        {
+            self.api_requests_per_minute = limit;
+        }
+
+        if let Ok(val) = std::env::var("LIVEDATA_QUERY_CACHE_TTL_SECS")
+            && let Ok(secs) = val.parse()
+        {
+            self.query_cache_ttl_secs = secs;
+        }
+
+        if let Ok(val) = std::env::var("LIVEDATA_QUERY_LOG_TABLE")
+            && let Ok(enabled) = val.parse()
+        {
+            self.query_log_table = enabled;
+        }
+
+        if let Ok(token) = std::env::var("LIVEDATA_ADMIN_TOKEN") {
+            self.admin_token = Some(token);
+        }
+
+        if let Ok(token) = std::env::var("LIVEDATA_WEB_TOKEN") {
+            self.web_token = Some(token);
+        }
+
+        if let Ok(username) = std::env::var("LIVEDATA_WEB_USERNAME") {
+            self.web_username = Some(username);
+        }
+
+        if let Ok(password) = std::env::var("LIVEDATA_WEB_PASSWORD") {
+            self.web_password = Some(password);
+        }
+
+        if let Ok(val) = std::env::var("LIVEDATA_UNIT_FAILURE_ALERTS")
+            && let Ok(enabled) = val.parse()
+        {
+            self.unit_failure_alerts = enabled;
+        }
+
+        if let Ok(url) = std::env::var("LIVEDATA_PUBLIC_URL") {
+            self.public_url = Some(url);
+        }
+
+        if let Some(smtp) = self.smtp.as_mut()
+            && let Ok(password) = std::env::var("LIVEDATA_SMTP_PASSWORD")
+        {
+            smtp.password = Some(password);
+        }
+
+        if let Some(oidc) = self.oidc.as_mut()
+            && let Ok(secret) = std::env::var("LIVEDATA_OIDC_CLIENT_SECRET")
+        {
+            oidc.client_secret = Some(secret);
+        }
+
+        if let Ok(path) = std::env::var("LIVEDATA_TLS_CERT") {
+            self.tls_cert_path = Some(PathBuf::from(path));
+        }
+
+        if let Ok(path) = std::env::var("LIVEDATA_TLS_KEY") {
+            self.tls_key_path = Some(PathBuf::from(path));
+        }
+
+        if let Ok(bucket) = std::env::var("LIVEDATA_ARCHIVE_BUCKET") {
+            self.archive.get_or_insert_with(Default::default).bucket = bucket;
+        }
+
+        if let Some(archive) = self.archive.as_mut() {
+            if let Ok(key_id) = std::env::var("LIVEDATA_ARCHIVE_ACCESS_KEY_ID") {
+                archive.access_key_id = Some(key_id);
+            }
+            if let Ok(secret) = std::env::var("LIVEDATA_ARCHIVE_SECRET_ACCESS_KEY") {
+                archive.secret_access_key = Some(secret);
+            }
+        }
+
+        if let Ok(url) = std::env::var("LIVEDATA_REMOTE_WRITE_URL") {
+            self.remote_write.get_or_insert_with(Default::default).url = url;
+        }
+
+        if let Some(remote_write) = self.remote_write.as_mut()
+            && let Ok(password) = std::env::var("LIVEDATA_REMOTE_WRITE_PASSWORD")
+        {
+            remote_write.password = Some(password);
+        }
+    }
+
+    /// Certificate and key paths the web server terminates TLS with, if configured
+    pub fn tls_paths(&self) -> Result<Option<(PathBuf, PathBuf)>> {
+        match (&self.tls_cert_path, &self.tls_key_path) {
+            (Some(cert), Some(key)) => Ok(Some((cert.clone(), key.clone()))),
+            (None, None) => Ok(None),
+            _ => bail!("tls_cert_path and tls_key_path must be set together"),
+        }
+    }
+
+    /// User and password for HTTP basic auth on the web server, if configured
+    pub fn basic_auth(&self) -> Result<Option<(&str, &str)>> {
+        match (&self.web_username, &self.web_password) {
+            (Some(user), Some(password)) => Ok(Some((user, password))),
+            (None, None) => Ok(None),
+            _ => bail!("web_username and web_password must be set together"),
+        }
+    }
+
+    /// Whether the web server requires credentials on its routes
+    pub fn web_auth_enabled(&self) -> bool {
+        self.web_token.is_some()
+            || matches!(self.basic_auth(), Ok(Some(_)))
+            || !self.web_users.is_empty()
+            || self.oidc.is_some()
+    }
+
+    /// `memory_budget_mb` split into DuckDB's memory limit and the bytes of journal
+    /// entries in flight, None without a budget
+    pub fn memory_budget(&self) -> Option<MemoryBudget> {
+        let total = self.memory_budget_mb * 1024 * 1024;
+        (total > 0).then(|| MemoryBudget {
+            duckdb_bytes: total / 4 * 3,
+            in_flight_bytes: (total / 4) as usize,
+        })
+    }
+
+    /// Top-level settings that differ in `other`, by key
+    pub fn changes(&self, other: &Settings) -> Vec<SettingChange> {
+        let as_map = |settings: &Settings| match serde_json::to_value(settings) {
+            Ok(Value::Object(map)) => map,
+            _ => serde_json::Map::new(),
+        };
+        let (mut old, mut new) = (as_map(self), as_map(other));
+        let mut keys: Vec<String> = old.keys().chain(new.keys()).cloned().collect();
+        keys.sort();
+        keys.dedup();
+        keys.into_iter()
+            .filter_map(|key| {
+                let old = old.remove(&key).unwrap_or(Value::Null);
+                let new = new.remove(&key).unwrap_or(Value::Null);
+                (old != new).then(|| SettingChange {
+                    old: mask_secrets(&key, old).to_string(),
+                    new: mask_secrets(&key, new).to_string(),
+                    key,
+                })
+            })
+            .collect()
     }
 
     /// Create a default config file
@@ -249,6 +1131,208 @@ mod tests {
         assert_eq!(settings.cleanup_interval_minutes, 10);
     }
 
+    #[test]
+    fn test_load_archive_section() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_path = temp_dir.path().join("config.toml");
+        fs::write(
+            &config_path,
+            r#"
+log_retention_days = 30
+log_max_size_gb = 1.0
+process_retention_days = 7
+process_max_size_gb = 0.5
+
+[archive]
+bucket = "livedata-archive"
+prefix = "host-a"
+delete_local = true
+"#,
+        )
+        .unwrap();
+
+        let settings = Settings::load_from_file(&config_path).unwrap();
+        let archive = settings.archive.unwrap();
+        assert_eq!(archive.bucket, "livedata-archive");
+        assert_eq!(archive.prefix, "host-a");
+        assert!(archive.delete_local);
+        assert!(archive.access_key_id.is_none());
+    }
+
+    #[test]
+    fn test_load_dropped_fields() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_path = temp_dir.path().join("config.toml");
+        fs::write(
+            &config_path,
+            r#"
+log_retention_days = 30
+log_max_size_gb = 1.0
+process_retention_days = 7
+process_max_size_gb = 0.5
+dropped_fields = ["_CMDLINE", "_SELINUX_CONTEXT"]
+"#,
+        )
+        .unwrap();
+
+        let settings = Settings::load_from_file(&config_path).unwrap();
+        assert_eq!(settings.dropped_fields, ["_CMDLINE", "_SELINUX_CONTEXT"]);
+        assert!(Settings::default().dropped_fields.is_empty());
+    }
+
+    #[test]
+    fn test_tls_paths_must_be_set_together() {
+        let mut settings = Settings::default();
+        assert!(settings.tls_paths().unwrap().is_none());
+
+        settings.tls_cert_path = Some(PathBuf::from("/etc/livedata/cert.pem"));
+        assert!(settings.tls_paths().is_err());
+
+        settings.tls_key_path = Some(PathBuf::from("/etc/livedata/key.pem"));
+        assert_eq!(
+            settings.tls_paths().unwrap(),
+            Some((
+                PathBuf::from("/etc/livedata/cert.pem"),
+                PathBuf::from("/etc/livedata/key.pem")
+            ))
+        );
+    }
+
+    #[test]
+    fn test_load_web_users() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_path = temp_dir.path().join("config.toml");
+        fs::write(
+            &config_path,
+            r#"
+log_retention_days = 30
+log_max_size_gb = 1.0
+process_retention_days = 7
+process_max_size_gb = 0.5
+
+[[web_users]]
+name = "alice"
+role = "operator"
+password = "secret"
+
+[[web_users]]
+name = "dashboard"
+role = "viewer"
+token = "abc123"
+"#,
+        )
+        .unwrap();
+
+        let settings = Settings::load_from_file(&config_path).unwrap();
+        assert_eq!(settings.web_users.len(), 2);
+        assert_eq!(settings.web_users[0].role, crate::auth::Role::Operator);
+        assert_eq!(settings.web_users[1].token.as_deref(), Some("abc123"));
+        assert!(settings.web_auth_enabled());
+        assert!(!Settings::default().web_auth_enabled());
+    }
+
+    #[test]
+    fn test_load_retention_rules() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_path = temp_dir.path().join("config.toml");
+        fs::write(
+            &config_path,
+            r#"
+log_retention_days = 30
+log_max_size_gb = 1.0
+process_retention_days = 7
+process_max_size_gb = 0.5
+
+[[retention_rules]]
+max_priority = 3
+days = 90
+
+[[retention_rules]]
+unit = "nginx.service"
+days = 7
+"#,
+        )
+        .unwrap();
+
+        let settings = Settings::load_from_file(&config_path).unwrap();
+        assert_eq!(
+            settings.retention_rules,
+            vec![
+                RetentionRule {
+                    unit: None,
+                    max_priority: Some(3),
+                    days: 90,
+                },
+                RetentionRule {
+                    unit: Some("nginx.service".to_string()),
+                    max_priority: None,
+                    days: 7,
+                },
+            ]
+        );
+
+        // Rules survive a round trip through the generated config file
+        let toml_content = toml::to_string_pretty(&settings).unwrap();
+        let reparsed: Settings = toml::from_str(&toml_content).unwrap();
+        assert_eq!(reparsed.retention_rules, settings.retention_rules);
+    }
+
+    #[test]
+    fn test_load_process_alert_rules() {
+        let settings: Settings = toml::from_str(
+            r#"
+log_retention_days = 30
+log_max_size_gb = 1.0
+process_retention_days = 7
+process_max_size_gb = 0.5
+
+[[process_alert_rules]]
+name = "postgres memory"
+process_name = "postgres"
+memory_bytes = 4294967296
+
+[[process_alert_rules]]
+name = "busy nginx"
+unit = "nginx.service"
+cpu_percent = 150.0
+samples = 10
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            settings.process_alert_rules,
+            vec![
+                ProcessAlertRule {
+                    name: "postgres memory".to_string(),
+                    process_name: Some("postgres".to_string()),
+                    memory_bytes: Some(4 * 1024 * 1024 * 1024),
+                    samples: 3,
+                    ..Default::default()
+                },
+                ProcessAlertRule {
+                    name: "busy nginx".to_string(),
+                    unit: Some("nginx.service".to_string()),
+                    cpu_percent: Some(150.0),
+                    samples: 10,
+                    ..Default::default()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("30s").unwrap(), TimeDelta::seconds(30));
+        assert_eq!(parse_duration(" 5m ").unwrap(), TimeDelta::minutes(5));
+        assert_eq!(parse_duration("1d").unwrap(), TimeDelta::days(1));
+        assert!(parse_duration("5").is_err());
+        assert!(parse_duration("m").is_err());
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("999999999999999d").is_err());
+        assert!(parse_duration("100000000d").is_err());
+        assert_eq!(parse_duration("3650d").unwrap(), MAX_DURATION);
+    }
+
     #[test]
     fn test_parse_size() {
         assert_eq!(parse_size("5G").unwrap(), 5 * 1024 * 1024 * 1024);
@@ -263,4 +1347,76 @@ mod tests {
         assert!(parse_size("abc").is_err());
         assert!(parse_size("G").is_err());
     }
+
+    #[test]
+    fn test_setting_changes() {
+        let old = Settings::default();
+        let mut new = old.clone();
+        assert!(old.changes(&new).is_empty());
+
+        new.log_retention_days = 14;
+        new.web_password = Some("hunter2".to_string());
+        new.max_db_size_bytes = Some(1);
+        let changes = old.changes(&new);
+        assert_eq!(
+            changes,
+            vec![
+                SettingChange {
+                    key: "log_retention_days".to_string(),
+                    old: "30".to_string(),
+                    new: "14".to_string(),
+                },
+                SettingChange {
+                    key: "web_password".to_string(),
+                    old: "null".to_string(),
+                    new: "\"***\"".to_string(),
+                },
+            ]
+        );
+        assert!(RELOADABLE_SETTINGS.contains(&"log_retention_days"));
+    }
+
+    #[test]
+    fn test_mask_secrets_covers_urls_and_headers() {
+        let masked = mask_secrets(
+            "outputs",
+            serde_json::json!([{
+                "name": "loki",
+                "url": "https://logs.example.com/push",
+                "headers": { "Authorization": "Bearer abc", "X-Scope-OrgID": "ops" },
+                "api_key": "k",
+            }]),
+        );
+        assert_eq!(
+            masked,
+            serde_json::json!([{
+                "name": "loki",
+                "url": "***",
+                "headers": { "Authorization": "***", "X-Scope-OrgID": "***" },
+                "api_key": "***",
+            }])
+        );
+        assert_eq!(
+            mask_secrets(
+                "chat_notifiers",
+                serde_json::json!([{ "service": "slack" }])
+            ),
+            serde_json::json!([{ "service": "slack" }])
+        );
+    }
+
+    #[test]
+    fn test_memory_budget() {
+        let mut settings = Settings::default();
+        assert_eq!(settings.memory_budget(), None);
+
+        settings.memory_budget_mb = 512;
+        assert_eq!(
+            settings.memory_budget(),
+            Some(MemoryBudget {
+                duckdb_bytes: 384 * 1024 * 1024,
+                in_flight_bytes: 128 * 1024 * 1024,
+            })
+        );
+    }
 }
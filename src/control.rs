@@ -0,0 +1,232 @@
+//! Control socket: a unix socket in the data directory through which operators can
+//! ask a running collector for its status, or have it checkpoint, run retention,
+//! reload its config, flush its buffers or copy its database for a backup, without
+//! the web server being enabled (`livedata status`, `livedata ctl <command>`,
+//! `livedata backup`).
+//!
+//! A connection sends one command on a line and gets one JSON line back. The socket
+//! is only accessible to the user running the collector.
+
+use anyhow::{Context, Result, anyhow, bail};
+use log::{info, warn};
+use serde_json::{Value, json};
+use std::fmt;
+use std::fs;
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+/// Socket file in the data directory
+pub const SOCKET_FILE: &str = "control.sock";
+/// Longest a client waits for a command to be carried out; retention can take a while
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(300);
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlCommand {
+    /// Ingest counts and database size
+    Status,
+    /// Checkpoint the WAL into the database file
+    Checkpoint,
+    /// Run a retention cycle now
+    Retention,
+    /// Reload the config, as on SIGHUP
+    Reload,
+    /// Write buffered counts and refresh the summaries and rollups
+    Flush,
+    /// Checkpoint and copy the database file into the backup staging directory
+    Backup,
+}
+
+impl ControlCommand {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Status => "status",
+            Self::Checkpoint => "checkpoint",
+            Self::Retention => "retention",
+            Self::Reload => "reload",
+            Self::Flush => "flush",
+            Self::Backup => "backup",
+        }
+    }
+}
+
+impl fmt::Display for ControlCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ControlCommand {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s.trim() {
+            "status" => Self::Status,
+            "checkpoint" => Self::Checkpoint,
+            "retention" => Self::Retention,
+            "reload" => Self::Reload,
+            "flush" => Self::Flush,
+            "backup" => Self::Backup,
+            other => bail!(
+                "unknown command '{}' (status, checkpoint, retention, reload, flush or backup)",
+                other
+            ),
+        })
+    }
+}
+
+/// A command for the collector's main loop, which carries it out and answers
+pub struct ControlRequest {
+    pub command: ControlCommand,
+    reply: Sender<Result<Value, String>>,
+}
+
+impl ControlRequest {
+    pub fn respond(self, result: Result<Value>) {
+        // The client may have given up waiting
+        let _ = self.reply.send(result.map_err(|e| format!("{:#}", e)));
+    }
+}
+
+/// Listen on `path` until `shutdown_signal` is set, passing commands to the returned
+/// receiver. The socket file is removed when the server stops.
+pub fn start_control_server(
+    path: &Path,
+    shutdown_signal: Arc<AtomicBool>,
+) -> Result<(Receiver<ControlRequest>, thread::JoinHandle<()>)> {
+    if path.exists() {
+        if is_listening(path) {
+            bail!("{} is in use by another collector", path.display());
+        }
+        // Left behind by a collector that did not shut down cleanly
+        fs::remove_file(path).with_context(|| format!("removing {}", path.display()))?;
+    }
+    let listener =
+        UnixListener::bind(path).with_context(|| format!("binding {}", path.display()))?;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    listener.set_nonblocking(true)?;
+
+    let (sender, receiver) = mpsc::channel();
+    let path = path.to_path_buf();
+    let handle = thread::spawn(move || {
+        info!("Control socket listening on {}", path.display());
+        while !shutdown_signal.load(Ordering::Relaxed) {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    if let Err(e) = serve(stream, &sender) {
+                        warn!("Control connection failed: {:#}", e);
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                    thread::sleep(Duration::from_millis(100));
+                }
+                Err(e) => {
+                    warn!("Failed to accept a control connection: {}", e);
+                    thread::sleep(Duration::from_millis(100));
+                }
+            }
+        }
+        let _ = fs::remove_file(&path);
+    });
+    Ok((receiver, handle))
+}
+
+fn serve(stream: UnixStream, requests: &Sender<ControlRequest>) -> Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    let result = match line.parse::<ControlCommand>() {
+        Ok(command) => {
+            let (reply, response) = mpsc::channel();
+            requests
+                .send(ControlRequest { command, reply })
+                .map_err(|_| anyhow!("the collector is shutting down"))?;
+            response
+                .recv_timeout(COMMAND_TIMEOUT)
+                .unwrap_or_else(|_| Err(format!("no answer to {} in time", command)))
+        }
+        Err(e) => Err(e.to_string()),
+    };
+    let response = match result {
+        Ok(result) => json!({"ok": true, "result": result}),
+        Err(error) => json!({"ok": false, "error": error}),
+    };
+    let mut stream = &stream;
+    writeln!(stream, "{}", response)?;
+    Ok(())
+}
+
+/// Socket of the collector using `data_dir`
+pub fn socket_path(data_dir: &Path) -> PathBuf {
+    data_dir.join(SOCKET_FILE)
+}
+
+/// Whether a collector is listening on `path`, rather than it having been left
+/// behind or never created
+pub fn is_listening(path: &Path) -> bool {
+    UnixStream::connect(path).is_ok()
+}
+
+/// Send `command` to the collector listening on `path` and return its result
+pub fn send_command(path: &Path, command: ControlCommand) -> Result<Value> {
+    let mut stream = UnixStream::connect(path).with_context(|| {
+        format!(
+            "connecting to {} (is the collector running with this data directory?)",
+            path.display()
+        )
+    })?;
+    stream.set_read_timeout(Some(COMMAND_TIMEOUT + READ_TIMEOUT))?;
+    writeln!(stream, "{}", command)?;
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    let mut response: Value = serde_json::from_str(&line).context("unreadable response")?;
+    if response["ok"] != Value::Bool(true) {
+        bail!("{}", response["error"].as_str().unwrap_or("command failed"));
+    }
+    Ok(response["result"].take())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_commands_round_trip_over_the_socket() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = socket_path(temp_dir.path());
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let (requests, handle) = start_control_server(&path, shutdown.clone()).unwrap();
+        assert!(start_control_server(&path, shutdown.clone()).is_err());
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let collector = thread::spawn(move || {
+            for request in requests.iter().take(2) {
+                let result = match request.command {
+                    ControlCommand::Status => Ok(json!({"total_entries": 3})),
+                    other => Err(anyhow!("{} failed", other)),
+                };
+                request.respond(result);
+            }
+        });
+        let status = send_command(&path, ControlCommand::Status).unwrap();
+        assert_eq!(status["total_entries"], 3);
+        let error = send_command(&path, ControlCommand::Checkpoint).unwrap_err();
+        assert_eq!(error.to_string(), "checkpoint failed");
+        collector.join().unwrap();
+
+        assert!("compact".parse::<ControlCommand>().is_err());
+        shutdown.store(true, Ordering::Relaxed);
+        handle.join().unwrap();
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,47 @@
+use std::path::Path;
+use sysinfo::Disks;
+
+/// Bytes available to unprivileged users on the filesystem holding `path`, or `None`
+/// when no mounted filesystem can be matched to it
+pub fn available_bytes(path: &Path) -> Option<u64> {
+    let path = path.canonicalize().ok()?;
+    let disks = Disks::new_with_refreshed_list();
+    let mounts: Vec<(&Path, u64)> = disks
+        .list()
+        .iter()
+        .map(|disk| (disk.mount_point(), disk.available_space()))
+        .collect();
+    containing_mount(&path, &mounts)
+}
+
+/// Value of the deepest mount point that contains `path`
+fn containing_mount(path: &Path, mounts: &[(&Path, u64)]) -> Option<u64> {
+    mounts
+        .iter()
+        .filter(|(mount, _)| path.starts_with(mount))
+        .max_by_key(|(mount, _)| mount.components().count())
+        .map(|(_, available)| *available)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deepest_mount_wins() {
+        let mounts = [
+            (Path::new("/"), 1),
+            (Path::new("/var"), 2),
+            (Path::new("/var/lib/livedata"), 3),
+            (Path::new("/var/lib/other"), 4),
+        ];
+        assert_eq!(
+            containing_mount(Path::new("/var/lib/livedata/data"), &mounts),
+            Some(3)
+        );
+        assert_eq!(containing_mount(Path::new("/var/log"), &mounts), Some(2));
+        // Path::starts_with compares whole components
+        assert_eq!(containing_mount(Path::new("/variable"), &mounts), Some(1));
+        assert_eq!(containing_mount(Path::new("relative"), &mounts), None);
+    }
+}
@@ -0,0 +1,206 @@
+//! Email notification channel: sends each alert over SMTP from a background thread,
+//! with the subject and body filled in from templates.
+
+use crate::notifications::{AlertEvent, NotificationChannel, check_template, render_template};
+use anyhow::{Context, Result, ensure};
+use lettre::message::Mailbox;
+use lettre::message::header::ContentType;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Message, SmtpTransport, Transport};
+use log::error;
+use serde::{Deserialize, Serialize};
+use std::sync::mpsc;
+use std::time::Duration;
+
+const SEND_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How the connection to the SMTP server is secured
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    /// Upgrade a plain connection with STARTTLS (port 587 by default)
+    #[default]
+    Starttls,
+    /// TLS from the start (port 465 by default)
+    Tls,
+    /// Unencrypted, only for a relay on the local host or network (port 25 by default)
+    None,
+}
+
+/// SMTP server and addresses alerts are emailed to (`[smtp]` table)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SmtpSettings {
+    pub host: String,
+
+    /// Defaults to the usual port for `tls`
+    #[serde(default)]
+    pub port: Option<u16>,
+
+    #[serde(default)]
+    pub tls: SmtpTls,
+
+    #[serde(default)]
+    pub username: Option<String>,
+
+    /// Password for `username` (or LIVEDATA_SMTP_PASSWORD)
+    #[serde(default)]
+    pub password: Option<String>,
+
+    /// Sender address, e.g. "livedata <alerts@example.com>"
+    pub from: String,
+
+    /// Recipient addresses
+    pub to: Vec<String>,
+
+    /// Subject template, with placeholders or in minijinja syntax; see
+    /// `render_template`
+    #[serde(default = "default_subject")]
+    pub subject: String,
+
+    /// Body template
+    #[serde(default = "default_body")]
+    pub body: String,
+}
+
+fn default_subject() -> String {
+    "[livedata] {rule} {state}".to_string()
+}
+
+fn default_body() -> String {
+    "{message}\n\n\
+     Rule: {rule} ({source})\n\
+     Value: {value} (threshold {threshold})\n\
+     At: {timestamp}\n\
+     {link}\n\n\
+     {samples}\n"
+        .to_string()
+}
+
+/// Queues alerts for a sending thread, so senders never wait on the mail server
+pub struct EmailChannel {
+    name: String,
+    queue: mpsc::Sender<AlertEvent>,
+}
+
+impl EmailChannel {
+    /// Start the sending thread; it ends once the channel is dropped
+    pub fn start(settings: SmtpSettings) -> Result<Self> {
+        let from: Mailbox = settings.from.parse()?;
+        let to = settings
+            .to
+            .iter()
+            .map(|address| address.parse())
+            .collect::<Result<Vec<Mailbox>, _>>()?;
+        ensure!(!to.is_empty(), "no recipients in `to`");
+        check_template(&settings.subject).context("subject")?;
+        check_template(&settings.body).context("body")?;
+        let transport = transport(&settings)?;
+
+        let name = format!("email {}", settings.host);
+        let (queue, alerts) = mpsc::channel::<AlertEvent>();
+        let thread_name = name.clone();
+        std::thread::spawn(move || {
+            for alert in alerts {
+                let sent = message(&settings, &from, &to, &alert)
+                    .and_then(|message| Ok(transport.send(&message)?));
+                if let Err(e) = sent {
+                    error!(
+                        "Failed to send alert {} to {}: {}",
+                        alert.rule, thread_name, e
+                    );
+                }
+            }
+        });
+        Ok(Self { name, queue })
+    }
+}
+
+impl NotificationChannel for EmailChannel {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn send(&self, alert: &AlertEvent) -> Result<()> {
+        self.queue.send(alert.clone())?;
+        Ok(())
+    }
+}
+
+fn transport(settings: &SmtpSettings) -> Result<SmtpTransport> {
+    let mut builder = match settings.tls {
+        SmtpTls::Starttls => SmtpTransport::starttls_relay(&settings.host)?,
+        SmtpTls::Tls => SmtpTransport::relay(&settings.host)?,
+        SmtpTls::None => SmtpTransport::builder_dangerous(&settings.host),
+    }
+    .timeout(Some(SEND_TIMEOUT));
+    if let Some(port) = settings.port {
+        builder = builder.port(port);
+    }
+    if let Some(username) = &settings.username {
+        builder = builder.credentials(Credentials::new(
+            username.clone(),
+            settings.password.clone().unwrap_or_default(),
+        ));
+    }
+    Ok(builder.build())
+}
+
+fn message(
+    settings: &SmtpSettings,
+    from: &Mailbox,
+    to: &[Mailbox],
+    alert: &AlertEvent,
+) -> Result<Message> {
+    let mut builder = Message::builder()
+        .from(from.clone())
+        .subject(render_template(&settings.subject, alert))
+        .header(ContentType::TEXT_PLAIN);
+    for mailbox in to {
+        builder = builder.to(mailbox.clone());
+    }
+    Ok(builder.body(render_template(&settings.body, alert))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notifications::AlertState;
+    use chrono::{TimeZone, Utc};
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_alert_message() {
+        let settings: SmtpSettings = toml::from_str(
+            r#"
+host = "smtp.example.com"
+from = "livedata <alerts@example.com>"
+to = ["ops@example.com", "oncall@example.com"]
+"#,
+        )
+        .unwrap();
+        assert_eq!(settings.tls, SmtpTls::Starttls);
+        assert!(transport(&settings).is_ok());
+
+        let from = settings.from.parse().unwrap();
+        let to: Vec<Mailbox> = settings.to.iter().map(|a| a.parse().unwrap()).collect();
+        let alert = AlertEvent {
+            timestamp: Utc.with_ymd_and_hms(2026, 3, 1, 12, 0, 0).unwrap(),
+            rule: "nginx errors".to_string(),
+            source: "log".to_string(),
+            state: AlertState::Firing,
+            value: 51.0,
+            threshold: 50.0,
+            message: "51 entries matched in the last 5m (threshold 50)".to_string(),
+            samples: vec!["upstream timed out".to_string()],
+            link: None,
+            tags: Vec::new(),
+            details: BTreeMap::new(),
+        };
+        let formatted =
+            String::from_utf8(message(&settings, &from, &to, &alert).unwrap().formatted()).unwrap();
+        assert!(formatted.contains("Subject: [livedata] nginx errors firing"));
+        assert!(formatted.contains("To: ops@example.com, oncall@example.com"));
+        assert!(formatted.contains("Value: 51 (threshold 50)"));
+        assert!(formatted.contains("upstream timed out"));
+    }
+}
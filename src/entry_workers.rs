@@ -0,0 +1,337 @@
+//! Worker pool preparing journal records for storage. Turning a record into a
+//! [`LogEntry`] (parsing its timestamp, collecting its fields, removing the dropped
+//! ones) happens on these threads rather than the collector's main loop, which only
+//! reads the journal, so preparing entries does not cap ingest throughput. Batches
+//! reach the [`IngestQueue`] in the order they were read, whichever worker prepared
+//! them. With a memory limit, batches are refused once the records and entries held
+//! would take more than it, and the main loop leaves the rest in the journal.
+
+use crate::ingest_queue::{InFlight, IngestQueue};
+use crate::journal_reader::{JournalRecord, log_entry_from_record};
+use crate::log_entry::LogEntry;
+use crate::supervisor::{Heartbeat, Supervisor};
+use log::{info, warn};
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::thread;
+
+/// Records handed to a worker at a time
+pub const READ_BATCH: usize = 100;
+
+pub struct EntryWorkers {
+    sender: SyncSender<(u64, Vec<JournalRecord>)>,
+    /// Sequence number of the next batch pushed
+    next_batch: u64,
+    /// Records pushed and not yet handed to the ingest queue
+    in_flight: Arc<InFlight>,
+    /// Most bytes of records and entries held at once, if limited
+    memory_limit: Option<usize>,
+    /// Whether a batch was refused for the memory limit since the last check
+    budget_hit: AtomicBool,
+    queue: Arc<IngestQueue>,
+    handles: Vec<thread::JoinHandle<()>>,
+}
+
+/// Whose turn it is to hand its batch to the ingest queue
+#[derive(Default)]
+struct Turn {
+    next: Mutex<u64>,
+    changed: Condvar,
+}
+
+/// A worker's place in line for handing over its batch. Dropping it passes the turn
+/// on, also when the worker panicked preparing the batch, so the others carry on.
+struct BatchTurn<'a> {
+    turn: &'a Turn,
+    batch: u64,
+    records: usize,
+    bytes: usize,
+    in_flight: &'a InFlight,
+    waited: bool,
+}
+
+impl BatchTurn<'_> {
+    fn wait(&mut self) {
+        self.turn.wait_for(self.batch);
+        self.waited = true;
+    }
+}
+
+impl Drop for BatchTurn<'_> {
+    fn drop(&mut self) {
+        if !self.waited {
+            self.turn.wait_for(self.batch);
+        }
+        self.in_flight.remove(self.records, self.bytes);
+        self.turn.pass();
+    }
+}
+
+impl Turn {
+    fn wait_for(&self, batch: u64) {
+        let mut next = self.next.lock().unwrap();
+        while *next != batch {
+            next = self.changed.wait(next).unwrap();
+        }
+    }
+
+    fn pass(&self) {
+        *self.next.lock().unwrap() += 1;
+        self.changed.notify_all();
+    }
+}
+
+impl EntryWorkers {
+    /// Start `workers` threads feeding `queue` under `supervisor`, with room for about
+    /// `capacity` records waiting to be prepared. Fields in `dropped_fields` are
+    /// removed from every entry.
+    pub fn start(
+        queue: IngestQueue,
+        dropped_fields: Arc<RwLock<HashSet<String>>>,
+        workers: usize,
+        capacity: usize,
+        supervisor: &Supervisor,
+    ) -> Self {
+        let (sender, receiver) = mpsc::sync_channel((capacity / READ_BATCH).max(1));
+        let receiver = Arc::new(Mutex::new(receiver));
+        let in_flight = Arc::new(InFlight::default());
+        let queue = Arc::new(queue);
+        let turn = Arc::new(Turn::default());
+        let handles = (1..=workers.max(1))
+            .map(|worker| {
+                let receiver = receiver.clone();
+                let dropped_fields = dropped_fields.clone();
+                let in_flight = in_flight.clone();
+                let queue = queue.clone();
+                let turn = turn.clone();
+                supervisor.spawn(format!("entry worker {}", worker), move |heartbeat| {
+                    let in_flight = &in_flight;
+                    prepare_entries(
+                        &receiver,
+                        &dropped_fields,
+                        in_flight,
+                        &queue,
+                        &turn,
+                        heartbeat,
+                    );
+                })
+            })
+            .collect();
+        info!("Started {} entry workers", workers.max(1));
+        Self {
+            sender,
+            next_batch: 0,
+            in_flight,
+            memory_limit: None,
+            budget_hit: AtomicBool::new(false),
+            queue,
+            handles,
+        }
+    }
+
+    /// Refuse batches once the records and entries held would take more than
+    /// `bytes`; a batch is always taken when nothing is held
+    pub fn with_memory_limit(mut self, bytes: Option<usize>) -> Self {
+        self.memory_limit = bytes;
+        self
+    }
+
+    /// Queue a batch of records, handing it back when the workers are full
+    pub fn try_push(&mut self, batch: Vec<JournalRecord>) -> Result<(), Vec<JournalRecord>> {
+        let len = batch.len();
+        let size = batch.iter().map(record_size).sum();
+        if let Some(limit) = self.memory_limit
+            && self.depth() > 0
+            && self.bytes() + size > limit
+        {
+            self.budget_hit.store(true, Ordering::Relaxed);
+            return Err(batch);
+        }
+        self.in_flight.add(len, size);
+        match self.sender.try_send((self.next_batch, batch)) {
+            Ok(()) => {
+                self.next_batch += 1;
+                Ok(())
+            }
+            Err(TrySendError::Full((_, batch)) | TrySendError::Disconnected((_, batch))) => {
+                self.in_flight.remove(len, size);
+                Err(batch)
+            }
+        }
+    }
+
+    /// Records read and not yet stored, whether waiting for a worker or for the writer
+    pub fn depth(&self) -> usize {
+        self.in_flight.entries() + self.queue.depth()
+    }
+
+    /// Rough size of the records and entries read and not yet stored
+    pub fn bytes(&self) -> usize {
+        self.in_flight.bytes() + self.queue.bytes()
+    }
+
+    /// Whether a batch was refused for the memory limit since the last call
+    pub fn take_budget_hit(&self) -> bool {
+        self.budget_hit.swap(false, Ordering::Relaxed)
+    }
+
+    /// Stop taking records; the workers prepare the ones pushed and the writer stores
+    /// them. The returned thread ends once the writer has.
+    pub fn close(self) -> thread::JoinHandle<()> {
+        let Self { handles, queue, .. } = self;
+        thread::spawn(move || {
+            for handle in handles {
+                let _ = handle.join();
+            }
+            // The workers held the only other references to the queue
+            if let Ok(queue) = Arc::try_unwrap(queue) {
+                let _ = queue.close().join();
+            }
+        })
+    }
+}
+
+fn prepare_entries(
+    receiver: &Mutex<Receiver<(u64, Vec<JournalRecord>)>>,
+    dropped_fields: &RwLock<HashSet<String>>,
+    in_flight: &InFlight,
+    queue: &IngestQueue,
+    turn: &Turn,
+    heartbeat: &Heartbeat,
+) {
+    loop {
+        // The lock is released before preparing, so the other workers can take the
+        // next batches meanwhile
+        let Ok((batch, records)) = receiver.lock().unwrap().recv() else {
+            break;
+        };
+        heartbeat.busy();
+        let mut turn = BatchTurn {
+            turn,
+            batch,
+            records: records.len(),
+            bytes: records.iter().map(record_size).sum(),
+            in_flight,
+            waited: false,
+        };
+        let entries = prepare_batch(records, &dropped_fields.read().unwrap());
+
+        turn.wait();
+        for entry in entries {
+            if !queue.push(entry) {
+                warn!("Ingest writer stopped, discarding prepared entries");
+                break;
+            }
+        }
+        drop(turn);
+        heartbeat.idle();
+    }
+}
+
+/// Rough size of `record`, counted like [`LogEntry::size_bytes`]
+fn record_size(record: &JournalRecord) -> usize {
+    let fields: usize = record.iter().map(|(k, v)| k.len() + v.len()).sum();
+    fields + record.len() * 2 * size_of::<String>()
+}
+
+/// Log entries for `records`, without `dropped_fields`; records that cannot be read
+/// are skipped
+fn prepare_batch(records: Vec<JournalRecord>, dropped_fields: &HashSet<String>) -> Vec<LogEntry> {
+    records
+        .into_iter()
+        .filter_map(|record| match log_entry_from_record(record) {
+            Ok(mut entry) => {
+                if !dropped_fields.is_empty() {
+                    entry
+                        .fields
+                        .retain(|field, _| !dropped_fields.contains(field));
+                }
+                Some(entry)
+            }
+            Err(e) => {
+                warn!("Skipping unreadable journal entry: {}", e);
+                None
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::duckdb_buffer::DuckDBBuffer;
+    use crate::metrics::Metrics;
+    use std::sync::atomic::AtomicU64;
+
+    #[test]
+    fn test_workers_keep_read_order() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let buffer = Arc::new(Mutex::new(DuckDBBuffer::new(temp_dir.path()).unwrap()));
+        let metrics = Arc::new(Metrics::default());
+        let stored = Arc::new(AtomicU64::new(0));
+        let supervisor = Supervisor::default();
+        let queue = IngestQueue::start(buffer.clone(), metrics, stored.clone(), 1000, &supervisor);
+        let dropped_fields = Arc::new(RwLock::new(HashSet::from(["_CMDLINE".to_string()])));
+        let mut workers = EntryWorkers::start(queue, dropped_fields, 4, 10_000, &supervisor);
+        let mut tail = buffer.lock().unwrap().subscribe_tail();
+
+        let base = 1_700_000_000_000_000u64;
+        let records: Vec<JournalRecord> = (0..1000)
+            .map(|i| {
+                JournalRecord::from([
+                    ("__REALTIME_TIMESTAMP".to_string(), (base + i).to_string()),
+                    ("MESSAGE".to_string(), format!("line {}", i)),
+                    ("_CMDLINE".to_string(), "secret --token".to_string()),
+                ])
+            })
+            .collect();
+        for batch in records.chunks(READ_BATCH) {
+            workers.try_push(batch.to_vec()).unwrap();
+        }
+        workers.close().join().unwrap();
+
+        assert_eq!(stored.load(Ordering::Relaxed), 1000);
+        for i in 0..1000 {
+            let entry = tail.try_recv().unwrap();
+            assert_eq!(entry.get_message().unwrap(), &format!("line {}", i));
+            assert!(entry.get_field("_CMDLINE").is_none());
+        }
+    }
+
+    #[test]
+    fn test_memory_limit_refuses_batches() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let buffer = Arc::new(Mutex::new(DuckDBBuffer::new(temp_dir.path()).unwrap()));
+        let stored = Arc::new(AtomicU64::new(0));
+        let supervisor = Supervisor::default();
+        let metrics = Arc::new(Metrics::default());
+        let queue = IngestQueue::start(buffer.clone(), metrics, stored.clone(), 1000, &supervisor);
+        let dropped_fields = Arc::new(RwLock::new(HashSet::new()));
+        let mut workers = EntryWorkers::start(queue, dropped_fields, 2, 10_000, &supervisor)
+            .with_memory_limit(Some(1));
+        let batch = || {
+            vec![JournalRecord::from([
+                (
+                    "__REALTIME_TIMESTAMP".to_string(),
+                    "1700000000000000".to_string(),
+                ),
+                ("MESSAGE".to_string(), "hello".to_string()),
+            ])]
+        };
+
+        // Holding the lock stalls the writer, so the first batch stays in flight
+        let held = buffer.lock().unwrap();
+        // Taken even though it is over the limit, as nothing else is held
+        workers.try_push(batch()).unwrap();
+        assert!(workers.bytes() > 1);
+        assert!(workers.try_push(batch()).is_err());
+        assert!(workers.take_budget_hit());
+        assert!(!workers.take_budget_hit());
+        drop(held);
+
+        workers.close().join().unwrap();
+        assert_eq!(stored.load(Ordering::Relaxed), 1);
+    }
+}
@@ -0,0 +1,266 @@
+use anyhow::{Result, bail};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Jobs allowed to run at once; each holds a read connection until it finishes
+pub const MAX_RUNNING_JOBS: usize = 2;
+
+/// File format of an export
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Ndjson,
+    Csv,
+    Parquet,
+}
+
+impl ExportFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Ndjson => "ndjson",
+            Self::Csv => "csv",
+            Self::Parquet => "parquet",
+        }
+    }
+
+    /// Format of a file, from its extension (`.json` and `.jsonl` being NDJSON)
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "ndjson" | "jsonl" | "json" => Some(Self::Ndjson),
+            "csv" => Some(Self::Csv),
+            "parquet" => Some(Self::Parquet),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Ndjson => "application/x-ndjson",
+            Self::Csv => "text/csv",
+            Self::Parquet => "application/vnd.apache.parquet",
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s.trim().to_ascii_lowercase().as_str() {
+            "ndjson" => Self::Ndjson,
+            "csv" => Self::Csv,
+            "parquet" => Self::Parquet,
+            other => bail!("unknown format '{}' (ndjson, csv or parquet)", other),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportJobState {
+    Running,
+    Completed,
+    Failed,
+}
+
+/// Background export writing to a file under the registry's directory
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportJob {
+    pub id: String,
+    pub format: ExportFormat,
+    pub state: ExportJobState,
+    /// Rows the export will write, once counted
+    pub total_rows: Option<usize>,
+    pub rows_written: usize,
+    /// Size of the finished file
+    pub size_bytes: Option<u64>,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    /// When the job is forgotten and its file deleted
+    pub expires_at: Option<DateTime<Utc>>,
+    #[serde(skip)]
+    pub path: PathBuf,
+}
+
+/// In-memory registry of background exports.
+///
+/// Jobs do not survive a restart, so files left in the directory by a previous run
+/// are deleted when the registry is created. Finished jobs expire `ttl` after they
+/// finish; [`ExportJobs::expire`] forgets them and deletes their files.
+pub struct ExportJobs {
+    dir: PathBuf,
+    ttl: Duration,
+    jobs: Mutex<HashMap<String, ExportJob>>,
+    next_id: AtomicU64,
+}
+
+impl ExportJobs {
+    pub fn new(dir: impl Into<PathBuf>, ttl: Duration) -> Self {
+        let dir = dir.into();
+        if let Ok(entries) = std::fs::read_dir(&dir) {
+            for entry in entries.flatten() {
+                if let Err(e) = std::fs::remove_file(entry.path()) {
+                    log::warn!(
+                        "Failed to remove stale export {}: {}",
+                        entry.path().display(),
+                        e
+                    );
+                }
+            }
+        }
+        Self {
+            dir,
+            ttl,
+            jobs: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    /// Register a running job, failing when [`MAX_RUNNING_JOBS`] are already running
+    pub fn start(&self, format: ExportFormat) -> Result<ExportJob> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let running = jobs
+            .values()
+            .filter(|job| job.state == ExportJobState::Running)
+            .count();
+        if running >= MAX_RUNNING_JOBS {
+            bail!("{} export jobs are already running", running);
+        }
+        std::fs::create_dir_all(&self.dir)?;
+
+        let created_at = Utc::now();
+        let id = format!(
+            "{}-{}",
+            created_at.format("%Y%m%d%H%M%S"),
+            self.next_id.fetch_add(1, Ordering::Relaxed)
+        );
+        let job = ExportJob {
+            path: self.dir.join(format!("{}.{}", id, format.extension())),
+            id: id.clone(),
+            format,
+            state: ExportJobState::Running,
+            total_rows: None,
+            rows_written: 0,
+            size_bytes: None,
+            error: None,
+            created_at,
+            finished_at: None,
+            expires_at: None,
+        };
+        jobs.insert(id, job.clone());
+        Ok(job)
+    }
+
+    pub fn get(&self, id: &str) -> Option<ExportJob> {
+        self.jobs.lock().unwrap().get(id).cloned()
+    }
+
+    pub fn set_total_rows(&self, id: &str, total: usize) {
+        if let Some(job) = self.jobs.lock().unwrap().get_mut(id) {
+            job.total_rows = Some(total);
+        }
+    }
+
+    pub fn set_rows_written(&self, id: &str, rows: usize) {
+        if let Some(job) = self.jobs.lock().unwrap().get_mut(id) {
+            job.rows_written = rows;
+        }
+    }
+
+    /// Record the outcome of a job: the rows written, or why it failed. The partial
+    /// file of a failed job is deleted right away.
+    pub fn finish(&self, id: &str, result: Result<usize>) {
+        let mut jobs = self.jobs.lock().unwrap();
+        let Some(job) = jobs.get_mut(id) else {
+            return;
+        };
+        let now = Utc::now();
+        job.finished_at = Some(now);
+        job.expires_at = Some(now + self.ttl);
+        match result {
+            Ok(rows) => {
+                job.state = ExportJobState::Completed;
+                job.rows_written = rows;
+                job.size_bytes = std::fs::metadata(&job.path).map(|m| m.len()).ok();
+            }
+            Err(e) => {
+                log::warn!("Export job {} failed: {}", id, e);
+                job.state = ExportJobState::Failed;
+                job.error = Some(e.to_string());
+                let _ = std::fs::remove_file(&job.path);
+            }
+        }
+    }
+
+    /// Forget finished jobs whose expiry has passed and delete their files; returns
+    /// the number of jobs removed
+    pub fn expire(&self, now: DateTime<Utc>) -> usize {
+        let mut jobs = self.jobs.lock().unwrap();
+        let expired: Vec<String> = jobs
+            .values()
+            .filter(|job| job.expires_at.is_some_and(|at| at <= now))
+            .map(|job| job.id.clone())
+            .collect();
+        for id in &expired {
+            if let Some(job) = jobs.remove(id)
+                && let Err(e) = std::fs::remove_file(&job.path)
+                && e.kind() != std::io::ErrorKind::NotFound
+            {
+                log::warn!("Failed to remove export {}: {}", job.path.display(), e);
+            }
+        }
+        expired.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_export_job_lifecycle() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = temp_dir.path().join("exports");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("stale.csv"), "left over").unwrap();
+
+        let jobs = ExportJobs::new(&dir, Duration::hours(1));
+        assert!(!dir.join("stale.csv").exists());
+
+        let first = jobs.start(ExportFormat::Csv).unwrap();
+        let second = jobs.start(ExportFormat::Parquet).unwrap();
+        assert_ne!(first.id, second.id);
+        assert!(first.path.to_string_lossy().ends_with(".csv"));
+        assert!(jobs.start(ExportFormat::Ndjson).is_err());
+
+        std::fs::write(&first.path, "a,b\r\n").unwrap();
+        jobs.finish(&first.id, Ok(1));
+        std::fs::write(&second.path, "partial").unwrap();
+        jobs.finish(&second.id, Err(anyhow::anyhow!("disk full")));
+
+        let first = jobs.get(&first.id).unwrap();
+        assert_eq!(first.state, ExportJobState::Completed);
+        assert_eq!(first.size_bytes, Some(5));
+        let second = jobs.get(&second.id).unwrap();
+        assert_eq!(second.state, ExportJobState::Failed);
+        assert_eq!(second.error.as_deref(), Some("disk full"));
+        assert!(!second.path.exists());
+
+        // Finished jobs free their running slot
+        let third = jobs.start(ExportFormat::Ndjson).unwrap();
+
+        assert_eq!(jobs.expire(Utc::now()), 0);
+        assert_eq!(jobs.expire(Utc::now() + Duration::hours(2)), 2);
+        assert!(jobs.get(&first.id).is_none());
+        assert!(!first.path.exists());
+        // Running jobs never expire
+        assert!(jobs.get(&third.id).is_some());
+    }
+}
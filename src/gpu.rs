@@ -0,0 +1,392 @@
+//! GPU utilization and memory, per device and per process. NVIDIA GPUs are read
+//! through `nvidia-smi` (which wraps NVML), AMD GPUs through the amdgpu sysfs files
+//! and the DRM fdinfo of each process.
+
+use crate::process_monitor::read_trimmed;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::io::Read;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+const DRM_PATH: &str = "/sys/class/drm";
+const PROC_PATH: &str = "/proc";
+const MIB: u64 = 1024 * 1024;
+/// How long one `nvidia-smi` query may take before it is killed; a wedged driver
+/// can leave it hanging indefinitely
+const NVIDIA_SMI_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// One GPU at one sample
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GpuDevice {
+    /// `nvidia<index>` or the DRM card, e.g. `card0`
+    pub device: String,
+    pub name: String,
+    pub utilization_percent: Option<f64>,
+    pub memory_used_bytes: Option<u64>,
+    pub memory_total_bytes: Option<u64>,
+}
+
+/// GPU memory one process holds on one device. Neither source reports per-process
+/// utilization cheaply, so only memory is recorded.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GpuProcess {
+    pub device: String,
+    pub pid: u32,
+    pub memory_used_bytes: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GpuSample {
+    pub devices: Vec<GpuDevice>,
+    pub processes: Vec<GpuProcess>,
+}
+
+impl GpuSample {
+    pub fn is_empty(&self) -> bool {
+        self.devices.is_empty() && self.processes.is_empty()
+    }
+}
+
+/// Samples every GPU found. Hosts without `nvidia-smi` stop trying it after the
+/// first failure or timeout.
+pub struct GpuCollector {
+    nvidia: bool,
+}
+
+impl Default for GpuCollector {
+    fn default() -> Self {
+        Self { nvidia: true }
+    }
+}
+
+impl GpuCollector {
+    pub fn sample(&mut self) -> GpuSample {
+        let mut sample = GpuSample::default();
+        if self.nvidia {
+            match nvidia_sample() {
+                Some(nvidia) => {
+                    sample.devices.extend(nvidia.devices);
+                    sample.processes.extend(nvidia.processes);
+                }
+                None => self.nvidia = false,
+            }
+        }
+        let amd = amdgpu_devices(Path::new(DRM_PATH));
+        if !amd.is_empty() {
+            let cards: HashMap<String, String> = amd
+                .iter()
+                .map(|(card, pci_slot)| (pci_slot.clone(), card.device.clone()))
+                .collect();
+            sample
+                .processes
+                .extend(amdgpu_processes(Path::new(PROC_PATH), &cards));
+            sample.devices.extend(amd.into_iter().map(|(card, _)| card));
+        }
+        sample
+    }
+}
+
+fn nvidia_smi(query: &str) -> Option<String> {
+    let mut command = Command::new("nvidia-smi");
+    command.args([query, "--format=csv,noheader,nounits"]);
+    run_with_timeout(command, NVIDIA_SMI_TIMEOUT)
+}
+
+/// Standard output of `command` if it succeeds within `timeout`; otherwise it is
+/// killed and `None` returned
+fn run_with_timeout(mut command: Command, timeout: Duration) -> Option<String> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+    // Read on another thread so a full pipe cannot stall the child
+    let mut stdout = child.stdout.take()?;
+    let reader = std::thread::spawn(move || {
+        let mut output = Vec::new();
+        stdout.read_to_end(&mut output).map(|_| output)
+    });
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if Instant::now() < deadline => {
+                std::thread::sleep(Duration::from_millis(20));
+            }
+            Ok(None) => {
+                warn!(
+                    "{:?} did not finish within {:?}, killing it",
+                    command.get_program(),
+                    timeout
+                );
+                let _ = child.kill();
+                let _ = child.wait();
+                return None;
+            }
+            Err(_) => return None,
+        }
+    };
+    let output = reader.join().ok()?.ok()?;
+    status
+        .success()
+        .then(|| String::from_utf8_lossy(&output).to_string())
+}
+
+fn nvidia_sample() -> Option<GpuSample> {
+    let gpus = nvidia_smi("--query-gpu=index,uuid,name,utilization.gpu,memory.used,memory.total")?;
+    let (devices, uuids) = parse_nvidia_gpus(&gpus);
+    let processes = nvidia_smi("--query-compute-apps=gpu_uuid,pid,used_memory")
+        .map(|apps| parse_nvidia_apps(&apps, &uuids))
+        .unwrap_or_default();
+    Some(GpuSample { devices, processes })
+}
+
+/// A numeric nvidia-smi field; unsupported ones read `[N/A]`
+fn nvidia_number(field: &str) -> Option<f64> {
+    field.trim().parse().ok()
+}
+
+/// Devices of `--query-gpu` output, and the device of each GPU uuid
+fn parse_nvidia_gpus(csv: &str) -> (Vec<GpuDevice>, HashMap<String, String>) {
+    let mut devices = Vec::new();
+    let mut uuids = HashMap::new();
+    for line in csv.lines() {
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        let [index, uuid, name, utilization, used, total] = fields[..] else {
+            continue;
+        };
+        let device = format!("nvidia{}", index);
+        uuids.insert(uuid.to_string(), device.clone());
+        devices.push(GpuDevice {
+            device,
+            name: name.to_string(),
+            utilization_percent: nvidia_number(utilization),
+            memory_used_bytes: nvidia_number(used).map(|mib| mib as u64 * MIB),
+            memory_total_bytes: nvidia_number(total).map(|mib| mib as u64 * MIB),
+        });
+    }
+    (devices, uuids)
+}
+
+/// Processes of `--query-compute-apps` output
+fn parse_nvidia_apps(csv: &str, uuids: &HashMap<String, String>) -> Vec<GpuProcess> {
+    csv.lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            let [uuid, pid, used] = fields[..] else {
+                return None;
+            };
+            Some(GpuProcess {
+                device: uuids.get(uuid)?.clone(),
+                pid: pid.parse().ok()?,
+                memory_used_bytes: nvidia_number(used)? as u64 * MIB,
+            })
+        })
+        .collect()
+}
+
+/// amdgpu cards under a DRM class directory, with the PCI slot each sits in
+fn amdgpu_devices(root: &Path) -> Vec<(GpuDevice, String)> {
+    let Ok(entries) = std::fs::read_dir(root) else {
+        return Vec::new();
+    };
+    let mut cards: Vec<(GpuDevice, String)> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let card = entry.file_name().to_string_lossy().to_string();
+            // card0-DP-1 and friends are connectors of card0
+            card.strip_prefix("card")
+                .filter(|index| index.chars().all(|c| c.is_ascii_digit()))?;
+            let device = entry.path().join("device");
+            let uevent = read_trimmed(&device.join("uevent"))?;
+            let field = |key: &str| {
+                uevent
+                    .lines()
+                    .find_map(|line| line.strip_prefix(key)?.strip_prefix('='))
+                    .map(str::to_string)
+            };
+            if field("DRIVER")? != "amdgpu" {
+                return None;
+            }
+            let number = |file: &str| read_trimmed(&device.join(file))?.parse::<u64>().ok();
+            let gpu = GpuDevice {
+                name: read_trimmed(&device.join("product_name"))
+                    .filter(|name| !name.is_empty())
+                    .unwrap_or_else(|| "amdgpu".to_string()),
+                device: card,
+                utilization_percent: number("gpu_busy_percent").map(|percent| percent as f64),
+                memory_used_bytes: number("mem_info_vram_used"),
+                memory_total_bytes: number("mem_info_vram_total"),
+            };
+            Some((gpu, field("PCI_SLOT_NAME")?))
+        })
+        .collect();
+    cards.sort_by(|a, b| a.0.device.cmp(&b.0.device));
+    cards
+}
+
+/// VRAM each process holds on the amdgpu cards in `cards` (PCI slot to card), from
+/// the `drm-*` keys of its fdinfo. A DRM client open on several descriptors counts
+/// once.
+fn amdgpu_processes(proc_root: &Path, cards: &HashMap<String, String>) -> Vec<GpuProcess> {
+    let Ok(entries) = std::fs::read_dir(proc_root) else {
+        return Vec::new();
+    };
+    let mut processes = Vec::new();
+    for entry in entries.flatten() {
+        let Ok(pid) = entry.file_name().to_string_lossy().parse::<u32>() else {
+            continue;
+        };
+        let Ok(fds) = std::fs::read_dir(entry.path().join("fdinfo")) else {
+            continue;
+        };
+        let mut clients: BTreeMap<(String, String), u64> = BTreeMap::new();
+        for fd in fds.flatten() {
+            let Some(info) = read_trimmed(&fd.path()) else {
+                continue;
+            };
+            if let Some((card, client, bytes)) = parse_drm_fdinfo(&info, cards) {
+                clients.insert((card, client), bytes);
+            }
+        }
+        let mut per_card: BTreeMap<String, u64> = BTreeMap::new();
+        for ((card, _), bytes) in clients {
+            *per_card.entry(card).or_default() += bytes;
+        }
+        processes.extend(per_card.into_iter().filter(|(_, bytes)| *bytes > 0).map(
+            |(device, memory_used_bytes)| GpuProcess {
+                device,
+                pid,
+                memory_used_bytes,
+            },
+        ));
+    }
+    processes
+}
+
+/// Card, DRM client id and VRAM bytes of an amdgpu fdinfo file
+fn parse_drm_fdinfo(info: &str, cards: &HashMap<String, String>) -> Option<(String, String, u64)> {
+    let field = |key: &str| {
+        info.lines()
+            .find_map(|line| line.strip_prefix(key)?.strip_prefix(':'))
+            .map(str::trim)
+    };
+    if field("drm-driver")? != "amdgpu" {
+        return None;
+    }
+    let card = cards.get(field("drm-pdev")?)?.clone();
+    let client = field("drm-client-id")?.to_string();
+    let vram = field("drm-memory-vram")?;
+    let (amount, unit) = vram.split_once(' ').unwrap_or((vram, ""));
+    let scale = match unit.trim() {
+        "" => 1,
+        "KiB" => 1024,
+        "MiB" => MIB,
+        "GiB" => 1024 * MIB,
+        _ => return None,
+    };
+    Some((card, client, amount.parse::<u64>().ok()? * scale))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_with_timeout_kills_slow_commands() {
+        let mut echo = Command::new("sh");
+        echo.args(["-c", "echo 0, 45"]);
+        assert_eq!(
+            run_with_timeout(echo, Duration::from_secs(5)).as_deref(),
+            Some("0, 45\n")
+        );
+
+        let started = Instant::now();
+        let mut sleep = Command::new("sleep");
+        sleep.arg("10");
+        assert_eq!(run_with_timeout(sleep, Duration::from_millis(100)), None);
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        assert_eq!(
+            run_with_timeout(Command::new("false"), NVIDIA_SMI_TIMEOUT),
+            None
+        );
+    }
+
+    #[test]
+    fn test_parse_nvidia_output() {
+        let (devices, uuids) = parse_nvidia_gpus(
+            "0, GPU-aaa, NVIDIA A100-SXM4-40GB, 87, 30000, 40960\n\
+             1, GPU-bbb, Tesla T4, [N/A], 0, 15360\n",
+        );
+        assert_eq!(devices.len(), 2);
+        assert_eq!(devices[0].device, "nvidia0");
+        assert_eq!(devices[0].utilization_percent, Some(87.0));
+        assert_eq!(devices[0].memory_used_bytes, Some(30000 * MIB));
+        assert_eq!(devices[1].name, "Tesla T4");
+        assert_eq!(devices[1].utilization_percent, None);
+
+        let processes =
+            parse_nvidia_apps("GPU-aaa, 4242, 29000\nGPU-zzz, 1, 10\nmalformed\n", &uuids);
+        assert_eq!(
+            processes,
+            [GpuProcess {
+                device: "nvidia0".to_string(),
+                pid: 4242,
+                memory_used_bytes: 29000 * MIB,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_amdgpu_sysfs_and_fdinfo() {
+        let dir = tempfile::tempdir().unwrap();
+        let drm = dir.path().join("drm");
+        let device = drm.join("card1").join("device");
+        std::fs::create_dir_all(&device).unwrap();
+        std::fs::create_dir_all(drm.join("card1-DP-1")).unwrap();
+        std::fs::write(
+            device.join("uevent"),
+            "DRIVER=amdgpu\nPCI_SLOT_NAME=0000:03:00.0\n",
+        )
+        .unwrap();
+        std::fs::write(device.join("gpu_busy_percent"), "42\n").unwrap();
+        std::fs::write(device.join("mem_info_vram_used"), "1048576\n").unwrap();
+        let cards = amdgpu_devices(&drm);
+        assert_eq!(cards.len(), 1);
+        assert_eq!(cards[0].0.device, "card1");
+        assert_eq!(cards[0].0.name, "amdgpu");
+        assert_eq!(cards[0].0.utilization_percent, Some(42.0));
+        assert_eq!(cards[0].0.memory_used_bytes, Some(MIB));
+        assert_eq!(cards[0].0.memory_total_bytes, None);
+        assert_eq!(cards[0].1, "0000:03:00.0");
+
+        let fdinfo = dir.path().join("proc").join("77").join("fdinfo");
+        std::fs::create_dir_all(&fdinfo).unwrap();
+        let client = |id: u32, kib: u64| {
+            format!(
+                "pos:\t0\ndrm-driver:\tamdgpu\ndrm-pdev:\t0000:03:00.0\n\
+                 drm-client-id:\t{}\ndrm-memory-vram:\t{} KiB\n",
+                id, kib
+            )
+        };
+        // fds 4 and 5 share a client
+        std::fs::write(fdinfo.join("4"), client(9, 2048)).unwrap();
+        std::fs::write(fdinfo.join("5"), client(9, 2048)).unwrap();
+        std::fs::write(fdinfo.join("6"), client(10, 1024)).unwrap();
+        std::fs::write(fdinfo.join("0"), "pos:\t0\nflags:\t02\n").unwrap();
+        let slots = HashMap::from([("0000:03:00.0".to_string(), "card1".to_string())]);
+        assert_eq!(
+            amdgpu_processes(&dir.path().join("proc"), &slots),
+            [GpuProcess {
+                device: "card1".to_string(),
+                pid: 77,
+                memory_used_bytes: 3 * MIB,
+            }]
+        );
+    }
+}
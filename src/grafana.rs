@@ -0,0 +1,212 @@
+//! Request and response shapes of the Grafana simple-json (and Infinity) datasource
+//! API served under /grafana, and the targets it offers.
+//!
+//! Targets name a series:
+//! - `logs.count` / `logs.errors`: journal entries (at priority err or worse) per bin,
+//!   optionally narrowed by a search query after a colon, e.g. `logs.errors:unit:nginx`
+//! - `process.cpu:NAME` / `process.mem:NAME`: CPU percent and memory bytes of the
+//!   processes called NAME, summed per sample and averaged over each bin
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Most points returned per series when Grafana does not say
+const DEFAULT_MAX_DATA_POINTS: i64 = 1_000;
+
+/// Most points returned per series whatever Grafana asks for; longer ranges get
+/// wider bins
+const MAX_DATA_POINTS: i64 = 2_000;
+
+/// Body of POST /grafana/search
+#[derive(Debug, Default, Deserialize)]
+pub struct SearchRequest {
+    /// Text typed into the metric picker
+    #[serde(default)]
+    pub target: String,
+}
+
+/// Body of POST /grafana/query
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryRequest {
+    pub range: TimeRange,
+    #[serde(default)]
+    pub interval_ms: Option<i64>,
+    #[serde(default)]
+    pub max_data_points: Option<i64>,
+    pub targets: Vec<QueryTarget>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TimeRange {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryTarget {
+    #[serde(default)]
+    pub target: String,
+    #[serde(default)]
+    pub ref_id: Option<String>,
+    /// `timeserie` (default) or `table`
+    #[serde(default, rename = "type")]
+    pub kind: Option<String>,
+    /// Set by Grafana on targets hidden in the panel
+    #[serde(default)]
+    pub hide: bool,
+}
+
+/// One entry of the /grafana/query response
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum QueryResult {
+    Series {
+        target: String,
+        /// `[value, unix milliseconds]` pairs
+        datapoints: Vec<(f64, i64)>,
+    },
+    Table {
+        #[serde(rename = "type")]
+        kind: String,
+        columns: Vec<TableColumn>,
+        rows: Vec<(i64, f64)>,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct TableColumn {
+    pub text: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+}
+
+impl QueryResult {
+    /// The series as a time/value table, for targets of type `table`
+    pub fn table(datapoints: Vec<(f64, i64)>) -> Self {
+        let column = |text: &str, kind: &str| TableColumn {
+            text: text.to_string(),
+            kind: kind.to_string(),
+        };
+        Self::Table {
+            kind: "table".to_string(),
+            columns: vec![column("Time", "time"), column("Value", "number")],
+            rows: datapoints.into_iter().map(|(v, t)| (t, v)).collect(),
+        }
+    }
+}
+
+/// Series a target names
+#[derive(Debug, Clone, PartialEq)]
+pub enum Metric {
+    /// Journal entries matching a search query, only errors when `errors_only`
+    LogCount {
+        query: String,
+        errors_only: bool,
+    },
+    ProcessCpu(String),
+    ProcessMemory(String),
+}
+
+/// Targets offered without a search query or process name
+pub const LOG_TARGETS: &[&str] = &["logs.count", "logs.errors"];
+
+impl Metric {
+    pub fn parse(target: &str) -> Result<Self, String> {
+        let target = target.trim();
+        let (name, argument) = match target.split_once(':') {
+            Some((name, argument)) => (name, argument.trim()),
+            None => (target, ""),
+        };
+        let process = |metric: fn(String) -> Self| {
+            if argument.is_empty() {
+                Err(format!("{} needs a process name, e.g. {}:sshd", name, name))
+            } else {
+                Ok(metric(argument.to_string()))
+            }
+        };
+        match name {
+            "logs.count" | "logs.errors" => Ok(Self::LogCount {
+                query: argument.to_string(),
+                errors_only: name == "logs.errors",
+            }),
+            "process.cpu" => process(Self::ProcessCpu),
+            "process.mem" => process(Self::ProcessMemory),
+            _ => Err(format!(
+                "Unknown target '{}'; use logs.count, logs.errors, process.cpu:NAME or \
+                 process.mem:NAME",
+                target
+            )),
+        }
+    }
+}
+
+/// Bin length in seconds for a query: Grafana's interval, widened so the range
+/// needs no more than `max_data_points` bins (at most [`MAX_DATA_POINTS`])
+pub fn bin_seconds(request: &QueryRequest) -> i64 {
+    let range_secs = (request.range.to - request.range.from).num_seconds().max(1);
+    let max_points = request
+        .max_data_points
+        .filter(|points| *points > 0)
+        .unwrap_or(DEFAULT_MAX_DATA_POINTS)
+        .min(MAX_DATA_POINTS);
+    let interval = request.interval_ms.unwrap_or(0) / 1000;
+    interval
+        .max((range_secs + max_points - 1) / max_points)
+        .max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_targets() {
+        assert_eq!(
+            Metric::parse("logs.count"),
+            Ok(Metric::LogCount {
+                query: String::new(),
+                errors_only: false
+            })
+        );
+        assert_eq!(
+            Metric::parse("logs.errors: unit:nginx.service"),
+            Ok(Metric::LogCount {
+                query: "unit:nginx.service".to_string(),
+                errors_only: true
+            })
+        );
+        assert_eq!(
+            Metric::parse("process.mem:postgres"),
+            Ok(Metric::ProcessMemory("postgres".to_string()))
+        );
+        assert!(Metric::parse("process.cpu").is_err());
+        assert!(Metric::parse("disk.io").is_err());
+    }
+
+    #[test]
+    fn test_bin_seconds_respects_max_points() {
+        let request: QueryRequest = serde_json::from_str(
+            r#"{"range": {"from": "2026-01-01T00:00:00Z", "to": "2026-01-01T06:00:00Z"},
+                "intervalMs": 15000, "maxDataPoints": 100, "targets": []}"#,
+        )
+        .unwrap();
+        // 6 hours in at most 100 points
+        assert_eq!(bin_seconds(&request), 216);
+
+        let request = QueryRequest {
+            max_data_points: Some(10_000),
+            ..request
+        };
+        assert_eq!(bin_seconds(&request), 15);
+
+        // Clamped to 2000 points: 21600s / 2000 rounds up to 11s bins
+        let request = QueryRequest {
+            interval_ms: None,
+            max_data_points: Some(10_000_000),
+            ..request
+        };
+        assert_eq!(bin_seconds(&request), 11);
+    }
+}
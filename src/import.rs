@@ -0,0 +1,268 @@
+//! `livedata import`: load log files into the database, to move evidence between
+//! machines. It reads what `livedata export` and /api/export write (NDJSON, CSV or
+//! Parquet, with any columns) and `journalctl -o json` output. Each row becomes an
+//! entry with the journal fields it has, stored like ingested ones under its own
+//! source so it can be told apart from the local journal. Rows whose `__CURSOR` is
+//! already stored under that source are skipped, so importing a file again adds nothing.
+
+use crate::duckdb_buffer::{DuckDBBuffer, INGEST_SEQ_COLUMN};
+use crate::export_jobs::ExportFormat;
+use crate::log_entry::LogEntry;
+use anyhow::{Context, Result};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use log::warn;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+/// Source of imported entries unless told otherwise
+pub const IMPORT_SOURCE: &str = "import";
+
+/// Rows checked against the stored entries and stored at a time
+const IMPORT_BATCH: usize = 10_000;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ImportStats {
+    pub imported: usize,
+    /// Rows without a readable timestamp, or that could not be stored
+    pub skipped: usize,
+    /// Rows already stored under the source
+    pub duplicates: usize,
+}
+
+/// Store the rows of `path`, read as `format`, as entries of `source`
+pub fn import_file(
+    buffer: &mut DuckDBBuffer,
+    path: &Path,
+    format: ExportFormat,
+    source: &str,
+) -> Result<ImportStats> {
+    let mut stats = ImportStats::default();
+    let mut batch = Vec::new();
+    match format {
+        ExportFormat::Ndjson => {
+            let file = File::open(path).with_context(|| format!("opening {}", path.display()))?;
+            for (number, line) in BufReader::new(file).lines().enumerate() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match serde_json::from_str(&line) {
+                    Ok(row) => batch_row(buffer, &mut batch, &row, source, &mut stats)?,
+                    Err(e) => {
+                        warn!("Skipping line {} of {}: {}", number + 1, path.display(), e);
+                        stats.skipped += 1;
+                    }
+                }
+            }
+        }
+        ExportFormat::Csv | ExportFormat::Parquet => {
+            let path = path.display().to_string().replace('\'', "''");
+            let table = match format {
+                ExportFormat::Csv => {
+                    format!("read_csv('{}', header = true, all_varchar = true)", path)
+                }
+                _ => format!("read_parquet('{}')", path),
+            };
+            // Read the file once, then page through it, as the rows are stored through
+            // the same connection
+            let columns = buffer.stage_rows(&table)?;
+            let mut offset = 0;
+            let result = loop {
+                let rows = match buffer.staged_rows(&columns, offset, IMPORT_BATCH) {
+                    Ok(rows) => rows,
+                    Err(e) => break Err(e),
+                };
+                if let Err(e) = rows
+                    .iter()
+                    .try_for_each(|row| batch_row(buffer, &mut batch, row, source, &mut stats))
+                {
+                    break Err(e);
+                }
+                if rows.len() < IMPORT_BATCH {
+                    break Ok(());
+                }
+                offset += rows.len();
+            };
+            buffer.drop_staged_rows()?;
+            result?;
+        }
+    }
+    store_batch(buffer, &mut batch, &mut stats)?;
+    buffer.flush_log_counts()?;
+    Ok(stats)
+}
+
+/// Add the entry of `row` to `batch`, storing the batch once it is full
+fn batch_row(
+    buffer: &mut DuckDBBuffer,
+    batch: &mut Vec<LogEntry>,
+    row: &Value,
+    source: &str,
+    stats: &mut ImportStats,
+) -> Result<()> {
+    let Some(entry) = entry_from_row(row) else {
+        stats.skipped += 1;
+        return Ok(());
+    };
+    batch.push(entry.with_source(source));
+    if batch.len() >= IMPORT_BATCH {
+        store_batch(buffer, batch, stats)?;
+    }
+    Ok(())
+}
+
+fn store_batch(
+    buffer: &mut DuckDBBuffer,
+    batch: &mut Vec<LogEntry>,
+    stats: &mut ImportStats,
+) -> Result<()> {
+    let entries = std::mem::take(batch);
+    let unstored = buffer.unstored_entries(&entries)?;
+    stats.duplicates += entries.len() - unstored.len();
+    for entry in unstored {
+        match buffer.add_entry(entry) {
+            Ok(()) => stats.imported += 1,
+            Err(e) => {
+                warn!("Failed to store an imported entry: {}", e);
+                stats.skipped += 1;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Entry for an exported or `journalctl -o json` row; None without a timestamp
+fn entry_from_row(row: &Value) -> Option<LogEntry> {
+    let mut fields = HashMap::new();
+    let mut timestamp = None;
+    for (column, value) in row.as_object()? {
+        let value = match value {
+            Value::Null => continue,
+            Value::String(s) => s.clone(),
+            other => other.to_string(),
+        };
+        match column.as_str() {
+            "timestamp" => timestamp = parse_timestamp(&value),
+            "__REALTIME_TIMESTAMP" => {
+                timestamp = value.parse().ok().and_then(DateTime::from_timestamp_micros);
+                fields.insert(column.clone(), value);
+            }
+            // The fields that have no column of their own
+            "extra_fields" => {
+                if let Ok(Value::Object(extra)) = serde_json::from_str(&value) {
+                    for (field, value) in extra {
+                        let value = value
+                            .as_str()
+                            .map_or_else(|| value.to_string(), str::to_string);
+                        fields.insert(field, value);
+                    }
+                }
+            }
+            // Replaced by the source of the import
+            "source" => {}
+            // Numbered again as it is stored
+            INGEST_SEQ_COLUMN => {}
+            _ => {
+                fields.insert(journal_field(column), value);
+            }
+        }
+    }
+    Some(LogEntry::new(timestamp?, fields))
+}
+
+/// Journal field of an exported column: the web UI's display names, otherwise the
+/// column name, which is the field name in lower case
+fn journal_field(column: &str) -> String {
+    match column {
+        "hostname" => "_HOSTNAME".to_string(),
+        "unit" => "_SYSTEMD_UNIT".to_string(),
+        "pid" => "_PID".to_string(),
+        "comm" => "_COMM".to_string(),
+        other => other.to_uppercase(),
+    }
+}
+
+/// Exported timestamps are UTC without an offset; RFC 3339 is accepted too
+fn parse_timestamp(value: &str) -> Option<DateTime<Utc>> {
+    NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S%.f")
+        .map(|naive| naive.and_utc())
+        .or_else(|_| DateTime::parse_from_rfc3339(value).map(|time| time.to_utc()))
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_import_exported_and_journalctl_rows() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut buffer = DuckDBBuffer::new(temp_dir.path()).unwrap();
+        let ndjson = temp_dir.path().join("evidence.ndjson");
+        let rows = [
+            json!({
+                "timestamp": "2026-10-17 09:05:01.123456",
+                "hostname": "web1",
+                "unit": "nginx.service",
+                "priority": 3,
+                "pid": "812",
+                "message": "upstream timed out",
+                "extra_fields": {"REQUEST_ID": "abc"},
+            }),
+            json!({
+                "__REALTIME_TIMESTAMP": "1792227901000000",
+                "__CURSOR": "s=1;i=2",
+                "_HOSTNAME": "db1",
+                "MESSAGE": "checkpoint complete",
+            }),
+            json!({"message": "no timestamp"}),
+        ];
+        let lines: Vec<String> = rows.iter().map(Value::to_string).collect();
+        std::fs::write(&ndjson, lines.join("\n") + "\nnot json\n").unwrap();
+
+        let stats = import_file(&mut buffer, &ndjson, ExportFormat::Ndjson, IMPORT_SOURCE).unwrap();
+        assert_eq!(
+            stats,
+            ImportStats {
+                imported: 2,
+                skipped: 2,
+                duplicates: 0,
+            }
+        );
+        assert_eq!(buffer.get_buffer_stats().unwrap().total_entries, 2);
+
+        // Only the row without a cursor is stored again
+        let stats = import_file(&mut buffer, &ndjson, ExportFormat::Ndjson, IMPORT_SOURCE).unwrap();
+        assert_eq!((stats.imported, stats.duplicates), (1, 1));
+        assert_eq!(buffer.get_buffer_stats().unwrap().total_entries, 3);
+
+        let csv = temp_dir.path().join("evidence.csv");
+        std::fs::write(
+            &csv,
+            "timestamp,message,__CURSOR\n\
+             2026-10-17 09:05:01,first,s=1;i=10\n\
+             2026-10-17 09:05:02,second,s=1;i=11\n",
+        )
+        .unwrap();
+        let stats = import_file(&mut buffer, &csv, ExportFormat::Csv, "evidence").unwrap();
+        assert_eq!((stats.imported, stats.duplicates), (2, 0));
+        let stats = import_file(&mut buffer, &csv, ExportFormat::Csv, "evidence").unwrap();
+        assert_eq!((stats.imported, stats.duplicates), (0, 2));
+        assert_eq!(buffer.get_buffer_stats().unwrap().total_entries, 5);
+
+        let entry = entry_from_row(&rows[0]).unwrap();
+        assert_eq!(entry.get_hostname().unwrap(), "web1");
+        assert_eq!(entry.get_field("PRIORITY").unwrap(), "3");
+        assert_eq!(entry.get_field("REQUEST_ID").unwrap(), "abc");
+        assert_eq!(
+            entry.timestamp,
+            parse_timestamp("2026-10-17T09:05:01.123456Z").unwrap()
+        );
+        let entry = entry_from_row(&rows[1]).unwrap();
+        assert_eq!(entry.timestamp.timestamp(), 1_792_227_901);
+    }
+}
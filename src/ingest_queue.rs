@@ -0,0 +1,218 @@
+//! Bounded queue between reading the journal and storing its entries. A writer thread
+//! stores what the entry workers prepare from the records the collector's main loop
+//! reads; when storage falls behind (a checkpoint, a retention run, a slow disk) the
+//! queue fills, the workers wait and the main loop stops reading until there is room
+//! again. Unread entries wait in the journal, so memory stays bounded and nothing is
+//! dropped.
+
+use crate::duckdb_buffer::DuckDBBuffer;
+use crate::internal_events::{self, InternalEvent, InternalEventKind};
+use crate::log_entry::LogEntry;
+use crate::metrics::Metrics;
+use crate::supervisor::{Heartbeat, Supervisor};
+use log::{error, info};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Most entries stored under one hold of the buffer lock, so web queries and process
+/// metrics get their turn
+const WRITE_BATCH: usize = 1000;
+
+pub struct IngestQueue {
+    sender: SyncSender<LogEntry>,
+    /// Entries queued and not yet taken by the writer
+    in_flight: Arc<InFlight>,
+    handle: thread::JoinHandle<()>,
+}
+
+/// Count and rough size of the entries held between two stages
+#[derive(Default)]
+pub struct InFlight {
+    entries: AtomicUsize,
+    bytes: AtomicUsize,
+}
+
+impl InFlight {
+    pub fn add(&self, entries: usize, bytes: usize) {
+        self.entries.fetch_add(entries, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn remove(&self, entries: usize, bytes: usize) {
+        self.entries.fetch_sub(entries, Ordering::Relaxed);
+        self.bytes.fetch_sub(bytes, Ordering::Relaxed);
+    }
+
+    pub fn entries(&self) -> usize {
+        self.entries.load(Ordering::Relaxed)
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.bytes.load(Ordering::Relaxed)
+    }
+}
+
+impl IngestQueue {
+    /// Start the writer storing into `buffer`, with room for `capacity` entries, under
+    /// `supervisor`. `stored` counts the entries stored.
+    pub fn start(
+        buffer: Arc<Mutex<DuckDBBuffer>>,
+        metrics: Arc<Metrics>,
+        stored: Arc<AtomicU64>,
+        capacity: usize,
+        supervisor: &Supervisor,
+    ) -> Self {
+        let (sender, receiver) = mpsc::sync_channel(capacity.max(1));
+        let in_flight = Arc::new(InFlight::default());
+        let writer_in_flight = in_flight.clone();
+        let handle = supervisor.spawn("ingest writer", move |heartbeat| {
+            write_entries(
+                &receiver,
+                &buffer,
+                &metrics,
+                &stored,
+                &writer_in_flight,
+                heartbeat,
+            );
+        });
+        Self {
+            sender,
+            in_flight,
+            handle,
+        }
+    }
+
+    /// Queue `entry`, handing it back when the queue is full
+    pub fn try_push(&self, entry: LogEntry) -> Result<(), LogEntry> {
+        // Counted first, so the writer never takes away more than was added
+        let size = entry.size_bytes();
+        self.in_flight.add(1, size);
+        match self.sender.try_send(entry) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(entry) | TrySendError::Disconnected(entry)) => {
+                self.in_flight.remove(1, size);
+                Err(entry)
+            }
+        }
+    }
+
+    /// Queue `entry`, waiting for room; false once the writer has stopped
+    pub fn push(&self, entry: LogEntry) -> bool {
+        let size = entry.size_bytes();
+        self.in_flight.add(1, size);
+        if self.sender.send(entry).is_err() {
+            self.in_flight.remove(1, size);
+            return false;
+        }
+        true
+    }
+
+    /// Entries queued and not yet taken by the writer
+    pub fn depth(&self) -> usize {
+        self.in_flight.entries()
+    }
+
+    /// Rough size of the entries queued
+    pub fn bytes(&self) -> usize {
+        self.in_flight.bytes()
+    }
+
+    /// Stop taking entries; the writer stores the ones queued, then ends
+    pub fn close(self) -> thread::JoinHandle<()> {
+        drop(self.sender);
+        self.handle
+    }
+}
+
+fn write_entries(
+    receiver: &Receiver<LogEntry>,
+    buffer: &Mutex<DuckDBBuffer>,
+    metrics: &Metrics,
+    stored: &AtomicU64,
+    in_flight: &InFlight,
+    heartbeat: &Heartbeat,
+) {
+    while let Ok(first) = receiver.recv() {
+        heartbeat.busy();
+        let mut batch = vec![first];
+        batch.extend(receiver.try_iter().take(WRITE_BATCH - 1));
+        // Taken off before storing, so a batch lost to a panic is not counted forever
+        let bytes = batch.iter().map(LogEntry::size_bytes).sum();
+        in_flight.remove(batch.len(), bytes);
+        let mut buffer = buffer.lock().unwrap();
+        let mut dropped = 0;
+        for entry in &batch {
+            match buffer.add_entry(entry) {
+                Ok(()) => {
+                    stored.fetch_add(1, Ordering::Relaxed);
+                    metrics.record_ingested(entry.timestamp);
+                }
+                Err(e) => {
+                    error!("Failed to process log entry: {}", e);
+                    dropped += 1;
+                }
+            }
+        }
+        if dropped > 0 {
+            let event = InternalEvent::new(
+                InternalEventKind::DroppedEntries,
+                format!("{} of {} entries could not be stored", dropped, batch.len()),
+                Some(dropped as f64),
+            );
+            internal_events::record(&buffer, event);
+        }
+        drop(buffer);
+        heartbeat.idle();
+    }
+    info!("Ingest writer stored the queued entries and stopped");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_full_queue_hands_entries_back() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let buffer = Arc::new(Mutex::new(DuckDBBuffer::new(temp_dir.path()).unwrap()));
+        let metrics = Arc::new(Metrics::default());
+        let stored = Arc::new(AtomicU64::new(0));
+        let entry = || {
+            let fields = HashMap::from([("MESSAGE".to_string(), "hello".to_string())]);
+            LogEntry::new(Utc::now(), fields)
+        };
+
+        // Holding the lock stalls the writer, as a long checkpoint would
+        let held = buffer.lock().unwrap();
+        let queue = IngestQueue::start(
+            buffer.clone(),
+            metrics.clone(),
+            stored.clone(),
+            2,
+            &Supervisor::default(),
+        );
+        let mut accepted = 0;
+        while queue.try_push(entry()).is_ok() {
+            accepted += 1;
+            assert!(accepted <= 3, "the queue should be bounded");
+        }
+        drop(held);
+
+        queue.close().join().unwrap();
+        assert_eq!(stored.load(Ordering::Relaxed), accepted);
+        assert_eq!(metrics.entries_ingested(), accepted);
+        assert_eq!(
+            buffer
+                .lock()
+                .unwrap()
+                .get_buffer_stats()
+                .unwrap()
+                .total_entries,
+            accepted as i64
+        );
+    }
+}
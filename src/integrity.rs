@@ -0,0 +1,281 @@
+use crate::duckdb_buffer::{MIGRATIONS, parse_partition_table};
+use crate::migrations;
+use crate::sql_trace::trace_sql;
+use anyhow::Result;
+use chrono::Utc;
+use duckdb::{Connection, params};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Rows stamped further than this into the future are reported as anomalies
+const FUTURE_TOLERANCE_MINUTES: i64 = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Ok,
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckResult {
+    pub name: String,
+    pub status: CheckStatus,
+    pub detail: String,
+}
+
+/// Outcome of [`check`]; `status` is the worst status of any individual check
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IntegrityReport {
+    pub status: CheckStatus,
+    pub checked_at: String,
+    pub checks: Vec<CheckResult>,
+}
+
+impl IntegrityReport {
+    fn new(checks: Vec<CheckResult>) -> Self {
+        Self {
+            status: checks
+                .iter()
+                .map(|c| c.status)
+                .max()
+                .unwrap_or(CheckStatus::Ok),
+            checked_at: Utc::now().to_rfc3339(),
+            checks,
+        }
+    }
+}
+
+/// Check the database behind `conn`.
+///
+/// DuckDB has no `PRAGMA integrity_check`; instead, with `scan_tables` every column of
+/// every table is read back, which verifies the block checksums of the whole file.
+/// That is too slow to run routinely, so the web server skips it.
+pub fn check(conn: &Connection, scan_tables: bool) -> IntegrityReport {
+    let mut checks = vec![run("schema_version", || check_schema_version(conn))];
+    if scan_tables {
+        checks.push(run("table_scan", || check_table_scan(conn)));
+    }
+    checks.push(run("partition_days", || check_partition_days(conn)));
+    checks.push(run("future_timestamps", || check_future_timestamps(conn)));
+    IntegrityReport::new(checks)
+}
+
+/// Full check of the database in `data_dir` from a separate process
+pub fn check_data_dir(data_dir: &Path) -> IntegrityReport {
+    let db_path = data_dir.join("livedata.duckdb");
+    if !db_path.exists() {
+        return IntegrityReport::new(vec![CheckResult {
+            name: "open".to_string(),
+            status: CheckStatus::Error,
+            detail: format!("No database found at {}", db_path.display()),
+        }]);
+    }
+    // Open directly: going through DuckDBBuffer would "recover" a database that fails
+    // to open by moving it aside
+    match Connection::open(&db_path) {
+        Ok(conn) => check(&conn, true),
+        Err(e) => IntegrityReport::new(vec![CheckResult {
+            name: "open".to_string(),
+            status: CheckStatus::Error,
+            detail: format!("Failed to open {}: {}", db_path.display(), e),
+        }]),
+    }
+}
+
+/// Run one check, turning a query failure into an error result
+fn run<F>(name: &str, f: F) -> CheckResult
+where
+    F: FnOnce() -> Result<(CheckStatus, String)>,
+{
+    let (status, detail) = f().unwrap_or_else(|e| (CheckStatus::Error, e.to_string()));
+    CheckResult {
+        name: name.to_string(),
+        status,
+        detail,
+    }
+}
+
+fn check_schema_version(conn: &Connection) -> Result<(CheckStatus, String)> {
+    let version = migrations::current_version(conn)?;
+    let latest = migrations::latest_version(MIGRATIONS);
+    Ok(if version == latest {
+        (CheckStatus::Ok, format!("version {}", version))
+    } else if version < latest {
+        (
+            CheckStatus::Warning,
+            format!(
+                "version {}, {} pending migrations",
+                version,
+                latest - version
+            ),
+        )
+    } else {
+        (
+            CheckStatus::Error,
+            format!("version {} is newer than this build ({})", version, latest),
+        )
+    })
+}
+
+fn base_tables(conn: &Connection) -> Result<Vec<String>> {
+    let sql = "SELECT table_name FROM duckdb_tables() \
+               WHERE database_name = current_database() AND NOT temporary ORDER BY table_name";
+    trace_sql(sql);
+    let mut stmt = conn.prepare(sql)?;
+    let names = stmt.query_map([], |row| row.get::<_, String>(0))?;
+    Ok(names.collect::<duckdb::Result<Vec<_>>>()?)
+}
+
+fn check_table_scan(conn: &Connection) -> Result<(CheckStatus, String)> {
+    let tables = base_tables(conn)?;
+    let mut failures = Vec::new();
+    for table in &tables {
+        // COUNT(COLUMNS(*)) reads every column, unlike COUNT(*)
+        let sql = format!(
+            "SELECT COUNT(COLUMNS(*)) FROM \"{}\"",
+            table.replace('"', "\"\"")
+        );
+        trace_sql(&sql);
+        if let Err(e) = conn.query_row(&sql, [], |_| Ok(())) {
+            failures.push(format!("{}: {}", table, e));
+        }
+    }
+    Ok(if failures.is_empty() {
+        (CheckStatus::Ok, format!("{} tables readable", tables.len()))
+    } else {
+        (CheckStatus::Error, failures.join("; "))
+    })
+}
+
+fn check_partition_days(conn: &Connection) -> Result<(CheckStatus, String)> {
+    let mut misplaced = Vec::new();
+    for table in base_tables(conn)? {
+        let Some(day) = parse_partition_table(&table) else {
+            continue;
+        };
+        let sql = format!(
+            "SELECT COUNT(*) FROM {} WHERE CAST(timestamp AS DATE) <> CAST(? AS DATE)",
+            table
+        );
+        trace_sql(&sql);
+        let count: i64 = conn.query_row(&sql, params![day.to_string()], |row| row.get(0))?;
+        if count > 0 {
+            misplaced.push(format!("{}: {} rows", table, count));
+        }
+    }
+    Ok(if misplaced.is_empty() {
+        (
+            CheckStatus::Ok,
+            "all rows in their day's partition".to_string(),
+        )
+    } else {
+        (
+            CheckStatus::Warning,
+            format!(
+                "rows outside their partition's day: {}",
+                misplaced.join(", ")
+            ),
+        )
+    })
+}
+
+fn check_future_timestamps(conn: &Connection) -> Result<(CheckStatus, String)> {
+    let cutoff = (Utc::now() + chrono::Duration::minutes(FUTURE_TOLERANCE_MINUTES))
+        .naive_utc()
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string();
+    let mut found = Vec::new();
+    for table in ["journal_logs", "process_metrics"] {
+        let sql = format!(
+            "SELECT COUNT(*) FROM {} WHERE timestamp > CAST(? AS TIMESTAMP)",
+            table
+        );
+        trace_sql(&sql);
+        let count: i64 = conn.query_row(&sql, params![cutoff], |row| row.get(0))?;
+        if count > 0 {
+            found.push(format!("{}: {} rows", table, count));
+        }
+    }
+    Ok(if found.is_empty() {
+        (CheckStatus::Ok, "no timestamps in the future".to_string())
+    } else {
+        (
+            CheckStatus::Warning,
+            format!("timestamps after {}: {}", cutoff, found.join(", ")),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::duckdb_buffer::DuckDBBuffer;
+    use crate::log_entry::LogEntry;
+    use tempfile::TempDir;
+
+    fn status_of<'a>(report: &'a IntegrityReport, name: &str) -> &'a CheckResult {
+        report.checks.iter().find(|c| c.name == name).unwrap()
+    }
+
+    #[test]
+    fn test_clean_database_passes() {
+        let temp_dir = TempDir::new().unwrap();
+        {
+            let mut buffer = DuckDBBuffer::new(temp_dir.path()).unwrap();
+            let mut fields = std::collections::HashMap::new();
+            fields.insert("MESSAGE".to_string(), "hello".to_string());
+            buffer
+                .add_entry(&LogEntry::new(Utc::now(), fields))
+                .unwrap();
+        }
+
+        let report = check_data_dir(temp_dir.path());
+        assert_eq!(report.status, CheckStatus::Ok, "{:?}", report);
+        assert_eq!(status_of(&report, "table_scan").status, CheckStatus::Ok);
+    }
+
+    #[test]
+    fn test_reports_anomalies() {
+        let temp_dir = TempDir::new().unwrap();
+        let now = Utc::now();
+        {
+            let mut buffer = DuckDBBuffer::new(temp_dir.path()).unwrap();
+            let mut fields = std::collections::HashMap::new();
+            fields.insert("MESSAGE".to_string(), "hello".to_string());
+            buffer.add_entry(&LogEntry::new(now, fields)).unwrap();
+        }
+        let conn = Connection::open(temp_dir.path().join("livedata.duckdb")).unwrap();
+        let today = now.date_naive().format("%Y%m%d");
+        conn.execute(
+            &format!(
+                "UPDATE journal_logs_{} SET timestamp = timestamp + INTERVAL 2 DAY",
+                today
+            ),
+            [],
+        )
+        .unwrap();
+
+        let report = check(&conn, false);
+        assert_eq!(report.status, CheckStatus::Warning);
+        assert!(report.checks.iter().all(|c| c.name != "table_scan"));
+        for name in ["partition_days", "future_timestamps"] {
+            assert_eq!(
+                status_of(&report, name).status,
+                CheckStatus::Warning,
+                "{}",
+                name
+            );
+        }
+        assert_eq!(status_of(&report, "schema_version").status, CheckStatus::Ok);
+    }
+
+    #[test]
+    fn test_missing_database_is_an_error() {
+        let temp_dir = TempDir::new().unwrap();
+        let report = check_data_dir(temp_dir.path());
+        assert_eq!(report.status, CheckStatus::Error);
+        assert_eq!(report.checks[0].name, "open");
+    }
+}
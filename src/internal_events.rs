@@ -0,0 +1,84 @@
+//! livedata's own operational events: backfill runs, retention deletions, checkpoints,
+//! ingest lag samples, reads paused for storage to catch up and entries that could not
+//! be stored. They are kept as long as logs and served on /api/internal, so problems
+//! with the collector itself can be looked into from its own UI.
+
+use crate::duckdb_buffer::DuckDBBuffer;
+use chrono::{DateTime, Utc};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InternalEventKind {
+    /// A backfill of older journal entries finished; value: entries stored
+    Backfill,
+    /// A retention or emergency cleanup ran; value: rows deleted (none when it failed)
+    Retention,
+    /// The WAL was checkpointed; value: seconds it took
+    Checkpoint,
+    /// Periodic sample; value: seconds between now and the newest entry stored
+    IngestLag,
+    /// Journal reads paused because storage fell behind; value: entries queued
+    Backpressure,
+    /// Entries that could not be stored; value: how many
+    DroppedEntries,
+    /// A supervised thread panicked and was started again
+    ThreadRestarted,
+}
+
+impl InternalEventKind {
+    pub const ALL: [Self; 7] = [
+        Self::Backfill,
+        Self::Retention,
+        Self::Checkpoint,
+        Self::IngestLag,
+        Self::Backpressure,
+        Self::DroppedEntries,
+        Self::ThreadRestarted,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Backfill => "backfill",
+            Self::Retention => "retention",
+            Self::Checkpoint => "checkpoint",
+            Self::IngestLag => "ingest_lag",
+            Self::Backpressure => "backpressure",
+            Self::DroppedEntries => "dropped_entries",
+            Self::ThreadRestarted => "thread_restarted",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.as_str() == name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InternalEvent {
+    pub timestamp: DateTime<Utc>,
+    pub kind: InternalEventKind,
+    pub message: String,
+    /// The quantity the kind measures, if any
+    pub value: Option<f64>,
+}
+
+impl InternalEvent {
+    /// Event happening now
+    pub fn new(kind: InternalEventKind, message: impl Into<String>, value: Option<f64>) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            kind,
+            message: message.into(),
+            value,
+        }
+    }
+}
+
+/// Store `event`, logging rather than failing when it cannot be
+pub fn record(buffer: &DuckDBBuffer, event: InternalEvent) {
+    if let Err(e) = buffer.add_internal_event(&event) {
+        warn!("Failed to record {} event: {}", event.kind.as_str(), e);
+    }
+}
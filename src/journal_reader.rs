@@ -2,9 +2,12 @@ use crate::log_entry::LogEntry;
 use anyhow::{Result, anyhow};
 use chrono::{DateTime, Utc};
 use log::info;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use systemd::journal::{Journal, OpenOptions};
 
+/// Fields of a journal entry as read, before it is turned into a [`LogEntry`]
+pub type JournalRecord = BTreeMap<String, String>;
+
 pub struct JournalLogReader {
     journal: Journal,
 }
@@ -35,6 +38,27 @@ impl JournalLogReader {
         Ok(())
     }
 
+    /// Position the reader so that the next entry read is the one after `cursor`
+    pub fn seek_after_cursor(&mut self, cursor: &str) -> Result<()> {
+        info!("Seeking to cursor {}", cursor);
+        self.journal
+            .seek_cursor(cursor)
+            .map_err(|e| anyhow!("Failed to seek to cursor: {}", e))?;
+        // Seeking lands on the entry itself; step over it
+        self.journal
+            .next_entry()
+            .map_err(|e| anyhow!("Failed to read entry at cursor: {}", e))?;
+        Ok(())
+    }
+
+    pub fn seek_to_head(&mut self) -> Result<()> {
+        info!("Seeking to head of journal");
+        self.journal
+            .seek_head()
+            .map_err(|e| anyhow!("Failed to seek to head: {}", e))?;
+        Ok(())
+    }
+
     pub fn previous_skip(&mut self, skip_count: u64) -> Result<()> {
         info!("previous_skip({})", skip_count);
         self.journal
@@ -136,40 +160,8 @@ impl JournalLogReader {
         }
     }
 
-    fn convert_journal_entry(
-        &self,
-        entry: &std::collections::BTreeMap<String, String>,
-    ) -> Result<LogEntry> {
-        let mut fields = HashMap::new();
-
-        // Extract all fields from the journal entry
-        for (field_name, field_value) in entry {
-            let name_str = field_name.to_string();
-            let value_str = field_value.clone();
-            fields.insert(name_str, value_str);
-        }
-
-        // Try to extract timestamp
-        let timestamp = self.extract_timestamp(&fields)?;
-
-        Ok(LogEntry::new(timestamp, fields))
-    }
-
-    fn extract_timestamp(&self, fields: &HashMap<String, String>) -> Result<DateTime<Utc>> {
-        if let Some(ts_usec) = fields.get("__REALTIME_TIMESTAMP") {
-            let timestamp_usec: u64 = ts_usec
-                .parse()
-                .map_err(|e| anyhow!("Failed to parse timestamp: {}", e))?;
-
-            let timestamp_sec = timestamp_usec / 1_000_000;
-            let timestamp_nsec = (timestamp_usec % 1_000_000) * 1000;
-
-            DateTime::from_timestamp(timestamp_sec as i64, timestamp_nsec as u32)
-                .ok_or_else(|| anyhow!("Invalid timestamp: {}", timestamp_usec))
-        } else {
-            // If no timestamp in entry, use current time
-            Ok(Utc::now())
-        }
+    fn convert_journal_entry(&self, entry: &JournalRecord) -> Result<LogEntry> {
+        log_entry_from_record(entry.clone())
     }
 
     pub fn previous_entry(&mut self) -> Result<Option<LogEntry>> {
@@ -186,6 +178,14 @@ impl JournalLogReader {
         }
     }
 
+    /// Next newly logged entry as read, leaving the conversion to the caller; `None`
+    /// once there are no more
+    pub fn next_record(&mut self) -> Result<Option<JournalRecord>> {
+        self.journal
+            .next_entry()
+            .map_err(|e| anyhow!("Failed to read journal entry: {}", e))
+    }
+
     pub fn next_log_entry(&mut self) -> Result<Option<LogEntry>> {
         // Check for entries and return them
         match self.journal.next_entry() {
@@ -257,6 +257,30 @@ impl JournalLogReader {
     }
 }
 
+/// Turn the fields of a journal entry into a [`LogEntry`]
+pub fn log_entry_from_record(record: JournalRecord) -> Result<LogEntry> {
+    let fields: HashMap<String, String> = record.into_iter().collect();
+    let timestamp = extract_timestamp(&fields)?;
+    Ok(LogEntry::new(timestamp, fields))
+}
+
+fn extract_timestamp(fields: &HashMap<String, String>) -> Result<DateTime<Utc>> {
+    if let Some(ts_usec) = fields.get("__REALTIME_TIMESTAMP") {
+        let timestamp_usec: u64 = ts_usec
+            .parse()
+            .map_err(|e| anyhow!("Failed to parse timestamp: {}", e))?;
+
+        let timestamp_sec = timestamp_usec / 1_000_000;
+        let timestamp_nsec = (timestamp_usec % 1_000_000) * 1000;
+
+        DateTime::from_timestamp(timestamp_sec as i64, timestamp_nsec as u32)
+            .ok_or_else(|| anyhow!("Invalid timestamp: {}", timestamp_usec))
+    } else {
+        // If no timestamp in entry, use current time
+        Ok(Utc::now())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
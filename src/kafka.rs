@@ -0,0 +1,199 @@
+//! Kafka output: publishes each entry as a JSON message. A batch only counts as sent
+//! once the brokers have acknowledged every message in it, so the output's cursor
+//! never moves past an entry that may not have arrived.
+
+use crate::log_entry::LogEntry;
+use crate::outputs::Rejected;
+use anyhow::{Result, bail};
+use futures_util::future::join_all;
+use rdkafka::config::ClientConfig;
+use rdkafka::error::{KafkaError, RDKafkaErrorCode};
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use rdkafka::util::Timeout;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Longest wait for room in the producer's queue
+const QUEUE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Field messages are keyed by, so that entries with the same value land in the same
+/// partition and keep their order
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KafkaKey {
+    Hostname,
+    Unit,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KafkaCompression {
+    #[default]
+    None,
+    Gzip,
+    Snappy,
+    Lz4,
+    Zstd,
+}
+
+impl KafkaCompression {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Gzip => "gzip",
+            Self::Snappy => "snappy",
+            Self::Lz4 => "lz4",
+            Self::Zstd => "zstd",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KafkaSettings {
+    /// Bootstrap brokers, e.g. "kafka1:9092,kafka2:9092"
+    pub brokers: String,
+
+    pub topic: String,
+
+    /// Key messages by `hostname` or `unit`; without one they are spread over the
+    /// partitions
+    #[serde(default)]
+    pub key: Option<KafkaKey>,
+
+    #[serde(default)]
+    pub compression: KafkaCompression,
+
+    /// Further librdkafka properties, e.g. `security.protocol` or `sasl.username`;
+    /// these win over the ones livedata sets
+    #[serde(default)]
+    pub properties: BTreeMap<String, String>,
+}
+
+pub struct KafkaSink {
+    producer: FutureProducer,
+    topic: String,
+    key: Option<KafkaKey>,
+}
+
+impl KafkaSink {
+    /// Producer for `settings`; brokers are only contacted once there is something
+    /// to send. Deliveries not acknowledged within `timeout` fail.
+    pub fn new(settings: &KafkaSettings, timeout: Duration) -> Result<Self> {
+        let mut config = ClientConfig::new();
+        config
+            .set("bootstrap.servers", &settings.brokers)
+            .set("compression.type", settings.compression.as_str())
+            .set("acks", "all")
+            .set("enable.idempotence", "true")
+            .set("message.timeout.ms", timeout.as_millis().to_string());
+        for (name, value) in &settings.properties {
+            config.set(name, value);
+        }
+        Ok(Self {
+            producer: config.create()?,
+            topic: settings.topic.clone(),
+            key: settings.key,
+        })
+    }
+
+    fn message_key<'a>(&self, entry: &'a LogEntry) -> Option<&'a str> {
+        match self.key? {
+            KafkaKey::Hostname => entry.get_hostname(),
+            KafkaKey::Unit => entry.get_systemd_unit(),
+        }
+        .map(String::as_str)
+    }
+
+    /// Publish `batch`, waiting for every message to be acknowledged
+    pub async fn send(&self, batch: &[Arc<LogEntry>]) -> Result<()> {
+        let payloads = batch
+            .iter()
+            .map(|entry| serde_json::to_vec(entry.as_ref()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let deliveries = batch.iter().zip(&payloads).map(|(entry, payload)| {
+            let mut record = FutureRecord::<str, [u8]>::to(&self.topic).payload(payload);
+            if let Some(key) = self.message_key(entry) {
+                record = record.key(key);
+            }
+            self.producer.send(record, Timeout::After(QUEUE_TIMEOUT))
+        });
+        let mut failures = 0;
+        let mut first_error = None;
+        let mut all_too_large = true;
+        for result in join_all(deliveries).await {
+            if let Err((e, _)) = result {
+                failures += 1;
+                all_too_large &= matches!(
+                    e,
+                    KafkaError::MessageProduction(RDKafkaErrorCode::MessageSizeTooLarge)
+                );
+                first_error.get_or_insert(e);
+            }
+        }
+        match first_error {
+            None => Ok(()),
+            // Sending them again cannot help
+            Some(e) if all_too_large => {
+                Err(Rejected(format!("{} of {} messages: {}", failures, batch.len(), e)).into())
+            }
+            Some(e) => bail!(
+                "{} of {} messages not delivered: {}",
+                failures,
+                batch.len(),
+                e
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::outputs::{OutputSettings, SinkSettings};
+    use chrono::Utc;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_kafka_settings_and_keys() {
+        let settings: OutputSettings = toml::from_str(
+            r#"
+name = "kafka"
+kind = "kafka"
+brokers = "localhost:9092"
+topic = "journal"
+key = "unit"
+compression = "zstd"
+properties = { "client.id" = "livedata-web1" }
+"#,
+        )
+        .unwrap();
+        let SinkSettings::Kafka(kafka) = &settings.sink else {
+            panic!("not a Kafka output: {:?}", settings.sink);
+        };
+        assert_eq!(kafka.compression, KafkaCompression::Zstd);
+        assert_eq!(kafka.key, Some(KafkaKey::Unit));
+
+        // No broker is contacted until something is sent
+        let sink = KafkaSink::new(kafka, Duration::from_secs(1)).unwrap();
+        let fields: HashMap<String, String> =
+            [("_HOSTNAME", "web1"), ("_SYSTEMD_UNIT", "nginx.service")]
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect();
+        let entry = LogEntry::new(Utc::now(), fields);
+        assert_eq!(sink.message_key(&entry), Some("nginx.service"));
+        let unkeyed = KafkaSink { key: None, ..sink };
+        assert_eq!(unkeyed.message_key(&entry), None);
+
+        let unknown_compression = r#"
+name = "kafka"
+kind = "kafka"
+brokers = "localhost:9092"
+topic = "journal"
+compression = "brotli"
+"#;
+        assert!(toml::from_str::<OutputSettings>(unknown_compression).is_err());
+    }
+}
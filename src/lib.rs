@@ -1,8 +1,55 @@
+pub mod agent;
+pub mod alerting;
+pub mod api_client;
 pub mod app_controller;
+pub mod archive;
+pub mod auth;
+pub mod backup;
+pub mod chat;
+pub mod clickhouse;
 pub mod config;
+pub mod control;
+pub mod disk_space;
 pub mod duckdb_buffer;
+pub mod email;
+pub mod entry_workers;
+pub mod export_jobs;
+pub mod gpu;
+pub mod grafana;
+pub mod import;
+pub mod ingest_queue;
+pub mod integrity;
+pub mod internal_events;
 pub mod journal_reader;
+#[cfg(feature = "kafka")]
+pub mod kafka;
 pub mod log_entry;
+pub mod logql;
+pub mod loki_push;
+pub mod metrics;
+pub mod migrations;
+pub mod notifications;
+pub mod oidc;
+pub mod otlp;
+pub mod outputs;
+pub mod parquet_writer;
+pub mod peer_replication;
+pub mod process_alerts;
 pub mod process_monitor;
+mod queries;
+pub mod query_cache;
+pub mod rate_limit;
+pub mod read_pool;
+pub mod remote_write;
+pub mod search_query;
+pub mod silences;
+pub mod spool;
 pub mod sql_trace;
+pub mod supervisor;
+pub mod syslog_forward;
+pub mod systemd_notify;
+pub mod tui;
+pub mod unit_failures;
+pub mod user_names;
 pub mod web_server;
+pub mod webhook;
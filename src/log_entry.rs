@@ -2,15 +2,40 @@ use chrono::{DateTime, Timelike, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+/// `source` of entries read from this machine's journal
+pub const LOCAL_SOURCE: &str = "local";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LogEntry {
     pub timestamp: DateTime<Utc>,
     pub fields: HashMap<String, String>,
+    /// Input the entry arrived through; [`LOCAL_SOURCE`] for the local journal
+    #[serde(default = "local_source")]
+    pub source: String,
+    /// Position in the order entries were stored, on entries read back from or tailed
+    /// off the database
+    #[serde(skip)]
+    pub seq: Option<i64>,
+}
+
+fn local_source() -> String {
+    LOCAL_SOURCE.to_string()
 }
 
 impl LogEntry {
     pub fn new(timestamp: DateTime<Utc>, fields: HashMap<String, String>) -> Self {
-        Self { timestamp, fields }
+        Self {
+            timestamp,
+            fields,
+            source: local_source(),
+            seq: None,
+        }
+    }
+
+    /// Tag the entry with the input it was received from
+    pub fn with_source(mut self, source: impl Into<String>) -> Self {
+        self.source = source.into();
+        self
     }
 
     pub fn get_field(&self, key: &str) -> Option<&String> {
@@ -310,6 +335,13 @@ impl LogEntry {
         self.get_field("__SEQNUM_ID")
     }
 
+    /// Rough heap size of the entry, for bounding the memory held by queued entries
+    pub fn size_bytes(&self) -> usize {
+        let fields: usize = self.fields.iter().map(|(k, v)| k.len() + v.len()).sum();
+        // Each map slot holds two strings besides their text
+        fields + self.fields.len() * 2 * size_of::<String>() + self.source.len()
+    }
+
     pub fn minute_key(&self) -> DateTime<Utc> {
         let mut minute_key = self.timestamp;
         minute_key = minute_key.with_second(0).unwrap_or(minute_key);
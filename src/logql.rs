@@ -0,0 +1,479 @@
+//! The subset of Loki's read API served under /loki: log queries made of a stream
+//! selector and line filters, answered from journal_logs, so Grafana's Loki datasource
+//! (Explore and log panels) works against livedata.
+
+use crate::duckdb_buffer::SqlParam;
+use crate::log_entry::LOCAL_SOURCE;
+use crate::parquet_writer::quote_ident;
+use crate::search_query::{escape_like, field_column, has_column};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Most entries one query returns
+pub const MAX_LIMIT: usize = 5_000;
+
+/// Label comparison of a stream selector
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MatchOp {
+    /// `=`
+    Eq,
+    /// `!=`
+    Ne,
+    /// `=~`, a regular expression matching the whole value
+    Re,
+    /// `!~`
+    NotRe,
+}
+
+/// One `label op "value"` of a stream selector
+#[derive(Debug, Clone, PartialEq)]
+pub struct LabelMatcher {
+    pub label: String,
+    pub op: MatchOp,
+    pub value: String,
+}
+
+/// Line filter applied to the message
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FilterOp {
+    /// `|=`, contains the text
+    Contains,
+    /// `!=`
+    NotContains,
+    /// `|~`, a regular expression matching anywhere in the line
+    Re,
+    /// `!~`
+    NotRe,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineFilter {
+    pub op: FilterOp,
+    pub value: String,
+}
+
+/// The subset of LogQL log queries livedata answers: a stream selector followed by
+/// line filters, e.g. `{unit="nginx.service", host=~"web-.*"} |= "error" != "debug"`.
+/// Parsers, formatters and metric queries (`count_over_time`, ...) are not supported.
+///
+/// Labels are `unit`, `host`/`hostname`, `source`, or any journal_logs column by the
+/// names of the search syntax.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LogQuery {
+    pub matchers: Vec<LabelMatcher>,
+    pub filters: Vec<LineFilter>,
+}
+
+/// Labels every stream is identified by, with the columns holding them
+pub const STREAM_LABELS: &[(&str, &str)] = &[
+    ("host", "_hostname"),
+    ("unit", "_systemd_unit"),
+    ("source", "source"),
+];
+
+/// SQL expression of a label's value
+pub fn label_expr(label: &str, schema: &[(String, String)]) -> Result<String, String> {
+    let column = field_column(label);
+    if column == "source" {
+        // Cold storage written before the column existed only holds local entries
+        return Ok(format!("COALESCE(source, '{}')", LOCAL_SOURCE));
+    }
+    if !has_column(schema, column) {
+        return Err(format!("Unknown label '{}'", label));
+    }
+    Ok(format!("CAST({} AS VARCHAR)", quote_ident(column)))
+}
+
+impl LogQuery {
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let mut parser = Parser {
+            rest: input.trim_start(),
+        };
+        let mut query = LogQuery::default();
+
+        parser.expect("{")?;
+        if !parser.eat("}") {
+            loop {
+                let label = parser.ident()?;
+                let op = if parser.eat("=~") {
+                    MatchOp::Re
+                } else if parser.eat("!~") {
+                    MatchOp::NotRe
+                } else if parser.eat("!=") {
+                    MatchOp::Ne
+                } else if parser.eat("=") {
+                    MatchOp::Eq
+                } else {
+                    return Err(format!("Expected =, !=, =~ or !~ after '{}'", label));
+                };
+                let value = parser.string()?;
+                query.matchers.push(LabelMatcher { label, op, value });
+                if parser.eat("}") {
+                    break;
+                }
+                parser.expect(",")?;
+            }
+        }
+
+        while !parser.rest.is_empty() {
+            let op = if parser.eat("|=") {
+                FilterOp::Contains
+            } else if parser.eat("!=") {
+                FilterOp::NotContains
+            } else if parser.eat("|~") {
+                FilterOp::Re
+            } else if parser.eat("!~") {
+                FilterOp::NotRe
+            } else {
+                return Err(format!(
+                    "Unsupported expression '{}'; only line filters (|=, !=, |~, !~) may \
+                     follow the stream selector",
+                    parser.rest
+                ));
+            };
+            let value = parser.string()?;
+            query.filters.push(LineFilter { op, value });
+        }
+        Ok(query)
+    }
+
+    /// WHERE clause condition over journal_logs columns with its bound values
+    pub fn to_sql(&self, schema: &[(String, String)]) -> Result<(String, Vec<SqlParam>), String> {
+        let mut conditions = Vec::new();
+        let mut params = Vec::new();
+        for matcher in &self.matchers {
+            let expr = label_expr(&matcher.label, schema)?;
+            // A missing label equals the empty string, as in Loki
+            let expr = format!("COALESCE({}, '')", expr);
+            conditions.push(match matcher.op {
+                MatchOp::Eq => format!("{} = ?", expr),
+                MatchOp::Ne => format!("{} <> ?", expr),
+                MatchOp::Re => format!("regexp_full_match({}, ?)", expr),
+                MatchOp::NotRe => format!("NOT regexp_full_match({}, ?)", expr),
+            });
+            params.push(SqlParam::Text(matcher.value.clone()));
+        }
+        for filter in &self.filters {
+            let (condition, value) = match filter.op {
+                FilterOp::Contains => (
+                    "message LIKE ? ESCAPE '\\'",
+                    format!("%{}%", escape_like(&filter.value)),
+                ),
+                FilterOp::NotContains => (
+                    "COALESCE(message, '') NOT LIKE ? ESCAPE '\\'",
+                    format!("%{}%", escape_like(&filter.value)),
+                ),
+                FilterOp::Re => ("regexp_matches(message, ?)", filter.value.clone()),
+                FilterOp::NotRe => (
+                    "NOT regexp_matches(COALESCE(message, ''), ?)",
+                    filter.value.clone(),
+                ),
+            };
+            conditions.push(condition.to_string());
+            params.push(SqlParam::Text(value));
+        }
+        if conditions.is_empty() {
+            return Ok(("TRUE".to_string(), params));
+        }
+        Ok((conditions.join(" AND "), params))
+    }
+}
+
+/// Query parameters of /loki/api/v1/query_range
+#[derive(Debug, Deserialize)]
+pub struct QueryRangeParams {
+    pub query: String,
+    /// Unix nanoseconds, seconds with a fraction, or RFC 3339 (default: an hour before end)
+    #[serde(default)]
+    pub start: Option<String>,
+    /// Same formats as start (default: now)
+    #[serde(default)]
+    pub end: Option<String>,
+    /// Entries returned (default: 100, max: 5000)
+    #[serde(default = "default_limit")]
+    pub limit: usize,
+    /// `backward` (newest first, the default) or `forward`
+    #[serde(default)]
+    pub direction: Option<String>,
+}
+
+/// Query parameters of /loki/api/v1/labels and /loki/api/v1/label/{name}/values
+#[derive(Debug, Deserialize)]
+pub struct LabelsParams {
+    #[serde(default)]
+    pub start: Option<String>,
+    #[serde(default)]
+    pub end: Option<String>,
+}
+
+fn default_limit() -> usize {
+    100
+}
+
+/// Range of a request: `end` defaults to `now` and `start` to an hour before it
+pub fn time_range(
+    start: Option<&str>,
+    end: Option<&str>,
+    now: DateTime<Utc>,
+) -> Result<(DateTime<Utc>, DateTime<Utc>), String> {
+    let end = end.map(parse_timestamp).transpose()?.unwrap_or(now);
+    let start = start
+        .map(parse_timestamp)
+        .transpose()?
+        .unwrap_or(end - chrono::Duration::hours(1));
+    Ok((start, end))
+}
+
+/// Parse a Loki timestamp: Unix seconds (integers of up to 10 digits or with a
+/// fraction), longer integer Unix nanoseconds, or RFC 3339
+pub fn parse_timestamp(s: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(value) = s.parse::<i64>() {
+        if s.trim_start_matches(['-', '+']).len() <= 10 {
+            return DateTime::from_timestamp(value, 0)
+                .ok_or_else(|| format!("Timestamp out of range: {}", s));
+        }
+        return Ok(DateTime::from_timestamp_nanos(value));
+    }
+    if let Ok(seconds) = s.parse::<f64>() {
+        return DateTime::from_timestamp_micros((seconds * 1e6) as i64)
+            .ok_or_else(|| format!("Timestamp out of range: {}", s));
+    }
+    DateTime::parse_from_rfc3339(s)
+        .map(|t| t.with_timezone(&Utc))
+        .map_err(|_| format!("Invalid timestamp: {}", s))
+}
+
+/// Envelope of every Loki API response
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LokiResponse<T> {
+    pub status: String,
+    pub data: T,
+}
+
+impl<T> LokiResponse<T> {
+    pub fn success(data: T) -> Self {
+        Self {
+            status: "success".to_string(),
+            data,
+        }
+    }
+}
+
+/// `data` of a log query response
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamsData {
+    /// Always `streams`
+    pub result_type: String,
+    pub result: Vec<Stream>,
+}
+
+/// Entries sharing one set of labels
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct Stream {
+    pub stream: BTreeMap<String, String>,
+    /// `[unix nanoseconds, line]` pairs
+    pub values: Vec<(String, String)>,
+}
+
+impl StreamsData {
+    /// Group `(labels, unix nanoseconds, line)` rows into streams, keeping the row order
+    /// within each stream and ordering streams by their first row
+    pub fn from_rows(
+        rows: impl IntoIterator<Item = (BTreeMap<String, String>, i64, String)>,
+    ) -> Self {
+        let mut result: Vec<Stream> = Vec::new();
+        let mut index: BTreeMap<BTreeMap<String, String>, usize> = BTreeMap::new();
+        for (labels, nanos, line) in rows {
+            let i = *index.entry(labels.clone()).or_insert_with(|| {
+                result.push(Stream {
+                    stream: labels,
+                    values: Vec::new(),
+                });
+                result.len() - 1
+            });
+            result[i].values.push((nanos.to_string(), line));
+        }
+        Self {
+            result_type: "streams".to_string(),
+            result,
+        }
+    }
+}
+
+struct Parser<'a> {
+    rest: &'a str,
+}
+
+impl Parser<'_> {
+    fn eat(&mut self, token: &str) -> bool {
+        match self.rest.strip_prefix(token) {
+            Some(rest) => {
+                self.rest = rest.trim_start();
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), String> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(format!("Expected '{}' at '{}'", token, self.rest))
+        }
+    }
+
+    fn ident(&mut self) -> Result<String, String> {
+        let end = self
+            .rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(self.rest.len());
+        if end == 0 {
+            return Err(format!("Expected a label name at '{}'", self.rest));
+        }
+        let ident = self.rest[..end].to_string();
+        self.rest = self.rest[end..].trim_start();
+        Ok(ident)
+    }
+
+    /// A `"double quoted"` string with backslash escapes, or a `` `raw` `` one
+    fn string(&mut self) -> Result<String, String> {
+        let mut chars = self.rest.char_indices();
+        let quote = match chars.next() {
+            Some((_, quote @ ('"' | '`'))) => quote,
+            _ => return Err(format!("Expected a quoted string at '{}'", self.rest)),
+        };
+        let mut value = String::new();
+        while let Some((i, c)) = chars.next() {
+            if c == quote {
+                self.rest = self.rest[i + 1..].trim_start();
+                return Ok(value);
+            }
+            if c == '\\' && quote == '"' {
+                match chars.next() {
+                    Some((_, 'n')) => value.push('\n'),
+                    Some((_, 't')) => value.push('\t'),
+                    Some((_, escaped)) => value.push(escaped),
+                    None => break,
+                }
+            } else {
+                value.push(c);
+            }
+        }
+        Err(format!("Unterminated string at '{}'", self.rest))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_log_queries() {
+        let query =
+            LogQuery::parse(r#"{unit="nginx.service", host=~"web-\\d+"} |= "error" != `debug`"#)
+                .unwrap();
+        assert_eq!(
+            query.matchers,
+            vec![
+                LabelMatcher {
+                    label: "unit".to_string(),
+                    op: MatchOp::Eq,
+                    value: "nginx.service".to_string(),
+                },
+                LabelMatcher {
+                    label: "host".to_string(),
+                    op: MatchOp::Re,
+                    value: r"web-\d+".to_string(),
+                },
+            ]
+        );
+        assert_eq!(
+            query.filters,
+            vec![
+                LineFilter {
+                    op: FilterOp::Contains,
+                    value: "error".to_string(),
+                },
+                LineFilter {
+                    op: FilterOp::NotContains,
+                    value: "debug".to_string(),
+                },
+            ]
+        );
+
+        assert_eq!(LogQuery::parse("{}").unwrap(), LogQuery::default());
+        assert!(LogQuery::parse(r#"{unit="a"} | json"#).is_err());
+        assert!(LogQuery::parse(r#"count_over_time({unit="a"}[5m])"#).is_err());
+        assert!(LogQuery::parse(r#"{unit="a}"#).is_err());
+    }
+
+    #[test]
+    fn test_log_query_sql() {
+        let schema = vec![
+            ("_systemd_unit".to_string(), "VARCHAR".to_string()),
+            ("message".to_string(), "VARCHAR".to_string()),
+        ];
+        let query = LogQuery::parse(r#"{unit!~"cron.*", source="local"} |~ "fail(ed)?""#).unwrap();
+        let (sql, params) = query.to_sql(&schema).unwrap();
+        assert_eq!(
+            sql,
+            "NOT regexp_full_match(COALESCE(CAST(\"_systemd_unit\" AS VARCHAR), ''), ?) \
+             AND COALESCE(COALESCE(source, 'local'), '') = ? \
+             AND regexp_matches(message, ?)"
+        );
+        assert_eq!(
+            params,
+            vec![
+                SqlParam::Text("cron.*".to_string()),
+                SqlParam::Text("local".to_string()),
+                SqlParam::Text("fail(ed)?".to_string()),
+            ]
+        );
+
+        let unknown = LogQuery::parse(r#"{team="web"}"#).unwrap();
+        assert!(unknown.to_sql(&schema).is_err());
+    }
+
+    #[test]
+    fn test_parse_timestamps_and_group_streams() {
+        let expected = DateTime::parse_from_rfc3339("2026-01-01T00:00:00.5Z").unwrap();
+        for input in [
+            "1767225600500000000",
+            "1767225600.5",
+            "2026-01-01T00:00:00.5Z",
+        ] {
+            assert_eq!(parse_timestamp(input), Ok(expected.with_timezone(&Utc)));
+        }
+        assert!(parse_timestamp("yesterday").is_err());
+        assert_eq!(
+            parse_timestamp("1767225600"),
+            Ok(DateTime::from_timestamp(1767225600, 0).unwrap())
+        );
+
+        let labels = |unit: &str| BTreeMap::from([("unit".to_string(), unit.to_string())]);
+        let data = StreamsData::from_rows([
+            (labels("b"), 3, "three".to_string()),
+            (labels("a"), 2, "two".to_string()),
+            (labels("b"), 1, "one".to_string()),
+        ]);
+        assert_eq!(
+            data.result,
+            vec![
+                Stream {
+                    stream: labels("b"),
+                    values: vec![
+                        ("3".to_string(), "three".to_string()),
+                        ("1".to_string(), "one".to_string()),
+                    ],
+                },
+                Stream {
+                    stream: labels("a"),
+                    values: vec![("2".to_string(), "two".to_string())],
+                },
+            ]
+        );
+    }
+}
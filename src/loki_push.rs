@@ -0,0 +1,248 @@
+//! Grafana Loki output: pushes entries to Loki's push API, one stream per distinct set
+//! of label values.
+
+use crate::log_entry::LogEntry;
+use crate::outputs::check_response;
+use crate::search_query::field_column;
+use anyhow::{Result, bail};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Label streams fall back to when an entry has none of the configured fields, as
+/// Loki refuses streams without labels
+const FALLBACK_LABEL: (&str, &str) = ("job", "livedata");
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LokiSettings {
+    /// Push endpoint, e.g. "http://loki:3100/loki/api/v1/push"
+    pub url: String,
+
+    /// Fields that become stream labels, by their search names (`unit`, `host`,
+    /// `priority`, `syslog_identifier`, ...) or `source`. Keep these to fields with
+    /// few distinct values; every combination is a stream to Loki.
+    #[serde(default = "default_labels")]
+    pub labels: Vec<String>,
+
+    /// Tenant sent as `X-Scope-OrgID` to a multi-tenant Loki
+    #[serde(default)]
+    pub tenant: Option<String>,
+
+    /// Extra request headers, e.g. an `Authorization` token
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+}
+
+fn default_labels() -> Vec<String> {
+    ["host", "unit", "source"].map(String::from).to_vec()
+}
+
+/// Loki label name for a field name: letters, digits and underscores, not starting
+/// with a digit
+fn label_name(field: &str) -> String {
+    let mut name: String = field
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        name.insert(0, '_');
+    }
+    name
+}
+
+/// Body of a push request
+#[derive(Debug, Serialize)]
+struct PushRequest {
+    streams: Vec<Stream>,
+}
+
+#[derive(Debug, Serialize)]
+struct Stream {
+    stream: BTreeMap<String, String>,
+    /// `[nanoseconds since the epoch, line]` pairs
+    values: Vec<[String; 2]>,
+}
+
+pub struct LokiSink {
+    client: reqwest::Client,
+    url: Url,
+    tenant: Option<String>,
+    headers: BTreeMap<String, String>,
+    /// Label names with the journal fields they are taken from
+    labels: Vec<(String, String)>,
+}
+
+impl LokiSink {
+    pub fn new(settings: &LokiSettings, timeout: Duration) -> Result<Self> {
+        if settings.labels.is_empty() {
+            bail!("at least one label is needed");
+        }
+        Ok(Self {
+            client: reqwest::Client::builder().timeout(timeout).build()?,
+            url: Url::parse(&settings.url)?,
+            tenant: settings.tenant.clone(),
+            headers: settings.headers.clone(),
+            labels: settings
+                .labels
+                .iter()
+                .map(|label| (label_name(label), field_column(label).to_uppercase()))
+                .collect(),
+        })
+    }
+
+    /// Labels of `entry`'s stream
+    fn stream_labels(&self, entry: &LogEntry) -> BTreeMap<String, String> {
+        let mut labels: BTreeMap<String, String> = self
+            .labels
+            .iter()
+            .filter_map(|(label, field)| {
+                let value = if field == "SOURCE" {
+                    Some(&entry.source)
+                } else {
+                    entry.get_field(field)
+                };
+                value
+                    .filter(|value| !value.is_empty())
+                    .map(|value| (label.clone(), value.clone()))
+            })
+            .collect();
+        if labels.is_empty() {
+            let (label, value) = FALLBACK_LABEL;
+            labels.insert(label.to_string(), value.to_string());
+        }
+        labels
+    }
+
+    /// Group `batch` into streams, keeping the entries' order within each
+    fn push_request(&self, batch: &[Arc<LogEntry>]) -> PushRequest {
+        let mut streams: BTreeMap<BTreeMap<String, String>, Vec<[String; 2]>> = BTreeMap::new();
+        for entry in batch {
+            let nanos = entry.timestamp.timestamp_nanos_opt().unwrap_or_default();
+            streams.entry(self.stream_labels(entry)).or_default().push([
+                nanos.to_string(),
+                entry.get_message().cloned().unwrap_or_default(),
+            ]);
+        }
+        PushRequest {
+            streams: streams
+                .into_iter()
+                .map(|(stream, values)| Stream { stream, values })
+                .collect(),
+        }
+    }
+
+    pub async fn send(&self, batch: &[Arc<LogEntry>]) -> Result<()> {
+        let mut request = self
+            .client
+            .post(self.url.clone())
+            .json(&self.push_request(batch));
+        if let Some(tenant) = &self.tenant {
+            request = request.header("X-Scope-OrgID", tenant);
+        }
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+        check_response(request.send().await).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::outputs::{OutputSettings, SinkSettings};
+    use chrono::{DateTime, TimeDelta, Utc};
+    use std::collections::HashMap;
+
+    fn entry(seconds: i64, fields: &[(&str, &str)]) -> Arc<LogEntry> {
+        let fields: HashMap<String, String> = fields
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        Arc::new(LogEntry::new(
+            DateTime::<Utc>::UNIX_EPOCH + TimeDelta::seconds(seconds),
+            fields,
+        ))
+    }
+
+    #[test]
+    fn test_push_request_groups_streams() {
+        let settings: OutputSettings = toml::from_str(
+            r#"
+name = "loki"
+kind = "loki"
+url = "http://loki:3100/loki/api/v1/push"
+labels = ["host", "unit", "priority"]
+tenant = "ops"
+"#,
+        )
+        .unwrap();
+        let SinkSettings::Loki(loki) = &settings.sink else {
+            panic!("not a Loki output: {:?}", settings.sink);
+        };
+        assert_eq!(loki.tenant.as_deref(), Some("ops"));
+        let sink = LokiSink::new(loki, Duration::from_secs(1)).unwrap();
+
+        let batch = [
+            entry(
+                1,
+                &[
+                    ("_HOSTNAME", "web1"),
+                    ("_SYSTEMD_UNIT", "nginx.service"),
+                    ("PRIORITY", "6"),
+                    ("MESSAGE", "GET /"),
+                ],
+            ),
+            entry(2, &[("MESSAGE", "kernel: oops")]),
+            entry(
+                3,
+                &[
+                    ("_HOSTNAME", "web1"),
+                    ("_SYSTEMD_UNIT", "nginx.service"),
+                    ("PRIORITY", "6"),
+                    ("MESSAGE", "GET /favicon.ico"),
+                ],
+            ),
+        ];
+        let request = serde_json::to_value(sink.push_request(&batch)).unwrap();
+        assert_eq!(
+            request,
+            serde_json::json!({
+                "streams": [
+                    {
+                        "stream": {"host": "web1", "priority": "6", "unit": "nginx.service"},
+                        "values": [
+                            ["1000000000", "GET /"],
+                            ["3000000000", "GET /favicon.ico"],
+                        ],
+                    },
+                    {
+                        "stream": {"job": "livedata"},
+                        "values": [["2000000000", "kernel: oops"]],
+                    },
+                ]
+            })
+        );
+    }
+
+    #[test]
+    fn test_default_labels() {
+        let settings: OutputSettings =
+            toml::from_str("name = \"loki\"\nkind = \"loki\"\nurl = \"http://loki:3100/push\"")
+                .unwrap();
+        let SinkSettings::Loki(loki) = &settings.sink else {
+            panic!("not a Loki output");
+        };
+        let sink = LokiSink::new(loki, Duration::from_secs(1)).unwrap();
+        let labels = sink.stream_labels(&entry(0, &[("_HOSTNAME", "db1")]));
+        assert_eq!(
+            labels,
+            BTreeMap::from([
+                ("host".to_string(), "db1".to_string()),
+                ("source".to_string(), "local".to_string()),
+            ])
+        );
+        assert_eq!(label_name("x-request.id"), "x_request_id");
+    }
+}
@@ -1,15 +1,27 @@
 use anyhow::Result;
-use clap::Parser;
-use livedata::app_controller::ApplicationController;
+use clap::{Parser, ValueEnum};
+use livedata::agent::Agent;
+use livedata::api_client::{self, ApiClient};
+use livedata::app_controller::{ApplicationController, join_until};
+use livedata::backup::{self, BackupOptions};
 use livedata::config::{Settings, parse_size};
-use livedata::web_server::run_web_server;
+use livedata::control::{self, ControlCommand};
+use livedata::duckdb_buffer::{DuckDBBuffer, PurgeFilter};
+use livedata::export_jobs::ExportFormat;
+use livedata::import::{self, IMPORT_SOURCE};
+use livedata::integrity::{self, CheckStatus};
+use livedata::tui;
+use livedata::web_server::{SearchResponse, WebServerDeps, parse_time, run_web_server};
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
 use std::thread;
+use std::time::Duration;
 use tracing::info;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 
 /// livedata - Journald log collector with DuckDB storage
-#[derive(Parser, Debug)]
+#[derive(Parser, Clone, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
     /// Data directory for storing DuckDB database
@@ -44,6 +56,14 @@ struct Args {
     #[arg(long)]
     cleanup_interval: Option<u32>,
 
+    /// Days of logs kept in DuckDB before older days roll into Parquet cold storage
+    #[arg(long)]
+    hot_storage_days: Option<u32>,
+
+    /// Export each completed minute of logs to Parquet under data_dir/parquet
+    #[arg(long)]
+    parquet_export: bool,
+
     /// Maximum database size for backfill (e.g., 5G, 500M). Enables historical journal scanning.
     #[arg(long)]
     max_db_size: Option<String>,
@@ -52,11 +72,75 @@ struct Args {
     #[arg(long)]
     sql_trace: bool,
 
+    /// What this instance does with the journal
+    #[arg(long, value_enum, default_value = "standalone")]
+    mode: Mode,
+
     #[command(subcommand)]
     command: Option<Commands>,
 }
 
-#[derive(Parser, Debug)]
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum Mode {
+    /// Store entries in the local database
+    Standalone,
+    /// Forward entries to the server in the `[agent]` table, keeping only a spool
+    Agent,
+    /// Store local entries and those agents and peers send to `[peer_input]`
+    Server,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum OutputFormat {
+    /// One line per entry, like journalctl
+    Short,
+    /// One JSON object per line
+    Json,
+}
+
+/// Filters of `search` and `tail`, with the meaning of the /api/search ones
+#[derive(clap::Args, Clone, Debug)]
+struct LogFilters {
+    /// Search query: free text, field:value, field!=value or field<value (also <=, >, >=)
+    /// terms, all of which must match; a leading - negates a term
+    #[arg(short)]
+    q: Option<String>,
+    /// Systemd units (comma-separated)
+    #[arg(long)]
+    unit: Option<String>,
+    /// Hostnames (comma-separated)
+    #[arg(long)]
+    hostname: Option<String>,
+    /// Inputs the entries arrived through (comma-separated, e.g. local)
+    #[arg(long)]
+    source: Option<String>,
+    /// Entries with priority at or below this value (0 = emerg .. 7 = debug)
+    #[arg(long)]
+    priority: Option<u8>,
+}
+
+impl LogFilters {
+    /// The filters as /api/search query parameters
+    fn query(&self) -> Vec<(&'static str, String)> {
+        let mut query = Vec::new();
+        for (name, value) in [
+            ("q", &self.q),
+            ("unit", &self.unit),
+            ("hostname", &self.hostname),
+            ("source", &self.source),
+        ] {
+            if let Some(value) = value {
+                query.push((name, value.clone()));
+            }
+        }
+        if let Some(priority) = self.priority {
+            query.push(("priority", priority.to_string()));
+        }
+        query
+    }
+}
+
+#[derive(Parser, Clone, Debug)]
 enum Commands {
     /// Run the web server
     Web {
@@ -64,11 +148,367 @@ enum Commands {
         #[arg(long)]
         listen_all: bool,
     },
+    /// Apply pending database schema migrations and exit
+    Migrate {
+        /// List pending migrations without applying them
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Write a consistent snapshot of the database to a directory and exit.
+    /// A running collector is asked to copy its database over the control socket.
+    Backup {
+        /// Directory to write the snapshot into
+        dest: PathBuf,
+        /// Gzip the snapshot
+        #[arg(long)]
+        compress: bool,
+        /// Keep only the newest N snapshots in the destination
+        #[arg(long)]
+        keep: Option<usize>,
+    },
+    /// Check database integrity, print a JSON report and exit (non-zero on errors).
+    /// The collector must not be running.
+    Check,
+    /// Print the status of the collector running with this data directory
+    Status,
+    /// Send a command to the collector running with this data directory
+    Ctl {
+        /// status, checkpoint, retention, reload or flush
+        command: ControlCommand,
+    },
+    /// Restore the database from a backup, migrating it to the current schema, and exit.
+    /// The collector must not be running.
+    Restore {
+        /// Snapshot file written by `backup`
+        backup: PathBuf,
+    },
+    /// Search the logs stored by the running web server, with the filters of the web UI,
+    /// printing the newest matches oldest first
+    Search {
+        #[command(flatten)]
+        filters: LogFilters,
+        /// Entries at or after this time (RFC 3339 or relative, e.g. -1h)
+        #[arg(long, default_value = "-1h", allow_hyphen_values = true)]
+        since: String,
+        /// Entries before this time (RFC 3339, relative or now)
+        #[arg(long, default_value = "now", allow_hyphen_values = true)]
+        until: String,
+        /// Columns to print (comma-separated)
+        #[arg(long)]
+        columns: Option<String>,
+        /// Most entries printed
+        #[arg(short = 'n', long, default_value = "100")]
+        limit: usize,
+        /// How entries are printed
+        #[arg(short, long, value_enum, default_value = "short")]
+        output: OutputFormat,
+        /// Web server to ask (default: this machine's, port 3000)
+        #[arg(long)]
+        url: Option<String>,
+    },
+    /// Print the newest matching entries, then the ones the running web server ingests
+    /// from then on, like `journalctl -f`
+    Tail {
+        #[command(flatten)]
+        filters: LogFilters,
+        /// Entries printed before following
+        #[arg(short = 'n', long, default_value = "10")]
+        lines: usize,
+        /// Poll the database every this many seconds instead of following the live
+        /// ingest stream, which can skip entries when the terminal falls behind
+        #[arg(long)]
+        poll: Option<u64>,
+        /// How entries are printed
+        #[arg(short, long, value_enum, default_value = "short")]
+        output: OutputFormat,
+        /// Web server to ask (default: this machine's, port 3000)
+        #[arg(long)]
+        url: Option<String>,
+    },
+    /// Export the logs matching the filters from the running web server to a file, as
+    /// the web UI's export does
+    Export {
+        /// File to write; its extension (.ndjson, .csv or .parquet) picks the format
+        output: PathBuf,
+        #[command(flatten)]
+        filters: LogFilters,
+        /// Entries at or after this time (RFC 3339 or relative, e.g. -1h)
+        #[arg(long, default_value = "-1h", allow_hyphen_values = true)]
+        since: String,
+        /// Entries before this time (RFC 3339, relative or now)
+        #[arg(long, default_value = "now", allow_hyphen_values = true)]
+        until: String,
+        /// Columns to export (comma-separated)
+        #[arg(long)]
+        columns: Option<String>,
+        /// ndjson, csv or parquet, when the extension does not say
+        #[arg(long)]
+        format: Option<ExportFormat>,
+        /// Most entries exported
+        #[arg(long)]
+        limit: Option<usize>,
+        /// Web server to ask (default: this machine's, port 3000)
+        #[arg(long)]
+        url: Option<String>,
+    },
+    /// Store the entries of exported files (or `journalctl -o json` output) in the
+    /// database and exit. The collector must not be running.
+    Import {
+        /// Files to import
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        /// ndjson, csv or parquet, when the extensions do not say
+        #[arg(long)]
+        format: Option<ExportFormat>,
+        /// Source the entries are stored under
+        #[arg(long, default_value = IMPORT_SOURCE)]
+        source: String,
+    },
+    /// Browse logs and processes in the terminal: a search box, a histogram of the
+    /// matches, the results and the busiest processes, read from the running web server
+    Tui {
+        /// Entries searched from this time (RFC 3339 or relative, e.g. -1h)
+        #[arg(long, default_value = "-1h", allow_hyphen_values = true)]
+        since: String,
+        /// Seconds between refreshes
+        #[arg(long, default_value = "5")]
+        refresh: u64,
+        /// Web server to ask (default: this machine's, port 3000)
+        #[arg(long)]
+        url: Option<String>,
+    },
+    /// Delete the log entries matching all given filters from DuckDB and cold storage,
+    /// then exit. At least one filter is required. The collector must not be running.
+    Purge {
+        /// Exact systemd unit
+        #[arg(long)]
+        unit: Option<String>,
+        /// Exact hostname
+        #[arg(long)]
+        hostname: Option<String>,
+        /// Entries at or after this time (RFC 3339 or relative, e.g. -7d)
+        #[arg(long)]
+        since: Option<String>,
+        /// Entries before this time (RFC 3339 or relative, e.g. -1h)
+        #[arg(long)]
+        until: Option<String>,
+        /// Entries with priority at or below this value (0 = emerg .. 7 = debug)
+        #[arg(long)]
+        max_priority: Option<u8>,
+        /// Rows deleted per statement
+        #[arg(long, default_value = "10000")]
+        batch_size: usize,
+    },
+}
+
+/// Run `livedata search` against the web server and print the matches
+fn search(command: &Commands, settings: &Settings) -> Result<()> {
+    let Commands::Search {
+        filters,
+        since,
+        until,
+        columns,
+        limit,
+        output,
+        url,
+    } = command
+    else {
+        return Ok(());
+    };
+    let mut query = filters.query();
+    query.extend([
+        ("start", since.clone()),
+        ("end", until.clone()),
+        ("limit", limit.to_string()),
+        ("sort", "timestamp".to_string()),
+        ("sort_dir", "desc".to_string()),
+    ]);
+    if let Some(columns) = columns {
+        query.push(("columns", columns.clone()));
+    }
+
+    let client = ApiClient::new(url.as_deref(), settings)?;
+    let response: SearchResponse = client.get_json("/api/search", &query)?;
+    // Newest first from the server, printed oldest first like journalctl
+    let color = use_color();
+    for row in response.results.iter().rev() {
+        print_row(row, *output, color);
+    }
+    if response.results.is_empty() {
+        eprintln!("-- No entries --");
+    } else if response.total > response.results.len() {
+        eprintln!(
+            "-- {} of {} matching entries, the newest shown --",
+            response.results.len(),
+            response.total
+        );
+    }
+    Ok(())
+}
+
+/// Most entries `livedata tail --poll` asks for at a time
+const POLL_LIMIT: usize = 1000;
+
+/// Run `livedata tail`: print the newest matches, then follow the live ingest stream
+/// (or poll) until interrupted
+fn tail(command: &Commands, settings: &Settings) -> Result<()> {
+    let Commands::Tail {
+        filters,
+        lines,
+        poll,
+        output,
+        url,
+    } = command
+    else {
+        return Ok(());
+    };
+    let client = ApiClient::new(url.as_deref(), settings)?;
+    let color = use_color();
+    let mut query = filters.query();
+    query.extend([
+        ("start", "-7d".to_string()),
+        ("sort", "timestamp".to_string()),
+        ("sort_dir", "desc".to_string()),
+    ]);
+    match poll {
+        // Each poll answers with up to `limit` entries: the first with the newest, newest
+        // first, the later ones with those after it, oldest first
+        Some(interval) => query.extend([
+            ("limit", (*lines).max(POLL_LIMIT).to_string()),
+            ("interval", interval.to_string()),
+        ]),
+        None => {
+            query.push(("limit", lines.to_string()));
+            let recent: SearchResponse = client.get_json("/api/search", &query)?;
+            for row in recent.results.iter().rev() {
+                print_row(row, *output, color);
+            }
+            query.push(("follow", "tail".to_string()));
+        }
+    }
+
+    let mut first = poll.is_some();
+    client.stream_events("/api/search/stream", &query, |event, data| {
+        match event {
+            "results" => {
+                let rows = data["results"].as_array().cloned().unwrap_or_default();
+                let rows: Vec<_> = if first {
+                    rows.into_iter().take(*lines).rev().collect()
+                } else {
+                    rows
+                };
+                first = false;
+                for row in &rows {
+                    print_row(row, *output, color);
+                }
+            }
+            "missed" => eprintln!("-- {} entries missed --", data["missed"]),
+            "error" => eprintln!("-- {} --", data["error"].as_str().unwrap_or("poll failed")),
+            _ => {}
+        }
+        true
+    })?;
+    eprintln!("-- The web server ended the stream --");
+    Ok(())
+}
+
+/// Run `livedata export`: stream the web server's export into the output file
+fn export(command: &Commands, settings: &Settings) -> Result<()> {
+    let Commands::Export {
+        output,
+        filters,
+        since,
+        until,
+        columns,
+        format,
+        limit,
+        url,
+    } = command
+    else {
+        return Ok(());
+    };
+    let Some(format) = format.or_else(|| ExportFormat::from_path(output)) else {
+        anyhow::bail!(
+            "cannot tell the format of {}, use --format",
+            output.display()
+        );
+    };
+    let mut query = filters.query();
+    query.extend([
+        ("start", since.clone()),
+        ("end", until.clone()),
+        ("sort", "timestamp".to_string()),
+        ("sort_dir", "asc".to_string()),
+        ("format", format.extension().to_string()),
+    ]);
+    if let Some(columns) = columns {
+        query.push(("columns", columns.clone()));
+    }
+    if let Some(limit) = limit {
+        query.push(("limit", limit.to_string()));
+    }
+
+    let client = ApiClient::new(url.as_deref(), settings)?;
+    let mut file = std::io::BufWriter::new(std::fs::File::create(output)?);
+    match client.download("/api/export", &query, &mut file) {
+        Ok(bytes) => {
+            info!("Exported {} bytes to {}", bytes, output.display());
+            Ok(())
+        }
+        Err(e) => {
+            drop(file);
+            let _ = std::fs::remove_file(output);
+            Err(e)
+        }
+    }
+}
+
+/// Whether entries are coloured: on a terminal, unless NO_COLOR is set
+fn use_color() -> bool {
+    std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none()
+}
+
+fn print_row(row: &serde_json::Value, output: OutputFormat, color: bool) {
+    match output {
+        OutputFormat::Short => {
+            let line = api_client::format_short(row);
+            if color {
+                println!("{}", api_client::colorize(&line, row["priority"].as_u64()));
+            } else {
+                println!("{}", line);
+            }
+        }
+        OutputFormat::Json => println!("{}", row),
+    }
+}
+
+/// Settings from the config file and environment with the command-line overrides
+fn load_settings(args: &Args) -> Result<Settings> {
+    let mut settings = Settings::load_with_cli_args(
+        args.log_retention_days,
+        args.log_max_size_gb,
+        args.process_retention_days,
+        args.process_max_size_gb,
+        args.cleanup_interval,
+    )?;
+
+    if let Some(days) = args.hot_storage_days {
+        settings.hot_storage_days = Some(days);
+    }
+    if args.parquet_export {
+        settings.parquet_export = true;
+    }
+    if let Some(ref size_str) = args.max_db_size {
+        settings.max_db_size_bytes = Some(parse_size(size_str)?);
+    }
+    Ok(settings)
 }
 
 fn main() -> Result<()> {
     // Initialize logging to stdout
+    // Logs go to stderr, so command output on stdout can be piped
     let fmt_layer = tracing_subscriber::fmt::layer()
+        .with_writer(std::io::stderr)
         .with_target(false)
         .with_level(true);
 
@@ -83,18 +523,22 @@ fn main() -> Result<()> {
     let args = Args::parse();
 
     // Load configuration with CLI overrides
-    let mut settings = Settings::load_with_cli_args(
-        args.log_retention_days,
-        args.log_max_size_gb,
-        args.process_retention_days,
-        args.process_max_size_gb,
-        args.cleanup_interval,
-    )?;
-
-    // Parse and set max_db_size if provided
-    if let Some(ref size_str) = args.max_db_size {
-        let max_bytes = parse_size(size_str)?;
-        settings.max_db_size_bytes = Some(max_bytes);
+    let settings = load_settings(&args)?;
+    match &args.command {
+        Some(command @ Commands::Search { .. }) => return search(command, &settings),
+        Some(command @ Commands::Tail { .. }) => return tail(command, &settings),
+        Some(command @ Commands::Export { .. }) => return export(command, &settings),
+        Some(Commands::Tui {
+            since,
+            refresh,
+            url,
+        }) => {
+            let client = ApiClient::new(url.as_deref(), &settings)?;
+            return tui::run(&client, since, Duration::from_secs((*refresh).max(1)));
+        }
+        _ => {}
+    }
+    if let Some(max_bytes) = settings.max_db_size_bytes {
         info!("Backfill enabled: max DB size = {} bytes", max_bytes);
     }
 
@@ -110,6 +554,108 @@ fn main() -> Result<()> {
         "  Cleanup interval: {} minutes",
         settings.cleanup_interval_minutes
     );
+    for rule in &settings.retention_rules {
+        info!(
+            "  Retention rule: unit={} max_priority={} -> {} days",
+            rule.unit.as_deref().unwrap_or("*"),
+            rule.max_priority
+                .map(|p| p.to_string())
+                .unwrap_or_else(|| "*".to_string()),
+            rule.days
+        );
+    }
+    if let Some(days) = settings.hot_storage_days {
+        info!("  Hot storage: {} days (older logs roll to Parquet)", days);
+    }
+    if settings.parquet_export {
+        info!("  Parquet export: enabled (one file per completed minute)");
+    }
+    if settings.web_auth_enabled() {
+        info!("  Web authentication: enabled");
+    }
+    if let Some(oidc) = &settings.oidc {
+        info!("  OIDC login: {}", oidc.issuer);
+    }
+    if let Some((cert, _)) = settings.tls_paths()? {
+        info!("  Web server TLS: enabled ({})", cert.display());
+    }
+    if !settings.dropped_fields.is_empty() {
+        info!("  Dropped fields: {}", settings.dropped_fields.join(", "));
+    }
+    for rule in &settings.alert_rules {
+        info!(
+            "  Alert rule {}: more than {} entries matching '{}' in {} (every {})",
+            rule.name, rule.threshold, rule.query, rule.window, rule.interval
+        );
+    }
+    for webhook in &settings.webhooks {
+        let host = reqwest::Url::parse(&webhook.url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string));
+        info!(
+            "  Alert webhook: {} ({} retries)",
+            host.as_deref().unwrap_or("invalid URL"),
+            webhook.max_retries
+        );
+    }
+    for chat in &settings.chat_notifiers {
+        info!(
+            "  Chat notifier: {} (tags: {})",
+            chat.service.as_str(),
+            if chat.tags.is_empty() {
+                "all".to_string()
+            } else {
+                chat.tags.join(", ")
+            }
+        );
+    }
+    for output in &settings.outputs {
+        info!(
+            "  Output {}: {} (query: '{}')",
+            output.name,
+            output.sink.kind(),
+            output.query
+        );
+    }
+    if let Some(remote_write) = &settings.remote_write {
+        let host = reqwest::Url::parse(&remote_write.url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string));
+        info!(
+            "  Remote write: {}",
+            host.as_deref().unwrap_or("invalid URL")
+        );
+    }
+    if let Some(agent) = settings.agent.as_ref().filter(|_| args.mode == Mode::Agent) {
+        let host = reqwest::Url::parse(&agent.server.url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string));
+        info!(
+            "  Agent mode: forwarding to {}",
+            host.as_deref().unwrap_or("invalid URL")
+        );
+    }
+    if let Some(peer_input) = &settings.peer_input {
+        info!("  Peer input: {} (mutual TLS)", peer_input.listen);
+    }
+    if let Some(smtp) = &settings.smtp {
+        info!("  Alert emails: {} via {}", smtp.to.join(", "), smtp.host);
+    }
+    for rule in &settings.process_alert_rules {
+        info!(
+            "  Process alert rule {}: name={} unit={} cpu>{} memory>{} for {} samples",
+            rule.name,
+            rule.process_name.as_deref().unwrap_or("*"),
+            rule.unit.as_deref().unwrap_or("*"),
+            rule.cpu_percent
+                .map(|p| format!("{}%", p))
+                .unwrap_or_else(|| "-".to_string()),
+            rule.memory_bytes
+                .map(|b| b.to_string())
+                .unwrap_or_else(|| "-".to_string()),
+            rule.samples
+        );
+    }
 
     info!("Using data directory: {}", args.data_dir);
     if args.follow {
@@ -131,11 +677,154 @@ fn main() -> Result<()> {
         }
     }
 
+    if let Some(Commands::Migrate { dry_run }) = args.command {
+        let report = DuckDBBuffer::migrate(&args.data_dir, dry_run)?;
+        if report.applied.is_empty() {
+            info!("Schema is up to date (version {})", report.from_version);
+        }
+        for (version, description) in &report.applied {
+            if dry_run {
+                info!("Pending migration {}: {}", version, description);
+            } else {
+                info!("Applied migration {}: {}", version, description);
+            }
+        }
+        return Ok(());
+    }
+
+    if let Some(Commands::Backup {
+        dest,
+        compress,
+        keep,
+    }) = &args.command
+    {
+        let options = BackupOptions {
+            compress: *compress,
+            keep: *keep,
+        };
+        let path = backup::backup_data_dir(Path::new(&args.data_dir), dest, &options)?;
+        info!("Backup written to {}", path.display());
+        return Ok(());
+    }
+
+    if let Some(command) = match &args.command {
+        Some(Commands::Status) => Some(ControlCommand::Status),
+        Some(Commands::Ctl { command }) => Some(*command),
+        _ => None,
+    } {
+        let socket = control::socket_path(Path::new(&args.data_dir));
+        let result = control::send_command(&socket, command)?;
+        println!("{}", serde_json::to_string_pretty(&result)?);
+        return Ok(());
+    }
+
+    if let Some(Commands::Check) = &args.command {
+        let report = integrity::check_data_dir(Path::new(&args.data_dir));
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        if report.status == CheckStatus::Error {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if let Some(Commands::Restore { backup }) = &args.command {
+        let report = DuckDBBuffer::restore(&args.data_dir, backup)?;
+        for (version, description) in &report.applied {
+            info!("Applied migration {}: {}", version, description);
+        }
+        info!(
+            "Restored {} (schema version {})",
+            backup.display(),
+            report.to_version
+        );
+        return Ok(());
+    }
+
+    if let Some(Commands::Purge {
+        unit,
+        hostname,
+        since,
+        until,
+        max_priority,
+        batch_size,
+    }) = &args.command
+    {
+        let now = chrono::Utc::now();
+        let parse = |time: &Option<String>| {
+            time.as_deref()
+                .map(|t| parse_time(t, now))
+                .transpose()
+                .map_err(anyhow::Error::msg)
+        };
+        let filter = PurgeFilter {
+            unit: unit.clone(),
+            hostname: hostname.clone(),
+            start: parse(since)?,
+            end: parse(until)?,
+            max_priority: *max_priority,
+        };
+        let mut buffer = DuckDBBuffer::new(&args.data_dir)?;
+        let deleted = buffer.purge_logs(&filter, *batch_size, |day, total| {
+            info!("Purging {}: {} entries deleted so far", day, total);
+        })?;
+        info!("Purge complete: {} log entries deleted", deleted);
+        return Ok(());
+    }
+
+    if let Some(Commands::Import {
+        files,
+        format,
+        source,
+    }) = &args.command
+    {
+        let mut buffer = DuckDBBuffer::new(&args.data_dir)?;
+        buffer.set_dropped_fields(&settings.dropped_fields);
+        for file in files {
+            let Some(format) = format.or_else(|| ExportFormat::from_path(file)) else {
+                anyhow::bail!("cannot tell the format of {}, use --format", file.display());
+            };
+            let stats = import::import_file(&mut buffer, file, format, source)?;
+            info!(
+                "Imported {} entries from {} ({} skipped, {} already stored)",
+                stats.imported,
+                file.display(),
+                stats.skipped,
+                stats.duplicates
+            );
+        }
+        return Ok(());
+    }
+
+    if args.mode == Mode::Agent {
+        if args.command.is_some() {
+            anyhow::bail!("subcommands need a local database, which agent mode has none of");
+        }
+        let mut agent = Agent::new(&args.data_dir, &settings)?;
+        agent.run(args.follow)?;
+        info!("Agent shutdown complete");
+        return Ok(());
+    }
+
+    if args.mode == Mode::Server && settings.peer_input.is_none() {
+        anyhow::bail!("server mode needs a [peer_input] table in the config");
+    }
+
+    // SIGHUP reloads the config, with the same command-line overrides
+    let reload_args = args.clone();
+    let reload_settings = move || load_settings(&reload_args);
+
     // Check if the web subcommand is present
     if let Some(Commands::Web { listen_all }) = args.command {
+        if listen_all && !settings.web_auth_enabled() {
+            tracing::warn!(
+                "Listening on all interfaces without web authentication: anyone who can \
+                 reach the port can read every log line"
+            );
+        }
         let settings_for_web = settings.clone();
         // Create and run the application in the main thread
-        let mut app = ApplicationController::new(&args.data_dir, args.process_interval, settings)?;
+        let mut app = ApplicationController::new(&args.data_dir, args.process_interval, settings)?
+            .with_settings_loader(reload_settings);
 
         // Get shutdown signal to share with web server
         let shutdown_signal = app.get_shutdown_signal();
@@ -143,31 +832,37 @@ fn main() -> Result<()> {
         // Get process monitor from app BEFORE moving app
         let process_monitor = app.get_process_monitor();
         let buffer = app.get_buffer();
+        let metrics = app.get_metrics();
+        let notifier = app.get_notifier();
 
         // Run the web server in a separate thread
-        let data_dir = args.data_dir.clone();
+        let deps = WebServerDeps {
+            data_dir: args.data_dir.clone(),
+            buffer,
+            shutdown_signal,
+            process_monitor,
+            metrics,
+            notifier,
+            settings: settings_for_web,
+            listen_all,
+        };
         let web_server_handle = thread::spawn(move || {
             let rt = tokio::runtime::Runtime::new().unwrap();
-            rt.block_on(run_web_server(
-                &data_dir,
-                buffer,
-                shutdown_signal,
-                process_monitor,
-                settings_for_web,
-                listen_all,
-            ));
+            rt.block_on(run_web_server(deps));
         });
 
         app.run(args.follow, false)?;
 
-        // Wait for the web server to finish
-        web_server_handle.join().unwrap();
+        // Wait for the web server to finish, so the checkpoint below is not raced by its
+        // last queries
+        join_until(web_server_handle, "web server", app.shutdown_deadline());
 
         // Ensure checkpoint after the web server releases its connection.
         app.checkpoint_database();
     } else {
         // Create and run the application in the main thread
-        let mut app = ApplicationController::new(&args.data_dir, args.process_interval, settings)?;
+        let mut app = ApplicationController::new(&args.data_dir, args.process_interval, settings)?
+            .with_settings_loader(reload_settings);
         app.run(args.follow, true)?;
     }
 
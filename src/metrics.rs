@@ -0,0 +1,526 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Internal counters shared by the collector and the web server, rendered on /metrics
+/// in the Prometheus text format
+#[derive(Default)]
+pub struct Metrics {
+    entries_ingested: AtomicU64,
+    /// Entries stored from peers since startup
+    entries_replicated: AtomicU64,
+    /// Journal timestamp of the newest ingested entry, in microseconds (0 = none yet)
+    last_entry_micros: AtomicI64,
+    flushes: Timing,
+    retention_rows_deleted: AtomicU64,
+    /// Rows deleted by retention, emergency cleanup and purges since startup
+    rows_deleted: AtomicU64,
+    process_channel_depth: AtomicU64,
+    tail_channel_depth: AtomicU64,
+    ingest_channel_depth: AtomicU64,
+    /// Times journal reads paused because storage fell behind
+    ingest_backpressure: AtomicU64,
+    /// 1 while journal reads are paused for storage to catch up
+    journal_reads_paused: AtomicU64,
+    /// Rough size of the journal entries read and not yet stored
+    in_flight_bytes: AtomicU64,
+    /// Times journal reads paused because entries waiting reached the memory budget
+    memory_budget_hits: AtomicU64,
+    /// Failed reads of newly logged journal entries
+    journal_read_errors: AtomicU64,
+    /// Supervised threads restarted after a panic, per thread
+    thread_restarts: Mutex<BTreeMap<String, u64>>,
+    /// Supervised threads stuck on one piece of work
+    threads_stalled: AtomicU64,
+    /// Last pass of the collector's main loop, in microseconds (0 = not started)
+    heartbeat_micros: AtomicI64,
+    last_retention: Mutex<Option<RetentionRun>>,
+    /// Load of older journal entries under way, if any
+    backfill: Mutex<Option<BackfillProgress>>,
+    /// Web query latency per stage
+    queries: Mutex<BTreeMap<&'static str, TimingTotals>>,
+}
+
+#[derive(Default)]
+struct Timing {
+    count: AtomicU64,
+    micros: AtomicU64,
+}
+
+impl Timing {
+    fn record(&self, elapsed: Duration) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.micros
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+    }
+}
+
+#[derive(Default, Clone, Copy)]
+struct TimingTotals {
+    count: u64,
+    micros: u64,
+}
+
+/// Outcome of the most recent retention pass
+#[derive(Debug, Clone)]
+pub struct RetentionRun {
+    pub at: DateTime<Utc>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackfillPhase {
+    /// The last hour of the journal, loaded before collection starts
+    Startup,
+    /// Older entries, loaded alongside collection until `max_db_size` is reached
+    History,
+}
+
+/// How far a load of older journal entries has got. Loads run backwards from the
+/// newest entry.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BackfillProgress {
+    pub phase: BackfillPhase,
+    pub started_at: DateTime<Utc>,
+    pub entries: u64,
+    /// Journal timestamp of the entry reached
+    pub journal_timestamp: Option<DateTime<Utc>>,
+    /// Share of the load done, from 0 to 1, when it can be told
+    pub fraction_done: Option<f64>,
+    /// Seconds left at the rate so far
+    pub eta_seconds: Option<f64>,
+}
+
+/// Sizes read from disk when the metrics are rendered
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StorageSizes {
+    pub database_bytes: u64,
+    pub wal_bytes: u64,
+    /// Data DuckDB spilled to disk for running over its memory limit
+    pub spill_bytes: u64,
+}
+
+impl Metrics {
+    pub fn record_ingested(&self, timestamp: DateTime<Utc>) {
+        self.entries_ingested.fetch_add(1, Ordering::Relaxed);
+        self.last_entry_micros
+            .fetch_max(timestamp.timestamp_micros(), Ordering::Relaxed);
+    }
+
+    /// Count `count` entries stored from a peer. They may be older than the newest
+    /// ingested entry, so only [`Self::data_version`] shows them.
+    pub fn record_replicated(&self, count: usize) {
+        self.entries_replicated
+            .fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_flush(&self, elapsed: Duration) {
+        self.flushes.record(elapsed);
+    }
+
+    pub fn record_retention_deleted(&self, rows: usize) {
+        self.retention_rows_deleted
+            .fetch_add(rows as u64, Ordering::Relaxed);
+        self.record_deleted(rows);
+    }
+
+    /// Count `rows` deleted from storage, so cached results holding them go stale
+    pub fn record_deleted(&self, rows: usize) {
+        self.rows_deleted.fetch_add(rows as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_retention_run(&self, at: DateTime<Utc>, error: Option<String>) {
+        *self.last_retention.lock().unwrap() = Some(RetentionRun { at, error });
+    }
+
+    pub fn last_retention_run(&self) -> Option<RetentionRun> {
+        self.last_retention.lock().unwrap().clone()
+    }
+
+    pub fn start_backfill(&self, phase: BackfillPhase, now: DateTime<Utc>) {
+        *self.backfill.lock().unwrap() = Some(BackfillProgress {
+            phase,
+            started_at: now,
+            entries: 0,
+            journal_timestamp: None,
+            fraction_done: None,
+            eta_seconds: None,
+        });
+    }
+
+    /// Record that the backfill under way has stored `entries`, reaching
+    /// `journal_timestamp`, and is `fraction_done` through
+    pub fn record_backfill_progress(
+        &self,
+        entries: u64,
+        journal_timestamp: DateTime<Utc>,
+        fraction_done: Option<f64>,
+        now: DateTime<Utc>,
+    ) {
+        if let Some(progress) = self.backfill.lock().unwrap().as_mut() {
+            let fraction_done = fraction_done.map(|f| f.clamp(0.0, 1.0));
+            let elapsed = (now - progress.started_at).num_milliseconds().max(0) as f64 / 1e3;
+            progress.entries = entries;
+            progress.journal_timestamp = Some(journal_timestamp);
+            progress.fraction_done = fraction_done;
+            progress.eta_seconds = fraction_done
+                .filter(|&f| f > 0.0)
+                .map(|f| elapsed * (1.0 - f) / f);
+        }
+    }
+
+    pub fn finish_backfill(&self) {
+        *self.backfill.lock().unwrap() = None;
+    }
+
+    pub fn backfill_progress(&self) -> Option<BackfillProgress> {
+        self.backfill.lock().unwrap().clone()
+    }
+
+    /// Mark the collector's main loop as alive at `now`
+    pub fn record_heartbeat(&self, now: DateTime<Utc>) {
+        self.heartbeat_micros
+            .store(now.timestamp_micros(), Ordering::Relaxed);
+    }
+
+    /// Seconds since the collector's main loop last ran, `None` before it starts
+    pub fn heartbeat_age_seconds(&self, now: DateTime<Utc>) -> Option<f64> {
+        match self.heartbeat_micros.load(Ordering::Relaxed) {
+            0 => None,
+            micros => Some((now.timestamp_micros() - micros).max(0) as f64 / 1e6),
+        }
+    }
+
+    pub fn set_process_channel_depth(&self, depth: usize) {
+        self.process_channel_depth
+            .store(depth as u64, Ordering::Relaxed);
+    }
+
+    pub fn set_tail_channel_depth(&self, depth: usize) {
+        self.tail_channel_depth
+            .store(depth as u64, Ordering::Relaxed);
+    }
+
+    pub fn set_ingest_channel_depth(&self, depth: usize) {
+        self.ingest_channel_depth
+            .store(depth as u64, Ordering::Relaxed);
+    }
+
+    /// Record whether journal reads are paused for storage to catch up
+    pub fn set_journal_reads_paused(&self, paused: bool) {
+        let was_paused = self
+            .journal_reads_paused
+            .swap(paused as u64, Ordering::Relaxed);
+        if paused && was_paused == 0 {
+            self.ingest_backpressure.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn set_in_flight_bytes(&self, bytes: usize) {
+        self.in_flight_bytes.store(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_memory_budget_hit(&self) {
+        self.memory_budget_hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_journal_read_error(&self) {
+        self.journal_read_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_thread_restart(&self, thread: &str) {
+        *self
+            .thread_restarts
+            .lock()
+            .unwrap()
+            .entry(thread.to_string())
+            .or_default() += 1;
+    }
+
+    pub fn set_threads_stalled(&self, count: usize) {
+        self.threads_stalled.store(count as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_query(&self, stage: &'static str, elapsed: Duration) {
+        let mut queries = self.queries.lock().unwrap();
+        let totals = queries.entry(stage).or_default();
+        totals.count += 1;
+        totals.micros += elapsed.as_micros() as u64;
+    }
+
+    /// Journal entries ingested since startup
+    pub fn entries_ingested(&self) -> u64 {
+        self.entries_ingested.load(Ordering::Relaxed)
+    }
+
+    /// Journal timestamp of the newest ingested entry in microseconds, 0 before any
+    pub fn newest_entry_micros(&self) -> i64 {
+        self.last_entry_micros.load(Ordering::Relaxed)
+    }
+
+    /// Changes whenever entries are ingested, replicated or deleted, so cached query
+    /// results can tell they are stale
+    pub fn data_version(&self) -> i64 {
+        let changed = self.entries_ingested.load(Ordering::Relaxed)
+            + self.entries_replicated.load(Ordering::Relaxed)
+            + self.rows_deleted.load(Ordering::Relaxed);
+        changed as i64
+    }
+
+    /// Seconds between `now` and the journal timestamp of the newest ingested entry.
+    /// Keeps growing while collection is stalled.
+    pub fn ingest_lag_seconds(&self, now: DateTime<Utc>) -> Option<f64> {
+        match self.last_entry_micros.load(Ordering::Relaxed) {
+            0 => None,
+            micros => Some((now.timestamp_micros() - micros).max(0) as f64 / 1e6),
+        }
+    }
+
+    /// Prometheus text exposition of every metric
+    pub fn render(&self, now: DateTime<Utc>, sizes: StorageSizes) -> String {
+        let mut out = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, samples: &[(String, String)]| {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            for (labels, value) in samples {
+                let _ = writeln!(out, "{}{} {}", name, labels, value);
+            }
+        };
+        let sample = |value: String| vec![(String::new(), value)];
+        let seconds = |micros: u64| (micros as f64 / 1e6).to_string();
+
+        metric(
+            "livedata_entries_ingested_total",
+            "counter",
+            "Journal entries written to the database",
+            &sample(self.entries_ingested.load(Ordering::Relaxed).to_string()),
+        );
+        if let Some(lag) = self.ingest_lag_seconds(now) {
+            metric(
+                "livedata_ingest_lag_seconds",
+                "gauge",
+                "Seconds since the journal timestamp of the newest ingested entry",
+                &sample(lag.to_string()),
+            );
+        }
+        metric(
+            "livedata_flush_duration_seconds_sum",
+            "counter",
+            "Total time spent flushing buffered log counts",
+            &sample(seconds(self.flushes.micros.load(Ordering::Relaxed))),
+        );
+        metric(
+            "livedata_flush_duration_seconds_count",
+            "counter",
+            "Buffered log count flushes",
+            &sample(self.flushes.count.load(Ordering::Relaxed).to_string()),
+        );
+        metric(
+            "livedata_retention_rows_deleted_total",
+            "counter",
+            "Rows deleted by retention and emergency cleanup",
+            &sample(
+                self.retention_rows_deleted
+                    .load(Ordering::Relaxed)
+                    .to_string(),
+            ),
+        );
+
+        let queries = self.queries.lock().unwrap().clone();
+        let per_stage = |value: fn(&TimingTotals) -> String| {
+            queries
+                .iter()
+                .map(|(stage, totals)| (format!("{{stage=\"{}\"}}", stage), value(totals)))
+                .collect::<Vec<_>>()
+        };
+        metric(
+            "livedata_query_duration_seconds_sum",
+            "counter",
+            "Total time spent running web queries",
+            &per_stage(|totals| (totals.micros as f64 / 1e6).to_string()),
+        );
+        metric(
+            "livedata_query_duration_seconds_count",
+            "counter",
+            "Web queries run",
+            &per_stage(|totals| totals.count.to_string()),
+        );
+
+        metric(
+            "livedata_database_size_bytes",
+            "gauge",
+            "Size of the database file",
+            &sample(sizes.database_bytes.to_string()),
+        );
+        metric(
+            "livedata_wal_size_bytes",
+            "gauge",
+            "Size of the database write-ahead log",
+            &sample(sizes.wal_bytes.to_string()),
+        );
+        metric(
+            "livedata_spill_size_bytes",
+            "gauge",
+            "Size of the data DuckDB spilled to disk over its memory limit",
+            &sample(sizes.spill_bytes.to_string()),
+        );
+        metric(
+            "livedata_channel_depth",
+            "gauge",
+            "Messages waiting in internal channels",
+            &[
+                (
+                    "{channel=\"process_metrics\"}".to_string(),
+                    self.process_channel_depth
+                        .load(Ordering::Relaxed)
+                        .to_string(),
+                ),
+                (
+                    "{channel=\"tail\"}".to_string(),
+                    self.tail_channel_depth.load(Ordering::Relaxed).to_string(),
+                ),
+                (
+                    "{channel=\"ingest\"}".to_string(),
+                    self.ingest_channel_depth
+                        .load(Ordering::Relaxed)
+                        .to_string(),
+                ),
+            ],
+        );
+        metric(
+            "livedata_journal_reads_paused",
+            "gauge",
+            "1 while journal reads wait for storage to catch up",
+            &sample(
+                self.journal_reads_paused
+                    .load(Ordering::Relaxed)
+                    .to_string(),
+            ),
+        );
+        metric(
+            "livedata_ingest_backpressure_total",
+            "counter",
+            "Times journal reads paused because storage fell behind",
+            &sample(self.ingest_backpressure.load(Ordering::Relaxed).to_string()),
+        );
+        metric(
+            "livedata_in_flight_bytes",
+            "gauge",
+            "Rough size of the journal entries read and not yet stored",
+            &sample(self.in_flight_bytes.load(Ordering::Relaxed).to_string()),
+        );
+        metric(
+            "livedata_memory_budget_hits_total",
+            "counter",
+            "Times journal reads paused because entries waiting reached the memory budget",
+            &sample(self.memory_budget_hits.load(Ordering::Relaxed).to_string()),
+        );
+        metric(
+            "livedata_journal_read_errors_total",
+            "counter",
+            "Failed reads of newly logged journal entries",
+            &sample(self.journal_read_errors.load(Ordering::Relaxed).to_string()),
+        );
+        let restarts = self.thread_restarts.lock().unwrap().clone();
+        metric(
+            "livedata_thread_restarts_total",
+            "counter",
+            "Supervised threads restarted after a panic",
+            &restarts
+                .iter()
+                .map(|(thread, count)| (format!("{{thread=\"{}\"}}", thread), count.to_string()))
+                .collect::<Vec<_>>(),
+        );
+        metric(
+            "livedata_threads_stalled",
+            "gauge",
+            "Supervised threads stuck on one piece of work",
+            &sample(self.threads_stalled.load(Ordering::Relaxed).to_string()),
+        );
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeDelta;
+
+    #[test]
+    fn test_backfill_progress() {
+        let metrics = Metrics::default();
+        let start = Utc::now();
+        metrics.start_backfill(BackfillPhase::Startup, start);
+        let reached = start - TimeDelta::minutes(15);
+        metrics.record_backfill_progress(500, reached, Some(0.25), start + TimeDelta::seconds(10));
+
+        let progress = metrics.backfill_progress().unwrap();
+        assert_eq!(progress.entries, 500);
+        assert_eq!(progress.journal_timestamp, Some(reached));
+        assert_eq!(progress.eta_seconds, Some(30.0));
+
+        metrics.finish_backfill();
+        assert_eq!(metrics.backfill_progress(), None);
+    }
+
+    #[test]
+    fn test_render_metrics() {
+        let metrics = Metrics::default();
+        let now = Utc::now();
+        assert_eq!(metrics.ingest_lag_seconds(now), None);
+
+        metrics.record_ingested(now - TimeDelta::seconds(3));
+        metrics.record_ingested(now - TimeDelta::seconds(10));
+        metrics.record_flush(Duration::from_millis(250));
+        metrics.record_retention_deleted(7);
+        metrics.record_query("search", Duration::from_millis(500));
+        metrics.record_query("search", Duration::from_millis(1500));
+        metrics.set_process_channel_depth(2);
+        metrics.set_journal_reads_paused(true);
+        metrics.set_journal_reads_paused(true);
+        metrics.set_journal_reads_paused(false);
+        metrics.record_thread_restart("ingest writer");
+        metrics.set_in_flight_bytes(2048);
+        metrics.record_memory_budget_hit();
+        metrics.record_journal_read_error();
+        assert_eq!(metrics.ingest_lag_seconds(now), Some(3.0));
+
+        let text = metrics.render(
+            now,
+            StorageSizes {
+                database_bytes: 4096,
+                wal_bytes: 0,
+                spill_bytes: 512,
+            },
+        );
+        for line in [
+            "# TYPE livedata_entries_ingested_total counter",
+            "livedata_entries_ingested_total 2",
+            "livedata_ingest_lag_seconds 3",
+            "livedata_flush_duration_seconds_sum 0.25",
+            "livedata_flush_duration_seconds_count 1",
+            "livedata_retention_rows_deleted_total 7",
+            "livedata_query_duration_seconds_sum{stage=\"search\"} 2",
+            "livedata_query_duration_seconds_count{stage=\"search\"} 2",
+            "livedata_database_size_bytes 4096",
+            "livedata_spill_size_bytes 512",
+            "livedata_channel_depth{channel=\"process_metrics\"} 2",
+            "livedata_channel_depth{channel=\"tail\"} 0",
+            "livedata_channel_depth{channel=\"ingest\"} 0",
+            "livedata_journal_reads_paused 0",
+            "livedata_ingest_backpressure_total 1",
+            "livedata_in_flight_bytes 2048",
+            "livedata_memory_budget_hits_total 1",
+            "livedata_journal_read_errors_total 1",
+            "livedata_thread_restarts_total{thread=\"ingest writer\"} 1",
+            "livedata_threads_stalled 0",
+        ] {
+            assert!(text.lines().any(|l| l == line), "missing {line:?}\n{text}");
+        }
+    }
+}
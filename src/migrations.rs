@@ -0,0 +1,363 @@
+use crate::sql_trace::trace_sql;
+use anyhow::{Context, Result};
+use duckdb::{Connection, params};
+use log::{info, warn};
+
+/// A single versioned schema change.
+///
+/// Migrations are listed in ascending `version` order and each one is applied at most
+/// once; the applied versions are recorded in the `_schema_version` table.
+pub struct Migration {
+    pub version: i32,
+    pub description: &'static str,
+    pub up: fn(&Connection) -> Result<()>,
+}
+
+/// Outcome of a [`Migrator::run`]
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct MigrationReport {
+    pub from_version: i32,
+    pub to_version: i32,
+    /// Versions and descriptions applied (or, for a dry run, that would be applied)
+    pub applied: Vec<(i32, String)>,
+    pub dry_run: bool,
+}
+
+type BackupHook<'a> = Box<dyn FnOnce(i32) -> Result<()> + 'a>;
+
+/// Applies pending migrations to a DuckDB connection.
+///
+/// Each migration runs in its own transaction together with its `_schema_version`
+/// record, so a failing migration leaves the schema at the previous version. Before
+/// the first migration is applied to an existing database (version > 0) the backup
+/// hook is invoked with the current version.
+pub struct Migrator<'a> {
+    migrations: &'a [Migration],
+    dry_run: bool,
+    backup: Option<BackupHook<'a>>,
+}
+
+impl<'a> Migrator<'a> {
+    pub fn new(migrations: &'a [Migration]) -> Self {
+        Self {
+            migrations,
+            dry_run: false,
+            backup: None,
+        }
+    }
+
+    /// Only report the pending migrations; nothing is written to the database
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Hook called once before an existing database is upgraded
+    pub fn with_backup<F>(mut self, backup: F) -> Self
+    where
+        F: FnOnce(i32) -> Result<()> + 'a,
+    {
+        self.backup = Some(Box::new(backup));
+        self
+    }
+
+    pub fn run(self, conn: &Connection) -> Result<MigrationReport> {
+        validate_order(self.migrations)?;
+
+        if !self.dry_run {
+            initialize(conn)?;
+        }
+        let from_version = current_version(conn)?;
+        info!("Current schema version: {}", from_version);
+
+        let pending: Vec<&Migration> = self
+            .migrations
+            .iter()
+            .filter(|m| m.version > from_version)
+            .collect();
+        let mut report = MigrationReport {
+            from_version,
+            to_version: from_version,
+            applied: Vec::new(),
+            dry_run: self.dry_run,
+        };
+
+        if pending.is_empty() {
+            info!("Schema is up to date at version {}", from_version);
+            return Ok(report);
+        }
+
+        if self.dry_run {
+            for migration in &pending {
+                info!(
+                    "Dry run: would apply migration {}: {}",
+                    migration.version, migration.description
+                );
+                report
+                    .applied
+                    .push((migration.version, migration.description.to_string()));
+                report.to_version = migration.version;
+            }
+            return Ok(report);
+        }
+
+        if let Some(backup) = self.backup.filter(|_| from_version > 0) {
+            backup(from_version).context("Backup before schema migration failed")?;
+        }
+
+        for migration in pending {
+            info!(
+                "Applying migration {}: {}",
+                migration.version, migration.description
+            );
+            apply(conn, migration)
+                .with_context(|| format!("Migration {} failed", migration.version))?;
+            report
+                .applied
+                .push((migration.version, migration.description.to_string()));
+            report.to_version = migration.version;
+        }
+
+        info!(
+            "Schema migrations complete. Current version: {}",
+            report.to_version
+        );
+        Ok(report)
+    }
+}
+
+/// Highest version in `migrations`
+pub fn latest_version(migrations: &[Migration]) -> i32 {
+    migrations.last().map(|m| m.version).unwrap_or(0)
+}
+
+/// Create the `_schema_version` table if needed
+pub fn initialize(conn: &Connection) -> Result<()> {
+    let sql = "CREATE TABLE IF NOT EXISTS _schema_version (
+                version INTEGER PRIMARY KEY,
+                applied_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                description TEXT
+            )";
+    trace_sql(sql);
+    conn.execute(sql, [])?;
+    Ok(())
+}
+
+/// Schema version recorded in the database, 0 for a new (or unversioned) database
+pub fn current_version(conn: &Connection) -> Result<i32> {
+    trace_sql("SELECT COUNT(*) FROM duckdb_tables() WHERE table_name = '_schema_version'");
+    let exists: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM duckdb_tables() WHERE table_name = '_schema_version'",
+        [],
+        |row| row.get(0),
+    )?;
+    if exists == 0 {
+        return Ok(0);
+    }
+
+    trace_sql("SELECT MAX(version) FROM _schema_version");
+    let version: Option<i32> =
+        conn.query_row("SELECT MAX(version) FROM _schema_version", [], |row| {
+            row.get(0)
+        })?;
+    Ok(version.unwrap_or(0))
+}
+
+fn validate_order(migrations: &[Migration]) -> Result<()> {
+    for pair in migrations.windows(2) {
+        if pair[1].version <= pair[0].version {
+            anyhow::bail!(
+                "Migrations out of order: version {} listed after {}",
+                pair[1].version,
+                pair[0].version
+            );
+        }
+    }
+    Ok(())
+}
+
+fn apply(conn: &Connection, migration: &Migration) -> Result<()> {
+    trace_sql("BEGIN TRANSACTION");
+    conn.execute("BEGIN TRANSACTION", [])?;
+
+    let result = (migration.up)(conn).and_then(|_| {
+        trace_sql("INSERT INTO _schema_version (version, description) VALUES (?, ?)");
+        conn.execute(
+            "INSERT INTO _schema_version (version, description) VALUES (?, ?)",
+            params![migration.version, migration.description],
+        )?;
+        Ok(())
+    });
+
+    match result {
+        Ok(()) => {
+            trace_sql("COMMIT");
+            conn.execute("COMMIT", [])?;
+            info!(
+                "Migration {} applied: {}",
+                migration.version, migration.description
+            );
+            Ok(())
+        }
+        Err(e) => {
+            trace_sql("ROLLBACK");
+            if let Err(rollback_err) = conn.execute("ROLLBACK", []) {
+                warn!(
+                    "Rollback of migration {} failed: {}",
+                    migration.version, rollback_err
+                );
+            }
+            Err(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    fn create_items(conn: &Connection) -> Result<()> {
+        conn.execute("CREATE TABLE items (id INTEGER)", [])?;
+        Ok(())
+    }
+
+    fn add_name(conn: &Connection) -> Result<()> {
+        conn.execute("ALTER TABLE items ADD COLUMN name TEXT", [])?;
+        Ok(())
+    }
+
+    fn broken(conn: &Connection) -> Result<()> {
+        conn.execute("CREATE TABLE half_done (id INTEGER)", [])?;
+        anyhow::bail!("boom")
+    }
+
+    const MIGRATIONS: &[Migration] = &[
+        Migration {
+            version: 1,
+            description: "Create items",
+            up: create_items,
+        },
+        Migration {
+            version: 2,
+            description: "Add items.name",
+            up: add_name,
+        },
+    ];
+
+    fn table_exists(conn: &Connection, name: &str) -> bool {
+        conn.query_row(
+            "SELECT COUNT(*) FROM duckdb_tables() WHERE table_name = ?",
+            [name],
+            |row| row.get::<_, i64>(0),
+        )
+        .unwrap()
+            > 0
+    }
+
+    #[test]
+    fn test_applies_pending_migrations_in_order() {
+        let conn = Connection::open_in_memory().unwrap();
+        let report = Migrator::new(&MIGRATIONS[..1]).run(&conn).unwrap();
+        assert_eq!(report.applied, vec![(1, "Create items".to_string())]);
+        assert_eq!(current_version(&conn).unwrap(), 1);
+
+        let report = Migrator::new(MIGRATIONS).run(&conn).unwrap();
+        assert_eq!(report.from_version, 1);
+        assert_eq!(report.to_version, 2);
+        assert_eq!(report.applied.len(), 1);
+        conn.execute("INSERT INTO items VALUES (1, 'a')", [])
+            .unwrap();
+
+        // Running again is a no-op
+        let report = Migrator::new(MIGRATIONS).run(&conn).unwrap();
+        assert!(report.applied.is_empty());
+        assert_eq!(latest_version(MIGRATIONS), 2);
+    }
+
+    #[test]
+    fn test_dry_run_does_not_write() {
+        let conn = Connection::open_in_memory().unwrap();
+        let report = Migrator::new(MIGRATIONS).dry_run(true).run(&conn).unwrap();
+        assert!(report.dry_run);
+        assert_eq!(report.to_version, 2);
+        assert_eq!(report.applied.len(), 2);
+        assert!(!table_exists(&conn, "items"));
+        assert!(!table_exists(&conn, "_schema_version"));
+    }
+
+    #[test]
+    fn test_failed_migration_rolls_back() {
+        let conn = Connection::open_in_memory().unwrap();
+        let migrations = [
+            Migration {
+                version: 1,
+                description: "Create items",
+                up: create_items,
+            },
+            Migration {
+                version: 2,
+                description: "Broken",
+                up: broken,
+            },
+        ];
+        assert!(Migrator::new(&migrations).run(&conn).is_err());
+        assert_eq!(current_version(&conn).unwrap(), 1);
+        assert!(table_exists(&conn, "items"));
+        assert!(!table_exists(&conn, "half_done"));
+    }
+
+    #[test]
+    fn test_backup_hook_runs_only_before_upgrading_existing_schema() {
+        let conn = Connection::open_in_memory().unwrap();
+        let calls = Cell::new(0);
+
+        // Fresh database: nothing to back up
+        Migrator::new(&MIGRATIONS[..1])
+            .with_backup(|_| {
+                calls.set(calls.get() + 1);
+                Ok(())
+            })
+            .run(&conn)
+            .unwrap();
+        assert_eq!(calls.get(), 0);
+
+        Migrator::new(MIGRATIONS)
+            .with_backup(|version| {
+                assert_eq!(version, 1);
+                calls.set(calls.get() + 1);
+                Ok(())
+            })
+            .run(&conn)
+            .unwrap();
+        assert_eq!(calls.get(), 1);
+
+        // Up to date: no backup
+        Migrator::new(MIGRATIONS)
+            .with_backup(|_| {
+                calls.set(calls.get() + 1);
+                Ok(())
+            })
+            .run(&conn)
+            .unwrap();
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[test]
+    fn test_rejects_out_of_order_migrations() {
+        let conn = Connection::open_in_memory().unwrap();
+        let migrations = [
+            Migration {
+                version: 2,
+                description: "Add items.name",
+                up: add_name,
+            },
+            Migration {
+                version: 1,
+                description: "Create items",
+                up: create_items,
+            },
+        ];
+        assert!(Migrator::new(&migrations).run(&conn).is_err());
+    }
+}
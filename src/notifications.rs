@@ -0,0 +1,355 @@
+//! Alert events and their delivery. Every alert is logged; configured channels
+//! receive it as well unless it is silenced, each failure being logged without
+//! holding up the others.
+
+use crate::chat::ChatChannel;
+use crate::config::Settings;
+use crate::email::EmailChannel;
+use crate::silences::Silence;
+use crate::webhook::WebhookChannel;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use log::{error, info, warn};
+use minijinja::Environment;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertState {
+    Firing,
+    Resolved,
+}
+
+impl AlertState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Firing => "firing",
+            Self::Resolved => "resolved",
+        }
+    }
+}
+
+/// A rule starting or stopping to fire
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AlertEvent {
+    pub timestamp: DateTime<Utc>,
+    /// Name of the rule
+    pub rule: String,
+    /// What the rule watches, e.g. `process`
+    pub source: String,
+    pub state: AlertState,
+    /// Value that crossed (or fell back under) the threshold
+    pub value: f64,
+    pub threshold: f64,
+    pub message: String,
+    /// A few of the log messages that made the rule fire
+    #[serde(default)]
+    pub samples: Vec<String>,
+    /// Page of the web UI showing what the rule matched
+    #[serde(default)]
+    pub link: Option<String>,
+    /// Tags of the rule
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Settings of the rule and figures behind the alert, e.g. its query and window
+    #[serde(default)]
+    pub details: BTreeMap<String, serde_json::Value>,
+}
+
+impl AlertEvent {
+    /// Add the fields of `details`, a JSON object, to the alert's details
+    pub fn with_details(mut self, details: serde_json::Value) -> Self {
+        if let serde_json::Value::Object(details) = details {
+            self.details.extend(details);
+        }
+        self
+    }
+}
+
+/// Whether `template` is a minijinja template rather than one with placeholders
+fn is_jinja(template: &str) -> bool {
+    template.contains("{{") || template.contains("{%")
+}
+
+/// Check that a notification template parses, so mistakes show at startup rather
+/// than when an alert is sent
+pub fn check_template(template: &str) -> anyhow::Result<()> {
+    if is_jinja(template) {
+        Environment::new()
+            .template_from_str(template)
+            .context("invalid template")?;
+    }
+    Ok(())
+}
+
+/// Fill the alert into `template`. Templates containing `{{` or `{%` are minijinja
+/// templates with the alert's fields as variables (`samples` and `tags` being lists
+/// and `details` a map). Others have the placeholders `{rule}`, `{state}`, `{source}`,
+/// `{value}`, `{threshold}`, `{message}`, `{timestamp}`, `{link}`, `{tags}` and
+/// `{samples}` (one per line). A template failing to render gives the alert's message.
+pub fn render_template(template: &str, alert: &AlertEvent) -> String {
+    if is_jinja(template) {
+        return Environment::new()
+            .render_str(template, alert)
+            .unwrap_or_else(|e| {
+                warn!(
+                    "Failed to render template for alert {}: {:#}",
+                    alert.rule, e
+                );
+                alert.message.clone()
+            });
+    }
+    template
+        .replace("{rule}", &alert.rule)
+        .replace("{state}", alert.state.as_str())
+        .replace("{source}", &alert.source)
+        .replace("{value}", &alert.value.to_string())
+        .replace("{threshold}", &alert.threshold.to_string())
+        .replace("{message}", &alert.message)
+        .replace("{timestamp}", &alert.timestamp.to_rfc3339())
+        .replace("{link}", alert.link.as_deref().unwrap_or_default())
+        .replace("{samples}", &alert.samples.join("\n"))
+        .replace("{tags}", &alert.tags.join(", "))
+}
+
+/// Destination alerts are delivered to
+pub trait NotificationChannel: Send + Sync {
+    fn name(&self) -> &str;
+    fn send(&self, alert: &AlertEvent) -> anyhow::Result<()>;
+}
+
+/// Delivers alerts to every channel. Clones share the silences.
+#[derive(Clone, Default)]
+pub struct Notifier {
+    channels: Vec<Arc<dyn NotificationChannel>>,
+    silences: Arc<RwLock<Vec<Silence>>>,
+}
+
+impl Notifier {
+    /// Notifier with a channel for each one configured; unusable ones are logged and
+    /// left out
+    pub fn from_settings(settings: &Settings) -> Self {
+        let mut notifier = Self::default();
+        for webhook in &settings.webhooks {
+            match WebhookChannel::start(webhook.clone()) {
+                Ok(channel) => notifier = notifier.with_channel(Arc::new(channel)),
+                Err(e) => error!("Ignoring webhook {}: {}", webhook.url, e),
+            }
+        }
+        for chat in &settings.chat_notifiers {
+            match ChatChannel::start(chat.clone()) {
+                Ok(channel) => notifier = notifier.with_channel(Arc::new(channel)),
+                Err(e) => error!("Ignoring {} notifier: {:#}", chat.service.as_str(), e),
+            }
+        }
+        if let Some(smtp) = &settings.smtp {
+            match EmailChannel::start(smtp.clone()) {
+                Ok(channel) => notifier = notifier.with_channel(Arc::new(channel)),
+                Err(e) => error!("Ignoring SMTP settings for {}: {}", smtp.host, e),
+            }
+        }
+        notifier
+    }
+
+    pub fn with_channel(mut self, channel: Arc<dyn NotificationChannel>) -> Self {
+        self.channels.push(channel);
+        self
+    }
+
+    /// Replace the silences alerts are checked against (the ones not ended yet)
+    pub fn set_silences(&self, silences: Vec<Silence>) {
+        *self.silences.write().unwrap() = silences;
+    }
+
+    /// The silence covering `alert`, if any
+    fn silenced_by(&self, alert: &AlertEvent) -> Option<i64> {
+        let silences = self.silences.read().unwrap();
+        silences
+            .iter()
+            .find(|silence| silence.matches(alert))
+            .map(|silence| silence.id)
+    }
+
+    pub fn notify(&self, alerts: &[AlertEvent]) {
+        for alert in alerts {
+            match alert.state {
+                AlertState::Firing => warn!("Alert {} firing: {}", alert.rule, alert.message),
+                AlertState::Resolved => info!("Alert {} resolved: {}", alert.rule, alert.message),
+            }
+            if let Some(id) = self.silenced_by(alert) {
+                info!("Alert {} silenced by silence {}", alert.rule, id);
+                continue;
+            }
+            for channel in &self.channels {
+                if let Err(e) = channel.send(alert) {
+                    error!(
+                        "Failed to send alert {} to {}: {}",
+                        alert.rule,
+                        channel.name(),
+                        e
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct Recorder {
+        sent: Mutex<Vec<String>>,
+    }
+
+    impl NotificationChannel for Recorder {
+        fn name(&self) -> &str {
+            "recorder"
+        }
+
+        fn send(&self, alert: &AlertEvent) -> anyhow::Result<()> {
+            self.sent
+                .lock()
+                .unwrap()
+                .push(format!("{} {}", alert.rule, alert.state.as_str()));
+            Ok(())
+        }
+    }
+
+    struct Broken;
+
+    impl NotificationChannel for Broken {
+        fn name(&self) -> &str {
+            "broken"
+        }
+
+        fn send(&self, _alert: &AlertEvent) -> anyhow::Result<()> {
+            anyhow::bail!("unreachable")
+        }
+    }
+
+    #[test]
+    fn test_notifier_reaches_every_channel() {
+        let recorder = Arc::new(Recorder::default());
+        let notifier = Notifier::default()
+            .with_channel(Arc::new(Broken))
+            .with_channel(recorder.clone());
+        let alert = |state| AlertEvent {
+            timestamp: Utc::now(),
+            rule: "hot".to_string(),
+            source: "process".to_string(),
+            state,
+            value: 95.0,
+            threshold: 90.0,
+            message: "CPU 95% > 90%".to_string(),
+            samples: Vec::new(),
+            link: None,
+            tags: Vec::new(),
+            details: BTreeMap::new(),
+        };
+        notifier.notify(&[alert(AlertState::Firing), alert(AlertState::Resolved)]);
+        assert_eq!(
+            *recorder.sent.lock().unwrap(),
+            ["hot firing", "hot resolved"]
+        );
+    }
+
+    #[test]
+    fn test_silenced_alerts_are_not_sent() {
+        let recorder = Arc::new(Recorder::default());
+        let notifier = Notifier::default().with_channel(recorder.clone());
+        let now = Utc::now();
+        notifier.clone().set_silences(vec![Silence {
+            id: 7,
+            rule: Some("disk*".to_string()),
+            source: None,
+            tag: None,
+            starts_at: now - chrono::TimeDelta::hours(1),
+            ends_at: now + chrono::TimeDelta::hours(1),
+            comment: "resizing volumes".to_string(),
+            created_by: Some("alice".to_string()),
+        }]);
+        let alert = |rule: &str| AlertEvent {
+            timestamp: now,
+            rule: rule.to_string(),
+            source: "log".to_string(),
+            state: AlertState::Firing,
+            value: 3.0,
+            threshold: 1.0,
+            message: "3 entries matched in the last 5m (threshold 1)".to_string(),
+            samples: Vec::new(),
+            link: None,
+            tags: Vec::new(),
+            details: BTreeMap::new(),
+        };
+        notifier.notify(&[alert("disk full"), alert("oom kills")]);
+        assert_eq!(*recorder.sent.lock().unwrap(), ["oom kills firing"]);
+    }
+
+    #[test]
+    fn test_render_jinja_template() {
+        let alert = AlertEvent {
+            timestamp: Utc::now(),
+            rule: "nginx errors".to_string(),
+            source: "log".to_string(),
+            state: AlertState::Firing,
+            value: 51.0,
+            threshold: 50.0,
+            message: "51 entries matched in the last 5m (threshold 50)".to_string(),
+            samples: vec![
+                "upstream timed out".to_string(),
+                "no live upstreams".to_string(),
+            ],
+            link: None,
+            tags: vec!["web".to_string()],
+            details: BTreeMap::new(),
+        }
+        .with_details(serde_json::json!({ "query": "unit:nginx.service", "window": "5m" }));
+        let template = "{% if state == \"firing\" %}:fire:{% endif %} {{ rule | upper }} \
+                        ({{ details.query }} over {{ details.window }}): {{ value | int }}\
+                        {% for line in samples %}\n> {{ line }}{% endfor %}\
+                        {% if link %}\n{{ link }}{% endif %}";
+        check_template(template).unwrap();
+        assert_eq!(
+            render_template(template, &alert),
+            ":fire: NGINX ERRORS (unit:nginx.service over 5m): 51\n\
+             > upstream timed out\n> no live upstreams"
+        );
+
+        assert!(check_template("{{ rule").is_err());
+        assert!(check_template("{rule} {state}").is_ok());
+        // Errors at render time fall back to the message
+        assert_eq!(
+            render_template("{{ value | nosuchfilter }}", &alert),
+            alert.message
+        );
+    }
+
+    #[test]
+    fn test_render_template() {
+        let alert = AlertEvent {
+            timestamp: Utc::now(),
+            rule: "disk".to_string(),
+            source: "livedata".to_string(),
+            state: AlertState::Resolved,
+            value: 2.5,
+            threshold: 1.0,
+            message: "ingestion resumed".to_string(),
+            samples: vec!["a".to_string(), "b".to_string()],
+            link: None,
+            tags: vec!["disk".to_string(), "infra".to_string()],
+            details: BTreeMap::new(),
+        };
+        assert_eq!(
+            render_template(
+                "{rule} {state} {value}/{threshold}: {message}{link} [{tags}]\n{samples}",
+                &alert
+            ),
+            "disk resolved 2.5/1: ingestion resumed [disk, infra]\na\nb"
+        );
+    }
+}
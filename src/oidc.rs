@@ -0,0 +1,470 @@
+use crate::auth::{Identity, Role};
+use anyhow::{Context, Result, anyhow, bail};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{DecodingKey, Validation};
+use reqwest::Url;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Mutex, RwLock};
+
+/// Cookie holding the id of a UI login session
+pub const SESSION_COOKIE: &str = "livedata_session";
+
+/// Lifetime of a UI login session
+const SESSION_HOURS: i64 = 8;
+
+/// Logins not completed within this many minutes are dropped
+const LOGIN_TIMEOUT_MINUTES: i64 = 10;
+
+/// Unknown key ids refetch the provider's keys at most this often
+const KEY_REFRESH_SECS: i64 = 60;
+
+/// OpenID Connect login settings (`[oidc]` table)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OidcSettings {
+    /// Issuer URL; `<issuer>/.well-known/openid-configuration` is read at startup
+    pub issuer: String,
+
+    pub client_id: String,
+
+    #[serde(default)]
+    pub client_secret: Option<String>,
+
+    /// Callback URL registered with the provider, ending in `/auth/callback`
+    pub redirect_url: String,
+
+    #[serde(default = "default_scopes")]
+    pub scopes: Vec<String>,
+
+    /// Claim listing role names (a string or a list); the highest role found wins
+    #[serde(default)]
+    pub role_claim: Option<String>,
+
+    /// Role of users whose tokens name no role
+    #[serde(default = "default_role")]
+    pub default_role: Role,
+}
+
+fn default_scopes() -> Vec<String> {
+    ["openid", "profile", "email"].map(String::from).to_vec()
+}
+
+fn default_role() -> Role {
+    Role::Viewer
+}
+
+/// Parts of the provider's discovery document used here
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProviderMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Claims {
+    sub: String,
+    #[serde(default)]
+    nonce: Option<String>,
+    #[serde(default)]
+    preferred_username: Option<String>,
+    #[serde(default)]
+    email: Option<String>,
+    #[serde(flatten)]
+    other: HashMap<String, serde_json::Value>,
+}
+
+struct PendingLogin {
+    nonce: String,
+    return_to: String,
+    started: DateTime<Utc>,
+}
+
+struct Session {
+    identity: Identity,
+    expires: DateTime<Utc>,
+}
+
+/// OpenID Connect provider: runs the authorization code login for the HTML UI,
+/// keeping the resulting sessions in memory, and validates bearer tokens it issued
+/// for API clients
+pub struct OidcProvider {
+    settings: OidcSettings,
+    metadata: ProviderMetadata,
+    http: reqwest::Client,
+    /// Signing keys and when they were fetched
+    keys: RwLock<(JwkSet, DateTime<Utc>)>,
+    pending: Mutex<HashMap<String, PendingLogin>>,
+    sessions: Mutex<HashMap<String, Session>>,
+}
+
+impl OidcProvider {
+    /// Read the provider's discovery document and signing keys
+    pub async fn discover(settings: OidcSettings) -> Result<Self> {
+        let http = reqwest::Client::new();
+        let url = format!(
+            "{}/.well-known/openid-configuration",
+            settings.issuer.trim_end_matches('/')
+        );
+        let metadata: ProviderMetadata = get_json(&http, &url).await?;
+        let keys = get_json(&http, &metadata.jwks_uri).await?;
+        Ok(Self::new(settings, metadata, keys))
+    }
+
+    pub fn new(settings: OidcSettings, metadata: ProviderMetadata, keys: JwkSet) -> Self {
+        Self {
+            settings,
+            metadata,
+            http: reqwest::Client::new(),
+            keys: RwLock::new((keys, Utc::now())),
+            pending: Mutex::new(HashMap::new()),
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Start a login returning to `return_to` afterwards; returns the provider URL to
+    /// send the browser to
+    pub fn login_url(&self, return_to: &str) -> Result<String> {
+        let state = random_token();
+        let nonce = random_token();
+        let scope = self.settings.scopes.join(" ");
+        let url = Url::parse_with_params(
+            &self.metadata.authorization_endpoint,
+            [
+                ("response_type", "code"),
+                ("client_id", self.settings.client_id.as_str()),
+                ("redirect_uri", self.settings.redirect_url.as_str()),
+                ("scope", scope.as_str()),
+                ("state", state.as_str()),
+                ("nonce", nonce.as_str()),
+            ],
+        )?;
+
+        let now = Utc::now();
+        let mut pending = self.pending.lock().unwrap();
+        pending.retain(|_, login| now - login.started < Duration::minutes(LOGIN_TIMEOUT_MINUTES));
+        pending.insert(
+            state,
+            PendingLogin {
+                nonce,
+                return_to: return_to.to_string(),
+                started: now,
+            },
+        );
+        Ok(url.to_string())
+    }
+
+    /// Finish a login from the provider's callback: exchange the code for an ID token,
+    /// validate it and open a session. Returns the session id and the path to return to.
+    pub async fn complete_login(&self, code: &str, state: &str) -> Result<(String, String)> {
+        let login = self
+            .pending
+            .lock()
+            .unwrap()
+            .remove(state)
+            .filter(|login| Utc::now() - login.started < Duration::minutes(LOGIN_TIMEOUT_MINUTES))
+            .ok_or_else(|| anyhow!("Unknown or expired login"))?;
+
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", self.settings.redirect_url.as_str()),
+            ("client_id", self.settings.client_id.as_str()),
+        ];
+        if let Some(secret) = &self.settings.client_secret {
+            form.push(("client_secret", secret.as_str()));
+        }
+        let response: TokenResponse = self
+            .http
+            .post(&self.metadata.token_endpoint)
+            .form(&form)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .context("OIDC token request failed")?
+            .json()
+            .await
+            .context("Invalid OIDC token response")?;
+
+        let claims = self.validate(&response.id_token).await?;
+        if claims.nonce.as_deref() != Some(login.nonce.as_str()) {
+            bail!("ID token does not belong to this login");
+        }
+        let identity = self.identity(&claims);
+        log::info!("OIDC login by {}", identity.name.as_deref().unwrap_or("?"));
+
+        let id = random_token();
+        let now = Utc::now();
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|_, session| session.expires > now);
+        sessions.insert(
+            id.clone(),
+            Session {
+                identity,
+                expires: now + Duration::hours(SESSION_HOURS),
+            },
+        );
+        Ok((id, login.return_to))
+    }
+
+    /// Caller of an open login session
+    pub fn session(&self, id: &str) -> Option<Identity> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .get(id)
+            .filter(|session| session.expires > Utc::now())
+            .map(|session| session.identity.clone())
+    }
+
+    pub fn end_session(&self, id: &str) {
+        self.sessions.lock().unwrap().remove(id);
+    }
+
+    /// Caller presenting a bearer token issued by the provider for this client
+    pub async fn authenticate_token(&self, token: &str) -> Result<Identity> {
+        Ok(self.identity(&self.validate(token).await?))
+    }
+
+    /// `Set-Cookie` value opening session `id` (empty `id` clears the cookie)
+    pub fn session_cookie(&self, id: &str) -> String {
+        let max_age = if id.is_empty() {
+            0
+        } else {
+            SESSION_HOURS * 3600
+        };
+        let secure = if self.settings.redirect_url.starts_with("https://") {
+            "; Secure"
+        } else {
+            ""
+        };
+        format!(
+            "{}={}; Path=/; HttpOnly; SameSite=Lax; Max-Age={}{}",
+            SESSION_COOKIE, id, max_age, secure
+        )
+    }
+
+    async fn validate(&self, token: &str) -> Result<Claims> {
+        let header = jsonwebtoken::decode_header(token)?;
+        let key = match self.key(header.kid.as_deref()) {
+            Some(key) => key,
+            None => {
+                // The provider may have rotated its keys since they were fetched
+                self.refresh_keys().await?;
+                self.key(header.kid.as_deref())
+                    .ok_or_else(|| anyhow!("Token signed with an unknown key"))?
+            }
+        };
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&self.metadata.issuer]);
+        validation.set_audience(&[&self.settings.client_id]);
+        Ok(jsonwebtoken::decode::<Claims>(token, &key, &validation)?.claims)
+    }
+
+    fn key(&self, kid: Option<&str>) -> Option<DecodingKey> {
+        let keys = self.keys.read().unwrap();
+        let jwk = match kid {
+            Some(kid) => keys.0.find(kid),
+            None => keys.0.keys.first(),
+        }?;
+        DecodingKey::from_jwk(jwk).ok()
+    }
+
+    async fn refresh_keys(&self) -> Result<()> {
+        let fetched = self.keys.read().unwrap().1;
+        if Utc::now() - fetched < Duration::seconds(KEY_REFRESH_SECS) {
+            return Ok(());
+        }
+        let keys = get_json(&self.http, &self.metadata.jwks_uri).await?;
+        *self.keys.write().unwrap() = (keys, Utc::now());
+        Ok(())
+    }
+
+    fn identity(&self, claims: &Claims) -> Identity {
+        let role = self
+            .settings
+            .role_claim
+            .as_ref()
+            .and_then(|claim| claims.other.get(claim))
+            .and_then(|value| roles_in(value).into_iter().max())
+            .unwrap_or(self.settings.default_role);
+        let name = claims
+            .preferred_username
+            .clone()
+            .or_else(|| claims.email.clone())
+            .unwrap_or_else(|| claims.sub.clone());
+        Identity {
+            name: Some(name),
+            role,
+        }
+    }
+}
+
+/// Roles named by a claim holding a string or a list of strings
+fn roles_in(value: &serde_json::Value) -> Vec<Role> {
+    let names: Vec<&str> = match value {
+        serde_json::Value::String(name) => vec![name.as_str()],
+        serde_json::Value::Array(names) => names.iter().filter_map(|v| v.as_str()).collect(),
+        _ => Vec::new(),
+    };
+    names
+        .into_iter()
+        .filter_map(|name| {
+            serde_json::from_value(serde_json::Value::String(name.to_lowercase())).ok()
+        })
+        .collect()
+}
+
+/// Local URL starting a login that returns to `return_to`
+pub fn login_path(return_to: &str) -> String {
+    let mut url = Url::parse("http://localhost/auth/login").expect("valid URL");
+    url.query_pairs_mut().append_pair("return_to", return_to);
+    format!("{}?{}", url.path(), url.query().unwrap_or_default())
+}
+
+/// Session id in a `Cookie` header value
+pub fn session_id(cookies: &str) -> Option<&str> {
+    cookies
+        .split(';')
+        .filter_map(|cookie| cookie.trim().split_once('='))
+        .find(|(name, _)| *name == SESSION_COOKIE)
+        .map(|(_, value)| value)
+        .filter(|value| !value.is_empty())
+}
+
+async fn get_json<T: DeserializeOwned>(http: &reqwest::Client, url: &str) -> Result<T> {
+    http.get(url)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .with_context(|| format!("Failed to fetch {}", url))?
+        .json()
+        .await
+        .with_context(|| format!("Invalid JSON from {}", url))
+}
+
+/// Unguessable URL-safe token for login states, nonces and session ids
+fn random_token() -> String {
+    URL_SAFE_NO_PAD.encode(rand::random::<[u8; 32]>())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::{EncodingKey, Header};
+
+    const SECRET: &[u8] = b"livedata-test-secret-0123456789ab";
+
+    fn provider() -> OidcProvider {
+        let settings = OidcSettings {
+            issuer: "https://sso.example.com".to_string(),
+            client_id: "livedata".to_string(),
+            client_secret: None,
+            redirect_url: "https://logs.example.com/auth/callback".to_string(),
+            scopes: default_scopes(),
+            role_claim: Some("groups".to_string()),
+            default_role: Role::Viewer,
+        };
+        let metadata = ProviderMetadata {
+            issuer: "https://sso.example.com".to_string(),
+            authorization_endpoint: "https://sso.example.com/authorize".to_string(),
+            token_endpoint: "https://sso.example.com/token".to_string(),
+            jwks_uri: "https://sso.example.com/jwks".to_string(),
+        };
+        let keys = serde_json::from_value(serde_json::json!({
+            "keys": [{
+                "kty": "oct",
+                "kid": "test",
+                "alg": "HS256",
+                "k": URL_SAFE_NO_PAD.encode(SECRET),
+            }]
+        }))
+        .unwrap();
+        OidcProvider::new(settings, metadata, keys)
+    }
+
+    fn token(claims: serde_json::Value) -> String {
+        let header = Header {
+            kid: Some("test".to_string()),
+            ..Header::default()
+        };
+        jsonwebtoken::encode(&header, &claims, &EncodingKey::from_secret(SECRET)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_token_maps_claims() {
+        let provider = provider();
+        let exp = (Utc::now() + Duration::hours(1)).timestamp();
+
+        let identity = provider
+            .authenticate_token(&token(serde_json::json!({
+                "iss": "https://sso.example.com",
+                "aud": "livedata",
+                "exp": exp,
+                "sub": "1234",
+                "preferred_username": "alice",
+                "groups": ["staff", "Operator"],
+            })))
+            .await
+            .unwrap();
+        assert_eq!(identity.name.as_deref(), Some("alice"));
+        assert_eq!(identity.role, Role::Operator);
+
+        let identity = provider
+            .authenticate_token(&token(serde_json::json!({
+                "iss": "https://sso.example.com",
+                "aud": "livedata",
+                "exp": exp,
+                "sub": "5678",
+            })))
+            .await
+            .unwrap();
+        assert_eq!(identity.name.as_deref(), Some("5678"));
+        assert_eq!(identity.role, Role::Viewer);
+
+        // Tokens for another client are rejected
+        assert!(
+            provider
+                .authenticate_token(&token(serde_json::json!({
+                    "iss": "https://sso.example.com",
+                    "aud": "other-app",
+                    "exp": exp,
+                    "sub": "1234",
+                })))
+                .await
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_login_url_and_session_cookie() {
+        let provider = provider();
+        let url = Url::parse(&provider.login_url("/processes.html").unwrap()).unwrap();
+        let params: HashMap<_, _> = url.query_pairs().into_owned().collect();
+        assert_eq!(url.path(), "/authorize");
+        assert_eq!(params["client_id"], "livedata");
+        assert_eq!(params["scope"], "openid profile email");
+        assert_ne!(params["state"], params["nonce"]);
+
+        let cookie = provider.session_cookie("abc");
+        assert!(cookie.starts_with("livedata_session=abc; "));
+        assert!(cookie.ends_with("; Secure"));
+        assert_eq!(session_id("theme=dark; livedata_session=abc"), Some("abc"));
+        assert_eq!(session_id("livedata_session="), None);
+        assert_eq!(provider.session("abc"), None);
+        assert_eq!(
+            login_path("/api/search?q=a b"),
+            "/auth/login?return_to=%2Fapi%2Fsearch%3Fq%3Da+b"
+        );
+    }
+}
@@ -0,0 +1,260 @@
+//! OpenTelemetry output: exports entries as OTLP log records over HTTP with the JSON
+//! encoding, which any OpenTelemetry Collector accepts on `/v1/logs`. Each entry's
+//! host and unit become the `host.name` and `service.name` resource attributes;
+//! its other journal fields become log record attributes.
+
+use crate::log_entry::LogEntry;
+use crate::outputs::check_response;
+use anyhow::Result;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Instrumentation scope the records are reported under
+const SCOPE_NAME: &str = "livedata";
+
+/// Journal fields with a semantic-convention attribute of their own, and whether
+/// their value is an integer
+const FIELD_ATTRIBUTES: &[(&str, &str, bool)] = &[
+    ("_PID", "process.pid", true),
+    ("_COMM", "process.executable.name", false),
+    ("_EXE", "process.executable.path", false),
+    ("_CMDLINE", "process.command_line", false),
+    ("_UID", "process.owner.id", true),
+    ("_SYSTEMD_INVOCATION_ID", "service.instance.id", false),
+    ("CODE_FILE", "code.filepath", false),
+    ("CODE_LINE", "code.lineno", true),
+    ("CODE_FUNC", "code.function", false),
+];
+
+/// Fields carried elsewhere in the record, or of no use outside this journal
+const SKIPPED_FIELDS: &[&str] = &[
+    "MESSAGE",
+    "PRIORITY",
+    "_HOSTNAME",
+    "_SYSTEMD_UNIT",
+    "__CURSOR",
+    "__REALTIME_TIMESTAMP",
+    "__MONOTONIC_TIMESTAMP",
+    "__SEQNUM",
+    "__SEQNUM_ID",
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OtlpSettings {
+    /// Logs endpoint, e.g. "http://otel-collector:4318/v1/logs"
+    pub endpoint: String,
+
+    /// Resource attributes added to every entry's, e.g.
+    /// `deployment.environment = "production"`
+    #[serde(default)]
+    pub resource_attributes: BTreeMap<String, String>,
+
+    /// Extra request headers, e.g. an API key of a hosted backend
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+}
+
+/// OTLP severity number and text of a syslog priority
+fn severity(priority: u8) -> (u8, &'static str) {
+    match priority {
+        0 => (24, "EMERG"),
+        1 => (23, "ALERT"),
+        2 => (21, "CRIT"),
+        3 => (17, "ERR"),
+        4 => (13, "WARNING"),
+        5 => (10, "NOTICE"),
+        6 => (9, "INFO"),
+        _ => (5, "DEBUG"),
+    }
+}
+
+fn string_attribute(key: &str, value: &str) -> Value {
+    json!({"key": key, "value": {"stringValue": value}})
+}
+
+/// Log record attributes of `entry`'s journal fields, sorted by key
+fn record_attributes(entry: &LogEntry) -> Vec<Value> {
+    let mut attributes: BTreeMap<String, Value> = BTreeMap::new();
+    for (field, value) in &entry.fields {
+        if SKIPPED_FIELDS.contains(&field.as_str()) {
+            continue;
+        }
+        let attribute = match FIELD_ATTRIBUTES
+            .iter()
+            .find(|(name, ..)| *name == field.as_str())
+        {
+            Some((_, key, true)) => match value.parse::<i64>() {
+                // OTLP/JSON carries 64-bit integers as strings
+                Ok(number) => json!({"key": key, "value": {"intValue": number.to_string()}}),
+                Err(_) => string_attribute(key, value),
+            },
+            Some((_, key, false)) => string_attribute(key, value),
+            None => string_attribute(&format!("journald.{}", field.to_lowercase()), value),
+        };
+        attributes.insert(
+            attribute["key"].as_str().unwrap_or_default().to_string(),
+            attribute,
+        );
+    }
+    attributes.into_values().collect()
+}
+
+pub struct OtlpSink {
+    client: reqwest::Client,
+    endpoint: Url,
+    resource_attributes: BTreeMap<String, String>,
+    headers: BTreeMap<String, String>,
+}
+
+impl OtlpSink {
+    pub fn new(settings: &OtlpSettings, timeout: Duration) -> Result<Self> {
+        Ok(Self {
+            client: reqwest::Client::builder().timeout(timeout).build()?,
+            endpoint: Url::parse(&settings.endpoint)?,
+            resource_attributes: settings.resource_attributes.clone(),
+            headers: settings.headers.clone(),
+        })
+    }
+
+    /// Resource attributes of `entry`: the configured ones, then its host and unit
+    fn resource(&self, entry: &LogEntry) -> BTreeMap<String, String> {
+        let mut resource = self.resource_attributes.clone();
+        if let Some(hostname) = entry.get_hostname() {
+            resource.insert("host.name".to_string(), hostname.clone());
+        }
+        if let Some(unit) = entry.get_systemd_unit() {
+            let service = unit.strip_suffix(".service").unwrap_or(unit);
+            resource.insert("service.name".to_string(), service.to_string());
+        }
+        resource
+    }
+
+    fn log_record(entry: &LogEntry) -> Value {
+        let nanos = entry
+            .timestamp
+            .timestamp_nanos_opt()
+            .unwrap_or_default()
+            .to_string();
+        let mut record = json!({
+            "timeUnixNano": nanos,
+            "observedTimeUnixNano": nanos,
+            "body": {"stringValue": entry.get_message().cloned().unwrap_or_default()},
+            "attributes": record_attributes(entry),
+        });
+        if let Some(priority) = entry.get_priority().and_then(|p| p.parse::<u8>().ok()) {
+            let (number, text) = severity(priority);
+            record["severityNumber"] = json!(number);
+            record["severityText"] = json!(text);
+        }
+        record
+    }
+
+    /// Body of an export request, with one resource per distinct set of resource
+    /// attributes
+    fn export_request(&self, batch: &[Arc<LogEntry>]) -> Value {
+        let mut resources: BTreeMap<BTreeMap<String, String>, Vec<Value>> = BTreeMap::new();
+        for entry in batch {
+            resources
+                .entry(self.resource(entry))
+                .or_default()
+                .push(Self::log_record(entry));
+        }
+        let resource_logs: Vec<Value> = resources
+            .into_iter()
+            .map(|(resource, records)| {
+                let attributes: Vec<Value> = resource
+                    .iter()
+                    .map(|(key, value)| string_attribute(key, value))
+                    .collect();
+                json!({
+                    "resource": {"attributes": attributes},
+                    "scopeLogs": [{
+                        "scope": {"name": SCOPE_NAME, "version": env!("CARGO_PKG_VERSION")},
+                        "logRecords": records,
+                    }],
+                })
+            })
+            .collect();
+        json!({"resourceLogs": resource_logs})
+    }
+
+    pub async fn send(&self, batch: &[Arc<LogEntry>]) -> Result<()> {
+        let mut request = self
+            .client
+            .post(self.endpoint.clone())
+            .json(&self.export_request(batch));
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+        check_response(request.send().await).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::outputs::{OutputSettings, SinkSettings};
+    use chrono::{DateTime, Utc};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_export_request() {
+        let settings: OutputSettings = toml::from_str(
+            r#"
+name = "otel"
+kind = "otlp"
+endpoint = "http://otel-collector:4318/v1/logs"
+resource_attributes = { "deployment.environment" = "production" }
+"#,
+        )
+        .unwrap();
+        let SinkSettings::Otlp(otlp) = &settings.sink else {
+            panic!("not an OTLP output: {:?}", settings.sink);
+        };
+        let sink = OtlpSink::new(otlp, Duration::from_secs(1)).unwrap();
+
+        let fields: HashMap<String, String> = [
+            ("_HOSTNAME", "web1"),
+            ("_SYSTEMD_UNIT", "nginx.service"),
+            ("PRIORITY", "3"),
+            ("MESSAGE", "upstream timed out"),
+            ("_PID", "812"),
+            ("_COMM", "nginx"),
+            ("__CURSOR", "s=abc"),
+            ("REQUEST_ID", "r-42"),
+        ]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+        let entry = Arc::new(LogEntry::new(
+            DateTime::<Utc>::from_timestamp(1_700_000_000, 5).unwrap(),
+            fields,
+        ));
+        let request = sink.export_request(&[entry]);
+        assert_eq!(
+            request["resourceLogs"][0]["resource"]["attributes"],
+            json!([
+                {"key": "deployment.environment", "value": {"stringValue": "production"}},
+                {"key": "host.name", "value": {"stringValue": "web1"}},
+                {"key": "service.name", "value": {"stringValue": "nginx"}},
+            ])
+        );
+        let record = &request["resourceLogs"][0]["scopeLogs"][0]["logRecords"][0];
+        assert_eq!(record["timeUnixNano"], "1700000000000000005");
+        assert_eq!(record["severityNumber"], 17);
+        assert_eq!(record["severityText"], "ERR");
+        assert_eq!(record["body"]["stringValue"], "upstream timed out");
+        assert_eq!(
+            record["attributes"],
+            json!([
+                {"key": "journald.request_id", "value": {"stringValue": "r-42"}},
+                {"key": "process.executable.name", "value": {"stringValue": "nginx"}},
+                {"key": "process.pid", "value": {"intValue": "812"}},
+            ])
+        );
+    }
+}
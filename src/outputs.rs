@@ -0,0 +1,822 @@
+//! Output routing: every `[[outputs]]` sink receives the ingested entries its filter
+//! matches. Each output runs on its own thread with its own buffer, sending in batches
+//! and retrying failed batches with backoff, so a slow or unreachable sink neither
+//! holds up ingestion nor the other outputs.
+//!
+//! Each output keeps a cursor in the database: the timestamp of the latest entry it
+//! delivered. Entries it could not take in live (after a restart, when its buffer was
+//! full or when it fell behind ingestion) are read back from the database after that
+//! cursor, so none are lost as long as retention keeps them.
+
+use crate::app_controller::join_until;
+use crate::clickhouse::{ClickHouseSettings, ClickHouseSink};
+use crate::duckdb_buffer::DuckDBBuffer;
+#[cfg(feature = "kafka")]
+use crate::kafka::{KafkaSettings, KafkaSink};
+use crate::log_entry::LogEntry;
+use crate::loki_push::{LokiSettings, LokiSink};
+use crate::otlp::{OtlpSettings, OtlpSink};
+use crate::peer_replication::{PeerSettings, PeerSink};
+use crate::search_query::{SearchQuery, glob_match};
+use crate::syslog_forward::{SyslogSettings, SyslogSink};
+use crate::webhook::retry_delay;
+use anyhow::{Context, Result};
+use chrono::Utc;
+use log::{error, info, warn};
+use reqwest::header::RETRY_AFTER;
+use reqwest::{StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+
+/// How often waiting outputs check for shutdown
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(500);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where an output sends its entries
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum SinkSettings {
+    /// Append entries as JSON lines to a file, reopened for every batch so it can be
+    /// rotated from outside
+    File { path: PathBuf },
+    /// POST each batch as a JSON array
+    Webhook {
+        url: String,
+        /// Extra request headers, e.g. an `Authorization` token
+        #[serde(default)]
+        headers: BTreeMap<String, String>,
+    },
+    /// Grafana Loki's push API
+    Loki(LokiSettings),
+    /// JSON messages to a Kafka topic
+    #[cfg(feature = "kafka")]
+    Kafka(KafkaSettings),
+    /// Kafka output of a config read by a build without the `kafka` feature, which
+    /// fails to connect
+    #[cfg(not(feature = "kafka"))]
+    Kafka(serde_json::Value),
+    /// RFC 5424 messages to a remote syslog receiver
+    Syslog(SyslogSettings),
+    /// OpenTelemetry log records over OTLP/HTTP
+    Otlp(OtlpSettings),
+    /// Rows inserted into a ClickHouse table
+    Clickhouse(ClickHouseSettings),
+    /// Another livedata instance's `[peer_input]`, over mutual TLS
+    Peer(PeerSettings),
+}
+
+impl SinkSettings {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::File { .. } => "file",
+            Self::Webhook { .. } => "webhook",
+            Self::Loki(_) => "loki",
+            Self::Kafka(_) => "kafka",
+            Self::Syslog(_) => "syslog",
+            Self::Otlp(_) => "otlp",
+            Self::Clickhouse(_) => "clickhouse",
+            Self::Peer(_) => "peer",
+        }
+    }
+}
+
+/// A destination ingested entries are forwarded to (`[[outputs]]` table)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OutputSettings {
+    /// Shown in logs; also keys the output's cursor, so renaming an output starts it
+    /// afresh
+    pub name: String,
+
+    #[serde(flatten)]
+    pub sink: SinkSettings,
+
+    /// Only forward entries of this `_SYSTEMD_UNIT`; `*` stands for any run of
+    /// characters
+    #[serde(default)]
+    pub unit: Option<String>,
+
+    /// Only forward entries at or below this priority (0 = emerg .. 7 = debug)
+    #[serde(default)]
+    pub max_priority: Option<u8>,
+
+    /// Search box query entries must match, e.g. `-host:db1 "timeout"`
+    #[serde(default)]
+    pub query: String,
+
+    /// Most entries sent at once
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+
+    /// Time entries wait for a batch to fill up
+    #[serde(default = "default_flush_interval_ms")]
+    pub flush_interval_ms: u64,
+
+    /// Entries held in memory while the sink is slow or failing; beyond that they are
+    /// read back from the database once there is room
+    #[serde(default = "default_buffer_entries")]
+    pub buffer_entries: usize,
+
+    /// Wait before the first retry of a failed batch, doubling with each further one
+    #[serde(default = "default_retry_backoff_ms")]
+    pub retry_backoff_ms: u64,
+}
+
+fn default_batch_size() -> usize {
+    500
+}
+
+fn default_flush_interval_ms() -> u64 {
+    1_000
+}
+
+fn default_buffer_entries() -> usize {
+    10_000
+}
+
+fn default_retry_backoff_ms() -> u64 {
+    1_000
+}
+
+/// Which entries an output forwards
+pub struct OutputFilter {
+    unit: Option<String>,
+    max_priority: Option<u8>,
+    query: SearchQuery,
+}
+
+impl OutputFilter {
+    /// Filter of `settings`, resolving query fields against the journal_logs `schema`
+    pub fn new(settings: &OutputSettings, schema: &[(String, String)]) -> Result<Self> {
+        Ok(Self {
+            unit: settings.unit.clone(),
+            max_priority: settings.max_priority,
+            query: SearchQuery::parse(&settings.query, schema)
+                .map_err(anyhow::Error::msg)
+                .context("query")?,
+        })
+    }
+
+    pub fn matches(&self, entry: &LogEntry) -> bool {
+        self.unit.as_ref().is_none_or(|pattern| {
+            entry
+                .get_systemd_unit()
+                .is_some_and(|unit| glob_match(pattern, unit))
+        }) && self.max_priority.is_none_or(|max| {
+            entry
+                .get_priority()
+                .and_then(|priority| priority.parse::<u8>().ok())
+                .is_some_and(|priority| priority <= max)
+        }) && self.query.matches(entry)
+    }
+}
+
+/// Counters of one output
+#[derive(Debug, Default)]
+pub struct OutputStats {
+    pub sent: AtomicU64,
+    /// Entries the sink refused for good
+    pub rejected: AtomicU64,
+    pub failed_batches: AtomicU64,
+    /// Entries read back from the database
+    pub replayed: AtomicU64,
+}
+
+/// A batch the sink refused in a way retrying cannot fix, e.g. as malformed
+#[derive(Debug)]
+pub struct Rejected(pub String);
+
+impl fmt::Display for Rejected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "rejected: {}", self.0)
+    }
+}
+
+impl std::error::Error for Rejected {}
+
+/// The sink asked to be left alone for a while before the batch is retried
+#[derive(Debug)]
+pub struct Throttled {
+    pub retry_after: Duration,
+    pub message: String,
+}
+
+impl fmt::Display for Throttled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (retry after {:?})", self.message, self.retry_after)
+    }
+}
+
+impl std::error::Error for Throttled {}
+
+/// Longest response body quoted in errors
+const MAX_ERROR_BODY: usize = 200;
+
+/// Error for an HTTP sink's unsuccessful response: 408, 429 and 5xx are retried
+/// (after `Retry-After` seconds, when given), anything else rejects the batch
+fn response_error(status: StatusCode, retry_after: Option<&str>, body: &str) -> anyhow::Error {
+    let body: String = body.trim().chars().take(MAX_ERROR_BODY).collect();
+    let message = if body.is_empty() {
+        status.to_string()
+    } else {
+        format!("{}: {}", status, body)
+    };
+    if status == StatusCode::TOO_MANY_REQUESTS
+        && let Some(seconds) = retry_after.and_then(|value| value.trim().parse().ok())
+    {
+        return Throttled {
+            retry_after: Duration::from_secs(seconds),
+            message,
+        }
+        .into();
+    }
+    if status.is_server_error()
+        || status == StatusCode::TOO_MANY_REQUESTS
+        || status == StatusCode::REQUEST_TIMEOUT
+    {
+        return anyhow::Error::msg(message);
+    }
+    Rejected(message).into()
+}
+
+/// Outcome of a batch sent to an HTTP sink. Connection errors leave out the URL,
+/// which can carry a token.
+pub(crate) async fn check_response(response: reqwest::Result<reqwest::Response>) -> Result<()> {
+    let response = response.map_err(reqwest::Error::without_url)?;
+    let status = response.status();
+    if status.is_success() {
+        return Ok(());
+    }
+    let retry_after = response
+        .headers()
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let body = response.text().await.unwrap_or_default();
+    Err(response_error(status, retry_after.as_deref(), &body))
+}
+
+/// A connected sink
+pub(crate) enum Sink {
+    File(PathBuf),
+    Webhook {
+        client: reqwest::Client,
+        url: Url,
+        headers: BTreeMap<String, String>,
+    },
+    Loki(LokiSink),
+    #[cfg(feature = "kafka")]
+    Kafka(KafkaSink),
+    Syslog(SyslogSink),
+    Otlp(OtlpSink),
+    Clickhouse(ClickHouseSink),
+    Peer(PeerSink),
+}
+
+impl Sink {
+    pub fn connect(settings: &SinkSettings) -> Result<Self> {
+        Ok(match settings {
+            SinkSettings::File { path } => {
+                if let Some(dir) = path.parent() {
+                    std::fs::create_dir_all(dir)?;
+                }
+                Self::File(path.clone())
+            }
+            SinkSettings::Webhook { url, headers } => Self::Webhook {
+                client: reqwest::Client::builder()
+                    .timeout(REQUEST_TIMEOUT)
+                    .build()?,
+                url: Url::parse(url)?,
+                headers: headers.clone(),
+            },
+            SinkSettings::Loki(loki) => Self::Loki(LokiSink::new(loki, REQUEST_TIMEOUT)?),
+            #[cfg(feature = "kafka")]
+            SinkSettings::Kafka(kafka) => Self::Kafka(KafkaSink::new(kafka, REQUEST_TIMEOUT)?),
+            #[cfg(not(feature = "kafka"))]
+            SinkSettings::Kafka(_) => {
+                anyhow::bail!("Kafka outputs need livedata built with --features kafka")
+            }
+            SinkSettings::Syslog(syslog) => Self::Syslog(SyslogSink::new(syslog, REQUEST_TIMEOUT)?),
+            SinkSettings::Otlp(otlp) => Self::Otlp(OtlpSink::new(otlp, REQUEST_TIMEOUT)?),
+            SinkSettings::Clickhouse(clickhouse) => {
+                Self::Clickhouse(ClickHouseSink::new(clickhouse, REQUEST_TIMEOUT)?)
+            }
+            SinkSettings::Peer(peer) => Self::Peer(PeerSink::new(peer, REQUEST_TIMEOUT)?),
+        })
+    }
+
+    /// Deliver `batch`, failing if any of it may not have arrived
+    pub async fn send(&self, batch: &[Arc<LogEntry>]) -> Result<()> {
+        match self {
+            Self::File(path) => {
+                let mut lines = Vec::new();
+                for entry in batch {
+                    serde_json::to_writer(&mut lines, entry.as_ref())?;
+                    lines.push(b'\n');
+                }
+                let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+                file.write_all(&lines)?;
+                Ok(())
+            }
+            Self::Webhook {
+                client,
+                url,
+                headers,
+            } => {
+                let entries: Vec<&LogEntry> = batch.iter().map(Arc::as_ref).collect();
+                let mut request = client.post(url.clone()).json(&entries);
+                for (name, value) in headers {
+                    request = request.header(name, value);
+                }
+                check_response(request.send().await).await
+            }
+            Self::Loki(loki) => loki.send(batch).await,
+            #[cfg(feature = "kafka")]
+            Self::Kafka(kafka) => kafka.send(batch).await,
+            Self::Syslog(syslog) => syslog.send(batch).await,
+            Self::Otlp(otlp) => otlp.send(batch).await,
+            Self::Clickhouse(clickhouse) => clickhouse.send(batch).await,
+            Self::Peer(peer) => peer.send(batch).await,
+        }
+    }
+}
+
+/// A running output
+pub struct Output {
+    pub name: String,
+    pub stats: Arc<OutputStats>,
+    handle: thread::JoinHandle<()>,
+}
+
+impl Output {
+    /// Start forwarding entries added to `buffer`, beginning after the output's
+    /// cursor or, the first time, from now on. The thread ends once `shutdown_signal`
+    /// is set, after a last attempt to send what is buffered.
+    pub fn start(
+        settings: OutputSettings,
+        buffer: &Arc<Mutex<DuckDBBuffer>>,
+        shutdown_signal: Arc<AtomicBool>,
+    ) -> Result<Self> {
+        let (entries, schema, cursor, replay) = {
+            let mut buffer = buffer.lock().unwrap();
+            let (cursor, replay) = match buffer.output_cursor(&settings.name)? {
+                Some(cursor) => (cursor, true),
+                None => {
+                    let cursor = buffer.last_seq();
+                    buffer.set_output_cursor(&settings.name, cursor, Utc::now())?;
+                    (cursor, false)
+                }
+            };
+            let schema = buffer.get_schema_columns();
+            (buffer.subscribe_tail(), schema, cursor, replay)
+        };
+        let filter = OutputFilter::new(&settings, &schema)?;
+        let sink = Sink::connect(&settings.sink)?;
+        let stats = Arc::new(OutputStats::default());
+        let name = settings.name.clone();
+        let output = Forwarder {
+            settings,
+            filter,
+            sink,
+            buffer: buffer.clone(),
+            stats: stats.clone(),
+            shutdown_signal,
+        };
+        let handle = thread::Builder::new()
+            .name(format!("output {}", name))
+            .spawn(move || {
+                let rt = tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                    .expect("Failed to create tokio runtime");
+                rt.block_on(output.run(entries, cursor, replay));
+            })?;
+        Ok(Self {
+            name,
+            stats,
+            handle,
+        })
+    }
+
+    /// Wait for the thread to end, giving up at `deadline`
+    pub fn join_until(self, deadline: Instant) {
+        join_until(self.handle, &format!("output {}", self.name), deadline);
+    }
+}
+
+/// Start every configured output; unusable ones are logged and left out
+pub fn start_outputs(
+    settings: &[OutputSettings],
+    buffer: &Arc<Mutex<DuckDBBuffer>>,
+    shutdown_signal: &Arc<AtomicBool>,
+) -> Vec<Output> {
+    settings
+        .iter()
+        .filter_map(
+            |output| match Output::start(output.clone(), buffer, shutdown_signal.clone()) {
+                Ok(started) => {
+                    info!("Forwarding entries to output {}", output.name);
+                    Some(started)
+                }
+                Err(e) => {
+                    error!("Ignoring output {}: {:#}", output.name, e);
+                    None
+                }
+            },
+        )
+        .collect()
+}
+
+/// Sleep for `duration`, waking early when shutdown is signalled
+async fn pause(duration: Duration, shutdown_signal: &AtomicBool) {
+    let mut remaining = duration;
+    while !remaining.is_zero() && !shutdown_signal.load(Ordering::Relaxed) {
+        let step = remaining.min(SHUTDOWN_POLL_INTERVAL);
+        tokio::time::sleep(step).await;
+        remaining -= step;
+    }
+}
+
+/// State of a running output, owned by its thread
+struct Forwarder {
+    settings: OutputSettings,
+    filter: OutputFilter,
+    sink: Sink,
+    buffer: Arc<Mutex<DuckDBBuffer>>,
+    stats: Arc<OutputStats>,
+    shutdown_signal: Arc<AtomicBool>,
+}
+
+impl Forwarder {
+    /// Take matching entries into the output's buffer while sending them on in
+    /// batches; the two run concurrently so entries keep being taken in while a batch
+    /// is on its way. With `replay`, entries stored after `cursor` are read back first.
+    async fn run(self, entries: broadcast::Receiver<Arc<LogEntry>>, cursor: i64, replay: bool) {
+        let pending = Mutex::new(VecDeque::new());
+        tokio::select! {
+            _ = self.receive(entries, cursor, replay, &pending) => {}
+            _ = self.send(&pending) => {}
+        }
+        info!(
+            "Output {} stopped: {} entries sent, {} read back from the database, {} rejected",
+            self.settings.name,
+            self.stats.sent.load(Ordering::Relaxed),
+            self.stats.replayed.load(Ordering::Relaxed),
+            self.stats.rejected.load(Ordering::Relaxed)
+        );
+    }
+
+    async fn receive(
+        &self,
+        mut entries: broadcast::Receiver<Arc<LogEntry>>,
+        cursor: i64,
+        replay: bool,
+        pending: &Mutex<VecDeque<Arc<LogEntry>>>,
+    ) {
+        let name = &self.settings.name;
+        let capacity = self.settings.buffer_entries.max(1);
+        let flush_interval = Duration::from_millis(self.settings.flush_interval_ms.max(1));
+        // While set, entries stored after this are read from the database instead of
+        // the tail
+        let mut replay_from = replay.then_some(cursor);
+        // Latest entry taken in, where reading back resumes
+        let mut latest = cursor;
+        // Once caught up, tail entries up to here were already read back
+        let mut replayed_through = None;
+        loop {
+            if let Some(after) = replay_from {
+                let room = capacity.saturating_sub(pending.lock().unwrap().len());
+                if room == 0 {
+                    pause(flush_interval, &self.shutdown_signal).await;
+                    continue;
+                }
+                let limit = room.min(self.settings.batch_size.max(1));
+                let page = self.buffer.lock().unwrap().entries_after(after, limit);
+                match page {
+                    Ok(page) => match page.last().and_then(|entry| entry.seq) {
+                        Some(last) => {
+                            replay_from = Some(last);
+                            latest = latest.max(last);
+                            self.stats
+                                .replayed
+                                .fetch_add(page.len() as u64, Ordering::Relaxed);
+                            pending.lock().unwrap().extend(
+                                page.into_iter()
+                                    .filter(|entry| self.filter.matches(entry))
+                                    .map(Arc::new),
+                            );
+                            // Let the batch go out before reading further
+                            tokio::task::yield_now().await;
+                        }
+                        None => {
+                            replay_from = None;
+                            replayed_through = Some(after);
+                        }
+                    },
+                    Err(e) => {
+                        warn!("Output {}: reading back entries failed: {}", name, e);
+                        pause(flush_interval, &self.shutdown_signal).await;
+                    }
+                }
+                continue;
+            }
+            match entries.recv().await {
+                Ok(entry) => {
+                    if let Some(through) = replayed_through {
+                        if entry.seq.is_some_and(|seq| seq <= through) {
+                            continue;
+                        }
+                        replayed_through = None;
+                    }
+                    if !self.filter.matches(&entry) {
+                        continue;
+                    }
+                    let mut pending = pending.lock().unwrap();
+                    if pending.len() >= capacity {
+                        // The entry is in the database already
+                        info!(
+                            "Output {} is {} entries behind, reading the rest back later",
+                            name,
+                            pending.len()
+                        );
+                        replay_from = Some(latest);
+                        continue;
+                    }
+                    latest = latest.max(entry.seq.unwrap_or(latest));
+                    pending.push_back(entry);
+                }
+                Err(RecvError::Lagged(missed)) => {
+                    info!(
+                        "Output {} fell behind ingestion by {} entries, reading them back",
+                        name, missed
+                    );
+                    replay_from = Some(latest);
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    }
+
+    async fn send(&self, pending: &Mutex<VecDeque<Arc<LogEntry>>>) {
+        let name = &self.settings.name;
+        let flush_interval = Duration::from_millis(self.settings.flush_interval_ms.max(1));
+        let batch_size = self.settings.batch_size.max(1);
+        let mut failures = 0;
+        loop {
+            let stopping = self.shutdown_signal.load(Ordering::Relaxed);
+            let batch: Vec<Arc<LogEntry>> = pending
+                .lock()
+                .unwrap()
+                .iter()
+                .take(batch_size)
+                .cloned()
+                .collect();
+            if batch.is_empty() {
+                if stopping {
+                    break;
+                }
+                pause(flush_interval, &self.shutdown_signal).await;
+                continue;
+            }
+            match self.sink.send(&batch).await {
+                Ok(()) => {
+                    failures = 0;
+                    self.stats
+                        .sent
+                        .fetch_add(batch.len() as u64, Ordering::Relaxed);
+                    self.delivered(pending, &batch);
+                }
+                Err(e) if e.is::<Rejected>() => {
+                    failures = 0;
+                    error!("Output {}: dropping {} entries: {:#}", name, batch.len(), e);
+                    self.stats
+                        .rejected
+                        .fetch_add(batch.len() as u64, Ordering::Relaxed);
+                    self.delivered(pending, &batch);
+                }
+                Err(e) => {
+                    self.stats.failed_batches.fetch_add(1, Ordering::Relaxed);
+                    if stopping {
+                        warn!(
+                            "Output {}: {} entries left for the next start: {:#}",
+                            name,
+                            pending.lock().unwrap().len(),
+                            e
+                        );
+                        break;
+                    }
+                    let mut delay = retry_delay(self.settings.retry_backoff_ms, failures);
+                    if let Some(throttled) = e.downcast_ref::<Throttled>() {
+                        delay = delay.max(throttled.retry_after);
+                    }
+                    warn!(
+                        "Output {}: sending {} entries failed ({:#}), retrying in {:?}",
+                        name,
+                        batch.len(),
+                        e,
+                        delay
+                    );
+                    failures += 1;
+                    pause(delay, &self.shutdown_signal).await;
+                    continue;
+                }
+            }
+            // Wait for a full batch, unless shutting down
+            if !stopping && pending.lock().unwrap().len() < batch_size {
+                pause(flush_interval, &self.shutdown_signal).await;
+            }
+        }
+    }
+
+    /// Take `batch` off the front of `pending` and move the cursor past it
+    fn delivered(&self, pending: &Mutex<VecDeque<Arc<LogEntry>>>, batch: &[Arc<LogEntry>]) {
+        pending.lock().unwrap().drain(..batch.len());
+        if let Some(seq) = batch.iter().filter_map(|entry| entry.seq).max()
+            && let Some(timestamp) = batch.iter().map(|entry| entry.timestamp).max()
+            && let Err(e) =
+                self.buffer
+                    .lock()
+                    .unwrap()
+                    .set_output_cursor(&self.settings.name, seq, timestamp)
+        {
+            warn!(
+                "Output {}: failed to save cursor: {}",
+                self.settings.name, e
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::path::Path;
+
+    fn entry(unit: &str, priority: u8, message: &str) -> LogEntry {
+        let fields: HashMap<String, String> = [
+            ("_SYSTEMD_UNIT", unit.to_string()),
+            ("PRIORITY", priority.to_string()),
+            ("MESSAGE", message.to_string()),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v))
+        .collect();
+        LogEntry::new(Utc::now(), fields)
+    }
+
+    #[test]
+    fn test_output_settings_and_filter() {
+        let settings: OutputSettings = toml::from_str(
+            r#"
+name = "errors"
+kind = "file"
+path = "/var/log/livedata/errors.jsonl"
+unit = "nginx*"
+max_priority = 3
+query = "-upstream"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            settings.sink,
+            SinkSettings::File {
+                path: PathBuf::from("/var/log/livedata/errors.jsonl")
+            }
+        );
+        assert_eq!(settings.batch_size, 500);
+
+        let filter = OutputFilter::new(&settings, &[]).unwrap();
+        assert!(filter.matches(&entry("nginx.service", 3, "disk full")));
+        assert!(!filter.matches(&entry("nginx.service", 6, "disk full")));
+        assert!(!filter.matches(&entry("nginx.service", 2, "upstream timed out")));
+        assert!(!filter.matches(&entry("postgres.service", 0, "disk full")));
+
+        let webhook: OutputSettings = toml::from_str(
+            "name = \"hook\"\nkind = \"webhook\"\nurl = \"https://example.com/ingest\"",
+        )
+        .unwrap();
+        assert!(matches!(webhook.sink, SinkSettings::Webhook { .. }));
+        assert!(
+            toml::from_str::<OutputSettings>("name = \"x\"\nkind = \"carrier-pigeon\"").is_err()
+        );
+
+        // A Kafka output still loads without the feature, failing only once started
+        let kafka: OutputSettings = toml::from_str(
+            "name = \"bus\"\nkind = \"kafka\"\nbrokers = \"kafka:9092\"\ntopic = \"logs\"",
+        )
+        .unwrap();
+        assert_eq!(kafka.sink.kind(), "kafka");
+        assert_eq!(
+            Sink::connect(&kafka.sink).is_err(),
+            cfg!(not(feature = "kafka"))
+        );
+    }
+
+    #[test]
+    fn test_response_error() {
+        let error = response_error(StatusCode::BAD_REQUEST, None, "entry too far behind\n");
+        assert_eq!(
+            error.downcast_ref::<Rejected>().unwrap().0,
+            "400 Bad Request: entry too far behind"
+        );
+        let error = response_error(StatusCode::TOO_MANY_REQUESTS, Some("30"), "");
+        assert_eq!(
+            error.downcast_ref::<Throttled>().unwrap().retry_after,
+            Duration::from_secs(30)
+        );
+        for status in [
+            StatusCode::TOO_MANY_REQUESTS,
+            StatusCode::BAD_GATEWAY,
+            StatusCode::REQUEST_TIMEOUT,
+        ] {
+            let error = response_error(status, None, "");
+            assert!(!error.is::<Rejected>() && !error.is::<Throttled>());
+        }
+    }
+
+    /// Run the `errors` output until it has sent `expected` entries
+    fn run_errors_output(
+        buffer: &Arc<Mutex<DuckDBBuffer>>,
+        path: &Path,
+        add: &[LogEntry],
+        expected: u64,
+    ) {
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let output = Output::start(
+            OutputSettings {
+                name: "errors".to_string(),
+                sink: SinkSettings::File {
+                    path: path.to_path_buf(),
+                },
+                unit: None,
+                max_priority: Some(3),
+                query: String::new(),
+                batch_size: 10,
+                flush_interval_ms: 10,
+                buffer_entries: 100,
+                retry_backoff_ms: 10,
+            },
+            buffer,
+            shutdown.clone(),
+        )
+        .unwrap();
+        for entry in add {
+            buffer.lock().unwrap().add_entry(entry).unwrap();
+        }
+        for _ in 0..200 {
+            if output.stats.sent.load(Ordering::Relaxed) >= expected {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        shutdown.store(true, Ordering::Relaxed);
+        let stats = output.stats.clone();
+        output.join_until(Instant::now() + Duration::from_secs(5));
+        assert_eq!(stats.sent.load(Ordering::Relaxed), expected);
+    }
+
+    #[test]
+    fn test_file_output_receives_matching_entries() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let buffer = Arc::new(Mutex::new(DuckDBBuffer::new(temp_dir.path()).unwrap()));
+        let path = temp_dir.path().join("out").join("errors.jsonl");
+        run_errors_output(
+            &buffer,
+            &path,
+            &[
+                entry("cron.service", 6, "ran job"),
+                entry("cron.service", 3, "job failed"),
+            ],
+            1,
+        );
+
+        // Entries ingested while the output was stopped are sent once it is back
+        thread::sleep(Duration::from_millis(5));
+        buffer
+            .lock()
+            .unwrap()
+            .add_entry(&entry("backup.service", 2, "disk full"))
+            .unwrap();
+        run_errors_output(&buffer, &path, &[], 1);
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<serde_json::Value> = written
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["fields"]["MESSAGE"], "job failed");
+        assert_eq!(lines[1]["fields"]["MESSAGE"], "disk full");
+        assert_eq!(lines[1]["fields"]["_SYSTEMD_UNIT"], "backup.service");
+    }
+}
@@ -0,0 +1,226 @@
+use anyhow::Result;
+use chrono::{DateTime, NaiveDate, Utc};
+use log::info;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Writes journal_logs data out to Parquet files.
+///
+/// Cold storage files are laid out as `<data_dir>/cold/journal_logs/YYYYMMDD.parquet`, one
+/// per day, so a day can be located (and deleted) without opening any file. Per-minute
+/// exports go to `<data_dir>/parquet/YYYY/MM/DD/HHMM.parquet`.
+#[derive(Clone)]
+pub struct ParquetWriter {
+    cold_dir: PathBuf,
+    export_dir: PathBuf,
+}
+
+impl ParquetWriter {
+    pub fn new<P: AsRef<Path>>(data_dir: P) -> Self {
+        Self {
+            cold_dir: data_dir.as_ref().join("cold").join("journal_logs"),
+            export_dir: data_dir.as_ref().join("parquet"),
+        }
+    }
+
+    /// Path of the Parquet file holding the exported `minute_key`
+    pub fn minute_path(&self, minute_key: DateTime<Utc>) -> PathBuf {
+        self.export_dir
+            .join(minute_key.format("%Y").to_string())
+            .join(minute_key.format("%m").to_string())
+            .join(minute_key.format("%d").to_string())
+            .join(format!("{}.parquet", minute_key.format("%H%M")))
+    }
+
+    /// Build the COPY statement exporting one minute of `source_table`.
+    ///
+    /// `columns` must come from the live table schema (`DESCRIBE`), so the export always
+    /// matches whatever columns journal_logs currently has. The statement binds the
+    /// start of the minute and the start of the next one, as naive UTC timestamps.
+    pub fn write_minute_to_parquet(
+        &self,
+        source_table: &str,
+        columns: &[String],
+        minute_key: DateTime<Utc>,
+    ) -> Result<(String, PathBuf)> {
+        if columns.is_empty() {
+            anyhow::bail!("Cannot export {}: schema has no columns", source_table);
+        }
+
+        let path = self.minute_path(minute_key);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let select_list = columns
+            .iter()
+            .map(|c| quote_ident(c))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let sql = format!(
+            "COPY (SELECT {} FROM {} WHERE timestamp >= CAST(? AS TIMESTAMP) AND timestamp < CAST(? AS TIMESTAMP) ORDER BY timestamp) TO '{}' (FORMAT PARQUET, COMPRESSION ZSTD)",
+            select_list,
+            source_table,
+            sql_path(&path)
+        );
+        Ok((sql, path))
+    }
+
+    /// Directory holding the per-day Parquet files
+    pub fn cold_dir(&self) -> &Path {
+        &self.cold_dir
+    }
+
+    /// Path of the Parquet file holding `day`
+    pub fn day_path(&self, day: NaiveDate) -> PathBuf {
+        self.cold_dir
+            .join(format!("{}.parquet", day.format("%Y%m%d")))
+    }
+
+    /// Build the COPY statement exporting `source_table` into the Parquet file for `day`.
+    /// The file is written under a temporary name first; see [`ParquetWriter::commit_day`].
+    pub fn copy_day_sql(&self, source_table: &str, day: NaiveDate) -> Result<String> {
+        fs::create_dir_all(&self.cold_dir)?;
+        Ok(format!(
+            "COPY (SELECT * FROM {} ORDER BY timestamp) TO '{}' (FORMAT PARQUET, COMPRESSION ZSTD)",
+            source_table,
+            sql_path(&self.pending_path(day))
+        ))
+    }
+
+    /// Move a freshly written file into place so readers never see a partial file
+    pub fn commit_day(&self, day: NaiveDate) -> Result<PathBuf> {
+        let path = self.day_path(day);
+        fs::rename(self.pending_path(day), &path)?;
+        info!("Wrote cold storage file: {}", path.display());
+        Ok(path)
+    }
+
+    /// Days that have a Parquet file in cold storage
+    pub fn list_days(&self) -> Result<BTreeSet<NaiveDate>> {
+        let mut days = BTreeSet::new();
+        if !self.cold_dir.exists() {
+            return Ok(days);
+        }
+
+        for entry in fs::read_dir(&self.cold_dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("parquet") {
+                continue;
+            }
+            if let Some(day) = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .and_then(|s| NaiveDate::parse_from_str(s, "%Y%m%d").ok())
+            {
+                days.insert(day);
+            }
+        }
+        Ok(days)
+    }
+
+    /// Delete the Parquet file for `day`, returning whether one existed
+    pub fn remove_day(&self, day: NaiveDate) -> Result<bool> {
+        let path = self.day_path(day);
+        if !path.exists() {
+            return Ok(false);
+        }
+        fs::remove_file(&path)?;
+        info!("Removed cold storage file: {}", path.display());
+        Ok(true)
+    }
+
+    /// Subquery reading the given days with `read_parquet(...)`
+    pub fn read_sql<'a, I>(&self, days: I) -> Option<String>
+    where
+        I: IntoIterator<Item = &'a NaiveDate>,
+    {
+        let files: Vec<String> = days
+            .into_iter()
+            .map(|day| format!("'{}'", sql_path(&self.day_path(*day))))
+            .collect();
+        if files.is_empty() {
+            return None;
+        }
+        Some(without_legacy_columns(&format!(
+            "read_parquet([{}], union_by_name = true)",
+            files.join(", ")
+        )))
+    }
+
+    fn pending_path(&self, day: NaiveDate) -> PathBuf {
+        self.cold_dir
+            .join(format!("{}.parquet.tmp", day.format("%Y%m%d")))
+    }
+}
+
+/// Wrap a Parquet `source` so it has the same columns as the DuckDB tables. Files
+/// written before minute_key was dropped from the schema still carry that column.
+pub(crate) fn without_legacy_columns(source: &str) -> String {
+    format!("(SELECT COLUMNS(c -> c <> 'minute_key') FROM {})", source)
+}
+
+/// Quote a column name taken from the schema as a SQL identifier
+pub(crate) fn quote_ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// Render a path as the body of a single-quoted SQL string literal
+pub(crate) fn sql_path(path: &Path) -> String {
+    path.to_string_lossy().replace('\'', "''")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_list_days_ignores_unrelated_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let writer = ParquetWriter::new(temp_dir.path());
+        fs::create_dir_all(writer.cold_dir()).unwrap();
+
+        let day = NaiveDate::from_ymd_opt(2026, 1, 17).unwrap();
+        fs::write(writer.day_path(day), b"").unwrap();
+        fs::write(writer.cold_dir().join("notes.txt"), b"").unwrap();
+        fs::write(writer.cold_dir().join("20260118.parquet.tmp"), b"").unwrap();
+
+        let days = writer.list_days().unwrap();
+        assert_eq!(days.into_iter().collect::<Vec<_>>(), vec![day]);
+    }
+
+    #[test]
+    fn test_write_minute_uses_given_columns() {
+        let temp_dir = TempDir::new().unwrap();
+        let writer = ParquetWriter::new(temp_dir.path());
+        let minute_key = chrono::TimeZone::with_ymd_and_hms(&Utc, 2026, 1, 17, 14, 30, 0).unwrap();
+        let columns = vec!["timestamp".to_string(), "_HOSTNAME".to_string()];
+
+        let (sql, path) = writer
+            .write_minute_to_parquet("journal_logs_20260117", &columns, minute_key)
+            .unwrap();
+        assert!(
+            sql.starts_with("COPY (SELECT \"timestamp\", \"_HOSTNAME\" FROM journal_logs_20260117")
+        );
+        assert!(path.ends_with("parquet/2026/01/17/1430.parquet"));
+        assert!(path.parent().unwrap().exists());
+
+        assert!(
+            writer
+                .write_minute_to_parquet("journal_logs_20260117", &[], minute_key)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_read_sql_quotes_paths() {
+        let writer = ParquetWriter::new("/tmp/o'brien");
+        let day = NaiveDate::from_ymd_opt(2026, 1, 17).unwrap();
+        let sql = writer.read_sql([day].iter()).unwrap();
+        assert!(sql.contains("o''brien"));
+        assert!(sql.contains("20260117.parquet"));
+        assert!(writer.read_sql(std::iter::empty()).is_none());
+    }
+}
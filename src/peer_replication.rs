@@ -0,0 +1,372 @@
+//! Replication between livedata instances: a `peer` output streams entries to another
+//! instance (agent → aggregator), whose `[peer_input]` listener stores them. Both ends
+//! authenticate each other with mutual TLS.
+//!
+//! A sender's identity is the common name (CN) of its client certificate. Entries from
+//! its own journal are stored as source `peer:<CN>`, and entries it relayed from
+//! further away as `peer:<CN>/<their source>`, so no sender can pass its entries off as
+//! another's.
+//!
+//! The agent's output cursor is its cursor for that peer, so after a restart or an
+//! outage it resumes where the aggregator left off. Batches sent again after a failure
+//! are recognised by (source, `__CURSOR`) and only stored once.
+
+use crate::duckdb_buffer::DuckDBBuffer;
+use crate::log_entry::{LOCAL_SOURCE, LogEntry};
+use crate::metrics::Metrics;
+use crate::outputs::check_response;
+use anyhow::{Context, Result};
+use axum::extract::{DefaultBodyLimit, State};
+use axum::http::StatusCode;
+use axum::middleware::AddExtension;
+use axum::routing::post;
+use axum::{Extension, Json, Router};
+use axum_server::accept::Accept;
+use axum_server::tls_rustls::{RustlsAcceptor, RustlsConfig};
+use chrono::{DateTime, Utc};
+use futures_util::future::BoxFuture;
+use log::{error, info};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tokio_rustls::rustls::{RootCertStore, ServerConfig, crypto};
+use tokio_rustls::server::TlsStream;
+use tower_layer::Layer;
+
+/// Path replicated batches are posted to
+const REPLICATE_PATH: &str = "/replicate";
+/// Largest batch accepted, well above what `batch_size` produces
+const MAX_BATCH_BYTES: usize = 64 * 1024 * 1024;
+
+/// Sending side, an output of kind `peer`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PeerSettings {
+    /// The receiving instance's `[peer_input]`, e.g. "https://aggregator:9440"
+    pub url: String,
+
+    /// Client certificate and key (PEM) presented to the receiver; the certificate's
+    /// common name is the name this instance's entries are stored under there
+    pub cert_file: PathBuf,
+    pub key_file: PathBuf,
+
+    /// PEM file with the CA certificates the receiver's certificate is checked
+    /// against; only these are trusted
+    pub ca_file: PathBuf,
+}
+
+/// Receiving side (`[peer_input]` table)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PeerInputSettings {
+    /// Address to listen on, e.g. "0.0.0.0:9440"
+    #[serde(default = "default_listen")]
+    pub listen: String,
+
+    /// Server certificate and key (PEM)
+    pub cert_file: PathBuf,
+    pub key_file: PathBuf,
+
+    /// PEM file with the CA certificates peers' client certificates must chain to
+    pub client_ca_file: PathBuf,
+}
+
+fn default_listen() -> String {
+    "0.0.0.0:9440".to_string()
+}
+
+fn read_pem(path: &Path) -> Result<Vec<u8>> {
+    std::fs::read(path).with_context(|| format!("reading {}", path.display()))
+}
+
+fn root_store(ca_file: &Path) -> Result<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+    for cert in CertificateDer::pem_file_iter(ca_file)
+        .with_context(|| format!("reading {}", ca_file.display()))?
+    {
+        roots.add(cert?)?;
+    }
+    Ok(roots)
+}
+
+pub struct PeerSink {
+    client: reqwest::Client,
+    url: Url,
+}
+
+impl PeerSink {
+    pub fn new(settings: &PeerSettings, timeout: Duration) -> Result<Self> {
+        let mut identity = read_pem(&settings.cert_file)?;
+        identity.extend(read_pem(&settings.key_file)?);
+        Ok(Self {
+            client: reqwest::Client::builder()
+                .timeout(timeout)
+                .identity(reqwest::Identity::from_pem(&identity)?)
+                .add_root_certificate(reqwest::Certificate::from_pem(&read_pem(
+                    &settings.ca_file,
+                )?)?)
+                .tls_built_in_root_certs(false)
+                .build()?,
+            url: Url::parse(&settings.url)?.join(REPLICATE_PATH)?,
+        })
+    }
+
+    pub async fn send(&self, batch: &[Arc<LogEntry>]) -> Result<()> {
+        let entries: Vec<&LogEntry> = batch.iter().map(Arc::as_ref).collect();
+        let request = self.client.post(self.url.clone()).json(&entries);
+        check_response(request.send().await).await
+    }
+}
+
+/// TLS settings of the listener, requiring a client certificate issued by one of
+/// `client_ca_file`'s CAs
+fn server_config(settings: &PeerInputSettings) -> Result<ServerConfig> {
+    let provider = Arc::new(crypto::ring::default_provider());
+    let verifier = WebPkiClientVerifier::builder_with_provider(
+        Arc::new(root_store(&settings.client_ca_file)?),
+        provider.clone(),
+    )
+    .build()?;
+    let certs = CertificateDer::pem_file_iter(&settings.cert_file)
+        .with_context(|| format!("reading {}", settings.cert_file.display()))?
+        .collect::<Result<Vec<_>, _>>()?;
+    let key = PrivateKeyDer::from_pem_file(&settings.key_file)
+        .with_context(|| format!("reading {}", settings.key_file.display()))?;
+    let mut config = ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()?
+        .with_client_cert_verifier(verifier)
+        .with_single_cert(certs, key)?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(config)
+}
+
+/// An instance that replicated to this one, as listed by /api/sources
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SourceStatus {
+    /// Source its own entries are stored under, `peer:<name>`
+    pub name: String,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    /// Timestamp of the newest entry it sent
+    pub last_entry_at: Option<DateTime<Utc>>,
+    /// How old the newest entry of its last batch was on arrival
+    pub lag_ms: Option<i64>,
+    pub entries_received: i64,
+    /// Received entries that were not duplicates
+    pub entries_stored: i64,
+    /// Its own entries stored per minute, over the last few minutes
+    pub entries_per_minute: f64,
+    /// Whether it was heard from in the last couple of minutes
+    pub connected: bool,
+}
+
+/// Common name of the sender's client certificate, added to each of its requests
+#[derive(Debug, Clone)]
+struct PeerIdentity(Option<String>);
+
+/// First common name in the subject of the DER certificate `cert`
+fn common_name(cert: &[u8]) -> Option<String> {
+    let (_, cert) = x509_parser::parse_x509_certificate(cert).ok()?;
+    let name = cert.subject().iter_common_name().next()?.as_str().ok()?;
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+/// TLS acceptor that hands the client certificate's common name to the handlers
+#[derive(Clone)]
+struct IdentityAcceptor {
+    inner: RustlsAcceptor,
+}
+
+impl<I, S> Accept<I, S> for IdentityAcceptor
+where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    S: Send + 'static,
+{
+    type Stream = TlsStream<I>;
+    type Service = AddExtension<S, PeerIdentity>;
+    type Future = BoxFuture<'static, std::io::Result<(Self::Stream, Self::Service)>>;
+
+    fn accept(&self, stream: I, service: S) -> Self::Future {
+        let acceptor = self.inner.clone();
+        Box::pin(async move {
+            let (stream, service) = acceptor.accept(stream, service).await?;
+            // The verifier already required a certificate chaining to a trusted CA
+            let identity = stream
+                .get_ref()
+                .1
+                .peer_certificates()
+                .and_then(|certs| certs.first())
+                .and_then(|cert| common_name(cert));
+            Ok((stream, Extension(PeerIdentity(identity)).layer(service)))
+        })
+    }
+}
+
+/// Source a replicated entry from `peer` is stored under
+fn replicated_source(entry: &LogEntry, peer: &str) -> String {
+    if entry.source == LOCAL_SOURCE {
+        format!("peer:{}", peer)
+    } else {
+        format!("peer:{}/{}", peer, entry.source)
+    }
+}
+
+/// What the peer input stores into
+#[derive(Clone)]
+struct PeerInput {
+    buffer: Arc<Mutex<DuckDBBuffer>>,
+    /// Told about stored entries, so the web server's query cache sees them
+    metrics: Arc<Metrics>,
+}
+
+async fn replicate(
+    State(input): State<PeerInput>,
+    Extension(PeerIdentity(identity)): Extension<PeerIdentity>,
+    Json(mut entries): Json<Vec<LogEntry>>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let peer_name = identity.ok_or((
+        StatusCode::FORBIDDEN,
+        "client certificate has no common name".to_string(),
+    ))?;
+    for entry in &mut entries {
+        entry.source = replicated_source(entry, &peer_name);
+    }
+    let received = entries.len();
+    let newest = entries.iter().map(|entry| entry.timestamp).max();
+    let source = format!("peer:{}", peer_name);
+    let buffer = input.buffer;
+    let stored = tokio::task::spawn_blocking(move || {
+        let mut buffer = buffer.lock().unwrap();
+        let stored = buffer.add_replicated_entries(&entries)?;
+        buffer.record_source_batch(&source, received, stored, newest, Utc::now())?;
+        Ok::<_, anyhow::Error>(stored)
+    })
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .map_err(|e| {
+        error!("Failed to store entries from peer {}: {:#}", peer_name, e);
+        (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+    })?;
+    input.metrics.record_replicated(stored);
+    Ok(Json(json!({"received": received, "stored": stored})))
+}
+
+/// Start the `[peer_input]` listener on its own thread, storing what peers send into
+/// `buffer` and counting them in `metrics`. The thread ends once `shutdown_signal` is
+/// set.
+pub fn start_peer_input(
+    settings: &PeerInputSettings,
+    buffer: Arc<Mutex<DuckDBBuffer>>,
+    metrics: Arc<Metrics>,
+    shutdown_signal: Arc<AtomicBool>,
+) -> Result<thread::JoinHandle<()>> {
+    let addr: SocketAddr = settings
+        .listen
+        .parse()
+        .with_context(|| format!("invalid listen address {}", settings.listen))?;
+    let config = RustlsConfig::from_config(Arc::new(server_config(settings)?));
+    let app = Router::new()
+        .route(REPLICATE_PATH, post(replicate))
+        .layer(DefaultBodyLimit::max(MAX_BATCH_BYTES))
+        .with_state(PeerInput { buffer, metrics });
+    let handle = thread::Builder::new()
+        .name("peer input".to_string())
+        .spawn(move || {
+            let rt = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .expect("Failed to create tokio runtime");
+            rt.block_on(async move {
+                let server = axum_server::Handle::new();
+                let shutdown = server.clone();
+                tokio::spawn(async move {
+                    while !shutdown_signal.load(Ordering::Relaxed) {
+                        tokio::time::sleep(Duration::from_millis(500)).await;
+                    }
+                    shutdown.graceful_shutdown(Some(Duration::from_secs(5)));
+                });
+                info!("Peer input listening on https://{}", addr);
+                if let Err(e) = axum_server::bind(addr)
+                    .acceptor(IdentityAcceptor {
+                        inner: RustlsAcceptor::new(config),
+                    })
+                    .handle(server)
+                    .serve(app.into_make_service())
+                    .await
+                {
+                    error!("Peer input stopped: {}", e);
+                }
+            });
+        })?;
+    Ok(handle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::outputs::{OutputSettings, SinkSettings};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_peer_settings_and_sources() {
+        let settings: OutputSettings = toml::from_str(
+            r#"
+name = "aggregator"
+kind = "peer"
+url = "https://aggregator:9440"
+cert_file = "/etc/livedata/agent.pem"
+key_file = "/etc/livedata/agent-key.pem"
+ca_file = "/etc/livedata/ca.pem"
+"#,
+        )
+        .unwrap();
+        let SinkSettings::Peer(peer) = &settings.sink else {
+            panic!("not a peer output: {:?}", settings.sink);
+        };
+        assert_eq!(peer.url, "https://aggregator:9440");
+        assert!(PeerSink::new(peer, Duration::from_secs(1)).is_err());
+
+        let input: PeerInputSettings = toml::from_str(
+            "cert_file = \"server.pem\"\nkey_file = \"server-key.pem\"\n\
+             client_ca_file = \"ca.pem\"",
+        )
+        .unwrap();
+        assert_eq!(input.listen, "0.0.0.0:9440");
+
+        let local = LogEntry::new(Utc::now(), HashMap::new());
+        assert_eq!(replicated_source(&local, "web1"), "peer:web1");
+        let relayed = local.with_source("syslog:10.0.0.5");
+        assert_eq!(
+            replicated_source(&relayed, "web1"),
+            "peer:web1/syslog:10.0.0.5"
+        );
+    }
+
+    #[test]
+    fn test_common_name() {
+        let pem = "-----BEGIN CERTIFICATE-----
+MIIBszCCAVmgAwIBAgIUBbEh8OPqafJCfMG22GMsZ6bbHAEwCgYIKoZIzj0EAwIw
+LjERMA8GA1UECgwIbGl2ZWRhdGExGTAXBgNVBAMMEHdlYjEuZXhhbXBsZS5jb20w
+IBcNMjYxMDE3MDMyODA3WhgPMjEyNjA5MjMwMzI4MDdaMC4xETAPBgNVBAoMCGxp
+dmVkYXRhMRkwFwYDVQQDDBB3ZWIxLmV4YW1wbGUuY29tMFkwEwYHKoZIzj0CAQYI
+KoZIzj0DAQcDQgAErPUNk2XkB2/domnL70zjEkzMMsqxfv6tSTcqYnu/uiYc3a/4
+kjGVo9d9ckWjeN4VK7ERNZAAUJZWXeERq/qopaNTMFEwHQYDVR0OBBYEFFnbUoNU
+TfsI+ZXv/fs0fn4TL8k8MB8GA1UdIwQYMBaAFFnbUoNUTfsI+ZXv/fs0fn4TL8k8
+MA8GA1UdEwEB/wQFMAMBAf8wCgYIKoZIzj0EAwIDSAAwRQIgMdo27zwpg3zGGeba
+wIcACDWPF3aPAGtw/DoH1Frt/LYCIQDsYNNUkldWbbZKbq2mnyhRppUVjyG3vT8i
+3AGXQO9xuQ==
+-----END CERTIFICATE-----
+";
+        let cert = CertificateDer::from_pem_slice(pem.as_bytes()).unwrap();
+        assert_eq!(common_name(&cert).as_deref(), Some("web1.example.com"));
+        assert_eq!(common_name(b"not a certificate"), None);
+    }
+}
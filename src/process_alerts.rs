@@ -0,0 +1,241 @@
+//! Evaluation of `[[process_alert_rules]]` against process samples. A rule fires once
+//! the processes it matches stay over a threshold for its number of consecutive
+//! samples, and resolves at the first sample back under it.
+
+use crate::config::ProcessAlertRule;
+use crate::notifications::{AlertEvent, AlertState};
+use crate::process_monitor::ProcessInfo;
+use chrono::{DateTime, Utc};
+use log::warn;
+use serde_json::json;
+use std::collections::BTreeMap;
+
+/// Source of the alerts raised here
+pub const PROCESS_ALERT_SOURCE: &str = "process";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Resource {
+    Cpu,
+    Memory,
+}
+
+impl Resource {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Cpu => "cpu",
+            Self::Memory => "memory",
+        }
+    }
+
+    fn describe(self, value: f64) -> String {
+        match self {
+            Self::Cpu => format!("CPU {:.1}%", value),
+            Self::Memory => format!("memory {:.0} MiB", value / (1024.0 * 1024.0)),
+        }
+    }
+}
+
+struct RuleState {
+    rule: ProcessAlertRule,
+    /// Consecutive samples over a threshold so far
+    streak: u32,
+    /// Resource the rule fired on, while it is firing
+    firing: Option<Resource>,
+}
+
+/// State of every process alert rule between samples
+pub struct ProcessAlerts {
+    rules: Vec<RuleState>,
+}
+
+impl ProcessAlerts {
+    pub fn new(rules: &[ProcessAlertRule]) -> Self {
+        let rules = rules
+            .iter()
+            .filter(|rule| {
+                let usable = rule.cpu_percent.is_some() || rule.memory_bytes.is_some();
+                if !usable {
+                    warn!(
+                        "Process alert rule {} has neither cpu_percent nor memory_bytes; ignoring it",
+                        rule.name
+                    );
+                }
+                usable
+            })
+            .map(|rule| RuleState {
+                rule: rule.clone(),
+                streak: 0,
+                firing: None,
+            })
+            .collect();
+        Self { rules }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Alerts that fire or resolve at the sample of `processes` taken at `timestamp`
+    pub fn evaluate(
+        &mut self,
+        processes: &[ProcessInfo],
+        timestamp: DateTime<Utc>,
+    ) -> Vec<AlertEvent> {
+        let mut alerts = Vec::new();
+        for state in &mut self.rules {
+            let rule = &state.rule;
+            let matching = processes.iter().filter(|process| {
+                rule.process_name
+                    .as_ref()
+                    .is_none_or(|name| process.name == *name)
+                    && rule
+                        .unit
+                        .as_ref()
+                        .is_none_or(|unit| process.unit.as_ref() == Some(unit))
+            });
+            let (mut cpu, mut memory, mut count) = (0.0, 0.0, 0);
+            for process in matching {
+                cpu += process.cpu_percent as f64;
+                memory += process.memory_bytes as f64;
+                count += 1;
+            }
+            let value = |resource| match resource {
+                Resource::Cpu => cpu,
+                Resource::Memory => memory,
+            };
+            let threshold = |resource| match resource {
+                Resource::Cpu => rule.cpu_percent.map(f64::from),
+                Resource::Memory => rule.memory_bytes.map(|bytes| bytes as f64),
+            };
+            let over = [Resource::Cpu, Resource::Memory]
+                .into_iter()
+                .find(|resource| threshold(*resource).is_some_and(|t| value(*resource) > t));
+
+            let alert = |alert_state, resource: Resource, message| {
+                AlertEvent {
+                    timestamp,
+                    rule: rule.name.clone(),
+                    source: PROCESS_ALERT_SOURCE.to_string(),
+                    state: alert_state,
+                    value: value(resource),
+                    threshold: threshold(resource).unwrap_or_default(),
+                    message,
+                    samples: Vec::new(),
+                    link: None,
+                    tags: rule.tags.clone(),
+                    details: BTreeMap::new(),
+                }
+                .with_details(json!({
+                    "process_name": rule.process_name,
+                    "unit": rule.unit,
+                    "resource": resource.as_str(),
+                    "processes": count,
+                }))
+            };
+            match (over, state.firing) {
+                (Some(resource), None) => {
+                    state.streak += 1;
+                    if state.streak >= rule.samples.max(1) {
+                        alerts.push(alert(
+                            AlertState::Firing,
+                            resource,
+                            format!(
+                                "{} process(es) at {} over {} for {} samples",
+                                count,
+                                resource.describe(value(resource)),
+                                resource.describe(threshold(resource).unwrap_or_default()),
+                                state.streak
+                            ),
+                        ));
+                        state.firing = Some(resource);
+                    }
+                }
+                (Some(_), Some(_)) => state.streak += 1,
+                (None, Some(resource)) => {
+                    alerts.push(alert(
+                        AlertState::Resolved,
+                        resource,
+                        format!(
+                            "{} process(es) back at {}",
+                            count,
+                            resource.describe(value(resource))
+                        ),
+                    ));
+                    state.streak = 0;
+                    state.firing = None;
+                }
+                (None, None) => state.streak = 0,
+            }
+        }
+        alerts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn process(pid: u32, name: &str, cpu: f32, memory: u64) -> ProcessInfo {
+        ProcessInfo {
+            pid,
+            name: name.to_string(),
+            cpu_percent: cpu,
+            memory_bytes: memory,
+            user_id: None,
+            runtime_secs: 10,
+            cmd: Vec::new(),
+            virtual_memory_bytes: 0,
+            status: "Run".to_string(),
+            parent_pid: None,
+            read_bytes_per_sec: 0.0,
+            write_bytes_per_sec: 0.0,
+            num_threads: None,
+            open_fd_count: None,
+            unit: Some("worker.service".to_string()),
+            container_id: None,
+        }
+    }
+
+    #[test]
+    fn test_rule_fires_after_sustained_breach_and_resolves() {
+        let mut alerts = ProcessAlerts::new(&[
+            ProcessAlertRule {
+                name: "busy workers".to_string(),
+                unit: Some("worker.service".to_string()),
+                cpu_percent: Some(100.0),
+                samples: 2,
+                ..Default::default()
+            },
+            ProcessAlertRule {
+                name: "no threshold".to_string(),
+                ..Default::default()
+            },
+        ]);
+        let now = Utc::now();
+        let states = |alerts: Vec<AlertEvent>| -> Vec<(AlertState, f64)> {
+            alerts.into_iter().map(|a| (a.state, a.value)).collect()
+        };
+
+        // Two workers at 60% each are summed
+        let busy = [process(1, "worker", 60.0, 0), process(2, "worker", 60.0, 0)];
+        assert!(alerts.evaluate(&busy, now).is_empty());
+        assert_eq!(
+            states(alerts.evaluate(&busy, now)),
+            [(AlertState::Firing, 120.0)]
+        );
+        // Firing again only after resolving
+        assert!(alerts.evaluate(&busy, now).is_empty());
+
+        let calm = [process(1, "worker", 10.0, 0)];
+        assert_eq!(
+            states(alerts.evaluate(&calm, now)),
+            [(AlertState::Resolved, 10.0)]
+        );
+        assert!(alerts.evaluate(&calm, now).is_empty());
+
+        // An interrupted streak starts over
+        assert!(alerts.evaluate(&busy, now).is_empty());
+        assert!(alerts.evaluate(&calm, now).is_empty());
+        assert!(alerts.evaluate(&busy, now).is_empty());
+    }
+}
@@ -1,8 +1,15 @@
+use crate::config::Settings;
+use crate::gpu::{GpuCollector, GpuSample};
+use crate::supervisor::Supervisor;
+use crate::user_names;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
-use sysinfo::{ProcessesToUpdate, System};
+use std::time::Instant;
+use sysinfo::{Components, Disks, Networks, ProcessesToUpdate, System};
 use tokio::sync::mpsc;
 use tokio::time::Duration;
 
@@ -19,6 +26,19 @@ pub struct ProcessInfo {
     pub virtual_memory_bytes: u64,
     pub status: String,
     pub parent_pid: Option<u32>,
+    /// Bytes read from and written to storage since the previous sample, per second.
+    /// sysinfo has no per-process network counters, so those are not collected.
+    pub read_bytes_per_sec: f64,
+    pub write_bytes_per_sec: f64,
+    /// Threads, from /proc; `None` where it is unreadable
+    pub num_threads: Option<u32>,
+    /// Open file descriptors, from /proc; `None` for other users' processes unless
+    /// running as root
+    pub open_fd_count: Option<u32>,
+    /// systemd unit or scope the process's cgroup belongs to
+    pub unit: Option<String>,
+    /// Docker, Podman or containerd container the process runs in
+    pub container_id: Option<String>,
 }
 
 impl ProcessInfo {
@@ -30,12 +50,686 @@ impl ProcessInfo {
             Some(self.cmd.join(" "))
         }
     }
+
+    /// Numeric user id, without sysinfo's `Uid(...)` wrapper
+    pub fn uid(&self) -> Option<String> {
+        self.user_id.as_ref().and_then(|uid_str| {
+            uid_str
+                .strip_prefix("Uid(")
+                .and_then(|s| s.strip_suffix(')'))
+                .map(|s| s.to_string())
+        })
+    }
+
+    pub fn uid_number(&self) -> Option<u32> {
+        self.uid()?.parse().ok()
+    }
+
+    /// Login name of the owner, or its uid if it has none
+    pub fn user_name(&self) -> Option<String> {
+        self.uid_number()
+            .and_then(user_names::user_name)
+            .or_else(|| self.uid())
+    }
+}
+
+/// Which processes of each sample are persisted to process_metrics. The live
+/// snapshot behind /api/processes always holds every process.
+#[derive(Debug, Clone, Default)]
+pub struct ProcessFilter {
+    pub names: Vec<String>,
+    pub users: Vec<String>,
+    pub min_cpu_percent: f32,
+    pub min_memory_bytes: u64,
+    pub top_n: usize,
+}
+
+impl ProcessFilter {
+    pub fn from_settings(settings: &Settings) -> Self {
+        Self {
+            names: settings.process_names.clone(),
+            users: settings.process_users.clone(),
+            min_cpu_percent: settings.process_min_cpu_percent,
+            min_memory_bytes: settings.process_min_memory_bytes,
+            top_n: settings.process_top_n,
+        }
+    }
+
+    /// Whether the filter lets every process through
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+            && self.users.is_empty()
+            && self.min_cpu_percent <= 0.0
+            && self.min_memory_bytes == 0
+            && self.top_n == 0
+    }
+
+    /// A process passes the thresholds if it reaches either of them
+    fn matches(&self, process: &ProcessInfo) -> bool {
+        if !self.names.is_empty() && !self.names.contains(&process.name) {
+            return false;
+        }
+        if !self.users.is_empty()
+            && ![process.uid(), process.user_name()]
+                .into_iter()
+                .flatten()
+                .any(|user| self.users.contains(&user))
+        {
+            return false;
+        }
+        let thresholds = self.min_cpu_percent > 0.0 || self.min_memory_bytes > 0;
+        !thresholds
+            || (self.min_cpu_percent > 0.0 && process.cpu_percent >= self.min_cpu_percent)
+            || (self.min_memory_bytes > 0 && process.memory_bytes >= self.min_memory_bytes)
+    }
+
+    /// Processes worth persisting, at most `top_n` of them by CPU then memory
+    pub fn apply(&self, processes: Vec<ProcessInfo>) -> Vec<ProcessInfo> {
+        if self.is_empty() {
+            return processes;
+        }
+        let mut kept: Vec<ProcessInfo> = processes
+            .into_iter()
+            .filter(|process| self.matches(process))
+            .collect();
+        if self.top_n > 0 && kept.len() > self.top_n {
+            kept.sort_by(|a, b| {
+                b.cpu_percent
+                    .total_cmp(&a.cpu_percent)
+                    .then(b.memory_bytes.cmp(&a.memory_bytes))
+            });
+            kept.truncate(self.top_n);
+        }
+        kept
+    }
+}
+
+/// Status of the row written when a process present in the previous delta sample is
+/// gone, so readers stop carrying it forward
+pub const EXITED_STATUS: &str = "Exited";
+
+/// Processes of one sample to persist, and whether they are the full sample
+#[derive(Debug)]
+pub struct ProcessDelta {
+    pub processes: Vec<ProcessInfo>,
+    /// Every live process is included; readers carry the processes of later delta
+    /// samples forward from here
+    pub keyframe: bool,
+}
+
+/// Cuts each sample down to the processes whose metrics changed by more than
+/// `epsilon` since their last persisted row, with a full keyframe every
+/// `keyframe_interval`
+#[derive(Debug)]
+pub struct ProcessDeltaTracker {
+    epsilon: Option<f64>,
+    keyframe_interval: chrono::Duration,
+    last_keyframe: Option<DateTime<Utc>>,
+    persisted: HashMap<u32, ProcessInfo>,
+}
+
+impl ProcessDeltaTracker {
+    pub fn new(epsilon: Option<f64>, keyframe_interval: chrono::Duration) -> Self {
+        Self {
+            epsilon,
+            keyframe_interval,
+            last_keyframe: None,
+            persisted: HashMap::new(),
+        }
+    }
+
+    pub fn from_settings(settings: &Settings) -> Self {
+        Self::new(
+            settings.process_delta_epsilon,
+            chrono::Duration::minutes(settings.process_keyframe_minutes as i64),
+        )
+    }
+
+    /// Make the next sample a keyframe, e.g. after it failed to persist
+    pub fn reset(&mut self) {
+        self.last_keyframe = None;
+        self.persisted.clear();
+    }
+
+    /// Whether `current` differs enough from the last persisted row to be written
+    fn changed(epsilon: f64, last: &ProcessInfo, current: &ProcessInfo) -> bool {
+        let cpu_delta = (current.cpu_percent - last.cpu_percent).abs() as f64;
+        let memory_delta = current.memory_bytes.abs_diff(last.memory_bytes) as f64;
+        cpu_delta > epsilon
+            || memory_delta > last.memory_bytes as f64 * epsilon / 100.0
+            || current.name != last.name
+            || current.status != last.status
+            || current.parent_pid != last.parent_pid
+            || current.unit != last.unit
+    }
+
+    pub fn select(
+        &mut self,
+        processes: Vec<ProcessInfo>,
+        timestamp: DateTime<Utc>,
+    ) -> ProcessDelta {
+        let Some(epsilon) = self.epsilon else {
+            return ProcessDelta {
+                processes,
+                keyframe: true,
+            };
+        };
+
+        let keyframe = self
+            .last_keyframe
+            .is_none_or(|last| timestamp - last >= self.keyframe_interval);
+        if keyframe {
+            self.last_keyframe = Some(timestamp);
+            self.persisted = processes.iter().map(|p| (p.pid, p.clone())).collect();
+            return ProcessDelta {
+                processes,
+                keyframe: true,
+            };
+        }
+
+        let mut gone = std::mem::take(&mut self.persisted);
+        let mut delta = Vec::new();
+        for process in processes {
+            let last = gone.remove(&process.pid);
+            let write = last
+                .as_ref()
+                .is_none_or(|last| Self::changed(epsilon, last, &process));
+            if write {
+                delta.push(process.clone());
+                self.persisted.insert(process.pid, process);
+            } else if let Some(last) = last {
+                self.persisted.insert(last.pid, last);
+            }
+        }
+        for (_, mut process) in gone {
+            process.status = EXITED_STATUS.to_string();
+            process.cpu_percent = 0.0;
+            delta.push(process);
+        }
+        ProcessDelta {
+            processes: delta,
+            keyframe: false,
+        }
+    }
+}
+
+/// Whether a process was first seen or found gone
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProcessEventKind {
+    Start,
+    Exit,
+}
+
+impl ProcessEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Start => "start",
+            Self::Exit => "exit",
+        }
+    }
+}
+
+/// A process that started or exited between two samples. Processes that both start
+/// and exit within one sampling interval are never seen.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProcessEvent {
+    pub kind: ProcessEventKind,
+    pub pid: u32,
+    pub parent_pid: Option<u32>,
+    pub name: String,
+    pub cmdline: Option<String>,
+    pub uid: Option<u32>,
+    /// Login name, or the uid if the user has none
+    pub user: Option<String>,
+    pub unit: Option<String>,
+    /// From the process's runtime at the first sample that saw it
+    pub start_time: DateTime<Utc>,
+    /// For exits, the first sample that no longer found the process
+    pub end_time: Option<DateTime<Utc>>,
+}
+
+/// Seconds two start times derived from runtimes may differ by and still be the same
+/// process rather than a reused pid
+const START_TIME_SLACK_SECS: i64 = 2;
+
+/// Compares consecutive samples to find the processes that started and exited in
+/// between. The first sample only establishes what is already running.
+#[derive(Debug, Default)]
+pub struct ProcessLifecycle {
+    known: Option<HashMap<u32, (DateTime<Utc>, ProcessInfo)>>,
+}
+
+impl ProcessLifecycle {
+    fn event(
+        kind: ProcessEventKind,
+        start_time: DateTime<Utc>,
+        process: &ProcessInfo,
+        end_time: Option<DateTime<Utc>>,
+    ) -> ProcessEvent {
+        ProcessEvent {
+            kind,
+            pid: process.pid,
+            parent_pid: process.parent_pid,
+            name: process.name.clone(),
+            cmdline: process.cmdline(),
+            uid: process.uid_number(),
+            user: process.user_name(),
+            unit: process.unit.clone(),
+            start_time,
+            end_time,
+        }
+    }
+
+    pub fn observe(
+        &mut self,
+        processes: &[ProcessInfo],
+        timestamp: DateTime<Utc>,
+    ) -> Vec<ProcessEvent> {
+        let mut previous = self.known.take();
+        let mut events = Vec::new();
+        let mut known = HashMap::with_capacity(processes.len());
+        for process in processes {
+            let start_time = timestamp - chrono::Duration::seconds(process.runtime_secs as i64);
+            let seen = previous
+                .as_mut()
+                .and_then(|previous| previous.remove(&process.pid));
+            let start_time = match seen {
+                Some((seen_start, _))
+                    if (seen_start - start_time).num_seconds().abs() <= START_TIME_SLACK_SECS =>
+                {
+                    seen_start
+                }
+                Some((seen_start, seen)) => {
+                    // The pid was reused
+                    events.push(Self::event(
+                        ProcessEventKind::Exit,
+                        seen_start,
+                        &seen,
+                        Some(timestamp),
+                    ));
+                    events.push(Self::event(
+                        ProcessEventKind::Start,
+                        start_time,
+                        process,
+                        None,
+                    ));
+                    start_time
+                }
+                None if previous.is_some() => {
+                    events.push(Self::event(
+                        ProcessEventKind::Start,
+                        start_time,
+                        process,
+                        None,
+                    ));
+                    start_time
+                }
+                None => start_time,
+            };
+            known.insert(process.pid, (start_time, process.clone()));
+        }
+        for (start_time, process) in previous.into_iter().flat_map(HashMap::into_values) {
+            events.push(Self::event(
+                ProcessEventKind::Exit,
+                start_time,
+                &process,
+                Some(timestamp),
+            ));
+        }
+        self.known = Some(known);
+        events
+    }
+}
+
+/// Thread count from the `Threads:` line of /proc/PID/status
+fn proc_thread_count(pid: u32) -> Option<u32> {
+    let status = std::fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("Threads:"))
+        .and_then(|count| count.trim().parse().ok())
+}
+
+/// Entries in /proc/PID/fd
+fn proc_open_fd_count(pid: u32) -> Option<u32> {
+    let entries = std::fs::read_dir(format!("/proc/{}/fd", pid)).ok()?;
+    Some(entries.count() as u32)
+}
+
+/// Path of the process's cgroup: the unified (v2) hierarchy, else systemd's v1
+/// hierarchy
+fn proc_cgroup(pid: u32) -> Option<String> {
+    let cgroups = std::fs::read_to_string(format!("/proc/{}/cgroup", pid)).ok()?;
+    let path = |prefix: &str| {
+        cgroups
+            .lines()
+            .find_map(|line| line.strip_prefix(prefix))
+            .map(str::to_string)
+    };
+    path("0::").or_else(|| {
+        cgroups.lines().find_map(|line| {
+            line.split_once(":name=systemd:")
+                .map(|(_, p)| p.to_string())
+        })
+    })
+}
+
+/// systemd unit and container id a cgroup path places a process in. The unit is the
+/// innermost service or scope, else the innermost slice; e.g.
+/// `/system.slice/docker-<id>.scope` gives `docker-<id>.scope` and `<id>`.
+pub fn cgroup_unit(path: &str) -> (Option<String>, Option<String>) {
+    let components: Vec<&str> = path.split('/').filter(|c| !c.is_empty()).collect();
+    let innermost = |suffixes: &[&str]| {
+        components
+            .iter()
+            .rev()
+            .find(|c| suffixes.iter().any(|suffix| c.ends_with(suffix)))
+            .map(|c| c.to_string())
+    };
+    let unit = innermost(&[".service", ".scope"]).or_else(|| innermost(&[".slice"]));
+
+    let container_id = components.iter().rev().find_map(|component| {
+        let id = component.strip_suffix(".scope").unwrap_or(component);
+        let id = ["docker-", "libpod-", "cri-containerd-", "crio-"]
+            .iter()
+            .find_map(|prefix| id.strip_prefix(prefix))
+            .unwrap_or(id);
+        (id.len() >= 12 && id.chars().all(|c| c.is_ascii_hexdigit())).then(|| id.to_string())
+    });
+    (unit, container_id)
+}
+
+/// Host-wide resource usage, sampled alongside the processes
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SystemSample {
+    pub load_1: f64,
+    pub load_5: f64,
+    pub load_15: f64,
+    pub memory_total_bytes: u64,
+    pub memory_used_bytes: u64,
+    pub swap_total_bytes: u64,
+    pub swap_used_bytes: u64,
+    pub disks: Vec<DiskUsage>,
+    pub networks: Vec<NetworkThroughput>,
+    /// Empty unless sensors are collected
+    #[serde(default)]
+    pub temperatures: Vec<SensorReading>,
+    #[serde(default)]
+    pub fans: Vec<SensorReading>,
+    #[serde(default)]
+    pub batteries: Vec<BatteryStatus>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiskUsage {
+    pub mount_point: String,
+    pub total_bytes: u64,
+    pub available_bytes: u64,
+}
+
+/// Bytes moved by one interface since the previous sample, per second
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetworkThroughput {
+    pub interface: String,
+    pub rx_bytes_per_sec: f64,
+    pub tx_bytes_per_sec: f64,
+}
+
+/// Degrees Celsius of a temperature sensor, or revolutions per minute of a fan
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SensorReading {
+    pub sensor: String,
+    pub value: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatteryStatus {
+    /// Power supply name, e.g. `BAT0`
+    pub name: String,
+    pub capacity_percent: f64,
+    /// Charging or full, i.e. on external power
+    pub charging: bool,
+}
+
+impl SystemSample {
+    /// `(metric, device, value)` rows as stored in the system_metrics table. Device is
+    /// the mount point of disk metrics, the interface of network metrics, the sensor
+    /// or battery of sensor metrics, else `None`.
+    pub fn rows(&self) -> Vec<(&'static str, Option<String>, f64)> {
+        let mut rows = vec![
+            ("load_1", None, self.load_1),
+            ("load_5", None, self.load_5),
+            ("load_15", None, self.load_15),
+            ("memory_total_bytes", None, self.memory_total_bytes as f64),
+            ("memory_used_bytes", None, self.memory_used_bytes as f64),
+            ("swap_total_bytes", None, self.swap_total_bytes as f64),
+            ("swap_used_bytes", None, self.swap_used_bytes as f64),
+        ];
+        for disk in &self.disks {
+            let device = Some(disk.mount_point.clone());
+            rows.push(("disk_total_bytes", device.clone(), disk.total_bytes as f64));
+            rows.push((
+                "disk_used_bytes",
+                device,
+                disk.total_bytes.saturating_sub(disk.available_bytes) as f64,
+            ));
+        }
+        for network in &self.networks {
+            let device = Some(network.interface.clone());
+            rows.push((
+                "network_rx_bytes_per_sec",
+                device.clone(),
+                network.rx_bytes_per_sec,
+            ));
+            rows.push(("network_tx_bytes_per_sec", device, network.tx_bytes_per_sec));
+        }
+        for reading in &self.temperatures {
+            rows.push((
+                "temperature_celsius",
+                Some(reading.sensor.clone()),
+                reading.value,
+            ));
+        }
+        for reading in &self.fans {
+            rows.push(("fan_rpm", Some(reading.sensor.clone()), reading.value));
+        }
+        for battery in &self.batteries {
+            let device = Some(battery.name.clone());
+            rows.push(("battery_percent", device.clone(), battery.capacity_percent));
+            rows.push((
+                "battery_charging",
+                device,
+                f64::from(u8::from(battery.charging)),
+            ));
+        }
+        rows
+    }
+}
+
+/// Metrics a [`SystemSample`] stores, for validating API requests
+pub const SYSTEM_METRICS: &[&str] = &[
+    "load_1",
+    "load_5",
+    "load_15",
+    "memory_total_bytes",
+    "memory_used_bytes",
+    "swap_total_bytes",
+    "swap_used_bytes",
+    "disk_total_bytes",
+    "disk_used_bytes",
+    "network_rx_bytes_per_sec",
+    "network_tx_bytes_per_sec",
+    "temperature_celsius",
+    "fan_rpm",
+    "battery_percent",
+    "battery_charging",
+];
+
+const HWMON_PATH: &str = "/sys/class/hwmon";
+const POWER_SUPPLY_PATH: &str = "/sys/class/power_supply";
+
+/// Contents of a sysfs or procfs file without surrounding whitespace; None when it
+/// cannot be read
+pub(crate) fn read_trimmed(path: &Path) -> Option<String> {
+    std::fs::read_to_string(path)
+        .ok()
+        .map(|contents| contents.trim().to_string())
+}
+
+/// Fans under a hwmon class directory, named `<chip> <label>` (label defaulting to
+/// `fanN`). sysinfo only reports temperatures, so fans are read from sysfs.
+fn hwmon_fans(root: &Path) -> Vec<SensorReading> {
+    let Ok(chips) = std::fs::read_dir(root) else {
+        return Vec::new();
+    };
+    let mut fans = Vec::new();
+    for chip in chips.flatten() {
+        let chip = chip.path();
+        let chip_name = read_trimmed(&chip.join("name")).unwrap_or_default();
+        let Ok(files) = std::fs::read_dir(&chip) else {
+            continue;
+        };
+        for file in files.flatten() {
+            let file_name = file.file_name().to_string_lossy().to_string();
+            let Some(fan) = file_name
+                .strip_suffix("_input")
+                .filter(|fan| fan.starts_with("fan"))
+            else {
+                continue;
+            };
+            let Some(rpm) = read_trimmed(&file.path()).and_then(|rpm| rpm.parse().ok()) else {
+                continue;
+            };
+            let label = read_trimmed(&chip.join(format!("{}_label", fan)))
+                .unwrap_or_else(|| fan.to_string());
+            fans.push(SensorReading {
+                sensor: format!("{} {}", chip_name, label).trim().to_string(),
+                value: rpm,
+            });
+        }
+    }
+    fans.sort_by(|a, b| a.sensor.cmp(&b.sensor));
+    fans
+}
+
+/// Batteries under a power_supply class directory
+fn power_supply_batteries(root: &Path) -> Vec<BatteryStatus> {
+    let Ok(supplies) = std::fs::read_dir(root) else {
+        return Vec::new();
+    };
+    let mut batteries: Vec<BatteryStatus> = supplies
+        .flatten()
+        .filter(|supply| read_trimmed(&supply.path().join("type")).as_deref() == Some("Battery"))
+        .filter_map(|supply| {
+            let path = supply.path();
+            let capacity = read_trimmed(&path.join("capacity"))?.parse().ok()?;
+            let status = read_trimmed(&path.join("status")).unwrap_or_default();
+            Some(BatteryStatus {
+                name: supply.file_name().to_string_lossy().to_string(),
+                capacity_percent: capacity,
+                charging: status == "Charging" || status == "Full",
+            })
+        })
+        .collect();
+    batteries.sort_by(|a, b| a.name.cmp(&b.name));
+    batteries
+}
+
+/// Disk and network state kept between samples; network counters are deltas since
+/// the previous refresh
+struct SystemSampler {
+    disks: Disks,
+    networks: Networks,
+    /// Temperature sensors, when sensors are collected
+    components: Option<Components>,
+    last_refresh: Instant,
+}
+
+impl SystemSampler {
+    fn new(collect_sensors: bool) -> Self {
+        Self {
+            disks: Disks::new_with_refreshed_list(),
+            networks: Networks::new_with_refreshed_list(),
+            components: collect_sensors.then(Components::new_with_refreshed_list),
+            last_refresh: Instant::now(),
+        }
+    }
+
+    fn sample(&mut self, sys: &mut System) -> SystemSample {
+        sys.refresh_memory();
+        self.disks.refresh(true);
+        self.networks.refresh(true);
+        let temperatures = match &mut self.components {
+            Some(components) => {
+                components.refresh(true);
+                components
+                    .iter()
+                    .filter_map(|component| {
+                        Some(SensorReading {
+                            sensor: component.label().to_string(),
+                            value: component.temperature()? as f64,
+                        })
+                    })
+                    .collect()
+            }
+            None => Vec::new(),
+        };
+        let (fans, batteries) = if self.components.is_some() {
+            (
+                hwmon_fans(Path::new(HWMON_PATH)),
+                power_supply_batteries(Path::new(POWER_SUPPLY_PATH)),
+            )
+        } else {
+            (Vec::new(), Vec::new())
+        };
+        let elapsed = self.last_refresh.elapsed().as_secs_f64().max(0.001);
+        self.last_refresh = Instant::now();
+
+        let load = System::load_average();
+        SystemSample {
+            load_1: load.one,
+            load_5: load.five,
+            load_15: load.fifteen,
+            memory_total_bytes: sys.total_memory(),
+            memory_used_bytes: sys.used_memory(),
+            swap_total_bytes: sys.total_swap(),
+            swap_used_bytes: sys.used_swap(),
+            disks: self
+                .disks
+                .iter()
+                .map(|disk| DiskUsage {
+                    mount_point: disk.mount_point().to_string_lossy().to_string(),
+                    total_bytes: disk.total_space(),
+                    available_bytes: disk.available_space(),
+                })
+                .collect(),
+            networks: self
+                .networks
+                .iter()
+                .map(|(interface, data)| NetworkThroughput {
+                    interface: interface.clone(),
+                    rx_bytes_per_sec: data.received() as f64 / elapsed,
+                    tx_bytes_per_sec: data.transmitted() as f64 / elapsed,
+                })
+                .collect(),
+            temperatures,
+            fans,
+            batteries,
+        }
+    }
 }
 
 /// Batch of process metrics with timestamp
 #[derive(Debug, Clone)]
 pub struct ProcessMetricsBatch {
     pub processes: Vec<ProcessInfo>,
+    /// Host-wide usage at the same time
+    pub system: Option<SystemSample>,
+    /// GPU usage at the same time, when GPUs are collected
+    pub gpu: Option<GpuSample>,
+    /// Processes that started or exited since the previous batch
+    pub events: Vec<ProcessEvent>,
     pub timestamp: DateTime<Utc>,
 }
 
@@ -84,19 +778,37 @@ impl ProcessMonitor {
     }
 
     /// Start background collection task (run once at startup)
-    /// Spawns a dedicated thread with its own tokio runtime for the collection loop
-    pub fn start_collection(&self, interval_secs: u64) -> std::thread::JoinHandle<()> {
+    /// Spawns a dedicated thread under `supervisor`, restarted should it panic, with
+    /// its own tokio runtime for the collection loop.
+    /// `collect_sensors` adds temperatures, fans and batteries to the system samples,
+    /// `collect_gpu` adds GPU samples to the batches.
+    pub fn start_collection(
+        &self,
+        interval_secs: u64,
+        collect_sensors: bool,
+        collect_gpu: bool,
+        supervisor: &Supervisor,
+    ) -> std::thread::JoinHandle<()> {
         let system = self.system.clone();
         let snapshot = self.snapshot.clone();
         let metrics_tx = self.metrics_tx.clone();
         let shutdown_signal = self.shutdown_signal.clone();
+        let supervisor = supervisor
+            .clone()
+            .with_shared_lock(&system)
+            .with_shared_lock(&snapshot);
+        let mut sampler = SystemSampler::new(collect_sensors);
+        let mut gpus = collect_gpu.then(GpuCollector::default);
+        let mut lifecycle = ProcessLifecycle::default();
+        let mut last_refresh = Instant::now();
 
-        std::thread::spawn(move || {
+        supervisor.spawn("process monitor", move |heartbeat| {
             // Create a local tokio runtime for this thread
             let rt = tokio::runtime::Runtime::new().expect("Failed to create tokio runtime");
 
-            rt.block_on(async move {
+            rt.block_on(async {
                 loop {
+                    heartbeat.idle();
                     if shutdown_signal.load(Ordering::Relaxed) {
                         log::info!("Process monitor shutting down");
                         break;
@@ -118,39 +830,64 @@ impl ProcessMonitor {
                         break;
                     }
 
+                    heartbeat.busy();
                     let mut sys = system.lock().unwrap();
                     sys.refresh_processes(ProcessesToUpdate::All, true);
+                    let elapsed = last_refresh.elapsed().as_secs_f64().max(0.001);
+                    last_refresh = Instant::now();
 
                     // Collect snapshot
                     let processes: Vec<ProcessInfo> = sys
                         .processes()
                         .iter()
-                        .map(|(pid, process)| ProcessInfo {
-                            pid: pid.as_u32(),
-                            name: process.name().to_string_lossy().to_string(),
-                            cpu_percent: process.cpu_usage(),
-                            memory_bytes: process.memory(),
-                            user_id: process.user_id().map(|u| format!("{:?}", u)),
-                            runtime_secs: process.run_time(),
-                            cmd: process
-                                .cmd()
-                                .iter()
-                                .map(|s| s.to_string_lossy().to_string())
-                                .collect(),
-                            virtual_memory_bytes: process.virtual_memory(),
-                            status: process.status().to_string(),
-                            parent_pid: process.parent().map(|p| p.as_u32()),
+                        .map(|(pid, process)| {
+                            let (unit, container_id) = proc_cgroup(pid.as_u32())
+                                .map(|path| cgroup_unit(&path))
+                                .unwrap_or_default();
+                            ProcessInfo {
+                                pid: pid.as_u32(),
+                                name: process.name().to_string_lossy().to_string(),
+                                cpu_percent: process.cpu_usage(),
+                                memory_bytes: process.memory(),
+                                user_id: process.user_id().map(|u| format!("{:?}", u)),
+                                runtime_secs: process.run_time(),
+                                cmd: process
+                                    .cmd()
+                                    .iter()
+                                    .map(|s| s.to_string_lossy().to_string())
+                                    .collect(),
+                                virtual_memory_bytes: process.virtual_memory(),
+                                status: process.status().to_string(),
+                                parent_pid: process.parent().map(|p| p.as_u32()),
+                                read_bytes_per_sec: process.disk_usage().read_bytes as f64
+                                    / elapsed,
+                                write_bytes_per_sec: process.disk_usage().written_bytes as f64
+                                    / elapsed,
+                                num_threads: proc_thread_count(pid.as_u32()),
+                                open_fd_count: proc_open_fd_count(pid.as_u32()),
+                                unit,
+                                container_id,
+                            }
                         })
                         .collect();
 
+                    let system_sample = sampler.sample(&mut sys);
+                    drop(sys);
+                    let gpu_sample = gpus.as_mut().map(GpuCollector::sample);
+
                     *snapshot.lock().unwrap() = processes.clone();
+                    let timestamp = Utc::now();
+                    let events = lifecycle.observe(&processes, timestamp);
 
                     // Send batch to persistence channel if available
                     let tx = metrics_tx.lock().unwrap().as_ref().cloned();
                     if let Some(tx) = tx {
                         let batch = ProcessMetricsBatch {
                             processes: processes.clone(),
-                            timestamp: Utc::now(),
+                            system: Some(system_sample),
+                            gpu: gpu_sample,
+                            events,
+                            timestamp,
                         };
 
                         log::debug!(
@@ -170,6 +907,14 @@ impl ProcessMonitor {
         })
     }
 
+    /// Batches waiting for the persistence receiver and the channel's capacity, or
+    /// `None` when no receiver is attached
+    pub fn metrics_backlog(&self) -> Option<(usize, usize)> {
+        let tx = self.metrics_tx.lock().unwrap();
+        tx.as_ref()
+            .map(|tx| (tx.max_capacity() - tx.capacity(), tx.max_capacity()))
+    }
+
     /// Close the metrics sender so the receiver can exit cleanly.
     pub fn shutdown_metrics_channel(&self) {
         *self.metrics_tx.lock().unwrap() = None;
@@ -192,4 +937,329 @@ mod tests {
         // Initially empty before collection starts
         assert!(snapshot.is_empty());
     }
+
+    #[test]
+    fn test_proc_counts_of_own_process() {
+        let pid = std::process::id();
+        // The test harness runs tests on several threads
+        assert!(proc_thread_count(pid).unwrap() >= 1);
+        assert!(proc_open_fd_count(pid).unwrap() >= 3);
+        assert_eq!(proc_thread_count(u32::MAX), None);
+    }
+
+    #[test]
+    fn test_cgroup_unit() {
+        assert_eq!(
+            cgroup_unit("/system.slice/nginx.service"),
+            (Some("nginx.service".to_string()), None)
+        );
+        assert_eq!(
+            cgroup_unit(
+                "/user.slice/user-1000.slice/user@1000.service/app.slice/app-firefox.scope"
+            ),
+            (Some("app-firefox.scope".to_string()), None)
+        );
+        assert_eq!(
+            cgroup_unit("/init.scope"),
+            (Some("init.scope".to_string()), None)
+        );
+        assert_eq!(cgroup_unit("/"), (None, None));
+
+        let id = "4f1c2a9e8b7d6c5f4e3d2c1b0a9f8e7d6c5b4a3f2e1d0c9b8a7f6e5d4c3b2a1f";
+        assert_eq!(
+            cgroup_unit(&format!("/system.slice/docker-{}.scope", id)),
+            (Some(format!("docker-{}.scope", id)), Some(id.to_string()))
+        );
+        // cgroup v1 Docker and Kubernetes paths carry no unit
+        assert_eq!(
+            cgroup_unit(&format!("/kubepods/burstable/pod1234-abcd/{}", id)),
+            (None, Some(id.to_string()))
+        );
+    }
+
+    #[test]
+    fn test_process_filter() {
+        let process =
+            |pid: u32, name: &str, uid: u32, cpu_percent: f32, memory_bytes: u64| ProcessInfo {
+                pid,
+                name: name.to_string(),
+                cpu_percent,
+                memory_bytes,
+                user_id: Some(format!("Uid({})", uid)),
+                runtime_secs: 0,
+                cmd: Vec::new(),
+                virtual_memory_bytes: 0,
+                status: "Sleep".to_string(),
+                parent_pid: None,
+                read_bytes_per_sec: 0.0,
+                write_bytes_per_sec: 0.0,
+                num_threads: None,
+                open_fd_count: None,
+                unit: None,
+                container_id: None,
+            };
+        let processes = vec![
+            process(2, "kworker/0:1", 0, 0.0, 0),
+            process(100, "postgres", 110, 1.5, 200_000_000),
+            process(200, "nginx", 33, 12.0, 10_000_000),
+            process(300, "bash", 1000, 0.1, 4_000_000),
+        ];
+        let pids = |filter: &ProcessFilter| -> Vec<u32> {
+            filter
+                .apply(processes.clone())
+                .iter()
+                .map(|p| p.pid)
+                .collect()
+        };
+
+        assert_eq!(pids(&ProcessFilter::default()), [2, 100, 200, 300]);
+
+        // Either threshold keeps a process
+        let thresholds = ProcessFilter {
+            min_cpu_percent: 5.0,
+            min_memory_bytes: 100_000_000,
+            ..Default::default()
+        };
+        assert_eq!(pids(&thresholds), [100, 200]);
+
+        let allowlists = ProcessFilter {
+            names: vec!["postgres".to_string(), "bash".to_string()],
+            users: vec!["1000".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(pids(&allowlists), [300]);
+
+        let top = ProcessFilter {
+            top_n: 2,
+            ..Default::default()
+        };
+        assert_eq!(pids(&top), [200, 100]);
+    }
+
+    #[test]
+    fn test_process_delta_tracker() {
+        let process = |pid: u32, cpu_percent: f32, memory_bytes: u64| ProcessInfo {
+            pid,
+            name: format!("proc{}", pid),
+            cpu_percent,
+            memory_bytes,
+            user_id: None,
+            runtime_secs: 0,
+            cmd: Vec::new(),
+            virtual_memory_bytes: 0,
+            status: "Sleep".to_string(),
+            parent_pid: None,
+            read_bytes_per_sec: 0.0,
+            write_bytes_per_sec: 0.0,
+            num_threads: None,
+            open_fd_count: None,
+            unit: None,
+            container_id: None,
+        };
+        let start = Utc::now();
+        let at = |secs: i64| start + chrono::Duration::seconds(secs);
+        let pids = |delta: &ProcessDelta| -> Vec<(u32, String)> {
+            let mut pids: Vec<(u32, String)> = delta
+                .processes
+                .iter()
+                .map(|p| (p.pid, p.status.clone()))
+                .collect();
+            pids.sort();
+            pids
+        };
+
+        let mut tracker = ProcessDeltaTracker::new(Some(1.0), chrono::Duration::minutes(5));
+        let first = tracker.select(vec![process(1, 0.0, 1000), process(2, 5.0, 1000)], at(0));
+        assert!(first.keyframe);
+        assert_eq!(first.processes.len(), 2);
+
+        // pid 1 drifts within epsilon, pid 2 changes, pid 3 is new
+        let delta = tracker.select(
+            vec![
+                process(1, 0.5, 1005),
+                process(2, 9.0, 1000),
+                process(3, 0.0, 10),
+            ],
+            at(5),
+        );
+        assert!(!delta.keyframe);
+        assert_eq!(
+            pids(&delta),
+            [(2, "Sleep".to_string()), (3, "Sleep".to_string())]
+        );
+
+        // Drift is measured against the last written row; pid 3 exited
+        let delta = tracker.select(vec![process(1, 1.5, 1005), process(2, 9.0, 1000)], at(10));
+        assert_eq!(
+            pids(&delta),
+            [(1, "Sleep".to_string()), (3, EXITED_STATUS.to_string())]
+        );
+
+        let keyframe = tracker.select(vec![process(1, 1.5, 1005)], at(300));
+        assert!(keyframe.keyframe);
+        assert_eq!(keyframe.processes.len(), 1);
+
+        let mut every_sample = ProcessDeltaTracker::new(None, chrono::Duration::minutes(5));
+        for secs in [0, 5] {
+            let delta = every_sample.select(vec![process(1, 0.0, 1000)], at(secs));
+            assert!(delta.keyframe);
+            assert_eq!(delta.processes.len(), 1);
+        }
+    }
+
+    #[test]
+    fn test_process_lifecycle_events() {
+        let process = |pid: u32, name: &str, runtime_secs: u64| ProcessInfo {
+            pid,
+            name: name.to_string(),
+            cpu_percent: 0.0,
+            memory_bytes: 0,
+            user_id: Some("Uid(1000)".to_string()),
+            runtime_secs,
+            cmd: vec![name.to_string(), "--serve".to_string()],
+            virtual_memory_bytes: 0,
+            status: "Run".to_string(),
+            parent_pid: Some(1),
+            read_bytes_per_sec: 0.0,
+            write_bytes_per_sec: 0.0,
+            num_threads: None,
+            open_fd_count: None,
+            unit: Some("app.service".to_string()),
+            container_id: None,
+        };
+        let start = Utc::now();
+        let at = |secs: i64| start + chrono::Duration::seconds(secs);
+        let kinds = |events: &[ProcessEvent]| -> Vec<(ProcessEventKind, u32)> {
+            let mut kinds: Vec<_> = events.iter().map(|e| (e.kind, e.pid)).collect();
+            kinds.sort_by_key(|(kind, pid)| (*pid, kind.as_str()));
+            kinds
+        };
+
+        let mut lifecycle = ProcessLifecycle::default();
+        // Already running processes are not reported as starting
+        assert!(
+            lifecycle
+                .observe(&[process(10, "app", 100)], at(0))
+                .is_empty()
+        );
+
+        let events = lifecycle.observe(&[process(10, "app", 105), process(11, "app", 2)], at(5));
+        assert_eq!(kinds(&events), [(ProcessEventKind::Start, 11)]);
+        assert_eq!(events[0].start_time, at(3));
+        assert_eq!(events[0].cmdline.as_deref(), Some("app --serve"));
+        assert_eq!(events[0].uid, Some(1000));
+
+        // pid 11 crashed and its pid went to a new process
+        let events = lifecycle.observe(&[process(10, "app", 110), process(11, "app", 1)], at(10));
+        assert_eq!(
+            kinds(&events),
+            [(ProcessEventKind::Exit, 11), (ProcessEventKind::Start, 11)]
+        );
+        let exit = events
+            .iter()
+            .find(|e| e.kind == ProcessEventKind::Exit)
+            .unwrap();
+        assert_eq!((exit.start_time, exit.end_time), (at(3), Some(at(10))));
+
+        let events = lifecycle.observe(&[process(11, "app", 6)], at(15));
+        assert_eq!(kinds(&events), [(ProcessEventKind::Exit, 10)]);
+        assert_eq!(events[0].start_time, at(-100));
+    }
+
+    #[test]
+    fn test_system_sample_rows() {
+        let mut sys = System::new();
+        let sample = SystemSampler::new(false).sample(&mut sys);
+        assert!(sample.temperatures.is_empty());
+        assert!(sample.memory_total_bytes > 0);
+
+        let sample = SystemSample {
+            memory_used_bytes: 512,
+            disks: vec![DiskUsage {
+                mount_point: "/".to_string(),
+                total_bytes: 1000,
+                available_bytes: 250,
+            }],
+            networks: vec![NetworkThroughput {
+                interface: "eth0".to_string(),
+                rx_bytes_per_sec: 10.0,
+                tx_bytes_per_sec: 2.5,
+            }],
+            temperatures: vec![SensorReading {
+                sensor: "coretemp Package id 0".to_string(),
+                value: 71.0,
+            }],
+            batteries: vec![BatteryStatus {
+                name: "BAT0".to_string(),
+                capacity_percent: 80.0,
+                charging: true,
+            }],
+            ..Default::default()
+        };
+        let rows = sample.rows();
+        assert!(rows.contains(&("memory_used_bytes", None, 512.0)));
+        assert!(rows.contains(&("disk_used_bytes", Some("/".to_string()), 750.0)));
+        assert!(rows.contains(&("network_tx_bytes_per_sec", Some("eth0".to_string()), 2.5)));
+        assert!(rows.contains(&(
+            "temperature_celsius",
+            Some("coretemp Package id 0".to_string()),
+            71.0
+        )));
+        assert!(rows.contains(&("battery_charging", Some("BAT0".to_string()), 1.0)));
+        assert!(
+            rows.iter()
+                .all(|(metric, _, _)| SYSTEM_METRICS.contains(metric))
+        );
+    }
+
+    #[test]
+    fn test_sysfs_fans_and_batteries() {
+        let dir = tempfile::tempdir().unwrap();
+        let hwmon = dir.path().join("hwmon");
+        let chip = hwmon.join("hwmon3");
+        std::fs::create_dir_all(&chip).unwrap();
+        std::fs::write(chip.join("name"), "thinkpad\n").unwrap();
+        std::fs::write(chip.join("fan1_input"), "2300\n").unwrap();
+        std::fs::write(chip.join("fan2_input"), "1800\n").unwrap();
+        std::fs::write(chip.join("fan2_label"), "GPU\n").unwrap();
+        std::fs::write(chip.join("temp1_input"), "45000\n").unwrap();
+        let fans: Vec<_> = hwmon_fans(&hwmon)
+            .into_iter()
+            .map(|fan| (fan.sensor, fan.value))
+            .collect();
+        assert_eq!(
+            fans,
+            [
+                ("thinkpad GPU".to_string(), 1800.0),
+                ("thinkpad fan1".to_string(), 2300.0)
+            ]
+        );
+
+        let supplies = dir.path().join("power_supply");
+        for (name, kind, capacity, status) in [
+            ("AC", "Mains", None, None),
+            ("BAT0", "Battery", Some("57"), Some("Discharging")),
+            ("BAT1", "Battery", Some("100"), Some("Full")),
+        ] {
+            let supply = supplies.join(name);
+            std::fs::create_dir_all(&supply).unwrap();
+            std::fs::write(supply.join("type"), kind).unwrap();
+            if let (Some(capacity), Some(status)) = (capacity, status) {
+                std::fs::write(supply.join("capacity"), capacity).unwrap();
+                std::fs::write(supply.join("status"), status).unwrap();
+            }
+        }
+        let batteries: Vec<_> = power_supply_batteries(&supplies)
+            .into_iter()
+            .map(|battery| (battery.name, battery.capacity_percent, battery.charging))
+            .collect();
+        assert_eq!(
+            batteries,
+            [
+                ("BAT0".to_string(), 57.0, false),
+                ("BAT1".to_string(), 100.0, true)
+            ]
+        );
+        assert!(hwmon_fans(&dir.path().join("missing")).is_empty());
+    }
 }
@@ -0,0 +1,760 @@
+//! Read queries shared by [`crate::duckdb_buffer::DuckDBBuffer`] and the web
+//! server's [`crate::read_pool::ReadPool`] connections.
+
+use crate::duckdb_buffer::{
+    DayRowCount, ExtraFieldUsage, INGEST_SEQ_COLUMN, IndexStorage, LOG_SUMMARIES_TABLE, LogSummary,
+    PROCESS_ROLLUP_TABLE, ProcessHistoryPoint, ProcessMetricRecord, SOURCE_CONNECTED_WITHIN,
+    SOURCE_RATE_WINDOW, SOURCES_TABLE, SYSTEM_METRICS_TABLE, SqlParam, StorageStats,
+    SystemHistoryPoint, TableStorage, UnitUsage,
+};
+use crate::parquet_writer::sql_path;
+use crate::peer_replication::SourceStatus;
+use crate::process_monitor::EXITED_STATUS;
+use crate::sql_trace::trace_sql;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use duckdb::{Connection, Row, params, params_from_iter};
+use std::path::Path;
+
+pub(crate) fn get_latest_process_timestamp(conn: &Connection) -> Result<Option<String>> {
+    let sql = "SELECT CAST(MAX(timestamp) AS VARCHAR) FROM process_metrics";
+    trace_sql(sql);
+    let mut stmt = conn.prepare(sql)?;
+    let mut rows = stmt.query([])?;
+    if let Some(row) = rows.next()? {
+        let ts: Option<String> = row.get(0)?;
+        Ok(ts)
+    } else {
+        Ok(None)
+    }
+}
+
+/// Timestamp of the newest process sample taken at or before `at`
+pub(crate) fn get_process_timestamp_at(
+    conn: &Connection,
+    at: DateTime<Utc>,
+) -> Result<Option<String>> {
+    let sql = "SELECT CAST(MAX(timestamp) AS VARCHAR) FROM process_metrics WHERE timestamp <= ?";
+    trace_sql(sql);
+    let mut stmt = conn.prepare(sql)?;
+    let ts: Option<String> = stmt.query_row([at.to_rfc3339()], |row| row.get(0))?;
+    Ok(ts)
+}
+
+/// SQL bound of the rows a process sample at `at` is rebuilt from: the newest keyframe
+/// at or before it, or every earlier row if retention removed that keyframe
+fn keyframe_bound(at: &str) -> String {
+    format!(
+        "COALESCE(
+             (SELECT MAX(timestamp) FROM process_metrics WHERE keyframe AND timestamp <= {at}),
+             '-infinity'::TIMESTAMP)"
+    )
+}
+
+/// The processes alive at sample `timestamp`: each process's newest row since the
+/// last keyframe, unless that row records its exit
+pub(crate) fn get_process_metrics_for_timestamp(
+    conn: &Connection,
+    timestamp: &str,
+) -> Result<Vec<ProcessMetricRecord>> {
+    let sql = format!(
+        "SELECT CAST(CAST($1 AS TIMESTAMP) AS VARCHAR), pid, name, cpu_usage, mem_usage, user, runtime,
+                cmdline, virtual_memory, status, parent_pid, read_bytes_per_sec,
+                write_bytes_per_sec, num_threads, open_fd_count, unit, container_id, uid
+         FROM (
+             SELECT * FROM process_metrics
+             WHERE timestamp <= $1 AND timestamp >= {bound}
+             QUALIFY ROW_NUMBER() OVER (PARTITION BY pid ORDER BY timestamp DESC) = 1
+         )
+         WHERE status IS DISTINCT FROM '{exited}'",
+        bound = keyframe_bound("$1"),
+        exited = EXITED_STATUS,
+    );
+    trace_sql(&sql);
+    let mut stmt = conn.prepare(&sql)?;
+
+    let rows = stmt.query_map([timestamp], |row| {
+        Ok(ProcessMetricRecord {
+            timestamp: row.get(0)?,
+            pid: row.get::<_, i64>(1)? as u32,
+            name: row.get(2)?,
+            cpu_usage: row.get::<_, f64>(3)? as f32,
+            mem_usage: row.get(4)?,
+            user: row.get(5)?,
+            runtime: row.get::<_, i64>(6)? as u64,
+            cmdline: row.get(7)?,
+            virtual_memory: row.get::<_, Option<f64>>(8)?.unwrap_or(0.0),
+            status: row.get(9)?,
+            parent_pid: row.get::<_, Option<i32>>(10)?.map(|v| v as u32),
+            // Samples taken before migration 011 have no I/O rates
+            read_bytes_per_sec: row.get::<_, Option<f64>>(11)?.unwrap_or(0.0),
+            write_bytes_per_sec: row.get::<_, Option<f64>>(12)?.unwrap_or(0.0),
+            num_threads: row.get::<_, Option<i64>>(13)?.map(|v| v as u32),
+            open_fd_count: row.get::<_, Option<i64>>(14)?.map(|v| v as u32),
+            unit: row.get(15)?,
+            container_id: row.get(16)?,
+            uid: row.get::<_, Option<i64>>(17)?.map(|v| v as u32),
+        })
+    })?;
+
+    let mut out = Vec::new();
+    for row in rows {
+        out.push(row?);
+    }
+    Ok(out)
+}
+
+/// Every sample from `start` (an SQL expression) to `$2` of the processes matching
+/// `filter`, with processes left out of delta samples carried forward from their last
+/// row. `filter` may use `$3`.
+pub(crate) fn process_samples_sql(start: &str, filter: &str) -> String {
+    format!(
+        "WITH recent AS (
+             SELECT * FROM process_metrics
+             WHERE timestamp < $2 AND timestamp >= {bound}
+         ),
+         samples AS (
+             SELECT DISTINCT timestamp FROM recent WHERE timestamp >= {start}
+         ),
+         keyframes AS (
+             SELECT DISTINCT timestamp FROM recent WHERE keyframe
+         ),
+         sample_keyframes AS (
+             SELECT s.timestamp, k.timestamp AS keyframe_ts
+             FROM samples s ASOF LEFT JOIN keyframes k ON s.timestamp >= k.timestamp
+         ),
+         candidates AS (
+             SELECT * FROM recent WHERE pid IN (SELECT pid FROM recent WHERE {filter})
+         ),
+         grid AS (
+             SELECT sk.timestamp, sk.keyframe_ts, p.pid
+             FROM sample_keyframes sk CROSS JOIN (SELECT DISTINCT pid FROM candidates) p
+         ),
+         filled AS (
+             SELECT c.* EXCLUDE (timestamp), g.timestamp
+             FROM grid g
+             ASOF JOIN candidates c ON g.pid = c.pid AND g.timestamp >= c.timestamp
+             WHERE g.keyframe_ts IS NULL OR c.timestamp >= g.keyframe_ts
+         )
+         SELECT * FROM filled
+         WHERE status IS DISTINCT FROM '{exited}' AND {filter}",
+        bound = keyframe_bound(start),
+        start = start,
+        exited = EXITED_STATUS,
+        filter = filter,
+    )
+}
+
+/// Per-sample sums of the processes matching `filter` from `start` to `$2`, in the
+/// columns of [`PROCESS_ROLLUP_TABLE`] with one sample per row
+pub(crate) fn process_sums_sql(start: &str, filter: &str) -> String {
+    format!(
+        "SELECT timestamp, 1 AS samples, processes, cpu AS avg_cpu, cpu AS max_cpu,
+                mem AS avg_mem, mem AS max_mem, threads AS avg_threads,
+                threads AS max_threads, fds AS avg_fds, fds AS max_fds
+         FROM (
+             SELECT timestamp, COUNT(*) AS processes, SUM(cpu_usage) AS cpu,
+                    SUM(mem_usage) AS mem,
+                    CAST(COALESCE(SUM(num_threads), 0) AS DOUBLE) AS threads,
+                    CAST(COALESCE(SUM(open_fd_count), 0) AS DOUBLE) AS fds
+             FROM ({samples})
+             GROUP BY timestamp
+         )",
+        samples = process_samples_sql(start, filter),
+    )
+}
+
+/// Series of the processes named `$3` between `$1` and `$2`: per-minute rollups where
+/// they exist, then raw samples. Rows carry `samples` to weight averages by.
+pub(crate) fn process_name_series_sql() -> String {
+    let raw_start = format!(
+        "GREATEST(CAST($1 AS TIMESTAMP), COALESCE(
+             (SELECT MAX(minute) + INTERVAL 1 MINUTE FROM {rollup}), CAST($1 AS TIMESTAMP)))",
+        rollup = PROCESS_ROLLUP_TABLE
+    );
+    format!(
+        "SELECT minute AS timestamp, samples, processes, avg_cpu, max_cpu, avg_mem, max_mem,
+                avg_threads, max_threads, avg_fds, max_fds
+         FROM {rollup}
+         WHERE name = $3 AND minute >= $1 AND minute < $2
+         UNION ALL
+         {raw}",
+        rollup = PROCESS_ROLLUP_TABLE,
+        raw = process_sums_sql(&raw_start, "name = $3"),
+    )
+}
+
+/// CPU and memory of the processes with `pid`, or else named `name`, or else in the
+/// systemd `unit`, in bins of `bin_secs` between `start` and `end`. Name lookups in
+/// whole-minute bins read the per-minute rollups, which outlive raw samples.
+pub(crate) fn get_process_history(
+    conn: &Connection,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    pid: Option<u32>,
+    name: Option<&str>,
+    unit: Option<&str>,
+    bin_secs: i64,
+) -> Result<Vec<ProcessHistoryPoint>> {
+    let mut params = vec![
+        SqlParam::Text(start.to_rfc3339()),
+        SqlParam::Text(end.to_rfc3339()),
+    ];
+    let series = match (pid, name, unit) {
+        (Some(pid), _, _) => {
+            params.push(SqlParam::Int(pid as i32));
+            process_sums_sql("$1", "pid = $3")
+        }
+        (None, Some(name), _) => {
+            params.push(SqlParam::Text(name.to_string()));
+            if bin_secs % 60 == 0 {
+                process_name_series_sql()
+            } else {
+                process_sums_sql("$1", "name = $3")
+            }
+        }
+        (None, None, Some(unit)) => {
+            params.push(SqlParam::Text(unit.to_string()));
+            process_sums_sql("$1", "unit = $3")
+        }
+        (None, None, None) => anyhow::bail!("Process history needs a pid, name or unit"),
+    };
+    let sql = format!(
+        "SELECT CAST(to_timestamp(floor(epoch(timestamp) / {bin}) * {bin}) AS VARCHAR) AS time_bin,
+                SUM(avg_cpu * samples) / SUM(samples), MAX(max_cpu),
+                SUM(avg_mem * samples) / SUM(samples), MAX(max_mem), MAX(processes),
+                SUM(avg_threads * samples) / SUM(samples), MAX(max_threads),
+                SUM(avg_fds * samples) / SUM(samples), MAX(max_fds)
+         FROM ({series})
+         GROUP BY 1
+         ORDER BY 1",
+        bin = bin_secs,
+        series = series,
+    );
+    trace_sql(&sql);
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt.query_map(params_from_iter(params.iter()), |row| {
+        Ok(ProcessHistoryPoint {
+            time_bin: row.get(0)?,
+            avg_cpu_usage: row.get(1)?,
+            max_cpu_usage: row.get(2)?,
+            avg_mem_usage: row.get(3)?,
+            max_mem_usage: row.get(4)?,
+            processes: row.get(5)?,
+            avg_threads: row.get(6)?,
+            max_threads: row.get(7)?,
+            avg_open_fds: row.get(8)?,
+            max_open_fds: row.get(9)?,
+        })
+    })?;
+
+    let mut out = Vec::new();
+    for row in rows {
+        out.push(row?);
+    }
+    Ok(out)
+}
+
+/// System metrics binned by `bin_secs`, per metric and device, optionally only the
+/// given metrics and one device
+pub(crate) fn get_system_history(
+    conn: &Connection,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    metrics: &[&str],
+    device: Option<&str>,
+    bin_secs: i64,
+) -> Result<Vec<SystemHistoryPoint>> {
+    let mut params = vec![
+        SqlParam::Text(start.to_rfc3339()),
+        SqlParam::Text(end.to_rfc3339()),
+    ];
+    let mut filter = String::new();
+    if !metrics.is_empty() {
+        filter.push_str(&format!(
+            " AND metric IN ({})",
+            vec!["?"; metrics.len()].join(", ")
+        ));
+        params.extend(metrics.iter().map(|m| SqlParam::Text(m.to_string())));
+    }
+    if let Some(device) = device {
+        filter.push_str(" AND device = ?");
+        params.push(SqlParam::Text(device.to_string()));
+    }
+    let sql = format!(
+        "SELECT metric, COALESCE(device, ''),
+                CAST(to_timestamp(floor(epoch(timestamp) / {bin}) * {bin}) AS VARCHAR) AS time_bin,
+                AVG(value), MAX(value)
+         FROM {table}
+         WHERE timestamp >= ? AND timestamp < ?{filter}
+         GROUP BY 1, 2, 3
+         ORDER BY 1, 2, 3",
+        bin = bin_secs,
+        table = SYSTEM_METRICS_TABLE,
+        filter = filter,
+    );
+    trace_sql(&sql);
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt.query_map(params_from_iter(params.iter()), |row| {
+        Ok(SystemHistoryPoint {
+            metric: row.get(0)?,
+            device: row.get(1)?,
+            time_bin: row.get(2)?,
+            avg: row.get(3)?,
+            max: row.get(4)?,
+        })
+    })?;
+
+    let mut out = Vec::new();
+    for row in rows {
+        out.push(row?);
+    }
+    Ok(out)
+}
+
+pub(crate) fn get_storage_stats(conn: &Connection) -> Result<StorageStats> {
+    trace_sql("SELECT COUNT(*) FROM journal_logs");
+    let journal_log_count: i64 = conn
+        .prepare("SELECT COUNT(*) FROM journal_logs")?
+        .query_row([], |row| row.get(0))
+        .unwrap_or(0);
+
+    trace_sql("SELECT COUNT(*) FROM process_metrics");
+    let process_metric_count: i64 = conn
+        .prepare("SELECT COUNT(*) FROM process_metrics")?
+        .query_row([], |row| row.get(0))
+        .unwrap_or(0);
+
+    trace_sql("SELECT MIN(timestamp) FROM journal_logs");
+    let oldest_log_timestamp: Option<String> = conn
+        .prepare("SELECT MIN(timestamp) FROM journal_logs")?
+        .query_row([], |row| row.get(0))
+        .ok();
+
+    trace_sql("SELECT MAX(timestamp) FROM journal_logs");
+    let newest_log_timestamp: Option<String> = conn
+        .prepare("SELECT MAX(timestamp) FROM journal_logs")?
+        .query_row([], |row| row.get(0))
+        .ok();
+
+    Ok(StorageStats {
+        journal_log_count,
+        process_metric_count,
+        oldest_log_timestamp,
+        newest_log_timestamp,
+        tables: get_table_storage(conn).unwrap_or_default(),
+        rows_per_day: get_rows_per_day(conn).unwrap_or_default(),
+        top_units: get_top_units(conn, TOP_UNITS).unwrap_or_default(),
+        indexes: get_indexes(conn).unwrap_or_default(),
+        index_memory_bytes: get_index_memory_bytes(conn).unwrap_or(0),
+    })
+}
+
+/// Number of units reported in [`StorageStats::top_units`]
+const TOP_UNITS: usize = 10;
+
+fn get_table_storage(conn: &Connection) -> Result<Vec<TableStorage>> {
+    let sql =
+        "SELECT block_size FROM pragma_database_size() WHERE database_name = current_database()";
+    trace_sql(sql);
+    let block_size: i64 = conn.query_row(sql, [], |row| row.get(0))?;
+
+    let sql = "SELECT table_name, estimated_size FROM duckdb_tables() \
+               WHERE database_name = current_database() ORDER BY table_name";
+    trace_sql(sql);
+    let mut stmt = conn.prepare(sql)?;
+    let tables = stmt
+        .query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, Option<i64>>(1)?))
+        })?
+        .collect::<duckdb::Result<Vec<_>>>()?;
+
+    let mut out = Vec::with_capacity(tables.len());
+    for (table_name, estimated_rows) in tables {
+        // pragma_storage_info takes the table name as a literal, not a parameter
+        let sql = format!(
+            "SELECT COUNT(DISTINCT block_id) FROM pragma_storage_info('{}') \
+             WHERE persistent AND block_id >= 0",
+            table_name.replace('\'', "''")
+        );
+        trace_sql(&sql);
+        let blocks: i64 = conn.query_row(&sql, [], |row| row.get(0)).unwrap_or(0);
+        out.push(TableStorage {
+            table_name,
+            estimated_rows: estimated_rows.unwrap_or(0),
+            estimated_bytes: blocks * block_size,
+        });
+    }
+    Ok(out)
+}
+
+fn get_rows_per_day(conn: &Connection) -> Result<Vec<DayRowCount>> {
+    let sql = "SELECT CAST(CAST(timestamp AS DATE) AS VARCHAR) AS day, COUNT(*) \
+               FROM journal_logs GROUP BY day ORDER BY day";
+    trace_sql(sql);
+    let mut stmt = conn.prepare(sql)?;
+    let rows = stmt.query_map([], |row| {
+        Ok(DayRowCount {
+            day: row.get(0)?,
+            rows: row.get(1)?,
+        })
+    })?;
+    Ok(rows.collect::<duckdb::Result<Vec<_>>>()?)
+}
+
+fn get_top_units(conn: &Connection, limit: usize) -> Result<Vec<UnitUsage>> {
+    let sql = "SELECT COALESCE(_SYSTEMD_UNIT, '') AS unit, COUNT(*) AS row_count, \
+               CAST(COALESCE(SUM(strlen(message)), 0) AS BIGINT) \
+               FROM journal_logs GROUP BY 1 ORDER BY row_count DESC, unit LIMIT ?";
+    trace_sql(sql);
+    let mut stmt = conn.prepare(sql)?;
+    let rows = stmt.query_map(params![limit as i64], |row| {
+        Ok(UnitUsage {
+            unit: row.get(0)?,
+            rows: row.get(1)?,
+            message_bytes: row.get(2)?,
+        })
+    })?;
+    Ok(rows.collect::<duckdb::Result<Vec<_>>>()?)
+}
+
+fn get_indexes(conn: &Connection) -> Result<Vec<IndexStorage>> {
+    let sql = "SELECT index_name, table_name, is_unique FROM duckdb_indexes() \
+               WHERE database_name = current_database() ORDER BY table_name, index_name";
+    trace_sql(sql);
+    let mut stmt = conn.prepare(sql)?;
+    let rows = stmt.query_map([], |row| {
+        Ok(IndexStorage {
+            index_name: row.get(0)?,
+            table_name: row.get(1)?,
+            is_unique: row.get(2)?,
+        })
+    })?;
+    Ok(rows.collect::<duckdb::Result<Vec<_>>>()?)
+}
+
+fn get_index_memory_bytes(conn: &Connection) -> Result<i64> {
+    let sql = "SELECT CAST(COALESCE(SUM(memory_usage_bytes), 0) AS BIGINT) \
+               FROM duckdb_memory() WHERE tag = 'ART_INDEX'";
+    trace_sql(sql);
+    Ok(conn.query_row(sql, [], |row| row.get(0))?)
+}
+
+pub(crate) fn get_extra_field_usage(
+    conn: &Connection,
+    since: DateTime<Utc>,
+) -> Result<Vec<ExtraFieldUsage>> {
+    let sql = "SELECT u.key, COUNT(*) AS row_count, p.name IS NOT NULL \
+               FROM (SELECT unnest(json_keys(extra_fields)) AS key FROM journal_logs \
+                     WHERE timestamp >= CAST(? AS TIMESTAMP) AND extra_fields IS NOT NULL) u \
+               LEFT JOIN _promoted_fields p ON p.name = u.key \
+               GROUP BY u.key, p.name ORDER BY row_count DESC, u.key";
+    trace_sql(sql);
+    let since = since.naive_utc().format("%Y-%m-%d %H:%M:%S").to_string();
+    let mut stmt = conn.prepare(sql)?;
+    let rows = stmt.query_map(params![since], |row| {
+        Ok(ExtraFieldUsage {
+            key: row.get(0)?,
+            rows: row.get(1)?,
+            promoted: row.get(2)?,
+        })
+    })?;
+    Ok(rows.collect::<duckdb::Result<Vec<_>>>()?)
+}
+
+/// Hourly summaries overlapping `start..end`, optionally restricted to comma-separated
+/// hostnames and units
+pub(crate) fn get_log_summaries(
+    conn: &Connection,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    hostname: Option<&str>,
+    unit: Option<&str>,
+) -> Result<Vec<LogSummary>> {
+    let format = |t: DateTime<Utc>| t.naive_utc().format("%Y-%m-%d %H:%M:%S").to_string();
+    let mut sql = format!(
+        "SELECT CAST(hour AS VARCHAR), _HOSTNAME, _SYSTEMD_UNIT, count, error_count, \
+         CAST(first_timestamp AS VARCHAR), CAST(last_timestamp AS VARCHAR) FROM {} \
+         WHERE hour >= date_trunc('hour', CAST(? AS TIMESTAMP)) AND hour < CAST(? AS TIMESTAMP)",
+        LOG_SUMMARIES_TABLE
+    );
+    let mut params = vec![SqlParam::Text(format(start)), SqlParam::Text(format(end))];
+    for (column, values) in [("_HOSTNAME", hostname), ("_SYSTEMD_UNIT", unit)] {
+        let Some(values) = values.filter(|v| !v.is_empty()) else {
+            continue;
+        };
+        let values: Vec<&str> = values.split(',').collect();
+        sql.push_str(&format!(
+            " AND {} IN ({})",
+            column,
+            vec!["?"; values.len()].join(",")
+        ));
+        params.extend(values.iter().map(|v| SqlParam::Text(v.to_string())));
+    }
+    sql.push_str(" ORDER BY hour, _SYSTEMD_UNIT, _HOSTNAME");
+    trace_sql(&sql);
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt.query_map(params_from_iter(params.iter()), |row| {
+        Ok(LogSummary {
+            hour: row.get(0)?,
+            hostname: row.get(1)?,
+            unit: row.get(2)?,
+            count: row.get(3)?,
+            error_count: row.get(4)?,
+            first_timestamp: row.get(5)?,
+            last_timestamp: row.get(6)?,
+        })
+    })?;
+    Ok(rows.collect::<duckdb::Result<Vec<_>>>()?)
+}
+
+/// Every source that replicated to this instance, most recently seen first, with
+/// its entry rate over [`SOURCE_RATE_WINDOW`]; `log_source` covers that window
+pub(crate) fn get_sources(
+    conn: &Connection,
+    log_source: &str,
+    now: DateTime<Utc>,
+) -> Result<Vec<SourceStatus>> {
+    let since = now - SOURCE_RATE_WINDOW;
+    let sql = format!(
+        "WITH recent AS (
+             SELECT source, COUNT(*) AS entries FROM {}
+             WHERE timestamp >= CAST(? AS TIMESTAMP) GROUP BY source
+         )
+         SELECT name, epoch_us(first_seen), epoch_us(last_seen), epoch_us(last_entry_at),
+             lag_ms, entries_received, entries_stored, COALESCE(recent.entries, 0)
+         FROM {} LEFT JOIN recent ON recent.source = name
+         ORDER BY last_seen DESC, name",
+        log_source, SOURCES_TABLE
+    );
+    trace_sql(&sql);
+    let minutes = SOURCE_RATE_WINDOW.num_seconds() as f64 / 60.0;
+    let mut stmt = conn.prepare(&sql)?;
+    let sources = stmt
+        .query_map(params![since.to_rfc3339()], |row| {
+            let last_seen = DateTime::from_timestamp_micros(row.get(2)?).unwrap_or_default();
+            Ok(SourceStatus {
+                name: row.get(0)?,
+                first_seen: DateTime::from_timestamp_micros(row.get(1)?).unwrap_or_default(),
+                last_seen,
+                last_entry_at: row
+                    .get::<_, Option<i64>>(3)?
+                    .and_then(DateTime::from_timestamp_micros),
+                lag_ms: row.get(4)?,
+                entries_received: row.get(5)?,
+                entries_stored: row.get(6)?,
+                entries_per_minute: row.get::<_, i64>(7)? as f64 / minutes,
+                connected: now - last_seen <= SOURCE_CONNECTED_WITHIN,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(sources)
+}
+
+/// Columns of journal_logs and their types, less the internal [`INGEST_SEQ_COLUMN`]
+pub(crate) fn get_schema_columns(conn: &Connection) -> Vec<(String, String)> {
+    trace_sql("DESCRIBE journal_logs");
+    conn.prepare("DESCRIBE journal_logs")
+        .and_then(|mut stmt| {
+            stmt.query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })
+            .map(|rows| {
+                rows.filter_map(|r| r.ok())
+                    .filter(|(name, _)| name != INGEST_SEQ_COLUMN)
+                    .collect()
+            })
+        })
+        .unwrap_or_default()
+}
+
+pub(crate) fn query_json_rows(
+    conn: &Connection,
+    sql: &str,
+    params: &[SqlParam],
+    display_names: &[String],
+) -> Result<Vec<serde_json::Value>> {
+    trace_sql(sql);
+    let mut stmt = conn.prepare(sql)?;
+    let rows = stmt.query_map(params_from_iter(params.iter()), |row| {
+        Ok(row_to_json(row, display_names))
+    })?;
+
+    let mut out = Vec::new();
+    for row in rows {
+        out.push(row?);
+    }
+    Ok(out)
+}
+
+/// Streaming variant of [`query_json_rows`]: rows are handed to `sink` in chunks of
+/// `chunk_size` as they are read, so only one chunk is held in memory at a time.
+/// Stops early when `sink` returns false. Returns the number of rows delivered.
+pub(crate) fn stream_json_rows<F>(
+    conn: &Connection,
+    sql: &str,
+    params: &[SqlParam],
+    display_names: &[String],
+    chunk_size: usize,
+    mut sink: F,
+) -> Result<usize>
+where
+    F: FnMut(Vec<serde_json::Value>) -> bool,
+{
+    let chunk_size = chunk_size.max(1);
+    trace_sql(sql);
+    let mut stmt = conn.prepare(sql)?;
+    let mut rows = stmt.query(params_from_iter(params.iter()))?;
+
+    let mut delivered = 0;
+    let mut chunk = Vec::with_capacity(chunk_size);
+    while let Some(row) = rows.next()? {
+        chunk.push(row_to_json(row, display_names));
+        if chunk.len() == chunk_size {
+            delivered += chunk.len();
+            if !sink(std::mem::replace(
+                &mut chunk,
+                Vec::with_capacity(chunk_size),
+            )) {
+                return Ok(delivered);
+            }
+        }
+    }
+    if !chunk.is_empty() {
+        delivered += chunk.len();
+        sink(chunk);
+    }
+    Ok(delivered)
+}
+
+/// Columns of DuckDB's JSON type, returned to clients as JSON values rather than strings
+const JSON_COLUMNS: &[&str] = &["extra_fields"];
+
+/// Map a result row to a JSON object keyed by `display_names`
+fn row_to_json(row: &Row<'_>, display_names: &[String]) -> serde_json::Value {
+    let mut map = serde_json::Map::new();
+    for (i, name) in display_names.iter().enumerate() {
+        let val = if let Ok(v) = row.get::<_, String>(i) {
+            if JSON_COLUMNS.contains(&name.as_str()) {
+                serde_json::from_str(&v).unwrap_or(serde_json::Value::String(v))
+            } else {
+                serde_json::Value::String(v)
+            }
+        } else if let Ok(v) = row.get::<_, i64>(i) {
+            serde_json::Value::Number(v.into())
+        } else if let Ok(v) = row.get::<_, f64>(i) {
+            serde_json::json!(v)
+        } else {
+            serde_json::Value::Null
+        };
+        map.insert(name.clone(), val);
+    }
+    serde_json::Value::Object(map)
+}
+
+/// Write the result of `sql` to a Parquet file at `path`; returns the number of rows
+pub(crate) fn copy_to_parquet(
+    conn: &Connection,
+    sql: &str,
+    params: &[SqlParam],
+    path: &Path,
+) -> Result<usize> {
+    let copy = format!(
+        "COPY ({}) TO '{}' (FORMAT PARQUET, COMPRESSION ZSTD)",
+        sql,
+        sql_path(path)
+    );
+    trace_sql(&copy);
+    Ok(conn.execute(&copy, params_from_iter(params.iter()))?)
+}
+
+pub(crate) fn query_usize(conn: &Connection, sql: &str, params: &[SqlParam]) -> usize {
+    trace_sql(sql);
+    conn.prepare(sql)
+        .and_then(|mut stmt| stmt.query_row(params_from_iter(params.iter()), |row| row.get(0)))
+        .unwrap_or(0)
+}
+
+pub(crate) fn query_distinct_strings(
+    conn: &Connection,
+    sql: &str,
+    params: &[SqlParam],
+) -> Vec<String> {
+    trace_sql(sql);
+    conn.prepare(sql)
+        .and_then(|mut stmt| {
+            stmt.query_map(params_from_iter(params.iter()), |row| row.get(0))
+                .map(|rows| rows.filter_map(|r| r.ok()).collect())
+        })
+        .unwrap_or_default()
+}
+
+pub(crate) fn query_histogram_rows(
+    conn: &Connection,
+    sql: &str,
+    params: &[SqlParam],
+) -> Vec<serde_json::Value> {
+    trace_sql(sql);
+    conn.prepare(sql)
+        .and_then(|mut stmt| {
+            stmt.query_map(params_from_iter(params.iter()), |row| {
+                Ok(serde_json::json!({
+                    "bin": row.get::<_, String>(0)?,
+                    "count": row.get::<_, i64>(1)?,
+                }))
+            })
+            .map(|rows| rows.filter_map(|r| r.ok()).collect())
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stream_json_rows_delivers_chunks() {
+        let conn = Connection::open_in_memory().unwrap();
+        let names = vec!["n".to_string(), "label".to_string()];
+        let sql = "SELECT i, 'row ' || i FROM range(?) t(i) ORDER BY i";
+
+        let mut chunks = Vec::new();
+        let delivered = stream_json_rows(&conn, sql, &[SqlParam::BigInt(25)], &names, 10, |rows| {
+            chunks.push(rows);
+            true
+        })
+        .unwrap();
+        assert_eq!(delivered, 25);
+        assert_eq!(
+            chunks.iter().map(|c| c.len()).collect::<Vec<_>>(),
+            vec![10, 10, 5]
+        );
+        assert_eq!(chunks[2][4]["n"], 24);
+        assert_eq!(chunks[0][1]["label"], "row 1");
+
+        // Same rows as the collecting variant
+        let all = query_json_rows(&conn, sql, &[SqlParam::BigInt(25)], &names).unwrap();
+        assert_eq!(all, chunks.concat());
+    }
+
+    #[test]
+    fn test_stream_json_rows_stops_when_sink_declines() {
+        let conn = Connection::open_in_memory().unwrap();
+        let names = vec!["n".to_string()];
+        let mut calls = 0;
+        let delivered = stream_json_rows(
+            &conn,
+            "SELECT i FROM range(100) t(i)",
+            &[],
+            &names,
+            10,
+            |_| {
+                calls += 1;
+                calls < 2
+            },
+        )
+        .unwrap();
+        assert_eq!(calls, 2);
+        assert_eq!(delivered, 20);
+    }
+}
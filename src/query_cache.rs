@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Once this many results are cached, expired ones are dropped, then the oldest
+const MAX_CACHED_RESULTS: usize = 256;
+
+/// Short-lived cache of query results keyed by request. A result is served only
+/// while it is younger than the TTL and was computed at the same data `version`
+/// (the count of entries stored), so new entries invalidate it immediately.
+pub struct QueryCache<T> {
+    ttl: Duration,
+    entries: Mutex<HashMap<String, Cached<T>>>,
+}
+
+struct Cached<T> {
+    value: T,
+    version: i64,
+    stored: Instant,
+}
+
+impl<T: Clone> QueryCache<T> {
+    pub fn with_ttl(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Result cached for `key` at `version`, if it has not expired by `now`
+    pub fn get(&self, key: &str, version: i64, now: Instant) -> Option<T> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .get(key)
+            .filter(|cached| {
+                cached.version == version && now.saturating_duration_since(cached.stored) < self.ttl
+            })
+            .map(|cached| cached.value.clone())
+    }
+
+    /// Drop every cached result, after changes that do not ingest new entries
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    pub fn insert(&self, key: String, version: i64, value: T, now: Instant) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= MAX_CACHED_RESULTS && !entries.contains_key(&key) {
+            entries.retain(|_, cached| now.saturating_duration_since(cached.stored) < self.ttl);
+            if entries.len() >= MAX_CACHED_RESULTS
+                && let Some(oldest) = entries
+                    .iter()
+                    .min_by_key(|(_, cached)| cached.stored)
+                    .map(|(key, _)| key.clone())
+            {
+                entries.remove(&oldest);
+            }
+        }
+        entries.insert(
+            key,
+            Cached {
+                value,
+                version,
+                stored: now,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_expires_and_invalidates() {
+        let cache = QueryCache::with_ttl(Duration::from_secs(5));
+        let start = Instant::now();
+        cache.insert("a".to_string(), 1, "result", start);
+
+        assert_eq!(
+            cache.get("a", 1, start + Duration::from_secs(4)),
+            Some("result")
+        );
+        assert_eq!(cache.get("b", 1, start), None);
+        // Newer data or an expired TTL both miss
+        assert_eq!(cache.get("a", 2, start), None);
+        assert_eq!(cache.get("a", 1, start + Duration::from_secs(5)), None);
+
+        for i in 0..MAX_CACHED_RESULTS {
+            cache.insert(i.to_string(), 1, "filler", start + Duration::from_millis(1));
+        }
+        // The oldest result made room
+        assert_eq!(cache.get("a", 1, start), None);
+        assert_eq!(cache.entries.lock().unwrap().len(), MAX_CACHED_RESULTS);
+    }
+}
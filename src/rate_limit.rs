@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Once this many clients are tracked, buckets that have refilled are dropped
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+/// Token bucket rate limiter keyed by client. Every client may burst up to `limit`
+/// requests; tokens refill continuously at `limit` per minute.
+pub struct RateLimiter {
+    limit: u32,
+    refill_per_sec: f64,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Outcome of [`RateLimiter::check`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Decision {
+    pub allowed: bool,
+    pub limit: u32,
+    /// Requests left in the bucket after this one
+    pub remaining: u32,
+    /// Seconds until the next request would be allowed (0 when allowed)
+    pub retry_after_secs: u64,
+}
+
+impl RateLimiter {
+    pub fn per_minute(limit: u32) -> Self {
+        Self {
+            limit,
+            refill_per_sec: f64::from(limit) / 60.0,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Take a token from `client`'s bucket at `now`
+    pub fn check(&self, client: &str, now: Instant) -> Decision {
+        let capacity = f64::from(self.limit);
+        let refill = |bucket: &Bucket| {
+            let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+            (bucket.tokens + elapsed * self.refill_per_sec).min(capacity)
+        };
+
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= MAX_TRACKED_CLIENTS && !buckets.contains_key(client) {
+            // A full bucket is the same as no bucket
+            buckets.retain(|_, bucket| refill(bucket) < capacity);
+        }
+        let bucket = buckets.entry(client.to_string()).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+        bucket.tokens = refill(bucket);
+        bucket.updated = now;
+
+        let allowed = bucket.tokens >= 1.0;
+        if allowed {
+            bucket.tokens -= 1.0;
+        }
+        let retry_after_secs = if allowed {
+            0
+        } else {
+            ((1.0 - bucket.tokens) / self.refill_per_sec).ceil() as u64
+        };
+        Decision {
+            allowed,
+            limit: self.limit,
+            remaining: bucket.tokens.floor() as u32,
+            retry_after_secs,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_bucket_allows_burst_then_refills() {
+        let limiter = RateLimiter::per_minute(3);
+        let start = Instant::now();
+
+        for remaining in [2, 1, 0] {
+            let decision = limiter.check("a", start);
+            assert!(decision.allowed);
+            assert_eq!(decision.remaining, remaining);
+        }
+        let denied = limiter.check("a", start);
+        assert!(!denied.allowed);
+        assert_eq!(denied.retry_after_secs, 20);
+
+        // Other clients have their own bucket
+        assert!(limiter.check("b", start).allowed);
+
+        // One token refills every 20 seconds
+        assert!(!limiter.check("a", start + Duration::from_secs(19)).allowed);
+        assert!(limiter.check("a", start + Duration::from_secs(21)).allowed);
+        assert!(!limiter.check("a", start + Duration::from_secs(21)).allowed);
+    }
+}
@@ -0,0 +1,341 @@
+use crate::duckdb_buffer::{
+    ExtraFieldUsage, LogSummary, ProcessHistoryPoint, ProcessMetricRecord, SqlParam, StorageStats,
+    SystemHistoryPoint,
+};
+use crate::integrity::{self, IntegrityReport};
+use crate::peer_replication::SourceStatus;
+use crate::queries;
+use crate::sql_trace::log_query;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use duckdb::{Connection, InterruptHandle};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Instant;
+
+/// Pool of DuckDB connections reserved for web queries.
+///
+/// The connections are clones of the writer's connection, so they share its database
+/// instance and see everything it has committed, but searches run on their own
+/// connection instead of queueing behind ingestion on the `Mutex<DuckDBBuffer>`.
+/// DuckDB cannot open a second, read-only instance of a file this process already has
+/// open for writing, so the connections could write too: they are kept to reads by
+/// exposing only read helpers on [`PooledReader`], whose SQL the web server builds
+/// itself. Statements passed in as SQL are recorded in the [`crate::sql_trace`] query
+/// log.
+pub struct ReadPool {
+    idle: Mutex<Vec<Connection>>,
+    available: Condvar,
+    size: usize,
+}
+
+impl ReadPool {
+    pub fn new(connections: Vec<Connection>) -> Self {
+        assert!(
+            !connections.is_empty(),
+            "ReadPool needs at least one connection"
+        );
+        Self {
+            size: connections.len(),
+            idle: Mutex::new(connections),
+            available: Condvar::new(),
+        }
+    }
+
+    /// Number of connections owned by the pool
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Borrow a connection, waiting for one to be returned if all are in use
+    pub fn get(&self) -> PooledReader<'_> {
+        let mut idle = self.idle.lock().unwrap();
+        loop {
+            if let Some(conn) = idle.pop() {
+                return PooledReader {
+                    pool: self,
+                    conn: Some(conn),
+                };
+            }
+            idle = self.available.wait(idle).unwrap();
+        }
+    }
+}
+
+/// Connection borrowed from a [`ReadPool`]; returned to the pool on drop
+pub struct PooledReader<'a> {
+    pool: &'a ReadPool,
+    conn: Option<Connection>,
+}
+
+impl PooledReader<'_> {
+    fn conn(&self) -> &Connection {
+        self.conn
+            .as_ref()
+            .expect("connection is present until drop")
+    }
+
+    /// Handle that interrupts whatever query is running on this connection
+    pub fn interrupt_handle(&self) -> Arc<InterruptHandle> {
+        self.conn().interrupt_handle()
+    }
+
+    pub fn get_latest_process_timestamp(&self) -> Result<Option<String>> {
+        queries::get_latest_process_timestamp(self.conn())
+    }
+
+    pub fn get_process_timestamp_at(&self, at: DateTime<Utc>) -> Result<Option<String>> {
+        queries::get_process_timestamp_at(self.conn(), at)
+    }
+
+    pub fn get_process_metrics_for_timestamp(
+        &self,
+        timestamp: &str,
+    ) -> Result<Vec<ProcessMetricRecord>> {
+        queries::get_process_metrics_for_timestamp(self.conn(), timestamp)
+    }
+
+    pub fn get_process_history(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        pid: Option<u32>,
+        name: Option<&str>,
+        unit: Option<&str>,
+        bin_secs: i64,
+    ) -> Result<Vec<ProcessHistoryPoint>> {
+        queries::get_process_history(self.conn(), start, end, pid, name, unit, bin_secs)
+    }
+
+    pub fn get_system_history(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        metrics: &[&str],
+        device: Option<&str>,
+        bin_secs: i64,
+    ) -> Result<Vec<SystemHistoryPoint>> {
+        queries::get_system_history(self.conn(), start, end, metrics, device, bin_secs)
+    }
+
+    pub fn get_storage_stats(&self) -> Result<StorageStats> {
+        queries::get_storage_stats(self.conn())
+    }
+
+    pub fn get_extra_field_usage(&self, since: DateTime<Utc>) -> Result<Vec<ExtraFieldUsage>> {
+        queries::get_extra_field_usage(self.conn(), since)
+    }
+
+    pub fn get_log_summaries(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        hostname: Option<&str>,
+        unit: Option<&str>,
+    ) -> Result<Vec<LogSummary>> {
+        queries::get_log_summaries(self.conn(), start, end, hostname, unit)
+    }
+
+    /// See [`crate::duckdb_buffer::DuckDBBuffer::sources`]; `log_source` covers the
+    /// rate window
+    pub fn sources(&self, log_source: &str, now: DateTime<Utc>) -> Result<Vec<SourceStatus>> {
+        queries::get_sources(self.conn(), log_source, now)
+    }
+
+    pub fn get_schema_columns(&self) -> Vec<(String, String)> {
+        queries::get_schema_columns(self.conn())
+    }
+
+    pub fn query_json_rows(
+        &self,
+        sql: &str,
+        params: &[SqlParam],
+        display_names: &[String],
+    ) -> Result<Vec<serde_json::Value>> {
+        let started = Instant::now();
+        let rows = queries::query_json_rows(self.conn(), sql, params, display_names);
+        log_query(sql, started.elapsed(), rows.as_ref().map_or(0, Vec::len));
+        rows
+    }
+
+    pub fn stream_json_rows<F>(
+        &self,
+        sql: &str,
+        params: &[SqlParam],
+        display_names: &[String],
+        chunk_size: usize,
+        sink: F,
+    ) -> Result<usize>
+    where
+        F: FnMut(Vec<serde_json::Value>) -> bool,
+    {
+        let started = Instant::now();
+        let rows =
+            queries::stream_json_rows(self.conn(), sql, params, display_names, chunk_size, sink);
+        log_query(sql, started.elapsed(), *rows.as_ref().unwrap_or(&0));
+        rows
+    }
+
+    pub fn copy_to_parquet(&self, sql: &str, params: &[SqlParam], path: &Path) -> Result<usize> {
+        let started = Instant::now();
+        let rows = queries::copy_to_parquet(self.conn(), sql, params, path);
+        log_query(sql, started.elapsed(), *rows.as_ref().unwrap_or(&0));
+        rows
+    }
+
+    pub fn query_usize(&self, sql: &str, params: &[SqlParam]) -> usize {
+        let started = Instant::now();
+        let value = queries::query_usize(self.conn(), sql, params);
+        log_query(sql, started.elapsed(), 1);
+        value
+    }
+
+    pub fn query_distinct_strings(&self, sql: &str, params: &[SqlParam]) -> Vec<String> {
+        let started = Instant::now();
+        let values = queries::query_distinct_strings(self.conn(), sql, params);
+        log_query(sql, started.elapsed(), values.len());
+        values
+    }
+
+    pub fn query_histogram_rows(&self, sql: &str, params: &[SqlParam]) -> Vec<serde_json::Value> {
+        let started = Instant::now();
+        let rows = queries::query_histogram_rows(self.conn(), sql, params);
+        log_query(sql, started.elapsed(), rows.len());
+        rows
+    }
+
+    pub fn check_integrity(&self, scan_tables: bool) -> IntegrityReport {
+        integrity::check(self.conn(), scan_tables)
+    }
+}
+
+impl Drop for PooledReader<'_> {
+    fn drop(&mut self) {
+        if let Some(conn) = self.conn.take() {
+            self.pool.idle.lock().unwrap().push(conn);
+            self.pool.available.notify_one();
+        }
+    }
+}
+
+/// Cancels a query running on a pooled connection from another thread.
+///
+/// The query side calls [`QueryCancel::register`] once it holds a connection and
+/// [`QueryCancel::clear`] when it is done; [`QueryCancel::cancel`] interrupts the
+/// registered connection, or makes a later `register` fail if none is registered yet.
+#[derive(Default)]
+pub struct QueryCancel {
+    cancelled: AtomicBool,
+    interrupt: Mutex<Option<Arc<InterruptHandle>>>,
+}
+
+impl QueryCancel {
+    /// Register the connection about to run the query; false if already cancelled
+    pub fn register(&self, handle: Arc<InterruptHandle>) -> bool {
+        let mut interrupt = self.interrupt.lock().unwrap();
+        if self.cancelled.load(Ordering::SeqCst) {
+            return false;
+        }
+        *interrupt = Some(handle);
+        true
+    }
+
+    /// Forget the connection so a late cancel cannot hit its next query
+    pub fn clear(&self) {
+        self.interrupt.lock().unwrap().take();
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        if let Some(handle) = self.interrupt.lock().unwrap().as_ref() {
+            handle.interrupt();
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_reader_waits_for_returned_connection() {
+        let conn = Connection::open_in_memory().unwrap();
+        let pool = Arc::new(ReadPool::new(vec![conn.try_clone().unwrap()]));
+        assert_eq!(pool.size(), 1);
+
+        let reader = pool.get();
+        let waiter = {
+            let pool = pool.clone();
+            thread::spawn(move || pool.get().query_usize("SELECT 42", &[]))
+        };
+        thread::sleep(Duration::from_millis(50));
+        assert!(!waiter.is_finished());
+
+        drop(reader);
+        assert_eq!(waiter.join().unwrap(), 42);
+    }
+
+    #[test]
+    fn test_readers_see_writer_commits() {
+        let writer = Connection::open_in_memory().unwrap();
+        let pool = ReadPool::new(vec![
+            writer.try_clone().unwrap(),
+            writer.try_clone().unwrap(),
+        ]);
+
+        writer.execute("CREATE TABLE t (v INTEGER)", []).unwrap();
+        writer.execute("INSERT INTO t VALUES (1), (2)", []).unwrap();
+
+        let first = pool.get();
+        let second = pool.get();
+        assert_eq!(first.query_usize("SELECT COUNT(*) FROM t", &[]), 2);
+        assert_eq!(second.query_usize("SELECT SUM(v) FROM t", &[]), 3);
+    }
+
+    #[test]
+    fn test_cancel_interrupts_running_query() {
+        let conn = Connection::open_in_memory().unwrap();
+        let pool = Arc::new(ReadPool::new(vec![conn.try_clone().unwrap()]));
+        let cancel = Arc::new(QueryCancel::default());
+
+        let query = {
+            let pool = pool.clone();
+            let cancel = cancel.clone();
+            thread::spawn(move || {
+                let reader = pool.get();
+                assert!(cancel.register(reader.interrupt_handle()));
+                let result = reader.query_json_rows(
+                    "SELECT SUM(i * i) FROM range(100000000000) t(i)",
+                    &[],
+                    &["sum".to_string()],
+                );
+                cancel.clear();
+                result
+            })
+        };
+        thread::sleep(Duration::from_millis(200));
+        cancel.cancel();
+
+        assert!(query.join().unwrap().is_err());
+        assert!(cancel.is_cancelled());
+        // The connection went back to the pool and is usable again
+        assert_eq!(pool.get().query_usize("SELECT 1", &[]), 1);
+    }
+
+    #[test]
+    fn test_register_fails_after_cancel() {
+        let conn = Connection::open_in_memory().unwrap();
+        let pool = ReadPool::new(vec![conn.try_clone().unwrap()]);
+        let cancel = QueryCancel::default();
+        cancel.cancel();
+        assert!(!cancel.register(pool.get().interrupt_handle()));
+    }
+}
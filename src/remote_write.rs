@@ -0,0 +1,478 @@
+//! Prometheus remote-write export of the process and system metrics, so a Prometheus,
+//! Mimir or similar setup gets per-process series without another exporter. Process
+//! series are summed over the processes sharing a name and unit, as series keyed by
+//! pid would grow without bound. Samples are sent as they are collected; a sample that cannot be delivered after a few
+//! attempts is dropped, as remote-write receivers reject samples that arrive too late
+//! anyway.
+
+use crate::outputs::{Rejected, check_response};
+use crate::process_monitor::{ProcessInfo, SystemSample};
+use crate::webhook::retry_delay;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use prost::Message;
+use reqwest::Url;
+use reqwest::header::{CONTENT_ENCODING, CONTENT_TYPE};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+
+/// Samples waiting to be sent; more are dropped while the receiver is unreachable
+const QUEUE_CAPACITY: usize = 64;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Attempts per request after the first, backing off like webhooks do
+const MAX_RETRIES: u32 = 2;
+const RETRY_BACKOFF_MS: u64 = 1_000;
+
+/// Remote-write receiver for process and system metrics (`[remote_write]` table)
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RemoteWriteSettings {
+    /// Receiver, e.g. "http://prometheus:9090/api/v1/write" or Mimir's
+    /// "http://mimir:8080/api/v1/push"
+    pub url: String,
+
+    /// Basic auth credentials
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+
+    /// Extra request headers, e.g. `X-Scope-OrgID` for a multi-tenant Mimir
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct WriteRequest {
+    #[prost(message, repeated, tag = "1")]
+    timeseries: Vec<TimeSeries>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct TimeSeries {
+    /// Sorted by name, `__name__` included
+    #[prost(message, repeated, tag = "1")]
+    labels: Vec<Label>,
+    #[prost(message, repeated, tag = "2")]
+    samples: Vec<Sample>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Label {
+    #[prost(string, tag = "1")]
+    name: String,
+    #[prost(string, tag = "2")]
+    value: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Sample {
+    #[prost(double, tag = "1")]
+    value: f64,
+    /// Milliseconds since the epoch
+    #[prost(int64, tag = "2")]
+    timestamp: i64,
+}
+
+/// Series of one collection round, all at the same timestamp
+struct SeriesBuilder<'a> {
+    instance: &'a str,
+    timestamp: i64,
+    series: Vec<TimeSeries>,
+}
+
+impl SeriesBuilder<'_> {
+    fn add(&mut self, name: &str, labels: &[(&str, &str)], value: f64) {
+        let mut labels: Vec<Label> = [
+            ("__name__", name),
+            ("instance", self.instance),
+            ("job", "livedata"),
+        ]
+        .iter()
+        .chain(labels)
+        .map(|(name, value)| Label {
+            name: name.to_string(),
+            value: value.to_string(),
+        })
+        .collect();
+        labels.sort_by(|a, b| a.name.cmp(&b.name));
+        self.series.push(TimeSeries {
+            labels,
+            samples: vec![Sample {
+                value,
+                timestamp: self.timestamp,
+            }],
+        });
+    }
+}
+
+/// Sums over the processes sharing a name and unit; thread and fd counts are of the
+/// processes they are known for, `None` when for none
+#[derive(Default)]
+struct ProcessTotals {
+    processes: usize,
+    cpu_percent: f64,
+    memory_bytes: u64,
+    virtual_memory_bytes: u64,
+    read_bytes_per_sec: f64,
+    write_bytes_per_sec: f64,
+    threads: Option<u64>,
+    open_fds: Option<u64>,
+}
+
+impl ProcessTotals {
+    fn add(&mut self, process: &ProcessInfo) {
+        self.processes += 1;
+        self.cpu_percent += process.cpu_percent as f64;
+        self.memory_bytes += process.memory_bytes;
+        self.virtual_memory_bytes += process.virtual_memory_bytes;
+        self.read_bytes_per_sec += process.read_bytes_per_sec;
+        self.write_bytes_per_sec += process.write_bytes_per_sec;
+        if let Some(threads) = process.num_threads {
+            *self.threads.get_or_insert(0) += threads as u64;
+        }
+        if let Some(open_fds) = process.open_fd_count {
+            *self.open_fds.get_or_insert(0) += open_fds as u64;
+        }
+    }
+}
+
+/// Remote-write request for one sample of `processes` and `system`
+fn write_request(
+    instance: &str,
+    processes: &[ProcessInfo],
+    system: Option<&SystemSample>,
+    timestamp: DateTime<Utc>,
+) -> WriteRequest {
+    let mut builder = SeriesBuilder {
+        instance,
+        timestamp: timestamp.timestamp_millis(),
+        series: Vec::new(),
+    };
+    // Totals per process name and unit
+    let mut groups: BTreeMap<(&str, Option<&str>), ProcessTotals> = BTreeMap::new();
+    for process in processes {
+        groups
+            .entry((process.name.as_str(), process.unit.as_deref()))
+            .or_default()
+            .add(process);
+    }
+    for ((process, unit), totals) in groups {
+        let mut labels = vec![("process", process)];
+        if let Some(unit) = unit {
+            labels.push(("unit", unit));
+        }
+        let counts = [
+            ("livedata_process_threads", totals.threads),
+            ("livedata_process_open_fds", totals.open_fds),
+        ];
+        for (name, value) in [
+            ("livedata_processes", totals.processes as f64),
+            ("livedata_process_cpu_percent", totals.cpu_percent),
+            (
+                "livedata_process_resident_memory_bytes",
+                totals.memory_bytes as f64,
+            ),
+            (
+                "livedata_process_virtual_memory_bytes",
+                totals.virtual_memory_bytes as f64,
+            ),
+            (
+                "livedata_process_read_bytes_per_second",
+                totals.read_bytes_per_sec,
+            ),
+            (
+                "livedata_process_write_bytes_per_second",
+                totals.write_bytes_per_sec,
+            ),
+        ]
+        .into_iter()
+        .chain(
+            counts
+                .into_iter()
+                .filter_map(|(name, count)| Some((name, count? as f64))),
+        ) {
+            builder.add(name, &labels, value);
+        }
+    }
+    if let Some(system) = system {
+        for (name, value) in [
+            ("livedata_load1", system.load_1),
+            ("livedata_load5", system.load_5),
+            ("livedata_load15", system.load_15),
+            (
+                "livedata_memory_total_bytes",
+                system.memory_total_bytes as f64,
+            ),
+            (
+                "livedata_memory_used_bytes",
+                system.memory_used_bytes as f64,
+            ),
+            ("livedata_swap_total_bytes", system.swap_total_bytes as f64),
+            ("livedata_swap_used_bytes", system.swap_used_bytes as f64),
+        ] {
+            builder.add(name, &[], value);
+        }
+        for disk in &system.disks {
+            let labels = [("mountpoint", disk.mount_point.as_str())];
+            builder.add(
+                "livedata_disk_total_bytes",
+                &labels,
+                disk.total_bytes as f64,
+            );
+            builder.add(
+                "livedata_disk_available_bytes",
+                &labels,
+                disk.available_bytes as f64,
+            );
+        }
+        for network in &system.networks {
+            let labels = [("interface", network.interface.as_str())];
+            builder.add(
+                "livedata_network_receive_bytes_per_second",
+                &labels,
+                network.rx_bytes_per_sec,
+            );
+            builder.add(
+                "livedata_network_transmit_bytes_per_second",
+                &labels,
+                network.tx_bytes_per_sec,
+            );
+        }
+    }
+    WriteRequest {
+        timeseries: builder.series,
+    }
+}
+
+/// Queues collected metrics for the remote-write thread
+pub struct RemoteWriter {
+    instance: String,
+    requests: mpsc::Sender<WriteRequest>,
+}
+
+impl RemoteWriter {
+    /// Start the thread sending to `settings.url`; series carry `instance` as their
+    /// instance label. The thread ends once every `RemoteWriter` is dropped.
+    pub fn start(
+        settings: &RemoteWriteSettings,
+        instance: &str,
+        shutdown_signal: Arc<AtomicBool>,
+    ) -> Result<(Self, thread::JoinHandle<()>)> {
+        let client = Client {
+            http: reqwest::Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .build()?,
+            url: Url::parse(&settings.url)?,
+            settings: settings.clone(),
+        };
+        let (requests, mut queued) = mpsc::channel(QUEUE_CAPACITY);
+        let handle = thread::Builder::new()
+            .name("remote write".to_string())
+            .spawn(move || {
+                let rt = tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                    .expect("Failed to create tokio runtime");
+                rt.block_on(async move {
+                    while let Some(request) = queued.recv().await {
+                        client.send(&request, &shutdown_signal).await;
+                    }
+                    info!("Remote write stopped");
+                });
+            })?;
+        Ok((
+            Self {
+                instance: instance.to_string(),
+                requests,
+            },
+            handle,
+        ))
+    }
+
+    /// Queue one sample of the metrics, unless the queue is full
+    pub fn push(
+        &self,
+        processes: &[ProcessInfo],
+        system: Option<&SystemSample>,
+        timestamp: DateTime<Utc>,
+    ) {
+        let request = write_request(&self.instance, processes, system, timestamp);
+        if request.timeseries.is_empty() {
+            return;
+        }
+        if let Err(TrySendError::Full(_)) = self.requests.try_send(request) {
+            warn!("Remote write is falling behind, dropping a metrics sample");
+        }
+    }
+}
+
+struct Client {
+    http: reqwest::Client,
+    url: Url,
+    settings: RemoteWriteSettings,
+}
+
+impl Client {
+    async fn try_send(&self, body: Vec<u8>) -> Result<()> {
+        let mut request = self
+            .http
+            .post(self.url.clone())
+            .header(CONTENT_ENCODING, "snappy")
+            .header(CONTENT_TYPE, "application/x-protobuf")
+            .header("X-Prometheus-Remote-Write-Version", "0.1.0")
+            .body(body);
+        if let Some(username) = &self.settings.username {
+            request = request.basic_auth(username, self.settings.password.as_ref());
+        }
+        for (name, value) in &self.settings.headers {
+            request = request.header(name, value);
+        }
+        check_response(request.send().await).await
+    }
+
+    async fn send(&self, request: &WriteRequest, shutdown_signal: &AtomicBool) {
+        let body = match snap::raw::Encoder::new().compress_vec(&request.encode_to_vec()) {
+            Ok(body) => body,
+            Err(e) => {
+                warn!("Failed to compress remote write request: {}", e);
+                return;
+            }
+        };
+        for attempt in 0..=MAX_RETRIES {
+            match self.try_send(body.clone()).await {
+                Ok(()) => return,
+                Err(e)
+                    if e.is::<Rejected>()
+                        || attempt == MAX_RETRIES
+                        || shutdown_signal.load(Ordering::Relaxed) =>
+                {
+                    warn!(
+                        "Dropping {} series after remote write failed: {:#}",
+                        request.timeseries.len(),
+                        e
+                    );
+                    return;
+                }
+                Err(e) => {
+                    let delay = retry_delay(RETRY_BACKOFF_MS, attempt);
+                    warn!("Remote write failed ({:#}), retrying in {:?}", e, delay);
+                    tokio::time::sleep(delay).await;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process_monitor::{DiskUsage, NetworkThroughput};
+
+    fn label_values(series: &TimeSeries) -> Vec<(&str, &str)> {
+        series
+            .labels
+            .iter()
+            .map(|label| (label.name.as_str(), label.value.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn test_write_request() {
+        let process = ProcessInfo {
+            pid: 812,
+            name: "nginx".to_string(),
+            cpu_percent: 12.5,
+            memory_bytes: 4096,
+            user_id: None,
+            runtime_secs: 60,
+            cmd: Vec::new(),
+            virtual_memory_bytes: 8192,
+            status: "Run".to_string(),
+            parent_pid: Some(1),
+            read_bytes_per_sec: 0.0,
+            write_bytes_per_sec: 10.0,
+            num_threads: Some(4),
+            open_fd_count: None,
+            unit: Some("nginx.service".to_string()),
+            container_id: None,
+        };
+        let system = SystemSample {
+            load_1: 0.5,
+            load_5: 0.25,
+            load_15: 0.125,
+            memory_total_bytes: 1 << 30,
+            memory_used_bytes: 1 << 29,
+            swap_total_bytes: 0,
+            swap_used_bytes: 0,
+            disks: vec![DiskUsage {
+                mount_point: "/".to_string(),
+                total_bytes: 100,
+                available_bytes: 40,
+            }],
+            networks: vec![NetworkThroughput {
+                interface: "eth0".to_string(),
+                rx_bytes_per_sec: 1.0,
+                tx_bytes_per_sec: 2.0,
+            }],
+            temperatures: Vec::new(),
+            fans: Vec::new(),
+            batteries: Vec::new(),
+        };
+        let timestamp = DateTime::from_timestamp_millis(1_700_000_000_123).unwrap();
+        let worker = ProcessInfo {
+            pid: 813,
+            cpu_percent: 2.5,
+            num_threads: None,
+            ..process.clone()
+        };
+        let request = write_request("web1", &[process, worker], Some(&system), timestamp);
+
+        // The two nginx processes share their series: a process count, 5 gauges and
+        // threads (open fds unknown); then 7 system, 2 disk and 2 network
+        assert_eq!(request.timeseries.len(), 7 + 7 + 2 + 2);
+        let series = |name: &str| {
+            request
+                .timeseries
+                .iter()
+                .find(|series| series.labels[0].value == name)
+                .unwrap()
+        };
+        let cpu = series("livedata_process_cpu_percent");
+        assert_eq!(
+            label_values(cpu),
+            [
+                ("__name__", "livedata_process_cpu_percent"),
+                ("instance", "web1"),
+                ("job", "livedata"),
+                ("process", "nginx"),
+                ("unit", "nginx.service"),
+            ]
+        );
+        assert_eq!(
+            cpu.samples,
+            [Sample {
+                value: 15.0,
+                timestamp: 1_700_000_000_123
+            }]
+        );
+        assert_eq!(series("livedata_processes").samples[0].value, 2.0);
+        assert_eq!(series("livedata_process_threads").samples[0].value, 4.0);
+        let disk = series("livedata_disk_available_bytes");
+        assert!(label_values(disk).contains(&("mountpoint", "/")));
+        assert_eq!(disk.samples[0].value, 40.0);
+
+        // What goes over the wire decodes back to the same request
+        let body = snap::raw::Encoder::new()
+            .compress_vec(&request.encode_to_vec())
+            .unwrap();
+        let decoded = snap::raw::Decoder::new().decompress_vec(&body).unwrap();
+        assert_eq!(WriteRequest::decode(decoded.as_slice()).unwrap(), request);
+    }
+}
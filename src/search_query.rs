@@ -0,0 +1,492 @@
+use crate::duckdb_buffer::SqlParam;
+use crate::log_entry::{LOCAL_SOURCE, LogEntry};
+use crate::parquet_writer::quote_ident;
+use crate::user_names;
+use std::cmp::Ordering;
+
+/// Comparison of a [`Term`]; `field:value` and `field=value` are both [`Op::Eq`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Op {
+    fn sql(self) -> &'static str {
+        match self {
+            Self::Eq => "=",
+            Self::Ne => "<>",
+            Self::Lt => "<",
+            Self::Le => "<=",
+            Self::Gt => ">",
+            Self::Ge => ">=",
+        }
+    }
+
+    fn holds(self, ordering: Ordering) -> bool {
+        match self {
+            Self::Eq => ordering == Ordering::Equal,
+            Self::Ne => ordering != Ordering::Equal,
+            Self::Lt => ordering == Ordering::Less,
+            Self::Le => ordering != Ordering::Greater,
+            Self::Gt => ordering == Ordering::Greater,
+            Self::Ge => ordering != Ordering::Less,
+        }
+    }
+}
+
+/// One condition of a search query
+#[derive(Debug, Clone, PartialEq)]
+pub struct Term {
+    /// Written with a leading `-`
+    pub negated: bool,
+    /// Column compared, `None` for free text matched against the message
+    pub column: Option<String>,
+    pub op: Op,
+    pub value: String,
+}
+
+/// Parsed search box query, e.g. `unit:nginx.service priority<=3 "connection refused"
+/// -host:db1`. Every term must hold:
+///
+/// - bare words and `"quoted phrases"` match anywhere in the message, ignoring case
+/// - `field:value` (or `field=value`) matches the value exactly; `*` is a wildcard
+///   and `message:` matches anywhere in the message like free text
+/// - `field!=value`, `<`, `<=`, `>` and `>=` compare numbers numerically and
+///   anything else as text; priorities may also be given by name (`priority<=err`)
+/// - a leading `-` negates a term, also matching entries without the field
+///
+/// Fields are journal_logs columns or the display names `unit`, `host`/`hostname`,
+/// `comm`, `pid`, `uid`/`user` and `msg`. Users may be given by login name
+/// (`user:postgres`).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SearchQuery {
+    pub terms: Vec<Term>,
+}
+
+/// Column behind a field name of the search syntax, /api/stats `group_by` or
+/// /api/search `facets`
+pub fn field_column(name: &str) -> &str {
+    match name {
+        "unit" => "_systemd_unit",
+        "hostname" | "host" => "_hostname",
+        "comm" => "_comm",
+        "pid" => "_pid",
+        "uid" | "user" => "_uid",
+        "msg" => "message",
+        other => other,
+    }
+}
+
+/// Whether journal_logs has `column`. The table declares the journal's fields in upper
+/// case, and DuckDB resolves identifiers case-insensitively.
+pub fn has_column(schema: &[(String, String)], column: &str) -> bool {
+    schema
+        .iter()
+        .any(|(name, _)| name.eq_ignore_ascii_case(column))
+}
+
+/// Priority number of a syslog level name
+fn priority_number(name: &str) -> Option<&'static str> {
+    Some(match name.to_lowercase().as_str() {
+        "emerg" | "emergency" => "0",
+        "alert" => "1",
+        "crit" | "critical" => "2",
+        "err" | "error" => "3",
+        "warning" | "warn" => "4",
+        "notice" => "5",
+        "info" => "6",
+        "debug" => "7",
+        _ => return None,
+    })
+}
+
+impl SearchQuery {
+    /// Parse `input`, resolving fields against the journal_logs `schema`
+    pub fn parse(input: &str, schema: &[(String, String)]) -> Result<Self, String> {
+        let mut terms = Vec::new();
+        let mut rest = input.trim_start();
+        while !rest.is_empty() {
+            let (term, after) = parse_term(rest, schema)?;
+            terms.push(term);
+            rest = after.trim_start();
+        }
+        Ok(Self { terms })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    /// SQL predicate over journal_logs with its bind parameters, `None` when there
+    /// are no terms. Values are only ever bound, never spliced into the SQL.
+    pub fn to_sql(&self) -> Option<(String, Vec<SqlParam>)> {
+        if self.terms.is_empty() {
+            return None;
+        }
+        let mut params = Vec::new();
+        let predicates: Vec<String> = self
+            .terms
+            .iter()
+            .map(|term| {
+                let predicate = term.sql(&mut params);
+                if term.negated {
+                    format!("({}) IS NOT TRUE", predicate)
+                } else {
+                    predicate
+                }
+            })
+            .collect();
+        Some((predicates.join(" AND "), params))
+    }
+
+    /// Whether a newly ingested entry matches, as the SQL predicate would
+    pub fn matches(&self, entry: &LogEntry) -> bool {
+        self.terms
+            .iter()
+            .all(|term| term.matches(entry) != term.negated)
+    }
+}
+
+impl Term {
+    /// Free text, or `message:` which means the same
+    fn is_text(&self) -> bool {
+        self.column.as_deref().is_none_or(|c| c == "message") && matches!(self.op, Op::Eq | Op::Ne)
+    }
+
+    fn is_pattern(&self) -> bool {
+        matches!(self.op, Op::Eq | Op::Ne) && self.value.contains('*')
+    }
+
+    fn sql(&self, params: &mut Vec<SqlParam>) -> String {
+        let not = if self.op == Op::Ne { "NOT " } else { "" };
+        if self.is_text() {
+            params.push(SqlParam::Text(format!("%{}%", escape_like(&self.value))));
+            return format!("message {}ILIKE ? ESCAPE '\\'", not);
+        }
+        let column = match self.column.as_deref() {
+            Some("source") => format!("COALESCE(source, '{}')", LOCAL_SOURCE),
+            Some(column) => format!("CAST({} AS VARCHAR)", quote_ident(column)),
+            None => unreachable!("free text is handled above"),
+        };
+        if self.is_pattern() {
+            let pattern: Vec<String> = self.value.split('*').map(escape_like).collect();
+            params.push(SqlParam::Text(pattern.join("%")));
+            return format!("{} {}LIKE ? ESCAPE '\\'", column, not);
+        }
+        if matches!(self.op, Op::Eq | Op::Ne) {
+            params.push(SqlParam::Text(self.value.clone()));
+            return format!("{} {} ?", column, self.op.sql());
+        }
+        let column = self.column.as_deref().map(quote_ident).unwrap_or_default();
+        if let Ok(number) = self.value.parse::<i64>() {
+            params.push(SqlParam::BigInt(number));
+            format!("TRY_CAST({} AS BIGINT) {} ?", column, self.op.sql())
+        } else if let Ok(number) = self.value.parse::<f64>() {
+            params.push(SqlParam::Double(number));
+            format!("TRY_CAST({} AS DOUBLE) {} ?", column, self.op.sql())
+        } else {
+            params.push(SqlParam::Text(self.value.clone()));
+            format!("CAST({} AS VARCHAR) {} ?", column, self.op.sql())
+        }
+    }
+
+    fn matches(&self, entry: &LogEntry) -> bool {
+        let timestamp;
+        let value = match self.column.as_deref() {
+            None | Some("message") => entry.get_message().map(String::as_str),
+            Some("source") => Some(entry.source.as_str()),
+            Some("timestamp") => {
+                timestamp = entry.timestamp.format("%Y-%m-%d %H:%M:%S%.6f").to_string();
+                Some(timestamp.as_str())
+            }
+            Some(column) => entry.get_field(&column.to_uppercase()).map(String::as_str),
+        };
+        let Some(value) = value else {
+            return false;
+        };
+
+        if self.is_text() {
+            let found = value.to_lowercase().contains(&self.value.to_lowercase());
+            return found == (self.op == Op::Eq);
+        }
+        if self.is_pattern() {
+            return glob_match(&self.value, value) == (self.op == Op::Eq);
+        }
+        let ordering = match (value.parse::<f64>(), self.value.parse::<f64>()) {
+            (Ok(a), Ok(b)) if !matches!(self.op, Op::Eq | Op::Ne) => a.partial_cmp(&b),
+            _ => Some(value.cmp(self.value.as_str())),
+        };
+        ordering.is_some_and(|ordering| self.op.holds(ordering))
+    }
+}
+
+/// Parse the term at the start of `input`, returning it and the remaining input
+fn parse_term<'a>(input: &'a str, schema: &[(String, String)]) -> Result<(Term, &'a str), String> {
+    let (negated, input) = match input.strip_prefix('-') {
+        Some(rest) if rest.starts_with(|c: char| !c.is_whitespace()) => (true, rest),
+        _ => (false, input),
+    };
+
+    let name_len = input
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .unwrap_or(input.len());
+    let after_name = &input[name_len..];
+    let op = [
+        ("!=", Op::Ne),
+        ("<=", Op::Le),
+        (">=", Op::Ge),
+        (":", Op::Eq),
+        ("=", Op::Eq),
+        ("<", Op::Lt),
+        (">", Op::Gt),
+    ]
+    .into_iter()
+    .find(|(token, _)| after_name.starts_with(token));
+
+    // A prefix naming no known field, like `http:` or `error:`, is part of the text
+    let name = &input[..name_len];
+    let column = field_column(name);
+    let Some((token, op)) = op.filter(|_| name_len > 0 && has_column(schema, column)) else {
+        let (value, rest) = parse_value(input)?;
+        let term = Term {
+            negated,
+            column: None,
+            op: Op::Eq,
+            value,
+        };
+        return Ok((term, rest));
+    };
+
+    let (mut value, rest) = parse_value(&after_name[token.len()..])?;
+    if value.is_empty() {
+        return Err(format!("Missing value for {}", name));
+    }
+    if column == "priority"
+        && let Some(number) = priority_number(&value)
+    {
+        value = number.to_string();
+    }
+    if column == "_uid"
+        && !value.contains('*')
+        && value.parse::<u32>().is_err()
+        && let Some(uid) = user_names::user_id(&value)
+    {
+        value = uid.to_string();
+    }
+    let term = Term {
+        negated,
+        column: Some(column.to_string()),
+        op,
+        value,
+    };
+    Ok((term, rest))
+}
+
+/// A `"quoted value"` (with `\"` and `\\` escapes) or a word running to the next space
+fn parse_value(input: &str) -> Result<(String, &str), String> {
+    let Some(quoted) = input.strip_prefix('"') else {
+        let end = input.find(char::is_whitespace).unwrap_or(input.len());
+        return Ok((input[..end].to_string(), &input[end..]));
+    };
+    let mut value = String::new();
+    let mut chars = quoted.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Ok((value, &quoted[i + 1..])),
+            '\\' => {
+                if let Some((_, escaped)) = chars.next() {
+                    value.push(escaped);
+                }
+            }
+            c => value.push(c),
+        }
+    }
+    Err("Unterminated quote in query".to_string())
+}
+
+/// Escape LIKE wildcards for safe SQL queries
+pub(crate) fn escape_like(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// Match `value` against `pattern`, where `*` stands for any run of characters
+pub(crate) fn glob_match(pattern: &str, value: &str) -> bool {
+    let parts: Vec<&str> = pattern.split('*').collect();
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if parts.len() == 1 {
+        return value == pattern;
+    }
+    if value.len() < first.len() + last.len() || !value.starts_with(first) || !value.ends_with(last)
+    {
+        return false;
+    }
+    let mut rest = &value[first.len()..value.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn schema() -> Vec<(String, String)> {
+        [
+            "timestamp",
+            "message",
+            "priority",
+            "_hostname",
+            "_systemd_unit",
+            "_comm",
+            "source",
+        ]
+        .iter()
+        .map(|c| (c.to_string(), "VARCHAR".to_string()))
+        .collect()
+    }
+
+    fn term(negated: bool, column: Option<&str>, op: Op, value: &str) -> Term {
+        Term {
+            negated,
+            column: column.map(String::from),
+            op,
+            value: value.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_escape_like() {
+        assert_eq!(escape_like("test"), "test");
+        assert_eq!(escape_like("test%value"), "test\\%value");
+        assert_eq!(escape_like("test_value"), "test\\_value");
+        assert_eq!(escape_like("test\\value"), "test\\\\value");
+    }
+
+    #[test]
+    fn test_parse_terms() {
+        let query = SearchQuery::parse(
+            r#"unit:nginx.service priority<=err "connection refused" -host:db1 timeout"#,
+            &schema(),
+        )
+        .unwrap();
+        assert_eq!(
+            query.terms,
+            vec![
+                term(false, Some("_systemd_unit"), Op::Eq, "nginx.service"),
+                term(false, Some("priority"), Op::Le, "3"),
+                term(false, None, Op::Eq, "connection refused"),
+                term(true, Some("_hostname"), Op::Eq, "db1"),
+                term(false, None, Op::Eq, "timeout"),
+            ]
+        );
+
+        assert!(SearchQuery::parse("", &schema()).unwrap().is_empty());
+        assert_eq!(
+            SearchQuery::parse(r#"- "50%" 'x'"#, &schema())
+                .unwrap()
+                .terms,
+            vec![
+                term(false, None, Op::Eq, "-"),
+                term(false, None, Op::Eq, "50%"),
+                term(false, None, Op::Eq, "'x'"),
+            ]
+        );
+        let with_uid = [schema(), vec![("_uid".to_string(), "INTEGER".to_string())]].concat();
+        assert_eq!(
+            SearchQuery::parse("user:root -uid:1000", &with_uid)
+                .unwrap()
+                .terms,
+            vec![
+                term(false, Some("_uid"), Op::Eq, "0"),
+                term(true, Some("_uid"), Op::Eq, "1000"),
+            ]
+        );
+        assert_eq!(
+            SearchQuery::parse("http://example.com error: disk", &schema())
+                .unwrap()
+                .terms,
+            vec![
+                term(false, None, Op::Eq, "http://example.com"),
+                term(false, None, Op::Eq, "error:"),
+                term(false, None, Op::Eq, "disk"),
+            ]
+        );
+        assert!(SearchQuery::parse("unit:", &schema()).is_err());
+        assert!(SearchQuery::parse(r#""open"#, &schema()).is_err());
+    }
+
+    #[test]
+    fn test_sql_binds_values() {
+        let query = SearchQuery::parse(
+            r#"unit:web* -host:"db'1" priority<=3 msg:50% pid>x"#,
+            &[schema(), vec![("_pid".to_string(), "BIGINT".to_string())]].concat(),
+        )
+        .unwrap();
+        let (sql, params) = query.to_sql().unwrap();
+        assert!(!sql.contains("db'1"));
+        assert_eq!(
+            sql,
+            "CAST(\"_systemd_unit\" AS VARCHAR) LIKE ? ESCAPE '\\' \
+             AND (CAST(\"_hostname\" AS VARCHAR) = ?) IS NOT TRUE \
+             AND TRY_CAST(\"priority\" AS BIGINT) <= ? \
+             AND message ILIKE ? ESCAPE '\\' \
+             AND CAST(\"_pid\" AS VARCHAR) > ?"
+        );
+        assert_eq!(
+            params,
+            vec![
+                SqlParam::Text("web%".to_string()),
+                SqlParam::Text("db'1".to_string()),
+                SqlParam::BigInt(3),
+                SqlParam::Text("%50\\%%".to_string()),
+                SqlParam::Text("x".to_string()),
+            ]
+        );
+        assert!(SearchQuery::default().to_sql().is_none());
+    }
+
+    #[test]
+    fn test_matches_entries() {
+        let fields = [
+            ("MESSAGE", "Connection Refused by peer"),
+            ("_HOSTNAME", "web1"),
+            ("_SYSTEMD_UNIT", "nginx.service"),
+            ("PRIORITY", "3"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+        let entry = LogEntry::new(Utc::now(), fields);
+        let matches = |query: &str| {
+            SearchQuery::parse(query, &schema())
+                .unwrap()
+                .matches(&entry)
+        };
+
+        assert!(matches(""));
+        assert!(matches(
+            r#"unit:nginx.service "connection refused" priority<=4"#
+        ));
+        assert!(matches("unit:nginx* -host:db1 source:local"));
+        assert!(matches("priority>2 priority!=4 host:*1"));
+        assert!(!matches("priority<3"));
+        assert!(!matches("-unit:*.service"));
+        assert!(!matches("refused timeout"));
+        assert!(!matches("source:remote"));
+        // Entries without the field only match negated terms
+        assert!(!matches("comm:app"));
+        assert!(matches("-comm:app -timestamp:x"));
+    }
+}
@@ -0,0 +1,96 @@
+//! Silences: matchers plus a time window during which matching alerts are still
+//! recorded and logged, but not sent to any notification channel. They are meant for
+//! planned maintenance.
+
+use crate::notifications::AlertEvent;
+use crate::search_query::glob_match;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Silence {
+    pub id: i64,
+    /// Rule name to match; `*` stands for any run of characters
+    pub rule: Option<String>,
+    /// Alert source to match, e.g. `log` or `process`
+    pub source: Option<String>,
+    /// Tag the rule must carry
+    pub tag: Option<String>,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+    pub comment: String,
+    /// User who created the silence, when authentication is on
+    pub created_by: Option<String>,
+}
+
+impl Silence {
+    /// Whether the silence is in effect at `at`
+    pub fn is_active(&self, at: DateTime<Utc>) -> bool {
+        self.starts_at <= at && at < self.ends_at
+    }
+
+    /// Whether `alert` falls in the window and meets every matcher that is set
+    pub fn matches(&self, alert: &AlertEvent) -> bool {
+        self.is_active(alert.timestamp)
+            && self
+                .rule
+                .as_ref()
+                .is_none_or(|pattern| glob_match(pattern, &alert.rule))
+            && self
+                .source
+                .as_ref()
+                .is_none_or(|source| *source == alert.source)
+            && self.tag.as_ref().is_none_or(|tag| alert.tags.contains(tag))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notifications::AlertState;
+    use chrono::TimeDelta;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_silence_matches() {
+        let at = |minute| DateTime::<Utc>::UNIX_EPOCH + TimeDelta::minutes(minute);
+        let alert = |rule: &str, minute| AlertEvent {
+            timestamp: at(minute),
+            rule: rule.to_string(),
+            source: "log".to_string(),
+            state: AlertState::Firing,
+            value: 12.0,
+            threshold: 10.0,
+            message: "12 entries matched in the last 5m (threshold 10)".to_string(),
+            samples: Vec::new(),
+            link: None,
+            tags: vec!["db".to_string()],
+            details: BTreeMap::new(),
+        };
+        let silence = Silence {
+            id: 1,
+            rule: Some("postgres *".to_string()),
+            source: None,
+            tag: Some("db".to_string()),
+            starts_at: at(10),
+            ends_at: at(70),
+            comment: "upgrade".to_string(),
+            created_by: None,
+        };
+        assert!(silence.matches(&alert("postgres errors", 10)));
+        assert!(!silence.matches(&alert("postgres errors", 9)));
+        assert!(!silence.matches(&alert("postgres errors", 70)));
+        assert!(!silence.matches(&alert("nginx errors", 30)));
+
+        let other_tag = Silence {
+            tag: Some("web".to_string()),
+            ..silence.clone()
+        };
+        assert!(!other_tag.matches(&alert("postgres errors", 30)));
+        let process_only = Silence {
+            source: Some("process".to_string()),
+            ..silence
+        };
+        assert!(!process_only.matches(&alert("postgres errors", 30)));
+    }
+}
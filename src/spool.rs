@@ -0,0 +1,279 @@
+//! On-disk spool for agent mode: entries wait here, as JSON lines in numbered segment
+//! files, until the server has acknowledged them. The spool is capped in size; when a
+//! long outage fills it, the oldest segments are dropped.
+//!
+//! `position` records the segment and byte offset up to which entries have been
+//! delivered, so a restart resumes with the first unacknowledged entry.
+
+use crate::log_entry::LogEntry;
+use anyhow::{Context, Result};
+use log::warn;
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Size at which a new segment is started
+const SEGMENT_BYTES: u64 = 4 * 1024 * 1024;
+const SEGMENT_EXTENSION: &str = "jsonl";
+const POSITION_FILE: &str = "position";
+
+pub struct Spool {
+    dir: PathBuf,
+    max_bytes: u64,
+    /// Segment numbers with their sizes, oldest first; entries are appended to the last
+    segments: VecDeque<(u64, u64)>,
+    writer: File,
+    /// Offset in the oldest segment up to which entries were delivered
+    read_offset: u64,
+    /// Offset in the oldest segment after the batch last read, until it is acknowledged
+    pending_offset: Option<u64>,
+    /// Entries dropped because the spool was full
+    dropped: u64,
+}
+
+fn segment_path(dir: &Path, segment: u64) -> PathBuf {
+    dir.join(format!("{:020}.{}", segment, SEGMENT_EXTENSION))
+}
+
+impl Spool {
+    /// Open the spool in `dir`, creating it if needed. Appends go to a fresh segment,
+    /// so a line cut short by a crash is never continued.
+    pub fn open(dir: &Path, max_bytes: u64) -> Result<Self> {
+        fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+        let mut segments = Vec::new();
+        for file in fs::read_dir(dir)? {
+            let path = file?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some(SEGMENT_EXTENSION) {
+                continue;
+            }
+            if let Some(segment) = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.parse::<u64>().ok())
+            {
+                segments.push((segment, fs::metadata(&path)?.len()));
+            }
+        }
+        segments.sort();
+        let mut segments = VecDeque::from(segments);
+
+        let (mut position_segment, mut read_offset) = (0, 0);
+        if let Ok(position) = fs::read_to_string(dir.join(POSITION_FILE))
+            && let Some((segment, offset)) = position.trim().split_once(' ')
+        {
+            position_segment = segment.parse().unwrap_or(0);
+            read_offset = offset.parse().unwrap_or(0);
+        }
+        // Segments delivered before the position was last saved
+        while segments
+            .front()
+            .is_some_and(|(segment, _)| *segment < position_segment)
+        {
+            let (segment, _) = segments.pop_front().unwrap();
+            fs::remove_file(segment_path(dir, segment))?;
+        }
+        if segments
+            .front()
+            .is_none_or(|(segment, _)| *segment != position_segment)
+        {
+            read_offset = 0;
+        }
+
+        let next = segments.back().map_or(0, |(segment, _)| segment + 1);
+        let writer = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(segment_path(dir, next))?;
+        segments.push_back((next, 0));
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            max_bytes,
+            segments,
+            writer,
+            read_offset,
+            pending_offset: None,
+            dropped: 0,
+        })
+    }
+
+    /// Bytes held, delivered or not
+    pub fn size(&self) -> u64 {
+        self.segments.iter().map(|(_, size)| size).sum()
+    }
+
+    /// Entries dropped so far because the spool was full
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Append `entries`, dropping the oldest segments if that takes the spool over
+    /// its size limit
+    pub fn append(&mut self, entries: &[LogEntry]) -> Result<()> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+        let mut lines = Vec::new();
+        for entry in entries {
+            serde_json::to_writer(&mut lines, entry)?;
+            lines.push(b'\n');
+        }
+        if self
+            .segments
+            .back()
+            .is_some_and(|(_, size)| *size >= SEGMENT_BYTES)
+        {
+            let next = self.segments.back().map_or(0, |(segment, _)| segment + 1);
+            self.writer = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(segment_path(&self.dir, next))?;
+            self.segments.push_back((next, 0));
+        }
+        self.writer.write_all(&lines)?;
+        if let Some((_, size)) = self.segments.back_mut() {
+            *size += lines.len() as u64;
+        }
+
+        while self.size() > self.max_bytes && self.segments.len() > 1 {
+            let (segment, _) = self.segments.pop_front().unwrap();
+            let path = segment_path(&self.dir, segment);
+            let mut file = File::open(&path)?;
+            file.seek(SeekFrom::Start(self.read_offset))?;
+            let lost = BufReader::new(file).lines().count();
+            fs::remove_file(&path)?;
+            self.dropped += lost as u64;
+            self.read_offset = 0;
+            self.pending_offset = None;
+            warn!(
+                "Spool is full ({} bytes), dropped {} undelivered entries",
+                self.max_bytes, lost
+            );
+        }
+        Ok(())
+    }
+
+    /// Up to `limit` of the oldest undelivered entries; they are read again until
+    /// [`Spool::acknowledge`] is called
+    pub fn read_batch(&mut self, limit: usize) -> Result<Vec<LogEntry>> {
+        loop {
+            let Some(&(segment, size)) = self.segments.front() else {
+                return Ok(Vec::new());
+            };
+            let is_current = self.segments.len() == 1;
+            if self.read_offset >= size && !is_current {
+                // Fully delivered and no longer written to
+                self.segments.pop_front();
+                fs::remove_file(segment_path(&self.dir, segment))?;
+                self.read_offset = 0;
+                self.save_position()?;
+                continue;
+            }
+
+            let mut file = File::open(segment_path(&self.dir, segment))?;
+            file.seek(SeekFrom::Start(self.read_offset))?;
+            let mut reader = BufReader::new(file);
+            let mut entries = Vec::new();
+            let mut offset = self.read_offset;
+            let mut line = String::new();
+            while entries.len() < limit && offset < size {
+                line.clear();
+                let read = reader.read_line(&mut line)?;
+                if read == 0 {
+                    break;
+                }
+                offset += read as u64;
+                match serde_json::from_str::<LogEntry>(&line) {
+                    Ok(entry) => entries.push(entry),
+                    Err(e) => warn!("Skipping unreadable spooled entry: {}", e),
+                }
+            }
+            self.pending_offset = Some(offset);
+            if entries.is_empty() && offset >= size && !is_current {
+                // Only unreadable lines were left
+                self.read_offset = offset;
+                continue;
+            }
+            return Ok(entries);
+        }
+    }
+
+    /// Mark the batch last read as delivered
+    pub fn acknowledge(&mut self) -> Result<()> {
+        if let Some(offset) = self.pending_offset.take() {
+            self.read_offset = offset;
+            self.save_position()?;
+        }
+        Ok(())
+    }
+
+    fn save_position(&self) -> Result<()> {
+        let segment = self.segments.front().map_or(0, |(segment, _)| *segment);
+        let temp = self.dir.join(format!("{}.tmp", POSITION_FILE));
+        fs::write(&temp, format!("{} {}\n", segment, self.read_offset))?;
+        fs::rename(&temp, self.dir.join(POSITION_FILE))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use std::collections::HashMap;
+    use tempfile::TempDir;
+
+    fn entries(messages: &[&str]) -> Vec<LogEntry> {
+        messages
+            .iter()
+            .map(|message| {
+                let fields = HashMap::from([("MESSAGE".to_string(), message.to_string())]);
+                LogEntry::new(Utc::now(), fields)
+            })
+            .collect()
+    }
+
+    fn messages(entries: &[LogEntry]) -> Vec<String> {
+        entries
+            .iter()
+            .filter_map(|entry| entry.get_message().cloned())
+            .collect()
+    }
+
+    #[test]
+    fn test_batches_survive_restarts_until_acknowledged() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut spool = Spool::open(temp_dir.path(), 1 << 20).unwrap();
+        spool.append(&entries(&["one", "two", "three"])).unwrap();
+
+        assert_eq!(messages(&spool.read_batch(2).unwrap()), ["one", "two"]);
+        spool.acknowledge().unwrap();
+        // Read but not acknowledged, so read again after a restart
+        assert_eq!(messages(&spool.read_batch(2).unwrap()), ["three"]);
+        drop(spool);
+
+        let mut spool = Spool::open(temp_dir.path(), 1 << 20).unwrap();
+        spool.append(&entries(&["four"])).unwrap();
+        assert_eq!(messages(&spool.read_batch(10).unwrap()), ["three"]);
+        spool.acknowledge().unwrap();
+        // The first segment is done with and removed
+        assert_eq!(messages(&spool.read_batch(10).unwrap()), ["four"]);
+        spool.acknowledge().unwrap();
+        assert!(spool.read_batch(10).unwrap().is_empty());
+        assert_eq!(spool.segments.len(), 1);
+    }
+
+    #[test]
+    fn test_full_spool_drops_oldest_segments() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut spool = Spool::open(temp_dir.path(), 1).unwrap();
+        spool.append(&entries(&["old"])).unwrap();
+        drop(spool);
+
+        // Over the limit, the segment of the previous run goes
+        let mut spool = Spool::open(temp_dir.path(), 1).unwrap();
+        spool.append(&entries(&["new"])).unwrap();
+        assert_eq!(spool.dropped(), 1);
+        assert_eq!(messages(&spool.read_batch(10).unwrap()), ["new"]);
+    }
+}
@@ -1,10 +1,38 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
 use std::fs::OpenOptions;
 use std::io::{BufWriter, Write};
 use std::path::Path;
 use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
 
 static SQL_TRACE_WRITER: OnceLock<Mutex<BufWriter<std::fs::File>>> = OnceLock::new();
 
+/// API statements kept in memory for /api/debug/queries
+const QUERY_LOG_CAPACITY: usize = 1000;
+
+static QUERY_LOG: Mutex<QueryLog> = Mutex::new(QueryLog {
+    next_id: 1,
+    records: VecDeque::new(),
+});
+
+struct QueryLog {
+    next_id: u64,
+    records: VecDeque<QueryRecord>,
+}
+
+/// One statement run for a web request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryRecord {
+    /// Increases by one per statement, so consumers can tell which ones are new
+    pub id: u64,
+    pub started_at: DateTime<Utc>,
+    pub sql: String,
+    pub duration_ms: f64,
+    pub rows: usize,
+}
+
 pub fn init_sql_trace(path: &Path) -> std::io::Result<()> {
     if SQL_TRACE_WRITER.get().is_some() {
         return Ok(());
@@ -41,3 +69,61 @@ pub fn trace_sql(sql: &str) {
         let _ = writer.flush();
     }
 }
+
+/// Keep `sql` in the query log, dropping the oldest statement once
+/// [`QUERY_LOG_CAPACITY`] are kept
+pub fn log_query(sql: &str, duration: Duration, rows: usize) {
+    let now = Utc::now();
+    let mut log = QUERY_LOG.lock().unwrap();
+    let id = log.next_id;
+    log.next_id += 1;
+    if log.records.len() >= QUERY_LOG_CAPACITY {
+        log.records.pop_front();
+    }
+    log.records.push_back(QueryRecord {
+        id,
+        started_at: now - duration,
+        sql: sql.trim().to_string(),
+        duration_ms: duration.as_secs_f64() * 1000.0,
+        rows,
+    });
+}
+
+/// Logged statements with an id above `after`, oldest first
+pub fn logged_queries(after: u64) -> Vec<QueryRecord> {
+    let log = QUERY_LOG.lock().unwrap();
+    log.records
+        .iter()
+        .filter(|record| record.id > after)
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_log_keeps_latest_statements() {
+        let before = logged_queries(0).last().map_or(0, |record| record.id);
+        log_query(" SELECT 'query log test' ", Duration::from_millis(12), 3);
+
+        let logged: Vec<QueryRecord> = logged_queries(before)
+            .into_iter()
+            .filter(|record| record.sql == "SELECT 'query log test'")
+            .collect();
+        assert_eq!(logged.len(), 1);
+        assert_eq!(logged[0].rows, 3);
+        assert_eq!(logged[0].duration_ms, 12.0);
+
+        for _ in 0..QUERY_LOG_CAPACITY {
+            log_query("SELECT 'filler'", Duration::ZERO, 0);
+        }
+        assert!(logged_queries(0).len() <= QUERY_LOG_CAPACITY);
+        assert!(
+            logged_queries(logged[0].id - 1)
+                .iter()
+                .all(|r| r.id != logged[0].id)
+        );
+    }
+}
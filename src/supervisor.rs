@@ -0,0 +1,292 @@
+//! Supervision of the collector's own threads. A supervised thread that panics is
+//! started again with the state it had (its channel, its counters) once the shared
+//! locks it may have poisoned are cleared, instead of its subsystem silently stopping
+//! for good. Threads mark when they start and finish a piece of work, and one busy
+//! for too long is reported as stalled. A stuck thread cannot be stopped, so it is
+//! left to resume if what it waits on frees up; the main loop itself is watched by
+//! the systemd watchdog, and its periodic cleanup runs under [`Supervisor::run_once`].
+
+use chrono::Utc;
+use log::{error, info};
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// When a supervised thread started its current piece of work
+#[derive(Clone, Default)]
+pub struct Heartbeat(Arc<AtomicI64>);
+
+impl Heartbeat {
+    /// Mark the start of a piece of work
+    pub fn busy(&self) {
+        self.0
+            .store(Utc::now().timestamp_micros(), Ordering::Relaxed);
+    }
+
+    /// Mark the thread as waiting for work
+    pub fn idle(&self) {
+        self.0.store(0, Ordering::Relaxed);
+    }
+
+    fn busy_for(&self) -> Option<Duration> {
+        match self.0.load(Ordering::Relaxed) {
+            0 => None,
+            micros => Some(Duration::from_micros(
+                (Utc::now().timestamp_micros() - micros).max(0) as u64,
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Incident {
+    /// The thread panicked and was started again
+    Restarted { thread: String, panic: String },
+    /// The thread has been busy with one piece of work for this long
+    Stalled { thread: String, busy_for: Duration },
+    /// A stalled thread finished its work
+    Recovered { thread: String },
+}
+
+struct Watched {
+    name: String,
+    heartbeat: Heartbeat,
+    stalled: bool,
+}
+
+type ClearPoison = Arc<dyn Fn() + Send + Sync>;
+
+#[derive(Default)]
+struct Shared {
+    threads: Mutex<Vec<Watched>>,
+    /// Restarts not yet reported by [`Supervisor::check`]
+    restarts: Mutex<Vec<Incident>>,
+    locks: Mutex<Vec<ClearPoison>>,
+}
+
+/// Starts supervised threads and reports on them; clones share the same threads
+#[derive(Clone, Default)]
+pub struct Supervisor {
+    shared: Arc<Shared>,
+}
+
+impl Supervisor {
+    /// Clear the poisoning of `mutex` before restarting a thread that panicked, so the
+    /// other users of the lock carry on
+    pub fn with_shared_lock<T: Send + 'static>(self, mutex: &Arc<Mutex<T>>) -> Self {
+        self.with_recovered_lock(mutex, |_| {})
+    }
+
+    /// Like [`Self::with_shared_lock`], running `recover` on the value when a panic
+    /// poisoned it, to undo what the panicking thread left half done
+    pub fn with_recovered_lock<T: Send + 'static>(
+        self,
+        mutex: &Arc<Mutex<T>>,
+        recover: impl Fn(&mut T) + Send + Sync + 'static,
+    ) -> Self {
+        let mutex = mutex.clone();
+        self.shared.locks.lock().unwrap().push(Arc::new(move || {
+            if mutex.is_poisoned() {
+                mutex.clear_poison();
+                recover(&mut mutex.lock().unwrap());
+            }
+        }));
+        self
+    }
+
+    /// Watch work the calling thread does under `name`, reporting it stalled like a
+    /// spawned thread
+    pub fn watch(&self, name: impl Into<String>) -> Heartbeat {
+        let heartbeat = Heartbeat::default();
+        self.shared.threads.lock().unwrap().push(Watched {
+            name: name.into(),
+            heartbeat: heartbeat.clone(),
+            stalled: false,
+        });
+        heartbeat
+    }
+
+    /// Run one piece of work on the calling thread under `heartbeat`. A panic is
+    /// caught and reported as a restart, with the shared locks cleared, and `None`
+    /// returned so the work can be tried again on its next run.
+    pub fn run_once<R>(
+        &self,
+        name: &str,
+        heartbeat: &Heartbeat,
+        work: impl FnOnce() -> R,
+    ) -> Option<R> {
+        heartbeat.busy();
+        let result = panic::catch_unwind(AssertUnwindSafe(work));
+        heartbeat.idle();
+        match result {
+            Ok(value) => Some(value),
+            Err(payload) => {
+                recover(&self.shared, name, payload.as_ref());
+                None
+            }
+        }
+    }
+
+    /// Run `body` on a new thread, running it again whenever it panics. The thread
+    /// ends when `body` returns.
+    pub fn spawn<F>(&self, name: impl Into<String>, mut body: F) -> thread::JoinHandle<()>
+    where
+        F: FnMut(&Heartbeat) + Send + 'static,
+    {
+        let name = name.into();
+        let heartbeat = self.watch(name.clone());
+        let shared = self.shared.clone();
+        thread::spawn(move || {
+            let mut restarts = 0;
+            while let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| body(&heartbeat))) {
+                restarts += 1;
+                heartbeat.idle();
+                recover(&shared, &name, payload.as_ref());
+                // Backs off when it keeps panicking
+                thread::sleep(Duration::from_secs(restarts.min(60)));
+                info!("Restarting {} thread", name);
+            }
+        })
+    }
+
+    /// Restarts since the last check, and threads that became stalled (busy for more
+    /// than `stall_after`) or recovered since then
+    pub fn check(&self, stall_after: Duration) -> Vec<Incident> {
+        let mut incidents: Vec<_> = self.shared.restarts.lock().unwrap().drain(..).collect();
+        for watched in self.shared.threads.lock().unwrap().iter_mut() {
+            let busy_for = watched.heartbeat.busy_for();
+            let stalled = busy_for.is_some_and(|busy_for| busy_for > stall_after);
+            if stalled && !watched.stalled {
+                incidents.push(Incident::Stalled {
+                    thread: watched.name.clone(),
+                    busy_for: busy_for.unwrap_or_default(),
+                });
+            } else if !stalled && watched.stalled {
+                incidents.push(Incident::Recovered {
+                    thread: watched.name.clone(),
+                });
+            }
+            watched.stalled = stalled;
+        }
+        incidents
+    }
+
+    /// Threads currently stalled
+    pub fn stalled(&self) -> Vec<String> {
+        let threads = self.shared.threads.lock().unwrap();
+        threads
+            .iter()
+            .filter(|watched| watched.stalled)
+            .map(|watched| watched.name.clone())
+            .collect()
+    }
+}
+
+/// Report `name`'s panic and clear the locks it may have poisoned
+fn recover(shared: &Shared, name: &str, payload: &(dyn Any + Send)) {
+    let message = panic_message(payload);
+    error!("{} thread panicked: {}; restarting it", name, message);
+    for clear_poison in shared.locks.lock().unwrap().iter() {
+        clear_poison();
+    }
+    shared.restarts.lock().unwrap().push(Incident::Restarted {
+        thread: name.to_string(),
+        panic: message,
+    });
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[test]
+    fn test_panicked_thread_restarts_and_stalls_are_reported() {
+        let shared = Arc::new(Mutex::new(0));
+        let supervisor = Supervisor::default().with_shared_lock(&shared);
+
+        let counter = shared.clone();
+        let mut runs = 0;
+        let handle = supervisor.spawn("flaky", move |_| {
+            runs += 1;
+            let mut count = counter.lock().unwrap();
+            *count += 1;
+            if runs == 1 {
+                panic!("first run fails");
+            }
+        });
+        handle.join().unwrap();
+        // The lock the panic poisoned was cleared before the second run
+        assert_eq!(*shared.lock().unwrap(), 2);
+
+        let (sender, receiver) = mpsc::channel::<()>();
+        let stuck = supervisor.spawn("stuck", move |heartbeat| {
+            heartbeat.busy();
+            let _ = receiver.recv();
+            heartbeat.idle();
+        });
+        let mut incidents = Vec::new();
+        while supervisor.stalled().is_empty() {
+            incidents.extend(supervisor.check(Duration::ZERO));
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert!(incidents.contains(&Incident::Restarted {
+            thread: "flaky".to_string(),
+            panic: "first run fails".to_string(),
+        }));
+        assert!(
+            incidents
+                .iter()
+                .any(|i| matches!(i, Incident::Stalled { thread, .. } if thread == "stuck"))
+        );
+        assert_eq!(supervisor.stalled(), ["stuck"]);
+
+        drop(sender);
+        stuck.join().unwrap();
+        assert_eq!(
+            supervisor.check(Duration::ZERO),
+            [Incident::Recovered {
+                thread: "stuck".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_work_panicking_on_the_calling_thread_recovers_its_lock() {
+        let open = Arc::new(Mutex::new(Vec::<&str>::new()));
+        let supervisor =
+            Supervisor::default().with_recovered_lock(&open, |open: &mut Vec<&str>| open.clear());
+        let heartbeat = supervisor.watch("cleanup");
+
+        let half_done = open.clone();
+        let result = supervisor.run_once("cleanup", &heartbeat, move || {
+            let mut open = half_done.lock().unwrap();
+            open.push("transaction");
+            panic!("cleanup fails");
+        });
+        assert_eq!(result, None);
+        // The poisoning was cleared and what the panic left behind undone
+        assert!(open.lock().unwrap().is_empty());
+        assert_eq!(
+            supervisor.check(Duration::ZERO),
+            [Incident::Restarted {
+                thread: "cleanup".to_string(),
+                panic: "cleanup fails".to_string(),
+            }]
+        );
+
+        assert_eq!(supervisor.run_once("cleanup", &heartbeat, || 1), Some(1));
+        assert!(supervisor.check(Duration::ZERO).is_empty());
+    }
+}
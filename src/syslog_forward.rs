@@ -0,0 +1,326 @@
+//! Syslog output: mirrors entries to a remote syslog receiver (e.g. a SIEM) as RFC 5424
+//! messages, over UDP, TCP or TLS. Over TCP and TLS, messages are framed by octet
+//! counting (RFC 6587, RFC 5425) on one connection that is reopened after a failure.
+//! Over UDP, messages longer than a datagram receivers must accept are cut short.
+
+use crate::log_entry::LogEntry;
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::io::Write as _;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::sync::Mutex;
+use tokio_rustls::TlsConnector;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName};
+use tokio_rustls::rustls::{ClientConfig, RootCertStore, crypto};
+
+/// Severity of entries without a `PRIORITY`
+const DEFAULT_SEVERITY: u8 = 6;
+
+/// Longest UDP message receivers should accept (RFC 5426); longer ones could fail to
+/// send every time, holding back the output
+const MAX_DATAGRAM_BYTES: usize = 2048;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SyslogTransport {
+    #[default]
+    Udp,
+    Tcp,
+    Tls,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SyslogSettings {
+    /// Receiver as "host:port", e.g. "siem.example.com:6514"
+    pub address: String,
+
+    #[serde(default)]
+    pub transport: SyslogTransport,
+
+    /// PEM file with the CA certificates the receiver's certificate is checked
+    /// against over TLS; without one, the public web roots are trusted
+    #[serde(default)]
+    pub ca_file: Option<PathBuf>,
+
+    /// Facility of entries without `SYSLOG_FACILITY` (1 = user, 4 = auth, 16..23 =
+    /// local0..local7)
+    #[serde(default = "default_facility")]
+    pub facility: u8,
+}
+
+fn default_facility() -> u8 {
+    1
+}
+
+/// Header field of a syslog message: printable ASCII without spaces, cut to `max`
+/// characters, `-` when empty
+fn header_field(value: Option<&String>, max: usize) -> String {
+    let field: String = value
+        .map(|value| {
+            value
+                .chars()
+                .filter(|c| c.is_ascii_graphic())
+                .take(max)
+                .collect()
+        })
+        .unwrap_or_default();
+    if field.is_empty() {
+        "-".to_string()
+    } else {
+        field
+    }
+}
+
+/// `entry` as an RFC 5424 message
+fn format_message(entry: &LogEntry, default_facility: u8) -> String {
+    let facility = entry
+        .get_syslog_facility()
+        .and_then(|facility| facility.parse::<u8>().ok())
+        .filter(|facility| *facility < 24)
+        .unwrap_or(default_facility.min(23));
+    let severity = entry
+        .get_priority()
+        .and_then(|priority| priority.parse::<u8>().ok())
+        .filter(|severity| *severity < 8)
+        .unwrap_or(DEFAULT_SEVERITY);
+    let app_name = entry.get_syslog_identifier().or_else(|| entry.get_comm());
+    let proc_id = entry.get_pid().or_else(|| entry.get_syslog_pid());
+    format!(
+        "<{}>1 {} {} {} {} {} - {}",
+        facility as u16 * 8 + severity as u16,
+        entry
+            .timestamp
+            .to_rfc3339_opts(chrono::SecondsFormat::Micros, true),
+        header_field(entry.get_hostname(), 255),
+        header_field(app_name, 48),
+        header_field(proc_id, 128),
+        header_field(entry.get_message_id(), 32),
+        entry.get_message().map(String::as_str).unwrap_or_default()
+    )
+}
+
+/// TLS client settings trusting the roots in `ca_file`, or the public web roots
+fn tls_config(ca_file: Option<&PathBuf>) -> Result<ClientConfig> {
+    let mut roots = RootCertStore::empty();
+    match ca_file {
+        Some(path) => {
+            for cert in CertificateDer::pem_file_iter(path)
+                .with_context(|| format!("reading {}", path.display()))?
+            {
+                roots.add(cert?)?;
+            }
+        }
+        None => roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned()),
+    }
+    Ok(
+        ClientConfig::builder_with_provider(Arc::new(crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()?
+            .with_root_certificates(roots)
+            .with_no_client_auth(),
+    )
+}
+
+enum Connection {
+    Udp(UdpSocket),
+    Stream(Box<dyn AsyncWrite + Unpin + Send>),
+}
+
+pub struct SyslogSink {
+    address: String,
+    transport: SyslogTransport,
+    /// TLS settings and the name the receiver's certificate must carry
+    tls: Option<(TlsConnector, ServerName<'static>)>,
+    facility: u8,
+    timeout: Duration,
+    /// Opened on first use, and again after a failure
+    connection: Mutex<Option<Connection>>,
+}
+
+impl SyslogSink {
+    pub fn new(settings: &SyslogSettings, timeout: Duration) -> Result<Self> {
+        let (host, _) = settings
+            .address
+            .rsplit_once(':')
+            .ok_or_else(|| anyhow!("address {} has no port", settings.address))?;
+        let tls = match settings.transport {
+            SyslogTransport::Tls => {
+                let host = host.trim_start_matches('[').trim_end_matches(']');
+                Some((
+                    TlsConnector::from(Arc::new(tls_config(settings.ca_file.as_ref())?)),
+                    ServerName::try_from(host.to_string())?,
+                ))
+            }
+            _ => None,
+        };
+        Ok(Self {
+            address: settings.address.clone(),
+            transport: settings.transport,
+            tls,
+            facility: settings.facility,
+            timeout,
+            connection: Mutex::new(None),
+        })
+    }
+
+    async fn connect(&self) -> Result<Connection> {
+        if self.transport == SyslogTransport::Udp {
+            let target = tokio::net::lookup_host(&self.address)
+                .await?
+                .next()
+                .ok_or_else(|| anyhow!("{} does not resolve", self.address))?;
+            let socket = UdpSocket::bind(if target.is_ipv4() {
+                "0.0.0.0:0"
+            } else {
+                "[::]:0"
+            })
+            .await?;
+            socket.connect(target).await?;
+            return Ok(Connection::Udp(socket));
+        }
+        let stream = TcpStream::connect(&self.address).await?;
+        Ok(Connection::Stream(match &self.tls {
+            Some((connector, server_name)) => {
+                Box::new(connector.connect(server_name.clone(), stream).await?)
+            }
+            None => Box::new(stream),
+        }))
+    }
+
+    async fn write(&self, connection: &mut Connection, batch: &[Arc<LogEntry>]) -> Result<()> {
+        match connection {
+            Connection::Udp(socket) => {
+                for entry in batch {
+                    let mut message = format_message(entry, self.facility);
+                    message.truncate(message.floor_char_boundary(MAX_DATAGRAM_BYTES));
+                    socket.send(message.as_bytes()).await?;
+                }
+            }
+            Connection::Stream(stream) => {
+                let mut frames = Vec::new();
+                for entry in batch {
+                    let message = format_message(entry, self.facility);
+                    write!(frames, "{} {}", message.len(), message)?;
+                }
+                stream.write_all(&frames).await?;
+                stream.flush().await?;
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn send(&self, batch: &[Arc<LogEntry>]) -> Result<()> {
+        let mut connection = self.connection.lock().await;
+        let result = tokio::time::timeout(self.timeout, async {
+            if connection.is_none() {
+                *connection = Some(self.connect().await?);
+            }
+            self.write(connection.as_mut().unwrap(), batch).await
+        })
+        .await
+        .unwrap_or_else(|_| Err(anyhow!("timed out after {:?}", self.timeout)));
+        if result.is_err() {
+            *connection = None;
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::outputs::{OutputSettings, SinkSettings};
+    use chrono::{DateTime, Utc};
+    use std::collections::HashMap;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    fn entry(fields: &[(&str, &str)]) -> Arc<LogEntry> {
+        let fields: HashMap<String, String> = fields
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        Arc::new(LogEntry::new(
+            DateTime::<Utc>::from_timestamp(1_700_000_000, 123_456_000).unwrap(),
+            fields,
+        ))
+    }
+
+    #[test]
+    fn test_format_message() {
+        let sshd = entry(&[
+            ("_HOSTNAME", "web1"),
+            ("SYSLOG_IDENTIFIER", "sshd"),
+            ("SYSLOG_FACILITY", "4"),
+            ("_PID", "812"),
+            ("PRIORITY", "5"),
+            ("MESSAGE", "Accepted publickey for deploy from 10.0.0.5"),
+        ]);
+        assert_eq!(
+            format_message(&sshd, 1),
+            "<37>1 2023-11-14T22:13:20.123456Z web1 sshd 812 - - \
+             Accepted publickey for deploy from 10.0.0.5"
+        );
+        let bare = entry(&[("MESSAGE", "hello"), ("_COMM", "my app")]);
+        assert_eq!(
+            format_message(&bare, 16),
+            "<134>1 2023-11-14T22:13:20.123456Z - myapp - - - hello"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_tcp_octet_counting() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let settings: OutputSettings = toml::from_str(&format!(
+            "name = \"siem\"\nkind = \"syslog\"\ntransport = \"tcp\"\naddress = \"{}\"",
+            listener.local_addr().unwrap()
+        ))
+        .unwrap();
+        let SinkSettings::Syslog(syslog) = &settings.sink else {
+            panic!("not a syslog output: {:?}", settings.sink);
+        };
+        let sink = SyslogSink::new(syslog, Duration::from_secs(5)).unwrap();
+        let batch = [entry(&[("MESSAGE", "one")]), entry(&[("MESSAGE", "two")])];
+        let (sent, accepted) = tokio::join!(sink.send(&batch), listener.accept());
+        sent.unwrap();
+        drop(sink);
+
+        let mut received = String::new();
+        accepted
+            .unwrap()
+            .0
+            .read_to_string(&mut received)
+            .await
+            .unwrap();
+        let first = "<14>1 2023-11-14T22:13:20.123456Z - - - - - one";
+        let second = first.replace("one", "two");
+        assert_eq!(
+            received,
+            format!("{} {}{} {}", first.len(), first, second.len(), second)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_udp_message_cut_to_datagram_limit() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let settings = SyslogSettings {
+            address: receiver.local_addr().unwrap().to_string(),
+            transport: SyslogTransport::Udp,
+            ca_file: None,
+            facility: default_facility(),
+        };
+        let sink = SyslogSink::new(&settings, Duration::from_secs(5)).unwrap();
+        let long = "é".repeat(40_000);
+        sink.send(&[entry(&[("MESSAGE", &long)])]).await.unwrap();
+
+        let mut datagram = vec![0; 65_536];
+        let len = receiver.recv(&mut datagram).await.unwrap();
+        assert!(len <= MAX_DATAGRAM_BYTES && len > MAX_DATAGRAM_BYTES - 2);
+        let message = String::from_utf8(datagram[..len].to_vec()).unwrap();
+        assert!(message.starts_with("<14>1 ") && message.ends_with('é'));
+    }
+}
@@ -0,0 +1,108 @@
+//! Supervision by systemd for `Type=notify` units: READY=1 once startup is done,
+//! STATUS= lines with ingest counts, and WATCHDOG=1 pings from the main loop, so a
+//! unit with `WatchdogSec=` is restarted when the main loop stops turning.
+//!
+//! Outside systemd (no `NOTIFY_SOCKET`) every call does nothing.
+
+use log::warn;
+use sd_notify::NotifyState;
+use std::time::{Duration, Instant};
+
+/// Least time between STATUS= updates
+const STATUS_INTERVAL: Duration = Duration::from_secs(10);
+
+pub struct SystemdNotifier {
+    /// Time between watchdog pings, half of `WatchdogSec=` (None = no watchdog)
+    watchdog_interval: Option<Duration>,
+    last_ping: Option<Instant>,
+    last_status: Option<Instant>,
+}
+
+impl SystemdNotifier {
+    /// Notifier set up from the environment systemd starts the service with
+    pub fn from_env() -> Self {
+        let mut usec = 0;
+        let watchdog_interval = sd_notify::watchdog_enabled(false, &mut usec)
+            .then(|| Duration::from_micros(usec) / 2)
+            .filter(|interval| !interval.is_zero());
+        Self {
+            watchdog_interval,
+            last_ping: None,
+            last_status: None,
+        }
+    }
+
+    /// Time between watchdog pings, if systemd expects them
+    pub fn watchdog_interval(&self) -> Option<Duration> {
+        self.watchdog_interval
+    }
+
+    /// Tell systemd startup has finished
+    pub fn ready(&mut self, status: &str) {
+        send(&[NotifyState::Ready, NotifyState::Status(status)]);
+        self.last_status = Some(Instant::now());
+    }
+
+    /// Update the status line `systemctl status` shows, unless it was updated less
+    /// than [`STATUS_INTERVAL`] ago
+    pub fn status(&mut self, status: impl FnOnce() -> String) {
+        if self
+            .last_status
+            .is_some_and(|at| at.elapsed() < STATUS_INTERVAL)
+        {
+            return;
+        }
+        send(&[NotifyState::Status(&status())]);
+        self.last_status = Some(Instant::now());
+    }
+
+    /// Ping the watchdog if it is due
+    pub fn watchdog(&mut self) {
+        let Some(interval) = self.watchdog_interval else {
+            return;
+        };
+        if self.last_ping.is_some_and(|at| at.elapsed() < interval) {
+            return;
+        }
+        send(&[NotifyState::Watchdog]);
+        self.last_ping = Some(Instant::now());
+    }
+
+    /// Tell systemd the service is shutting down
+    pub fn stopping(&mut self) {
+        send(&[NotifyState::Stopping, NotifyState::Status("Shutting down")]);
+    }
+}
+
+fn send(states: &[NotifyState]) {
+    if let Err(e) = sd_notify::notify(false, states) {
+        warn!("Failed to notify systemd: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_updates_are_spaced_out() {
+        let mut notifier = SystemdNotifier {
+            watchdog_interval: None,
+            last_ping: None,
+            last_status: None,
+        };
+        let mut built = 0;
+        notifier.status(|| {
+            built += 1;
+            "first".to_string()
+        });
+        notifier.status(|| {
+            built += 1;
+            "second".to_string()
+        });
+        assert_eq!(built, 1);
+        // Without a watchdog there is nothing to ping
+        notifier.watchdog();
+        assert_eq!(notifier.last_ping, None);
+    }
+}
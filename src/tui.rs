@@ -0,0 +1,391 @@
+//! Terminal UI (`livedata tui`) for servers whose web UI cannot be opened in a browser:
+//! a search box, a sparkline of the matches over time, the matching entries and the
+//! busiest processes. Everything is read from the running web server through
+//! [`ApiClient`], so it shows what the web UI would, and refreshed periodically.
+
+use crate::api_client::{self, ApiClient};
+use crate::web_server::{
+    HistogramResponse, ProcessResponse, SearchResponse, TimechartBin, format_bytes,
+};
+use anyhow::Result;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::widgets::{
+    Block, Borders, List, ListItem, ListState, Paragraph, Row, Sparkline, Table, TableState,
+};
+use ratatui::{DefaultTerminal, Frame};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+/// Entries fetched per search, newest first
+const RESULT_LIMIT: usize = 500;
+
+/// Processes listed, busiest first
+const PROCESS_LIMIT: usize = 100;
+
+/// Rows moved by PageUp / PageDown
+const PAGE: isize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Focus {
+    Search,
+    Results,
+    Processes,
+}
+
+/// What a key press asks of the event loop
+#[derive(Debug, PartialEq, Eq)]
+enum Action {
+    None,
+    Refresh,
+    Quit,
+}
+
+struct App {
+    /// Start of the time range searched (RFC 3339 or relative, e.g. -1h)
+    since: String,
+    /// Text in the search box; the results shown are for `query`
+    input: String,
+    query: String,
+    focus: Focus,
+    results: Vec<Value>,
+    total: usize,
+    histogram: Vec<u64>,
+    bin: String,
+    processes: Vec<Value>,
+    results_state: ListState,
+    processes_state: TableState,
+    status: String,
+}
+
+/// Run the UI against `client` until the user quits, searching from `since` and
+/// refreshing every `refresh`
+pub fn run(client: &ApiClient, since: &str, refresh: Duration) -> Result<()> {
+    let mut terminal = ratatui::init();
+    let result = event_loop(&mut terminal, client, since, refresh);
+    ratatui::restore();
+    result
+}
+
+fn event_loop(
+    terminal: &mut DefaultTerminal,
+    client: &ApiClient,
+    since: &str,
+    refresh: Duration,
+) -> Result<()> {
+    let mut app = App::new(since);
+    app.refresh(client);
+    let mut refreshed = Instant::now();
+    loop {
+        terminal.draw(|frame| app.draw(frame))?;
+        if !event::poll(refresh.saturating_sub(refreshed.elapsed()))? {
+            app.refresh(client);
+            refreshed = Instant::now();
+            continue;
+        }
+        if let Event::Key(key) = event::read()?
+            && key.kind == KeyEventKind::Press
+        {
+            match app.handle_key(key) {
+                Action::Quit => return Ok(()),
+                Action::Refresh => {
+                    app.refresh(client);
+                    refreshed = Instant::now();
+                }
+                Action::None => {}
+            }
+        }
+    }
+}
+
+impl App {
+    fn new(since: &str) -> Self {
+        Self {
+            since: since.to_string(),
+            input: String::new(),
+            query: String::new(),
+            focus: Focus::Results,
+            results: Vec::new(),
+            total: 0,
+            histogram: Vec::new(),
+            bin: String::new(),
+            processes: Vec::new(),
+            results_state: ListState::default(),
+            processes_state: TableState::default(),
+            status: String::new(),
+        }
+    }
+
+    /// Fetch the results, histogram and processes again; failures are shown in the
+    /// status line and the previous data kept
+    fn refresh(&mut self, client: &ApiClient) {
+        let mut filters = vec![("start", self.since.clone())];
+        if !self.query.is_empty() {
+            filters.push(("q", self.query.clone()));
+        }
+        let mut errors = Vec::new();
+
+        let mut search = filters.clone();
+        search.extend([
+            ("limit", RESULT_LIMIT.to_string()),
+            ("sort", "timestamp".to_string()),
+            ("sort_dir", "desc".to_string()),
+        ]);
+        match client.get_json::<SearchResponse>("/api/search", &search) {
+            Ok(response) => {
+                self.results = response.results;
+                self.total = response.total;
+            }
+            Err(e) => errors.push(format!("search: {:#}", e)),
+        }
+        match client.get_json::<HistogramResponse>("/api/histogram", &filters) {
+            Ok(response) => {
+                self.histogram = histogram_counts(&response.bins);
+                self.bin = response.bin;
+            }
+            Err(e) => errors.push(format!("histogram: {:#}", e)),
+        }
+        let processes = [("limit", PROCESS_LIMIT.to_string())];
+        match client.get_json::<ProcessResponse>("/api/processes", &processes) {
+            Ok(response) => self.processes = response.processes,
+            Err(e) => errors.push(format!("processes: {:#}", e)),
+        }
+
+        self.status = if errors.is_empty() {
+            format!(
+                "{} of {} matching entries since {}",
+                self.results.len(),
+                self.total,
+                self.since
+            )
+        } else {
+            errors.join("; ")
+        };
+        clamp_selection(self.results_state.selected_mut(), self.results.len());
+        clamp_selection(self.processes_state.selected_mut(), self.processes.len());
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) -> Action {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            return Action::Quit;
+        }
+        if self.focus == Focus::Search {
+            match key.code {
+                KeyCode::Enter => {
+                    self.query = self.input.trim().to_string();
+                    self.focus = Focus::Results;
+                    self.results_state.select(None);
+                    return Action::Refresh;
+                }
+                KeyCode::Esc => {
+                    self.input = self.query.clone();
+                    self.focus = Focus::Results;
+                }
+                KeyCode::Backspace => {
+                    self.input.pop();
+                }
+                KeyCode::Char(c) => self.input.push(c),
+                _ => {}
+            }
+            return Action::None;
+        }
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Action::Quit,
+            KeyCode::Char('/') => self.focus = Focus::Search,
+            KeyCode::Char('r') => return Action::Refresh,
+            KeyCode::Tab => {
+                self.focus = match self.focus {
+                    Focus::Processes => Focus::Results,
+                    _ => Focus::Processes,
+                };
+            }
+            KeyCode::Down | KeyCode::Char('j') => self.scroll(1),
+            KeyCode::Up | KeyCode::Char('k') => self.scroll(-1),
+            KeyCode::PageDown => self.scroll(PAGE),
+            KeyCode::PageUp => self.scroll(-PAGE),
+            KeyCode::Home => self.scroll(isize::MIN),
+            KeyCode::End => self.scroll(isize::MAX),
+            _ => {}
+        }
+        Action::None
+    }
+
+    /// Move the selection of the focused pane by `by` rows
+    fn scroll(&mut self, by: isize) {
+        let (len, state) = match self.focus {
+            Focus::Processes => (self.processes.len(), self.processes_state.selected_mut()),
+            _ => (self.results.len(), self.results_state.selected_mut()),
+        };
+        if len == 0 {
+            return;
+        }
+        let current = state.unwrap_or(0) as isize;
+        *state = Some(current.saturating_add(by).clamp(0, len as isize - 1) as usize);
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [search, histogram, main, status] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Length(5),
+            Constraint::Min(5),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [results, processes] =
+            Layout::horizontal([Constraint::Percentage(70), Constraint::Percentage(30)])
+                .areas(main);
+        let pane = |title: String, focus: Focus| {
+            let block = Block::default().borders(Borders::ALL).title(title);
+            if self.focus == focus {
+                block.border_style(Style::default().fg(Color::Cyan))
+            } else {
+                block
+            }
+        };
+        let selected = Style::default().add_modifier(Modifier::REVERSED);
+
+        let search_box = Paragraph::new(self.input.as_str()).block(pane(
+            format!(" Search since {} ", self.since),
+            Focus::Search,
+        ));
+        frame.render_widget(search_box, search);
+        if self.focus == Focus::Search {
+            let column = self.input.chars().count() as u16;
+            frame.set_cursor_position((search.x + 1 + column, search.y + 1));
+        }
+
+        let sparkline = Sparkline::default()
+            .data(&self.histogram)
+            .style(Style::default().fg(Color::Green))
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(format!(" Matches per {} ", self.bin)),
+            );
+        frame.render_widget(sparkline, histogram);
+
+        let items: Vec<ListItem> = self
+            .results
+            .iter()
+            .map(|row| {
+                ListItem::new(api_client::format_short(row))
+                    .style(priority_style(row["priority"].as_u64()))
+            })
+            .collect();
+        let list = List::new(items)
+            .block(pane(format!(" Results ({}) ", self.total), Focus::Results))
+            .highlight_style(selected);
+        frame.render_stateful_widget(list, results, &mut self.results_state);
+
+        let rows = self.processes.iter().map(|process| {
+            Row::new([
+                process["pid"].to_string(),
+                process["name"].as_str().unwrap_or_default().to_string(),
+                format!("{:.1}", process["cpu_usage"].as_f64().unwrap_or_default()),
+                format_bytes(process["mem_usage"].as_f64().unwrap_or_default()),
+            ])
+        });
+        let widths = [
+            Constraint::Length(7),
+            Constraint::Min(8),
+            Constraint::Length(6),
+            Constraint::Length(9),
+        ];
+        let table = Table::new(rows, widths)
+            .header(
+                Row::new(["PID", "NAME", "CPU%", "MEM"])
+                    .style(Style::default().add_modifier(Modifier::BOLD)),
+            )
+            .block(pane(" Processes ".to_string(), Focus::Processes))
+            .row_highlight_style(selected);
+        frame.render_stateful_widget(table, processes, &mut self.processes_state);
+
+        let help = "/ search  Tab switch pane  r refresh  q quit";
+        frame.render_widget(
+            Paragraph::new(format!("{}  |  {}", self.status, help)),
+            status,
+        );
+    }
+}
+
+/// Entries per bin, the levels added up, oldest bin first
+fn histogram_counts(bins: &[TimechartBin]) -> Vec<u64> {
+    let mut counts: BTreeMap<&str, u64> = BTreeMap::new();
+    for bin in bins {
+        *counts.entry(&bin.time_bin).or_default() += bin.count.max(0) as u64;
+    }
+    counts.into_values().collect()
+}
+
+/// Colours of [`api_client::colorize`] as a style
+fn priority_style(priority: Option<u64>) -> Style {
+    match priority {
+        Some(0..=2) => Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+        Some(3) => Style::default().fg(Color::Red),
+        Some(4) => Style::default().fg(Color::Yellow),
+        Some(5) => Style::default().add_modifier(Modifier::BOLD),
+        Some(7) => Style::default().fg(Color::DarkGray),
+        _ => Style::default(),
+    }
+}
+
+/// Keep a selected row within `len` rows, selecting the first when there is none
+fn clamp_selection(selected: &mut Option<usize>, len: usize) {
+    *selected = match len {
+        0 => None,
+        len => Some(selected.unwrap_or(0).min(len - 1)),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(app: &mut App, code: KeyCode) -> Action {
+        app.handle_key(KeyEvent::new(code, KeyModifiers::NONE))
+    }
+
+    #[test]
+    fn test_keys_edit_search_and_scroll() {
+        let mut app = App::new("-1h");
+        app.results = vec![Value::Null; 3];
+        clamp_selection(app.results_state.selected_mut(), app.results.len());
+        assert_eq!(app.results_state.selected(), Some(0));
+
+        assert_eq!(press(&mut app, KeyCode::Char('/')), Action::None);
+        for c in "error".chars() {
+            press(&mut app, KeyCode::Char(c));
+        }
+        // Typed into the search box rather than taken as commands
+        assert_eq!(app.input, "error");
+        assert_eq!(press(&mut app, KeyCode::Enter), Action::Refresh);
+        assert_eq!(app.query, "error");
+        assert_eq!(app.focus, Focus::Results);
+
+        clamp_selection(app.results_state.selected_mut(), app.results.len());
+        press(&mut app, KeyCode::End);
+        assert_eq!(app.results_state.selected(), Some(2));
+        press(&mut app, KeyCode::Down);
+        assert_eq!(app.results_state.selected(), Some(2));
+        press(&mut app, KeyCode::PageUp);
+        assert_eq!(app.results_state.selected(), Some(0));
+        assert_eq!(press(&mut app, KeyCode::Char('q')), Action::Quit);
+    }
+
+    #[test]
+    fn test_histogram_counts() {
+        let bin = |time_bin: &str, level: &str, count| TimechartBin {
+            time_bin: time_bin.to_string(),
+            level: level.to_string(),
+            count,
+        };
+        let bins = [
+            bin("2026-10-17 10:01:00", "error", 2),
+            bin("2026-10-17 10:00:00", "info", 5),
+            bin("2026-10-17 10:01:00", "info", 1),
+        ];
+        assert_eq!(histogram_counts(&bins), [5, 3]);
+    }
+}
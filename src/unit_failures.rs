@@ -0,0 +1,254 @@
+//! Recognizes systemd reporting a unit as failed among the ingested journal entries.
+//! Failures are recorded in the unit_failures table together with the last lines the
+//! failed invocation logged.
+
+use crate::log_entry::LogEntry;
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Source of the alerts raised for unit failures
+pub const UNIT_FAILURE_ALERT_SOURCE: &str = "unit";
+
+/// `MESSAGE_ID` of systemd's "<unit>: Failed with result '<result>'." message
+pub const UNIT_FAILED_MESSAGE_ID: &str = "d9b373ed55a64feb8242e02dbe79a49c";
+
+/// `JOB_RESULT`s of a start job that count as the unit failing
+const FAILED_JOB_RESULTS: [&str; 3] = ["failed", "timeout", "dependency"];
+
+/// Without invocation IDs to tell them apart, reports of the same unit this close
+/// together describe one failure: systemd logs both the unit failing and its start
+/// job failing
+const SAME_FAILURE_WINDOW: TimeDelta = TimeDelta::seconds(10);
+
+/// Journal lines kept with each failure
+pub const UNIT_FAILURE_LINES: usize = 10;
+
+/// Without an invocation ID, a failure is shown with the lines its unit logged this
+/// long before it
+pub const UNIT_FAILURE_LOOKBACK: TimeDelta = TimeDelta::minutes(10);
+
+/// A journal line logged by a failed unit
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UnitLogLine {
+    pub timestamp: DateTime<Utc>,
+    pub priority: Option<i32>,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UnitFailure {
+    pub id: i64,
+    pub timestamp: DateTime<Utc>,
+    pub hostname: Option<String>,
+    /// Input the report arrived through
+    pub source: String,
+    pub unit: String,
+    /// Invocation of the unit that failed, when systemd reported it
+    pub invocation_id: Option<String>,
+    /// Why the unit failed, e.g. `exit-code`, `timeout` or `dependency`
+    pub result: String,
+    /// systemd's message about the failure
+    pub message: String,
+    /// Last lines the failed invocation logged, oldest first
+    pub last_lines: Vec<UnitLogLine>,
+}
+
+/// Whether `entry` was logged by systemd itself (the system or a user manager)
+fn from_systemd(entry: &LogEntry) -> bool {
+    entry.get_pid().is_some_and(|pid| pid == "1")
+        || entry
+            .get_syslog_identifier()
+            .is_some_and(|ident| ident == "systemd")
+}
+
+/// Unit and result of a failure report in plain text, for entries that lost their
+/// structured fields on the way (e.g. forwarded over syslog)
+fn parse_message(message: &str) -> Option<(String, String)> {
+    if let Some(unit) = message
+        .strip_prefix("Unit ")
+        .and_then(|rest| rest.strip_suffix(" entered failed state."))
+    {
+        return Some((unit.to_string(), "failed".to_string()));
+    }
+    let (unit, rest) = message.split_once(": Failed with result '")?;
+    let result = rest.strip_suffix("'.")?;
+    Some((unit.to_string(), result.to_string()))
+}
+
+/// Latest failure per hostname and unit, with its invocation
+type FailureLog = HashMap<(Option<String>, String), (DateTime<Utc>, Option<String>)>;
+
+/// Picks failure reports out of the ingested entries, reporting each failure once
+#[derive(Default)]
+pub struct UnitFailureDetector {
+    last_seen: FailureLog,
+}
+
+impl UnitFailureDetector {
+    /// The failure `entry` reports, unless it is another report of one already seen.
+    /// The failure's `id` and `last_lines` are left for the caller to fill in.
+    pub fn detect(&mut self, entry: &LogEntry) -> Option<UnitFailure> {
+        if !from_systemd(entry) {
+            return None;
+        }
+        let unit = entry
+            .get_field("UNIT")
+            .or_else(|| entry.get_field("USER_UNIT"))
+            .cloned();
+        let message = entry.get_message().cloned().unwrap_or_default();
+        let (unit, result) = if entry
+            .get_message_id()
+            .is_some_and(|id| id == UNIT_FAILED_MESSAGE_ID)
+        {
+            let result = entry.get_field("UNIT_RESULT").cloned();
+            (unit?, result.unwrap_or_else(|| "failed".to_string()))
+        } else if let Some(result) = entry.get_field("JOB_RESULT") {
+            if !FAILED_JOB_RESULTS.contains(&result.as_str()) {
+                return None;
+            }
+            (unit?, result.clone())
+        } else if entry.get_message_id().is_none() {
+            parse_message(&message)?
+        } else {
+            return None;
+        };
+
+        let hostname = entry.get_hostname().cloned();
+        let invocation_id = entry.get_invocation_id().cloned();
+        let key = (hostname.clone(), unit.clone());
+        if let Some((seen_at, seen_invocation)) = self.last_seen.get(&key) {
+            let same_failure = match (&invocation_id, seen_invocation) {
+                (Some(invocation_id), Some(seen)) => invocation_id == seen,
+                _ => (entry.timestamp - *seen_at).abs() < SAME_FAILURE_WINDOW,
+            };
+            if same_failure {
+                return None;
+            }
+        }
+        self.last_seen
+            .insert(key, (entry.timestamp, invocation_id.clone()));
+        Some(UnitFailure {
+            id: 0,
+            timestamp: entry.timestamp,
+            hostname,
+            source: entry.source.clone(),
+            unit,
+            invocation_id,
+            result,
+            message,
+            last_lines: Vec::new(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(seconds: i64, fields: &[(&str, &str)]) -> LogEntry {
+        let mut all: HashMap<String, String> = [("_PID", "1"), ("_HOSTNAME", "web1")]
+            .iter()
+            .chain(fields)
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        all.retain(|_, v| !v.is_empty());
+        LogEntry::new(
+            DateTime::<Utc>::UNIX_EPOCH + TimeDelta::seconds(seconds),
+            all,
+        )
+    }
+
+    #[test]
+    fn test_detects_each_failure_once() {
+        let mut detector = UnitFailureDetector::default();
+        let failed = entry(
+            0,
+            &[
+                ("MESSAGE", "nginx.service: Failed with result 'exit-code'."),
+                ("MESSAGE_ID", UNIT_FAILED_MESSAGE_ID),
+                ("UNIT", "nginx.service"),
+                ("UNIT_RESULT", "exit-code"),
+                ("INVOCATION_ID", "abc"),
+            ],
+        );
+        let failure = detector.detect(&failed).unwrap();
+        assert_eq!(failure.unit, "nginx.service");
+        assert_eq!(failure.result, "exit-code");
+        assert_eq!(failure.hostname.as_deref(), Some("web1"));
+        assert_eq!(failure.invocation_id.as_deref(), Some("abc"));
+
+        // The start job failing is the same failure
+        let job_failed = entry(
+            1,
+            &[
+                ("MESSAGE", "Failed to start nginx.service - A web server."),
+                ("MESSAGE_ID", "be02cf6855d2428ba40df7e9d022f03d"),
+                ("UNIT", "nginx.service"),
+                ("JOB_TYPE", "start"),
+                ("JOB_RESULT", "failed"),
+                ("INVOCATION_ID", "abc"),
+            ],
+        );
+        assert_eq!(detector.detect(&job_failed), None);
+
+        let dependency = entry(
+            2,
+            &[
+                ("MESSAGE", "Dependency failed for app.service."),
+                ("UNIT", "app.service"),
+                ("JOB_RESULT", "dependency"),
+            ],
+        );
+        assert_eq!(detector.detect(&dependency).unwrap().result, "dependency");
+        let done = entry(3, &[("UNIT", "cron.service"), ("JOB_RESULT", "done")]);
+        assert_eq!(detector.detect(&done), None);
+
+        // Restarted and failed again
+        let again = entry(
+            60,
+            &[
+                ("MESSAGE", "nginx.service: Failed with result 'exit-code'."),
+                ("MESSAGE_ID", UNIT_FAILED_MESSAGE_ID),
+                ("UNIT", "nginx.service"),
+                ("INVOCATION_ID", "def"),
+            ],
+        );
+        assert_eq!(
+            detector.detect(&again).unwrap().invocation_id.as_deref(),
+            Some("def")
+        );
+    }
+
+    #[test]
+    fn test_detects_plain_text_reports() {
+        let mut detector = UnitFailureDetector::default();
+        let legacy = entry(
+            0,
+            &[
+                ("_PID", ""),
+                ("SYSLOG_IDENTIFIER", "systemd"),
+                ("MESSAGE", "Unit backup.service entered failed state."),
+            ],
+        );
+        let failure = detector.detect(&legacy).unwrap();
+        assert_eq!(
+            (failure.unit.as_str(), failure.result.as_str()),
+            ("backup.service", "failed")
+        );
+
+        // Only systemd's own messages count
+        let quoted = entry(
+            100,
+            &[
+                ("_PID", "4242"),
+                ("MESSAGE", "worker.service: Failed with result 'timeout'."),
+            ],
+        );
+        assert_eq!(detector.detect(&quoted), None);
+        assert_eq!(
+            parse_message("worker.service: Failed with result 'timeout'."),
+            Some(("worker.service".to_string(), "timeout".to_string()))
+        );
+    }
+}
@@ -0,0 +1,108 @@
+//! User names for user ids and back, from /etc/passwd and otherwise NSS (via
+//! `getent`, which also covers LDAP and sssd users). Lookups are cached, including
+//! misses, so users created later resolve after a restart. `getent` can block on a
+//! directory server, so async callers run lookups on the blocking pool.
+
+use std::collections::BTreeMap;
+use std::process::Command;
+use std::sync::Mutex;
+
+const PASSWD_PATH: &str = "/etc/passwd";
+/// Lookups remembered per direction before the cache starts over; user ids come from
+/// search queries, so the names looked up are not limited to real users
+const MAX_CACHED_LOOKUPS: usize = 1024;
+
+static NAMES: Mutex<BTreeMap<u32, Option<String>>> = Mutex::new(BTreeMap::new());
+static IDS: Mutex<BTreeMap<String, Option<u32>>> = Mutex::new(BTreeMap::new());
+
+/// Name and uid of each passwd(5) line
+fn passwd_entries(contents: &str) -> impl Iterator<Item = (&str, u32)> {
+    contents.lines().filter_map(|line| {
+        let mut fields = line.split(':');
+        let name = fields.next().filter(|name| !name.is_empty())?;
+        let uid = fields.nth(1)?.parse().ok()?;
+        Some((name, uid))
+    })
+}
+
+/// `find` over /etc/passwd, or else over the output of `getent passwd key`
+fn lookup<T>(key: &str, find: impl Fn(&str) -> Option<T>) -> Option<T> {
+    if let Ok(contents) = std::fs::read_to_string(PASSWD_PATH)
+        && let Some(found) = find(&contents)
+    {
+        return Some(found);
+    }
+    let output = Command::new("getent").args(["passwd", key]).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    find(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Remember `value` for `key`, forgetting every earlier lookup once the cache is full
+fn remember<K: Ord, V>(cache: &Mutex<BTreeMap<K, V>>, key: K, value: V) {
+    let mut cache = cache.lock().unwrap();
+    if cache.len() >= MAX_CACHED_LOOKUPS {
+        cache.clear();
+    }
+    cache.insert(key, value);
+}
+
+/// Login name of `uid`, if the system knows one
+pub fn user_name(uid: u32) -> Option<String> {
+    if let Some(name) = NAMES.lock().unwrap().get(&uid) {
+        return name.clone();
+    }
+    let name = lookup(&uid.to_string(), |contents| {
+        passwd_entries(contents)
+            .find(|(_, id)| *id == uid)
+            .map(|(name, _)| name.to_string())
+    });
+    remember(&NAMES, uid, name.clone());
+    name
+}
+
+/// Uid of the user called `name`, if the system knows one
+pub fn user_id(name: &str) -> Option<u32> {
+    if let Some(uid) = IDS.lock().unwrap().get(name) {
+        return *uid;
+    }
+    let uid = lookup(name, |contents| {
+        passwd_entries(contents)
+            .find(|(user, _)| *user == name)
+            .map(|(_, uid)| uid)
+    });
+    remember(&IDS, name.to_string(), uid);
+    uid
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_passwd_entries() {
+        let contents = "root:x:0:0:root:/root:/bin/bash\n\
+                        # comment\n\
+                        alice:x:1000:1000:Alice:/home/alice:/bin/zsh\n\
+                        broken:x:notanumber:0::/:/bin/false\n";
+        let entries: Vec<_> = passwd_entries(contents).collect();
+        assert_eq!(entries, [("root", 0), ("alice", 1000)]);
+    }
+
+    #[test]
+    fn test_root_resolves_both_ways() {
+        assert_eq!(user_name(0).as_deref(), Some("root"));
+        assert_eq!(user_id("root"), Some(0));
+        assert_eq!(user_id("no-such-user-livedata"), None);
+    }
+
+    #[test]
+    fn test_cache_is_bounded() {
+        let cache = Mutex::new(BTreeMap::new());
+        for uid in 0..MAX_CACHED_LOOKUPS as u32 + 10 {
+            remember(&cache, uid, None::<String>);
+        }
+        assert_eq!(cache.lock().unwrap().len(), 10);
+    }
+}
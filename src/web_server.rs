@@ -1,47 +1,170 @@
-use crate::config::Settings;
-use crate::duckdb_buffer::{DuckDBBuffer, ProcessMetricRecord};
-use crate::process_monitor::ProcessMonitor;
+use crate::alerting::{self, LOG_ALERT_SOURCE};
+use crate::auth::{self, Identity, Role};
+use crate::config::{AlertRule, Settings, parse_duration};
+use crate::duckdb_buffer::{
+    ALERTS_TABLE, DayRowCount, DuckDBBuffer, ExtraFieldUsage, GPU_METRICS_TABLE, IndexStorage,
+    LOG_COUNTS_TABLE, LogCatalog, LogSource, LogSummary, PROCESS_EVENTS_TABLE, ProcessHistoryPoint,
+    ProcessMetricRecord, PurgeFilter, SOURCE_RATE_WINDOW, SPILL_DIR, SqlParam, SystemHistoryPoint,
+    TableStorage, UNIT_FAILURES_TABLE, UnitUsage,
+};
+use crate::export_jobs::{ExportFormat, ExportJob, ExportJobState, ExportJobs};
+use crate::grafana::{self, Metric, QueryResult};
+use crate::integrity::{CheckResult, CheckStatus, IntegrityReport};
+use crate::internal_events::{InternalEvent, InternalEventKind};
+use crate::log_entry::{LOCAL_SOURCE, LogEntry};
+use crate::logql::{self, LogQuery, LokiResponse, StreamsData};
+use crate::metrics::{BackfillProgress, Metrics, StorageSizes};
+use crate::notifications::{AlertEvent, Notifier};
+use crate::oidc::{self, OidcProvider};
+use crate::parquet_writer::quote_ident;
+use crate::peer_replication::SourceStatus;
+use crate::process_monitor::{ProcessMonitor, SYSTEM_METRICS};
+use crate::query_cache::QueryCache;
+use crate::rate_limit::RateLimiter;
+use crate::read_pool::{PooledReader, QueryCancel, ReadPool};
+use crate::search_query::{self, SearchQuery, escape_like};
+use crate::silences::Silence;
+use crate::sql_trace::{self, QueryRecord};
 use axum::{
     Json, Router,
-    extract::{Query, State},
-    http::StatusCode,
-    response::{Html, IntoResponse},
-    routing::get,
+    body::{Body, Bytes, HttpBody},
+    extract::{
+        ConnectInfo, Extension, FromRequestParts, Path, Query, State,
+        rejection::QueryRejection,
+        ws::{Message, WebSocket, WebSocketUpgrade},
+    },
+    http::{HeaderMap, HeaderValue, Method, StatusCode, header, request::Parts},
+    middleware::{self, Next},
+    response::{
+        Html, IntoResponse, Redirect, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
+    routing::{delete, get, post, put},
 };
-use chrono::{DateTime, Duration, Utc};
+use axum_server::tls_rustls::RustlsConfig;
+use chrono::{DateTime, Duration, DurationRound, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json;
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, RwLock};
+use tokio::signal::unix::{SignalKind, signal};
+use tokio::sync::broadcast;
+use tower_http::compression::CompressionLayer;
+use tower_http::compression::predicate::{DefaultPredicate, NotForContentType, Predicate};
 use tower_http::trace::TraceLayer;
 use tracing::info;
 
 /// Application state shared across handlers
 pub struct AppState {
     pub data_dir: String,
+    /// Writer-side buffer, locked only by handlers that change what is stored
     pub buffer: Arc<Mutex<DuckDBBuffer>>,
+    /// Connections that searches run on
+    pub readers: ReadPool,
+    /// Storage layout log sources are resolved from, without the buffer's lock
+    pub catalog: Arc<RwLock<LogCatalog>>,
+    /// Live tail of stored entries, subscribed to without the buffer's lock
+    pub tail: broadcast::Sender<Arc<LogEntry>>,
     pub process_monitor: Arc<ProcessMonitor>,
     pub settings: Settings,
+    /// Latest periodic integrity check, reported on /health
+    pub integrity: Mutex<Option<IntegrityReport>>,
+    /// Background exports written to data_dir/exports
+    pub export_jobs: ExportJobs,
+    /// Single sign-on provider, when `oidc` is configured
+    pub oidc: Option<OidcProvider>,
+    /// Per-client limit on /api requests, when `api_requests_per_minute` is set
+    pub rate_limiter: Option<RateLimiter>,
+    /// Collector and query counters reported on /metrics
+    pub metrics: Arc<Metrics>,
+    /// Recent responses of cached routes, when `query_cache_ttl_secs` is set
+    pub query_cache: Option<QueryCache<CachedResponse>>,
+    /// Channels test alerts are sent to
+    pub notifier: Notifier,
+}
+
+/// Response body and headers kept by the query cache
+#[derive(Clone)]
+pub struct CachedResponse {
+    headers: HeaderMap,
+    body: Bytes,
 }
 
+/// Largest response body the query cache keeps
+const MAX_CACHED_RESPONSE_BYTES: u64 = 4 * 1024 * 1024;
+
 impl AppState {
     pub fn new(
         data_dir: &str,
         buffer: Arc<Mutex<DuckDBBuffer>>,
+        readers: ReadPool,
         process_monitor: Arc<ProcessMonitor>,
         settings: Settings,
     ) -> Self {
+        let (catalog, tail) = {
+            let buffer = buffer.lock().unwrap();
+            (buffer.catalog(), buffer.tail_sender())
+        };
         Self {
             data_dir: data_dir.to_string(),
             buffer,
+            readers,
+            catalog,
+            tail,
             process_monitor,
+            integrity: Mutex::new(None),
+            oidc: None,
+            rate_limiter: (settings.api_requests_per_minute > 0)
+                .then(|| RateLimiter::per_minute(settings.api_requests_per_minute)),
+            metrics: Arc::new(Metrics::default()),
+            notifier: Notifier::default(),
+            query_cache: (settings.query_cache_ttl_secs > 0).then(|| {
+                QueryCache::with_ttl(std::time::Duration::from_secs(
+                    settings.query_cache_ttl_secs,
+                ))
+            }),
+            export_jobs: ExportJobs::new(
+                std::path::Path::new(data_dir).join("exports"),
+                Duration::minutes(settings.export_job_ttl_minutes as i64),
+            ),
             settings,
         }
     }
+
+    /// FROM-clause source for logs between `start` and `end`
+    pub fn log_source(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> LogSource {
+        self.catalog.read().unwrap().log_source(start, end)
+    }
+
+    /// extra_fields keys that have their own column, in promotion order
+    pub fn promoted_fields(&self) -> Vec<String> {
+        self.catalog.read().unwrap().promoted_fields().to_vec()
+    }
+
+    /// Columns of journal_logs, empty before the first entry is stored
+    pub async fn schema_columns(self: &Arc<Self>) -> Vec<(String, String)> {
+        self.with_reader(|reader| reader.get_schema_columns()).await
+    }
+
+    /// Run `read` on a pooled connection on the blocking thread pool, so waiting for a
+    /// free connection never holds up the async runtime
+    pub async fn with_reader<T, F>(self: &Arc<Self>, read: F) -> T
+    where
+        F: FnOnce(&PooledReader<'_>) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let state = self.clone();
+        tokio::task::spawn_blocking(move || read(&state.readers.get()))
+            .await
+            .unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()))
+    }
 }
 
 /// Search parameters from query string
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct SearchParams {
     /// Text search (MESSAGE field, case-insensitive ILIKE)
     #[serde(default)]
@@ -58,9 +181,15 @@ pub struct SearchParams {
     /// Filter by systemd unit (comma-separated)
     #[serde(default)]
     pub unit: Option<String>,
+    /// Filter by input the entries arrived through (comma-separated, e.g. local)
+    #[serde(default)]
+    pub source: Option<String>,
     /// Max priority level (0-7, lower = more severe)
     #[serde(default)]
     pub priority: Option<u8>,
+    /// Filter on extra_fields (comma-separated KEY=VALUE pairs, all must match)
+    #[serde(default)]
+    pub extra: Option<String>,
     /// Results per page (default: 100, max: 100000)
     #[serde(default = "default_limit")]
     pub limit: usize,
@@ -76,6 +205,102 @@ pub struct SearchParams {
     /// Comma-separated list of columns to include
     #[serde(default)]
     pub columns: Option<String>,
+    /// How /api/search computes `total`: exact COUNT(*) or a sampled estimate
+    #[serde(default)]
+    pub count_mode: CountMode,
+    /// Keyset cursor: return the page following this position (from `next_cursor`)
+    #[serde(default)]
+    pub after: Option<String>,
+    /// Keyset cursor: return the page preceding this position (from `prev_cursor`)
+    #[serde(default)]
+    pub before: Option<String>,
+    /// Fields to return value counts for (comma-separated, e.g. unit,hostname,priority)
+    #[serde(default)]
+    pub facets: Option<String>,
+    /// Values per facet, most frequent first (default: 10, max: 100)
+    #[serde(default = "default_facet_limit")]
+    pub facet_limit: usize,
+    /// Column filters from `filter[COLUMN]=VALUE` parameters, which serde cannot map
+    /// onto a field; collected by the [`SearchParams`] extractor
+    #[serde(skip)]
+    pub filters: Vec<(String, String)>,
+}
+
+/// Extracts the query string like `Query<SearchParams>`, adding the `filter[...]`
+/// parameters
+impl<S: Send + Sync> FromRequestParts<S> for SearchParams {
+    type Rejection = QueryRejection;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(mut params) = Query::<SearchParams>::from_request_parts(parts, state).await?;
+        let Query(pairs) = Query::<Vec<(String, String)>>::from_request_parts(parts, state).await?;
+        params.filters = pairs
+            .into_iter()
+            .filter_map(|(key, value)| {
+                let column = key.strip_prefix("filter[")?.strip_suffix(']')?;
+                Some((column.to_string(), value))
+            })
+            .collect();
+        Ok(params)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CountMode {
+    #[default]
+    Exact,
+    Estimate,
+}
+
+/// Query parameters of /api/export and /api/export/jobs on top of the /api/search
+/// filters
+#[derive(Debug, Deserialize)]
+pub struct ExportParams {
+    #[serde(default)]
+    pub format: ExportFormat,
+    /// Row cap (default and maximum: MAX_STREAM_ROWS, or MAX_EXPORT_JOB_ROWS for jobs)
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+/// Server-sent events options of /api/search/stream
+#[derive(Debug, Deserialize)]
+pub struct LiveParams {
+    #[serde(default)]
+    pub follow: FollowMode,
+    /// Seconds between polls (1-3600)
+    #[serde(default = "default_poll_interval")]
+    pub interval: u64,
+}
+
+/// How a server-sent events search finds new rows
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FollowMode {
+    /// Re-run the search every `interval` seconds for rows newer than the last sent
+    #[default]
+    Poll,
+    /// Send entries as they are ingested, filtered like /api/tail
+    Tail,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LoginParams {
+    /// Page to return to after logging in
+    #[serde(default)]
+    pub return_to: Option<String>,
+}
+
+/// Query parameters of the OIDC provider's redirect back to /auth/callback
+#[derive(Debug, Deserialize)]
+pub struct CallbackParams {
+    #[serde(default)]
+    pub code: Option<String>,
+    #[serde(default)]
+    pub state: Option<String>,
+    #[serde(default)]
+    pub error: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -95,9 +320,329 @@ pub struct TimechartParams {
     /// Filter by systemd unit (comma-separated)
     #[serde(default)]
     pub unit: Option<String>,
+    /// Filter by input the entries arrived through (comma-separated, e.g. local)
+    #[serde(default)]
+    pub source: Option<String>,
     /// Max priority level (0-7, lower = more severe)
     #[serde(default)]
     pub priority: Option<u8>,
+    /// Filter on extra_fields (comma-separated KEY=VALUE pairs, all must match)
+    #[serde(default)]
+    pub extra: Option<String>,
+}
+
+/// Bin size of /api/histogram, on top of the timechart filters
+#[derive(Debug, Deserialize)]
+pub struct HistogramParams {
+    /// 10s, 1m, 5m, 1h, 1d or auto (default)
+    #[serde(default)]
+    pub bin: Option<String>,
+}
+
+/// Query parameters for /api/processes; without any, every process of the latest
+/// sample is returned, busiest first
+#[derive(Debug, Deserialize)]
+pub struct ProcessesParams {
+    /// Sort field: pid, name, cpu_usage (cpu, default), mem_usage (mem), user,
+    /// runtime, virtual_memory, status, parent_pid, read_bytes_per_sec (read),
+    /// write_bytes_per_sec (write), io (read and write together), num_threads
+    /// (threads), open_fd_count (fds), unit or container_id (container)
+    #[serde(default)]
+    pub sort: Option<String>,
+    /// Sort direction (asc or desc)
+    #[serde(default = "default_sort_dir")]
+    pub sort_dir: String,
+    /// Only processes whose name contains this, ignoring case
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Only processes of this user, by login name or uid
+    #[serde(default)]
+    pub user: Option<String>,
+    /// Only processes of this systemd unit
+    #[serde(default)]
+    pub unit: Option<String>,
+    /// Only processes in this container (full id or a prefix)
+    #[serde(default)]
+    pub container: Option<String>,
+    #[serde(default)]
+    pub pid: Option<u32>,
+    /// Only processes using at least this much CPU, in percent
+    #[serde(default)]
+    pub min_cpu: Option<f32>,
+    /// Only processes using at least this much memory, in bytes
+    #[serde(default)]
+    pub min_mem: Option<f64>,
+    /// Processes returned (default: all)
+    #[serde(default)]
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub offset: usize,
+    /// Comma-separated fields to include in each process (default: all)
+    #[serde(default)]
+    pub fields: Option<String>,
+}
+
+/// Query parameters for /api/processes/tree
+#[derive(Debug, Deserialize)]
+pub struct ProcessTreeParams {
+    /// Use the newest sample taken at or before this time (default: the latest)
+    #[serde(default)]
+    pub at: Option<String>,
+    /// Only the subtree rooted at this process
+    #[serde(default)]
+    pub pid: Option<u32>,
+}
+
+/// Query parameters for /api/processes/history; `pid` takes precedence over `name`,
+/// and `name` over `unit`
+#[derive(Debug, Deserialize)]
+pub struct ProcessHistoryParams {
+    #[serde(default = "default_start")]
+    pub start: String,
+    #[serde(default = "default_end")]
+    pub end: String,
+    #[serde(default)]
+    pub pid: Option<u32>,
+    /// Every process with this name, summed per sample
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Every process in this systemd unit, summed per sample
+    #[serde(default)]
+    pub unit: Option<String>,
+    /// 10s, 1m, 5m, 1h, 1d or auto (default), as for /api/histogram
+    #[serde(default)]
+    pub bin: Option<String>,
+}
+
+/// Query parameters for /api/system/history
+#[derive(Debug, Deserialize)]
+pub struct SystemHistoryParams {
+    #[serde(default = "default_start")]
+    pub start: String,
+    #[serde(default = "default_end")]
+    pub end: String,
+    /// Comma-separated metrics, e.g. load_1,memory_used_bytes (default: all)
+    #[serde(default)]
+    pub metrics: Option<String>,
+    /// Only this mount point, network interface, sensor or battery
+    #[serde(default)]
+    pub device: Option<String>,
+    /// 10s, 1m, 5m, 1h, 1d or auto (default), as for /api/histogram
+    #[serde(default)]
+    pub bin: Option<String>,
+}
+
+/// Query parameters for /api/processes/events
+#[derive(Debug, Deserialize)]
+pub struct ProcessEventsParams {
+    #[serde(default = "default_start")]
+    pub start: String,
+    #[serde(default = "default_end")]
+    pub end: String,
+    #[serde(default)]
+    pub pid: Option<u32>,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub unit: Option<String>,
+    /// `start` or `exit` (default: both)
+    #[serde(default)]
+    pub event: Option<String>,
+    #[serde(default = "default_process_events_limit")]
+    pub limit: usize,
+}
+
+fn default_process_events_limit() -> usize {
+    200
+}
+
+/// Query parameters for /api/alerts
+#[derive(Debug, Deserialize)]
+pub struct AlertsParams {
+    #[serde(default = "default_alerts_start")]
+    pub start: String,
+    #[serde(default = "default_end")]
+    pub end: String,
+    #[serde(default)]
+    pub rule: Option<String>,
+    /// `log`, `process` or `livedata`
+    #[serde(default)]
+    pub source: Option<String>,
+    /// `firing` or `resolved` (default: both)
+    #[serde(default)]
+    pub state: Option<String>,
+    #[serde(default = "default_alerts_limit")]
+    pub limit: usize,
+}
+
+fn default_alerts_start() -> String {
+    "-7d".to_string()
+}
+
+fn default_alerts_limit() -> usize {
+    200
+}
+
+/// Query parameters for /api/internal
+#[derive(Debug, Deserialize)]
+pub struct InternalParams {
+    #[serde(default = "default_alerts_start")]
+    pub start: String,
+    #[serde(default = "default_end")]
+    pub end: String,
+    /// One of the [`InternalEventKind`] names (default: every kind)
+    #[serde(default)]
+    pub kind: Option<String>,
+    #[serde(default = "default_alerts_limit")]
+    pub limit: usize,
+}
+
+/// Query parameters for /api/gpu/history
+#[derive(Debug, Deserialize)]
+pub struct GpuHistoryParams {
+    #[serde(default = "default_start")]
+    pub start: String,
+    #[serde(default = "default_end")]
+    pub end: String,
+    /// Only this GPU, e.g. nvidia0 or card1
+    #[serde(default)]
+    pub device: Option<String>,
+    /// The GPU memory of this process instead of whole devices
+    #[serde(default)]
+    pub pid: Option<u32>,
+    /// 10s, 1m, 5m, 1h, 1d or auto (default), as for /api/histogram
+    #[serde(default)]
+    pub bin: Option<String>,
+}
+
+/// Window for /api/extra-fields
+#[derive(Debug, Deserialize)]
+pub struct ExtraFieldsParams {
+    #[serde(default = "default_day_start")]
+    pub start: String,
+}
+
+/// Query parameters for /api/summary
+#[derive(Debug, Deserialize)]
+pub struct SummaryParams {
+    #[serde(default = "default_day_start")]
+    pub start: String,
+    #[serde(default = "default_end")]
+    pub end: String,
+    /// Filter by hostname (comma-separated)
+    #[serde(default)]
+    pub hostname: Option<String>,
+    /// Filter by systemd unit (comma-separated)
+    #[serde(default)]
+    pub unit: Option<String>,
+}
+
+/// Query parameters for /api/values
+#[derive(Debug, Deserialize)]
+pub struct ValuesParams {
+    /// Column to list, by column or display name (e.g. _comm or comm)
+    #[serde(default)]
+    pub column: String,
+    /// Only values starting with this, ignoring case
+    #[serde(default)]
+    pub prefix: Option<String>,
+    #[serde(default = "default_day_start")]
+    pub start: String,
+    #[serde(default = "default_end")]
+    pub end: String,
+    /// Values returned, most frequent first (default: 20, max: 1000)
+    #[serde(default = "default_values_limit")]
+    pub limit: usize,
+}
+
+/// Query parameters for /api/debug/queries
+#[derive(Debug, Deserialize)]
+pub struct DebugQueriesParams {
+    /// Only statements logged after this id, for polling
+    #[serde(default)]
+    pub after: u64,
+    /// Only statements that took at least this long
+    #[serde(default)]
+    pub min_ms: f64,
+    /// `recent` (default) or `slowest`
+    #[serde(default)]
+    pub sort: Option<String>,
+    /// Statements returned (default: 100, max: 1000)
+    #[serde(default = "default_stats_limit")]
+    pub limit: usize,
+}
+
+/// Query parameters for /api/units
+#[derive(Debug, Deserialize)]
+pub struct UnitsParams {
+    #[serde(default = "default_day_start")]
+    pub start: String,
+    #[serde(default = "default_end")]
+    pub end: String,
+    #[serde(default)]
+    pub hostname: Option<String>,
+    #[serde(default)]
+    pub source: Option<String>,
+    /// Units returned, busiest first (default: 100, max: 10000)
+    #[serde(default = "default_stats_limit")]
+    pub limit: usize,
+}
+
+/// Query parameters for /api/hosts
+#[derive(Debug, Deserialize)]
+pub struct HostsParams {
+    #[serde(default = "default_day_start")]
+    pub start: String,
+    #[serde(default = "default_end")]
+    pub end: String,
+}
+
+/// Query parameters for /api/stats; filters mean the same as for /api/search
+#[derive(Debug, Deserialize)]
+pub struct StatsParams {
+    /// Columns to group by (comma-separated, display or column names, e.g. unit,priority)
+    #[serde(default)]
+    pub group_by: String,
+    #[serde(default = "default_day_start")]
+    pub start: String,
+    #[serde(default = "default_end")]
+    pub end: String,
+    #[serde(default)]
+    pub q: Option<String>,
+    #[serde(default)]
+    pub hostname: Option<String>,
+    #[serde(default)]
+    pub unit: Option<String>,
+    #[serde(default)]
+    pub source: Option<String>,
+    #[serde(default)]
+    pub priority: Option<u8>,
+    #[serde(default)]
+    pub extra: Option<String>,
+    /// Groups returned, largest first (default: 100, max: 10000)
+    #[serde(default = "default_stats_limit")]
+    pub limit: usize,
+}
+
+/// Query parameters for DELETE /api/logs; at least one filter is required
+#[derive(Debug, Deserialize)]
+pub struct PurgeParams {
+    /// Exact systemd unit
+    #[serde(default)]
+    pub unit: Option<String>,
+    /// Exact hostname
+    #[serde(default)]
+    pub hostname: Option<String>,
+    #[serde(default)]
+    pub start: Option<String>,
+    #[serde(default)]
+    pub end: Option<String>,
+    /// Only entries with priority at or below this value
+    #[serde(default)]
+    pub max_priority: Option<u8>,
+    /// Rows deleted per statement
+    #[serde(default = "default_purge_batch_size")]
+    pub batch_size: usize,
 }
 
 #[derive(Debug, Deserialize)]
@@ -114,6 +659,14 @@ fn default_start() -> String {
     "-1h".to_string()
 }
 
+fn default_day_start() -> String {
+    "-1d".to_string()
+}
+
+fn default_purge_batch_size() -> usize {
+    10_000
+}
+
 fn default_end() -> String {
     "now".to_string()
 }
@@ -130,6 +683,22 @@ fn default_sort_dir() -> String {
     "desc".to_string()
 }
 
+fn default_values_limit() -> usize {
+    20
+}
+
+fn default_facet_limit() -> usize {
+    10
+}
+
+fn default_stats_limit() -> usize {
+    100
+}
+
+fn default_poll_interval() -> u64 {
+    5
+}
+
 fn default_process_limit() -> usize {
     100
 }
@@ -151,31 +720,293 @@ pub struct SearchResult {
 pub struct SearchResponse {
     pub results: Vec<serde_json::Value>,
     pub columns: Vec<String>,
+    /// Number of rows matching the filters, across all pages
     pub total: usize,
+    /// True when `total` is a sampled estimate (`count_mode=estimate`)
+    #[serde(default)]
+    pub total_is_estimate: bool,
     pub limit: usize,
     pub offset: usize,
     pub query_time_ms: u128,
+    /// True when Parquet cold storage or an attached archive contributed to the search
+    #[serde(default)]
+    pub cold_storage: bool,
+    /// Pass as `after` to fetch the next page (timestamp sort only)
+    #[serde(default)]
+    pub next_cursor: Option<String>,
+    /// Pass as `before` to fetch the previous page (timestamp sort only)
+    #[serde(default)]
+    pub prev_cursor: Option<String>,
+    /// Most frequent values of each requested facet across all matches
+    #[serde(default)]
+    pub facets: BTreeMap<String, Vec<FacetValue>>,
 }
 
-/// Timechart bin response row
-#[derive(Debug, Serialize, Deserialize)]
-pub struct TimechartBin {
-    pub time_bin: String,
-    pub level: String,
+/// Value of a facet field and how many matching entries have it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FacetValue {
+    pub value: serde_json::Value,
     pub count: i64,
 }
 
-/// Column info for /api/columns endpoint
+/// /api/histogram response
 #[derive(Debug, Serialize, Deserialize)]
-pub struct ColumnInfo {
-    pub name: String,
-    pub column_type: String,
-    pub default: bool,
+pub struct HistogramResponse {
+    /// Bin size used, which may be coarser than the one requested
+    pub bin: String,
+    pub bin_seconds: i64,
+    pub bins: Vec<TimechartBin>,
 }
 
-/// Internal columns to exclude from the column chooser
-const EXCLUDED_COLUMNS: &[&str] = &["__CURSOR", "__MONOTONIC_TIMESTAMP", "minute_key"];
-
+/// /api/processes/history response
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProcessHistoryResponse {
+    pub pid: Option<u32>,
+    pub name: Option<String>,
+    #[serde(default)]
+    pub unit: Option<String>,
+    pub bin: String,
+    pub bin_seconds: i64,
+    pub points: Vec<ProcessHistoryPoint>,
+}
+
+/// /api/system/history response
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SystemHistoryResponse {
+    pub bin: String,
+    pub bin_seconds: i64,
+    pub series: Vec<SystemSeries>,
+}
+
+/// Binned values of one metric on one device
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SystemSeries {
+    pub metric: String,
+    /// Mount point or interface, empty for host-wide metrics
+    pub device: String,
+    pub points: Vec<SystemPoint>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SystemPoint {
+    pub time_bin: String,
+    pub avg: f64,
+    pub max: f64,
+}
+
+/// /api/gpu/history response
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GpuHistoryResponse {
+    pub bin: String,
+    pub bin_seconds: i64,
+    pub series: Vec<GpuSeries>,
+}
+
+/// Binned usage of one GPU, or of one process on one GPU
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GpuSeries {
+    pub device: String,
+    /// Model of the GPU, absent on process series
+    pub name: Option<String>,
+    pub pid: Option<u32>,
+    pub points: Vec<GpuPoint>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GpuPoint {
+    pub time_bin: String,
+    pub avg_utilization_percent: Option<f64>,
+    pub max_utilization_percent: Option<f64>,
+    pub avg_memory_used_bytes: Option<f64>,
+    pub max_memory_used_bytes: Option<f64>,
+    pub memory_total_bytes: Option<f64>,
+}
+
+/// /api/internal response
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InternalResponse {
+    /// Current ingest lag, none before the first entry is stored
+    pub ingest_lag_seconds: Option<f64>,
+    /// Events in the requested range, newest first
+    pub events: Vec<InternalEvent>,
+}
+
+/// /api/alerts response
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AlertsResponse {
+    /// Latest alert of every rule still firing, however long ago it fired
+    pub firing: Vec<serde_json::Value>,
+    /// Alerts in the requested range, newest first
+    pub history: Vec<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertRuleOrigin {
+    /// `[[alert_rules]]` in the config file, read-only over the API
+    Config,
+    /// Created through /api/alert-rules
+    Api,
+}
+
+/// An alert rule listed by /api/alert-rules
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AlertRuleInfo {
+    #[serde(flatten)]
+    pub rule: AlertRule,
+    pub origin: AlertRuleOrigin,
+    /// Whether the rule's latest alert is a firing one
+    pub firing: bool,
+}
+
+/// Query parameters for /api/unit-failures
+#[derive(Debug, Deserialize)]
+pub struct UnitFailuresParams {
+    #[serde(default = "default_alerts_start")]
+    pub start: String,
+    #[serde(default = "default_end")]
+    pub end: String,
+    #[serde(default)]
+    pub unit: Option<String>,
+    #[serde(default)]
+    pub hostname: Option<String>,
+    #[serde(default = "default_alerts_limit")]
+    pub limit: usize,
+}
+
+/// Query parameters for GET /api/silences
+#[derive(Debug, Deserialize)]
+pub struct SilencesParams {
+    /// Include silences that have ended
+    #[serde(default)]
+    pub all: bool,
+}
+
+/// Body of POST /api/silences; at least one matcher is required
+#[derive(Debug, Deserialize)]
+pub struct CreateSilenceRequest {
+    /// Rule name, `*` standing for any run of characters
+    #[serde(default)]
+    pub rule: Option<String>,
+    /// `log`, `process` or `livedata`
+    #[serde(default)]
+    pub source: Option<String>,
+    #[serde(default)]
+    pub tag: Option<String>,
+    #[serde(default = "default_end")]
+    pub starts_at: String,
+    /// End time, or a duration from `starts_at` such as `2h`
+    pub ends_at: String,
+    #[serde(default)]
+    pub comment: String,
+}
+
+/// /api/processes/events response, newest first
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProcessEventsResponse {
+    /// Exits carry the process's last journal entries as `journal`
+    pub events: Vec<serde_json::Value>,
+}
+
+/// /api/values response
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ColumnValues {
+    /// Display name of the column
+    pub column: String,
+    pub values: Vec<FacetValue>,
+}
+
+/// /api/stats response
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StatsResponse {
+    /// Display names of the grouped columns
+    pub group_by: Vec<String>,
+    /// One object per group: its `group_by` values, `count`, and the `first_timestamp`
+    /// and `last_timestamp` of its entries
+    pub groups: Vec<serde_json::Value>,
+    pub query_time_ms: u128,
+}
+
+/// Log volume of one systemd unit in /api/units; entries without a unit are
+/// reported under an empty name
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UnitStats {
+    pub unit: String,
+    pub entries: i64,
+    /// Entries at priority err or more severe (0-3)
+    pub errors: i64,
+    /// Entries at priority warning (4)
+    pub warnings: i64,
+    pub last_timestamp: Option<String>,
+    /// Total length of the unit's messages, a proxy for its share of storage
+    pub message_bytes: i64,
+}
+
+/// One host of one input in /api/hosts
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HostStats {
+    pub source: String,
+    pub hostname: String,
+    pub entries: i64,
+    pub first_seen: Option<String>,
+    pub last_seen: Option<String>,
+    /// Average entries per minute over the requested range
+    pub entries_per_minute: f64,
+}
+
+/// /api/hosts response
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HostsResponse {
+    pub hosts: Vec<HostStats>,
+    pub query_time_ms: u128,
+}
+
+/// /api/debug/queries response
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DebugQueriesResponse {
+    pub queries: Vec<QueryRecord>,
+}
+
+/// /api/units response
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UnitsResponse {
+    pub units: Vec<UnitStats>,
+    pub query_time_ms: u128,
+}
+
+/// Timechart bin response row
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TimechartBin {
+    pub time_bin: String,
+    pub level: String,
+    pub count: i64,
+}
+
+/// Column info for /api/columns endpoint
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ColumnInfo {
+    pub name: String,
+    pub column_type: String,
+    pub default: bool,
+}
+
+/// Result of promoting an extra field to a column
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PromoteResponse {
+    pub key: String,
+    /// Existing rows whose value was copied into the new column
+    pub backfilled_rows: usize,
+}
+
+/// Result of DELETE /api/logs
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PurgeResponse {
+    pub deleted: usize,
+}
+
+/// Internal columns to exclude from the column chooser
+const EXCLUDED_COLUMNS: &[&str] = &["__CURSOR", "__MONOTONIC_TIMESTAMP"];
+
 /// Default columns shown when no column selection is made
 const DEFAULT_COLUMNS: &[&str] = &[
     "timestamp",
@@ -187,11 +1018,36 @@ const DEFAULT_COLUMNS: &[&str] = &[
     "message",
 ];
 
+/// Upper bound on rows returned by the streaming search and export endpoints
+const MAX_STREAM_ROWS: usize = 1_000_000;
+
+/// Upper bound on rows written by a background export job
+const MAX_EXPORT_JOB_ROWS: usize = 50_000_000;
+
+/// How often finished export jobs past their expiry are removed
+const EXPORT_EXPIRY_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// How often newly logged queries are copied into the query log table
+const QUERY_LOG_PERSIST_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Rows read per chunk of the streaming search endpoint
+const STREAM_CHUNK_ROWS: usize = 1_000;
+
+/// Longest wait between server-sent events polls after repeated failures
+const MAX_POLL_BACKOFF: std::time::Duration = std::time::Duration::from_secs(300);
+
+/// How often the web server re-runs the quick integrity checks reported on /health
+const INTEGRITY_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(3600);
+
+/// The collector's main loop counts as stalled after this many seconds without a pass
+const COLLECTOR_STALL_SECS: f64 = 300.0;
+
 /// Filter values response
 #[derive(Debug, Serialize, Deserialize)]
 pub struct FilterValues {
     pub hostnames: Vec<String>,
     pub units: Vec<String>,
+    pub sources: Vec<String>,
     pub priorities: Vec<PriorityOption>,
 }
 
@@ -204,15 +1060,32 @@ pub struct PriorityOption {
 /// Health check response
 #[derive(Debug, Serialize, Deserialize)]
 pub struct HealthResponse {
+    /// "ok", or "degraded" when the last integrity check found errors
     pub status: String,
     pub data_dir: String,
+    #[serde(default)]
+    pub integrity: Option<IntegrityReport>,
+    /// Load of older journal entries under way; startup replays the last hour before
+    /// the collector runs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backfill: Option<BackfillProgress>,
+}
+
+/// Liveness and readiness probe response; served with 503 unless every check is ok
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProbeResponse {
+    /// "ok" or "unavailable"
+    pub status: String,
+    pub checks: Vec<CheckResult>,
 }
 
 /// Process list API response
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct ProcessResponse {
-    pub processes: Vec<ProcessMetricsRow>,
+    /// The requested page, each process limited to the selected fields
+    pub processes: Vec<serde_json::Value>,
     pub timestamp: String,
+    /// Processes matching the filters, before pagination
     pub total: usize,
 }
 
@@ -224,12 +1097,41 @@ pub struct ProcessMetricsRow {
     pub name: String,
     pub cpu_usage: f32,
     pub mem_usage: f64,
+    /// Login name, or the uid if the user has none
     pub user: Option<String>,
+    pub uid: Option<u32>,
     pub runtime: u64,
     pub cmdline: Option<String>,
     pub virtual_memory: f64,
     pub status: Option<String>,
     pub parent_pid: Option<u32>,
+    /// Storage I/O since the previous sample, in bytes per second
+    pub read_bytes_per_sec: f64,
+    pub write_bytes_per_sec: f64,
+    pub num_threads: Option<u32>,
+    pub open_fd_count: Option<u32>,
+    /// systemd unit or scope, from the process's cgroup
+    pub unit: Option<String>,
+    pub container_id: Option<String>,
+}
+
+/// A process with its children, from one sample
+#[derive(Debug, Serialize)]
+pub struct ProcessTreeNode {
+    #[serde(flatten)]
+    pub process: ProcessMetricsRow,
+    /// CPU and memory of the process and all its descendants
+    pub total_cpu_usage: f64,
+    pub total_mem_usage: f64,
+    pub children: Vec<ProcessTreeNode>,
+}
+
+/// /api/processes/tree response
+#[derive(Debug, Serialize)]
+pub struct ProcessTreeResponse {
+    pub timestamp: String,
+    /// Processes whose parent is not in the sample (usually just init and kthreadd)
+    pub roots: Vec<ProcessTreeNode>,
 }
 
 fn to_process_row(r: ProcessMetricRecord) -> ProcessMetricsRow {
@@ -240,11 +1142,18 @@ fn to_process_row(r: ProcessMetricRecord) -> ProcessMetricsRow {
         cpu_usage: r.cpu_usage,
         mem_usage: r.mem_usage,
         user: r.user,
+        uid: r.uid,
         runtime: r.runtime,
         cmdline: r.cmdline,
         virtual_memory: r.virtual_memory,
         status: r.status,
         parent_pid: r.parent_pid,
+        read_bytes_per_sec: r.read_bytes_per_sec,
+        write_bytes_per_sec: r.write_bytes_per_sec,
+        num_threads: r.num_threads,
+        open_fd_count: r.open_fd_count,
+        unit: r.unit,
+        container_id: r.container_id,
     }
 }
 
@@ -256,6 +1165,12 @@ pub struct StorageHealthResponse {
     pub process_metric_count: i64,
     pub oldest_log_timestamp: Option<String>,
     pub newest_log_timestamp: Option<String>,
+    pub wal_size_bytes: u64,
+    pub tables: Vec<TableStorage>,
+    pub rows_per_day: Vec<DayRowCount>,
+    pub top_units: Vec<UnitUsage>,
+    pub indexes: Vec<IndexStorage>,
+    pub index_memory_bytes: i64,
     pub retention_policy: RetentionPolicy,
 }
 
@@ -268,7 +1183,7 @@ pub struct RetentionPolicy {
 }
 
 /// Parse time string (ISO 8601 or relative like -1h, -15m, -7d)
-fn parse_time(s: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>, String> {
+pub fn parse_time(s: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>, String> {
     if s == "now" {
         return Ok(now);
     }
@@ -308,11 +1223,212 @@ fn parse_time(s: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>, String> {
         .map_err(|e| format!("Invalid time format: {} ({})", s, e))
 }
 
-/// Escape LIKE wildcards for safe SQL queries
-fn escape_like(s: &str) -> String {
-    s.replace('\\', "\\\\")
-        .replace('%', "\\%")
-        .replace('_', "\\_")
+/// Parse the search box query `q` against the journal_logs `schema`. Runs on the
+/// blocking pool since `user:` terms may look the user up through NSS.
+async fn parse_search_query(
+    q: Option<&str>,
+    schema: &[(String, String)],
+) -> Result<SearchQuery, (StatusCode, String)> {
+    let q = q.unwrap_or_default().to_string();
+    let schema = schema.to_vec();
+    tokio::task::spawn_blocking(move || SearchQuery::parse(&q, &schema))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))
+}
+
+/// WHERE clause fragment with its positional bind parameters
+#[derive(Debug, Default)]
+struct WhereClause {
+    sql: String,
+    params: Vec<SqlParam>,
+}
+
+/// Build the shared log filter predicates (time range, search query, hostname, unit,
+/// priority, extra fields). User input is only ever passed as bind parameters, never
+/// spliced into the SQL text.
+#[allow(clippy::too_many_arguments)]
+fn build_where_clause(
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    query: &SearchQuery,
+    hostname: Option<&str>,
+    unit: Option<&str>,
+    source: Option<&str>,
+    priority: Option<u8>,
+    extra: &[ExtraFilter],
+    fields: &[FieldFilter],
+) -> WhereClause {
+    let mut clause = WhereClause {
+        sql: "timestamp >= ? AND timestamp < ?".to_string(),
+        params: vec![
+            SqlParam::Text(start.to_rfc3339()),
+            SqlParam::Text(end.to_rfc3339()),
+        ],
+    };
+
+    if let Some((sql, params)) = query.to_sql() {
+        clause.sql.push_str(&format!(" AND {}", sql));
+        clause.params.extend(params);
+    }
+    if let Some(hostname) = hostname
+        && !hostname.is_empty()
+    {
+        push_in_list(&mut clause, "_hostname", hostname);
+    }
+    if let Some(unit) = unit
+        && !unit.is_empty()
+    {
+        push_in_list(&mut clause, "_systemd_unit", unit);
+    }
+    if let Some(source) = source
+        && !source.is_empty()
+    {
+        // Cold storage written before the column existed only holds local entries
+        push_in_list(
+            &mut clause,
+            &format!("COALESCE(source, '{}')", LOCAL_SOURCE),
+            source,
+        );
+    }
+    if let Some(priority) = priority {
+        clause.sql.push_str(" AND CAST(priority AS INTEGER) <= ?");
+        clause.params.push(SqlParam::Int(priority as i32));
+    }
+    for filter in extra {
+        if filter.promoted {
+            // Rows written before the promotion may only have the value in extra_fields
+            clause.sql.push_str(&format!(
+                " AND COALESCE({}, extra_fields->>?) = ?",
+                quote_ident(&filter.key)
+            ));
+        } else {
+            clause.sql.push_str(" AND (extra_fields->>?) = ?");
+        }
+        clause.params.push(SqlParam::Text(filter.key.clone()));
+        clause.params.push(SqlParam::Text(filter.value.clone()));
+    }
+    for filter in fields {
+        let column = format!("CAST({} AS VARCHAR)", quote_ident(&filter.column));
+        push_in_list(&mut clause, &column, &filter.values);
+    }
+
+    clause
+}
+
+/// `filter[COLUMN]=VALUE` condition on a journal_logs column
+#[derive(Debug, PartialEq)]
+struct FieldFilter {
+    column: String,
+    /// Comma-separated values, any of which matches
+    values: String,
+}
+
+/// Validate `filter[...]` parameters against the journal_logs `schema`. Columns may
+/// be named as in the search syntax (`unit`, `comm`, ...); repeated filters on one
+/// column match any of their values.
+fn parse_field_filters(
+    filters: &[(String, String)],
+    schema: &[(String, String)],
+) -> Result<Vec<FieldFilter>, (StatusCode, String)> {
+    let mut parsed: Vec<FieldFilter> = Vec::new();
+    for (name, values) in filters {
+        if values.is_empty() {
+            continue;
+        }
+        let column = group_column(name, schema).ok_or_else(|| {
+            (
+                StatusCode::BAD_REQUEST,
+                format!("Unknown filter column '{}'", name),
+            )
+        })?;
+        match parsed.iter_mut().find(|filter| filter.column == column) {
+            Some(filter) => {
+                filter.values.push(',');
+                filter.values.push_str(values);
+            }
+            None => parsed.push(FieldFilter {
+                column,
+                values: values.clone(),
+            }),
+        }
+    }
+    Ok(parsed)
+}
+
+/// One `KEY=VALUE` condition on an extra field
+#[derive(Debug, PartialEq)]
+struct ExtraFilter {
+    key: String,
+    value: String,
+    /// The key has been promoted to a column of its own
+    promoted: bool,
+}
+
+/// Parse the `extra` filter (`KEY=VALUE,KEY2=VALUE2`). Keys are journal field names,
+/// so anything other than letters, digits and underscores is rejected rather than
+/// being interpreted as a JSON path.
+fn parse_extra_filters(
+    extra: Option<&str>,
+    promoted: &[String],
+) -> Result<Vec<ExtraFilter>, (StatusCode, String)> {
+    let Some(extra) = extra.filter(|e| !e.is_empty()) else {
+        return Ok(Vec::new());
+    };
+    extra
+        .split(',')
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').ok_or_else(|| {
+                (
+                    StatusCode::BAD_REQUEST,
+                    format!("Invalid extra filter '{}': expected KEY=VALUE", pair),
+                )
+            })?;
+            if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    format!("Invalid extra field name '{}'", key),
+                ));
+            }
+            Ok(ExtraFilter {
+                key: key.to_string(),
+                value: value.to_string(),
+                promoted: promoted.iter().any(|p| p == key),
+            })
+        })
+        .collect()
+}
+
+/// Append `AND <column> IN (?, ?, ...)` for a comma-separated value list
+fn push_in_list(clause: &mut WhereClause, column: &str, values: &str) {
+    let values: Vec<&str> = values.split(',').collect();
+    let placeholders = vec!["?"; values.len()].join(",");
+    clause
+        .sql
+        .push_str(&format!(" AND {} IN ({})", column, placeholders));
+    clause
+        .params
+        .extend(values.iter().map(|v| SqlParam::Text(v.to_string())));
+}
+
+/// Map a search sort parameter onto a whitelisted column
+fn sort_column(sort: &str) -> &'static str {
+    match sort.to_lowercase().as_str() {
+        "timestamp" => "timestamp",
+        "hostname" | "host" => "_hostname",
+        "unit" => "_systemd_unit",
+        "priority" | "pri" => "priority",
+        "comm" => "_comm",
+        _ => "timestamp",
+    }
+}
+
+/// Map a search sort direction onto ASC/DESC
+fn sort_direction(sort_dir: &str) -> &'static str {
+    match sort_dir.to_lowercase().as_str() {
+        "asc" => "ASC",
+        _ => "DESC",
+    }
 }
 
 /// Get priority label
@@ -344,30 +1460,60 @@ fn priority_level_name(p: i32) -> &'static str {
     }
 }
 
-pub async fn run_web_server(
-    data_dir: &str,
-    buffer: Arc<Mutex<DuckDBBuffer>>,
-    shutdown_signal: Arc<AtomicBool>,
-    process_monitor: Arc<ProcessMonitor>,
-    settings: Settings,
-    listen_all: bool,
-) {
-    let state = Arc::new(AppState::new(data_dir, buffer, process_monitor, settings));
+/// What the web server shares with the collector it runs beside
+pub struct WebServerDeps {
+    pub data_dir: String,
+    pub buffer: Arc<Mutex<DuckDBBuffer>>,
+    pub shutdown_signal: Arc<AtomicBool>,
+    pub process_monitor: Arc<ProcessMonitor>,
+    pub metrics: Arc<Metrics>,
+    pub notifier: Notifier,
+    pub settings: Settings,
+    /// Listen on every interface rather than only localhost
+    pub listen_all: bool,
+}
 
-    let app = Router::new()
-        .route("/", get(search_ui))
-        .route("/htmx/logs/chunk", get(htmx_logs_chunk))
-        .route("/api/search", get(api_search))
-        .route("/api/timechart", get(api_timechart))
-        .route("/api/columns", get(api_columns))
-        .route("/api/filters", get(api_filters))
-        .route("/api/processes", get(api_processes))
-        .route("/htmx/processes/chunk", get(htmx_processes_chunk))
-        .route("/api/storage/health", get(api_storage_health))
-        .route("/health", get(health))
-        // Static file routes for process monitoring UI
-        .route("/index.html", get(serve_index_html))
-        .route("/processes.html", get(processes_ui))
+pub async fn run_web_server(deps: WebServerDeps) {
+    let WebServerDeps {
+        data_dir,
+        buffer,
+        shutdown_signal,
+        process_monitor,
+        metrics,
+        notifier,
+        settings,
+        listen_all,
+    } = deps;
+    let readers = buffer
+        .lock()
+        .unwrap()
+        .read_pool(settings.web_read_connections)
+        .expect("Failed to open reader connections for the web server");
+    log::info!(
+        "Web server using {} DuckDB reader connections",
+        readers.size()
+    );
+    let tls = settings.tls_paths().expect("Invalid TLS settings");
+    let oidc = match settings.oidc.clone() {
+        Some(oidc) => Some(
+            OidcProvider::discover(oidc)
+                .await
+                .expect("Failed to read the OIDC provider configuration"),
+        ),
+        None => None,
+    };
+    let mut state = AppState::new(&data_dir, buffer, readers, process_monitor, settings);
+    state.oidc = oidc;
+    state.metrics = metrics;
+    state.notifier = notifier;
+    let state = Arc::new(state);
+    tokio::spawn(refresh_integrity(state.clone()));
+    tokio::spawn(expire_export_jobs(state.clone()));
+    if state.settings.query_log_table {
+        tokio::spawn(persist_query_log(state.clone()));
+    }
+
+    let app = routes(&state)
         .layer(
             TraceLayer::new_for_http()
                 .on_request(|request: &axum::http::Request<_>, _span: &tracing::Span| {
@@ -398,826 +1544,4479 @@ pub async fn run_web_server(
         "127.0.0.1:3000"
     };
 
+    if let Some((cert, key)) = tls {
+        let config = RustlsConfig::from_pem_file(&cert, &key)
+            .await
+            .expect("Failed to load TLS certificate and key");
+        tokio::spawn(reload_tls_on_sighup(config.clone(), cert, key));
+
+        let handle = axum_server::Handle::new();
+        let shutdown = handle.clone();
+        tokio::spawn(async move {
+            wait_for_shutdown(shutdown_signal).await;
+            shutdown.graceful_shutdown(None);
+        });
+
+        let addr: SocketAddr = bind_addr.parse().unwrap();
+        log::info!("Web server listening on https://{}", addr);
+        axum_server::bind_rustls(addr, config)
+            .handle(handle)
+            .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+            .await
+            .unwrap();
+        return;
+    }
+
     let listener = tokio::net::TcpListener::bind(bind_addr).await.unwrap();
     log::info!("Web server listening on {}", listener.local_addr().unwrap());
 
     // Run axum server with graceful shutdown
-    axum::serve(listener, app)
-        .with_graceful_shutdown(async move {
-            // Poll the shutdown signal
-            loop {
-                if shutdown_signal.load(Ordering::Relaxed) {
-                    log::info!("Web server received shutdown signal");
-                    break;
-                }
-                tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
-            }
-        })
-        .await
-        .unwrap();
-}
-
-/// Health check endpoint
-async fn health(State(state): State<Arc<AppState>>) -> impl IntoResponse {
-    Json(HealthResponse {
-        status: "ok".to_string(),
-        data_dir: state.data_dir.clone(),
-    })
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(wait_for_shutdown(shutdown_signal))
+    .await
+    .unwrap();
 }
 
-/// Serve index.html static file
-async fn serve_index_html() -> impl IntoResponse {
-    match tokio::fs::read_to_string("static/index.html").await {
-        Ok(content) => Html(content).into_response(),
-        Err(_) => (StatusCode::NOT_FOUND, "index.html not found").into_response(),
+/// Resolve once the application's shutdown signal is set
+async fn wait_for_shutdown(shutdown_signal: Arc<AtomicBool>) {
+    // Poll the shutdown signal
+    loop {
+        if shutdown_signal.load(Ordering::Relaxed) {
+            log::info!("Web server received shutdown signal");
+            break;
+        }
+        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
     }
 }
 
-/// Serve process monitor page rendered with HTMX table fragments
-async fn processes_ui() -> impl IntoResponse {
-    Html(build_processes_html())
+/// Reload the TLS certificate and key on every SIGHUP, so renewed certificates are
+/// served without a restart. A bad pair is logged and the current one kept.
+async fn reload_tls_on_sighup(config: RustlsConfig, cert: PathBuf, key: PathBuf) {
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(e) => {
+            log::warn!("Failed to listen for SIGHUP, TLS reload disabled: {}", e);
+            return;
+        }
+    };
+    while hangup.recv().await.is_some() {
+        match config.reload_from_pem_file(&cert, &key).await {
+            Ok(()) => log::info!("Reloaded TLS certificate from {}", cert.display()),
+            Err(e) => log::warn!(
+                "Failed to reload TLS certificate, keeping the old one: {}",
+                e
+            ),
+        }
+    }
 }
 
-/// API endpoint returning current process snapshot
-async fn api_processes(
-    State(state): State<Arc<AppState>>,
-) -> Result<Json<ProcessResponse>, (StatusCode, String)> {
-    let (processes, timestamp) = get_current_process_rows(&state)?;
-    let total = processes.len();
-    Ok(Json(ProcessResponse {
-        processes,
-        timestamp,
-        total,
-    }))
+/// Every route, each group behind the [`Role`] it requires. /health is open.
+fn routes(state: &Arc<AppState>) -> Router<Arc<AppState>> {
+    let cached = middleware::from_fn_with_state(state.clone(), cache_query);
+    let viewer = Router::new()
+        .route("/", get(search_ui))
+        .route("/htmx/logs/chunk", get(htmx_logs_chunk))
+        .route("/api/search", get(api_search).route_layer(cached.clone()))
+        .route("/api/search/stream", get(api_search_stream))
+        .route("/api/tail", get(api_tail))
+        .route(
+            "/api/timechart",
+            get(api_timechart).route_layer(cached.clone()),
+        )
+        .route(
+            "/api/histogram",
+            get(api_histogram).route_layer(cached.clone()),
+        )
+        .route("/api/columns", get(api_columns).route_layer(cached.clone()))
+        .route("/api/extra-fields", get(api_extra_fields))
+        .route("/api/summary", get(api_summary))
+        .route("/api/stats", get(api_stats))
+        .route("/api/units", get(api_units))
+        .route("/api/hosts", get(api_hosts))
+        .route("/api/filters", get(api_filters).route_layer(cached.clone()))
+        .route("/api/values", get(api_values))
+        .route("/api/processes", get(api_processes))
+        .route("/api/processes/history", get(api_process_history))
+        .route("/api/processes/tree", get(api_process_tree))
+        .route("/api/processes/events", get(api_process_events))
+        .route("/api/system/history", get(api_system_history))
+        .route("/api/gpu/history", get(api_gpu_history))
+        .route("/api/alerts", get(api_alerts))
+        .route("/api/alert-rules", get(api_alert_rules))
+        .route("/api/silences", get(api_silences))
+        .route("/api/unit-failures", get(api_unit_failures))
+        .route("/api/sources", get(api_sources))
+        .route("/api/internal", get(api_internal))
+        .route("/htmx/processes/chunk", get(htmx_processes_chunk))
+        .route("/api/storage/health", get(api_storage_health))
+        .route("/metrics", get(metrics))
+        // Grafana simple-json datasource
+        .route("/grafana", get(grafana_check))
+        .route("/grafana/search", post(grafana_search))
+        .route("/grafana/query", post(grafana_query))
+        // Loki read API, log queries only
+        .route("/loki/api/v1/query_range", get(loki_query_range))
+        .route("/loki/api/v1/labels", get(loki_labels))
+        .route("/loki/api/v1/label/{name}/values", get(loki_label_values))
+        // Static file routes for process monitoring UI
+        .route("/index.html", get(serve_index_html))
+        .route("/processes.html", get(processes_ui))
+        .route_layer(middleware::from_fn_with_state(
+            (state.clone(), Role::Viewer),
+            require_role,
+        ));
+    let operator = Router::new()
+        .route("/api/export", get(api_export))
+        .route("/api/export/jobs", post(api_create_export_job))
+        .route("/api/export/jobs/{id}", get(api_export_job))
+        .route(
+            "/api/export/jobs/{id}/download",
+            get(api_export_job_download),
+        )
+        .route("/api/logs", delete(api_purge_logs))
+        .route("/api/alert-rules", post(api_create_alert_rule))
+        .route(
+            "/api/alert-rules/{name}",
+            put(api_update_alert_rule).delete(api_delete_alert_rule),
+        )
+        .route("/api/alert-rules/{name}/test", post(api_test_alert_rule))
+        .route("/api/silences", post(api_create_silence))
+        .route("/api/silences/{id}", delete(api_expire_silence))
+        .route_layer(middleware::from_fn_with_state(
+            (state.clone(), Role::Operator),
+            require_role,
+        ));
+    let admin = Router::new()
+        .route(
+            "/api/extra-fields/{key}/promote",
+            post(api_promote_extra_field),
+        )
+        .route("/api/debug/queries", get(api_debug_queries))
+        .route_layer(middleware::from_fn_with_state(
+            (state.clone(), Role::Admin),
+            require_role,
+        ));
+    Router::new()
+        .merge(viewer)
+        .merge(operator)
+        .merge(admin)
+        .route("/auth/login", get(auth_login))
+        .route("/auth/callback", get(auth_callback))
+        .route("/auth/logout", get(auth_logout))
+        .route("/health", get(health))
+        .route("/health/live", get(health_live))
+        .route("/health/ready", get(health_ready))
+        .layer(middleware::from_fn_with_state(state.clone(), rate_limit))
+        // gzip/brotli for clients that accept it; Parquet exports are compressed already
+        .layer(
+            CompressionLayer::new().compress_when(
+                DefaultPredicate::new()
+                    .and(NotForContentType::new(ExportFormat::Parquet.content_type())),
+            ),
+        )
 }
 
-fn get_current_process_rows(
-    state: &Arc<AppState>,
-) -> Result<(Vec<ProcessMetricsRow>, String), (StatusCode, String)> {
-    let latest_timestamp = state
-        .buffer
-        .lock()
-        .unwrap()
-        .get_latest_process_timestamp()
-        .ok()
-        .flatten();
+/// Health check endpoint
+async fn health(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let integrity = state.integrity.lock().unwrap().clone();
+    let status = match &integrity {
+        Some(report) if report.status == CheckStatus::Error => "degraded",
+        _ => "ok",
+    };
+    Json(HealthResponse {
+        status: status.to_string(),
+        data_dir: state.data_dir.clone(),
+        integrity,
+        backfill: state.metrics.backfill_progress(),
+    })
+}
 
-    if let Some(latest_timestamp) = latest_timestamp {
-        let rows = state
-            .buffer
-            .lock()
-            .unwrap()
-            .get_process_metrics_for_timestamp(&latest_timestamp)
-            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+fn probe_check(name: &str, ok: bool, detail: String) -> CheckResult {
+    CheckResult {
+        name: name.to_string(),
+        status: if ok {
+            CheckStatus::Ok
+        } else {
+            CheckStatus::Error
+        },
+        detail,
+    }
+}
 
-        let processes: Vec<ProcessMetricsRow> = rows.into_iter().map(to_process_row).collect();
-        return Ok((processes, latest_timestamp));
+fn probe_response(checks: Vec<CheckResult>) -> Response {
+    let ok = checks.iter().all(|c| c.status == CheckStatus::Ok);
+    let status = if ok {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    let body = ProbeResponse {
+        status: if ok { "ok" } else { "unavailable" }.to_string(),
+        checks,
+    };
+    (status, Json(body)).into_response()
+}
+
+/// Whether the collector's main loop is still running. `required` fails the check
+/// while the loop has not started yet (it replays recent history first).
+fn collector_check(state: &AppState, now: DateTime<Utc>, required: bool) -> CheckResult {
+    match state.metrics.heartbeat_age_seconds(now) {
+        Some(age) => probe_check(
+            "collector",
+            age <= COLLECTOR_STALL_SECS,
+            format!("main loop last ran {:.1}s ago", age),
+        ),
+        None => probe_check(
+            "collector",
+            !required,
+            match state.metrics.backfill_progress() {
+                Some(progress) => format!(
+                    "main loop has not started, loading recent history ({} entries so far)",
+                    progress.entries
+                ),
+                None => "main loop has not started".to_string(),
+            },
+        ),
     }
+}
 
-    let snapshot = state.process_monitor.get_snapshot();
-    let timestamp = chrono::Utc::now().to_rfc3339();
-    let processes: Vec<ProcessMetricsRow> = snapshot
-        .into_iter()
-        .map(|process| {
-            let user = process.user_id.as_ref().and_then(|uid_str| {
-                uid_str
-                    .strip_prefix("Uid(")
-                    .and_then(|s| s.strip_suffix(')'))
-                    .map(|s| s.to_string())
-            });
+/// Liveness probe: fails only when the collector has stalled and needs a restart
+async fn health_live(State(state): State<Arc<AppState>>) -> Response {
+    probe_response(vec![collector_check(&state, Utc::now(), false)])
+}
 
-            let cmdline = process.cmdline();
+/// Readiness probe: the database answers queries, the collector is running and
+/// keeping up, and the last retention pass succeeded
+async fn health_ready(State(state): State<Arc<AppState>>) -> Response {
+    let database = match run_query(&state, "health", serde_json::json!({}), |reader| {
+        reader.query_json_rows("SELECT 1 AS ok", &[], &["ok".to_string()])
+    })
+    .await
+    {
+        Ok(_) => probe_check("database", true, "query succeeded".to_string()),
+        Err(e) => probe_check("database", false, e.to_string()),
+    };
 
-            ProcessMetricsRow {
-                timestamp: timestamp.clone(),
-                pid: process.pid,
-                name: process.name,
-                cpu_usage: process.cpu_percent,
-                mem_usage: process.memory_bytes as f64,
-                user,
-                runtime: process.runtime_secs,
-                cmdline,
-                virtual_memory: process.virtual_memory_bytes as f64,
-                status: Some(process.status),
-                parent_pid: process.parent_pid,
-            }
+    let now = Utc::now();
+    let mut checks = vec![database, collector_check(&state, now, true)];
+
+    let max_age = state.settings.ready_max_entry_age_secs;
+    if max_age > 0 {
+        checks.push(match state.metrics.ingest_lag_seconds(now) {
+            Some(age) => probe_check(
+                "newest_entry",
+                age <= max_age as f64,
+                format!("newest entry is {:.1}s old (limit {}s)", age, max_age),
+            ),
+            None => probe_check("newest_entry", false, "no entries ingested yet".to_string()),
+        });
+    }
+
+    if let Some((queued, capacity)) = state.process_monitor.metrics_backlog() {
+        checks.push(probe_check(
+            "process_metrics_channel",
+            queued * 2 <= capacity,
+            format!("{} of {} batches waiting", queued, capacity),
+        ));
+    }
+
+    checks.push(match state.metrics.last_retention_run() {
+        Some(run) => match run.error {
+            Some(e) => probe_check(
+                "retention",
+                false,
+                format!("failed at {}: {}", run.at.to_rfc3339(), e),
+            ),
+            None => probe_check(
+                "retention",
+                true,
+                format!("last ran at {}", run.at.to_rfc3339()),
+            ),
+        },
+        None => probe_check("retention", true, "not run yet".to_string()),
+    });
+
+    probe_response(checks)
+}
+
+/// Re-run the quick integrity checks on a pooled connection every
+/// [`INTEGRITY_CHECK_INTERVAL`], keeping the latest report for /health
+async fn refresh_integrity(state: Arc<AppState>) {
+    loop {
+        let worker = state.clone();
+        match tokio::task::spawn_blocking(move || {
+            let report = worker.readers.get().check_integrity(false);
+            *worker.integrity.lock().unwrap() = Some(report.clone());
+            report
         })
-        .collect();
-    Ok((processes, timestamp))
+        .await
+        {
+            Ok(report) if report.status != CheckStatus::Ok => {
+                log::warn!(
+                    "Integrity check reported {:?}: {:?}",
+                    report.status,
+                    report.checks
+                )
+            }
+            Ok(_) => {}
+            Err(e) => log::warn!("Integrity check task failed: {}", e),
+        }
+        tokio::time::sleep(INTEGRITY_CHECK_INTERVAL).await;
+    }
 }
 
-/// API endpoint returning storage health and statistics
-async fn api_storage_health(
-    State(state): State<Arc<AppState>>,
-) -> Result<Json<StorageHealthResponse>, (StatusCode, String)> {
-    // Get database file size
-    let db_path = std::path::Path::new(&state.data_dir).join("livedata.duckdb");
-    let database_size_bytes = std::fs::metadata(&db_path).map(|m| m.len()).unwrap_or(0);
+/// Serve index.html static file
+async fn serve_index_html(headers: HeaderMap) -> impl IntoResponse {
+    match tokio::fs::read_to_string("static/index.html").await {
+        // Revalidated on every load, so edits to the file show up immediately
+        Ok(content) => with_etag(
+            &headers,
+            "no-cache",
+            Html(content.clone()),
+            content.as_bytes(),
+        ),
+        Err(_) => (StatusCode::NOT_FOUND, "index.html not found").into_response(),
+    }
+}
 
-    let stats = state
-        .buffer
-        .lock()
-        .unwrap()
-        .get_storage_stats()
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+/// Serve process monitor page rendered with HTMX table fragments
+async fn processes_ui() -> impl IntoResponse {
+    Html(build_processes_html())
+}
 
-    let retention_policy = RetentionPolicy {
-        log_retention_days: state.settings.log_retention_days,
-        log_max_size_gb: state.settings.log_max_size_gb,
-        process_retention_days: state.settings.process_retention_days,
-        process_max_size_gb: state.settings.process_max_size_gb,
-    };
+/// API endpoint returning current process snapshot
+async fn api_processes(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ProcessesParams>,
+) -> Result<Json<ProcessResponse>, (StatusCode, String)> {
+    let sort = params.sort.as_deref().unwrap_or("cpu_usage");
+    let sort_field = process_field(sort).ok_or_else(|| {
+        (
+            StatusCode::BAD_REQUEST,
+            format!("Unknown sort field '{}'", sort),
+        )
+    })?;
+    let fields = params
+        .fields
+        .as_deref()
+        .map(|fields| {
+            fields
+                .split(',')
+                .map(str::trim)
+                .filter(|f| !f.is_empty())
+                .map(|f| {
+                    process_field(f)
+                        .ok_or_else(|| (StatusCode::BAD_REQUEST, format!("Unknown field '{}'", f)))
+                })
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .transpose()?;
+
+    let (mut processes, timestamp) = get_current_process_rows(&state).await?;
+    let name = params.name.as_deref().map(str::to_lowercase);
+    processes.retain(|p| {
+        name.as_deref()
+            .is_none_or(|name| p.name.to_lowercase().contains(name))
+            && params.user.as_deref().is_none_or(|user| {
+                p.user.as_deref() == Some(user) || p.uid.is_some_and(|uid| uid.to_string() == user)
+            })
+            && params.pid.is_none_or(|pid| p.pid == pid)
+            && params
+                .unit
+                .as_deref()
+                .is_none_or(|unit| p.unit.as_deref() == Some(unit))
+            && params.container.as_deref().is_none_or(|container| {
+                p.container_id
+                    .as_deref()
+                    .is_some_and(|id| id.starts_with(container))
+            })
+            && params.min_cpu.is_none_or(|min| p.cpu_usage >= min)
+            && params.min_mem.is_none_or(|min| p.mem_usage >= min)
+    });
+    processes.sort_by(|a, b| {
+        let ordering = compare_processes(a, b, sort_field);
+        match sort_direction(&params.sort_dir) {
+            "ASC" => ordering,
+            _ => ordering.reverse(),
+        }
+    });
 
-    Ok(Json(StorageHealthResponse {
-        database_size_bytes,
-        journal_log_count: stats.journal_log_count,
-        process_metric_count: stats.process_metric_count,
-        oldest_log_timestamp: stats.oldest_log_timestamp,
-        newest_log_timestamp: stats.newest_log_timestamp,
-        retention_policy,
+    let total = processes.len();
+    let page = processes
+        .into_iter()
+        .skip(params.offset)
+        .take(params.limit.unwrap_or(usize::MAX))
+        .map(|process| {
+            let mut value = serde_json::to_value(process)
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+            if let (Some(fields), Some(object)) = (&fields, value.as_object_mut()) {
+                object.retain(|key, _| fields.contains(&key.as_str()));
+            }
+            Ok(value)
+        })
+        .collect::<Result<Vec<_>, (StatusCode, String)>>()?;
+    Ok(Json(ProcessResponse {
+        processes: page,
+        timestamp,
+        total,
     }))
 }
 
-async fn htmx_logs_chunk(
+/// Field of [`ProcessMetricsRow`] a sort or field name refers to
+fn process_field(name: &str) -> Option<&'static str> {
+    Some(match name.to_lowercase().as_str() {
+        "timestamp" => "timestamp",
+        "pid" => "pid",
+        "name" => "name",
+        "cpu" | "cpu_usage" => "cpu_usage",
+        "mem" | "memory" | "mem_usage" => "mem_usage",
+        "user" => "user",
+        "uid" => "uid",
+        "runtime" => "runtime",
+        "cmdline" => "cmdline",
+        "virtual_memory" => "virtual_memory",
+        "status" => "status",
+        "parent_pid" | "ppid" => "parent_pid",
+        "read" | "read_bytes_per_sec" => "read_bytes_per_sec",
+        "write" | "write_bytes_per_sec" => "write_bytes_per_sec",
+        "io" => "io",
+        "threads" | "num_threads" => "num_threads",
+        "fds" | "open_fd_count" => "open_fd_count",
+        "unit" => "unit",
+        "container" | "container_id" => "container_id",
+        _ => return None,
+    })
+}
+
+/// Ascending order of two processes by a [`process_field`]
+fn compare_processes(
+    a: &ProcessMetricsRow,
+    b: &ProcessMetricsRow,
+    field: &str,
+) -> std::cmp::Ordering {
+    match field {
+        "pid" => a.pid.cmp(&b.pid),
+        "name" => a.name.cmp(&b.name),
+        "mem_usage" => a.mem_usage.total_cmp(&b.mem_usage),
+        "user" => a.user.cmp(&b.user),
+        "uid" => a.uid.cmp(&b.uid),
+        "runtime" => a.runtime.cmp(&b.runtime),
+        "cmdline" => a.cmdline.cmp(&b.cmdline),
+        "virtual_memory" => a.virtual_memory.total_cmp(&b.virtual_memory),
+        "status" => a.status.cmp(&b.status),
+        "parent_pid" => a.parent_pid.cmp(&b.parent_pid),
+        "read_bytes_per_sec" => a.read_bytes_per_sec.total_cmp(&b.read_bytes_per_sec),
+        "write_bytes_per_sec" => a.write_bytes_per_sec.total_cmp(&b.write_bytes_per_sec),
+        "io" => (a.read_bytes_per_sec + a.write_bytes_per_sec)
+            .total_cmp(&(b.read_bytes_per_sec + b.write_bytes_per_sec)),
+        "num_threads" => a.num_threads.cmp(&b.num_threads),
+        "open_fd_count" => a.open_fd_count.cmp(&b.open_fd_count),
+        "unit" => a.unit.cmp(&b.unit),
+        "container_id" => a.container_id.cmp(&b.container_id),
+        "timestamp" => a.timestamp.cmp(&b.timestamp),
+        _ => a.cpu_usage.total_cmp(&b.cpu_usage),
+    }
+    // Ties keep a stable order across pages
+    .then(a.pid.cmp(&b.pid))
+}
+
+/// Most process events returned by /api/processes/events
+const MAX_PROCESS_EVENTS: usize = 5_000;
+
+/// Journal entries of an exited process logged up to this long before its exit was
+/// noticed are shown with the exit
+const EXIT_JOURNAL_LOOKBACK_SECS: i64 = 60;
+
+/// Journal entries shown with each exit
+const EXIT_JOURNAL_LINES: usize = 5;
+
+/// Process starts and exits recorded between samples. Each exit comes with the last
+/// journal entries its pid logged, which usually explain a crash.
+async fn api_process_events(
     State(state): State<Arc<AppState>>,
-    Query(params): Query<SearchParams>,
-) -> impl IntoResponse {
-    match query_log_results(&state, &params) {
-        Ok((results, display_names, total_count)) => Html(render_log_chunk_fragment(
-            &params,
-            &results,
-            &display_names,
-            total_count,
-        ))
-        .into_response(),
-        Err((status, msg)) => (status, msg).into_response(),
+    Query(params): Query<ProcessEventsParams>,
+) -> Result<Json<ProcessEventsResponse>, ApiError> {
+    let now = Utc::now();
+    let start = parse_time(&params.start, now).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let end = parse_time(&params.end, now).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    let mut conditions = vec!["e.timestamp >= ?", "e.timestamp < ?"];
+    let mut sql_params = vec![
+        SqlParam::Text(start.to_rfc3339()),
+        SqlParam::Text(end.to_rfc3339()),
+    ];
+    if let Some(pid) = params.pid {
+        conditions.push("e.pid = ?");
+        sql_params.push(SqlParam::Int(pid as i32));
+    }
+    if let Some(name) = params.name.filter(|name| !name.is_empty()) {
+        conditions.push("e.name = ?");
+        sql_params.push(SqlParam::Text(name));
+    }
+    if let Some(unit) = params.unit.filter(|unit| !unit.is_empty()) {
+        conditions.push("e.unit = ?");
+        sql_params.push(SqlParam::Text(unit));
+    }
+    if let Some(event) = params.event.filter(|event| !event.is_empty()) {
+        if event != "start" && event != "exit" {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("Unknown event '{}'; use start or exit", event),
+            )
+                .into());
+        }
+        conditions.push("e.event = ?");
+        sql_params.push(SqlParam::Text(event));
+    }
+    sql_params.push(SqlParam::Int(params.limit.min(MAX_PROCESS_EVENTS) as i32));
+
+    let source = state.log_source(
+        start - chrono::Duration::seconds(EXIT_JOURNAL_LOOKBACK_SECS),
+        end,
+    );
+    let sql = format!(
+        "SELECT CAST(e.timestamp AS VARCHAR), e.event, e.pid, e.parent_pid, e.name,
+                e.cmdline, e.uid, e.user, e.unit, CAST(e.start_time AS VARCHAR),
+                CAST(e.end_time AS VARCHAR),
+                date_diff('second', e.start_time, COALESCE(e.end_time, e.timestamp)),
+                CASE WHEN e.event = 'exit' THEN (
+                    SELECT CAST(to_json(list({{
+                        'timestamp': CAST(j.timestamp AS VARCHAR),
+                        'priority': j.priority,
+                        'message': j.message
+                    }} ORDER BY j.timestamp DESC)[1:{lines}]) AS VARCHAR)
+                    FROM {source} j
+                    WHERE j._PID = e.pid
+                      AND j.timestamp >= e.end_time - INTERVAL {lookback} SECOND
+                      AND j.timestamp <= e.end_time
+                ) END
+         FROM {events} e
+         WHERE {conditions}
+         ORDER BY e.timestamp DESC, e.pid
+         LIMIT ?",
+        lines = EXIT_JOURNAL_LINES,
+        source = source.sql,
+        lookback = EXIT_JOURNAL_LOOKBACK_SECS,
+        events = PROCESS_EVENTS_TABLE,
+        conditions = conditions.join(" AND "),
+    );
+    let names: Vec<String> = [
+        "timestamp",
+        "event",
+        "pid",
+        "parent_pid",
+        "name",
+        "cmdline",
+        "uid",
+        "user",
+        "unit",
+        "start_time",
+        "end_time",
+        "runtime_secs",
+        "journal",
+    ]
+    .iter()
+    .map(|name| name.to_string())
+    .collect();
+    let mut events = run_query(
+        &state,
+        "process_events",
+        serde_json::json!({}),
+        move |reader| reader.query_json_rows(&sql, &sql_params, &names),
+    )
+    .await?;
+
+    for event in &mut events {
+        let journal = match event["journal"].as_str() {
+            Some(json) => serde_json::from_str(json).unwrap_or_default(),
+            None if event["event"] == "exit" => serde_json::json!([]),
+            None => serde_json::Value::Null,
+        };
+        event["journal"] = journal;
     }
+    Ok(Json(ProcessEventsResponse { events }))
 }
 
-async fn htmx_processes_chunk(
+/// Process hierarchy of one stored sample, rebuilt from parent PIDs, so a runaway
+/// child can be traced back to the service that spawned it
+async fn api_process_tree(
     State(state): State<Arc<AppState>>,
-    Query(params): Query<ProcessTableParams>,
-) -> impl IntoResponse {
-    let (mut processes, timestamp) = match get_current_process_rows(&state) {
-        Ok(data) => data,
-        Err((status, msg)) => return (status, msg).into_response(),
+    Query(params): Query<ProcessTreeParams>,
+) -> Result<Json<ProcessTreeResponse>, ApiError> {
+    let at = params
+        .at
+        .as_deref()
+        .map(|at| parse_time(at, Utc::now()))
+        .transpose()
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let (processes, timestamp) = match at {
+        None => get_current_process_rows(&state).await?,
+        Some(at) => {
+            let found = run_query(
+                &state,
+                "process_tree",
+                serde_json::json!({}),
+                move |reader| {
+                    let Some(timestamp) = reader.get_process_timestamp_at(at)? else {
+                        return Ok(None);
+                    };
+                    let rows = reader.get_process_metrics_for_timestamp(&timestamp)?;
+                    Ok(Some((rows, timestamp)))
+                },
+            )
+            .await?;
+            let (rows, timestamp) = found.ok_or_else(|| {
+                (
+                    StatusCode::NOT_FOUND,
+                    format!("No process sample at or before {}", at.to_rfc3339()),
+                )
+            })?;
+            (rows.into_iter().map(to_process_row).collect(), timestamp)
+        }
     };
 
-    processes.sort_by(|a, b| b.cpu_usage.total_cmp(&a.cpu_usage));
-
-    if let Some(q) = params.q.as_deref()
-        && !q.trim().is_empty()
-    {
-        let needle = q.to_lowercase();
-        processes.retain(|p| {
-            let haystack = format!(
-                "{} {} {} {:.1} {}",
-                p.pid,
-                p.name,
-                p.user.as_deref().unwrap_or(""),
-                p.cpu_usage,
-                p.timestamp
+    let mut roots = build_process_tree(processes);
+    if let Some(pid) = params.pid {
+        let node = take_process_subtree(roots, pid).ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                format!("Process {} is not in the sample at {}", pid, timestamp),
             )
-            .to_lowercase();
-            fuzzy_match(&needle, &haystack)
-        });
+        })?;
+        roots = vec![node];
     }
+    Ok(Json(ProcessTreeResponse { timestamp, roots }))
+}
 
-    let total_count = processes.len();
-    let limit = params.limit.clamp(10, 1000);
-    let start = params.offset.min(total_count);
-    let end = (start + limit).min(total_count);
-    let page = &processes[start..end];
+/// Arrange one sample's processes under their parents, busiest subtrees first.
+/// Processes whose parent is missing from the sample become roots.
+fn build_process_tree(processes: Vec<ProcessMetricsRow>) -> Vec<ProcessTreeNode> {
+    let pids: std::collections::HashSet<u32> = processes.iter().map(|p| p.pid).collect();
+    let mut children: std::collections::HashMap<Option<u32>, Vec<ProcessMetricsRow>> =
+        std::collections::HashMap::new();
+    for process in processes {
+        let parent = process
+            .parent_pid
+            .filter(|ppid| *ppid != process.pid && pids.contains(ppid));
+        children.entry(parent).or_default().push(process);
+    }
 
-    Html(render_process_chunk_fragment(
-        &params,
-        page,
-        total_count,
-        &timestamp,
-    ))
-    .into_response()
+    fn attach(
+        parent: Option<u32>,
+        children: &mut std::collections::HashMap<Option<u32>, Vec<ProcessMetricsRow>>,
+    ) -> Vec<ProcessTreeNode> {
+        let mut nodes: Vec<ProcessTreeNode> = children
+            .remove(&parent)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|process| {
+                let descendants = attach(Some(process.pid), children);
+                ProcessTreeNode {
+                    total_cpu_usage: process.cpu_usage as f64
+                        + descendants.iter().map(|c| c.total_cpu_usage).sum::<f64>(),
+                    total_mem_usage: process.mem_usage
+                        + descendants.iter().map(|c| c.total_mem_usage).sum::<f64>(),
+                    process,
+                    children: descendants,
+                }
+            })
+            .collect();
+        nodes.sort_by(|a, b| {
+            b.total_cpu_usage
+                .total_cmp(&a.total_cpu_usage)
+                .then(a.process.pid.cmp(&b.process.pid))
+        });
+        nodes
+    }
+    attach(None, &mut children)
 }
 
-type LogQueryResult = Result<(Vec<serde_json::Value>, Vec<String>, usize), (StatusCode, String)>;
+/// The node of `pid`, searched depth first
+fn take_process_subtree(nodes: Vec<ProcessTreeNode>, pid: u32) -> Option<ProcessTreeNode> {
+    for node in nodes {
+        if node.process.pid == pid {
+            return Some(node);
+        }
+        if let Some(found) = take_process_subtree(node.children, pid) {
+            return Some(found);
+        }
+    }
+    None
+}
 
-fn query_log_results(state: &Arc<AppState>, params: &SearchParams) -> LogQueryResult {
+/// CPU and memory of one process (by `pid`), all processes with a `name` or all
+/// processes of a systemd `unit` over time, from the stored process metrics
+async fn api_process_history(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ProcessHistoryParams>,
+) -> Result<Json<ProcessHistoryResponse>, ApiError> {
     let now = Utc::now();
     let start = parse_time(&params.start, now).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
     let end = parse_time(&params.end, now).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
-    let limit = params.limit.min(100_000);
+    let name = params.name.filter(|name| !name.is_empty());
+    let unit = params.unit.filter(|unit| !unit.is_empty());
+    if params.pid.is_none() && name.is_none() && unit.is_none() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Process history needs a pid, name or unit".to_string(),
+        )
+            .into());
+    }
+    let (bin, bin_seconds) = histogram_bin(params.bin.as_deref(), end - start)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    let pid = params.pid;
+    let (query_name, query_unit) = (name.clone(), unit.clone());
+    let points = run_query(
+        &state,
+        "process_history",
+        serde_json::json!({}),
+        move |reader| {
+            reader.get_process_history(
+                start,
+                end,
+                pid,
+                query_name.as_deref(),
+                query_unit.as_deref(),
+                bin_seconds,
+            )
+        },
+    )
+    .await?;
+    Ok(Json(ProcessHistoryResponse {
+        pid,
+        name,
+        unit,
+        bin: bin.to_string(),
+        bin_seconds,
+        points,
+    }))
+}
 
-    let schema = get_schema_columns(&state.buffer);
-    if schema.is_empty() {
-        return Ok((
-            Vec::new(),
-            DEFAULT_COLUMNS
+/// Host load, memory, swap, disk, network and (when collected) sensor readings over
+/// time, one series per metric and device
+async fn api_system_history(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<SystemHistoryParams>,
+) -> Result<Json<SystemHistoryResponse>, ApiError> {
+    let now = Utc::now();
+    let start = parse_time(&params.start, now).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let end = parse_time(&params.end, now).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let metrics = params
+        .metrics
+        .as_deref()
+        .unwrap_or("")
+        .split(',')
+        .map(str::trim)
+        .filter(|m| !m.is_empty())
+        .map(|m| {
+            SYSTEM_METRICS
                 .iter()
-                .map(|c| column_display_name(c))
-                .collect(),
-            0,
-        ));
+                .find(|known| **known == m)
+                .copied()
+                .ok_or_else(|| (StatusCode::BAD_REQUEST, format!("Unknown metric '{}'", m)))
+        })
+        .collect::<Result<Vec<&'static str>, _>>()?;
+    let (bin, bin_seconds) = histogram_bin(params.bin.as_deref(), end - start)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    let device = params.device.filter(|device| !device.is_empty());
+    let rows = run_query(
+        &state,
+        "system_history",
+        serde_json::json!({}),
+        move |reader| {
+            reader.get_system_history(start, end, &metrics, device.as_deref(), bin_seconds)
+        },
+    )
+    .await?;
+
+    let mut series: Vec<SystemSeries> = Vec::new();
+    for SystemHistoryPoint {
+        metric,
+        device,
+        time_bin,
+        avg,
+        max,
+    } in rows
+    {
+        let point = SystemPoint { time_bin, avg, max };
+        match series.last_mut() {
+            Some(last) if last.metric == metric && last.device == device => last.points.push(point),
+            _ => series.push(SystemSeries {
+                metric,
+                device,
+                points: vec![point],
+            }),
+        }
     }
+    Ok(Json(SystemHistoryResponse {
+        bin: bin.to_string(),
+        bin_seconds,
+        series,
+    }))
+}
 
-    let requested_cols: Vec<&str> = if let Some(ref cols) = params.columns
-        && !cols.is_empty()
-    {
-        cols.split(',').map(|s| s.trim()).collect()
-    } else {
-        DEFAULT_COLUMNS.to_vec()
-    };
-    let select_exprs = validate_columns(&requested_cols, &schema);
-    let select_list = if select_exprs.is_empty() {
-        DEFAULT_COLUMNS
-            .iter()
-            .filter_map(|c| {
-                let exprs = validate_columns(&[c], &schema);
-                exprs.into_iter().next()
-            })
-            .collect::<Vec<_>>()
-    } else {
-        select_exprs
-    };
-    let display_names: Vec<String> = select_list.iter().map(|e| column_display_name(e)).collect();
+/// GPU utilization and memory over time, one series per device, or the GPU memory of
+/// one process when `pid` is given
+async fn api_gpu_history(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<GpuHistoryParams>,
+) -> Result<Json<GpuHistoryResponse>, ApiError> {
+    let now = Utc::now();
+    let start = parse_time(&params.start, now).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let end = parse_time(&params.end, now).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let (bin, bin_seconds) = histogram_bin(params.bin.as_deref(), end - start)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
 
-    let mut where_sql = format!(
-        "timestamp >= '{}' AND timestamp < '{}'",
-        start.to_rfc3339(),
-        end.to_rfc3339()
+    let mut conditions = vec!["timestamp >= ?", "timestamp < ?"];
+    let mut sql_params = vec![
+        SqlParam::Text(start.to_rfc3339()),
+        SqlParam::Text(end.to_rfc3339()),
+    ];
+    match params.pid {
+        Some(pid) => {
+            conditions.push("pid = ?");
+            sql_params.push(SqlParam::Int(pid as i32));
+        }
+        None => conditions.push("pid IS NULL"),
+    }
+    if let Some(device) = params.device.filter(|device| !device.is_empty()) {
+        conditions.push("device = ?");
+        sql_params.push(SqlParam::Text(device));
+    }
+    let sql = format!(
+        "SELECT device, MAX(name), pid,
+                CAST(to_timestamp(floor(epoch(timestamp) / {bin}) * {bin}) AS VARCHAR) AS time_bin,
+                AVG(utilization_percent), MAX(utilization_percent),
+                AVG(memory_used_bytes), MAX(memory_used_bytes), MAX(memory_total_bytes)
+         FROM {table}
+         WHERE {conditions}
+         GROUP BY device, pid, time_bin
+         ORDER BY device, pid, time_bin",
+        bin = bin_seconds,
+        table = GPU_METRICS_TABLE,
+        conditions = conditions.join(" AND "),
     );
+    let names: Vec<String> = [
+        "device",
+        "name",
+        "pid",
+        "time_bin",
+        "avg_utilization_percent",
+        "max_utilization_percent",
+        "avg_memory_used_bytes",
+        "max_memory_used_bytes",
+        "memory_total_bytes",
+    ]
+    .iter()
+    .map(|name| name.to_string())
+    .collect();
+    let rows = run_query(
+        &state,
+        "gpu_history",
+        serde_json::json!({}),
+        move |reader| reader.query_json_rows(&sql, &sql_params, &names),
+    )
+    .await?;
 
-    if let Some(ref q) = params.q
-        && !q.is_empty()
-    {
-        let escaped = escape_like(q);
-        where_sql.push_str(&format!(" AND message ILIKE '%{}%' ESCAPE '\\'", escaped));
+    let mut series: Vec<GpuSeries> = Vec::new();
+    for row in rows {
+        let device = row["device"].as_str().unwrap_or_default().to_string();
+        let pid = row["pid"].as_u64().map(|pid| pid as u32);
+        let point: GpuPoint = serde_json::from_value(row.clone())
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        match series.last_mut() {
+            Some(last) if last.device == device && last.pid == pid => last.points.push(point),
+            _ => series.push(GpuSeries {
+                device,
+                name: row["name"].as_str().map(str::to_string),
+                pid,
+                points: vec![point],
+            }),
+        }
     }
-    if let Some(ref hostname) = params.hostname
-        && !hostname.is_empty()
-    {
-        let hosts: Vec<&str> = hostname.split(',').collect();
-        let host_list: Vec<String> = hosts
-            .iter()
-            .map(|h| format!("'{}'", h.replace('\'', "''")))
-            .collect();
-        where_sql.push_str(&format!(" AND _hostname IN ({})", host_list.join(",")));
+    Ok(Json(GpuHistoryResponse {
+        bin: bin.to_string(),
+        bin_seconds,
+        series,
+    }))
+}
+
+/// Most alerts returned by /api/alerts
+const MAX_ALERTS: usize = 5_000;
+
+/// Alerts that fired or resolved in a time range, newest first, along with every rule
+/// whose latest alert is a firing one
+async fn api_alerts(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<AlertsParams>,
+) -> Result<Json<AlertsResponse>, ApiError> {
+    let now = Utc::now();
+    let start = parse_time(&params.start, now).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let end = parse_time(&params.end, now).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    let mut conditions = Vec::new();
+    let mut filter_params = Vec::new();
+    if let Some(rule) = params.rule.filter(|rule| !rule.is_empty()) {
+        conditions.push("rule = ?");
+        filter_params.push(SqlParam::Text(rule));
     }
-    if let Some(ref unit) = params.unit
-        && !unit.is_empty()
-    {
-        let units: Vec<&str> = unit.split(',').collect();
-        let unit_list: Vec<String> = units
-            .iter()
-            .map(|u| format!("'{}'", u.replace('\'', "''")))
-            .collect();
-        where_sql.push_str(&format!(" AND _systemd_unit IN ({})", unit_list.join(",")));
+    if let Some(source) = params.source.filter(|source| !source.is_empty()) {
+        conditions.push("source = ?");
+        filter_params.push(SqlParam::Text(source));
     }
-    if let Some(priority) = params.priority {
-        where_sql.push_str(&format!(" AND CAST(priority AS INTEGER) <= {}", priority));
+    let mut history_conditions = conditions.clone();
+    let mut history_params = filter_params.clone();
+    history_conditions.extend(["timestamp >= ?", "timestamp < ?"]);
+    history_params.extend([
+        SqlParam::Text(start.to_rfc3339()),
+        SqlParam::Text(end.to_rfc3339()),
+    ]);
+    if let Some(alert_state) = params.state.filter(|s| !s.is_empty()) {
+        if alert_state != "firing" && alert_state != "resolved" {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("Unknown state '{}'; use firing or resolved", alert_state),
+            )
+                .into());
+        }
+        history_conditions.push("state = ?");
+        history_params.push(SqlParam::Text(alert_state));
     }
-
-    let sort_column = match params.sort.to_lowercase().as_str() {
-        "timestamp" => "timestamp",
-        "hostname" | "host" => "_hostname",
-        "unit" => "_systemd_unit",
-        "priority" | "pri" => "priority",
-        "comm" => "_comm",
-        _ => "timestamp",
+    history_params.push(SqlParam::Int(params.limit.min(MAX_ALERTS) as i32));
+    let firing_conditions = if conditions.is_empty() {
+        "TRUE".to_string()
+    } else {
+        conditions.join(" AND ")
     };
 
-    let sort_direction = match params.sort_dir.to_lowercase().as_str() {
-        "asc" => "ASC",
-        "desc" => "DESC",
-        _ => "DESC",
-    };
+    let columns = "CAST(timestamp AS VARCHAR), rule, source, state, value, threshold, message";
+    let history_sql = format!(
+        "SELECT {} FROM {} WHERE {} ORDER BY timestamp DESC LIMIT ?",
+        columns,
+        ALERTS_TABLE,
+        history_conditions.join(" AND ")
+    );
+    let firing_sql = format!(
+        "SELECT {} FROM (
+             SELECT *, row_number() OVER (PARTITION BY source, rule ORDER BY timestamp DESC)
+                 AS latest
+             FROM {} WHERE {}
+         ) WHERE latest = 1 AND state = 'firing'
+         ORDER BY timestamp DESC",
+        columns, ALERTS_TABLE, firing_conditions
+    );
+    let names: Vec<String> = [
+        "timestamp",
+        "rule",
+        "source",
+        "state",
+        "value",
+        "threshold",
+        "message",
+    ]
+    .iter()
+    .map(|name| name.to_string())
+    .collect();
+    let (firing, history) = run_query(&state, "alerts", serde_json::json!({}), move |reader| {
+        let firing = reader.query_json_rows(&firing_sql, &filter_params, &names)?;
+        let history = reader.query_json_rows(&history_sql, &history_params, &names)?;
+        Ok((firing, history))
+    })
+    .await?;
+    Ok(Json(AlertsResponse { firing, history }))
+}
+
+/// Configured and stored log alert rules
+async fn api_alert_rules(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<AlertRuleInfo>>, ApiError> {
+    let buffer = state.buffer.clone();
+    let stored = tokio::task::spawn_blocking(move || buffer.lock().unwrap().stored_alert_rules())
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-    let count_sql = format!("SELECT COUNT(*) FROM journal_logs WHERE {}", where_sql);
     let sql = format!(
-        "SELECT {} FROM journal_logs WHERE {} ORDER BY {} {} LIMIT {} OFFSET {}",
-        select_list.join(", "),
-        where_sql,
-        sort_column,
-        sort_direction,
-        limit,
-        params.offset
+        "SELECT rule FROM {} WHERE source = ?
+         GROUP BY rule HAVING arg_max(state, timestamp) = 'firing'",
+        ALERTS_TABLE
     );
+    let firing: Vec<String> = run_query(
+        &state,
+        "alert_rules",
+        serde_json::json!({}),
+        move |reader| {
+            reader.query_json_rows(
+                &sql,
+                &[SqlParam::Text(LOG_ALERT_SOURCE.to_string())],
+                &["rule".to_string()],
+            )
+        },
+    )
+    .await?
+    .iter()
+    .filter_map(|row| row["rule"].as_str().map(str::to_string))
+    .collect();
 
-    let total_count = state.buffer.lock().unwrap().query_usize(&count_sql);
-    let results = state
-        .buffer
-        .lock()
-        .unwrap()
-        .query_json_rows(&sql, &display_names)
-        .unwrap_or_default();
+    let configured = &state.settings.alert_rules;
+    let rules = configured
+        .iter()
+        .map(|rule| (rule.clone(), AlertRuleOrigin::Config))
+        .chain(
+            stored
+                .into_iter()
+                .filter(|rule| !configured.iter().any(|c| c.name == rule.name))
+                .map(|rule| (rule, AlertRuleOrigin::Api)),
+        )
+        .map(|(rule, origin)| AlertRuleInfo {
+            firing: firing.contains(&rule.name),
+            rule,
+            origin,
+        })
+        .collect();
+    Ok(Json(rules))
+}
 
-    Ok((results, display_names, total_count))
+/// The rule named `name`, with where it comes from
+async fn find_alert_rule(
+    state: &AppState,
+    name: &str,
+) -> Result<(AlertRule, AlertRuleOrigin), (StatusCode, String)> {
+    if let Some(rule) = state.settings.alert_rules.iter().find(|r| r.name == name) {
+        return Ok((rule.clone(), AlertRuleOrigin::Config));
+    }
+    let buffer = state.buffer.clone();
+    let stored = tokio::task::spawn_blocking(move || buffer.lock().unwrap().stored_alert_rules())
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    stored
+        .into_iter()
+        .find(|rule| rule.name == name)
+        .map(|rule| (rule, AlertRuleOrigin::Api))
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                format!("No alert rule named '{}'", name),
+            )
+        })
 }
 
-/// Get valid column names from the journal_logs schema
-fn get_schema_columns(buffer: &Arc<Mutex<DuckDBBuffer>>) -> Vec<(String, String)> {
-    buffer.lock().unwrap().get_schema_columns()
+/// Validate and store `rule`, creating or replacing it. Raw SQL conditions are
+/// reserved for admins, as they run against the database directly.
+async fn save_alert_rule(
+    state: &Arc<AppState>,
+    identity: &Identity,
+    rule: AlertRule,
+) -> Result<AlertRule, (StatusCode, String)> {
+    if rule.sql.as_ref().is_some_and(|sql| !sql.trim().is_empty()) && identity.role < Role::Admin {
+        return Err((
+            StatusCode::FORBIDDEN,
+            format!("Alert rules with sql require the {} role", Role::Admin),
+        ));
+    }
+    let rule = state
+        .with_reader(move |reader| alerting::validate_rule(&rule, reader).map(|()| rule))
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("{:#}", e)))?;
+    let buffer = state.buffer.clone();
+    tokio::task::spawn_blocking(move || {
+        buffer
+            .lock()
+            .unwrap()
+            .save_alert_rule(&rule)
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        Ok(rule)
+    })
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
 }
 
-/// Validate requested columns against the actual schema, returning SQL expressions
-fn validate_columns(requested: &[&str], schema: &[(String, String)]) -> Vec<String> {
-    let schema_names: std::collections::HashSet<&str> =
-        schema.iter().map(|(name, _)| name.as_str()).collect();
-    requested
-        .iter()
-        .filter(|col| schema_names.contains(**col))
-        .map(|col| {
-            // Cast certain columns for display
-            match *col {
-                "timestamp" => "CAST(timestamp AS VARCHAR)".to_string(),
-                "_pid" => "CAST(_pid AS VARCHAR)".to_string(),
-                other => other.to_string(),
-            }
-        })
-        .collect()
+fn config_rule_conflict(name: &str) -> (StatusCode, String) {
+    (
+        StatusCode::CONFLICT,
+        format!(
+            "Alert rule '{}' is defined in the config file and can only be changed there",
+            name
+        ),
+    )
 }
 
-/// Column alias for display (strips leading underscore, etc.)
-fn column_display_name(col: &str) -> String {
-    match col {
-        "CAST(timestamp AS VARCHAR)" | "timestamp" => "timestamp".to_string(),
-        "CAST(_pid AS VARCHAR)" | "_pid" => "pid".to_string(),
-        "_hostname" => "hostname".to_string(),
-        "_systemd_unit" => "unit".to_string(),
-        "_comm" => "comm".to_string(),
-        other => other.to_string(),
+/// Store a new alert rule; the scheduler picks it up within a second
+async fn api_create_alert_rule(
+    State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<Identity>,
+    Json(rule): Json<AlertRule>,
+) -> Result<(StatusCode, Json<AlertRule>), (StatusCode, String)> {
+    match find_alert_rule(&state, &rule.name).await {
+        Ok((_, AlertRuleOrigin::Config)) => return Err(config_rule_conflict(&rule.name)),
+        Ok((_, AlertRuleOrigin::Api)) => {
+            return Err((
+                StatusCode::CONFLICT,
+                format!("Alert rule '{}' already exists", rule.name),
+            ));
+        }
+        Err((status, _)) if status == StatusCode::NOT_FOUND => {}
+        Err(e) => return Err(e),
     }
+    let rule = save_alert_rule(&state, &identity, rule).await?;
+    Ok((StatusCode::CREATED, Json(rule)))
 }
 
-/// API columns endpoint returning available columns
-async fn api_columns(
+/// Replace a stored alert rule; the name in the path wins over one in the body
+async fn api_update_alert_rule(
     State(state): State<Arc<AppState>>,
-) -> Result<Json<Vec<ColumnInfo>>, (StatusCode, String)> {
-    let schema = get_schema_columns(&state.buffer);
+    Extension(identity): Extension<Identity>,
+    Path(name): Path<String>,
+    Json(mut rule): Json<AlertRule>,
+) -> Result<Json<AlertRule>, (StatusCode, String)> {
+    if let (_, AlertRuleOrigin::Config) = find_alert_rule(&state, &name).await? {
+        return Err(config_rule_conflict(&name));
+    }
+    rule.name = name;
+    Ok(Json(save_alert_rule(&state, &identity, rule).await?))
+}
 
-    let columns: Vec<ColumnInfo> = schema
-        .iter()
-        .filter(|(name, _)| !EXCLUDED_COLUMNS.contains(&name.as_str()))
-        .map(|(name, col_type)| ColumnInfo {
-            name: name.clone(),
-            column_type: col_type.clone(),
-            default: DEFAULT_COLUMNS.contains(&name.as_str()),
-        })
-        .collect();
+async fn api_delete_alert_rule(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    if let (_, AlertRuleOrigin::Config) = find_alert_rule(&state, &name).await? {
+        return Err(config_rule_conflict(&name));
+    }
+    let buffer = state.buffer.clone();
+    tokio::task::spawn_blocking(move || buffer.lock().unwrap().delete_alert_rule(&name))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(StatusCode::NO_CONTENT)
+}
 
-    Ok(Json(columns))
+/// Evaluate a rule now and send the result to every notifier as a test alert, which
+/// is not stored and does not change the rule's state
+async fn api_test_alert_rule(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Result<Json<AlertEvent>, (StatusCode, String)> {
+    let (rule, _) = find_alert_rule(&state, &name).await?;
+    let task_state = state.clone();
+    let alert = tokio::task::spawn_blocking(move || {
+        alerting::test_alert(
+            &rule,
+            &task_state.buffer,
+            &task_state.readers,
+            task_state.settings.public_url.as_deref(),
+        )
+    })
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .map_err(|e| (StatusCode::BAD_REQUEST, format!("{:#}", e)))?;
+    state.notifier.notify(std::slice::from_ref(&alert));
+    Ok(Json(alert))
 }
 
-/// API search endpoint returning JSON results
-async fn api_search(
+/// Most unit failures returned by /api/unit-failures
+const MAX_UNIT_FAILURES: usize = 5_000;
+
+/// systemd units that failed in a time range, newest first, each with the last lines
+/// its invocation logged
+async fn api_unit_failures(
     State(state): State<Arc<AppState>>,
-    Query(params): Query<SearchParams>,
-) -> Result<Json<SearchResponse>, (StatusCode, String)> {
-    let start_time = std::time::Instant::now();
+    Query(params): Query<UnitFailuresParams>,
+) -> Result<Json<Vec<serde_json::Value>>, ApiError> {
     let now = Utc::now();
+    let start = parse_time(&params.start, now).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let end = parse_time(&params.end, now).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
 
-    // Parse time range
+    let mut conditions = vec!["timestamp >= ?", "timestamp < ?"];
+    let mut sql_params = vec![
+        SqlParam::Text(start.to_rfc3339()),
+        SqlParam::Text(end.to_rfc3339()),
+    ];
+    if let Some(unit) = params.unit.filter(|unit| !unit.is_empty()) {
+        conditions.push("unit = ?");
+        sql_params.push(SqlParam::Text(unit));
+    }
+    if let Some(hostname) = params.hostname.filter(|hostname| !hostname.is_empty()) {
+        conditions.push("hostname = ?");
+        sql_params.push(SqlParam::Text(hostname));
+    }
+    sql_params.push(SqlParam::Int(params.limit.min(MAX_UNIT_FAILURES) as i32));
+    let sql = format!(
+        "SELECT id, CAST(timestamp AS VARCHAR), hostname, source, unit, invocation_id, result,
+                message, last_lines
+         FROM {} WHERE {} ORDER BY timestamp DESC, id DESC LIMIT ?",
+        UNIT_FAILURES_TABLE,
+        conditions.join(" AND ")
+    );
+    let names: Vec<String> = [
+        "id",
+        "timestamp",
+        "hostname",
+        "source",
+        "unit",
+        "invocation_id",
+        "result",
+        "message",
+        "last_lines",
+    ]
+    .iter()
+    .map(|name| name.to_string())
+    .collect();
+    let mut failures = run_query(
+        &state,
+        "unit_failures",
+        serde_json::json!({}),
+        move |reader| reader.query_json_rows(&sql, &sql_params, &names),
+    )
+    .await?;
+    for failure in &mut failures {
+        let last_lines = failure["last_lines"]
+            .as_str()
+            .and_then(|json| serde_json::from_str(json).ok())
+            .unwrap_or_else(|| serde_json::json!([]));
+        failure["last_lines"] = last_lines;
+    }
+    Ok(Json(failures))
+}
+
+/// Instances replicating to this one, most recently seen first
+async fn api_sources(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<SourceStatus>>, ApiError> {
+    let now = Utc::now();
+    let source = state.log_source(now - SOURCE_RATE_WINDOW, now);
+    let sources = run_query(&state, "sources", serde_json::json!({}), move |reader| {
+        reader.sources(&source.sql, now)
+    })
+    .await?;
+    Ok(Json(sources))
+}
+
+/// livedata's own operational events in a time range, newest first
+async fn api_internal(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<InternalParams>,
+) -> Result<Json<InternalResponse>, (StatusCode, String)> {
+    let now = Utc::now();
     let start = parse_time(&params.start, now).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
     let end = parse_time(&params.end, now).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let kind = match params.kind.filter(|kind| !kind.is_empty()) {
+        Some(name) => Some(InternalEventKind::from_name(&name).ok_or_else(|| {
+            let kinds: Vec<_> = InternalEventKind::ALL.iter().map(|k| k.as_str()).collect();
+            (
+                StatusCode::BAD_REQUEST,
+                format!("Unknown kind '{}'; use one of {}", name, kinds.join(", ")),
+            )
+        })?),
+        None => None,
+    };
+    let limit = params.limit.min(MAX_ALERTS);
 
-    // Validate and clamp limit
-    let limit = params.limit.min(100_000);
+    let buffer = state.buffer.clone();
+    let events = tokio::task::spawn_blocking(move || {
+        buffer
+            .lock()
+            .unwrap()
+            .internal_events(start, end, kind, limit)
+    })
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(InternalResponse {
+        ingest_lag_seconds: state.metrics.ingest_lag_seconds(now),
+        events,
+    }))
+}
 
-    // Determine which columns to select
-    let schema = get_schema_columns(&state.buffer);
+/// Silences not ended yet (or every one with `all=true`), latest start first
+async fn api_silences(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<SilencesParams>,
+) -> Result<Json<Vec<Silence>>, (StatusCode, String)> {
+    let after = (!params.all).then(Utc::now);
+    let buffer = state.buffer.clone();
+    let silences = tokio::task::spawn_blocking(move || buffer.lock().unwrap().silences(after))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(silences))
+}
 
-    // If the table doesn't exist yet, return empty results
-    if schema.is_empty() {
-        return Ok(Json(SearchResponse {
-            results: Vec::new(),
-            columns: DEFAULT_COLUMNS
-                .iter()
-                .map(|c| column_display_name(c))
-                .collect(),
-            total: 0,
-            limit,
-            offset: params.offset,
-            query_time_ms: start_time.elapsed().as_millis(),
-        }));
-    }
+/// Store a change to the silences and hand the ones not ended yet to the notifier
+async fn update_silences<T: Send + 'static>(
+    state: &AppState,
+    change: impl FnOnce(&mut DuckDBBuffer) -> anyhow::Result<T> + Send + 'static,
+) -> Result<T, (StatusCode, String)> {
+    let buffer = state.buffer.clone();
+    let notifier = state.notifier.clone();
+    tokio::task::spawn_blocking(move || {
+        let mut buffer = buffer.lock().unwrap();
+        let result = change(&mut buffer)?;
+        notifier.set_silences(buffer.silences(Some(Utc::now()))?);
+        Ok(result)
+    })
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .map_err(|e: anyhow::Error| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
 
-    let requested_cols: Vec<&str> = if let Some(ref cols) = params.columns
-        && !cols.is_empty()
-    {
-        cols.split(',').map(|s| s.trim()).collect()
-    } else {
-        DEFAULT_COLUMNS.to_vec()
+/// Silence notifications for the alerts matching the request, e.g. during planned
+/// maintenance. Silenced alerts are still recorded.
+async fn api_create_silence(
+    State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<Identity>,
+    Json(request): Json<CreateSilenceRequest>,
+) -> Result<(StatusCode, Json<Silence>), (StatusCode, String)> {
+    let matcher = |value: Option<String>| value.filter(|value| !value.is_empty());
+    let (rule, source, tag) = (
+        matcher(request.rule),
+        matcher(request.source),
+        matcher(request.tag),
+    );
+    if rule.is_none() && source.is_none() && tag.is_none() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "A silence needs a rule, source or tag to match".to_string(),
+        ));
+    }
+    let now = Utc::now();
+    let starts_at =
+        parse_time(&request.starts_at, now).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let ends_at = match parse_duration(&request.ends_at) {
+        Ok(duration) => starts_at.checked_add_signed(duration).ok_or_else(|| {
+            (
+                StatusCode::BAD_REQUEST,
+                format!("ends_at {} is out of range", request.ends_at),
+            )
+        })?,
+        Err(_) => parse_time(&request.ends_at, now).map_err(|e| (StatusCode::BAD_REQUEST, e))?,
     };
-    let select_exprs = validate_columns(&requested_cols, &schema);
-    if select_exprs.is_empty() {
-        return Err((StatusCode::BAD_REQUEST, "No valid columns specified".into()));
+    if ends_at <= starts_at.max(now) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "ends_at must be in the future and after starts_at".to_string(),
+        ));
     }
-
-    // Build display names for the response
-    let display_names: Vec<String> = select_exprs
-        .iter()
-        .map(|e| column_display_name(e))
-        .collect();
-
-    // Build SQL query against the journal_logs table
-    let mut sql = format!(
-        "SELECT {} FROM journal_logs WHERE timestamp >= '{}' AND timestamp < '{}'",
-        select_exprs.join(", "),
-        start.to_rfc3339(),
-        end.to_rfc3339()
+    let mut silence = Silence {
+        id: 0,
+        rule,
+        source,
+        tag,
+        starts_at,
+        ends_at,
+        comment: request.comment,
+        created_by: identity.name,
+    };
+    let to_store = silence.clone();
+    silence.id = update_silences(&state, move |buffer| buffer.add_silence(&to_store)).await?;
+    info!(
+        "Silence {} created by {} until {}",
+        silence.id,
+        silence.created_by.as_deref().unwrap_or("anonymous"),
+        silence.ends_at
     );
+    Ok((StatusCode::CREATED, Json(silence)))
+}
 
-    // Add text search filter
-    if let Some(ref q) = params.q
-        && !q.is_empty()
-    {
-        let escaped = escape_like(q);
-        sql.push_str(&format!(" AND message ILIKE '%{}%' ESCAPE '\\'", escaped));
+/// End a silence now
+async fn api_expire_silence(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let expired =
+        update_silences(&state, move |buffer| buffer.expire_silence(id, Utc::now())).await?;
+    if !expired {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("No active or pending silence {}", id),
+        ));
     }
+    Ok(StatusCode::NO_CONTENT)
+}
 
-    // Add hostname filter
-    if let Some(ref hostname) = params.hostname
-        && !hostname.is_empty()
-    {
-        let hosts: Vec<&str> = hostname.split(',').collect();
-        let host_list: Vec<String> = hosts
-            .iter()
-            .map(|h| format!("'{}'", h.replace('\'', "''")))
-            .collect();
-        sql.push_str(&format!(" AND _hostname IN ({})", host_list.join(",")));
-    }
+async fn get_current_process_rows(
+    state: &Arc<AppState>,
+) -> Result<(Vec<ProcessMetricsRow>, String), (StatusCode, String)> {
+    let latest = state
+        .with_reader(|reader| {
+            let latest_timestamp = reader.get_latest_process_timestamp().ok().flatten()?;
+            let rows = reader.get_process_metrics_for_timestamp(&latest_timestamp);
+            Some(rows.map(|rows| (rows, latest_timestamp)))
+        })
+        .await;
 
-    // Add unit filter
-    if let Some(ref unit) = params.unit
-        && !unit.is_empty()
-    {
-        let units: Vec<&str> = unit.split(',').collect();
-        let unit_list: Vec<String> = units
-            .iter()
-            .map(|u| format!("'{}'", u.replace('\'', "''")))
-            .collect();
-        sql.push_str(&format!(" AND _systemd_unit IN ({})", unit_list.join(",")));
-    }
+    if let Some(latest) = latest {
+        let (rows, latest_timestamp) =
+            latest.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-    // Add priority filter
-    if let Some(priority) = params.priority {
-        sql.push_str(&format!(" AND CAST(priority AS INTEGER) <= {}", priority));
+        let processes: Vec<ProcessMetricsRow> = rows.into_iter().map(to_process_row).collect();
+        return Ok((processes, latest_timestamp));
     }
 
-    // Validate and build ORDER BY clause
-    let sort_column = match params.sort.to_lowercase().as_str() {
-        "timestamp" => "timestamp",
-        "hostname" | "host" => "_hostname",
-        "unit" => "_systemd_unit",
-        "priority" | "pri" => "priority",
-        "comm" => "_comm",
-        _ => "timestamp",
-    };
+    let snapshot = state.process_monitor.get_snapshot();
+    let timestamp = chrono::Utc::now().to_rfc3339();
+    let processes: Vec<ProcessMetricsRow> = snapshot
+        .into_iter()
+        .map(|process| {
+            let user = process.user_name();
+            let uid = process.uid_number();
 
-    let sort_direction = match params.sort_dir.to_lowercase().as_str() {
-        "asc" => "ASC",
-        "desc" => "DESC",
-        _ => "DESC",
-    };
+            let cmdline = process.cmdline();
 
-    sql.push_str(&format!(
-        " ORDER BY {} {} LIMIT {} OFFSET {}",
-        sort_column, sort_direction, limit, params.offset
-    ));
+            ProcessMetricsRow {
+                timestamp: timestamp.clone(),
+                pid: process.pid,
+                name: process.name,
+                cpu_usage: process.cpu_percent,
+                mem_usage: process.memory_bytes as f64,
+                user,
+                uid,
+                runtime: process.runtime_secs,
+                cmdline,
+                virtual_memory: process.virtual_memory_bytes as f64,
+                status: Some(process.status),
+                parent_pid: process.parent_pid,
+                read_bytes_per_sec: process.read_bytes_per_sec,
+                write_bytes_per_sec: process.write_bytes_per_sec,
+                num_threads: process.num_threads,
+                open_fd_count: process.open_fd_count,
+                unit: process.unit,
+                container_id: process.container_id,
+            }
+        })
+        .collect();
+    Ok((processes, timestamp))
+}
 
-    // Execute query with dynamic column mapping
-    let results: Vec<serde_json::Value> = state
-        .buffer
-        .lock()
-        .unwrap()
-        .query_json_rows(&sql, &display_names)
-        .unwrap_or_default();
+/// Internal counters in the Prometheus text format, for alerting when collection stalls
+async fn metrics(State(state): State<Arc<AppState>>) -> Response {
+    let size = |name: &str| {
+        std::fs::metadata(std::path::Path::new(&state.data_dir).join(name))
+            .map(|m| m.len())
+            .unwrap_or(0)
+    };
+    let spill_bytes = std::fs::read_dir(std::path::Path::new(&state.data_dir).join(SPILL_DIR))
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter_map(|entry| entry.metadata().ok())
+                .map(|m| m.len())
+                .sum()
+        })
+        .unwrap_or(0);
+    let sizes = StorageSizes {
+        database_bytes: size("livedata.duckdb"),
+        wal_bytes: size("livedata.duckdb.wal"),
+        spill_bytes,
+    };
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.metrics.render(Utc::now(), sizes),
+    )
+        .into_response()
+}
 
-    let total = results.len();
-    let query_time_ms = start_time.elapsed().as_millis();
+/// API endpoint returning storage health and statistics
+async fn api_storage_health(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<StorageHealthResponse>, (StatusCode, String)> {
+    // Get database file size
+    let db_path = std::path::Path::new(&state.data_dir).join("livedata.duckdb");
+    let database_size_bytes = std::fs::metadata(&db_path).map(|m| m.len()).unwrap_or(0);
+    let wal_path = std::path::Path::new(&state.data_dir).join("livedata.duckdb.wal");
+    let wal_size_bytes = std::fs::metadata(&wal_path).map(|m| m.len()).unwrap_or(0);
 
-    Ok(Json(SearchResponse {
-        results,
-        columns: display_names,
-        total,
-        limit,
-        offset: params.offset,
-        query_time_ms,
+    let stats = state
+        .with_reader(|reader| reader.get_storage_stats())
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let retention_policy = RetentionPolicy {
+        log_retention_days: state.settings.log_retention_days,
+        log_max_size_gb: state.settings.log_max_size_gb,
+        process_retention_days: state.settings.process_retention_days,
+        process_max_size_gb: state.settings.process_max_size_gb,
+    };
+
+    Ok(Json(StorageHealthResponse {
+        database_size_bytes,
+        journal_log_count: stats.journal_log_count,
+        process_metric_count: stats.process_metric_count,
+        oldest_log_timestamp: stats.oldest_log_timestamp,
+        newest_log_timestamp: stats.newest_log_timestamp,
+        wal_size_bytes,
+        tables: stats.tables,
+        rows_per_day: stats.rows_per_day,
+        top_units: stats.top_units,
+        indexes: stats.indexes,
+        index_memory_bytes: stats.index_memory_bytes,
+        retention_policy,
     }))
 }
 
-/// API timechart endpoint returning 1-minute bins grouped by log level
-async fn api_timechart(
+async fn htmx_logs_chunk(
     State(state): State<Arc<AppState>>,
-    Query(params): Query<TimechartParams>,
-) -> Result<Json<Vec<TimechartBin>>, (StatusCode, String)> {
-    let now = Utc::now();
-    let start = parse_time(&params.start, now).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
-    let end = parse_time(&params.end, now).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
-
-    let schema = get_schema_columns(&state.buffer);
-    if schema.is_empty() {
-        return Ok(Json(Vec::new()));
+    params: SearchParams,
+) -> impl IntoResponse {
+    match query_log_results(&state, &params).await {
+        Ok((results, display_names, total_count)) => Html(render_log_chunk_fragment(
+            &params,
+            &results,
+            &display_names,
+            total_count,
+        ))
+        .into_response(),
+        Err((status, msg)) => (status, msg).into_response(),
     }
+}
 
-    let mut where_sql = format!(
-        "timestamp >= '{}' AND timestamp < '{}'",
-        start.to_rfc3339(),
-        end.to_rfc3339()
-    );
+async fn htmx_processes_chunk(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ProcessTableParams>,
+) -> impl IntoResponse {
+    let (mut processes, timestamp) = match get_current_process_rows(&state).await {
+        Ok(data) => data,
+        Err((status, msg)) => return (status, msg).into_response(),
+    };
 
-    if let Some(ref q) = params.q
-        && !q.is_empty()
-    {
-        let escaped = escape_like(q);
-        where_sql.push_str(&format!(" AND message ILIKE '%{}%' ESCAPE '\\'", escaped));
-    }
-    if let Some(ref hostname) = params.hostname
-        && !hostname.is_empty()
-    {
-        let hosts: Vec<&str> = hostname.split(',').collect();
-        let host_list: Vec<String> = hosts
-            .iter()
-            .map(|h| format!("'{}'", h.replace('\'', "''")))
-            .collect();
-        where_sql.push_str(&format!(" AND _hostname IN ({})", host_list.join(",")));
-    }
-    if let Some(ref unit) = params.unit
-        && !unit.is_empty()
+    processes.sort_by(|a, b| b.cpu_usage.total_cmp(&a.cpu_usage));
+
+    if let Some(q) = params.q.as_deref()
+        && !q.trim().is_empty()
     {
-        let units: Vec<&str> = unit.split(',').collect();
-        let unit_list: Vec<String> = units
-            .iter()
-            .map(|u| format!("'{}'", u.replace('\'', "''")))
-            .collect();
-        where_sql.push_str(&format!(" AND _systemd_unit IN ({})", unit_list.join(",")));
-    }
-    if let Some(priority) = params.priority {
-        where_sql.push_str(&format!(" AND CAST(priority AS INTEGER) <= {}", priority));
+        let needle = q.to_lowercase();
+        processes.retain(|p| {
+            let haystack = format!(
+                "{} {} {} {:.1} {}",
+                p.pid,
+                p.name,
+                p.user.as_deref().unwrap_or(""),
+                p.cpu_usage,
+                p.timestamp
+            )
+            .to_lowercase();
+            fuzzy_match(&needle, &haystack)
+        });
     }
 
-    let sql = format!(
-        "SELECT CAST(to_timestamp(floor(epoch(timestamp) / 60) * 60) AS VARCHAR) AS time_bin,
-                COALESCE(TRY_CAST(priority AS INTEGER), 6) AS priority,
-                COUNT(*) AS count
-         FROM journal_logs
-         WHERE {}
-         GROUP BY 1, 2
-         ORDER BY 1 ASC, 2 ASC",
-        where_sql
-    );
+    let total_count = processes.len();
+    let limit = params.limit.clamp(10, 1000);
+    let start = params.offset.min(total_count);
+    let end = (start + limit).min(total_count);
+    let page = &processes[start..end];
 
-    let display_names = vec![
-        "time_bin".to_string(),
-        "priority".to_string(),
-        "count".to_string(),
-    ];
-    let rows = state
-        .buffer
-        .lock()
-        .unwrap()
-        .query_json_rows(&sql, &display_names)
-        .unwrap_or_default();
+    Html(render_process_chunk_fragment(
+        &params,
+        page,
+        total_count,
+        &timestamp,
+    ))
+    .into_response()
+}
 
-    let bins = rows
-        .into_iter()
-        .filter_map(|row| {
-            let obj = row.as_object()?;
-            let time_bin = obj.get("time_bin")?.as_str()?.to_string();
-            let priority = obj.get("priority").and_then(|v| {
-                v.as_i64()
-                    .or_else(|| v.as_str().and_then(|s| s.parse::<i64>().ok()))
-            })?;
-            let count = obj.get("count").and_then(|v| {
-                v.as_i64()
-                    .or_else(|| v.as_str().and_then(|s| s.parse::<i64>().ok()))
-            })?;
+/// Error response of a handler: a status with a plain text message, or the JSON body
+/// of a query interrupted by the timeout
+#[derive(Debug)]
+enum ApiError {
+    Text(StatusCode, String),
+    Timeout(QueryTimeoutResponse),
+}
 
-            Some(TimechartBin {
-                time_bin,
-                level: priority_level_name(priority as i32).to_string(),
-                count,
-            })
-        })
-        .collect::<Vec<_>>();
+impl From<(StatusCode, String)> for ApiError {
+    fn from((status, message): (StatusCode, String)) -> Self {
+        Self::Text(status, message)
+    }
+}
 
-    Ok(Json(bins))
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Text(_, message) => f.write_str(message),
+            Self::Timeout(body) => f.write_str(&body.error),
+        }
+    }
 }
 
-/// API filters endpoint returning available filter values
-async fn api_filters(
-    State(state): State<Arc<AppState>>,
-) -> Result<Json<FilterValues>, (StatusCode, String)> {
-    // Get distinct hostnames
-    let hostnames = state.buffer.lock().unwrap().query_distinct_strings(
-        "SELECT DISTINCT _hostname FROM journal_logs WHERE _hostname IS NOT NULL ORDER BY _hostname",
-    );
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::Text(status, message) => (status, message).into_response(),
+            Self::Timeout(body) => (StatusCode::GATEWAY_TIMEOUT, Json(body)).into_response(),
+        }
+    }
+}
 
-    // Get distinct units
-    let units = state.buffer.lock().unwrap().query_distinct_strings(
-        "SELECT DISTINCT _systemd_unit FROM journal_logs WHERE _systemd_unit IS NOT NULL ORDER BY _systemd_unit",
-    );
+/// Body of the 504 returned when a query is interrupted by the timeout
+#[derive(Debug, Serialize)]
+struct QueryTimeoutResponse {
+    error: String,
+    stage: &'static str,
+    timeout_ms: u64,
+    /// Whatever was already known when the query was interrupted
+    partial: serde_json::Value,
+}
 
-    // Static priority options
-    let priorities: Vec<PriorityOption> = (0..=7)
-        .map(|p| PriorityOption {
-            value: p,
-            label: format!("{} - {}", p, priority_label(p)),
+/// Run `query` on a pooled read connection in the blocking task pool. If it outlives
+/// `settings.query_timeout_ms` the connection is interrupted and a 504 carrying
+/// `partial` is returned instead of waiting for the query to finish.
+async fn run_query<T, F>(
+    state: &Arc<AppState>,
+    stage: &'static str,
+    partial: serde_json::Value,
+    query: F,
+) -> Result<T, ApiError>
+where
+    T: Send + 'static,
+    F: FnOnce(&PooledReader) -> anyhow::Result<T> + Send + 'static,
+{
+    let cancel = Arc::new(QueryCancel::default());
+    let task = {
+        let state = state.clone();
+        let cancel = cancel.clone();
+        tokio::task::spawn_blocking(move || {
+            let reader = state.readers.get();
+            if !cancel.register(reader.interrupt_handle()) {
+                anyhow::bail!("Query cancelled before it started");
+            }
+            let result = query(&reader);
+            cancel.clear();
+            result
         })
-        .collect();
+    };
 
-    Ok(Json(FilterValues {
-        hostnames,
-        units,
-        priorities,
-    }))
+    let timeout_ms = state.settings.query_timeout_ms;
+    let started = std::time::Instant::now();
+    let result = tokio::time::timeout(std::time::Duration::from_millis(timeout_ms), task).await;
+    state.metrics.record_query(stage, started.elapsed());
+    match result {
+        Ok(Ok(result)) => {
+            result.map_err(|e| ApiError::Text(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+        }
+        Ok(Err(e)) => Err(ApiError::Text(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            e.to_string(),
+        )),
+        Err(_) => {
+            cancel.cancel();
+            log::warn!("{} query interrupted after {} ms", stage, timeout_ms);
+            let body = QueryTimeoutResponse {
+                error: format!(
+                    "Query exceeded {} ms; narrow the time range or filters",
+                    timeout_ms
+                ),
+                stage,
+                timeout_ms,
+                partial,
+            };
+            Err(ApiError::Timeout(body))
+        }
+    }
 }
 
-/// Main search UI (HTML)
-async fn search_ui(
-    State(state): State<Arc<AppState>>,
-    Query(params): Query<SearchParams>,
-) -> impl IntoResponse {
-    let (results, display_names, total_count) =
-        query_log_results(&state, &params).unwrap_or_default();
+type LogQueryResult = Result<(Vec<serde_json::Value>, Vec<String>, usize), (StatusCode, String)>;
 
-    // Get filter options
-    let hostnames = state.buffer.lock().unwrap().query_distinct_strings(
-        "SELECT DISTINCT _hostname FROM journal_logs WHERE _hostname IS NOT NULL ORDER BY _hostname",
-    );
+async fn query_log_results(state: &Arc<AppState>, params: &SearchParams) -> LogQueryResult {
+    let now = Utc::now();
+    let start = parse_time(&params.start, now).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let end = parse_time(&params.end, now).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let limit = params.limit.min(100_000);
+
+    let schema = state.schema_columns().await;
+    if schema.is_empty() {
+        return Ok((
+            Vec::new(),
+            DEFAULT_COLUMNS
+                .iter()
+                .map(|c| column_display_name(c))
+                .collect(),
+            0,
+        ));
+    }
+
+    let requested_cols: Vec<&str> = if let Some(ref cols) = params.columns
+        && !cols.is_empty()
+    {
+        cols.split(',').map(|s| s.trim()).collect()
+    } else {
+        DEFAULT_COLUMNS.to_vec()
+    };
+    let select_exprs = validate_columns(&requested_cols, &schema);
+    let select_list = if select_exprs.is_empty() {
+        DEFAULT_COLUMNS
+            .iter()
+            .filter_map(|c| {
+                let exprs = validate_columns(&[c], &schema);
+                exprs.into_iter().next()
+            })
+            .collect::<Vec<_>>()
+    } else {
+        select_exprs
+    };
+    let display_names: Vec<String> = select_list.iter().map(|e| column_display_name(e)).collect();
 
-    let units = state.buffer.lock().unwrap().query_distinct_strings(
-        "SELECT DISTINCT _systemd_unit FROM journal_logs WHERE _systemd_unit IS NOT NULL ORDER BY _systemd_unit",
+    let query = parse_search_query(params.q.as_deref(), &schema).await?;
+    let fields = parse_field_filters(&params.filters, &schema)?;
+    let extra = parse_extra_filters(params.extra.as_deref(), &state.promoted_fields())?;
+    let where_clause = build_where_clause(
+        start,
+        end,
+        &query,
+        params.hostname.as_deref(),
+        params.unit.as_deref(),
+        params.source.as_deref(),
+        params.priority,
+        &extra,
+        &fields,
     );
 
-    let html = build_search_html(
-        &params,
-        &results,
-        &display_names,
-        total_count,
-        &hostnames,
-        &units,
+    let source = state.log_source(start, end);
+    let count_sql = format!("SELECT COUNT(*) FROM {} WHERE {}", source, where_clause.sql);
+    let sql = format!(
+        "SELECT {} FROM {} WHERE {} ORDER BY {} {} LIMIT ? OFFSET ?",
+        select_list.join(", "),
+        source,
+        where_clause.sql,
+        sort_column(&params.sort),
+        sort_direction(&params.sort_dir),
     );
+    let mut query_params = where_clause.params.clone();
+    query_params.push(SqlParam::BigInt(limit as i64));
+    query_params.push(SqlParam::BigInt(params.offset as i64));
+
+    let names = display_names.clone();
+    let (results, total_count) = state
+        .with_reader(move |reader| {
+            let total_count = reader.query_usize(&count_sql, &where_clause.params);
+            let results = reader
+                .query_json_rows(&sql, &query_params, &names)
+                .unwrap_or_default();
+            (results, total_count)
+        })
+        .await;
 
-    Html(html)
+    Ok((results, display_names, total_count))
 }
 
-#[allow(clippy::too_many_arguments)]
-fn build_search_html(
-    params: &SearchParams,
-    results: &[serde_json::Value],
-    display_names: &[String],
-    total_count: usize,
-    hostnames: &[String],
-    units: &[String],
-) -> String {
-    let query_value = params.q.as_deref().unwrap_or("");
-    let hostname_value = params.hostname.as_deref().unwrap_or("");
-    let unit_value = params.unit.as_deref().unwrap_or("");
-    let priority_value = params.priority;
-
-    // Build hostname options
-    let hostname_options: String = hostnames
+/// Validate requested columns against the actual schema, returning SQL expressions
+fn validate_columns(requested: &[&str], schema: &[(String, String)]) -> Vec<String> {
+    requested
         .iter()
-        .map(|h| {
-            let selected = if hostname_value == h { " selected" } else { "" };
-            format!(
-                "<option value=\"{}\"{}>{}</option>",
-                html_escape(h),
-                selected,
-                html_escape(h)
-            )
+        .filter(|col| search_query::has_column(schema, col))
+        .map(|col| {
+            // Cast certain columns for display
+            match *col {
+                "timestamp" => "CAST(timestamp AS VARCHAR)".to_string(),
+                "_pid" => "CAST(_pid AS VARCHAR)".to_string(),
+                other => other.to_string(),
+            }
         })
-        .collect::<Vec<_>>()
-        .join("\n");
+        .collect()
+}
 
-    // Build unit options
-    let unit_options: String = units
+/// Column alias for display (strips leading underscore, etc.)
+fn column_display_name(col: &str) -> String {
+    match col {
+        "CAST(timestamp AS VARCHAR)" | "timestamp" => "timestamp".to_string(),
+        "CAST(_pid AS VARCHAR)" | "_pid" => "pid".to_string(),
+        "_hostname" => "hostname".to_string(),
+        "_systemd_unit" => "unit".to_string(),
+        "_comm" => "comm".to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// API columns endpoint returning available columns
+async fn api_columns(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Response, (StatusCode, String)> {
+    let schema = state.schema_columns().await;
+
+    let columns: Vec<ColumnInfo> = schema
         .iter()
-        .map(|u| {
-            let selected = if unit_value == u { " selected" } else { "" };
-            format!(
-                "<option value=\"{}\"{}>{}</option>",
-                html_escape(u),
-                selected,
-                html_escape(u)
+        .filter(|(name, _)| !EXCLUDED_COLUMNS.contains(&name.as_str()))
+        .filter(|(name, _)| !state.settings.dropped_fields.contains(name))
+        .map(|(name, col_type)| ColumnInfo {
+            name: name.clone(),
+            column_type: col_type.clone(),
+            default: DEFAULT_COLUMNS.contains(&name.as_str()),
+        })
+        .collect();
+
+    Ok(cached_json(&headers, &columns))
+}
+
+/// How often each extra_fields key appeared since `start` (default the last day)
+async fn api_extra_fields(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ExtraFieldsParams>,
+) -> Result<Json<Vec<ExtraFieldUsage>>, (StatusCode, String)> {
+    let since = parse_time(&params.start, Utc::now()).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    state
+        .with_reader(move |reader| reader.get_extra_field_usage(since))
+        .await
+        .map(Json)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+/// Hourly count, error count and first/last timestamp per unit and host, from the
+/// background-maintained summaries (default the last day)
+async fn api_summary(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<SummaryParams>,
+) -> Result<Json<Vec<LogSummary>>, (StatusCode, String)> {
+    let now = Utc::now();
+    let start = parse_time(&params.start, now).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let end = parse_time(&params.end, now).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    state
+        .with_reader(move |reader| {
+            reader.get_log_summaries(
+                start,
+                end,
+                params.hostname.as_deref(),
+                params.unit.as_deref(),
             )
         })
+        .await
+        .map(Json)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+/// Journal column behind a /api/stats `group_by` or /api/search `facets` name,
+/// accepting the display names used by /api/search as well as column names
+fn group_column(name: &str, schema: &[(String, String)]) -> Option<String> {
+    let column = search_query::field_column(name);
+    (column != "timestamp" && search_query::has_column(schema, column)).then(|| column.to_string())
+}
+
+/// Entry count and first/last timestamp per group of `group_by` values over the
+/// filtered time range (default the last day), largest groups first. Answers
+/// questions like errors by unit over the last day without raw SQL.
+async fn api_stats(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<StatsParams>,
+) -> Result<Json<StatsResponse>, ApiError> {
+    let start_time = std::time::Instant::now();
+    let now = Utc::now();
+    let start = parse_time(&params.start, now).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let end = parse_time(&params.end, now).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    let requested: Vec<&str> = params
+        .group_by
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .collect();
+    if requested.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "group_by is required".into()).into());
+    }
+    let schema = state.schema_columns().await;
+    if schema.is_empty() {
+        return Ok(Json(StatsResponse {
+            group_by: requested.iter().map(|name| name.to_string()).collect(),
+            groups: Vec::new(),
+            query_time_ms: start_time.elapsed().as_millis(),
+        }));
+    }
+    let columns = requested
+        .iter()
+        .map(|name| {
+            group_column(name, &schema)
+                .ok_or_else(|| (StatusCode::BAD_REQUEST, format!("Cannot group by {}", name)))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let query = parse_search_query(params.q.as_deref(), &schema).await?;
+    let extra = parse_extra_filters(params.extra.as_deref(), &state.promoted_fields())?;
+    let where_clause = build_where_clause(
+        start,
+        end,
+        &query,
+        params.hostname.as_deref(),
+        params.unit.as_deref(),
+        params.source.as_deref(),
+        params.priority,
+        &extra,
+        &[],
+    );
+    let source = state.log_source(start, end);
+    let keys = columns
+        .iter()
+        .map(|column| quote_ident(column))
         .collect::<Vec<_>>()
-        .join("\n");
+        .join(", ");
+    let sql = format!(
+        "SELECT {keys}, COUNT(*), CAST(MIN(timestamp) AS VARCHAR), CAST(MAX(timestamp) AS VARCHAR) \
+         FROM {} WHERE {} GROUP BY {keys} ORDER BY COUNT(*) DESC, {keys} LIMIT ?",
+        source.sql, where_clause.sql,
+    );
+    let mut query_params = where_clause.params;
+    query_params.push(SqlParam::BigInt(params.limit.min(10_000) as i64));
+
+    let group_by: Vec<String> = columns.iter().map(|c| column_display_name(c)).collect();
+    let mut names = group_by.clone();
+    names.extend(["count", "first_timestamp", "last_timestamp"].map(String::from));
+    let partial = serde_json::json!({ "group_by": group_by });
+    let groups = run_query(&state, "stats", partial, move |reader| {
+        reader.query_json_rows(&sql, &query_params, &names)
+    })
+    .await?;
 
-    // Build priority options
-    let priority_options: String = (0..=7)
-        .map(|p| {
-            let selected = if priority_value == Some(p) {
-                " selected"
+    Ok(Json(StatsResponse {
+        group_by,
+        groups,
+        query_time_ms: start_time.elapsed().as_millis(),
+    }))
+}
+
+/// Recent SQL statements run for web requests with their duration and row count,
+/// newest (or slowest) first
+async fn api_debug_queries(
+    Query(params): Query<DebugQueriesParams>,
+) -> Result<Json<DebugQueriesResponse>, (StatusCode, String)> {
+    let mut queries: Vec<QueryRecord> = sql_trace::logged_queries(params.after)
+        .into_iter()
+        .filter(|record| record.duration_ms >= params.min_ms)
+        .collect();
+    match params.sort.as_deref() {
+        None | Some("") | Some("recent") => queries.reverse(),
+        Some("slowest") => queries.sort_by(|a, b| b.duration_ms.total_cmp(&a.duration_ms)),
+        Some(other) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("Unknown sort {}; use recent or slowest", other),
+            ));
+        }
+    }
+    queries.truncate(params.limit.min(1000));
+    Ok(Json(DebugQueriesResponse { queries }))
+}
+
+/// Connection test of the Grafana datasource
+async fn grafana_check() -> &'static str {
+    "ok"
+}
+
+/// Targets for Grafana's metric picker containing the typed text: the log targets,
+/// and CPU and memory of every process seen in the last hour
+async fn grafana_search(
+    State(state): State<Arc<AppState>>,
+    body: Option<Json<grafana::SearchRequest>>,
+) -> Result<Json<Vec<String>>, ApiError> {
+    let typed = body.map(|Json(body)| body.target).unwrap_or_default();
+    let since = SqlParam::Text((Utc::now() - Duration::hours(1)).to_rfc3339());
+    let names = run_query(
+        &state,
+        "grafana_search",
+        serde_json::json!([]),
+        move |reader| {
+            Ok(reader.query_distinct_strings(
+                "SELECT DISTINCT name FROM process_metrics
+             WHERE timestamp >= ? AND name IS NOT NULL ORDER BY name LIMIT 500",
+                &[since],
+            ))
+        },
+    )
+    .await?;
+
+    let targets = grafana::LOG_TARGETS
+        .iter()
+        .map(|target| target.to_string())
+        .chain(names.iter().flat_map(|name| {
+            [
+                format!("process.cpu:{}", name),
+                format!("process.mem:{}", name),
+            ]
+        }))
+        .filter(|target| target.contains(typed.trim()))
+        .collect();
+    Ok(Json(targets))
+}
+
+/// Series (or time/value tables) for each visible target of a Grafana panel
+async fn grafana_query(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<grafana::QueryRequest>,
+) -> Result<Json<Vec<QueryResult>>, ApiError> {
+    let bin_secs = grafana::bin_seconds(&request);
+    let (start, end) = (request.range.from, request.range.to);
+    let mut results = Vec::new();
+    for target in request.targets.iter().filter(|target| !target.hide) {
+        let metric = Metric::parse(&target.target).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+        let datapoints = grafana_series(&state, metric, start, end, bin_secs).await?;
+        results.push(match target.kind.as_deref() {
+            Some("table") => QueryResult::table(datapoints),
+            _ => QueryResult::Series {
+                target: target.target.clone(),
+                datapoints,
+            },
+        });
+    }
+    Ok(Json(results))
+}
+
+/// `[value, unix milliseconds]` points of `metric` in bins of `bin_secs`. Log counts
+/// include empty bins as zero.
+async fn grafana_series(
+    state: &Arc<AppState>,
+    metric: Metric,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    bin_secs: i64,
+) -> Result<Vec<(f64, i64)>, ApiError> {
+    let bin_expr = format!(
+        "epoch_ms(to_timestamp(floor(epoch(timestamp) / {bin}) * {bin}))",
+        bin = bin_secs
+    );
+    let column = match &metric {
+        Metric::ProcessCpu(_) => "avg_cpu",
+        _ => "avg_mem",
+    };
+    let (sql, params, fill_zero) = match metric {
+        Metric::LogCount { query, errors_only } => {
+            let schema = state.schema_columns().await;
+            if schema.is_empty() {
+                return Ok(Vec::new());
+            }
+            let query = parse_search_query(Some(query.as_str()), &schema).await?;
+            let where_clause = build_where_clause(
+                start,
+                end,
+                &query,
+                None,
+                None,
+                None,
+                errors_only.then_some(3),
+                &[],
+                &[],
+            );
+            let source = state.log_source(start, end);
+            let sql = format!(
+                "SELECT {} AS t, COUNT(*) AS v FROM {} WHERE {} GROUP BY 1 ORDER BY 1",
+                bin_expr, source.sql, where_clause.sql
+            );
+            (sql, where_clause.params, true)
+        }
+        Metric::ProcessCpu(name) | Metric::ProcessMemory(name) => {
+            // Per-minute rollups only line up with whole-minute bins
+            let series = if bin_secs % 60 == 0 {
+                crate::queries::process_name_series_sql()
             } else {
-                ""
+                crate::queries::process_sums_sql("$1", "name = $3")
             };
-            format!(
-                "<option value=\"{}\"{}>{} - {}</option>",
-                p,
-                selected,
-                p,
-                priority_label(p)
+            let sql = format!(
+                "SELECT {bin} AS t, SUM({column} * samples) / SUM(samples) AS v
+                 FROM ({series})
+                 GROUP BY 1 ORDER BY 1",
+                bin = bin_expr,
+                column = column,
+                series = series,
+            );
+            let params = vec![
+                SqlParam::Text(start.to_rfc3339()),
+                SqlParam::Text(end.to_rfc3339()),
+                SqlParam::Text(name),
+            ];
+            (sql, params, false)
+        }
+    };
+
+    let names = vec!["t".to_string(), "v".to_string()];
+    let rows = run_query(state, "grafana", serde_json::json!([]), move |reader| {
+        reader.query_json_rows(&sql, &params, &names)
+    })
+    .await?;
+    let points: Vec<(f64, i64)> = rows
+        .iter()
+        .filter_map(|row| Some((row["v"].as_f64()?, row["t"].as_i64()?)))
+        .collect();
+    if !fill_zero {
+        return Ok(points);
+    }
+
+    let bin_ms = bin_secs * 1000;
+    let first = start.timestamp_millis().div_euclid(bin_ms) * bin_ms;
+    let counts: BTreeMap<i64, f64> = points.into_iter().map(|(v, t)| (t, v)).collect();
+    Ok((first..end.timestamp_millis())
+        .step_by(bin_ms as usize)
+        .map(|t| (counts.get(&t).copied().unwrap_or(0.0), t))
+        .collect())
+}
+
+/// Entries matching a LogQL stream selector and line filters, grouped into streams by
+/// host, unit and source
+async fn loki_query_range(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<logql::QueryRangeParams>,
+) -> Result<Json<LokiResponse<StreamsData>>, ApiError> {
+    let (start, end) =
+        logql::time_range(params.start.as_deref(), params.end.as_deref(), Utc::now())
+            .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let query = LogQuery::parse(&params.query).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let order = match params.direction.as_deref() {
+        None | Some("backward") => "DESC",
+        Some("forward") => "ASC",
+        Some(other) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("Invalid direction '{}'; use forward or backward", other),
             )
+                .into());
+        }
+    };
+    let schema = state.schema_columns().await;
+    if schema.is_empty() {
+        return Ok(Json(LokiResponse::success(StreamsData::from_rows([]))));
+    }
+    let (condition, condition_params) = query
+        .to_sql(&schema)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    let mut where_clause = build_where_clause(
+        start,
+        end,
+        &SearchQuery::default(),
+        None,
+        None,
+        None,
+        None,
+        &[],
+        &[],
+    );
+    where_clause.sql.push_str(&format!(" AND ({})", condition));
+    where_clause.params.extend(condition_params);
+    where_clause.params.push(SqlParam::BigInt(
+        params.limit.clamp(1, logql::MAX_LIMIT) as i64
+    ));
+    let source = state.log_source(start, end);
+    let labels = logql::STREAM_LABELS
+        .iter()
+        .map(|(label, _)| {
+            let expr = logql::label_expr(label, &schema)
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+            Ok(format!("COALESCE({}, '') AS {}", expr, quote_ident(label)))
         })
-        .collect::<Vec<_>>()
-        .join("\n");
+        .collect::<Result<Vec<_>, (StatusCode, String)>>()?;
+    let sql = format!(
+        "SELECT epoch_us(timestamp) AS ts, COALESCE(message, '') AS line, {}
+         FROM {} WHERE {} ORDER BY timestamp {} LIMIT ?",
+        labels.join(", "),
+        source.sql,
+        where_clause.sql,
+        order
+    );
+    let names: Vec<String> = ["ts", "line"]
+        .into_iter()
+        .chain(logql::STREAM_LABELS.iter().map(|(label, _)| *label))
+        .map(String::from)
+        .collect();
+    let partial = serde_json::json!({ "cold_storage": source.cold_storage });
+    let rows = run_query(&state, "loki", partial, move |reader| {
+        reader.query_json_rows(&sql, &where_clause.params, &names)
+    })
+    .await?;
 
-    let chunk_fragment = render_log_chunk_fragment(params, results, display_names, total_count);
+    let data = StreamsData::from_rows(rows.into_iter().map(|row| {
+        // Loki streams carry no empty labels
+        let stream = logql::STREAM_LABELS
+            .iter()
+            .filter_map(|(label, _)| {
+                let value = row[*label].as_str().filter(|v| !v.is_empty())?;
+                Some((label.to_string(), value.to_string()))
+            })
+            .collect();
+        let nanos = row["ts"].as_i64().unwrap_or(0) * 1000;
+        let line = row["line"].as_str().unwrap_or_default().to_string();
+        (stream, nanos, line)
+    }));
+    Ok(Json(LokiResponse::success(data)))
+}
 
-    format!(
-        r##"<!DOCTYPE html>
-<html lang="en">
-<head>
-    <meta charset="UTF-8">
-    <meta name="viewport" content="width=device-width, initial-scale=1.0">
-    <title>Livedata - Log Search</title>
-    <script src="https://unpkg.com/htmx.org@1.9.12"></script>
-    <script src="https://cdn.jsdelivr.net/npm/d3@7"></script>
-    <style>
-        :root {{
-            --bg: #272822;
-            --surface: #2d2e27;
-            --surface-alt: #3e3d32;
-            --text: #f8f8f2;
-            --muted: #a59f85;
-            --border: #49483e;
-            --accent: #a6e22e;
-            --accent-2: #66d9ef;
-            --warn: #fd971f;
-            --danger: #f92672;
-        }}
-        body.theme-light {{
-            --bg: #f8f8f2;
-            --surface: #efefe7;
-            --surface-alt: #ffffff;
-            --text: #272822;
-            --muted: #6f6b57;
-            --border: #b7b39e;
-            --accent: #3b7d15;
-            --accent-2: #0f8395;
-            --warn: #c15d00;
-            --danger: #c2175b;
-        }}
-        * {{
-            box-sizing: border-box;
-            margin: 0;
-            padding: 0;
-        }}
-        body {{
-            font-family: -apple-system, BlinkMacSystemFont, "Segoe UI", Roboto, "Helvetica Neue", Arial, sans-serif;
-            background-color: var(--bg);
-            color: var(--text);
-            line-height: 1.5;
-        }}
-        .global-header {{
-            background-color: var(--surface);
-            border-bottom: 2px solid var(--accent);
-            margin-bottom: 0;
-        }}
-        .global-header nav {{
-            display: flex;
-            gap: 5px;
-            padding: 12px 20px;
-            max-width: 100%;
-        }}
-        .global-header nav a {{
-            color: var(--accent-2);
-            text-decoration: none;
-            font-size: 15px;
-            font-weight: 500;
-            padding: 8px 15px;
-            border-radius: 4px;
-            transition: background-color 0.2s;
-        }}
-        .global-header nav a:hover {{
-            background-color: var(--surface-alt);
-        }}
-        .global-header nav a.active {{
-            background-color: var(--accent);
-            color: var(--bg);
-        }}
-        .container {{
-            max-width: 100%;
-            padding: 20px;
-        }}
-        #storage-health {{
-            background-color: var(--surface);
-            padding: 12px 20px;
-            border-radius: 8px;
-            margin-bottom: 20px;
-            font-size: 0.9rem;
-            border: 1px solid var(--border);
-        }}
-        #storage-health .health-item {{
-            display: inline-block;
-            margin-right: 25px;
-        }}
-        #storage-health .health-label {{
-            color: var(--muted);
-            margin-right: 5px;
-        }}
-        #storage-health .status-good {{ color: var(--accent); }}
+/// Labels streams are identified by. Grafana's Loki datasource also calls this to
+/// test the connection.
+async fn loki_labels() -> Json<LokiResponse<Vec<String>>> {
+    let mut labels: Vec<String> = logql::STREAM_LABELS
+        .iter()
+        .map(|(label, _)| label.to_string())
+        .collect();
+    labels.sort();
+    Json(LokiResponse::success(labels))
+}
+
+/// Distinct values of a label over the range, for Grafana's label browser. Unknown
+/// labels have no values.
+async fn loki_label_values(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Query(params): Query<logql::LabelsParams>,
+) -> Result<Json<LokiResponse<Vec<String>>>, ApiError> {
+    let (start, end) =
+        logql::time_range(params.start.as_deref(), params.end.as_deref(), Utc::now())
+            .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let schema = state.schema_columns().await;
+    let expr = match logql::label_expr(&name, &schema) {
+        Ok(expr) if !schema.is_empty() => expr,
+        _ => return Ok(Json(LokiResponse::success(Vec::new()))),
+    };
+
+    let where_clause = build_where_clause(
+        start,
+        end,
+        &SearchQuery::default(),
+        None,
+        None,
+        None,
+        None,
+        &[],
+        &[],
+    );
+    let source = state.log_source(start, end);
+    let sql = format!(
+        "SELECT DISTINCT {expr} FROM {} WHERE {} AND {expr} <> '' ORDER BY 1 LIMIT 1000",
+        source.sql,
+        where_clause.sql,
+        expr = expr,
+    );
+    let values = run_query(&state, "loki", serde_json::json!([]), move |reader| {
+        Ok(reader.query_distinct_strings(&sql, &where_clause.params))
+    })
+    .await?;
+    Ok(Json(LokiResponse::success(values)))
+}
+
+/// Per-unit overview: entries, error and warning counts, newest entry and message
+/// bytes of every systemd unit that logged in the range
+async fn api_units(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<UnitsParams>,
+) -> Result<Json<UnitsResponse>, ApiError> {
+    let start_time = std::time::Instant::now();
+    let now = Utc::now();
+    let start = parse_time(&params.start, now).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let end = parse_time(&params.end, now).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    if state.schema_columns().await.is_empty() {
+        return Ok(Json(UnitsResponse {
+            units: Vec::new(),
+            query_time_ms: start_time.elapsed().as_millis(),
+        }));
+    }
+
+    let where_clause = build_where_clause(
+        start,
+        end,
+        &SearchQuery::default(),
+        params.hostname.as_deref(),
+        None,
+        params.source.as_deref(),
+        None,
+        &[],
+        &[],
+    );
+    let source = state.log_source(start, end);
+    let sql = format!(
+        "SELECT COALESCE(_systemd_unit, '') AS unit, COUNT(*) AS entries,
+                COUNT(*) FILTER (WHERE TRY_CAST(priority AS INTEGER) <= 3),
+                COUNT(*) FILTER (WHERE TRY_CAST(priority AS INTEGER) = 4),
+                CAST(MAX(timestamp) AS VARCHAR),
+                CAST(COALESCE(SUM(strlen(message)), 0) AS BIGINT)
+         FROM {} WHERE {}
+         GROUP BY 1 ORDER BY entries DESC, unit LIMIT ?",
+        source.sql, where_clause.sql,
+    );
+    let mut query_params = where_clause.params;
+    query_params.push(SqlParam::BigInt(params.limit.min(10_000) as i64));
+
+    let names = [
+        "unit",
+        "entries",
+        "errors",
+        "warnings",
+        "last_timestamp",
+        "message_bytes",
+    ]
+    .map(String::from);
+    let partial = serde_json::json!({ "cold_storage": source.cold_storage });
+    let rows = run_query(&state, "units", partial, move |reader| {
+        reader.query_json_rows(&sql, &query_params, &names)
+    })
+    .await?;
+    let units = rows
+        .into_iter()
+        .map(serde_json::from_value)
+        .collect::<Result<Vec<UnitStats>, _>>()
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(UnitsResponse {
+        units,
+        query_time_ms: start_time.elapsed().as_millis(),
+    }))
+}
+
+/// Every host that logged in the range, per input source, with first and last entry
+/// times and its average ingest rate
+async fn api_hosts(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<HostsParams>,
+) -> Result<Json<HostsResponse>, ApiError> {
+    let start_time = std::time::Instant::now();
+    let now = Utc::now();
+    let start = parse_time(&params.start, now).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let end = parse_time(&params.end, now).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    if state.schema_columns().await.is_empty() {
+        return Ok(Json(HostsResponse {
+            hosts: Vec::new(),
+            query_time_ms: start_time.elapsed().as_millis(),
+        }));
+    }
+
+    let where_clause = build_where_clause(
+        start,
+        end,
+        &SearchQuery::default(),
+        None,
+        None,
+        None,
+        None,
+        &[],
+        &[],
+    );
+    let source = state.log_source(start, end);
+    let sql = format!(
+        "SELECT COALESCE(source, '{local}') AS host_source,
+                COALESCE(_hostname, '') AS host,
+                COUNT(*) AS entries,
+                CAST(MIN(timestamp) AS VARCHAR),
+                CAST(MAX(timestamp) AS VARCHAR)
+         FROM {} WHERE {}
+         GROUP BY 1, 2 ORDER BY 1, 2",
+        source.sql,
+        where_clause.sql,
+        local = LOCAL_SOURCE,
+    );
+    let names = ["source", "hostname", "entries", "first_seen", "last_seen"].map(String::from);
+    let partial = serde_json::json!({ "cold_storage": source.cold_storage });
+    let rows = run_query(&state, "hosts", partial, move |reader| {
+        reader.query_json_rows(&sql, &where_clause.params, &names)
+    })
+    .await?;
+
+    let minutes = ((end - start).num_seconds() as f64 / 60.0).max(1.0);
+    let hosts = rows
+        .into_iter()
+        .map(|mut row| {
+            let entries = row["entries"].as_i64().unwrap_or(0);
+            row["entries_per_minute"] = serde_json::json!(entries as f64 / minutes);
+            serde_json::from_value(row)
+        })
+        .collect::<Result<Vec<HostStats>, _>>()
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(HostsResponse {
+        hosts,
+        query_time_ms: start_time.elapsed().as_millis(),
+    }))
+}
+
+/// Promote an extra_fields key to its own column. Altering every partition holds the
+/// writer lock, so it runs off the async workers.
+async fn api_promote_extra_field(
+    State(state): State<Arc<AppState>>,
+    Path(key): Path<String>,
+) -> Result<Json<PromoteResponse>, (StatusCode, String)> {
+    let buffer = state.buffer.clone();
+    let promote_key = key.clone();
+    let backfilled_rows = tokio::task::spawn_blocking(move || {
+        buffer.lock().unwrap().promote_extra_field(&promote_key)
+    })
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    if let Some(cache) = &state.query_cache {
+        cache.clear();
+    }
+    Ok(Json(PromoteResponse {
+        key,
+        backfilled_rows,
+    }))
+}
+
+/// Let a request through once its caller has at least the route's role, recording
+/// the caller's [`Identity`] in the request extensions
+async fn require_role(
+    State((state, role)): State<(Arc<AppState>, Role)>,
+    mut request: axum::extract::Request,
+    next: Next,
+) -> Response {
+    // The rate limit has already identified the caller of /api requests
+    let identity = match request.extensions().get::<Identity>() {
+        Some(identity) => Some(identity.clone()),
+        None => identify(&state, request.headers()).await,
+    };
+    let Some(identity) = identity else {
+        return unauthenticated(&state, &request);
+    };
+    if identity.role < role {
+        return (
+            StatusCode::FORBIDDEN,
+            format!("This endpoint requires the {} role", role),
+        )
+            .into_response();
+    }
+    request.extensions_mut().insert(identity);
+    next.run(request).await
+}
+
+/// Apply the per-client rate limit to /api requests, reporting the client's quota in
+/// `X-RateLimit-*` headers. Runs before the role check so credential guessing is
+/// limited too: requests whose credentials fail count against their IP address.
+async fn rate_limit(
+    State(state): State<Arc<AppState>>,
+    mut request: axum::extract::Request,
+    next: Next,
+) -> Response {
+    let Some(limiter) = &state.rate_limiter else {
+        return next.run(request).await;
+    };
+    if !request.uri().path().starts_with("/api/") {
+        return next.run(request).await;
+    }
+
+    let identity = identify(&state, request.headers()).await;
+    let client = rate_limit_client(&request, identity.as_ref());
+    if let Some(identity) = identity {
+        request.extensions_mut().insert(identity);
+    }
+    let decision = limiter.check(&client, std::time::Instant::now());
+    let mut response = if decision.allowed {
+        next.run(request).await
+    } else {
+        let mut response = (StatusCode::TOO_MANY_REQUESTS, "Rate limit exceeded").into_response();
+        response
+            .headers_mut()
+            .insert(header::RETRY_AFTER, decision.retry_after_secs.into());
+        response
+    };
+    let headers = response.headers_mut();
+    headers.insert("x-ratelimit-limit", decision.limit.into());
+    headers.insert("x-ratelimit-remaining", decision.remaining.into());
+    response
+}
+
+/// Rate limit key of a request: the user it authenticated as, else the client's IP
+/// address, so that unknown credentials share their sender's quota
+fn rate_limit_client(request: &axum::extract::Request, identity: Option<&Identity>) -> String {
+    if let Some(name) = identity.and_then(|identity| identity.name.as_ref()) {
+        return format!("user:{}", name);
+    }
+    request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| format!("ip:{}", addr.ip()))
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Identify the caller by configured credentials, then by an OIDC bearer token or
+/// login session cookie; `None` when the request must be rejected
+async fn identify(state: &AppState, headers: &HeaderMap) -> Option<Identity> {
+    let authorization = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok());
+    if let Some(identity) = auth::authenticate(&state.settings, authorization) {
+        return Some(identity);
+    }
+    let oidc = state.oidc.as_ref()?;
+    match authorization {
+        Some(value) => {
+            let token = value.strip_prefix("Bearer ")?;
+            oidc.authenticate_token(token)
+                .await
+                .inspect_err(|e| log::debug!("Rejected bearer token: {}", e))
+                .ok()
+        }
+        None => headers
+            .get(header::COOKIE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(oidc::session_id)
+            .and_then(|id| oidc.session(id)),
+    }
+}
+
+/// Response to a request without valid credentials: browsers opening a page are sent
+/// to the OIDC login, everything else gets a 401
+fn unauthenticated(state: &AppState, request: &axum::extract::Request) -> Response {
+    let opens_page = request.method() == Method::GET
+        && request
+            .headers()
+            .get(header::ACCEPT)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|accept| accept.contains("text/html"));
+    if state.oidc.is_some() && opens_page {
+        let return_to = request
+            .uri()
+            .path_and_query()
+            .map_or("/", |path| path.as_str());
+        return Redirect::to(&oidc::login_path(return_to)).into_response();
+    }
+
+    let mut response = (StatusCode::UNAUTHORIZED, "Authentication required").into_response();
+    // Ask browsers for credentials whenever basic auth can succeed
+    if state.settings.web_username.is_some()
+        || state
+            .settings
+            .web_users
+            .iter()
+            .any(|user| user.password.is_some())
+    {
+        response.headers_mut().insert(
+            header::WWW_AUTHENTICATE,
+            HeaderValue::from_static("Basic realm=\"livedata\""),
+        );
+    }
+    response
+}
+
+fn oidc_provider(state: &AppState) -> Result<&OidcProvider, (StatusCode, String)> {
+    state.oidc.as_ref().ok_or_else(|| {
+        (
+            StatusCode::NOT_FOUND,
+            "OIDC login is not configured".to_string(),
+        )
+    })
+}
+
+/// Send the browser to the OIDC provider's login page
+async fn auth_login(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<LoginParams>,
+) -> Result<Redirect, (StatusCode, String)> {
+    let oidc = oidc_provider(&state)?;
+    // Only paths on this server, so the login cannot be used as an open redirect
+    let return_to = params
+        .return_to
+        .filter(|path| path.starts_with('/') && !path.starts_with("//") && !path.starts_with("/\\"))
+        .unwrap_or_else(|| "/".to_string());
+    let url = oidc
+        .login_url(&return_to)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Redirect::to(&url))
+}
+
+/// Provider redirect after a login: open a session and return to the original page
+async fn auth_callback(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<CallbackParams>,
+) -> Result<Response, (StatusCode, String)> {
+    let oidc = oidc_provider(&state)?;
+    if let Some(error) = params.error {
+        return Err((StatusCode::UNAUTHORIZED, format!("Login failed: {}", error)));
+    }
+    let (Some(code), Some(login)) = (params.code, params.state) else {
+        return Err((StatusCode::BAD_REQUEST, "Missing code or state".to_string()));
+    };
+    let (session, return_to) = oidc
+        .complete_login(&code, &login)
+        .await
+        .map_err(|e| (StatusCode::UNAUTHORIZED, format!("Login failed: {}", e)))?;
+
+    let mut response = Redirect::to(&return_to).into_response();
+    response.headers_mut().insert(
+        header::SET_COOKIE,
+        HeaderValue::from_str(&oidc.session_cookie(&session)).unwrap(),
+    );
+    Ok(response)
+}
+
+/// End the caller's login session
+async fn auth_logout(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Response, (StatusCode, String)> {
+    let oidc = oidc_provider(&state)?;
+    if let Some(id) = headers
+        .get(header::COOKIE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(oidc::session_id)
+    {
+        oidc.end_session(id);
+    }
+    let mut response = "Logged out".into_response();
+    response.headers_mut().insert(
+        header::SET_COOKIE,
+        HeaderValue::from_str(&oidc.session_cookie("")).unwrap(),
+    );
+    Ok(response)
+}
+
+/// Delete the log entries matching the filters (operators only), e.g. for data
+/// subject erasure requests. Holds the writer lock throughout, so ingestion waits.
+async fn api_purge_logs(
+    State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<Identity>,
+    Query(params): Query<PurgeParams>,
+) -> Result<Json<PurgeResponse>, (StatusCode, String)> {
+    // Never anonymous, even while web auth is off and other operator routes are open
+    let Some(user) = identity.name else {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "Purging is disabled: no admin_token or web_users configured".to_string(),
+        ));
+    };
+    let now = Utc::now();
+    let parse = |time: &Option<String>| {
+        time.as_deref()
+            .map(|t| parse_time(t, now))
+            .transpose()
+            .map_err(|e| (StatusCode::BAD_REQUEST, e))
+    };
+    let filter = PurgeFilter {
+        unit: params.unit,
+        hostname: params.hostname,
+        start: parse(&params.start)?,
+        end: parse(&params.end)?,
+        max_priority: params.max_priority,
+    };
+    log::info!("User {} purging logs matching {:?}", user, filter);
+
+    let buffer = state.buffer.clone();
+    let batch_size = params.batch_size;
+    let deleted = tokio::task::spawn_blocking(move || {
+        buffer
+            .lock()
+            .unwrap()
+            .purge_logs(&filter, batch_size, |day, total| {
+                log::info!("Purged {} log entries through {}", total, day);
+            })
+    })
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    log::info!("Purge complete: {} log entries deleted", deleted);
+    state.metrics.record_deleted(deleted);
+    Ok(Json(PurgeResponse { deleted }))
+}
+
+/// API search endpoint returning JSON results
+async fn api_search(
+    State(state): State<Arc<AppState>>,
+    params: SearchParams,
+) -> Result<Json<SearchResponse>, ApiError> {
+    let start_time = std::time::Instant::now();
+
+    // Validate and clamp limit
+    let limit = params.limit.min(100_000);
+
+    // If the table doesn't exist yet, return empty results
+    let Some(search) = prepare_search(&state, &params, limit).await? else {
+        return Ok(Json(SearchResponse {
+            results: Vec::new(),
+            columns: DEFAULT_COLUMNS
+                .iter()
+                .map(|c| column_display_name(c))
+                .collect(),
+            total: 0,
+            total_is_estimate: false,
+            limit,
+            offset: params.offset,
+            query_time_ms: start_time.elapsed().as_millis(),
+            cold_storage: false,
+            next_cursor: None,
+            prev_cursor: None,
+            facets: BTreeMap::new(),
+        }));
+    };
+    let PreparedSearch {
+        sql,
+        params: query_params,
+        columns: display_names,
+        cold_storage,
+        source,
+        where_sql,
+        where_params,
+        keyed,
+        reversed,
+        paged_from_start,
+        ..
+    } = search;
+    let facet_fields = facet_columns(&state, params.facets.as_deref()).await?;
+
+    // Count matches across all pages so API pagination matches the HTML UI
+    let count_mode = params.count_mode;
+    let facet_query = (source.clone(), where_sql.clone(), where_params.clone());
+    let partial = serde_json::json!({ "cold_storage": cold_storage });
+    let (total, total_is_estimate) = run_query(&state, "count", partial, move |reader| {
+        Ok(count_matches(
+            reader,
+            &source,
+            &where_sql,
+            &where_params,
+            count_mode,
+        ))
+    })
+    .await?;
+
+    // Execute query with dynamic column mapping
+    let partial = serde_json::json!({
+        "total": total,
+        "total_is_estimate": total_is_estimate,
+        "limit": limit,
+        "offset": params.offset,
+        "cold_storage": cold_storage,
+    });
+    let mut columns = display_names.clone();
+    if keyed {
+        columns.push(PAGE_TS_KEY.to_string());
+        columns.push(PAGE_CURSOR_KEY.to_string());
+    }
+    let mut results: Vec<serde_json::Value> = run_query(&state, "search", partial, move |reader| {
+        reader.query_json_rows(&sql, &query_params, &columns)
+    })
+    .await?;
+    let (next_cursor, prev_cursor) = if keyed {
+        finish_keyset_page(&mut results, reversed, paged_from_start, limit)
+    } else {
+        (None, None)
+    };
+
+    let facets = if facet_fields.is_empty() {
+        BTreeMap::new()
+    } else {
+        let facet_limit = params.facet_limit.clamp(1, 100);
+        let partial = serde_json::json!({ "total": total, "cold_storage": cold_storage });
+        run_query(&state, "facets", partial, move |reader| {
+            let (source, where_sql, where_params) = facet_query;
+            facet_counts(
+                reader,
+                &source,
+                &where_sql,
+                &where_params,
+                &facet_fields,
+                facet_limit,
+            )
+        })
+        .await?
+    };
+
+    let query_time_ms = start_time.elapsed().as_millis();
+
+    Ok(Json(SearchResponse {
+        results,
+        columns: display_names,
+        total,
+        total_is_estimate,
+        limit,
+        offset: params.offset,
+        query_time_ms,
+        cold_storage,
+        next_cursor,
+        prev_cursor,
+        facets,
+    }))
+}
+
+/// Resolve the `facets` of a search to (display name, column) pairs
+async fn facet_columns(
+    state: &Arc<AppState>,
+    facets: Option<&str>,
+) -> Result<Vec<(String, String)>, (StatusCode, String)> {
+    let names: Vec<&str> = facets
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .collect();
+    if names.is_empty() {
+        return Ok(Vec::new());
+    }
+    let schema = state.schema_columns().await;
+    names
+        .into_iter()
+        .map(|name| {
+            let column = group_column(name, &schema)
+                .ok_or_else(|| (StatusCode::BAD_REQUEST, format!("Unknown facet {}", name)))?;
+            Ok((column_display_name(&column), column))
+        })
+        .collect()
+}
+
+/// Top `limit` values of each facet column among the rows matching `where_sql`
+fn facet_counts(
+    reader: &PooledReader,
+    source: &str,
+    where_sql: &str,
+    where_params: &[SqlParam],
+    columns: &[(String, String)],
+    limit: usize,
+) -> anyhow::Result<BTreeMap<String, Vec<FacetValue>>> {
+    let mut params = where_params.to_vec();
+    params.push(SqlParam::BigInt(limit as i64));
+    let names = ["value".to_string(), "count".to_string()];
+    let mut facets = BTreeMap::new();
+    for (name, column) in columns {
+        let column = quote_ident(column);
+        let sql = format!(
+            "SELECT {column}, COUNT(*) FROM {source} WHERE {where_sql} \
+             GROUP BY {column} ORDER BY COUNT(*) DESC, {column} LIMIT ?"
+        );
+        let values = reader
+            .query_json_rows(&sql, &params, &names)?
+            .into_iter()
+            .map(|row| FacetValue {
+                value: row["value"].clone(),
+                count: row["count"].as_i64().unwrap_or_default(),
+            })
+            .collect();
+        facets.insert(name.clone(), values);
+    }
+    Ok(facets)
+}
+
+/// Streaming search endpoint returning matching rows as NDJSON (one object per line).
+/// Rows are read and sent in chunks, so large exports never sit in memory at once.
+///
+/// Clients accepting `text/event-stream` (EventSource) get a live search instead, see
+/// [`search_events`].
+async fn api_search_stream(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    params: SearchParams,
+    Query(live): Query<LiveParams>,
+) -> Result<Response, (StatusCode, String)> {
+    let wants_events = headers
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/event-stream"));
+    if wants_events {
+        return search_events(state, params, live).await;
+    }
+
+    let limit = params.limit.min(MAX_STREAM_ROWS);
+    // Checks the parameters before the response starts
+    if prepare_search(&state, &params, limit).await?.is_none() {
+        return Ok(ndjson_response(Body::empty()));
+    }
+
+    let (tx, rx) = tokio::sync::mpsc::channel::<Result<Vec<u8>, std::io::Error>>(4);
+    tokio::spawn(async move {
+        let result = send_search_pages(&state, "stream", params, limit, &tx, |_, rows| {
+            ndjson_chunk(rows)
+        })
+        .await;
+        if let Err(e) = result {
+            log::warn!("Streaming search failed: {}", e);
+            let _ = tx.send(Err(std::io::Error::other(e.to_string()))).await;
+        }
+    });
+
+    let stream = futures_util::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|chunk| (chunk, rx))
+    });
+    Ok(ndjson_response(Body::from_stream(stream)))
+}
+
+/// Live search as server-sent events, for clients that cannot open a WebSocket.
+///
+/// `follow=poll` (default) sends the search results, then re-runs the search every
+/// `interval` seconds for rows after the newest one sent. `follow=tail` sends entries
+/// as they are ingested, filtered like /api/tail. Rows go out as `results` events
+/// carrying `{"columns": [...], "results": [...]}`. A failed poll is reported as an
+/// `error` event and retried with exponential backoff, up to [`MAX_POLL_BACKOFF`].
+async fn search_events(
+    state: Arc<AppState>,
+    params: SearchParams,
+    live: LiveParams,
+) -> Result<Response, (StatusCode, String)> {
+    if live.follow == FollowMode::Poll && sort_column(&params.sort) != "timestamp" {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Live search requires sort=timestamp".into(),
+        ));
+    }
+    let interval = std::time::Duration::from_secs(live.interval.clamp(1, 3600));
+
+    let (tx, rx) = tokio::sync::mpsc::channel::<Event>(16);
+    match live.follow {
+        FollowMode::Poll => {
+            tokio::spawn(poll_search_events(state, params, interval, tx));
+        }
+        FollowMode::Tail => {
+            let filter = TailParams {
+                q: params.q,
+                hostname: params.hostname,
+                unit: params.unit,
+                source: params.source,
+                priority: params.priority,
+                ..TailParams::default()
+            }
+            .parse_query(&state.schema_columns().await)
+            .await?;
+            let entries = state.tail.subscribe();
+            tokio::spawn(tail_search_events(entries, filter, tx));
+        }
+    }
+
+    let stream = futures_util::stream::unfold(rx, |mut rx| async move {
+        rx.recv()
+            .await
+            .map(|event| (Ok::<_, std::convert::Infallible>(event), rx))
+    });
+    Ok(Sse::new(stream)
+        .keep_alive(KeepAlive::default())
+        .into_response())
+}
+
+fn results_event(columns: &[String], results: &[serde_json::Value]) -> Event {
+    Event::default()
+        .event("results")
+        .data(serde_json::json!({ "columns": columns, "results": results }).to_string())
+}
+
+/// Poll loop of [`search_events`]; ends when the client goes away
+async fn poll_search_events(
+    state: Arc<AppState>,
+    params: SearchParams,
+    interval: std::time::Duration,
+    tx: tokio::sync::mpsc::Sender<Event>,
+) {
+    let limit = params.limit.min(100_000);
+    let mut newest: Option<PageCursor> = None;
+    let mut failures = 0u32;
+    let mut first = true;
+    loop {
+        // After the first run, only ask for rows past the newest one sent, oldest first
+        let poll = match &newest {
+            Some(cursor) => SearchParams {
+                after: Some(cursor.encode()),
+                before: None,
+                sort_dir: "asc".to_string(),
+                offset: 0,
+                ..params.clone()
+            },
+            None => params.clone(),
+        };
+        let event = match poll_search(&state, &poll, limit).await {
+            Ok((columns, results, cursor)) => {
+                failures = 0;
+                if cursor.is_some() {
+                    newest = cursor;
+                }
+                // The first run always answers, so the client learns the columns
+                (first || !results.is_empty()).then(|| results_event(&columns, &results))
+            }
+            Err(e) => {
+                failures += 1;
+                log::warn!("Live search poll failed: {}", e);
+                Some(
+                    Event::default()
+                        .event("error")
+                        .data(serde_json::json!({ "error": e.to_string() }).to_string()),
+                )
+            }
+        };
+        first = false;
+        if let Some(event) = event
+            && tx.send(event).await.is_err()
+        {
+            return;
+        }
+
+        let delay = if failures == 0 {
+            interval
+        } else {
+            (interval * 2u32.pow(failures.min(10))).min(MAX_POLL_BACKOFF)
+        };
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = tx.closed() => return,
+        }
+    }
+}
+
+/// One run of a live search: the display columns, the rows in order and the
+/// position of the newest row
+async fn poll_search(
+    state: &Arc<AppState>,
+    params: &SearchParams,
+    limit: usize,
+) -> Result<(Vec<String>, Vec<serde_json::Value>, Option<PageCursor>), ApiError> {
+    let Some(search) = prepare_search(state, params, limit).await? else {
+        let columns = DEFAULT_COLUMNS
+            .iter()
+            .map(|c| column_display_name(c))
+            .collect();
+        return Ok((columns, Vec::new(), None));
+    };
+    let mut columns = search.columns.clone();
+    columns.push(PAGE_TS_KEY.to_string());
+    columns.push(PAGE_CURSOR_KEY.to_string());
+    let (sql, query_params) = (search.sql, search.params);
+    let mut results = run_query(state, "search", serde_json::json!({}), move |reader| {
+        reader.query_json_rows(&sql, &query_params, &columns)
+    })
+    .await?;
+    let newest = results
+        .iter_mut()
+        .filter_map(PageCursor::take_from)
+        .max_by(|a, b| (a.timestamp_us, &a.cursor).cmp(&(b.timestamp_us, &b.cursor)));
+    Ok((search.columns, results, newest))
+}
+
+/// Tail loop of [`search_events`]; ends when the client goes away
+async fn tail_search_events(
+    mut entries: broadcast::Receiver<Arc<LogEntry>>,
+    filter: TailParams,
+    tx: tokio::sync::mpsc::Sender<Event>,
+) {
+    let columns: Vec<String> = DEFAULT_COLUMNS
+        .iter()
+        .map(|c| column_display_name(c))
+        .chain(["source".to_string()])
+        .collect();
+    loop {
+        let event = tokio::select! {
+            entry = entries.recv() => match entry {
+                Ok(entry) if filter.matches(&entry) => results_event(&columns, &[tail_row(&entry)]),
+                Ok(_) => continue,
+                Err(broadcast::error::RecvError::Lagged(missed)) => Event::default()
+                    .event("missed")
+                    .data(serde_json::json!({ "missed": missed }).to_string()),
+                Err(broadcast::error::RecvError::Closed) => return,
+            },
+            _ = tx.closed() => return,
+        };
+        if tx.send(event).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Filters of /api/tail, with the same meaning as the /api/search ones
+#[derive(Debug, Default, Deserialize)]
+pub struct TailParams {
+    /// Search query, see [`SearchQuery`]
+    #[serde(default)]
+    pub q: Option<String>,
+    /// Filter by hostname (comma-separated)
+    #[serde(default)]
+    pub hostname: Option<String>,
+    /// Filter by systemd unit (comma-separated)
+    #[serde(default)]
+    pub unit: Option<String>,
+    /// Filter by input the entries arrived through (comma-separated, e.g. local)
+    #[serde(default)]
+    pub source: Option<String>,
+    /// Max priority level (0-7, lower = more severe)
+    #[serde(default)]
+    pub priority: Option<u8>,
+    /// `q` once parsed by [`TailParams::parse_query`]
+    #[serde(skip)]
+    query: SearchQuery,
+}
+
+impl TailParams {
+    async fn parse_query(
+        mut self,
+        schema: &[(String, String)],
+    ) -> Result<Self, (StatusCode, String)> {
+        self.query = parse_search_query(self.q.as_deref(), schema).await?;
+        Ok(self)
+    }
+
+    /// Whether a newly ingested entry passes every filter
+    fn matches(&self, entry: &LogEntry) -> bool {
+        let in_list = |filter: &Option<String>, value: Option<&str>| match filter.as_deref() {
+            None | Some("") => true,
+            Some(list) => value.is_some_and(|value| list.split(',').any(|item| item == value)),
+        };
+        let priority_matches = self.priority.is_none_or(|max| {
+            entry
+                .get_priority()
+                .and_then(|priority| priority.parse::<u8>().ok())
+                .is_some_and(|priority| priority <= max)
+        });
+        self.query.matches(entry)
+            && priority_matches
+            && in_list(&self.hostname, entry.get_hostname().map(String::as_str))
+            && in_list(&self.unit, entry.get_systemd_unit().map(String::as_str))
+            && in_list(&self.source, Some(entry.source.as_str()))
+    }
+}
+
+/// An entry as a row of the default /api/search columns
+fn tail_row(entry: &LogEntry) -> serde_json::Value {
+    serde_json::json!({
+        "timestamp": entry.timestamp.format("%Y-%m-%d %H:%M:%S%.6f").to_string(),
+        "hostname": entry.get_hostname(),
+        "unit": entry.get_systemd_unit(),
+        "priority": entry.get_priority().and_then(|p| p.parse::<u8>().ok()),
+        "pid": entry.get_pid(),
+        "comm": entry.get_comm(),
+        "message": entry.get_message(),
+        "source": entry.source,
+    })
+}
+
+/// Live view of newly ingested entries, like `journalctl -f`. After the WebSocket
+/// upgrade every entry matching the filters is sent as a JSON text message. A client
+/// that falls too far behind is sent `{"missed": n}` and continues from the newest entry.
+async fn api_tail(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<TailParams>,
+    ws: WebSocketUpgrade,
+) -> Result<Response, (StatusCode, String)> {
+    let filter = params.parse_query(&state.schema_columns().await).await?;
+    let entries = state.tail.subscribe();
+    Ok(ws.on_upgrade(move |socket| tail_socket(socket, entries, filter)))
+}
+
+async fn tail_socket(
+    mut socket: WebSocket,
+    mut entries: broadcast::Receiver<Arc<LogEntry>>,
+    filter: TailParams,
+) {
+    loop {
+        tokio::select! {
+            entry = entries.recv() => {
+                let message = match entry {
+                    Ok(entry) if filter.matches(&entry) => tail_row(&entry).to_string(),
+                    Ok(_) => continue,
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        serde_json::json!({ "missed": missed }).to_string()
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                if socket.send(Message::Text(message.into())).await.is_err() {
+                    break;
+                }
+            }
+            incoming = socket.recv() => match incoming {
+                // Pings are answered by axum; anything else from the client is ignored
+                Some(Ok(Message::Close(_)) | Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
+
+/// Download every row matching the /api/search filters, up to `limit`, as NDJSON,
+/// CSV or a Parquet file written by DuckDB's COPY TO. Offsets and cursors are ignored.
+async fn api_export(
+    State(state): State<Arc<AppState>>,
+    params: SearchParams,
+    Query(export): Query<ExportParams>,
+) -> Result<Response, (StatusCode, String)> {
+    let format = export.format;
+    let limit = export.limit.unwrap_or(MAX_STREAM_ROWS).min(MAX_STREAM_ROWS);
+    let params = SearchParams {
+        after: None,
+        before: None,
+        offset: 0,
+        ..params
+    };
+    let Some(query) = prepare_export(&state, &params, limit).await? else {
+        return Ok(export_response(format, Utc::now(), Body::empty()));
+    };
+
+    let (tx, rx) = tokio::sync::mpsc::channel::<Result<Vec<u8>, std::io::Error>>(4);
+    tokio::spawn(async move {
+        let result = match format {
+            ExportFormat::Parquet => send_parquet(&state, query, &tx).await,
+            ExportFormat::Csv => {
+                let header = csv_line(&query.columns, None).into_bytes();
+                if tx.send(Ok(header)).await.is_err() {
+                    return;
+                }
+                send_search_pages(&state, "export", params, limit, &tx, |columns, rows| {
+                    rows.iter()
+                        .map(|row| csv_line(columns, Some(row)))
+                        .collect::<String>()
+                        .into_bytes()
+                })
+                .await
+            }
+            ExportFormat::Ndjson => {
+                send_search_pages(&state, "export", params, limit, &tx, |_, rows| {
+                    ndjson_chunk(rows)
+                })
+                .await
+            }
+        };
+        if let Err(e) = result {
+            log::warn!("Export failed: {}", e);
+            let _ = tx.send(Err(std::io::Error::other(e.to_string()))).await;
+        }
+    });
+
+    let stream = futures_util::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|chunk| (chunk, rx))
+    });
+    Ok(export_response(
+        format,
+        Utc::now(),
+        Body::from_stream(stream),
+    ))
+}
+
+/// Start a background export of the rows matching the /api/search filters, up to
+/// `limit`, into a file under data_dir/exports. Poll the returned job on
+/// /api/export/jobs/{id} and fetch the file from /api/export/jobs/{id}/download.
+async fn api_create_export_job(
+    State(state): State<Arc<AppState>>,
+    params: SearchParams,
+    Query(export): Query<ExportParams>,
+) -> Result<(StatusCode, Json<ExportJob>), (StatusCode, String)> {
+    let limit = export
+        .limit
+        .unwrap_or(MAX_EXPORT_JOB_ROWS)
+        .min(MAX_EXPORT_JOB_ROWS);
+    let Some(query) = prepare_export(&state, &params, limit).await? else {
+        return Err((StatusCode::NOT_FOUND, "No logs to export yet".into()));
+    };
+    state.export_jobs.expire(Utc::now());
+    let job = state
+        .export_jobs
+        .start(export.format)
+        .map_err(|e| (StatusCode::TOO_MANY_REQUESTS, e.to_string()))?;
+
+    let worker = job.clone();
+    tokio::task::spawn_blocking(move || {
+        let result = run_export_job(&state, &worker, &query);
+        state.export_jobs.finish(&worker.id, result);
+    });
+    Ok((StatusCode::ACCEPTED, Json(job)))
+}
+
+/// Write an export job's file, reporting progress to the job registry
+fn run_export_job(state: &AppState, job: &ExportJob, query: &ExportQuery) -> anyhow::Result<usize> {
+    use std::io::Write;
+
+    let reader = state.readers.get();
+    let total = reader.query_usize(
+        &format!("SELECT COUNT(*) FROM ({})", query.sql),
+        &query.params,
+    );
+    state.export_jobs.set_total_rows(&job.id, total);
+    if job.format == ExportFormat::Parquet {
+        return reader.copy_to_parquet(&query.sql, &query.params, &job.path);
+    }
+
+    let mut file = std::io::BufWriter::new(std::fs::File::create(&job.path)?);
+    let mut write_error = None;
+    let rows = write_export_rows(&reader, job.format, query, |bytes, rows| {
+        match file.write_all(&bytes) {
+            Ok(()) => {
+                state.export_jobs.set_rows_written(&job.id, rows);
+                true
+            }
+            Err(e) => {
+                write_error = Some(e);
+                false
+            }
+        }
+    })?;
+    if let Some(e) = write_error {
+        return Err(e.into());
+    }
+    file.flush()?;
+    Ok(rows)
+}
+
+async fn api_export_job(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<ExportJob>, (StatusCode, String)> {
+    state
+        .export_jobs
+        .get(&id)
+        .map(Json)
+        .ok_or_else(|| (StatusCode::NOT_FOUND, format!("Unknown export job {}", id)))
+}
+
+/// Download the file of a completed export job
+async fn api_export_job_download(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Response, (StatusCode, String)> {
+    let job = state
+        .export_jobs
+        .get(&id)
+        .ok_or_else(|| (StatusCode::NOT_FOUND, format!("Unknown export job {}", id)))?;
+    match job.state {
+        ExportJobState::Completed => {}
+        ExportJobState::Running => {
+            return Err((StatusCode::CONFLICT, "Export job is still running".into()));
+        }
+        ExportJobState::Failed => {
+            return Err((StatusCode::CONFLICT, "Export job failed".into()));
+        }
+    }
+    // Open before streaming so an expired file is a 404 rather than a broken body
+    let file = std::fs::File::open(&job.path)
+        .map_err(|_| (StatusCode::NOT_FOUND, format!("Unknown export job {}", id)))?;
+
+    let (tx, rx) = tokio::sync::mpsc::channel::<Result<Vec<u8>, std::io::Error>>(4);
+    tokio::task::spawn_blocking(move || {
+        if let Err(e) = send_file(file, &tx) {
+            let _ = tx.blocking_send(Err(e));
+        }
+    });
+    let stream = futures_util::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|chunk| (chunk, rx))
+    });
+    Ok(export_response(
+        job.format,
+        job.created_at,
+        Body::from_stream(stream),
+    ))
+}
+
+/// Copy statements logged since the previous pass into the query log table every
+/// [`QUERY_LOG_PERSIST_INTERVAL`]
+async fn persist_query_log(state: Arc<AppState>) {
+    let mut last_id = 0;
+    loop {
+        tokio::time::sleep(QUERY_LOG_PERSIST_INTERVAL).await;
+        let records = sql_trace::logged_queries(last_id);
+        let Some(last) = records.last() else {
+            continue;
+        };
+        last_id = last.id;
+        let keep_since = Utc::now() - Duration::days(state.settings.log_retention_days as i64);
+        let buffer = state.buffer.clone();
+        match tokio::task::spawn_blocking(move || {
+            buffer
+                .lock()
+                .unwrap()
+                .append_query_log(&records, keep_since)
+        })
+        .await
+        {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => log::warn!("Failed to write the query log table: {}", e),
+            Err(e) => log::warn!("Query log task failed: {}", e),
+        }
+    }
+}
+
+/// Forget expired export jobs and delete their files every [`EXPORT_EXPIRY_INTERVAL`]
+async fn expire_export_jobs(state: Arc<AppState>) {
+    loop {
+        tokio::time::sleep(EXPORT_EXPIRY_INTERVAL).await;
+        let expired = state.export_jobs.expire(Utc::now());
+        if expired > 0 {
+            log::info!("Removed {} expired export jobs", expired);
+        }
+    }
+}
+
+/// Export query: the /api/search filters with the requested columns, sort and a
+/// row limit, but no paging
+struct ExportQuery {
+    sql: String,
+    params: Vec<SqlParam>,
+    columns: Vec<String>,
+}
+
+/// Build the export query for `params`, or `None` while journal_logs has no schema yet
+async fn prepare_export(
+    state: &Arc<AppState>,
+    params: &SearchParams,
+    limit: usize,
+) -> Result<Option<ExportQuery>, (StatusCode, String)> {
+    let Some(search) = prepare_search(state, params, limit).await? else {
+        return Ok(None);
+    };
+    let select = search
+        .select_exprs
+        .iter()
+        .zip(&search.columns)
+        .map(|(expr, name)| format!("{} AS {}", expr, quote_ident(name)))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let sql = format!(
+        "SELECT {} FROM {} WHERE {} ORDER BY {} {} LIMIT ?",
+        select,
+        search.source,
+        search.where_sql,
+        sort_column(&params.sort),
+        sort_direction(&params.sort_dir)
+    );
+    let mut sql_params = search.where_params;
+    sql_params.push(SqlParam::BigInt(limit as i64));
+    Ok(Some(ExportQuery {
+        sql,
+        params: sql_params,
+        columns: search.columns,
+    }))
+}
+
+/// Read an NDJSON or CSV export in chunks, handing each encoded chunk and the rows
+/// written so far to `sink` until it returns false; returns the rows read
+fn write_export_rows<F>(
+    reader: &PooledReader,
+    format: ExportFormat,
+    query: &ExportQuery,
+    mut sink: F,
+) -> anyhow::Result<usize>
+where
+    F: FnMut(Vec<u8>, usize) -> bool,
+{
+    if format == ExportFormat::Csv && !sink(csv_line(&query.columns, None).into_bytes(), 0) {
+        return Ok(0);
+    }
+    let mut written = 0;
+    reader.stream_json_rows(
+        &query.sql,
+        &query.params,
+        &query.columns,
+        STREAM_CHUNK_ROWS,
+        |rows| {
+            let mut chunk = String::new();
+            written += rows.len();
+            for row in rows {
+                if format == ExportFormat::Csv {
+                    chunk.push_str(&csv_line(&query.columns, Some(&row)));
+                } else {
+                    chunk.push_str(&row.to_string());
+                    chunk.push('\n');
+                }
+            }
+            sink(chunk.into_bytes(), written)
+        },
+    )
+}
+
+/// Read the rows matching `params`, up to `limit`, a page at a time and send each
+/// page encoded by `encode`, until the receiver goes away. Every page is a query of
+/// its own through [`run_query`], so it is bound by the query timeout and its
+/// connection is back in the pool before the page is sent. Timestamp-sorted reads
+/// continue from the last row's key, others by offset.
+async fn send_search_pages<F>(
+    state: &Arc<AppState>,
+    stage: &'static str,
+    mut params: SearchParams,
+    limit: usize,
+    tx: &tokio::sync::mpsc::Sender<Result<Vec<u8>, std::io::Error>>,
+    encode: F,
+) -> Result<(), ApiError>
+where
+    F: Fn(&[String], Vec<serde_json::Value>) -> Vec<u8>,
+{
+    let mut sent = 0;
+    while sent < limit {
+        let page_size = (limit - sent).min(STREAM_CHUNK_ROWS);
+        let Some(search) = prepare_search(state, &params, page_size).await? else {
+            return Ok(());
+        };
+        let mut columns = search.columns.clone();
+        if search.keyed {
+            columns.push(PAGE_TS_KEY.to_string());
+            columns.push(PAGE_CURSOR_KEY.to_string());
+        }
+        let (sql, query_params) = (search.sql, search.params);
+        let partial = serde_json::json!({ "rows_sent": sent });
+        let mut rows = run_query(state, stage, partial, move |reader| {
+            reader.query_json_rows(&sql, &query_params, &columns)
+        })
+        .await?;
+        let read = rows.len();
+        let last = rows.iter_mut().filter_map(PageCursor::take_from).last();
+        // A failed send means the client went away; stop reading
+        if read > 0 && tx.send(Ok(encode(&search.columns, rows))).await.is_err() {
+            return Ok(());
+        }
+        sent += read;
+        if read < page_size {
+            break;
+        }
+        match last {
+            Some(cursor) if search.reversed => params.before = Some(cursor.encode()),
+            Some(cursor) => params.after = Some(cursor.encode()),
+            None => params.offset += read,
+        }
+    }
+    Ok(())
+}
+
+fn ndjson_chunk(rows: Vec<serde_json::Value>) -> Vec<u8> {
+    let mut chunk = String::new();
+    for row in rows {
+        chunk.push_str(&row.to_string());
+        chunk.push('\n');
+    }
+    chunk.into_bytes()
+}
+
+/// COPY the export query into a temporary Parquet file, bound by the query timeout,
+/// then send it in chunks once the connection is back in the pool
+async fn send_parquet(
+    state: &Arc<AppState>,
+    query: ExportQuery,
+    tx: &tokio::sync::mpsc::Sender<Result<Vec<u8>, std::io::Error>>,
+) -> Result<(), ApiError> {
+    let file = tempfile::Builder::new()
+        .prefix("livedata-export-")
+        .suffix(".parquet")
+        .tempfile()
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let path = file.path().to_path_buf();
+    run_query(state, "export", serde_json::json!({}), move |reader| {
+        reader.copy_to_parquet(&query.sql, &query.params, &path)
+    })
+    .await?;
+    let tx = tx.clone();
+    tokio::task::spawn_blocking(move || {
+        // DuckDB replaced the file, so read it through a fresh handle
+        let sent = std::fs::File::open(file.path()).and_then(|opened| send_file(opened, &tx));
+        drop(file);
+        sent
+    })
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .map_err(|e| ApiError::Text(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+/// Send a file in 64 KiB chunks, stopping early if the receiver went away
+fn send_file(
+    mut file: std::fs::File,
+    tx: &tokio::sync::mpsc::Sender<Result<Vec<u8>, std::io::Error>>,
+) -> std::io::Result<()> {
+    use std::io::Read;
+
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 || tx.blocking_send(Ok(buf[..n].to_vec())).is_err() {
+            return Ok(());
+        }
+    }
+}
+
+/// One CSV record: the header when `row` is `None`, else the row's values in
+/// `columns` order. Nested JSON values are written as JSON text.
+fn csv_line(columns: &[String], row: Option<&serde_json::Value>) -> String {
+    let fields: Vec<String> = columns
+        .iter()
+        .map(|column| {
+            let text = match row.map(|row| &row[column.as_str()]) {
+                None => column.clone(),
+                Some(serde_json::Value::Null) => String::new(),
+                Some(serde_json::Value::String(s)) => s.clone(),
+                Some(other) => other.to_string(),
+            };
+            if text.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", text.replace('"', "\"\""))
+            } else {
+                text
+            }
+        })
+        .collect();
+    format!("{}\r\n", fields.join(","))
+}
+
+fn export_response(format: ExportFormat, created: DateTime<Utc>, body: Body) -> Response {
+    let filename = format!(
+        "livedata-export-{}.{}",
+        created.format("%Y%m%dT%H%M%SZ"),
+        format.extension()
+    );
+    Response::builder()
+        .header(header::CONTENT_TYPE, format.content_type())
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", filename),
+        )
+        .body(body)
+        .unwrap()
+}
+
+fn ndjson_response(body: Body) -> Response {
+    Response::builder()
+        .header(header::CONTENT_TYPE, "application/x-ndjson")
+        .body(body)
+        .unwrap()
+}
+
+/// SQL for one page of an API search, shared by the JSON and streaming endpoints
+struct PreparedSearch {
+    sql: String,
+    params: Vec<SqlParam>,
+    columns: Vec<String>,
+    /// Select expressions behind `columns`
+    select_exprs: Vec<String>,
+    cold_storage: bool,
+    /// FROM source and filter, for counting matches independently of the page
+    source: String,
+    where_sql: String,
+    where_params: Vec<SqlParam>,
+    /// Page key columns are selected after `columns` (timestamp sort)
+    keyed: bool,
+    /// Rows come back in reverse display order (`before` cursor)
+    reversed: bool,
+    /// First page of the results, no cursor or offset given
+    paged_from_start: bool,
+}
+
+/// Estimated counts below this are recounted exactly; sampling is only worth it on
+/// large result sets and is noticeably off on small ones
+const EXACT_COUNT_BELOW: usize = 100_000;
+
+/// Percentage of rows sampled for `count_mode=estimate`
+const COUNT_SAMPLE_PERCENT: usize = 10;
+
+/// Count rows matching `where_sql`, returning the count and whether it is an estimate
+fn count_matches(
+    reader: &PooledReader,
+    source: &str,
+    where_sql: &str,
+    where_params: &[SqlParam],
+    mode: CountMode,
+) -> (usize, bool) {
+    if mode == CountMode::Estimate {
+        let sql = format!(
+            "SELECT COUNT(*) FROM {} WHERE {} USING SAMPLE {} PERCENT (system)",
+            source, where_sql, COUNT_SAMPLE_PERCENT
+        );
+        let estimate = reader.query_usize(&sql, where_params) * (100 / COUNT_SAMPLE_PERCENT);
+        if estimate >= EXACT_COUNT_BELOW {
+            return (estimate, true);
+        }
+    }
+    let sql = format!("SELECT COUNT(*) FROM {} WHERE {}", source, where_sql);
+    (reader.query_usize(&sql, where_params), false)
+}
+
+/// Build the page query for `params`, or `None` while journal_logs has no schema yet
+async fn prepare_search(
+    state: &Arc<AppState>,
+    params: &SearchParams,
+    limit: usize,
+) -> Result<Option<PreparedSearch>, (StatusCode, String)> {
+    let now = Utc::now();
+
+    // Parse time range
+    let start = parse_time(&params.start, now).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let end = parse_time(&params.end, now).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    // Determine which columns to select
+    let schema = state.schema_columns().await;
+    if schema.is_empty() {
+        return Ok(None);
+    }
+
+    let requested_cols: Vec<&str> = if let Some(ref cols) = params.columns
+        && !cols.is_empty()
+    {
+        cols.split(',').map(|s| s.trim()).collect()
+    } else {
+        DEFAULT_COLUMNS.to_vec()
+    };
+    let select_exprs = validate_columns(&requested_cols, &schema);
+    if select_exprs.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "No valid columns specified".into()));
+    }
+
+    // Build display names for the response
+    let columns: Vec<String> = select_exprs
+        .iter()
+        .map(|e| column_display_name(e))
+        .collect();
+
+    // Build SQL query against the journal_logs table
+    let query = parse_search_query(params.q.as_deref(), &schema).await?;
+    let fields = parse_field_filters(&params.filters, &schema)?;
+    let extra = parse_extra_filters(params.extra.as_deref(), &state.promoted_fields())?;
+    let where_clause = build_where_clause(
+        start,
+        end,
+        &query,
+        params.hostname.as_deref(),
+        params.unit.as_deref(),
+        params.source.as_deref(),
+        params.priority,
+        &extra,
+        &fields,
+    );
+    let source = state.log_source(start, end);
+    let mut query_params = where_clause.params.clone();
+    let sort = sort_column(&params.sort);
+    let direction = sort_direction(&params.sort_dir);
+
+    // Timestamp-sorted searches page by (timestamp, __CURSOR) keys instead of OFFSET
+    let keyed = sort == "timestamp";
+    let position = match (&params.after, &params.before) {
+        (Some(_), Some(_)) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "Use either after or before, not both".into(),
+            ));
+        }
+        (Some(cursor), None) => Some((cursor, false)),
+        (None, Some(cursor)) => Some((cursor, true)),
+        (None, None) => None,
+    };
+    let reversed = position.is_some_and(|(_, before)| before);
+    let sql = if keyed {
+        // Pages before the cursor are read in the opposite order, then flipped back
+        let direction = match (direction, reversed) {
+            ("ASC", true) => "DESC",
+            (_, true) => "ASC",
+            (direction, false) => direction,
+        };
+        let mut filter = where_clause.sql.clone();
+        if let Some((cursor, _)) = position {
+            let cursor = PageCursor::decode(cursor)
+                .ok_or_else(|| (StatusCode::BAD_REQUEST, "Invalid page cursor".to_string()))?;
+            let op = if direction == "DESC" { "<" } else { ">" };
+            filter.push_str(&format!(
+                " AND (timestamp {op} make_timestamp(?) OR \
+                 (timestamp = make_timestamp(?) AND COALESCE(__CURSOR, '') {op} ?))"
+            ));
+            query_params.push(SqlParam::BigInt(cursor.timestamp_us));
+            query_params.push(SqlParam::BigInt(cursor.timestamp_us));
+            query_params.push(SqlParam::Text(cursor.cursor));
+        }
+        format!(
+            "SELECT {}, epoch_us(timestamp) AS {}, COALESCE(__CURSOR, '') AS {} FROM {} WHERE {} \
+             ORDER BY timestamp {dir}, COALESCE(__CURSOR, '') {dir} LIMIT ? OFFSET ?",
+            select_exprs.join(", "),
+            PAGE_TS_KEY,
+            PAGE_CURSOR_KEY,
+            source,
+            filter,
+            dir = direction,
+        )
+    } else if position.is_some() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Cursor pagination requires sort=timestamp".into(),
+        ));
+    } else {
+        // Ties are broken by the page key so pages read by offset neither repeat nor
+        // skip rows
+        format!(
+            "SELECT {} FROM {} WHERE {} \
+             ORDER BY {} {dir}, timestamp {dir}, COALESCE(__CURSOR, '') {dir} LIMIT ? OFFSET ?",
+            select_exprs.join(", "),
+            source,
+            where_clause.sql,
+            sort,
+            dir = direction,
+        )
+    };
+    // A cursor already marks the position, so OFFSET only applies without one
+    let offset = if position.is_some() { 0 } else { params.offset };
+    query_params.push(SqlParam::BigInt(limit as i64));
+    query_params.push(SqlParam::BigInt(offset as i64));
+
+    Ok(Some(PreparedSearch {
+        sql,
+        params: query_params,
+        columns,
+        select_exprs,
+        cold_storage: source.cold_storage,
+        source: source.sql,
+        where_sql: where_clause.sql,
+        where_params: where_clause.params,
+        keyed,
+        reversed,
+        paged_from_start: position.is_none() && params.offset == 0,
+    }))
+}
+
+/// Result keys selected alongside the requested columns for keyset pagination
+const PAGE_TS_KEY: &str = "__page_ts";
+const PAGE_CURSOR_KEY: &str = "__page_cursor";
+
+/// Keyset pagination position: the timestamp and journal `__CURSOR` of a result row
+#[derive(Debug, Clone, PartialEq)]
+struct PageCursor {
+    timestamp_us: i64,
+    cursor: String,
+}
+
+impl PageCursor {
+    /// Read the position of a row selected with the page key columns, removing them
+    fn take_from(row: &mut serde_json::Value) -> Option<Self> {
+        let obj = row.as_object_mut()?;
+        let ts = obj.remove(PAGE_TS_KEY)?;
+        let cursor = obj.remove(PAGE_CURSOR_KEY)?;
+        Some(Self {
+            timestamp_us: ts
+                .as_i64()
+                .or_else(|| ts.as_str().and_then(|s| s.parse().ok()))?,
+            cursor: cursor.as_str().unwrap_or_default().to_string(),
+        })
+    }
+
+    /// Opaque, URL-safe form handed to clients
+    fn encode(&self) -> String {
+        format!("{}:{}", self.timestamp_us, self.cursor)
+            .bytes()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    fn decode(encoded: &str) -> Option<Self> {
+        if !encoded.is_ascii() || !encoded.len().is_multiple_of(2) {
+            return None;
+        }
+        let bytes = (0..encoded.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&encoded[i..i + 2], 16).ok())
+            .collect::<Option<Vec<u8>>>()?;
+        let text = String::from_utf8(bytes).ok()?;
+        let (ts, cursor) = text.split_once(':')?;
+        Some(Self {
+            timestamp_us: ts.parse().ok()?,
+            cursor: cursor.to_string(),
+        })
+    }
+}
+
+/// Strip the page keys from keyset results, restore display order, and derive the
+/// cursors of the neighbouring pages
+fn finish_keyset_page(
+    results: &mut [serde_json::Value],
+    reversed: bool,
+    paged_from_start: bool,
+    limit: usize,
+) -> (Option<String>, Option<String>) {
+    if reversed {
+        results.reverse();
+    }
+    let keys: Vec<Option<PageCursor>> = results.iter_mut().map(PageCursor::take_from).collect();
+    let full_page = results.len() == limit;
+    let (has_next, has_prev) = if reversed {
+        (true, full_page)
+    } else {
+        (full_page, !paged_from_start)
+    };
+    let first = keys.first().cloned().flatten();
+    let last = keys.last().cloned().flatten();
+    (
+        last.filter(|_| has_next).map(|c| c.encode()),
+        first.filter(|_| has_prev).map(|c| c.encode()),
+    )
+}
+
+/// API timechart endpoint returning 1-minute bins grouped by log level
+async fn api_timechart(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<TimechartParams>,
+) -> Result<Json<Vec<TimechartBin>>, ApiError> {
+    let now = Utc::now();
+    let start = parse_time(&params.start, now).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let end = parse_time(&params.end, now).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    level_bins(&state, &params, start, end, 60).await.map(Json)
+}
+
+/// Histogram of the timechart filters: counts per log level in bins of `bin` (10s,
+/// 1m, 5m, 1h or 1d). Without `bin`, or when it would produce too many bins for the
+/// range, a bin size is chosen from the range; the response names the one used.
+async fn api_histogram(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<TimechartParams>,
+    Query(histogram): Query<HistogramParams>,
+) -> Result<Json<HistogramResponse>, ApiError> {
+    let now = Utc::now();
+    let start = parse_time(&params.start, now).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let end = parse_time(&params.end, now).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let (bin, bin_seconds) = histogram_bin(histogram.bin.as_deref(), end - start)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let bins = level_bins(&state, &params, start, end, bin_seconds).await?;
+    Ok(Json(HistogramResponse {
+        bin: bin.to_string(),
+        bin_seconds,
+        bins,
+    }))
+}
+
+/// Bin sizes offered by /api/histogram, smallest first
+const HISTOGRAM_BINS: &[(&str, i64)] = &[
+    ("10s", 10),
+    ("1m", 60),
+    ("5m", 300),
+    ("1h", 3600),
+    ("1d", 86400),
+];
+
+/// Bins an automatically sized histogram aims to stay under
+const AUTO_HISTOGRAM_BINS: i64 = 300;
+
+/// Most bins a histogram may have; larger requested ranges get coarser bins
+const MAX_HISTOGRAM_BINS: i64 = 2_000;
+
+/// Name and length in seconds of the bins for a histogram over `range`: the
+/// requested size unless it needs more than [`MAX_HISTOGRAM_BINS`], otherwise the
+/// smallest size that fits
+fn histogram_bin(requested: Option<&str>, range: Duration) -> Result<(&'static str, i64), String> {
+    let (smallest, max_bins) = match requested.map(str::trim) {
+        None | Some("") | Some("auto") => (0, AUTO_HISTOGRAM_BINS),
+        Some(name) => {
+            let index = HISTOGRAM_BINS
+                .iter()
+                .position(|(bin, _)| *bin == name)
+                .ok_or_else(|| {
+                    let names: Vec<&str> = HISTOGRAM_BINS.iter().map(|(bin, _)| *bin).collect();
+                    format!("Unknown bin {}; use one of {}", name, names.join(", "))
+                })?;
+            (index, MAX_HISTOGRAM_BINS)
+        }
+    };
+    let range_secs = range.num_seconds().max(0);
+    let fits = |(_, secs): &&(&str, i64)| range_secs / secs <= max_bins;
+    let (name, secs) = HISTOGRAM_BINS[smallest..]
+        .iter()
+        .find(fits)
+        .or(HISTOGRAM_BINS.last())
+        .copied()
+        .unwrap_or(("1m", 60));
+    Ok((name, secs))
+}
+
+/// Entry counts per log level in bins of `bin_secs` between `start` and `end`
+async fn level_bins(
+    state: &Arc<AppState>,
+    params: &TimechartParams,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    bin_secs: i64,
+) -> Result<Vec<TimechartBin>, ApiError> {
+    let schema = state.schema_columns().await;
+    if schema.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let query = parse_search_query(params.q.as_deref(), &schema).await?;
+    let extra = parse_extra_filters(params.extra.as_deref(), &state.promoted_fields())?;
+    let source = state.log_source(start, end);
+
+    // Without query, source or extra field filters the per-minute rollup answers the
+    // query, as long as the range stays within the hot partitions it covers and bins are
+    // whole minutes. The first minute is then not cut short at `start`.
+    let use_rollup = query.is_empty()
+        && params.source.as_deref().is_none_or(str::is_empty)
+        && extra.is_empty()
+        && !source.cold_storage
+        && bin_secs % 60 == 0;
+    let chart_start = if use_rollup {
+        start.duration_trunc(Duration::minutes(1)).unwrap_or(start)
+    } else {
+        start
+    };
+    let where_clause = build_where_clause(
+        chart_start,
+        end,
+        &query,
+        params.hostname.as_deref(),
+        params.unit.as_deref(),
+        params.source.as_deref(),
+        params.priority,
+        &extra,
+        &[],
+    );
+
+    let sql = if use_rollup {
+        format!(
+            "SELECT CAST(to_timestamp(floor(epoch(timestamp) / {bin}) * {bin}) AS VARCHAR) AS time_bin,
+                    COALESCE(priority, 6) AS priority,
+                    CAST(SUM(count) AS BIGINT) AS count
+             FROM {}
+             WHERE {}
+             GROUP BY 1, 2
+             ORDER BY 1 ASC, 2 ASC",
+            LOG_COUNTS_TABLE,
+            where_clause.sql,
+            bin = bin_secs,
+        )
+    } else {
+        format!(
+            "SELECT CAST(to_timestamp(floor(epoch(timestamp) / {bin}) * {bin}) AS VARCHAR) AS time_bin,
+                    COALESCE(TRY_CAST(priority AS INTEGER), 6) AS priority,
+                    COUNT(*) AS count
+             FROM {}
+             WHERE {}
+             GROUP BY 1, 2
+             ORDER BY 1 ASC, 2 ASC",
+            source,
+            where_clause.sql,
+            bin = bin_secs,
+        )
+    };
+
+    let display_names = vec![
+        "time_bin".to_string(),
+        "priority".to_string(),
+        "count".to_string(),
+    ];
+    let partial = serde_json::json!({ "cold_storage": source.cold_storage });
+    let rows = run_query(state, "timechart", partial, move |reader| {
+        reader.query_json_rows(&sql, &where_clause.params, &display_names)
+    })
+    .await?;
+
+    let bins = rows
+        .into_iter()
+        .filter_map(|row| {
+            let obj = row.as_object()?;
+            let time_bin = obj.get("time_bin")?.as_str()?.to_string();
+            let priority = obj.get("priority").and_then(|v| {
+                v.as_i64()
+                    .or_else(|| v.as_str().and_then(|s| s.parse::<i64>().ok()))
+            })?;
+            let count = obj.get("count").and_then(|v| {
+                v.as_i64()
+                    .or_else(|| v.as_str().and_then(|s| s.parse::<i64>().ok()))
+            })?;
+
+            Some(TimechartBin {
+                time_bin,
+                level: priority_level_name(priority as i32).to_string(),
+                count,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    Ok(bins)
+}
+
+/// API filters endpoint returning available filter values
+async fn api_filters(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Response, (StatusCode, String)> {
+    let (hostnames, units, sources) = state
+        .with_reader(|reader| {
+            // Get distinct hostnames
+            let hostnames = reader.query_distinct_strings(
+                "SELECT DISTINCT _hostname FROM journal_logs WHERE _hostname IS NOT NULL \
+                 ORDER BY _hostname",
+                &[],
+            );
+
+            // Get distinct units
+            let units = reader.query_distinct_strings(
+                "SELECT DISTINCT _systemd_unit FROM journal_logs WHERE _systemd_unit IS NOT NULL \
+                 ORDER BY _systemd_unit",
+                &[],
+            );
+
+            // Get distinct sources
+            let sources = reader.query_distinct_strings(
+                &format!(
+                    "SELECT DISTINCT COALESCE(source, '{}') AS source FROM journal_logs \
+                     ORDER BY source",
+                    LOCAL_SOURCE
+                ),
+                &[],
+            );
+            (hostnames, units, sources)
+        })
+        .await;
+
+    // Static priority options
+    let priorities: Vec<PriorityOption> = (0..=7)
+        .map(|p| PriorityOption {
+            value: p,
+            label: format!("{} - {}", p, priority_label(p)),
+        })
+        .collect();
+
+    Ok(cached_json(
+        &headers,
+        &FilterValues {
+            hostnames,
+            units,
+            sources,
+            priorities,
+        },
+    ))
+}
+
+/// Cache-Control of JSON that changes rarely (columns, filter values): reused for a
+/// minute, then revalidated by ETag. Private because the web server may require auth.
+const SLOW_CHANGING_CACHE_CONTROL: &str = "private, max-age=60";
+
+/// Most frequent distinct values of any column over a time range (default the last
+/// day), optionally only those starting with `prefix`, for filter autocomplete
+async fn api_values(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(params): Query<ValuesParams>,
+) -> Result<Response, ApiError> {
+    let now = Utc::now();
+    let start = parse_time(&params.start, now).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let end = parse_time(&params.end, now).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let schema = state.schema_columns().await;
+    if schema.is_empty() {
+        return Ok(cached_json(
+            &headers,
+            &ColumnValues {
+                column: params.column,
+                values: Vec::new(),
+            },
+        ));
+    }
+    let column = group_column(&params.column, &schema).ok_or_else(|| {
+        (
+            StatusCode::BAD_REQUEST,
+            format!("Unknown column '{}'", params.column),
+        )
+    })?;
+
+    let mut where_clause = build_where_clause(
+        start,
+        end,
+        &SearchQuery::default(),
+        None,
+        None,
+        None,
+        None,
+        &[],
+        &[],
+    );
+    where_clause
+        .sql
+        .push_str(&format!(" AND {} IS NOT NULL", quote_ident(&column)));
+    if let Some(prefix) = params.prefix.as_deref().filter(|p| !p.is_empty()) {
+        where_clause.sql.push_str(&format!(
+            " AND CAST({} AS VARCHAR) ILIKE ? ESCAPE '\\'",
+            quote_ident(&column)
+        ));
+        where_clause
+            .params
+            .push(SqlParam::Text(format!("{}%", escape_like(prefix))));
+    }
+    let source = state.log_source(start, end);
+    let display_name = column_display_name(&column);
+    let limit = params.limit.clamp(1, 1000);
+    let columns = vec![(display_name.clone(), column)];
+    let partial = serde_json::json!({ "column": display_name });
+    let mut counts = run_query(&state, "values", partial, move |reader| {
+        facet_counts(
+            reader,
+            &source.sql,
+            &where_clause.sql,
+            &where_clause.params,
+            &columns,
+            limit,
+        )
+    })
+    .await?;
+
+    Ok(cached_json(
+        &headers,
+        &ColumnValues {
+            values: counts.remove(&display_name).unwrap_or_default(),
+            column: display_name,
+        },
+    ))
+}
+
+/// JSON response carrying an ETag and [`SLOW_CHANGING_CACHE_CONTROL`], or 304 when
+/// the client already has it
+fn cached_json<T: Serialize>(headers: &HeaderMap, value: &T) -> Response {
+    let body = serde_json::to_vec(value).unwrap_or_default();
+    let response = ([(header::CONTENT_TYPE, "application/json")], body.clone());
+    with_etag(headers, SLOW_CHANGING_CACHE_CONTROL, response, &body)
+}
+
+/// Add an ETag derived from `body` and `cache_control` to `response`, replacing it
+/// with 304 Not Modified when the request's If-None-Match already names that ETag
+fn with_etag(
+    headers: &HeaderMap,
+    cache_control: &'static str,
+    response: impl IntoResponse,
+    body: &[u8],
+) -> Response {
+    use std::hash::{DefaultHasher, Hash, Hasher};
+
+    let mut hasher = DefaultHasher::new();
+    body.hash(&mut hasher);
+    let etag = format!("\"{:016x}\"", hasher.finish());
+    let mut response = if etag_matches(headers, &etag) {
+        StatusCode::NOT_MODIFIED.into_response()
+    } else {
+        response.into_response()
+    };
+    let response_headers = response.headers_mut();
+    response_headers.insert(header::ETAG, HeaderValue::from_str(&etag).unwrap());
+    response_headers.insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_static(cache_control),
+    );
+    response
+}
+
+/// Whether the request's If-None-Match names `etag`
+fn etag_matches(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|tags| {
+            tags.split(',')
+                .any(|tag| tag.trim().trim_start_matches("W/") == etag || tag.trim() == "*")
+        })
+}
+
+/// Reuse the response to an identical earlier request while it is younger than
+/// `query_cache_ttl_secs` and no entry has been stored or deleted since. Only complete
+/// 200 responses are kept.
+async fn cache_query(
+    State(state): State<Arc<AppState>>,
+    request: axum::extract::Request,
+    next: Next,
+) -> Response {
+    let Some(cache) = &state.query_cache else {
+        return next.run(request).await;
+    };
+    let accept = request
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("");
+    let key = format!("{} {}", request.uri(), accept);
+    let version = state.metrics.data_version();
+    let now = std::time::Instant::now();
+
+    if let Some(cached) = cache.get(&key, version, now) {
+        let etag = cached
+            .headers
+            .get(header::ETAG)
+            .and_then(|value| value.to_str().ok());
+        let mut response = if etag.is_some_and(|etag| etag_matches(request.headers(), etag)) {
+            StatusCode::NOT_MODIFIED.into_response()
+        } else {
+            Response::new(Body::from(cached.body))
+        };
+        *response.headers_mut() = cached.headers;
+        return response;
+    }
+
+    let response = next.run(request).await;
+    let cacheable = response.status() == StatusCode::OK
+        && response
+            .body()
+            .size_hint()
+            .exact()
+            .is_some_and(|len| len <= MAX_CACHED_RESPONSE_BYTES);
+    if !cacheable {
+        return response;
+    }
+    let (parts, body) = response.into_parts();
+    let Ok(body) = axum::body::to_bytes(body, MAX_CACHED_RESPONSE_BYTES as usize).await else {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+    cache.insert(
+        key,
+        version,
+        CachedResponse {
+            headers: parts.headers.clone(),
+            body: body.clone(),
+        },
+        now,
+    );
+    Response::from_parts(parts, Body::from(body))
+}
+
+/// Main search UI (HTML)
+async fn search_ui(State(state): State<Arc<AppState>>, params: SearchParams) -> impl IntoResponse {
+    let (results, display_names, total_count) =
+        query_log_results(&state, &params).await.unwrap_or_default();
+
+    // Get filter options
+    let (hostnames, units) = state
+        .with_reader(|reader| {
+            let hostnames = reader.query_distinct_strings(
+                "SELECT DISTINCT _hostname FROM journal_logs WHERE _hostname IS NOT NULL \
+                 ORDER BY _hostname",
+                &[],
+            );
+            let units = reader.query_distinct_strings(
+                "SELECT DISTINCT _systemd_unit FROM journal_logs WHERE _systemd_unit IS NOT NULL \
+                 ORDER BY _systemd_unit",
+                &[],
+            );
+            (hostnames, units)
+        })
+        .await;
+
+    let html = build_search_html(
+        &params,
+        &results,
+        &display_names,
+        total_count,
+        &hostnames,
+        &units,
+    );
+
+    Html(html)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn build_search_html(
+    params: &SearchParams,
+    results: &[serde_json::Value],
+    display_names: &[String],
+    total_count: usize,
+    hostnames: &[String],
+    units: &[String],
+) -> String {
+    let query_value = params.q.as_deref().unwrap_or("");
+    let hostname_value = params.hostname.as_deref().unwrap_or("");
+    let unit_value = params.unit.as_deref().unwrap_or("");
+    let priority_value = params.priority;
+
+    // Build hostname options
+    let hostname_options: String = hostnames
+        .iter()
+        .map(|h| {
+            let selected = if hostname_value == h { " selected" } else { "" };
+            format!(
+                "<option value=\"{}\"{}>{}</option>",
+                html_escape(h),
+                selected,
+                html_escape(h)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    // Build unit options
+    let unit_options: String = units
+        .iter()
+        .map(|u| {
+            let selected = if unit_value == u { " selected" } else { "" };
+            format!(
+                "<option value=\"{}\"{}>{}</option>",
+                html_escape(u),
+                selected,
+                html_escape(u)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    // Build priority options
+    let priority_options: String = (0..=7)
+        .map(|p| {
+            let selected = if priority_value == Some(p) {
+                " selected"
+            } else {
+                ""
+            };
+            format!(
+                "<option value=\"{}\"{}>{} - {}</option>",
+                p,
+                selected,
+                p,
+                priority_label(p)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let chunk_fragment = render_log_chunk_fragment(params, results, display_names, total_count);
+
+    format!(
+        r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>Livedata - Log Search</title>
+    <script src="https://unpkg.com/htmx.org@1.9.12"></script>
+    <script src="https://cdn.jsdelivr.net/npm/d3@7"></script>
+    <style>
+        :root {{
+            --bg: #272822;
+            --surface: #2d2e27;
+            --surface-alt: #3e3d32;
+            --text: #f8f8f2;
+            --muted: #a59f85;
+            --border: #49483e;
+            --accent: #a6e22e;
+            --accent-2: #66d9ef;
+            --warn: #fd971f;
+            --danger: #f92672;
+        }}
+        body.theme-light {{
+            --bg: #f8f8f2;
+            --surface: #efefe7;
+            --surface-alt: #ffffff;
+            --text: #272822;
+            --muted: #6f6b57;
+            --border: #b7b39e;
+            --accent: #3b7d15;
+            --accent-2: #0f8395;
+            --warn: #c15d00;
+            --danger: #c2175b;
+        }}
+        * {{
+            box-sizing: border-box;
+            margin: 0;
+            padding: 0;
+        }}
+        body {{
+            font-family: -apple-system, BlinkMacSystemFont, "Segoe UI", Roboto, "Helvetica Neue", Arial, sans-serif;
+            background-color: var(--bg);
+            color: var(--text);
+            line-height: 1.5;
+        }}
+        .global-header {{
+            background-color: var(--surface);
+            border-bottom: 2px solid var(--accent);
+            margin-bottom: 0;
+        }}
+        .global-header nav {{
+            display: flex;
+            gap: 5px;
+            padding: 12px 20px;
+            max-width: 100%;
+        }}
+        .global-header nav a {{
+            color: var(--accent-2);
+            text-decoration: none;
+            font-size: 15px;
+            font-weight: 500;
+            padding: 8px 15px;
+            border-radius: 4px;
+            transition: background-color 0.2s;
+        }}
+        .global-header nav a:hover {{
+            background-color: var(--surface-alt);
+        }}
+        .global-header nav a.active {{
+            background-color: var(--accent);
+            color: var(--bg);
+        }}
+        .container {{
+            max-width: 100%;
+            padding: 20px;
+        }}
+        #storage-health {{
+            background-color: var(--surface);
+            padding: 12px 20px;
+            border-radius: 8px;
+            margin-bottom: 20px;
+            font-size: 0.9rem;
+            border: 1px solid var(--border);
+        }}
+        #storage-health .health-item {{
+            display: inline-block;
+            margin-right: 25px;
+        }}
+        #storage-health .health-label {{
+            color: var(--muted);
+            margin-right: 5px;
+        }}
+        #storage-health .status-good {{ color: var(--accent); }}
         #storage-health .status-warning {{ color: var(--warn); }}
         #storage-health .status-critical {{ color: var(--danger); }}
         header {{
@@ -1333,6 +6132,10 @@ fn build_search_html(
             color: var(--muted);
             font-size: 0.8rem;
         }}
+        .timechart-subtitle select {{
+            margin-left: 6px;
+            font: inherit;
+        }}
         #timechart {{
             width: 100%;
             min-height: 260px;
@@ -1453,1089 +6256,4164 @@ fn build_search_html(
             gap: 4px 12px;
             padding: 8px 16px 12px;
         }}
-        .column-chooser-grid label {{
-            display: flex;
-            align-items: center;
-            gap: 6px;
-            font-size: 0.85rem;
-            color: var(--text);
-            cursor: pointer;
-            padding: 3px 0;
+        .column-chooser-grid label {{
+            display: flex;
+            align-items: center;
+            gap: 6px;
+            font-size: 0.85rem;
+            color: var(--text);
+            cursor: pointer;
+            padding: 3px 0;
+        }}
+        .column-chooser-grid input[type="checkbox"] {{
+            accent-color: var(--accent);
+        }}
+        .column-chooser-grid .col-type {{
+            color: var(--muted);
+            font-size: 0.75rem;
+        }}
+        @media (max-width: 768px) {{
+            .search-row {{
+                flex-direction: column;
+            }}
+            .form-group.search-input {{
+                min-width: 100%;
+            }}
+            .time-presets {{
+                flex-wrap: wrap;
+            }}
+        }}
+    </style>
+</head>
+<body class="theme-dark">
+    <button id="theme-toggle" class="theme-toggle" type="button" aria-label="Toggle theme">&#9680;</button>
+    <div class="global-header">
+        <nav>
+            <a href="/" target="_blank" class="active">Log Search</a>
+            <a href="/processes.html" target="_blank">Processes</a>
+        </nav>
+    </div>
+    <div class="container">
+        <div id="storage-health">
+            <span class="health-item">
+                <span class="health-label">Storage:</span>
+                <span id="storage-info">Loading...</span>
+            </span>
+            <span class="health-item">
+                <span class="health-label">Retention:</span>
+                <span id="retention-info">Loading...</span>
+            </span>
+        </div>
+        <header>
+            <h1>Livedata Log Search</h1>
+        </header>
+        <form class="search-form" method="get" action="/">
+            <div class="search-row">
+                <div class="form-group search-input">
+                    <label for="q">Search <span class="keyboard-hint">(Press / to focus)</span></label>
+                    <input type="text" id="q" name="q" value="{}" placeholder="Search messages, or unit:nginx.service priority<=3 -host:db1 ...">
+                </div>
+                <div class="form-group">
+                    <label>&nbsp;</label>
+                    <button type="submit">Search</button>
+                </div>
+            </div>
+            <div class="search-row">
+                <div class="form-group">
+                    <label for="start">Start Time</label>
+                    <input type="text" id="start" name="start" value="{}" placeholder="-1h">
+                </div>
+                <div class="form-group">
+                    <label for="end">End Time</label>
+                    <input type="text" id="end" name="end" value="{}" placeholder="now">
+                </div>
+                <div class="time-presets">
+                    <button type="button" class="time-preset" onclick="setTimeRange('-15m', 'now')">15m</button>
+                    <button type="button" class="time-preset" onclick="setTimeRange('-1h', 'now')">1h</button>
+                    <button type="button" class="time-preset" onclick="setTimeRange('-4h', 'now')">4h</button>
+                    <button type="button" class="time-preset" onclick="setTimeRange('-24h', 'now')">24h</button>
+                    <button type="button" class="time-preset" onclick="setTimeRange('-7d', 'now')">7d</button>
+                </div>
+            </div>
+            <div class="search-row">
+                <div class="form-group">
+                    <label for="hostname">Hostname</label>
+                    <select id="hostname" name="hostname">
+                        <option value="">All Hosts</option>
+                        {}
+                    </select>
+                </div>
+                <div class="form-group">
+                    <label for="unit">Systemd Unit</label>
+                    <select id="unit" name="unit">
+                        <option value="">All Units</option>
+                        {}
+                    </select>
+                </div>
+                <div class="form-group">
+                    <label for="priority">Max Priority</label>
+                    <select id="priority" name="priority">
+                        <option value="">All Priorities</option>
+                        {}
+                    </select>
+                </div>
+            </div>
+            <input type="hidden" name="limit" value="{}">
+            <input type="hidden" name="offset" value="0">
+            <input type="hidden" name="columns" value="{}">
+        </form>
+
+        <details class="column-chooser" id="column-chooser">
+            <summary>Columns</summary>
+            <div class="column-chooser-grid" id="column-checkboxes">Loading columns...</div>
+        </details>
+
+        <section class="timechart-panel" aria-label="Log level timechart">
+            <div class="timechart-header">
+                <div class="timechart-title">Timechart</div>
+                <div class="timechart-subtitle">
+                    <span id="timechart-bin-label">1m</span> bins by log level
+                    <select id="timechart-bin" aria-label="Bin size">
+                        <option value="auto" selected>auto</option>
+                        <option value="10s">10s</option>
+                        <option value="1m">1m</option>
+                        <option value="5m">5m</option>
+                        <option value="1h">1h</option>
+                        <option value="1d">1d</option>
+                    </select>
+                </div>
+            </div>
+            <div id="timechart"></div>
+        </section>
+
+        <div class="summary">
+            Showing {} loaded rows out of {} total
+        </div>
+
+        <div class="results-table-wrap">
+            <table class="results-table">
+                <thead>
+                    <tr>{}</tr>
+                </thead>
+                <tbody id="log-rows">
+                    {}
+                </tbody>
+            </table>
+        </div>
+    </div>
+
+    <script>
+        // Focus search on / key
+        document.addEventListener('keydown', function(e) {{
+            if (e.key === '/' && document.activeElement.tagName !== 'INPUT') {{
+                e.preventDefault();
+                document.getElementById('q').focus();
+            }}
+        }});
+
+        // Time preset buttons
+        function setTimeRange(start, end) {{
+            document.getElementById('start').value = start;
+            document.getElementById('end').value = end;
+            document.querySelector('form').submit();
+        }}
+        window.setTimeRange = setTimeRange;
+
+        const TIMECHART_LEVELS = ['Emergency', 'Alert', 'Critical', 'Error', 'Warning', 'Notice', 'Info', 'Debug'];
+        const TIMECHART_COLORS = {{
+            Emergency: '#7f0000',
+            Alert: '#b10026',
+            Critical: '#d7301f',
+            Error: '#ef6548',
+            Warning: '#fc8d59',
+            Notice: '#fdbb84',
+            Info: '#74add1',
+            Debug: '#4575b4'
+        }};
+        let cachedTimechartData = [];
+        let cachedBinSeconds = 60;
+
+        function getTimechartQueryParams() {{
+            const form = document.querySelector('.search-form');
+            const formData = new FormData(form);
+            const params = new URLSearchParams();
+            ['q', 'start', 'end', 'hostname', 'unit', 'priority'].forEach((key) => {{
+                const value = String(formData.get(key) || '').trim();
+                if (value !== '') params.set(key, value);
+            }});
+            return params;
         }}
-        .column-chooser-grid input[type="checkbox"] {{
-            accent-color: var(--accent);
+
+        async function loadTimechart() {{
+            const chartEl = document.getElementById('timechart');
+            if (typeof d3 === 'undefined') {{
+                chartEl.innerHTML = '<div class="timechart-empty">Failed to load D3</div>';
+                return;
+            }}
+
+            chartEl.innerHTML = '<div class="timechart-empty">Loading timechart...</div>';
+            try {{
+                const params = getTimechartQueryParams();
+                params.set('bin', document.getElementById('timechart-bin').value);
+                const response = await fetch(`/api/histogram?${{params.toString()}}`);
+                if (!response.ok) throw new Error('Failed to fetch timechart data');
+                const histogram = await response.json();
+                document.getElementById('timechart-bin-label').textContent = histogram.bin;
+                cachedTimechartData = histogram.bins;
+                cachedBinSeconds = histogram.bin_seconds;
+                renderTimechart(histogram.bins);
+            }} catch (error) {{
+                console.error('Failed to load timechart:', error);
+                chartEl.innerHTML = '<div class="timechart-empty">Unable to load timechart</div>';
+            }}
         }}
-        .column-chooser-grid .col-type {{
-            color: var(--muted);
-            font-size: 0.75rem;
+
+        function renderTimechart(rows) {{
+            const chartEl = document.getElementById('timechart');
+            chartEl.innerHTML = '';
+
+            if (!Array.isArray(rows) || rows.length === 0) {{
+                chartEl.innerHTML = '<div class="timechart-empty">No matching log events for this range</div>';
+                return;
+            }}
+
+            const binsByTime = new Map();
+            rows.forEach((row) => {{
+                const ts = new Date(row.time_bin);
+                if (Number.isNaN(ts.getTime())) return;
+                const key = ts.toISOString();
+                if (!binsByTime.has(key)) {{
+                    const seeded = {{ time: ts }};
+                    TIMECHART_LEVELS.forEach((level) => {{
+                        seeded[level] = 0;
+                    }});
+                    binsByTime.set(key, seeded);
+                }}
+                const bin = binsByTime.get(key);
+                const level = TIMECHART_LEVELS.includes(row.level) ? row.level : 'Info';
+                const count = Number(row.count) || 0;
+                bin[level] += count;
+            }});
+
+            const data = Array.from(binsByTime.values()).sort((a, b) => a.time - b.time);
+            if (data.length === 0) {{
+                chartEl.innerHTML = '<div class="timechart-empty">No matching log events for this range</div>';
+                return;
+            }}
+
+            const styles = getComputedStyle(document.body);
+            const textColor = styles.getPropertyValue('--text').trim();
+            const borderColor = styles.getPropertyValue('--border').trim();
+            const width = Math.max(chartEl.clientWidth, 320);
+            const height = 280;
+            const margin = {{ top: 12, right: 10, bottom: 38, left: 50 }};
+
+            const x = d3.scaleBand()
+                .domain(data.map((d) => d.time))
+                .range([margin.left, width - margin.right])
+                .paddingInner(0.1);
+
+            const yMax = d3.max(data, (d) => d3.sum(TIMECHART_LEVELS, (k) => d[k])) || 0;
+            const y = d3.scaleLinear()
+                .domain([0, yMax > 0 ? yMax : 1])
+                .nice()
+                .range([height - margin.bottom, margin.top]);
+
+            const stack = d3.stack().keys(TIMECHART_LEVELS);
+            const series = stack(data);
+
+            const svg = d3.create('svg')
+                .attr('viewBox', `0 0 ${{width}} ${{height}}`)
+                .attr('width', width)
+                .attr('height', height)
+                .attr('role', 'img')
+                .attr('aria-label', `Stacked bar chart of log counts by level and ${{cachedBinSeconds}}-second time bin`);
+
+            svg.append('g')
+                .attr('stroke', borderColor)
+                .attr('stroke-opacity', 0.6)
+                .selectAll('line')
+                .data(y.ticks(4))
+                .join('line')
+                .attr('x1', margin.left)
+                .attr('x2', width - margin.right)
+                .attr('y1', (d) => y(d))
+                .attr('y2', (d) => y(d));
+
+            const levelGroups = svg.append('g')
+                .selectAll('g')
+                .data(series)
+                .join('g')
+                .attr('fill', (d) => TIMECHART_COLORS[d.key] || TIMECHART_COLORS.Info);
+
+            levelGroups.selectAll('rect')
+                .data((d) => d.map((v) => Object.assign(v, {{ level: d.key }})))
+                .join('rect')
+                .attr('x', (d) => x(d.data.time) || 0)
+                .attr('y', (d) => y(d[1]))
+                .attr('height', (d) => Math.max(0, y(d[0]) - y(d[1])))
+                .attr('width', x.bandwidth())
+                .append('title')
+                .text((d) => `${{d.level}}: ${{d.data[d.level]}} @ ${{d3.timeFormat('%Y-%m-%d %H:%M:%S')(d.data.time)}}`);
+
+            const tickEvery = Math.max(1, Math.ceil(data.length / 12));
+            svg.append('g')
+                .attr('transform', `translate(0,${{height - margin.bottom}})`)
+                .call(
+                    d3.axisBottom(x)
+                        .tickValues(data.filter((_, i) => i % tickEvery === 0).map((d) => d.time))
+                        .tickFormat(d3.timeFormat(
+                            cachedBinSeconds >= 86400 ? '%Y-%m-%d'
+                                : cachedBinSeconds >= 3600 ? '%m-%d %H:%M'
+                                : cachedBinSeconds < 60 ? '%H:%M:%S' : '%H:%M'
+                        ))
+                )
+                .call((g) => g.selectAll('text').attr('fill', textColor))
+                .call((g) => g.selectAll('line,path').attr('stroke', borderColor));
+
+            svg.append('g')
+                .attr('transform', `translate(${{margin.left}},0)`)
+                .call(d3.axisLeft(y).ticks(4).tickFormat(d3.format('d')))
+                .call((g) => g.selectAll('text').attr('fill', textColor))
+                .call((g) => g.selectAll('line,path').attr('stroke', borderColor));
+
+            chartEl.append(svg.node());
         }}
-        @media (max-width: 768px) {{
-            .search-row {{
-                flex-direction: column;
+
+        window.addEventListener('resize', () => {{
+            if (cachedTimechartData.length > 0) renderTimechart(cachedTimechartData);
+        }});
+
+        document.getElementById('timechart-bin').addEventListener('change', loadTimechart);
+        loadTimechart();
+
+        // Theme toggle (dark default)
+        (function() {{
+            const key = 'livedata-theme';
+            const body = document.body;
+            const btn = document.getElementById('theme-toggle');
+            const iconDark = '&#9680;';
+            const iconLight = '&#9681;';
+
+            function apply(theme) {{
+                body.classList.remove('theme-dark', 'theme-light');
+                body.classList.add(theme === 'light' ? 'theme-light' : 'theme-dark');
+                btn.innerHTML = theme === 'light' ? iconLight : iconDark;
+            }}
+
+            const saved = localStorage.getItem(key);
+            apply(saved === 'light' ? 'light' : 'dark');
+
+            btn.addEventListener('click', function() {{
+                const next = body.classList.contains('theme-dark') ? 'light' : 'dark';
+                localStorage.setItem(key, next);
+                apply(next);
+                if (cachedTimechartData.length > 0) renderTimechart(cachedTimechartData);
+            }});
+        }})();
+
+        // Storage health indicator
+        (async function() {{
+            async function updateStorageHealth() {{
+                try {{
+                    const response = await fetch('/api/storage/health');
+                    if (!response.ok) throw new Error('Failed to fetch storage health');
+
+                    const data = await response.json();
+
+                    // Calculate storage usage percentage
+                    const sizeGB = (data.database_size_bytes / (1024 * 1024 * 1024)).toFixed(2);
+                    const maxSizeGB = Math.max(data.retention_policy.log_max_size_gb, data.retention_policy.process_max_size_gb);
+                    const usagePercent = (parseFloat(sizeGB) / maxSizeGB) * 100;
+
+                    // Determine status color
+                    let statusClass = 'status-good';
+                    if (usagePercent >= 90) statusClass = 'status-critical';
+                    else if (usagePercent >= 75) statusClass = 'status-warning';
+
+                    // Update storage info
+                    document.getElementById('storage-info').innerHTML =
+                        `<span class="${{statusClass}}">${{sizeGB}}GB / ${{maxSizeGB}}GB</span> (${{data.journal_log_count.toLocaleString()}} logs, ${{data.process_metric_count.toLocaleString()}} metrics)`;
+
+                    // Update retention info
+                    document.getElementById('retention-info').textContent =
+                        `${{data.retention_policy.log_retention_days}}d logs / ${{data.retention_policy.process_retention_days}}d proc`;
+
+                }} catch (error) {{
+                    console.error('Failed to fetch storage health:', error);
+                    document.getElementById('storage-info').innerHTML = '<span class="status-critical">Error loading</span>';
+                    document.getElementById('retention-info').textContent = 'N/A';
+                }}
             }}
-            .form-group.search-input {{
-                min-width: 100%;
+
+            // Update on load
+            await updateStorageHealth();
+
+            // Refresh every 30 seconds
+            setInterval(updateStorageHealth, 30000);
+        }})();
+
+        // Column chooser
+        (async function() {{
+            const STORAGE_KEY = 'livedata-columns';
+            const columnsInput = document.querySelector('input[name="columns"]');
+            const grid = document.getElementById('column-checkboxes');
+
+            // Load saved selection from localStorage (or use current columns param)
+            let savedCols = null;
+            try {{
+                const stored = localStorage.getItem(STORAGE_KEY);
+                if (stored) savedCols = JSON.parse(stored);
+            }} catch(_) {{}}
+
+            // If columns were passed in the URL, use those instead
+            const urlCols = columnsInput.value.trim();
+            if (urlCols) {{
+                savedCols = urlCols.split(',').map(s => s.trim()).filter(Boolean);
             }}
-            .time-presets {{
-                flex-wrap: wrap;
+
+            try {{
+                const response = await fetch('/api/columns');
+                if (!response.ok) throw new Error('Failed to fetch columns');
+                const columns = await response.json();
+
+                // Determine which columns are checked
+                const activeSet = savedCols
+                    ? new Set(savedCols)
+                    : new Set(columns.filter(c => c.default).map(c => c.name));
+
+                grid.innerHTML = '';
+                columns.forEach(col => {{
+                    const label = document.createElement('label');
+                    const cb = document.createElement('input');
+                    cb.type = 'checkbox';
+                    cb.value = col.name;
+                    cb.checked = activeSet.has(col.name);
+                    cb.addEventListener('change', onColumnChange);
+
+                    const nameSpan = document.createElement('span');
+                    nameSpan.textContent = col.name;
+
+                    const typeSpan = document.createElement('span');
+                    typeSpan.className = 'col-type';
+                    typeSpan.textContent = col.column_type;
+
+                    label.appendChild(cb);
+                    label.appendChild(nameSpan);
+                    label.appendChild(typeSpan);
+                    grid.appendChild(label);
+                }});
+
+                // Sync hidden input with current selection
+                syncColumns();
+            }} catch (error) {{
+                console.error('Failed to load columns:', error);
+                grid.textContent = 'Failed to load columns';
             }}
-        }}
+
+            function getSelectedColumns() {{
+                return Array.from(grid.querySelectorAll('input[type="checkbox"]:checked'))
+                    .map(cb => cb.value);
+            }}
+
+            function syncColumns() {{
+                const selected = getSelectedColumns();
+                columnsInput.value = selected.join(',');
+                try {{
+                    localStorage.setItem(STORAGE_KEY, JSON.stringify(selected));
+                }} catch(_) {{}}
+            }}
+
+            function onColumnChange() {{
+                syncColumns();
+                // Re-submit the search form to apply column changes
+                document.querySelector('form.search-form').submit();
+            }}
+        }})();
+    </script>
+</body>
+</html>"##,
+        html_escape(query_value),                // {0} search input value
+        html_escape(&params.start),              // {1} start time
+        html_escape(&params.end),                // {2} end time
+        hostname_options,                        // {3} hostname options
+        unit_options,                            // {4} unit options
+        priority_options,                        // {5} priority options
+        params.limit.min(100_000),               // {6} limit
+        params.columns.as_deref().unwrap_or(""), // {7} columns hidden input
+        results.len(),                           // {8} loaded row count
+        total_count,                             // {9} total count
+        display_names
+            .iter()
+            .map(|name| format!("<th>{}</th>", html_escape(name)))
+            .collect::<Vec<_>>()
+            .join(""), // {10} table headers
+        chunk_fragment,                          // {11} rows fragment
+    )
+}
+
+fn render_log_chunk_fragment(
+    params: &SearchParams,
+    results: &[serde_json::Value],
+    display_names: &[String],
+    total_count: usize,
+) -> String {
+    let rows = render_log_rows(params, results, display_names);
+    let loaded_end = params.offset + results.len();
+    let col_span = display_names.len().max(1);
+    let load_more_row = if loaded_end < total_count {
+        let next_offset = loaded_end;
+        let next_url = build_log_chunk_url(params, next_offset);
+        format!(
+            r##"<tr id="load-more-logs"><td class="load-row" colspan="{}"><button hx-get="{}" hx-target="#load-more-logs" hx-swap="outerHTML">Load more</button></td></tr>"##,
+            col_span, next_url
+        )
+    } else if total_count == 0 {
+        format!(
+            r##"<tr id="load-more-logs"><td class="no-results" colspan="{}">No results found</td></tr>"##,
+            col_span
+        )
+    } else {
+        format!(
+            r##"<tr id="load-more-logs"><td class="load-row" colspan="{}">End of results</td></tr>"##,
+            col_span
+        )
+    };
+
+    format!("{}{}", rows, load_more_row)
+}
+
+fn render_log_rows(
+    params: &SearchParams,
+    results: &[serde_json::Value],
+    display_names: &[String],
+) -> String {
+    let mut out = String::new();
+
+    for row in results {
+        let Some(obj) = row.as_object() else {
+            continue;
+        };
+
+        let priority_value = obj.get("priority").and_then(|v| {
+            v.as_i64()
+                .or_else(|| v.as_str().and_then(|s| s.parse::<i64>().ok()))
+        });
+        let row_class = match priority_value {
+            Some(p) if p <= 2 => "priority-critical",
+            Some(3) => "priority-error",
+            Some(4) => "priority-warning",
+            _ => "",
+        };
+
+        out.push_str(&format!("<tr class=\"{}\">", row_class));
+        for col in display_names {
+            let value = obj.get(col).unwrap_or(&serde_json::Value::Null);
+            let text = match value {
+                serde_json::Value::Null => String::new(),
+                serde_json::Value::String(s) => s.clone(),
+                _ => value.to_string(),
+            };
+            if col == "unit" && !text.is_empty() {
+                // Cross-link to the unit's processes over the same window
+                out.push_str(&format!(
+                    "<td><a href=\"/processes.html?unit={}&amp;start={}&amp;end={}\">{}</a></td>",
+                    url_encode(&text),
+                    url_encode(&params.start),
+                    url_encode(&params.end),
+                    html_escape(&text)
+                ));
+                continue;
+            }
+            out.push_str(&format!("<td>{}</td>", html_escape(&text)));
+        }
+        out.push_str("</tr>");
+    }
+
+    out
+}
+
+fn build_log_chunk_url(params: &SearchParams, offset: usize) -> String {
+    format!(
+        "/htmx/logs/chunk?q={}&start={}&end={}&hostname={}&unit={}&limit={}&offset={}&sort={}&sort_dir={}{}{}{}",
+        url_encode(params.q.as_deref().unwrap_or("")),
+        url_encode(&params.start),
+        url_encode(&params.end),
+        url_encode(params.hostname.as_deref().unwrap_or("")),
+        url_encode(params.unit.as_deref().unwrap_or("")),
+        params.limit.min(100_000),
+        offset,
+        url_encode(&params.sort),
+        url_encode(&params.sort_dir),
+        params
+            .priority
+            .map(|p| format!("&priority={}", p))
+            .unwrap_or_default(),
+        params
+            .columns
+            .as_deref()
+            .map(|c| format!("&columns={}", url_encode(c)))
+            .unwrap_or_default(),
+        params
+            .extra
+            .as_deref()
+            .map(|e| format!("&extra={}", url_encode(e)))
+            .unwrap_or_default()
+    )
+}
+
+fn build_processes_html() -> String {
+    r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>livedata - Process Monitor</title>
+    <script src="https://unpkg.com/htmx.org@1.9.12"></script>
+    <style>
+        :root {
+            --bg: #272822;
+            --surface: #2d2e27;
+            --surface-alt: #3e3d32;
+            --text: #f8f8f2;
+            --muted: #a59f85;
+            --border: #49483e;
+            --accent: #a6e22e;
+            --accent-2: #66d9ef;
+            --warn: #fd971f;
+            --danger: #f92672;
+        }
+        body.theme-light {
+            --bg: #f8f8f2;
+            --surface: #efefe7;
+            --surface-alt: #ffffff;
+            --text: #272822;
+            --muted: #6f6b57;
+            --border: #b7b39e;
+            --accent: #3b7d15;
+            --accent-2: #0f8395;
+            --warn: #c15d00;
+            --danger: #c2175b;
+        }
+        body { font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, sans-serif; max-width: 1400px; margin: 0 auto; padding: 0; background-color: var(--bg); color: var(--text); }
+        .global-header { background: var(--surface); border-bottom: 2px solid var(--accent); box-shadow: 0 2px 4px rgba(0,0,0,0.2); margin-bottom: 20px; }
+        .global-header nav { display: flex; gap: 5px; padding: 15px 20px; max-width: 1400px; margin: 0 auto; }
+        .global-header nav a { color: var(--accent-2); text-decoration: none; font-size: 16px; font-weight: 500; padding: 10px 18px; border-radius: 4px; }
+        .global-header nav a:hover { background: var(--surface-alt); }
+        .global-header nav a.active { background-color: var(--accent); color: var(--bg); }
+        .container { padding: 0 20px 20px; }
+        .controls { display: flex; gap: 12px; align-items: end; margin-bottom: 14px; flex-wrap: wrap; }
+        input, button { padding: 8px 12px; border: 1px solid var(--border); border-radius: 4px; font-size: 14px; }
+        input { background: var(--surface-alt); color: var(--text); }
+        button { background-color: var(--accent); color: var(--bg); border: none; cursor: pointer; }
+        button:hover { background: var(--accent-2); }
+        table { width: 100%; border-collapse: collapse; background: var(--surface); box-shadow: 0 1px 3px rgba(0,0,0,0.25); }
+        th, td { padding: 10px; border-bottom: 1px solid var(--border); text-align: left; }
+        th { background: var(--surface-alt); color: var(--accent-2); }
+        .muted { color: var(--muted); }
+        .load-row { text-align: center; padding: 12px; }
+        .load-row button { background: var(--surface-alt); color: var(--accent-2); border: 1px solid var(--accent-2); }
+        .load-row button:hover { background: var(--accent-2); color: var(--bg); }
+        #storage-health { margin-bottom: 20px; padding: 15px 20px; background: var(--surface); border-radius: 4px; box-shadow: 0 1px 3px rgba(0,0,0,0.2); font-size: 14px; color: var(--text); border: 1px solid var(--border); }
+        #storage-health .health-item { display: inline-block; margin-right: 25px; }
+        #storage-health .health-label { font-weight: 600; margin-right: 5px; color: var(--muted); }
+        #storage-health .status-good { color: var(--accent); }
+        #storage-health .status-warning { color: var(--warn); }
+        #storage-health .status-critical { color: var(--danger); }
+        .theme-toggle { position: fixed; top: 10px; right: 12px; z-index: 1000; border: 1px solid var(--accent-2); background: var(--surface-alt); color: var(--accent-2); width: 36px; height: 36px; border-radius: 18px; padding: 0; display: inline-flex; align-items: center; justify-content: center; font-size: 18px; line-height: 1; }
     </style>
 </head>
 <body class="theme-dark">
     <button id="theme-toggle" class="theme-toggle" type="button" aria-label="Toggle theme">&#9680;</button>
     <div class="global-header">
         <nav>
-            <a href="/" target="_blank" class="active">Log Search</a>
-            <a href="/processes.html" target="_blank">Processes</a>
+            <a href="/" target="_blank">Log Search</a>
+            <a href="/processes.html" target="_blank" class="active">Processes</a>
         </nav>
     </div>
     <div class="container">
         <div id="storage-health">
-            <span class="health-item">
-                <span class="health-label">Storage:</span>
-                <span id="storage-info">Loading...</span>
-            </span>
-            <span class="health-item">
-                <span class="health-label">Retention:</span>
-                <span id="retention-info">Loading...</span>
-            </span>
+            <span class="health-item"><span class="health-label">Storage:</span><span id="storage-info">Loading...</span></span>
+            <span class="health-item"><span class="health-label">Retention:</span><span id="retention-info">Loading...</span></span>
         </div>
-        <header>
-            <h1>Livedata Log Search</h1>
-        </header>
-        <form class="search-form" method="get" action="/">
-            <div class="search-row">
-                <div class="form-group search-input">
-                    <label for="q">Search <span class="keyboard-hint">(Press / to focus)</span></label>
-                    <input type="text" id="q" name="q" value="{}" placeholder="Search log messages...">
-                </div>
-                <div class="form-group">
-                    <label>&nbsp;</label>
-                    <button type="submit">Search</button>
-                </div>
+
+        <h1>Process Monitor</h1>
+
+        <form class="controls" hx-get="/htmx/processes/chunk" hx-target="#processes-body" hx-swap="innerHTML">
+            <div>
+                <label for="q">Search</label><br>
+                <input id="q" name="q" type="text" placeholder="Fuzzy search processes..." />
             </div>
-            <div class="search-row">
-                <div class="form-group">
-                    <label for="start">Start Time</label>
-                    <input type="text" id="start" name="start" value="{}" placeholder="-1h">
-                </div>
-                <div class="form-group">
-                    <label for="end">End Time</label>
-                    <input type="text" id="end" name="end" value="{}" placeholder="now">
-                </div>
-                <div class="time-presets">
-                    <button type="button" class="time-preset" onclick="setTimeRange('-15m', 'now')">15m</button>
-                    <button type="button" class="time-preset" onclick="setTimeRange('-1h', 'now')">1h</button>
-                    <button type="button" class="time-preset" onclick="setTimeRange('-4h', 'now')">4h</button>
-                    <button type="button" class="time-preset" onclick="setTimeRange('-24h', 'now')">24h</button>
-                    <button type="button" class="time-preset" onclick="setTimeRange('-7d', 'now')">7d</button>
-                </div>
+            <div>
+                <label for="limit">Rows</label><br>
+                <input id="limit" name="limit" type="number" min="10" max="1000" value="100" />
             </div>
-            <div class="search-row">
-                <div class="form-group">
-                    <label for="hostname">Hostname</label>
-                    <select id="hostname" name="hostname">
-                        <option value="">All Hosts</option>
-                        {}
-                    </select>
-                </div>
-                <div class="form-group">
-                    <label for="unit">Systemd Unit</label>
-                    <select id="unit" name="unit">
-                        <option value="">All Units</option>
-                        {}
-                    </select>
-                </div>
-                <div class="form-group">
-                    <label for="priority">Max Priority</label>
-                    <select id="priority" name="priority">
-                        <option value="">All Priorities</option>
-                        {}
-                    </select>
-                </div>
+            <div>
+                <input name="offset" type="hidden" value="0" />
+                <button type="submit">Refresh</button>
             </div>
-            <input type="hidden" name="limit" value="{}">
-            <input type="hidden" name="offset" value="0">
-            <input type="hidden" name="columns" value="{}">
+            <div class="muted" id="last-updated">Never</div>
         </form>
 
-        <details class="column-chooser" id="column-chooser">
-            <summary>Columns</summary>
-            <div class="column-chooser-grid" id="column-checkboxes">Loading columns...</div>
-        </details>
+        <table>
+            <thead>
+                <tr>
+                    <th>Timestamp</th>
+                    <th>PID</th>
+                    <th>PPID</th>
+                    <th>Name</th>
+                    <th>Status</th>
+                    <th>CPU %</th>
+                    <th>Memory</th>
+                    <th>VSize</th>
+                    <th>User</th>
+                    <th>Runtime</th>
+                    <th>Cmdline</th>
+                </tr>
+            </thead>
+            <tbody id="processes-body" hx-get="/htmx/processes/chunk?offset=0&limit=100" hx-trigger="load" hx-swap="innerHTML"></tbody>
+        </table>
+    </div>
+
+    <script>
+        (function() {
+            const key = 'livedata-theme';
+            const body = document.body;
+            const btn = document.getElementById('theme-toggle');
+            const iconDark = '&#9680;';
+            const iconLight = '&#9681;';
+
+            function apply(theme) {
+                body.classList.remove('theme-dark', 'theme-light');
+                body.classList.add(theme === 'light' ? 'theme-light' : 'theme-dark');
+                btn.innerHTML = theme === 'light' ? iconLight : iconDark;
+            }
+
+            const saved = localStorage.getItem(key);
+            apply(saved === 'light' ? 'light' : 'dark');
+
+            btn.addEventListener('click', function() {
+                const next = body.classList.contains('theme-dark') ? 'light' : 'dark';
+                localStorage.setItem(key, next);
+                apply(next);
+            });
+        })();
+
+        (async function() {
+            async function updateStorageHealth() {
+                try {
+                    const response = await fetch('/api/storage/health');
+                    if (!response.ok) throw new Error('Failed to fetch storage health');
+                    const data = await response.json();
+                    const sizeGB = (data.database_size_bytes / (1024 * 1024 * 1024)).toFixed(2);
+                    const maxSizeGB = Math.max(data.retention_policy.log_max_size_gb, data.retention_policy.process_max_size_gb);
+                    const usagePercent = (parseFloat(sizeGB) / maxSizeGB) * 100;
+                    let statusClass = 'status-good';
+                    if (usagePercent >= 90) statusClass = 'status-critical';
+                    else if (usagePercent >= 75) statusClass = 'status-warning';
+                    document.getElementById('storage-info').innerHTML =
+                        `<span class="${statusClass}">${sizeGB}GB / ${maxSizeGB}GB</span> (${data.journal_log_count.toLocaleString()} logs, ${data.process_metric_count.toLocaleString()} metrics)`;
+                    document.getElementById('retention-info').textContent =
+                        `${data.retention_policy.log_retention_days}d logs / ${data.retention_policy.process_retention_days}d proc`;
+                } catch (error) {
+                    document.getElementById('storage-info').innerHTML = '<span class="status-critical">Error loading</span>';
+                    document.getElementById('retention-info').textContent = 'N/A';
+                }
+            }
+            await updateStorageHealth();
+            setInterval(updateStorageHealth, 30000);
+        })();
+    </script>
+</body>
+</html>"##
+        .to_string()
+}
+
+fn render_process_chunk_fragment(
+    params: &ProcessTableParams,
+    rows: &[ProcessMetricsRow],
+    total_count: usize,
+    timestamp: &str,
+) -> String {
+    let mut html = String::new();
+    for p in rows {
+        let ppid = p
+            .parent_pid
+            .map(|v| v.to_string())
+            .unwrap_or_else(|| "-".to_string());
+        let status = p.status.as_deref().unwrap_or("-");
+        let cmdline = p.cmdline.as_deref().unwrap_or("-");
+        html.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{:.1}%</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td class=\"cmdline\">{}</td></tr>",
+            html_escape(timestamp),
+            p.pid,
+            html_escape(&ppid),
+            html_escape(&p.name),
+            html_escape(status),
+            p.cpu_usage,
+            html_escape(&format_bytes(p.mem_usage)),
+            html_escape(&format_bytes(p.virtual_memory)),
+            html_escape(p.user.as_deref().unwrap_or("-")),
+            html_escape(&format_runtime(p.runtime)),
+            html_escape(cmdline),
+        ));
+    }
+
+    let loaded_end = params.offset + rows.len();
+    if loaded_end < total_count {
+        let next_offset = loaded_end;
+        let next_url = format!(
+            "/htmx/processes/chunk?q={}&limit={}&offset={}",
+            url_encode(params.q.as_deref().unwrap_or("")),
+            params.limit.clamp(10, 1000),
+            next_offset
+        );
+        html.push_str(&format!(
+            r##"<tr id="load-more-processes"><td class="load-row" colspan="11"><button hx-get="{}" hx-target="#load-more-processes" hx-swap="outerHTML">Load more</button></td></tr>"##,
+            next_url
+        ));
+    } else if total_count == 0 {
+        html.push_str(
+            r##"<tr id="load-more-processes"><td class="load-row muted" colspan="11">No processes found</td></tr>"##,
+        );
+    } else {
+        html.push_str(
+            r##"<tr id="load-more-processes"><td class="load-row muted" colspan="11">End of results</td></tr>"##,
+        );
+    }
+
+    html
+}
+
+fn fuzzy_match(query: &str, text: &str) -> bool {
+    let mut text_chars = text.chars();
+    for qc in query.chars() {
+        if !text_chars.any(|tc| tc == qc) {
+            return false;
+        }
+    }
+    true
+}
+
+pub(crate) fn format_bytes(bytes: f64) -> String {
+    if bytes <= 0.0 {
+        return "-".to_string();
+    }
+    let units = ["B", "KB", "MB", "GB", "TB", "PB"];
+    let mut value = bytes;
+    let mut idx = 0usize;
+    while value >= 1024.0 && idx < units.len() - 1 {
+        value /= 1024.0;
+        idx += 1;
+    }
+    format!("{:.1} {}", value, units[idx])
+}
+
+fn format_runtime(seconds: u64) -> String {
+    if seconds < 60 {
+        return format!("{}s", seconds);
+    }
+    let minutes = seconds / 60;
+    if minutes < 60 {
+        let rem = seconds % 60;
+        return if rem > 0 {
+            format!("{}m {}s", minutes, rem)
+        } else {
+            format!("{}m", minutes)
+        };
+    }
+    let hours = minutes / 60;
+    if hours < 24 {
+        let rem = minutes % 60;
+        return if rem > 0 {
+            format!("{}h {}m", hours, rem)
+        } else {
+            format!("{}h", hours)
+        };
+    }
+    let days = hours / 24;
+    let rem = hours % 24;
+    if rem > 0 {
+        format!("{}d {}h", days, rem)
+    } else {
+        format!("{}d", days)
+    }
+}
+
+/// HTML escape helper
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#x27;")
+}
+
+/// URL encode helper
+fn url_encode(s: &str) -> String {
+    let mut result = String::with_capacity(s.len() * 3);
+    for c in s.chars() {
+        match c {
+            'A'..='Z' | 'a'..='z' | '0'..='9' | '-' | '_' | '.' | '~' => result.push(c),
+            ' ' => result.push('+'),
+            _ => {
+                for b in c.to_string().as_bytes() {
+                    result.push_str(&format!("%{:02X}", b));
+                }
+            }
+        }
+    }
+    result
+}
+
+/// Create router for testing
+#[cfg(test)]
+fn create_test_app(data_dir: &str) -> Router {
+    create_test_app_with_settings(data_dir, Settings::default())
+}
+
+#[cfg(test)]
+fn create_test_app_with_settings(data_dir: &str, settings: Settings) -> Router {
+    let process_monitor = Arc::new(ProcessMonitor::new());
+    let buffer = DuckDBBuffer::new(data_dir).expect("Failed to create test buffer");
+    let readers = buffer
+        .read_pool(settings.web_read_connections)
+        .expect("Failed to create test read pool");
+    let state = Arc::new(AppState::new(
+        data_dir,
+        Arc::new(Mutex::new(buffer)),
+        readers,
+        process_monitor,
+        settings,
+    ));
+    routes(&state).with_state(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode as AxumStatusCode};
+    use base64::Engine;
+    use base64::engine::general_purpose::STANDARD as BASE64;
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    #[test]
+    fn test_parse_relative_time() {
+        let now = Utc::now();
+
+        let result = parse_time("-1h", now).unwrap();
+        assert!((now - result).num_minutes() >= 59);
+        assert!((now - result).num_minutes() <= 61);
+
+        let result = parse_time("-15m", now).unwrap();
+        assert!((now - result).num_minutes() >= 14);
+        assert!((now - result).num_minutes() <= 16);
+
+        let result = parse_time("-7d", now).unwrap();
+        assert!((now - result).num_days() >= 6);
+        assert!((now - result).num_days() <= 8);
+    }
+
+    #[test]
+    fn test_parse_now() {
+        let now = Utc::now();
+        let result = parse_time("now", now).unwrap();
+        assert_eq!(result, now);
+    }
+
+    #[test]
+    fn test_build_where_clause_binds_user_input() {
+        let now = Utc::now();
+        let clause = build_where_clause(
+            now - Duration::hours(1),
+            now,
+            &SearchQuery::parse("50%", &[]).unwrap(),
+            Some("a,b'c"),
+            Some("x.service"),
+            Some("syslog:host'b"),
+            Some(3),
+            &[ExtraFilter {
+                key: "FOO".to_string(),
+                value: "it's".to_string(),
+                promoted: false,
+            }],
+            &[FieldFilter {
+                column: "_comm".to_string(),
+                values: "ss'hd,cron".to_string(),
+            }],
+        );
+        assert!(!clause.sql.contains("b'c"));
+        assert!(!clause.sql.contains("x.service"));
+        assert!(!clause.sql.contains("host'b"));
+        assert!(!clause.sql.contains("it's"));
+        assert!(!clause.sql.contains("ss'hd"));
+        assert!(clause.sql.contains("_hostname IN (?,?)"));
+        assert!(clause.sql.contains("CAST(\"_comm\" AS VARCHAR) IN (?,?)"));
+        // start, end, q, two hostnames, one unit, one source, priority, extra key and
+        // value, two comm values
+        assert_eq!(clause.params.len(), 12);
+        assert_eq!(clause.params[2], SqlParam::Text("%50\\%%".to_string()));
+    }
+
+    #[test]
+    fn test_parse_field_filters() {
+        let schema: Vec<(String, String)> = ["_comm", "_uid"]
+            .iter()
+            .map(|c| (c.to_string(), "VARCHAR".to_string()))
+            .collect();
+        let filters = |pairs: &[(&str, &str)]| {
+            let pairs: Vec<(String, String)> = pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect();
+            parse_field_filters(&pairs, &schema)
+        };
+        assert_eq!(
+            filters(&[
+                ("comm", "sshd"),
+                ("_uid", "0"),
+                ("_comm", "cron"),
+                ("_uid", "")
+            ])
+            .unwrap(),
+            vec![
+                FieldFilter {
+                    column: "_comm".to_string(),
+                    values: "sshd,cron".to_string(),
+                },
+                FieldFilter {
+                    column: "_uid".to_string(),
+                    values: "0".to_string(),
+                },
+            ]
+        );
+        assert!(filters(&[("_gid", "0")]).is_err());
+        assert!(filters(&[("_comm\" OR 1=1 --", "x")]).is_err());
+    }
+
+    #[test]
+    fn test_parse_extra_filters() {
+        assert!(parse_extra_filters(None, &[]).unwrap().is_empty());
+        assert_eq!(
+            parse_extra_filters(Some("FOO=bar,SYSLOG_RAW=a=b"), &["FOO".to_string()]).unwrap(),
+            vec![
+                ExtraFilter {
+                    key: "FOO".to_string(),
+                    value: "bar".to_string(),
+                    promoted: true,
+                },
+                ExtraFilter {
+                    key: "SYSLOG_RAW".to_string(),
+                    value: "a=b".to_string(),
+                    promoted: false,
+                },
+            ]
+        );
+        assert!(parse_extra_filters(Some("FOO"), &[]).is_err());
+        assert!(parse_extra_filters(Some("$.a.b=1"), &[]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_tail_params_match_entries() {
+        let fields = [
+            ("MESSAGE", "Connection Refused"),
+            ("_HOSTNAME", "web1"),
+            ("_SYSTEMD_UNIT", "nginx.service"),
+            ("PRIORITY", "3"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+        let entry = LogEntry::new(Utc::now(), fields);
+        let schema: Vec<(String, String)> = ["message", "_hostname", "_systemd_unit"]
+            .iter()
+            .map(|c| (c.to_string(), "VARCHAR".to_string()))
+            .collect();
+        let matches = async |query: &str| {
+            let uri = format!("/api/tail?{}", query).parse().unwrap();
+            let Query(params) = Query::<TailParams>::try_from_uri(&uri).unwrap();
+            params.parse_query(&schema).await.unwrap().matches(&entry)
+        };
+
+        assert!(matches("").await);
+        assert!(matches("q=refused&hostname=web2,web1&unit=nginx.service").await);
+        assert!(matches("q=unit:nginx.service+-host:web2").await);
+        assert!(!matches("q=unit:nginx.service+-host:web1").await);
+        assert!(matches("priority=3&source=local").await);
+        assert!(!matches("q=timeout").await);
+        assert!(!matches("hostname=web2").await);
+        assert!(!matches("priority=2").await);
+        assert!(!matches("source=syslog").await);
+
+        let row = tail_row(&entry);
+        assert_eq!(row["unit"], "nginx.service");
+        assert_eq!(row["priority"], 3);
+        assert!(row["pid"].is_null());
+    }
+
+    #[test]
+    fn test_html_escape() {
+        assert_eq!(html_escape("<script>"), "&lt;script&gt;");
+        assert_eq!(html_escape("a & b"), "a &amp; b");
+        assert_eq!(html_escape("\"quoted\""), "&quot;quoted&quot;");
+    }
+
+    #[test]
+    fn test_url_encode() {
+        assert_eq!(url_encode("hello"), "hello");
+        assert_eq!(url_encode("hello world"), "hello+world");
+        assert_eq!(url_encode("a=b&c=d"), "a%3Db%26c%3Dd");
+    }
+
+    #[test]
+    fn test_log_rows_link_units_to_processes() {
+        let params: SearchParams =
+            serde_json::from_value(serde_json::json!({ "start": "-2h" })).unwrap();
+        let rows = [serde_json::json!({ "unit": "nginx.service", "message": "started" })];
+        let html = render_log_rows(&params, &rows, &["unit".to_string(), "message".to_string()]);
+        assert!(html.contains(
+            "<td><a href=\"/processes.html?unit=nginx.service&amp;start=-2h&amp;end=now\">nginx.service</a></td>"
+        ));
+        assert!(html.contains("<td>started</td>"));
+    }
+
+    #[test]
+    fn test_priority_label() {
+        assert_eq!(priority_label(0), "Emergency");
+        assert_eq!(priority_label(3), "Error");
+        assert_eq!(priority_label(6), "Info");
+        assert_eq!(priority_label(7), "Debug");
+    }
+
+    // API Tests
+
+    #[tokio::test]
+    async fn test_health_endpoint() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let app = create_test_app(temp_dir.path().to_str().unwrap());
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/health")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), AxumStatusCode::OK);
+
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let health: HealthResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(health.status, "ok");
+    }
+
+    #[tokio::test]
+    async fn test_health_probes() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let data_dir = temp_dir.path().to_str().unwrap();
+        let settings = Settings {
+            ready_max_entry_age_secs: 60,
+            ..Settings::default()
+        };
+        let buffer = DuckDBBuffer::new(data_dir).unwrap();
+        let readers = buffer.read_pool(1).unwrap();
+        let state = Arc::new(AppState::new(
+            data_dir,
+            Arc::new(Mutex::new(buffer)),
+            readers,
+            Arc::new(ProcessMonitor::new()),
+            settings,
+        ));
+        let app = routes(&state).with_state(state.clone());
+        let probe = |uri: &str| {
+            let app = app.clone();
+            let uri = uri.to_string();
+            async move {
+                let response = app
+                    .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+                    .await
+                    .unwrap();
+                let status = response.status();
+                let body = response.into_body().collect().await.unwrap().to_bytes();
+                let probe: ProbeResponse = serde_json::from_slice(&body).unwrap();
+                let failed: Vec<String> = probe
+                    .checks
+                    .into_iter()
+                    .filter(|c| c.status != CheckStatus::Ok)
+                    .map(|c| c.name)
+                    .collect();
+                (status, failed)
+            }
+        };
+
+        // Still replaying history: alive, but not ready, and /health says how far along
+        state
+            .metrics
+            .start_backfill(crate::metrics::BackfillPhase::Startup, Utc::now());
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/health")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let health: HealthResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(health.backfill.unwrap().entries, 0);
+        let (status, _) = probe("/health/live").await;
+        assert_eq!(status, AxumStatusCode::OK);
+        let (status, failed) = probe("/health/ready").await;
+        assert_eq!(status, AxumStatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(failed, vec!["collector", "newest_entry"]);
+
+        let now = Utc::now();
+        state.metrics.record_heartbeat(now);
+        state.metrics.record_ingested(now - Duration::seconds(5));
+        state.metrics.record_retention_run(now, None);
+        let (status, failed) = probe("/health/ready").await;
+        assert_eq!(status, AxumStatusCode::OK, "{:?}", failed);
+
+        state
+            .metrics
+            .record_retention_run(now, Some("disk full".to_string()));
+        state.metrics.record_heartbeat(now - Duration::minutes(10));
+        let (status, failed) = probe("/health/ready").await;
+        assert_eq!(status, AxumStatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(failed, vec!["collector", "retention"]);
+        let (status, failed) = probe("/health/live").await;
+        assert_eq!(status, AxumStatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(failed, vec!["collector"]);
+    }
+
+    #[tokio::test]
+    #[allow(clippy::await_holding_lock)]
+    async fn test_search_does_not_wait_for_writer_lock() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let data_dir = temp_dir.path().to_str().unwrap();
+        let mut buffer = DuckDBBuffer::new(data_dir).unwrap();
+        let mut fields = std::collections::HashMap::new();
+        fields.insert("MESSAGE".to_string(), "while ingesting".to_string());
+        let entry = crate::log_entry::LogEntry::new(Utc::now() - Duration::minutes(1), fields);
+        buffer.add_entry(&entry).unwrap();
+        let readers = buffer.read_pool(1).unwrap();
+        let state = Arc::new(AppState::new(
+            data_dir,
+            Arc::new(Mutex::new(buffer)),
+            readers,
+            Arc::new(ProcessMonitor::new()),
+            Settings::default(),
+        ));
+        let app = routes(&state).with_state(state.clone());
+
+        // Held as a long checkpoint or retention run would
+        let _writer = state.buffer.lock().unwrap();
+        let request = Request::builder()
+            .uri("/api/search?start=-1h&q=ingesting")
+            .body(Body::empty())
+            .unwrap();
+        let response =
+            tokio::time::timeout(std::time::Duration::from_secs(10), app.oneshot(request))
+                .await
+                .expect("search waited for the writer's lock")
+                .unwrap();
+        assert_eq!(response.status(), AxumStatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let results: SearchResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(results.total, 1);
+    }
+
+    #[tokio::test]
+    async fn test_query_cache_until_new_entries() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let data_dir = temp_dir.path().to_str().unwrap();
+        let buffer = DuckDBBuffer::new(data_dir).unwrap();
+        let readers = buffer.read_pool(1).unwrap();
+        let state = Arc::new(AppState::new(
+            data_dir,
+            Arc::new(Mutex::new(buffer)),
+            readers,
+            Arc::new(ProcessMonitor::new()),
+            Settings::default(),
+        ));
+        let app = routes(&state).with_state(state.clone());
+        let total = || {
+            let app = app.clone();
+            async move {
+                let response = app
+                    .oneshot(
+                        Request::builder()
+                            .uri("/api/search?start=-1h")
+                            .body(Body::empty())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+                assert_eq!(response.status(), AxumStatusCode::OK);
+                let body = response.into_body().collect().await.unwrap().to_bytes();
+                serde_json::from_slice::<SearchResponse>(&body)
+                    .unwrap()
+                    .total
+            }
+        };
+
+        assert_eq!(total().await, 0);
+        let mut fields = std::collections::HashMap::new();
+        fields.insert("MESSAGE".to_string(), "fresh".to_string());
+        let entry = crate::log_entry::LogEntry::new(Utc::now() - Duration::minutes(1), fields);
+        state.buffer.lock().unwrap().add_entry(&entry).unwrap();
+        // Served from the cache until the collector reports a newer entry
+        assert_eq!(total().await, 0);
+        state.metrics.record_ingested(entry.timestamp);
+        assert_eq!(total().await, 1);
+
+        // Entries from peers are often older than the newest local one
+        let mut replicated = entry.clone().with_source("peer:db1");
+        replicated.timestamp -= Duration::minutes(5);
+        let stored = state
+            .buffer
+            .lock()
+            .unwrap()
+            .add_replicated_entries(&[replicated]);
+        assert_eq!(stored.unwrap(), 1);
+        assert_eq!(total().await, 1);
+        state.metrics.record_replicated(1);
+        assert_eq!(total().await, 2);
+
+        // Rows deleted by retention stay cached until the deletion is counted
+        let stats = state
+            .buffer
+            .lock()
+            .unwrap()
+            .enforce_retention(0, &[], 100.0, 30, 100.0)
+            .unwrap();
+        assert_eq!(stats.logs_deleted_by_time, 2);
+        assert_eq!(total().await, 2);
+        state
+            .metrics
+            .record_retention_deleted(stats.total_deleted());
+        assert_eq!(total().await, 0);
+
+        // Cached responses still honour If-None-Match
+        let columns = |etag: Option<&str>| {
+            let mut request = Request::builder().uri("/api/columns");
+            if let Some(etag) = etag {
+                request = request.header(header::IF_NONE_MATCH, etag);
+            }
+            app.clone().oneshot(request.body(Body::empty()).unwrap())
+        };
+        let response = columns(None).await.unwrap();
+        let etag = response.headers()[header::ETAG]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let response = columns(Some(&etag)).await.unwrap();
+        assert_eq!(response.status(), AxumStatusCode::NOT_MODIFIED);
+    }
+
+    #[tokio::test]
+    async fn test_api_search_empty_results() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let app = create_test_app(temp_dir.path().to_str().unwrap());
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/search?start=-1h&end=now")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), AxumStatusCode::OK);
 
-        <section class="timechart-panel" aria-label="Log level timechart">
-            <div class="timechart-header">
-                <div class="timechart-title">Timechart</div>
-                <div class="timechart-subtitle">1-minute bins by log level</div>
-            </div>
-            <div id="timechart"></div>
-        </section>
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let search_response: SearchResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(search_response.total, 0);
+        assert!(search_response.results.is_empty());
+    }
 
-        <div class="summary">
-            Showing {} loaded rows out of {} total
-        </div>
+    #[tokio::test]
+    async fn test_api_search_with_query_param() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let app = create_test_app(temp_dir.path().to_str().unwrap());
 
-        <div class="results-table-wrap">
-            <table class="results-table">
-                <thead>
-                    <tr>{}</tr>
-                </thead>
-                <tbody id="log-rows">
-                    {}
-                </tbody>
-            </table>
-        </div>
-    </div>
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/search?q=error&start=-1h&end=now&limit=10")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
 
-    <script>
-        // Focus search on / key
-        document.addEventListener('keydown', function(e) {{
-            if (e.key === '/' && document.activeElement.tagName !== 'INPUT') {{
-                e.preventDefault();
-                document.getElementById('q').focus();
-            }}
-        }});
+        assert_eq!(response.status(), AxumStatusCode::OK);
 
-        // Time preset buttons
-        function setTimeRange(start, end) {{
-            document.getElementById('start').value = start;
-            document.getElementById('end').value = end;
-            document.querySelector('form').submit();
-        }}
-        window.setTimeRange = setTimeRange;
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let search_response: SearchResponse = serde_json::from_slice(&body).unwrap();
+        // Empty dir should have no results
+        assert_eq!(search_response.total, 0);
+    }
 
-        const TIMECHART_LEVELS = ['Emergency', 'Alert', 'Critical', 'Error', 'Warning', 'Notice', 'Info', 'Debug'];
-        const TIMECHART_COLORS = {{
-            Emergency: '#7f0000',
-            Alert: '#b10026',
-            Critical: '#d7301f',
-            Error: '#ef6548',
-            Warning: '#fc8d59',
-            Notice: '#fdbb84',
-            Info: '#74add1',
-            Debug: '#4575b4'
-        }};
-        let cachedTimechartData = [];
+    #[tokio::test]
+    async fn test_api_filters_endpoint() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let app = create_test_app(temp_dir.path().to_str().unwrap());
 
-        function getTimechartQueryParams() {{
-            const form = document.querySelector('.search-form');
-            const formData = new FormData(form);
-            const params = new URLSearchParams();
-            ['q', 'start', 'end', 'hostname', 'unit', 'priority'].forEach((key) => {{
-                const value = String(formData.get(key) || '').trim();
-                if (value !== '') params.set(key, value);
-            }});
-            return params;
-        }}
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/filters")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
 
-        async function loadTimechart() {{
-            const chartEl = document.getElementById('timechart');
-            if (typeof d3 === 'undefined') {{
-                chartEl.innerHTML = '<div class="timechart-empty">Failed to load D3</div>';
-                return;
-            }}
+        assert_eq!(response.status(), AxumStatusCode::OK);
 
-            chartEl.innerHTML = '<div class="timechart-empty">Loading timechart...</div>';
-            try {{
-                const params = getTimechartQueryParams();
-                const response = await fetch(`/api/timechart?${{params.toString()}}`);
-                if (!response.ok) throw new Error('Failed to fetch timechart data');
-                const rows = await response.json();
-                cachedTimechartData = rows;
-                renderTimechart(rows);
-            }} catch (error) {{
-                console.error('Failed to load timechart:', error);
-                chartEl.innerHTML = '<div class="timechart-empty">Unable to load timechart</div>';
-            }}
-        }}
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let filters: FilterValues = serde_json::from_slice(&body).unwrap();
+        // Empty dir should have empty filter lists
+        assert!(filters.hostnames.is_empty());
+        assert!(filters.units.is_empty());
+    }
 
-        function renderTimechart(rows) {{
-            const chartEl = document.getElementById('timechart');
-            chartEl.innerHTML = '';
+    #[tokio::test]
+    async fn test_search_ui_returns_html() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let app = create_test_app(temp_dir.path().to_str().unwrap());
 
-            if (!Array.isArray(rows) || rows.length === 0) {{
-                chartEl.innerHTML = '<div class="timechart-empty">No matching log events for this range</div>';
-                return;
-            }}
+        let response = app
+            .oneshot(Request::builder().uri("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
 
-            const binsByTime = new Map();
-            rows.forEach((row) => {{
-                const ts = new Date(row.time_bin);
-                if (Number.isNaN(ts.getTime())) return;
-                const key = ts.toISOString();
-                if (!binsByTime.has(key)) {{
-                    const seeded = {{ time: ts }};
-                    TIMECHART_LEVELS.forEach((level) => {{
-                        seeded[level] = 0;
-                    }});
-                    binsByTime.set(key, seeded);
-                }}
-                const bin = binsByTime.get(key);
-                const level = TIMECHART_LEVELS.includes(row.level) ? row.level : 'Info';
-                const count = Number(row.count) || 0;
-                bin[level] += count;
-            }});
+        assert_eq!(response.status(), AxumStatusCode::OK);
 
-            const data = Array.from(binsByTime.values()).sort((a, b) => a.time - b.time);
-            if (data.length === 0) {{
-                chartEl.innerHTML = '<div class="timechart-empty">No matching log events for this range</div>';
-                return;
-            }}
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let html = String::from_utf8(body.to_vec()).unwrap();
+        assert!(html.contains("<!DOCTYPE html>"));
+        assert!(html.contains("Livedata"));
+    }
 
-            const styles = getComputedStyle(document.body);
-            const textColor = styles.getPropertyValue('--text').trim();
-            const borderColor = styles.getPropertyValue('--border').trim();
-            const width = Math.max(chartEl.clientWidth, 320);
-            const height = 280;
-            const margin = {{ top: 12, right: 10, bottom: 38, left: 50 }};
+    #[tokio::test]
+    async fn test_api_search_with_priority_filter() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let app = create_test_app(temp_dir.path().to_str().unwrap());
 
-            const x = d3.scaleBand()
-                .domain(data.map((d) => d.time))
-                .range([margin.left, width - margin.right])
-                .paddingInner(0.1);
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/search?start=-1h&end=now&priority=3")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
 
-            const yMax = d3.max(data, (d) => d3.sum(TIMECHART_LEVELS, (k) => d[k])) || 0;
-            const y = d3.scaleLinear()
-                .domain([0, yMax > 0 ? yMax : 1])
-                .nice()
-                .range([height - margin.bottom, margin.top]);
+        assert_eq!(response.status(), AxumStatusCode::OK);
+    }
 
-            const stack = d3.stack().keys(TIMECHART_LEVELS);
-            const series = stack(data);
+    #[tokio::test]
+    async fn test_api_search_hostname_with_quote() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        {
+            let mut buffer = DuckDBBuffer::new(temp_dir.path()).unwrap();
+            let mut fields = std::collections::HashMap::new();
+            fields.insert("MESSAGE".to_string(), "hello".to_string());
+            fields.insert("_HOSTNAME".to_string(), "o'brien".to_string());
+            let entry = crate::log_entry::LogEntry::new(Utc::now() - Duration::minutes(1), fields);
+            buffer.add_entry(&entry).unwrap();
+        }
+        let app = create_test_app(temp_dir.path().to_str().unwrap());
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/api/search?start=-1h&end=now&hostname=o%27brien")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), AxumStatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let search_response: SearchResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(search_response.results.len(), 1);
+
+        // A classic injection payload is treated as a literal hostname
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/search?start=-1h&end=now&hostname=x%27)%20OR%201%3D1%20--")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), AxumStatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let search_response: SearchResponse = serde_json::from_slice(&body).unwrap();
+        assert!(search_response.results.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_api_search_filters_on_extra_fields() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        {
+            let mut buffer = DuckDBBuffer::new(temp_dir.path()).unwrap();
+            for value in ["one", "two"] {
+                let mut fields = std::collections::HashMap::new();
+                fields.insert("MESSAGE".to_string(), format!("message {}", value));
+                fields.insert("FOO".to_string(), value.to_string());
+                let entry =
+                    crate::log_entry::LogEntry::new(Utc::now() - Duration::minutes(1), fields);
+                buffer.add_entry(&entry).unwrap();
+            }
+        }
+        let app = create_test_app(temp_dir.path().to_str().unwrap());
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/api/search?start=-1h&end=now&extra=FOO%3Dtwo&columns=message,extra_fields")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), AxumStatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let search_response: SearchResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(search_response.total, 1);
+        let row = &search_response.results[0];
+        assert_eq!(row["message"], "message two");
+        // Returned as an object, not a JSON-encoded string
+        assert_eq!(row["extra_fields"]["FOO"], "two");
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/search?extra=foo.bar%3D1")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), AxumStatusCode::BAD_REQUEST);
+    }
 
-            const svg = d3.create('svg')
-                .attr('viewBox', `0 0 ${{width}} ${{height}}`)
-                .attr('width', width)
-                .attr('height', height)
-                .attr('role', 'img')
-                .attr('aria-label', 'Stacked bar chart of log counts by level and 1-minute time bin');
+    #[tokio::test]
+    async fn test_promoted_extra_field_is_searchable() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        {
+            let mut buffer = DuckDBBuffer::new(temp_dir.path()).unwrap();
+            let mut fields = std::collections::HashMap::new();
+            fields.insert("MESSAGE".to_string(), "checkout".to_string());
+            fields.insert("TENANT".to_string(), "acme".to_string());
+            let entry = crate::log_entry::LogEntry::new(Utc::now() - Duration::minutes(1), fields);
+            buffer.add_entry(&entry).unwrap();
+        }
+        let app = create_test_app(temp_dir.path().to_str().unwrap());
+        let request = |method: &str, uri: &str| {
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .body(Body::empty())
+                .unwrap()
+        };
 
-            svg.append('g')
-                .attr('stroke', borderColor)
-                .attr('stroke-opacity', 0.6)
-                .selectAll('line')
-                .data(y.ticks(4))
-                .join('line')
-                .attr('x1', margin.left)
-                .attr('x2', width - margin.right)
-                .attr('y1', (d) => y(d))
-                .attr('y2', (d) => y(d));
+        let response = app
+            .clone()
+            .oneshot(request("GET", "/api/extra-fields"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), AxumStatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let usage: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(usage[0]["key"], "TENANT");
+        assert_eq!(usage[0]["rows"], 1);
 
-            const levelGroups = svg.append('g')
-                .selectAll('g')
-                .data(series)
-                .join('g')
-                .attr('fill', (d) => TIMECHART_COLORS[d.key] || TIMECHART_COLORS.Info);
+        let response = app
+            .clone()
+            .oneshot(request("POST", "/api/extra-fields/TENANT/promote"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), AxumStatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let promoted: PromoteResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(promoted.backfilled_rows, 1);
 
-            levelGroups.selectAll('rect')
-                .data((d) => d.map((v) => Object.assign(v, {{ level: d.key }})))
-                .join('rect')
-                .attr('x', (d) => x(d.data.time) || 0)
-                .attr('y', (d) => y(d[1]))
-                .attr('height', (d) => Math.max(0, y(d[0]) - y(d[1])))
-                .attr('width', x.bandwidth())
-                .append('title')
-                .text((d) => `${{d.level}}: ${{d.data[d.level]}} @ ${{d3.timeFormat('%Y-%m-%d %H:%M')(d.data.time)}}`);
+        // A second promotion is refused
+        let response = app
+            .clone()
+            .oneshot(request("POST", "/api/extra-fields/TENANT/promote"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), AxumStatusCode::BAD_REQUEST);
 
-            const tickEvery = Math.max(1, Math.ceil(data.length / 12));
-            svg.append('g')
-                .attr('transform', `translate(0,${{height - margin.bottom}})`)
-                .call(
-                    d3.axisBottom(x)
-                        .tickValues(data.filter((_, i) => i % tickEvery === 0).map((d) => d.time))
-                        .tickFormat(d3.timeFormat('%H:%M'))
-                )
-                .call((g) => g.selectAll('text').attr('fill', textColor))
-                .call((g) => g.selectAll('line,path').attr('stroke', borderColor));
+        let response = app
+            .oneshot(request(
+                "GET",
+                "/api/search?start=-1h&end=now&extra=TENANT%3Dacme&columns=message,TENANT",
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), AxumStatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let search_response: SearchResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(search_response.total, 1);
+        assert_eq!(search_response.results[0]["TENANT"], "acme");
+    }
 
-            svg.append('g')
-                .attr('transform', `translate(${{margin.left}},0)`)
-                .call(d3.axisLeft(y).ticks(4).tickFormat(d3.format('d')))
-                .call((g) => g.selectAll('text').attr('fill', textColor))
-                .call((g) => g.selectAll('line,path').attr('stroke', borderColor));
+    #[tokio::test]
+    async fn test_timechart_rollup_matches_scan() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let minute = (Utc::now() - Duration::minutes(30))
+            .duration_trunc(Duration::minutes(1))
+            .unwrap();
+        {
+            let mut buffer = DuckDBBuffer::new(temp_dir.path()).unwrap();
+            for (offset, priority) in [(1, "3"), (2, "6"), (61, "6"), (62, "6")] {
+                let mut fields = std::collections::HashMap::new();
+                fields.insert("MESSAGE".to_string(), "tick".to_string());
+                fields.insert("_HOSTNAME".to_string(), "host1".to_string());
+                fields.insert("PRIORITY".to_string(), priority.to_string());
+                let entry =
+                    crate::log_entry::LogEntry::new(minute + Duration::seconds(offset), fields);
+                buffer.add_entry(&entry).unwrap();
+            }
+        }
+        let app = create_test_app(temp_dir.path().to_str().unwrap());
+        let fetch = |query: &str| {
+            let app = app.clone();
+            let uri = format!("/api/timechart?start=-1h&end=now&hostname=host1{}", query);
+            async move {
+                let response = app
+                    .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+                    .await
+                    .unwrap();
+                assert_eq!(response.status(), AxumStatusCode::OK);
+                let body = response.into_body().collect().await.unwrap().to_bytes();
+                let bins: Vec<TimechartBin> = serde_json::from_slice(&body).unwrap();
+                bins.into_iter()
+                    .map(|b| (b.time_bin, b.level, b.count))
+                    .collect::<Vec<_>>()
+            }
+        };
 
-            chartEl.append(svg.node());
-        }}
+        // A text filter forces the scan of the log tables
+        let rollup = fetch("").await;
+        let scan = fetch("&q=tick").await;
+        assert_eq!(rollup.len(), 3);
+        assert_eq!(rollup, scan);
+        assert_eq!(rollup.iter().map(|b| b.2).sum::<i64>(), 4);
+    }
 
-        window.addEventListener('resize', () => {{
-            if (cachedTimechartData.length > 0) renderTimechart(cachedTimechartData);
-        }});
+    #[test]
+    fn test_histogram_bin_sizes() {
+        assert_eq!(histogram_bin(None, Duration::minutes(15)), Ok(("10s", 10)));
+        assert_eq!(
+            histogram_bin(Some("auto"), Duration::hours(1)),
+            Ok(("1m", 60))
+        );
+        assert_eq!(histogram_bin(None, Duration::days(1)), Ok(("5m", 300)));
+        assert_eq!(histogram_bin(None, Duration::days(365)), Ok(("1d", 86400)));
+        assert_eq!(
+            histogram_bin(Some("10s"), Duration::hours(1)),
+            Ok(("10s", 10))
+        );
+        // Requested bins are coarsened when the range would need too many
+        assert_eq!(
+            histogram_bin(Some("10s"), Duration::days(7)),
+            Ok(("1h", 3600))
+        );
+        assert!(histogram_bin(Some("2m"), Duration::hours(1)).is_err());
+    }
 
-        loadTimechart();
+    #[tokio::test]
+    async fn test_api_histogram_rebins() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let hour = (Utc::now() - Duration::hours(3))
+            .duration_trunc(Duration::hours(1))
+            .unwrap();
+        {
+            let mut buffer = DuckDBBuffer::new(temp_dir.path()).unwrap();
+            for (minutes, priority) in [(1, "3"), (2, "6"), (30, "6"), (61, "6")] {
+                let mut fields = std::collections::HashMap::new();
+                fields.insert("MESSAGE".to_string(), "tick".to_string());
+                fields.insert("PRIORITY".to_string(), priority.to_string());
+                let entry =
+                    crate::log_entry::LogEntry::new(hour + Duration::minutes(minutes), fields);
+                buffer.add_entry(&entry).unwrap();
+            }
+        }
+        let app = create_test_app(temp_dir.path().to_str().unwrap());
+        let fetch = |query: &str| {
+            let app = app.clone();
+            let uri = format!("/api/histogram?start=-4h&end=now{}", query);
+            async move {
+                let response = app
+                    .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+                    .await
+                    .unwrap();
+                assert_eq!(response.status(), AxumStatusCode::OK);
+                let body = response.into_body().collect().await.unwrap().to_bytes();
+                serde_json::from_slice::<HistogramResponse>(&body).unwrap()
+            }
+        };
 
-        // Theme toggle (dark default)
-        (function() {{
-            const key = 'livedata-theme';
-            const body = document.body;
-            const btn = document.getElementById('theme-toggle');
-            const iconDark = '&#9680;';
-            const iconLight = '&#9681;';
+        // Rollup and scan agree on hourly bins too
+        for query in ["&bin=1h", "&bin=1h&q=tick"] {
+            let histogram = fetch(query).await;
+            assert_eq!(histogram.bin, "1h");
+            assert_eq!(histogram.bin_seconds, 3600);
+            let bins: Vec<(&str, i64)> = histogram
+                .bins
+                .iter()
+                .map(|b| (b.level.as_str(), b.count))
+                .collect();
+            assert_eq!(
+                bins,
+                vec![("Error", 1), ("Info", 2), ("Info", 1)],
+                "{}",
+                query
+            );
+        }
 
-            function apply(theme) {{
-                body.classList.remove('theme-dark', 'theme-light');
-                body.classList.add(theme === 'light' ? 'theme-light' : 'theme-dark');
-                btn.innerHTML = theme === 'light' ? iconLight : iconDark;
-            }}
+        let histogram = fetch("&bin=10s&q=tick").await;
+        assert_eq!(histogram.bin, "10s");
+        assert_eq!(histogram.bins.len(), 4);
+    }
 
-            const saved = localStorage.getItem(key);
-            apply(saved === 'light' ? 'light' : 'dark');
+    #[tokio::test]
+    async fn test_api_summary_reports_hourly_counts() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let hour = (Utc::now() - Duration::hours(2))
+            .duration_trunc(Duration::hours(1))
+            .unwrap();
+        {
+            let mut buffer = DuckDBBuffer::new(temp_dir.path()).unwrap();
+            for (minutes, unit, priority) in [
+                (5, "a.service", "3"),
+                (10, "a.service", "6"),
+                (20, "b.service", "6"),
+                (70, "a.service", "2"),
+            ] {
+                let mut fields = std::collections::HashMap::new();
+                fields.insert("MESSAGE".to_string(), "summarized".to_string());
+                fields.insert("_HOSTNAME".to_string(), "host1".to_string());
+                fields.insert("_SYSTEMD_UNIT".to_string(), unit.to_string());
+                fields.insert("PRIORITY".to_string(), priority.to_string());
+                let entry =
+                    crate::log_entry::LogEntry::new(hour + Duration::minutes(minutes), fields);
+                buffer.add_entry(&entry).unwrap();
+            }
+            assert_eq!(buffer.refresh_log_summaries().unwrap(), 3);
+        }
+        let app = create_test_app(temp_dir.path().to_str().unwrap());
 
-            btn.addEventListener('click', function() {{
-                const next = body.classList.contains('theme-dark') ? 'light' : 'dark';
-                localStorage.setItem(key, next);
-                apply(next);
-                if (cachedTimechartData.length > 0) renderTimechart(cachedTimechartData);
-            }});
-        }})();
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/summary?unit=a.service")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), AxumStatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let summaries: Vec<LogSummary> = serde_json::from_slice(&body).unwrap();
+        let counts: Vec<_> = summaries.iter().map(|s| (s.count, s.error_count)).collect();
+        assert_eq!(counts, vec![(2, 1), (1, 1)]);
+        assert_eq!(summaries[0].hostname.as_deref(), Some("host1"));
+        assert!(summaries[0].first_timestamp < summaries[0].last_timestamp);
+    }
 
-        // Storage health indicator
-        (async function() {{
-            async function updateStorageHealth() {{
-                try {{
-                    const response = await fetch('/api/storage/health');
-                    if (!response.ok) throw new Error('Failed to fetch storage health');
+    #[tokio::test]
+    async fn test_web_auth_protects_routes_except_health() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let settings = Settings {
+            web_token: Some("reader".to_string()),
+            web_username: Some("ops".to_string()),
+            web_password: Some("hunter2".to_string()),
+            admin_token: Some("admin".to_string()),
+            ..Settings::default()
+        };
+        let app = create_test_app_with_settings(temp_dir.path().to_str().unwrap(), settings);
+        let get = |uri: &str, authorization: Option<String>| {
+            let mut request = Request::builder().uri(uri);
+            if let Some(authorization) = authorization {
+                request = request.header("Authorization", authorization);
+            }
+            app.clone().oneshot(request.body(Body::empty()).unwrap())
+        };
 
-                    const data = await response.json();
+        let response = get("/api/search?start=-1h&end=now", None).await.unwrap();
+        assert_eq!(response.status(), AxumStatusCode::UNAUTHORIZED);
+        assert_eq!(
+            response.headers().get("www-authenticate").unwrap(),
+            "Basic realm=\"livedata\""
+        );
+        let response = get("/", Some("Bearer wrong".into())).await.unwrap();
+        assert_eq!(response.status(), AxumStatusCode::UNAUTHORIZED);
+        let basic = |credentials: &str| Some(format!("Basic {}", BASE64.encode(credentials)));
+        let response = get("/", basic("ops:wrong")).await.unwrap();
+        assert_eq!(response.status(), AxumStatusCode::UNAUTHORIZED);
+
+        for authorization in [
+            Some("Bearer reader".to_string()),
+            Some("Bearer admin".to_string()),
+            basic("ops:hunter2"),
+        ] {
+            let response = get("/api/search?start=-1h&end=now", authorization)
+                .await
+                .unwrap();
+            assert_eq!(response.status(), AxumStatusCode::OK);
+        }
 
-                    // Calculate storage usage percentage
-                    const sizeGB = (data.database_size_bytes / (1024 * 1024 * 1024)).toFixed(2);
-                    const maxSizeGB = Math.max(data.retention_policy.log_max_size_gb, data.retention_policy.process_max_size_gb);
-                    const usagePercent = (parseFloat(sizeGB) / maxSizeGB) * 100;
+        let response = get("/health", None).await.unwrap();
+        assert_eq!(response.status(), AxumStatusCode::OK);
+    }
 
-                    // Determine status color
-                    let statusClass = 'status-good';
-                    if (usagePercent >= 90) statusClass = 'status-critical';
-                    else if (usagePercent >= 75) statusClass = 'status-warning';
+    #[tokio::test]
+    async fn test_oidc_redirects_pages_to_login() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let data_dir = temp_dir.path().to_str().unwrap();
+        let oidc_settings = crate::oidc::OidcSettings {
+            issuer: "https://sso.example.com".to_string(),
+            client_id: "livedata".to_string(),
+            client_secret: None,
+            redirect_url: "https://logs.example.com/auth/callback".to_string(),
+            scopes: vec!["openid".to_string()],
+            role_claim: None,
+            default_role: Role::Viewer,
+        };
+        let metadata = crate::oidc::ProviderMetadata {
+            issuer: "https://sso.example.com".to_string(),
+            authorization_endpoint: "https://sso.example.com/authorize".to_string(),
+            token_endpoint: "https://sso.example.com/token".to_string(),
+            jwks_uri: "https://sso.example.com/jwks".to_string(),
+        };
+        let settings = Settings {
+            oidc: Some(oidc_settings.clone()),
+            ..Settings::default()
+        };
+        let buffer = DuckDBBuffer::new(data_dir).unwrap();
+        let readers = buffer.read_pool(1).unwrap();
+        let mut state = AppState::new(
+            data_dir,
+            Arc::new(Mutex::new(buffer)),
+            readers,
+            Arc::new(ProcessMonitor::new()),
+            settings,
+        );
+        let keys = serde_json::from_str(r#"{"keys": []}"#).unwrap();
+        state.oidc = Some(OidcProvider::new(oidc_settings, metadata, keys));
+        let state = Arc::new(state);
+        let app = routes(&state).with_state(state);
+        let get = |uri: &str, accept: &str| {
+            app.clone().oneshot(
+                Request::builder()
+                    .uri(uri)
+                    .header("Accept", accept)
+                    .header("Cookie", "livedata_session=forged")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+        };
+        let location =
+            |response: &Response| response.headers()["location"].to_str().unwrap().to_string();
+
+        let response = get("/processes.html", "text/html").await.unwrap();
+        assert_eq!(response.status(), AxumStatusCode::SEE_OTHER);
+        assert_eq!(
+            location(&response),
+            "/auth/login?return_to=%2Fprocesses.html"
+        );
 
-                    // Update storage info
-                    document.getElementById('storage-info').innerHTML =
-                        `<span class="${{statusClass}}">${{sizeGB}}GB / ${{maxSizeGB}}GB</span> (${{data.journal_log_count.toLocaleString()}} logs, ${{data.process_metric_count.toLocaleString()}} metrics)`;
+        let response = get("/api/search", "application/json").await.unwrap();
+        assert_eq!(response.status(), AxumStatusCode::UNAUTHORIZED);
 
-                    // Update retention info
-                    document.getElementById('retention-info').textContent =
-                        `${{data.retention_policy.log_retention_days}}d logs / ${{data.retention_policy.process_retention_days}}d proc`;
+        let response = get("/auth/login?return_to=%2Fprocesses.html", "text/html")
+            .await
+            .unwrap();
+        assert_eq!(response.status(), AxumStatusCode::SEE_OTHER);
+        assert!(location(&response).starts_with("https://sso.example.com/authorize?"));
 
-                }} catch (error) {{
-                    console.error('Failed to fetch storage health:', error);
-                    document.getElementById('storage-info').innerHTML = '<span class="status-critical">Error loading</span>';
-                    document.getElementById('retention-info').textContent = 'N/A';
-                }}
-            }}
+        let response = get("/auth/callback?code=abc&state=unknown", "text/html")
+            .await
+            .unwrap();
+        assert_eq!(response.status(), AxumStatusCode::UNAUTHORIZED);
+    }
 
-            // Update on load
-            await updateStorageHealth();
+    #[tokio::test]
+    async fn test_api_filters_compressed_and_revalidated() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let app = create_test_app(temp_dir.path().to_str().unwrap());
+        let get = |headers: &[(&str, &str)]| {
+            let mut request = Request::builder().uri("/api/filters");
+            for (name, value) in headers {
+                request = request.header(*name, *value);
+            }
+            app.clone().oneshot(request.body(Body::empty()).unwrap())
+        };
 
-            // Refresh every 30 seconds
-            setInterval(updateStorageHealth, 30000);
-        }})();
+        let response = get(&[("Accept-Encoding", "gzip")]).await.unwrap();
+        assert_eq!(response.status(), AxumStatusCode::OK);
+        assert_eq!(response.headers()["content-encoding"], "gzip");
+        assert_eq!(response.headers()["cache-control"], "private, max-age=60");
+        let etag = response.headers()["etag"].to_str().unwrap().to_string();
 
-        // Column chooser
-        (async function() {{
-            const STORAGE_KEY = 'livedata-columns';
-            const columnsInput = document.querySelector('input[name="columns"]');
-            const grid = document.getElementById('column-checkboxes');
+        let response = get(&[("If-None-Match", &etag)]).await.unwrap();
+        assert_eq!(response.status(), AxumStatusCode::NOT_MODIFIED);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert!(body.is_empty());
 
-            // Load saved selection from localStorage (or use current columns param)
-            let savedCols = null;
-            try {{
-                const stored = localStorage.getItem(STORAGE_KEY);
-                if (stored) savedCols = JSON.parse(stored);
-            }} catch(_) {{}}
+        let response = get(&[("If-None-Match", "\"stale\"")]).await.unwrap();
+        assert_eq!(response.status(), AxumStatusCode::OK);
+        assert!(response.headers().get("content-encoding").is_none());
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let filters: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(filters["priorities"].as_array().unwrap().len(), 8);
+    }
 
-            // If columns were passed in the URL, use those instead
-            const urlCols = columnsInput.value.trim();
-            if (urlCols) {{
-                savedCols = urlCols.split(',').map(s => s.trim()).filter(Boolean);
-            }}
+    #[tokio::test]
+    async fn test_api_rate_limit_per_client() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let settings = Settings {
+            api_requests_per_minute: 2,
+            ..Settings::default()
+        };
+        let app = create_test_app_with_settings(temp_dir.path().to_str().unwrap(), settings);
+        let get = |uri: &str, ip: [u8; 4]| {
+            let mut request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+            request
+                .extensions_mut()
+                .insert(ConnectInfo(SocketAddr::from((ip, 40000))));
+            app.clone().oneshot(request)
+        };
 
-            try {{
-                const response = await fetch('/api/columns');
-                if (!response.ok) throw new Error('Failed to fetch columns');
-                const columns = await response.json();
+        for remaining in ["1", "0"] {
+            let response = get("/api/filters", [10, 0, 0, 1]).await.unwrap();
+            assert_eq!(response.status(), AxumStatusCode::OK);
+            assert_eq!(response.headers()["x-ratelimit-limit"], "2");
+            assert_eq!(response.headers()["x-ratelimit-remaining"], remaining);
+        }
+        let response = get("/api/filters", [10, 0, 0, 1]).await.unwrap();
+        assert_eq!(response.status(), AxumStatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()["retry-after"], "30");
 
-                // Determine which columns are checked
-                const activeSet = savedCols
-                    ? new Set(savedCols)
-                    : new Set(columns.filter(c => c.default).map(c => c.name));
+        // Other clients and non-API routes are unaffected
+        let response = get("/api/filters", [10, 0, 0, 2]).await.unwrap();
+        assert_eq!(response.status(), AxumStatusCode::OK);
+        let response = get("/health", [10, 0, 0, 1]).await.unwrap();
+        assert_eq!(response.status(), AxumStatusCode::OK);
 
-                grid.innerHTML = '';
-                columns.forEach(col => {{
-                    const label = document.createElement('label');
-                    const cb = document.createElement('input');
-                    cb.type = 'checkbox';
-                    cb.value = col.name;
-                    cb.checked = activeSet.has(col.name);
-                    cb.addEventListener('change', onColumnChange);
+        // Bogus credentials count against their IP, a valid user has a quota of its own
+        let temp_dir = tempfile::tempdir().unwrap();
+        let settings = Settings {
+            api_requests_per_minute: 2,
+            web_users: vec![crate::auth::WebUser {
+                name: "ops".to_string(),
+                role: Role::Operator,
+                token: Some("ops-token".to_string()),
+                password: None,
+            }],
+            ..Settings::default()
+        };
+        let app = create_test_app_with_settings(temp_dir.path().to_str().unwrap(), settings);
+        let get = |token: &str| {
+            let mut request = Request::builder()
+                .uri("/api/filters")
+                .header("Authorization", format!("Bearer {}", token))
+                .body(Body::empty())
+                .unwrap();
+            request
+                .extensions_mut()
+                .insert(ConnectInfo(SocketAddr::from(([10, 0, 0, 3], 40000))));
+            app.clone().oneshot(request)
+        };
+        for token in ["guess-1", "guess-2"] {
+            let response = get(token).await.unwrap();
+            assert_eq!(response.status(), AxumStatusCode::UNAUTHORIZED);
+        }
+        let response = get("guess-3").await.unwrap();
+        assert_eq!(response.status(), AxumStatusCode::TOO_MANY_REQUESTS);
+        let response = get("ops-token").await.unwrap();
+        assert_eq!(response.status(), AxumStatusCode::OK);
+        assert_eq!(response.headers()["x-ratelimit-remaining"], "1");
+    }
 
-                    const nameSpan = document.createElement('span');
-                    nameSpan.textContent = col.name;
+    #[tokio::test]
+    async fn test_routes_enforce_roles() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let user = |name: &str, role: Role| crate::auth::WebUser {
+            name: name.to_string(),
+            role,
+            token: Some(format!("{}-token", name)),
+            password: None,
+        };
+        let settings = Settings {
+            web_users: vec![
+                user("viewer", Role::Viewer),
+                user("ops", Role::Operator),
+                user("root", Role::Admin),
+            ],
+            ..Settings::default()
+        };
+        let app = create_test_app_with_settings(temp_dir.path().to_str().unwrap(), settings);
+        let status = |uri: &str, token: &str| {
+            let request = Request::builder()
+                .uri(uri)
+                .header("Authorization", format!("Bearer {}", token))
+                .body(Body::empty())
+                .unwrap();
+            let app = app.clone();
+            async move { app.oneshot(request).await.unwrap().status() }
+        };
 
-                    const typeSpan = document.createElement('span');
-                    typeSpan.className = 'col-type';
-                    typeSpan.textContent = col.column_type;
+        assert_eq!(
+            status("/api/search?start=-1h&end=now", "viewer-token").await,
+            AxumStatusCode::OK
+        );
+        assert_eq!(
+            status("/api/export?start=-1h&end=now", "viewer-token").await,
+            AxumStatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status("/api/export?start=-1h&end=now", "ops-token").await,
+            AxumStatusCode::OK
+        );
+        assert_eq!(
+            status("/api/search?start=-1h&end=now", "ops-token").await,
+            AxumStatusCode::OK
+        );
+        assert_eq!(
+            status("/api/debug/queries", "ops-token").await,
+            AxumStatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status("/api/debug/queries", "root-token").await,
+            AxumStatusCode::OK
+        );
+    }
 
-                    label.appendChild(cb);
-                    label.appendChild(nameSpan);
-                    label.appendChild(typeSpan);
-                    grid.appendChild(label);
-                }});
+    #[tokio::test]
+    async fn test_alert_rule_sql_requires_admin() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let user = |name: &str, role: Role| crate::auth::WebUser {
+            name: name.to_string(),
+            role,
+            token: Some(format!("{}-token", name)),
+            password: None,
+        };
+        let settings = Settings {
+            web_users: vec![user("ops", Role::Operator), user("root", Role::Admin)],
+            ..Settings::default()
+        };
+        let app = create_test_app_with_settings(temp_dir.path().to_str().unwrap(), settings);
+        let create = |token: &str, name: &str, sql: &str| {
+            let request = Request::builder()
+                .method("POST")
+                .uri("/api/alert-rules")
+                .header("Authorization", format!("Bearer {}", token))
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(
+                    serde_json::json!({ "name": name, "sql": sql }).to_string(),
+                ))
+                .unwrap();
+            let app = app.clone();
+            async move { app.oneshot(request).await.unwrap().status() }
+        };
 
-                // Sync hidden input with current selection
-                syncColumns();
-            }} catch (error) {{
-                console.error('Failed to load columns:', error);
-                grid.textContent = 'Failed to load columns';
-            }}
+        assert_eq!(
+            create("ops-token", "errors", "priority <= 3").await,
+            AxumStatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            create("root-token", "errors", "priority <= 3").await,
+            AxumStatusCode::CREATED
+        );
+        assert_eq!(
+            create(
+                "root-token",
+                "shadow",
+                "message IN (SELECT content FROM read_text('/etc/shadow'))"
+            )
+            .await,
+            AxumStatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            create(
+                "root-token",
+                "copy",
+                "1) ; COPY journal_logs TO '/tmp/x' ; SELECT (1"
+            )
+            .await,
+            AxumStatusCode::BAD_REQUEST
+        );
+    }
 
-            function getSelectedColumns() {{
-                return Array.from(grid.querySelectorAll('input[type="checkbox"]:checked'))
-                    .map(cb => cb.value);
-            }}
+    #[tokio::test]
+    async fn test_api_purge_logs_requires_admin_token() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        {
+            let mut buffer = DuckDBBuffer::new(temp_dir.path()).unwrap();
+            for unit in ["crm.service", "crm.service", "other.service"] {
+                let mut fields = std::collections::HashMap::new();
+                fields.insert("MESSAGE".to_string(), "customer record".to_string());
+                fields.insert("_SYSTEMD_UNIT".to_string(), unit.to_string());
+                let entry = crate::log_entry::LogEntry::new(Utc::now(), fields);
+                buffer.add_entry(&entry).unwrap();
+            }
+        }
+        let data_dir = temp_dir.path().to_str().unwrap();
+        let purge = |token: Option<&str>, uri: &str| {
+            let mut request = Request::builder().method("DELETE").uri(uri);
+            if let Some(token) = token {
+                request = request.header("Authorization", format!("Bearer {}", token));
+            }
+            request.body(Body::empty()).unwrap()
+        };
 
-            function syncColumns() {{
-                const selected = getSelectedColumns();
-                columnsInput.value = selected.join(',');
-                try {{
-                    localStorage.setItem(STORAGE_KEY, JSON.stringify(selected));
-                }} catch(_) {{}}
-            }}
+        // Disabled without a configured token
+        let response = create_test_app(data_dir)
+            .oneshot(purge(Some("secret"), "/api/logs?unit=crm.service"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), AxumStatusCode::FORBIDDEN);
 
-            function onColumnChange() {{
-                syncColumns();
-                // Re-submit the search form to apply column changes
-                document.querySelector('form.search-form').submit();
-            }}
-        }})();
-    </script>
-</body>
-</html>"##,
-        html_escape(query_value),                // {0} search input value
-        html_escape(&params.start),              // {1} start time
-        html_escape(&params.end),                // {2} end time
-        hostname_options,                        // {3} hostname options
-        unit_options,                            // {4} unit options
-        priority_options,                        // {5} priority options
-        params.limit.min(100_000),               // {6} limit
-        params.columns.as_deref().unwrap_or(""), // {7} columns hidden input
-        results.len(),                           // {8} loaded row count
-        total_count,                             // {9} total count
-        display_names
-            .iter()
-            .map(|name| format!("<th>{}</th>", html_escape(name)))
-            .collect::<Vec<_>>()
-            .join(""), // {10} table headers
-        chunk_fragment,                          // {11} rows fragment
-    )
-}
+        let settings = Settings {
+            admin_token: Some("secret".to_string()),
+            ..Settings::default()
+        };
+        let app = create_test_app_with_settings(data_dir, settings);
+        let response = app
+            .clone()
+            .oneshot(purge(Some("wrong"), "/api/logs?unit=crm.service"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), AxumStatusCode::UNAUTHORIZED);
 
-fn render_log_chunk_fragment(
-    params: &SearchParams,
-    results: &[serde_json::Value],
-    display_names: &[String],
-    total_count: usize,
-) -> String {
-    let rows = render_log_rows(results, display_names);
-    let loaded_end = params.offset + results.len();
-    let col_span = display_names.len().max(1);
-    let load_more_row = if loaded_end < total_count {
-        let next_offset = loaded_end;
-        let next_url = build_log_chunk_url(params, next_offset);
-        format!(
-            r##"<tr id="load-more-logs"><td class="load-row" colspan="{}"><button hx-get="{}" hx-target="#load-more-logs" hx-swap="outerHTML">Load more</button></td></tr>"##,
-            col_span, next_url
-        )
-    } else if total_count == 0 {
-        format!(
-            r##"<tr id="load-more-logs"><td class="no-results" colspan="{}">No results found</td></tr>"##,
-            col_span
-        )
-    } else {
-        format!(
-            r##"<tr id="load-more-logs"><td class="load-row" colspan="{}">End of results</td></tr>"##,
-            col_span
-        )
-    };
+        let response = app
+            .clone()
+            .oneshot(purge(Some("secret"), "/api/logs"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), AxumStatusCode::BAD_REQUEST);
 
-    format!("{}{}", rows, load_more_row)
-}
+        let response = app
+            .oneshot(purge(
+                Some("secret"),
+                "/api/logs?unit=crm.service&start=-1h",
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), AxumStatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let result: PurgeResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(result.deleted, 2);
+    }
 
-fn render_log_rows(results: &[serde_json::Value], display_names: &[String]) -> String {
-    let mut out = String::new();
+    #[tokio::test]
+    async fn test_api_search_filters_on_source() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        {
+            let mut buffer = DuckDBBuffer::new(temp_dir.path()).unwrap();
+            for source in [LOCAL_SOURCE, "syslog:10.0.0.5", "syslog:10.0.0.5"] {
+                let mut fields = std::collections::HashMap::new();
+                fields.insert("MESSAGE".to_string(), "tagged".to_string());
+                let entry = crate::log_entry::LogEntry::new(Utc::now(), fields).with_source(source);
+                buffer.add_entry(&entry).unwrap();
+            }
+        }
+        let app = create_test_app(temp_dir.path().to_str().unwrap());
 
-    for row in results {
-        let Some(obj) = row.as_object() else {
-            continue;
-        };
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/api/search?start=-1h&end=now&source=syslog:10.0.0.5")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), AxumStatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let search: SearchResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(search.total, 2);
 
-        let priority_value = obj.get("priority").and_then(|v| {
-            v.as_i64()
-                .or_else(|| v.as_str().and_then(|s| s.parse::<i64>().ok()))
-        });
-        let row_class = match priority_value {
-            Some(p) if p <= 2 => "priority-critical",
-            Some(3) => "priority-error",
-            Some(4) => "priority-warning",
-            _ => "",
-        };
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/filters")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let filters: FilterValues = serde_json::from_slice(&body).unwrap();
+        assert_eq!(filters.sources, vec!["local", "syslog:10.0.0.5"]);
+    }
 
-        out.push_str(&format!("<tr class=\"{}\">", row_class));
-        for col in display_names {
-            let value = obj.get(col).unwrap_or(&serde_json::Value::Null);
-            let text = match value {
-                serde_json::Value::Null => String::new(),
-                serde_json::Value::String(s) => s.clone(),
-                _ => value.to_string(),
-            };
-            out.push_str(&format!("<td>{}</td>", html_escape(&text)));
+    #[tokio::test]
+    async fn test_api_search_total_counts_all_pages() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        {
+            let mut buffer = DuckDBBuffer::new(temp_dir.path()).unwrap();
+            for i in 0..25 {
+                let mut fields = std::collections::HashMap::new();
+                fields.insert("MESSAGE".to_string(), format!("line {}", i));
+                let entry =
+                    crate::log_entry::LogEntry::new(Utc::now() - Duration::minutes(1), fields);
+                buffer.add_entry(&entry).unwrap();
+            }
+        }
+        let app = create_test_app(temp_dir.path().to_str().unwrap());
+
+        for count_mode in ["exact", "estimate"] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .uri(format!(
+                            "/api/search?start=-1h&end=now&limit=10&count_mode={}",
+                            count_mode
+                        ))
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), AxumStatusCode::OK);
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            let search_response: SearchResponse = serde_json::from_slice(&body).unwrap();
+            assert_eq!(search_response.results.len(), 10);
+            // Small result sets are always counted exactly
+            assert_eq!(search_response.total, 25);
+            assert!(!search_response.total_is_estimate);
         }
-        out.push_str("</tr>");
     }
 
-    out
-}
+    #[tokio::test]
+    async fn test_api_storage_health_reports_breakdown() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        {
+            let mut buffer = DuckDBBuffer::new(temp_dir.path()).unwrap();
+            for (unit, count) in [("sshd.service", 3), ("cron.service", 1)] {
+                for i in 0..count {
+                    let mut fields = std::collections::HashMap::new();
+                    fields.insert("MESSAGE".to_string(), format!("{} line {}", unit, i));
+                    fields.insert("_SYSTEMD_UNIT".to_string(), unit.to_string());
+                    let entry =
+                        crate::log_entry::LogEntry::new(Utc::now() - Duration::minutes(1), fields);
+                    buffer.add_entry(&entry).unwrap();
+                }
+            }
+        }
+        let app = create_test_app(temp_dir.path().to_str().unwrap());
 
-fn build_log_chunk_url(params: &SearchParams, offset: usize) -> String {
-    format!(
-        "/htmx/logs/chunk?q={}&start={}&end={}&hostname={}&unit={}&limit={}&offset={}&sort={}&sort_dir={}{}{}",
-        url_encode(params.q.as_deref().unwrap_or("")),
-        url_encode(&params.start),
-        url_encode(&params.end),
-        url_encode(params.hostname.as_deref().unwrap_or("")),
-        url_encode(params.unit.as_deref().unwrap_or("")),
-        params.limit.min(100_000),
-        offset,
-        url_encode(&params.sort),
-        url_encode(&params.sort_dir),
-        params
-            .priority
-            .map(|p| format!("&priority={}", p))
-            .unwrap_or_default(),
-        params
-            .columns
-            .as_deref()
-            .map(|c| format!("&columns={}", url_encode(c)))
-            .unwrap_or_default()
-    )
-}
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/storage/health")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), AxumStatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let health: serde_json::Value = serde_json::from_slice(&body).unwrap();
 
-fn build_processes_html() -> String {
-    r##"<!DOCTYPE html>
-<html lang="en">
-<head>
-    <meta charset="UTF-8">
-    <meta name="viewport" content="width=device-width, initial-scale=1.0">
-    <title>livedata - Process Monitor</title>
-    <script src="https://unpkg.com/htmx.org@1.9.12"></script>
-    <style>
-        :root {
-            --bg: #272822;
-            --surface: #2d2e27;
-            --surface-alt: #3e3d32;
-            --text: #f8f8f2;
-            --muted: #a59f85;
-            --border: #49483e;
-            --accent: #a6e22e;
-            --accent-2: #66d9ef;
-            --warn: #fd971f;
-            --danger: #f92672;
+        assert_eq!(health["journal_log_count"], 4);
+        let top_units = health["top_units"].as_array().unwrap();
+        assert_eq!(top_units[0]["unit"], "sshd.service");
+        assert_eq!(top_units[0]["rows"], 3);
+        assert_eq!(top_units[1]["unit"], "cron.service");
+        assert!(top_units[1]["message_bytes"].as_i64().unwrap() > 0);
+
+        let day_total: i64 = health["rows_per_day"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|day| day["rows"].as_i64().unwrap())
+            .sum();
+        assert_eq!(day_total, 4);
+
+        let tables = health["tables"].as_array().unwrap();
+        assert!(tables.iter().any(|t| t["table_name"] == "process_metrics"));
+        assert!(health["wal_size_bytes"].is_u64());
+        assert!(health["indexes"].is_array());
+    }
+
+    #[test]
+    fn test_page_cursor_round_trip() {
+        let cursor = PageCursor {
+            timestamp_us: 1_768_660_245_123_456,
+            cursor: "s=abc;i=1f:x".to_string(),
+        };
+        let encoded = cursor.encode();
+        assert!(encoded.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_eq!(PageCursor::decode(&encoded), Some(cursor));
+        assert_eq!(PageCursor::decode("zz"), None);
+        assert_eq!(PageCursor::decode("abc"), None);
+    }
+
+    #[tokio::test]
+    async fn test_api_search_keyset_pagination() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        {
+            let mut buffer = DuckDBBuffer::new(temp_dir.path()).unwrap();
+            for i in 0..25 {
+                let mut fields = std::collections::HashMap::new();
+                fields.insert("MESSAGE".to_string(), format!("line {:02}", i));
+                fields.insert("__CURSOR".to_string(), format!("c{:02}", i));
+                let entry = crate::log_entry::LogEntry::new(
+                    Utc::now() - Duration::minutes(30) + Duration::seconds(i),
+                    fields,
+                );
+                buffer.add_entry(&entry).unwrap();
+            }
         }
-        body.theme-light {
-            --bg: #f8f8f2;
-            --surface: #efefe7;
-            --surface-alt: #ffffff;
-            --text: #272822;
-            --muted: #6f6b57;
-            --border: #b7b39e;
-            --accent: #3b7d15;
-            --accent-2: #0f8395;
-            --warn: #c15d00;
-            --danger: #c2175b;
+        let app = create_test_app(temp_dir.path().to_str().unwrap());
+        let fetch = |query: String| {
+            let app = app.clone();
+            async move {
+                let response = app
+                    .oneshot(
+                        Request::builder()
+                            .uri(format!(
+                                "/api/search?start=-1h&end=now&limit=10&columns=message{}",
+                                query
+                            ))
+                            .body(Body::empty())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+                assert_eq!(response.status(), AxumStatusCode::OK);
+                let body = response.into_body().collect().await.unwrap().to_bytes();
+                serde_json::from_slice::<SearchResponse>(&body).unwrap()
+            }
+        };
+        let messages = |page: &SearchResponse| -> Vec<String> {
+            page.results
+                .iter()
+                .map(|r| r["message"].as_str().unwrap().to_string())
+                .collect()
+        };
+
+        // Newest first; page keys are not leaked into the results
+        let first = fetch(String::new()).await;
+        assert_eq!(messages(&first)[0], "line 24");
+        assert_eq!(first.results[0].as_object().unwrap().len(), 1);
+        assert!(first.prev_cursor.is_none());
+
+        let second = fetch(format!("&after={}", first.next_cursor.clone().unwrap())).await;
+        assert_eq!(messages(&second)[0], "line 14");
+        let third = fetch(format!("&after={}", second.next_cursor.clone().unwrap())).await;
+        assert_eq!(
+            messages(&third),
+            vec!["line 04", "line 03", "line 02", "line 01", "line 00"]
+        );
+        assert!(third.next_cursor.is_none());
+
+        // Walking back lands on the same page
+        let back = fetch(format!("&before={}", third.prev_cursor.clone().unwrap())).await;
+        assert_eq!(messages(&back), messages(&second));
+    }
+
+    #[tokio::test]
+    async fn test_api_search_stream_returns_ndjson() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        {
+            let mut buffer = DuckDBBuffer::new(temp_dir.path()).unwrap();
+            for i in 0..(STREAM_CHUNK_ROWS + 5) {
+                let mut fields = std::collections::HashMap::new();
+                fields.insert("MESSAGE".to_string(), format!("line {}", i));
+                let entry = crate::log_entry::LogEntry::new(
+                    Utc::now() - Duration::minutes(1) + Duration::milliseconds(i as i64),
+                    fields,
+                );
+                buffer.add_entry(&entry).unwrap();
+            }
         }
-        body { font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, sans-serif; max-width: 1400px; margin: 0 auto; padding: 0; background-color: var(--bg); color: var(--text); }
-        .global-header { background: var(--surface); border-bottom: 2px solid var(--accent); box-shadow: 0 2px 4px rgba(0,0,0,0.2); margin-bottom: 20px; }
-        .global-header nav { display: flex; gap: 5px; padding: 15px 20px; max-width: 1400px; margin: 0 auto; }
-        .global-header nav a { color: var(--accent-2); text-decoration: none; font-size: 16px; font-weight: 500; padding: 10px 18px; border-radius: 4px; }
-        .global-header nav a:hover { background: var(--surface-alt); }
-        .global-header nav a.active { background-color: var(--accent); color: var(--bg); }
-        .container { padding: 0 20px 20px; }
-        .controls { display: flex; gap: 12px; align-items: end; margin-bottom: 14px; flex-wrap: wrap; }
-        input, button { padding: 8px 12px; border: 1px solid var(--border); border-radius: 4px; font-size: 14px; }
-        input { background: var(--surface-alt); color: var(--text); }
-        button { background-color: var(--accent); color: var(--bg); border: none; cursor: pointer; }
-        button:hover { background: var(--accent-2); }
-        table { width: 100%; border-collapse: collapse; background: var(--surface); box-shadow: 0 1px 3px rgba(0,0,0,0.25); }
-        th, td { padding: 10px; border-bottom: 1px solid var(--border); text-align: left; }
-        th { background: var(--surface-alt); color: var(--accent-2); }
-        .muted { color: var(--muted); }
-        .load-row { text-align: center; padding: 12px; }
-        .load-row button { background: var(--surface-alt); color: var(--accent-2); border: 1px solid var(--accent-2); }
-        .load-row button:hover { background: var(--accent-2); color: var(--bg); }
-        #storage-health { margin-bottom: 20px; padding: 15px 20px; background: var(--surface); border-radius: 4px; box-shadow: 0 1px 3px rgba(0,0,0,0.2); font-size: 14px; color: var(--text); border: 1px solid var(--border); }
-        #storage-health .health-item { display: inline-block; margin-right: 25px; }
-        #storage-health .health-label { font-weight: 600; margin-right: 5px; color: var(--muted); }
-        #storage-health .status-good { color: var(--accent); }
-        #storage-health .status-warning { color: var(--warn); }
-        #storage-health .status-critical { color: var(--danger); }
-        .theme-toggle { position: fixed; top: 10px; right: 12px; z-index: 1000; border: 1px solid var(--accent-2); background: var(--surface-alt); color: var(--accent-2); width: 36px; height: 36px; border-radius: 18px; padding: 0; display: inline-flex; align-items: center; justify-content: center; font-size: 18px; line-height: 1; }
-    </style>
-</head>
-<body class="theme-dark">
-    <button id="theme-toggle" class="theme-toggle" type="button" aria-label="Toggle theme">&#9680;</button>
-    <div class="global-header">
-        <nav>
-            <a href="/" target="_blank">Log Search</a>
-            <a href="/processes.html" target="_blank" class="active">Processes</a>
-        </nav>
-    </div>
-    <div class="container">
-        <div id="storage-health">
-            <span class="health-item"><span class="health-label">Storage:</span><span id="storage-info">Loading...</span></span>
-            <span class="health-item"><span class="health-label">Retention:</span><span id="retention-info">Loading...</span></span>
-        </div>
+        let app = create_test_app(temp_dir.path().to_str().unwrap());
+        let stream = |query: &str| {
+            app.clone().oneshot(
+                Request::builder()
+                    .uri(format!("/api/search/stream?start=-1h&end=now&{}", query))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+        };
 
-        <h1>Process Monitor</h1>
+        let response = stream("limit=5000&sort_dir=asc&columns=message")
+            .await
+            .unwrap();
+        assert_eq!(response.status(), AxumStatusCode::OK);
+        assert_eq!(
+            response.headers().get("content-type").unwrap(),
+            "application/x-ndjson"
+        );
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let lines: Vec<serde_json::Value> = std::str::from_utf8(&body)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), STREAM_CHUNK_ROWS + 5);
+        assert_eq!(lines[0]["message"], "line 0");
+        assert!(lines[0].get(PAGE_TS_KEY).is_none());
 
-        <form class="controls" hx-get="/htmx/processes/chunk" hx-target="#processes-body" hx-swap="innerHTML">
-            <div>
-                <label for="q">Search</label><br>
-                <input id="q" name="q" type="text" placeholder="Fuzzy search processes..." />
-            </div>
-            <div>
-                <label for="limit">Rows</label><br>
-                <input id="limit" name="limit" type="number" min="10" max="1000" value="100" />
-            </div>
-            <div>
-                <input name="offset" type="hidden" value="0" />
-                <button type="submit">Refresh</button>
-            </div>
-            <div class="muted" id="last-updated">Never</div>
-        </form>
+        // Other sorts page by offset; every row still comes out once
+        let response = stream("limit=5000&sort=priority&columns=message")
+            .await
+            .unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let messages: std::collections::HashSet<String> = std::str::from_utf8(&body)
+            .unwrap()
+            .lines()
+            .map(|line| {
+                serde_json::from_str::<serde_json::Value>(line).unwrap()["message"].to_string()
+            })
+            .collect();
+        assert_eq!(messages.len(), STREAM_CHUNK_ROWS + 5);
+    }
 
-        <table>
-            <thead>
-                <tr>
-                    <th>Timestamp</th>
-                    <th>PID</th>
-                    <th>PPID</th>
-                    <th>Name</th>
-                    <th>Status</th>
-                    <th>CPU %</th>
-                    <th>Memory</th>
-                    <th>VSize</th>
-                    <th>User</th>
-                    <th>Runtime</th>
-                    <th>Cmdline</th>
-                </tr>
-            </thead>
-            <tbody id="processes-body" hx-get="/htmx/processes/chunk?offset=0&limit=100" hx-trigger="load" hx-swap="innerHTML"></tbody>
-        </table>
-    </div>
+    #[tokio::test]
+    async fn test_api_search_structured_query() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        {
+            let mut buffer = DuckDBBuffer::new(temp_dir.path()).unwrap();
+            for (i, (unit, priority, message)) in [
+                ("nginx.service", "3", "connection refused"),
+                ("nginx.service", "6", "connection accepted"),
+                ("db.service", "3", "connection refused"),
+            ]
+            .into_iter()
+            .enumerate()
+            {
+                let mut fields = std::collections::HashMap::new();
+                fields.insert("MESSAGE".to_string(), message.to_string());
+                fields.insert("_SYSTEMD_UNIT".to_string(), unit.to_string());
+                fields.insert("PRIORITY".to_string(), priority.to_string());
+                let entry = crate::log_entry::LogEntry::new(
+                    Utc::now() - Duration::minutes(10) + Duration::seconds(i as i64),
+                    fields,
+                );
+                buffer.add_entry(&entry).unwrap();
+            }
+        }
+        let app = create_test_app(temp_dir.path().to_str().unwrap());
+        let search = |q: &str| {
+            let app = app.clone();
+            let uri = format!(
+                "/api/search?columns=_systemd_unit,message&q={}",
+                url_encode(q)
+            );
+            async move {
+                let response = app
+                    .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+                    .await
+                    .unwrap();
+                let status = response.status();
+                let body = response.into_body().collect().await.unwrap().to_bytes();
+                if status != AxumStatusCode::OK {
+                    return Err(status);
+                }
+                let search: SearchResponse = serde_json::from_slice(&body).unwrap();
+                Ok(search.total)
+            }
+        };
 
-    <script>
-        (function() {
-            const key = 'livedata-theme';
-            const body = document.body;
-            const btn = document.getElementById('theme-toggle');
-            const iconDark = '&#9680;';
-            const iconLight = '&#9681;';
+        assert_eq!(search("connection").await, Ok(3));
+        assert_eq!(
+            search(r#"unit:nginx.service "connection refused""#).await,
+            Ok(1)
+        );
+        assert_eq!(search("priority<=err -unit:db.*").await, Ok(1));
+        assert_eq!(search("-msg:refused").await, Ok(1));
+        assert_eq!(search("nope:value").await, Ok(0));
+
+        // filter[COLUMN] parameters on any column, combined with the query
+        for (filters, expected) in [
+            ("filter%5Bunit%5D=db.service", AxumStatusCode::OK),
+            (
+                "filter%5B_systemd_unit%5D=db.service,x&filter%5Bpriority%5D=3",
+                AxumStatusCode::OK,
+            ),
+            ("filter%5B_nope%5D=1", AxumStatusCode::BAD_REQUEST),
+        ] {
+            let uri = format!("/api/search?q=refused&{}", filters);
+            let response = app
+                .clone()
+                .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(response.status(), expected, "{}", filters);
+            if expected == AxumStatusCode::OK {
+                let body = response.into_body().collect().await.unwrap().to_bytes();
+                let search: SearchResponse = serde_json::from_slice(&body).unwrap();
+                assert_eq!(search.total, 1, "{}", filters);
+            }
+        }
+    }
 
-            function apply(theme) {
-                body.classList.remove('theme-dark', 'theme-light');
-                body.classList.add(theme === 'light' ? 'theme-light' : 'theme-dark');
-                btn.innerHTML = theme === 'light' ? iconLight : iconDark;
+    #[tokio::test]
+    async fn test_api_search_facets() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        {
+            let mut buffer = DuckDBBuffer::new(temp_dir.path()).unwrap();
+            for (i, (unit, host)) in [
+                ("a.service", "web1"),
+                ("a.service", "web2"),
+                ("a.service", "web1"),
+                ("b.service", "web1"),
+            ]
+            .into_iter()
+            .enumerate()
+            {
+                let mut fields = std::collections::HashMap::new();
+                fields.insert("MESSAGE".to_string(), format!("line {}", i));
+                fields.insert("_SYSTEMD_UNIT".to_string(), unit.to_string());
+                fields.insert("_HOSTNAME".to_string(), host.to_string());
+                let entry = crate::log_entry::LogEntry::new(
+                    Utc::now() - Duration::minutes(10) + Duration::seconds(i as i64),
+                    fields,
+                );
+                buffer.add_entry(&entry).unwrap();
             }
+        }
+        let app = create_test_app(temp_dir.path().to_str().unwrap());
+        let get = |uri: &str| {
+            app.clone()
+                .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+        };
 
-            const saved = localStorage.getItem(key);
-            apply(saved === 'light' ? 'light' : 'dark');
+        let response = get("/api/search?limit=1&facets=unit,hostname&facet_limit=1")
+            .await
+            .unwrap();
+        assert_eq!(response.status(), AxumStatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let search: SearchResponse = serde_json::from_slice(&body).unwrap();
+        // Facets count every match, not just the page
+        assert_eq!(search.results.len(), 1);
+        assert_eq!(
+            search.facets["unit"],
+            vec![FacetValue {
+                value: serde_json::json!("a.service"),
+                count: 3
+            }]
+        );
+        assert_eq!(search.facets["hostname"][0].count, 3);
 
-            btn.addEventListener('click', function() {
-                const next = body.classList.contains('theme-dark') ? 'light' : 'dark';
-                localStorage.setItem(key, next);
-                apply(next);
-            });
-        })();
+        // Facets follow the filters
+        let response = get("/api/search?unit=b.service&facets=hostname")
+            .await
+            .unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let search: SearchResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(search.facets.len(), 1);
+        assert_eq!(search.facets["hostname"].len(), 1);
+        assert_eq!(search.facets["hostname"][0].value, "web1");
 
-        (async function() {
-            async function updateStorageHealth() {
-                try {
-                    const response = await fetch('/api/storage/health');
-                    if (!response.ok) throw new Error('Failed to fetch storage health');
-                    const data = await response.json();
-                    const sizeGB = (data.database_size_bytes / (1024 * 1024 * 1024)).toFixed(2);
-                    const maxSizeGB = Math.max(data.retention_policy.log_max_size_gb, data.retention_policy.process_max_size_gb);
-                    const usagePercent = (parseFloat(sizeGB) / maxSizeGB) * 100;
-                    let statusClass = 'status-good';
-                    if (usagePercent >= 90) statusClass = 'status-critical';
-                    else if (usagePercent >= 75) statusClass = 'status-warning';
-                    document.getElementById('storage-info').innerHTML =
-                        `<span class="${statusClass}">${sizeGB}GB / ${maxSizeGB}GB</span> (${data.journal_log_count.toLocaleString()} logs, ${data.process_metric_count.toLocaleString()} metrics)`;
-                    document.getElementById('retention-info').textContent =
-                        `${data.retention_policy.log_retention_days}d logs / ${data.retention_policy.process_retention_days}d proc`;
-                } catch (error) {
-                    document.getElementById('storage-info').innerHTML = '<span class="status-critical">Error loading</span>';
-                    document.getElementById('retention-info').textContent = 'N/A';
-                }
+        let response = get("/api/search").await.unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let search: SearchResponse = serde_json::from_slice(&body).unwrap();
+        assert!(search.facets.is_empty());
+
+        let response = get("/api/search?facets=nope").await.unwrap();
+        assert_eq!(response.status(), AxumStatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_api_values_lists_distinct_values() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        {
+            let mut buffer = DuckDBBuffer::new(temp_dir.path()).unwrap();
+            for (i, comm) in ["sshd", "sshd", "ssh-agent", "cron", "SSHD"]
+                .into_iter()
+                .enumerate()
+            {
+                let mut fields = std::collections::HashMap::new();
+                fields.insert("MESSAGE".to_string(), format!("line {}", i));
+                fields.insert("_COMM".to_string(), comm.to_string());
+                let entry = crate::log_entry::LogEntry::new(
+                    Utc::now() - Duration::minutes(10) + Duration::seconds(i as i64),
+                    fields,
+                );
+                buffer.add_entry(&entry).unwrap();
             }
-            await updateStorageHealth();
-            setInterval(updateStorageHealth, 30000);
-        })();
-    </script>
-</body>
-</html>"##
-        .to_string()
-}
+        }
+        let app = create_test_app(temp_dir.path().to_str().unwrap());
+        let get = |uri: &str| {
+            app.clone()
+                .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+        };
 
-fn render_process_chunk_fragment(
-    params: &ProcessTableParams,
-    rows: &[ProcessMetricsRow],
-    total_count: usize,
-    timestamp: &str,
-) -> String {
-    let mut html = String::new();
-    for p in rows {
-        let ppid = p
-            .parent_pid
-            .map(|v| v.to_string())
-            .unwrap_or_else(|| "-".to_string());
-        let status = p.status.as_deref().unwrap_or("-");
-        let cmdline = p.cmdline.as_deref().unwrap_or("-");
-        html.push_str(&format!(
-            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{:.1}%</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td class=\"cmdline\">{}</td></tr>",
-            html_escape(timestamp),
-            p.pid,
-            html_escape(&ppid),
-            html_escape(&p.name),
-            html_escape(status),
-            p.cpu_usage,
-            html_escape(&format_bytes(p.mem_usage)),
-            html_escape(&format_bytes(p.virtual_memory)),
-            html_escape(p.user.as_deref().unwrap_or("-")),
-            html_escape(&format_runtime(p.runtime)),
-            html_escape(cmdline),
-        ));
+        let response = get("/api/values?column=comm&prefix=ssh").await.unwrap();
+        assert_eq!(response.status(), AxumStatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let values: ColumnValues = serde_json::from_slice(&body).unwrap();
+        assert_eq!(values.column, "comm");
+        let counts: Vec<(&str, i64)> = values
+            .values
+            .iter()
+            .map(|v| (v.value.as_str().unwrap(), v.count))
+            .collect();
+        assert_eq!(counts, vec![("sshd", 2), ("SSHD", 1), ("ssh-agent", 1)]);
+
+        let response = get("/api/values?column=_comm&limit=1").await.unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let values: ColumnValues = serde_json::from_slice(&body).unwrap();
+        assert_eq!(values.values.len(), 1);
+
+        let response = get("/api/values?column=nope").await.unwrap();
+        assert_eq!(response.status(), AxumStatusCode::BAD_REQUEST);
     }
 
-    let loaded_end = params.offset + rows.len();
-    if loaded_end < total_count {
-        let next_offset = loaded_end;
-        let next_url = format!(
-            "/htmx/processes/chunk?q={}&limit={}&offset={}",
-            url_encode(params.q.as_deref().unwrap_or("")),
-            params.limit.clamp(10, 1000),
-            next_offset
+    #[tokio::test]
+    async fn test_api_processes_filters_sorts_and_pages() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        {
+            let mut buffer = DuckDBBuffer::new(temp_dir.path()).unwrap();
+            let process =
+                |pid: u32, name: &str, cpu: f32, user: &str| crate::process_monitor::ProcessInfo {
+                    pid,
+                    name: name.to_string(),
+                    cpu_percent: cpu,
+                    memory_bytes: 1000 * pid as u64,
+                    user_id: Some(format!("Uid({})", user)),
+                    runtime_secs: 1,
+                    cmd: Vec::new(),
+                    virtual_memory_bytes: 0,
+                    status: "Run".to_string(),
+                    parent_pid: None,
+                    read_bytes_per_sec: 0.0,
+                    // Mostly idle, but busy writing
+                    write_bytes_per_sec: if pid == 1 { 5000.0 } else { 0.0 },
+                    num_threads: Some(pid),
+                    open_fd_count: None,
+                    unit: Some(format!("{}.service", name)),
+                    container_id: None,
+                };
+            let processes = vec![
+                process(1, "systemd", 0.5, "0"),
+                process(20, "postgres", 40.0, "70"),
+                process(21, "postgres", 10.0, "70"),
+                process(30, "nginx", 25.0, "33"),
+            ];
+            buffer
+                .add_process_metrics(processes, Utc::now() - Duration::seconds(5))
+                .unwrap();
+        }
+        let app = create_test_app(temp_dir.path().to_str().unwrap());
+        let get = |uri: &str| {
+            app.clone()
+                .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+        };
+        let body = |response: Response| async move {
+            let bytes = response.into_body().collect().await.unwrap().to_bytes();
+            serde_json::from_slice::<serde_json::Value>(&bytes).unwrap()
+        };
+
+        // Busiest first by default
+        let all = body(get("/api/processes").await.unwrap()).await;
+        assert_eq!(all["total"], 4);
+        let pids: Vec<u64> = all["processes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|p| p["pid"].as_u64().unwrap())
+            .collect();
+        assert_eq!(pids, vec![20, 30, 21, 1]);
+
+        let page = body(
+            get("/api/processes?min_cpu=5&sort=mem&sort_dir=asc&limit=2&offset=1&fields=pid,name")
+                .await
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(page["total"], 3);
+        assert_eq!(
+            page["processes"],
+            serde_json::json!([
+                { "pid": 21, "name": "postgres" },
+                { "pid": 30, "name": "nginx" },
+            ])
         );
-        html.push_str(&format!(
-            r##"<tr id="load-more-processes"><td class="load-row" colspan="11"><button hx-get="{}" hx-target="#load-more-processes" hx-swap="outerHTML">Load more</button></td></tr>"##,
-            next_url
-        ));
-    } else if total_count == 0 {
-        html.push_str(
-            r##"<tr id="load-more-processes"><td class="load-row muted" colspan="11">No processes found</td></tr>"##,
+
+        let filtered = body(
+            get("/api/processes?name=POST&user=70&min_mem=21000")
+                .await
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(filtered["total"], 1);
+        assert_eq!(filtered["processes"][0]["pid"], 21);
+
+        let by_unit = body(get("/api/processes?unit=nginx.service").await.unwrap()).await;
+        assert_eq!(by_unit["total"], 1);
+        assert_eq!(by_unit["processes"][0]["pid"], 30);
+
+        let threads = body(
+            get("/api/processes?sort=threads&limit=1&fields=pid,num_threads,open_fd_count")
+                .await
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(
+            threads["processes"],
+            serde_json::json!([{ "pid": 30, "num_threads": 30, "open_fd_count": null }])
         );
-    } else {
-        html.push_str(
-            r##"<tr id="load-more-processes"><td class="load-row muted" colspan="11">End of results</td></tr>"##,
+
+        let io = body(
+            get("/api/processes?sort=io&limit=1&fields=pid,write_bytes_per_sec")
+                .await
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(
+            io["processes"],
+            serde_json::json!([{ "pid": 1, "write_bytes_per_sec": 5000.0 }])
         );
-    }
 
-    html
-}
+        let response = get("/api/processes?fields=pid,colour").await.unwrap();
+        assert_eq!(response.status(), AxumStatusCode::BAD_REQUEST);
+        let response = get("/api/processes?sort=colour").await.unwrap();
+        assert_eq!(response.status(), AxumStatusCode::BAD_REQUEST);
+    }
 
-fn fuzzy_match(query: &str, text: &str) -> bool {
-    let mut text_chars = text.chars();
-    for qc in query.chars() {
-        if !text_chars.any(|tc| tc == qc) {
-            return false;
+    #[tokio::test]
+    async fn test_api_system_history() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let base = (Utc::now() - Duration::minutes(30))
+            .duration_trunc(Duration::minutes(1))
+            .unwrap();
+        {
+            let mut buffer = DuckDBBuffer::new(temp_dir.path()).unwrap();
+            for (offset, load, used) in [(0, 1.0, 100), (30, 3.0, 300), (60, 0.5, 200)] {
+                let sample = crate::process_monitor::SystemSample {
+                    load_1: load,
+                    memory_used_bytes: used,
+                    disks: vec![crate::process_monitor::DiskUsage {
+                        mount_point: "/".to_string(),
+                        total_bytes: 1000,
+                        available_bytes: 400,
+                    }],
+                    ..Default::default()
+                };
+                buffer
+                    .add_system_metrics(&sample, base + Duration::seconds(offset))
+                    .unwrap();
+            }
         }
+        let app = create_test_app(temp_dir.path().to_str().unwrap());
+        let get = |uri: &str| {
+            app.clone()
+                .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+        };
+
+        let response = get("/api/system/history?metrics=load_1,disk_used_bytes&bin=1m")
+            .await
+            .unwrap();
+        assert_eq!(response.status(), AxumStatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let history: SystemHistoryResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(history.bin_seconds, 60);
+        type Series<'a> = (&'a str, &'a str, Vec<(f64, f64)>);
+        let series: Vec<Series> = history
+            .series
+            .iter()
+            .map(|s| {
+                let points = s.points.iter().map(|p| (p.avg, p.max)).collect();
+                (s.metric.as_str(), s.device.as_str(), points)
+            })
+            .collect();
+        assert_eq!(
+            series,
+            vec![
+                ("disk_used_bytes", "/", vec![(600.0, 600.0), (600.0, 600.0)]),
+                ("load_1", "", vec![(2.0, 3.0), (0.5, 0.5)]),
+            ]
+        );
+
+        let response = get("/api/system/history?metrics=entropy").await.unwrap();
+        assert_eq!(response.status(), AxumStatusCode::BAD_REQUEST);
     }
-    true
-}
 
-fn format_bytes(bytes: f64) -> String {
-    if bytes <= 0.0 {
-        return "-".to_string();
+    #[tokio::test]
+    async fn test_api_gpu_history() {
+        use crate::gpu::{GpuDevice, GpuProcess, GpuSample};
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let base = (Utc::now() - Duration::minutes(30))
+            .duration_trunc(Duration::minutes(1))
+            .unwrap();
+        {
+            let mut buffer = DuckDBBuffer::new(temp_dir.path()).unwrap();
+            for (offset, utilization, used) in [(0, 20.0, 100), (30, 60.0, 300)] {
+                let sample = GpuSample {
+                    devices: vec![GpuDevice {
+                        device: "nvidia0".to_string(),
+                        name: "Tesla T4".to_string(),
+                        utilization_percent: Some(utilization),
+                        memory_used_bytes: Some(used),
+                        memory_total_bytes: Some(1000),
+                    }],
+                    processes: vec![GpuProcess {
+                        device: "nvidia0".to_string(),
+                        pid: 4242,
+                        memory_used_bytes: used / 2,
+                    }],
+                };
+                buffer
+                    .add_gpu_metrics(&sample, base + Duration::seconds(offset))
+                    .unwrap();
+            }
+        }
+        let app = create_test_app(temp_dir.path().to_str().unwrap());
+        let get = |uri: &str| {
+            app.clone()
+                .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+        };
+
+        let response = get("/api/gpu/history?bin=1m").await.unwrap();
+        assert_eq!(response.status(), AxumStatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let history: GpuHistoryResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(history.series.len(), 1);
+        let series = &history.series[0];
+        assert_eq!(series.device, "nvidia0");
+        assert_eq!(series.name.as_deref(), Some("Tesla T4"));
+        assert_eq!(series.pid, None);
+        assert_eq!(series.points.len(), 1);
+        assert_eq!(series.points[0].avg_utilization_percent, Some(40.0));
+        assert_eq!(series.points[0].max_memory_used_bytes, Some(300.0));
+        assert_eq!(series.points[0].memory_total_bytes, Some(1000.0));
+
+        let response = get("/api/gpu/history?bin=1m&pid=4242").await.unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let history: GpuHistoryResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(history.series.len(), 1);
+        assert_eq!(history.series[0].pid, Some(4242));
+        assert_eq!(
+            history.series[0].points[0].avg_memory_used_bytes,
+            Some(100.0)
+        );
+        assert_eq!(history.series[0].points[0].avg_utilization_percent, None);
     }
-    let units = ["B", "KB", "MB", "GB", "TB", "PB"];
-    let mut value = bytes;
-    let mut idx = 0usize;
-    while value >= 1024.0 && idx < units.len() - 1 {
-        value /= 1024.0;
-        idx += 1;
+
+    #[tokio::test]
+    async fn test_alert_rules_api() {
+        use crate::notifications::AlertState;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        {
+            let mut buffer = DuckDBBuffer::new(temp_dir.path()).unwrap();
+            let alert = |minutes: i64, state| AlertEvent {
+                timestamp: Utc::now() - Duration::minutes(minutes),
+                rule: "disk errors".to_string(),
+                source: "log".to_string(),
+                state,
+                value: 3.0,
+                threshold: 1.0,
+                message: "3 entries matched in the last 5m (threshold 1)".to_string(),
+                samples: Vec::new(),
+                link: None,
+                tags: Vec::new(),
+                details: BTreeMap::new(),
+            };
+            buffer
+                .add_alert_events(&[
+                    alert(30, AlertState::Firing),
+                    alert(20, AlertState::Resolved),
+                    alert(10, AlertState::Firing),
+                ])
+                .unwrap();
+        }
+        let settings = Settings {
+            alert_rules: vec![AlertRule {
+                name: "disk errors".to_string(),
+                query: "disk".to_string(),
+                threshold: 1,
+                window: "5m".to_string(),
+                interval: "1m".to_string(),
+                ..Default::default()
+            }],
+            ..Settings::default()
+        };
+        let app = create_test_app_with_settings(temp_dir.path().to_str().unwrap(), settings);
+        let send = |method: &str, uri: &str, body: Option<serde_json::Value>| {
+            let request = Request::builder()
+                .method(method)
+                .uri(uri)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(body.map_or_else(Body::empty, |body| Body::from(body.to_string())))
+                .unwrap();
+            app.clone().oneshot(request)
+        };
+        let json = |response: Response| async move {
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+        };
+
+        let response = send("GET", "/api/alerts?state=firing", None).await.unwrap();
+        assert_eq!(response.status(), AxumStatusCode::OK);
+        let alerts = json(response).await;
+        assert_eq!(alerts["firing"].as_array().unwrap().len(), 1);
+        assert_eq!(alerts["history"].as_array().unwrap().len(), 2);
+
+        let rule = serde_json::json!({
+            "name": "cron failures",
+            "query": "unit:cron.service priority<=err",
+            "threshold": 0,
+            "tags": ["batch"],
+        });
+        let response = send("POST", "/api/alert-rules", Some(rule.clone()))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), AxumStatusCode::CREATED);
+        let response = send("POST", "/api/alert-rules", Some(rule)).await.unwrap();
+        assert_eq!(response.status(), AxumStatusCode::CONFLICT);
+        let response = send(
+            "POST",
+            "/api/alert-rules",
+            Some(serde_json::json!({ "name": "bad", "query": "\"unterminated" })),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), AxumStatusCode::BAD_REQUEST);
+        for window in ["999999999999999d", "100000000d", "32d"] {
+            let response = send(
+                "POST",
+                "/api/alert-rules",
+                Some(serde_json::json!({ "name": "long", "window": window })),
+            )
+            .await
+            .unwrap();
+            assert_eq!(response.status(), AxumStatusCode::BAD_REQUEST);
+        }
+
+        let response = send(
+            "PUT",
+            "/api/alert-rules/cron%20failures",
+            Some(serde_json::json!({ "name": "ignored", "query": "unit:cron.service", "window": "15m" })),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), AxumStatusCode::OK);
+        let response = send("DELETE", "/api/alert-rules/disk%20errors", None)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), AxumStatusCode::CONFLICT);
+
+        let rules = json(send("GET", "/api/alert-rules", None).await.unwrap()).await;
+        let rules = rules.as_array().unwrap();
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[0]["origin"], "config");
+        assert_eq!(rules[0]["firing"], true);
+        assert_eq!(rules[1]["name"], "cron failures");
+        assert_eq!(rules[1]["origin"], "api");
+        assert_eq!(rules[1]["window"], "15m");
+        assert_eq!(rules[1]["firing"], false);
+
+        let response = send("POST", "/api/alert-rules/cron%20failures/test", None)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), AxumStatusCode::OK);
+        let test = json(response).await;
+        assert_eq!(test["state"], "firing");
+        assert_eq!(test["value"], 0.0);
+
+        let response = send("DELETE", "/api/alert-rules/cron%20failures", None)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), AxumStatusCode::NO_CONTENT);
+        let response = send("POST", "/api/alert-rules/cron%20failures/test", None)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), AxumStatusCode::NOT_FOUND);
     }
-    format!("{:.1} {}", value, units[idx])
-}
 
-fn format_runtime(seconds: u64) -> String {
-    if seconds < 60 {
-        return format!("{}s", seconds);
+    #[tokio::test]
+    async fn test_unit_failures_api() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        {
+            let mut buffer = DuckDBBuffer::new(temp_dir.path()).unwrap();
+            let entry = |seconds: i64, fields: &[(&str, &str)]| {
+                LogEntry::new(
+                    Utc::now() - Duration::seconds(seconds),
+                    fields
+                        .iter()
+                        .map(|(k, v)| (k.to_string(), v.to_string()))
+                        .collect(),
+                )
+            };
+            buffer
+                .add_entry(&entry(
+                    10,
+                    &[
+                        ("_SYSTEMD_UNIT", "backup.service"),
+                        ("_HOSTNAME", "db1"),
+                        ("MESSAGE", "rsync: connection unexpectedly closed"),
+                    ],
+                ))
+                .unwrap();
+            buffer
+                .add_entry(&entry(
+                    5,
+                    &[
+                        ("_PID", "1"),
+                        ("_HOSTNAME", "db1"),
+                        ("MESSAGE", "backup.service: Failed with result 'exit-code'."),
+                        ("MESSAGE_ID", crate::unit_failures::UNIT_FAILED_MESSAGE_ID),
+                        ("UNIT", "backup.service"),
+                        ("UNIT_RESULT", "exit-code"),
+                    ],
+                ))
+                .unwrap();
+        }
+        let app = create_test_app(temp_dir.path().to_str().unwrap());
+        let get = |uri: &str| {
+            app.clone()
+                .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+        };
+
+        let response = get("/api/unit-failures?unit=backup.service").await.unwrap();
+        assert_eq!(response.status(), AxumStatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let failures: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(failures.as_array().unwrap().len(), 1);
+        assert_eq!(failures[0]["hostname"], "db1");
+        assert_eq!(failures[0]["result"], "exit-code");
+        assert_eq!(
+            failures[0]["last_lines"][0]["message"],
+            "rsync: connection unexpectedly closed"
+        );
+
+        let body = get("/api/unit-failures?hostname=web1")
+            .await
+            .unwrap()
+            .into_body()
+            .collect()
+            .await
+            .unwrap()
+            .to_bytes();
+        let failures: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert!(failures.as_array().unwrap().is_empty());
     }
-    let minutes = seconds / 60;
-    if minutes < 60 {
-        let rem = seconds % 60;
-        return if rem > 0 {
-            format!("{}m {}s", minutes, rem)
-        } else {
-            format!("{}m", minutes)
+
+    #[tokio::test]
+    async fn test_silences_api() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let app = create_test_app(temp_dir.path().to_str().unwrap());
+        let send = |method: &str, uri: &str, body: Option<serde_json::Value>| {
+            let request = Request::builder()
+                .method(method)
+                .uri(uri)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(body.map_or_else(Body::empty, |body| Body::from(body.to_string())))
+                .unwrap();
+            app.clone().oneshot(request)
         };
+        let json = |response: Response| async move {
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+        };
+
+        let response = send(
+            "POST",
+            "/api/silences",
+            Some(serde_json::json!({ "ends_at": "1h", "comment": "nothing to match" })),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), AxumStatusCode::BAD_REQUEST);
+        let response = send(
+            "POST",
+            "/api/silences",
+            Some(serde_json::json!({ "rule": "disk*", "ends_at": "-1h" })),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), AxumStatusCode::BAD_REQUEST);
+        let response = send(
+            "POST",
+            "/api/silences",
+            Some(serde_json::json!({ "rule": "disk*", "ends_at": "9999999999999d" })),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), AxumStatusCode::BAD_REQUEST);
+
+        let response = send(
+            "POST",
+            "/api/silences",
+            Some(serde_json::json!({
+                "rule": "postgres *",
+                "tag": "db",
+                "ends_at": "2h",
+                "comment": "major version upgrade",
+            })),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), AxumStatusCode::CREATED);
+        let silence = json(response).await;
+        let id = silence["id"].as_i64().unwrap();
+        assert_eq!(silence["rule"], "postgres *");
+        assert_eq!(silence["source"], serde_json::Value::Null);
+
+        let silences = json(send("GET", "/api/silences", None).await.unwrap()).await;
+        assert_eq!(silences.as_array().unwrap().len(), 1);
+        assert_eq!(silences[0]["id"], id);
+        assert_eq!(silences[0]["comment"], "major version upgrade");
+
+        let uri = format!("/api/silences/{}", id);
+        let response = send("DELETE", &uri, None).await.unwrap();
+        assert_eq!(response.status(), AxumStatusCode::NO_CONTENT);
+        let response = send("DELETE", &uri, None).await.unwrap();
+        assert_eq!(response.status(), AxumStatusCode::NOT_FOUND);
+
+        let silences = json(send("GET", "/api/silences", None).await.unwrap()).await;
+        assert!(silences.as_array().unwrap().is_empty());
+        let silences = json(send("GET", "/api/silences?all=true", None).await.unwrap()).await;
+        assert_eq!(silences.as_array().unwrap().len(), 1);
     }
-    let hours = minutes / 60;
-    if hours < 24 {
-        let rem = minutes % 60;
-        return if rem > 0 {
-            format!("{}h {}m", hours, rem)
-        } else {
-            format!("{}h", hours)
+
+    #[tokio::test]
+    async fn test_api_process_tree() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let sampled = Utc::now() - Duration::minutes(10);
+        {
+            let mut buffer = DuckDBBuffer::new(temp_dir.path()).unwrap();
+            let process = |pid: u32, ppid: Option<u32>, name: &str, cpu: f32| {
+                crate::process_monitor::ProcessInfo {
+                    pid,
+                    name: name.to_string(),
+                    cpu_percent: cpu,
+                    memory_bytes: 100,
+                    user_id: None,
+                    runtime_secs: 1,
+                    cmd: vec![name.to_string()],
+                    virtual_memory_bytes: 0,
+                    status: "Run".to_string(),
+                    parent_pid: ppid,
+                    read_bytes_per_sec: 0.0,
+                    write_bytes_per_sec: 0.0,
+                    num_threads: None,
+                    open_fd_count: None,
+                    unit: None,
+                    container_id: None,
+                }
+            };
+            let processes = vec![
+                process(1, None, "systemd", 0.0),
+                process(200, Some(1), "cron", 0.0),
+                process(300, Some(1), "sshd", 1.0),
+                process(201, Some(200), "backup.sh", 0.0),
+                process(202, Some(201), "gzip", 95.0),
+                // Its parent already exited
+                process(400, Some(399), "orphan", 0.0),
+            ];
+            buffer.add_process_metrics(processes, sampled).unwrap();
+            // A later sample without the runaway child
+            buffer
+                .add_process_metrics(vec![process(1, None, "systemd", 0.0)], Utc::now())
+                .unwrap();
+        }
+        let app = create_test_app(temp_dir.path().to_str().unwrap());
+        let get = |uri: String| {
+            app.clone()
+                .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
         };
+        let at = url_encode(&(sampled + Duration::seconds(1)).to_rfc3339());
+
+        let response = get(format!("/api/processes/tree?at={}", at)).await.unwrap();
+        assert_eq!(response.status(), AxumStatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let tree: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let roots = tree["roots"].as_array().unwrap();
+        assert_eq!(roots.len(), 2);
+        assert_eq!(roots[0]["pid"], 1);
+        assert_eq!(roots[0]["total_cpu_usage"], 96.0);
+        assert_eq!(roots[1]["name"], "orphan");
+        // cron's subtree holds the busy gzip, so it sorts ahead of sshd
+        let cron = &roots[0]["children"][0];
+        assert_eq!(cron["name"], "cron");
+        assert_eq!(cron["children"][0]["children"][0]["name"], "gzip");
+
+        let response = get(format!("/api/processes/tree?at={}&pid=201", at))
+            .await
+            .unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let tree: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(tree["roots"][0]["name"], "backup.sh");
+        assert_eq!(tree["roots"][0]["total_mem_usage"], 200.0);
+
+        // The latest sample no longer has the child
+        let response = get("/api/processes/tree?pid=202".to_string())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), AxumStatusCode::NOT_FOUND);
     }
-    let days = hours / 24;
-    let rem = hours % 24;
-    if rem > 0 {
-        format!("{}d {}h", days, rem)
-    } else {
-        format!("{}d", days)
+
+    #[tokio::test]
+    async fn test_api_process_history() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let base = (Utc::now() - Duration::minutes(30))
+            .duration_trunc(Duration::minutes(1))
+            .unwrap();
+        {
+            let mut buffer = DuckDBBuffer::new(temp_dir.path()).unwrap();
+            let process = |pid: u32, name: &str, cpu: f32| crate::process_monitor::ProcessInfo {
+                pid,
+                name: name.to_string(),
+                cpu_percent: cpu,
+                memory_bytes: 100 * pid as u64,
+                user_id: None,
+                runtime_secs: 1,
+                cmd: Vec::new(),
+                virtual_memory_bytes: 0,
+                status: "Run".to_string(),
+                parent_pid: None,
+                read_bytes_per_sec: 0.0,
+                write_bytes_per_sec: 0.0,
+                num_threads: Some(2),
+                open_fd_count: Some(pid * 10),
+                unit: Some(format!("{}.service", name)),
+                container_id: None,
+            };
+            let samples = [
+                (
+                    0,
+                    vec![process(10, "worker", 10.0), process(11, "worker", 30.0)],
+                ),
+                (
+                    20,
+                    vec![process(10, "worker", 20.0), process(12, "other", 90.0)],
+                ),
+                (60, vec![process(10, "worker", 5.0)]),
+            ];
+            for (offset, processes) in samples {
+                buffer
+                    .add_process_metrics(processes, base + Duration::seconds(offset))
+                    .unwrap();
+            }
+        }
+        let app = create_test_app(temp_dir.path().to_str().unwrap());
+        let get = |uri: &str| {
+            app.clone()
+                .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+        };
+
+        let response = get("/api/processes/history?name=worker&bin=1m")
+            .await
+            .unwrap();
+        assert_eq!(response.status(), AxumStatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let history: ProcessHistoryResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(history.bin_seconds, 60);
+        let points: Vec<(f64, f64, i64)> = history
+            .points
+            .iter()
+            .map(|p| (p.avg_cpu_usage, p.max_cpu_usage, p.processes))
+            .collect();
+        assert_eq!(points, vec![(30.0, 40.0, 2), (5.0, 5.0, 1)]);
+        assert_eq!(history.points[0].max_mem_usage, 2100.0);
+        assert_eq!(history.points[0].avg_threads, 3.0);
+        assert_eq!(history.points[0].max_open_fds, 210.0);
+
+        let response = get("/api/processes/history?pid=11&bin=1m").await.unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let history: ProcessHistoryResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(history.points.len(), 1);
+        assert_eq!(history.points[0].avg_cpu_usage, 30.0);
+
+        let response = get("/api/processes/history?unit=other.service&bin=1m")
+            .await
+            .unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let history: ProcessHistoryResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(history.unit.as_deref(), Some("other.service"));
+        let points: Vec<f64> = history.points.iter().map(|p| p.avg_cpu_usage).collect();
+        assert_eq!(points, vec![90.0]);
+
+        let response = get("/api/processes/history").await.unwrap();
+        assert_eq!(response.status(), AxumStatusCode::BAD_REQUEST);
     }
-}
 
-/// HTML escape helper
-fn html_escape(s: &str) -> String {
-    s.replace('&', "&amp;")
-        .replace('<', "&lt;")
-        .replace('>', "&gt;")
-        .replace('"', "&quot;")
-        .replace('\'', "&#x27;")
-}
+    #[tokio::test]
+    async fn test_process_delta_samples_are_carried_forward() {
+        use crate::process_monitor::{ProcessDeltaTracker, ProcessInfo};
 
-/// URL encode helper
-fn url_encode(s: &str) -> String {
-    let mut result = String::with_capacity(s.len() * 3);
-    for c in s.chars() {
-        match c {
-            'A'..='Z' | 'a'..='z' | '0'..='9' | '-' | '_' | '.' | '~' => result.push(c),
-            ' ' => result.push('+'),
-            _ => {
-                for b in c.to_string().as_bytes() {
-                    result.push_str(&format!("%{:02X}", b));
-                }
+        let temp_dir = tempfile::tempdir().unwrap();
+        let base = (Utc::now() - Duration::minutes(30))
+            .duration_trunc(Duration::minutes(1))
+            .unwrap();
+        {
+            let mut buffer = DuckDBBuffer::new(temp_dir.path()).unwrap();
+            let process = |pid: u32, cpu: f32| ProcessInfo {
+                pid,
+                name: "worker".to_string(),
+                cpu_percent: cpu,
+                memory_bytes: 1000,
+                user_id: None,
+                runtime_secs: 1,
+                cmd: Vec::new(),
+                virtual_memory_bytes: 0,
+                status: "Run".to_string(),
+                parent_pid: None,
+                read_bytes_per_sec: 0.0,
+                write_bytes_per_sec: 0.0,
+                num_threads: None,
+                open_fd_count: None,
+                unit: None,
+                container_id: None,
+            };
+            let mut tracker = ProcessDeltaTracker::new(Some(1.0), Duration::minutes(5));
+            let samples = [
+                (0, vec![process(10, 10.0), process(11, 30.0)]),
+                // Only pid 10 changed
+                (20, vec![process(10, 20.0), process(11, 30.0)]),
+                // pid 11 exited
+                (40, vec![process(10, 20.0)]),
+            ];
+            for (offset, processes) in samples {
+                let timestamp = base + Duration::seconds(offset);
+                let delta = tracker.select(processes, timestamp);
+                buffer
+                    .add_process_sample(delta.processes, timestamp, delta.keyframe)
+                    .unwrap();
             }
         }
-    }
-    result
-}
+        let app = create_test_app(temp_dir.path().to_str().unwrap());
+        let get = |uri: String| {
+            app.clone()
+                .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+        };
 
-/// Create router for testing
-#[cfg(test)]
-fn create_test_app(data_dir: &str) -> Router {
-    let process_monitor = Arc::new(ProcessMonitor::new());
-    let settings = Settings::default();
-    let buffer = Arc::new(Mutex::new(
-        DuckDBBuffer::new(data_dir).expect("Failed to create test buffer"),
-    ));
-    let state = Arc::new(AppState::new(data_dir, buffer, process_monitor, settings));
-    Router::new()
-        .route("/", get(search_ui))
-        .route("/htmx/logs/chunk", get(htmx_logs_chunk))
-        .route("/api/search", get(api_search))
-        .route("/api/columns", get(api_columns))
-        .route("/api/filters", get(api_filters))
-        .route("/api/processes", get(api_processes))
-        .route("/htmx/processes/chunk", get(htmx_processes_chunk))
-        .route("/api/storage/health", get(api_storage_health))
-        .route("/health", get(health))
-        .with_state(state)
-}
+        let response = get("/api/processes/history?name=worker&bin=1m".to_string())
+            .await
+            .unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let history: ProcessHistoryResponse = serde_json::from_slice(&body).unwrap();
+        // Per-sample sums 40, 50 and 20
+        assert_eq!(history.points.len(), 1);
+        assert!((history.points[0].avg_cpu_usage - 110.0 / 3.0).abs() < 1e-9);
+        assert_eq!(history.points[0].max_cpu_usage, 50.0);
+        assert_eq!(history.points[0].processes, 2);
+
+        let at = url_encode(&(base + Duration::seconds(30)).to_rfc3339());
+        let response = get(format!("/api/processes/tree?at={}", at)).await.unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let tree: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(tree["roots"].as_array().unwrap().len(), 2);
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use axum::body::Body;
-    use axum::http::{Request, StatusCode as AxumStatusCode};
-    use http_body_util::BodyExt;
-    use tower::ServiceExt;
+        let response = get("/api/processes/tree".to_string()).await.unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let tree: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let roots = tree["roots"].as_array().unwrap();
+        assert_eq!(roots.len(), 1);
+        assert_eq!(roots[0]["pid"], 10);
+        assert_eq!(roots[0]["cpu_usage"], 20.0);
+    }
 
-    #[test]
-    fn test_parse_relative_time() {
-        let now = Utc::now();
+    #[tokio::test]
+    async fn test_api_process_events() {
+        use crate::process_monitor::{ProcessEvent, ProcessEventKind};
 
-        let result = parse_time("-1h", now).unwrap();
-        assert!((now - result).num_minutes() >= 59);
-        assert!((now - result).num_minutes() <= 61);
+        let temp_dir = tempfile::tempdir().unwrap();
+        let exited = (Utc::now() - Duration::minutes(5))
+            .duration_trunc(Duration::seconds(1))
+            .unwrap();
+        {
+            let mut buffer = DuckDBBuffer::new(temp_dir.path()).unwrap();
+            let event = |kind: ProcessEventKind, pid: u32, end_time| ProcessEvent {
+                kind,
+                pid,
+                parent_pid: Some(1),
+                name: "flaky".to_string(),
+                cmdline: Some("flaky --serve".to_string()),
+                uid: Some(0),
+                user: Some("root".to_string()),
+                unit: Some("flaky.service".to_string()),
+                start_time: exited - Duration::seconds(3),
+                end_time,
+            };
+            buffer
+                .add_process_events(
+                    &[event(ProcessEventKind::Start, 500, None)],
+                    exited - Duration::seconds(1),
+                )
+                .unwrap();
+            buffer
+                .add_process_events(
+                    &[
+                        event(ProcessEventKind::Exit, 500, Some(exited)),
+                        event(ProcessEventKind::Start, 501, None),
+                    ],
+                    exited,
+                )
+                .unwrap();
+
+            for (pid, message) in [(500, "panic: config missing"), (501, "starting")] {
+                let mut fields = std::collections::HashMap::new();
+                fields.insert("MESSAGE".to_string(), message.to_string());
+                fields.insert("_PID".to_string(), pid.to_string());
+                fields.insert("PRIORITY".to_string(), "2".to_string());
+                let entry = crate::log_entry::LogEntry::new(exited - Duration::seconds(2), fields);
+                buffer.add_entry(&entry).unwrap();
+            }
+        }
+        let app = create_test_app(temp_dir.path().to_str().unwrap());
+        let get = |uri: &str| {
+            app.clone()
+                .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+        };
 
-        let result = parse_time("-15m", now).unwrap();
-        assert!((now - result).num_minutes() >= 14);
-        assert!((now - result).num_minutes() <= 16);
+        let response = get("/api/processes/events?unit=flaky.service")
+            .await
+            .unwrap();
+        assert_eq!(response.status(), AxumStatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let response: ProcessEventsResponse = serde_json::from_slice(&body).unwrap();
+        let events: Vec<(&str, i64)> = response
+            .events
+            .iter()
+            .map(|e| (e["event"].as_str().unwrap(), e["pid"].as_i64().unwrap()))
+            .collect();
+        assert_eq!(events, [("exit", 500), ("start", 501), ("start", 500)]);
 
-        let result = parse_time("-7d", now).unwrap();
-        assert!((now - result).num_days() >= 6);
-        assert!((now - result).num_days() <= 8);
-    }
+        let exit = &response.events[0];
+        assert_eq!(exit["runtime_secs"], 3);
+        assert_eq!(exit["cmdline"], "flaky --serve");
+        let journal = exit["journal"].as_array().unwrap();
+        assert_eq!(journal.len(), 1);
+        assert_eq!(journal[0]["message"], "panic: config missing");
+        assert!(response.events[1]["journal"].is_null());
 
-    #[test]
-    fn test_parse_now() {
-        let now = Utc::now();
-        let result = parse_time("now", now).unwrap();
-        assert_eq!(result, now);
-    }
+        let response = get("/api/processes/events?event=exit").await.unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let response: ProcessEventsResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(response.events.len(), 1);
 
-    #[test]
-    fn test_escape_like() {
-        assert_eq!(escape_like("test"), "test");
-        assert_eq!(escape_like("test%value"), "test\\%value");
-        assert_eq!(escape_like("test_value"), "test\\_value");
-        assert_eq!(escape_like("test\\value"), "test\\\\value");
+        let response = get("/api/processes/events?event=crash").await.unwrap();
+        assert_eq!(response.status(), AxumStatusCode::BAD_REQUEST);
     }
 
-    #[test]
-    fn test_html_escape() {
-        assert_eq!(html_escape("<script>"), "&lt;script&gt;");
-        assert_eq!(html_escape("a & b"), "a &amp; b");
-        assert_eq!(html_escape("\"quoted\""), "&quot;quoted&quot;");
-    }
+    #[tokio::test]
+    async fn test_query_timeout_responds_with_json() {
+        let error = ApiError::Timeout(QueryTimeoutResponse {
+            error: "Query exceeded 10 ms; narrow the time range or filters".to_string(),
+            stage: "search",
+            timeout_ms: 10,
+            partial: serde_json::json!({ "total": 5 }),
+        });
+        assert_eq!(
+            error.to_string(),
+            "Query exceeded 10 ms; narrow the time range or filters"
+        );
 
-    #[test]
-    fn test_url_encode() {
-        assert_eq!(url_encode("hello"), "hello");
-        assert_eq!(url_encode("hello world"), "hello+world");
-        assert_eq!(url_encode("a=b&c=d"), "a%3Db%26c%3Dd");
+        let response = error.into_response();
+        assert_eq!(response.status(), AxumStatusCode::GATEWAY_TIMEOUT);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["stage"], "search");
+        assert_eq!(body["partial"]["total"], 5);
     }
 
-    #[test]
-    fn test_priority_label() {
-        assert_eq!(priority_label(0), "Emergency");
-        assert_eq!(priority_label(3), "Error");
-        assert_eq!(priority_label(6), "Info");
-        assert_eq!(priority_label(7), "Debug");
-    }
+    #[tokio::test]
+    async fn test_metrics_reports_query_latency() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let app = create_test_app(temp_dir.path().to_str().unwrap());
+        let get = |uri: &str| {
+            app.clone()
+                .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+        };
 
-    // API Tests
+        let response = get("/api/search?q=anything").await.unwrap();
+        assert_eq!(response.status(), AxumStatusCode::OK);
+
+        let response = get("/metrics").await.unwrap();
+        assert_eq!(response.status(), AxumStatusCode::OK);
+        assert!(
+            response.headers()[header::CONTENT_TYPE]
+                .to_str()
+                .unwrap()
+                .starts_with("text/plain")
+        );
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let text = String::from_utf8(body.to_vec()).unwrap();
+        assert!(text.contains("livedata_entries_ingested_total 0"));
+        assert!(text.contains("livedata_query_duration_seconds_count{stage=\"search\"}"));
+        assert!(text.contains("livedata_database_size_bytes "));
+    }
 
     #[tokio::test]
-    async fn test_health_endpoint() {
+    async fn test_api_debug_queries_lists_search_statements() {
         let temp_dir = tempfile::tempdir().unwrap();
+        {
+            let mut buffer = DuckDBBuffer::new(temp_dir.path()).unwrap();
+            let mut fields = std::collections::HashMap::new();
+            fields.insert("MESSAGE".to_string(), "debug me".to_string());
+            let entry = crate::log_entry::LogEntry::new(Utc::now() - Duration::minutes(1), fields);
+            buffer.add_entry(&entry).unwrap();
+        }
         let app = create_test_app(temp_dir.path().to_str().unwrap());
+        let get = |uri: &str| {
+            app.clone()
+                .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+        };
+        let before = sql_trace::logged_queries(0).last().map_or(0, |r| r.id);
 
-        let response = app
-            .oneshot(
-                Request::builder()
-                    .uri("/health")
-                    .body(Body::empty())
-                    .unwrap(),
-            )
+        let response = get("/api/search?q=debug").await.unwrap();
+        assert_eq!(response.status(), AxumStatusCode::OK);
+
+        let response = get(&format!("/api/debug/queries?after={}&sort=slowest", before))
             .await
             .unwrap();
-
         assert_eq!(response.status(), AxumStatusCode::OK);
-
         let body = response.into_body().collect().await.unwrap().to_bytes();
-        let health: HealthResponse = serde_json::from_slice(&body).unwrap();
-        assert_eq!(health.status, "ok");
+        let debug: DebugQueriesResponse = serde_json::from_slice(&body).unwrap();
+        assert!(debug.queries.iter().all(|r| r.id > before));
+        assert!(
+            debug
+                .queries
+                .windows(2)
+                .all(|pair| pair[0].duration_ms >= pair[1].duration_ms)
+        );
+        assert!(
+            debug
+                .queries
+                .iter()
+                .any(|r| r.sql.contains("ILIKE") && r.rows == 1)
+        );
+
+        let response = get("/api/debug/queries?sort=fastest").await.unwrap();
+        assert_eq!(response.status(), AxumStatusCode::BAD_REQUEST);
     }
 
     #[tokio::test]
-    async fn test_api_search_empty_results() {
+    async fn test_grafana_datasource() {
         let temp_dir = tempfile::tempdir().unwrap();
+        let minute = (Utc::now() - Duration::minutes(30))
+            .duration_trunc(Duration::minutes(1))
+            .unwrap();
+        {
+            let mut buffer = DuckDBBuffer::new(temp_dir.path()).unwrap();
+            for (offset, priority) in [(0, "3"), (10, "6"), (120, "2")] {
+                let mut fields = std::collections::HashMap::new();
+                fields.insert("MESSAGE".to_string(), "event".to_string());
+                fields.insert("PRIORITY".to_string(), priority.to_string());
+                let entry =
+                    crate::log_entry::LogEntry::new(minute + Duration::seconds(offset), fields);
+                buffer.add_entry(&entry).unwrap();
+            }
+            let process = crate::process_monitor::ProcessInfo {
+                pid: 7,
+                name: "postgres".to_string(),
+                cpu_percent: 12.0,
+                memory_bytes: 2048,
+                user_id: None,
+                runtime_secs: 1,
+                cmd: Vec::new(),
+                virtual_memory_bytes: 0,
+                status: "Run".to_string(),
+                parent_pid: None,
+                read_bytes_per_sec: 0.0,
+                write_bytes_per_sec: 0.0,
+                num_threads: None,
+                open_fd_count: None,
+                unit: None,
+                container_id: None,
+            };
+            buffer
+                .add_process_metrics(vec![process], Utc::now() - Duration::minutes(5))
+                .unwrap();
+        }
         let app = create_test_app(temp_dir.path().to_str().unwrap());
-
-        let response = app
-            .oneshot(
+        let post = |uri: &str, body: serde_json::Value| {
+            app.clone().oneshot(
                 Request::builder()
-                    .uri("/api/search?start=-1h&end=now")
-                    .body(Body::empty())
+                    .method("POST")
+                    .uri(uri)
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(body.to_string()))
                     .unwrap(),
             )
+        };
+
+        let response = post("/grafana/search", serde_json::json!({ "target": "post" }))
             .await
             .unwrap();
+        assert_eq!(response.status(), AxumStatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let targets: Vec<String> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            targets,
+            vec!["process.cpu:postgres", "process.mem:postgres"]
+        );
+
+        let request = serde_json::json!({
+            "range": {
+                "from": minute.to_rfc3339(),
+                "to": (minute + Duration::minutes(3)).to_rfc3339(),
+            },
+            "intervalMs": 60_000,
+            "targets": [
+                { "target": "logs.errors", "refId": "A" },
+                { "target": "logs.count", "refId": "B", "type": "table" },
+                { "target": "process.cpu:postgres", "refId": "C" },
+                { "target": "logs.count", "refId": "D", "hide": true },
+            ],
+        });
+        let response = post("/grafana/query", request).await.unwrap();
+        assert_eq!(response.status(), AxumStatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let results: Vec<QueryResult> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(results.len(), 3);
+        let start_ms = minute.timestamp_millis();
+        assert_eq!(
+            results[0],
+            QueryResult::Series {
+                target: "logs.errors".to_string(),
+                datapoints: vec![
+                    (1.0, start_ms),
+                    (0.0, start_ms + 60_000),
+                    (1.0, start_ms + 120_000)
+                ],
+            }
+        );
+        let QueryResult::Table { rows, .. } = &results[1] else {
+            panic!("expected a table, got {:?}", results[1]);
+        };
+        assert_eq!(rows[0], (start_ms, 2.0));
+        // The process sample falls outside the requested range
+        assert_eq!(
+            results[2],
+            QueryResult::Series {
+                target: "process.cpu:postgres".to_string(),
+                datapoints: Vec::new(),
+            }
+        );
+
+        let bad = serde_json::json!({
+            "range": { "from": minute.to_rfc3339(), "to": minute.to_rfc3339() },
+            "targets": [{ "target": "disk.io" }],
+        });
+        let response = post("/grafana/query", bad).await.unwrap();
+        assert_eq!(response.status(), AxumStatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_loki_query_range() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let now = Utc::now();
+        {
+            let mut buffer = DuckDBBuffer::new(temp_dir.path()).unwrap();
+            for (offset, unit, message) in [
+                (30, "nginx.service", "GET / 200"),
+                (20, "nginx.service", "upstream error"),
+                (10, "cron.service", "job error"),
+                (5, "nginx.service", "debug error"),
+            ] {
+                let mut fields = std::collections::HashMap::new();
+                fields.insert("MESSAGE".to_string(), message.to_string());
+                fields.insert("_SYSTEMD_UNIT".to_string(), unit.to_string());
+                fields.insert("_HOSTNAME".to_string(), "web-1".to_string());
+                let entry =
+                    crate::log_entry::LogEntry::new(now - Duration::minutes(offset), fields);
+                buffer.add_entry(&entry).unwrap();
+            }
+        }
+        let app = create_test_app(temp_dir.path().to_str().unwrap());
+        let get = |uri: String| {
+            app.clone()
+                .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+        };
 
+        let query = r#"{unit=~"nginx.*", host="web-1"} |= "error" != "debug""#;
+        let response = get(format!(
+            "/loki/api/v1/query_range?query={}&direction=forward",
+            url_encode(query)
+        ))
+        .await
+        .unwrap();
         assert_eq!(response.status(), AxumStatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let response: LokiResponse<StreamsData> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(response.status, "success");
+        assert_eq!(response.data.result_type, "streams");
+        assert_eq!(response.data.result.len(), 1);
+        let stream = &response.data.result[0];
+        assert_eq!(stream.stream["unit"], "nginx.service");
+        assert_eq!(stream.stream["host"], "web-1");
+        assert_eq!(stream.stream["source"], LOCAL_SOURCE);
+        assert_eq!(stream.values.len(), 1);
+        assert_eq!(stream.values[0].1, "upstream error");
+        let nanos: i64 = stream.values[0].0.parse().unwrap();
+        assert_eq!(
+            nanos / 1_000_000_000,
+            (now - Duration::minutes(20)).timestamp()
+        );
 
+        // A start given in nanoseconds leaves out the older nginx entries
+        let start = (now - Duration::minutes(15)).timestamp_nanos_opt().unwrap();
+        let response = get(format!(
+            "/loki/api/v1/query_range?query={}&start={}",
+            url_encode(r#"{host="web-1"} |~ "err(or)?""#),
+            start
+        ))
+        .await
+        .unwrap();
         let body = response.into_body().collect().await.unwrap().to_bytes();
-        let search_response: SearchResponse = serde_json::from_slice(&body).unwrap();
-        assert_eq!(search_response.total, 0);
-        assert!(search_response.results.is_empty());
+        let response: LokiResponse<StreamsData> = serde_json::from_slice(&body).unwrap();
+        let lines: Vec<&str> = response
+            .data
+            .result
+            .iter()
+            .flat_map(|stream| stream.values.iter().map(|(_, line)| line.as_str()))
+            .collect();
+        assert_eq!(lines, vec!["debug error", "job error"]);
+
+        let response = get(format!(
+            "/loki/api/v1/query_range?query={}",
+            url_encode(r#"sum(count_over_time({unit="a"}[5m]))"#)
+        ))
+        .await
+        .unwrap();
+        assert_eq!(response.status(), AxumStatusCode::BAD_REQUEST);
+
+        let response = get("/loki/api/v1/label/unit/values".to_string())
+            .await
+            .unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let response: LokiResponse<Vec<String>> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(response.data, vec!["cron.service", "nginx.service"]);
     }
 
     #[tokio::test]
-    async fn test_api_search_with_query_param() {
+    async fn test_api_units_summarises_each_unit() {
         let temp_dir = tempfile::tempdir().unwrap();
+        {
+            let mut buffer = DuckDBBuffer::new(temp_dir.path()).unwrap();
+            for (i, (unit, priority)) in [
+                ("a.service", "3"),
+                ("a.service", "4"),
+                ("a.service", "6"),
+                ("b.service", "2"),
+            ]
+            .into_iter()
+            .enumerate()
+            {
+                let mut fields = std::collections::HashMap::new();
+                fields.insert("MESSAGE".to_string(), "12345".to_string());
+                fields.insert("_SYSTEMD_UNIT".to_string(), unit.to_string());
+                fields.insert("PRIORITY".to_string(), priority.to_string());
+                let entry = crate::log_entry::LogEntry::new(
+                    Utc::now() - Duration::minutes(10) + Duration::seconds(i as i64),
+                    fields,
+                );
+                buffer.add_entry(&entry).unwrap();
+            }
+        }
         let app = create_test_app(temp_dir.path().to_str().unwrap());
 
         let response = app
             .oneshot(
                 Request::builder()
-                    .uri("/api/search?q=error&start=-1h&end=now&limit=10")
+                    .uri("/api/units")
                     .body(Body::empty())
                     .unwrap(),
             )
             .await
             .unwrap();
-
         assert_eq!(response.status(), AxumStatusCode::OK);
-
         let body = response.into_body().collect().await.unwrap().to_bytes();
-        let search_response: SearchResponse = serde_json::from_slice(&body).unwrap();
-        // Empty dir should have no results
-        assert_eq!(search_response.total, 0);
+        let units: UnitsResponse = serde_json::from_slice(&body).unwrap();
+        let summary: Vec<(&str, i64, i64, i64, i64)> = units
+            .units
+            .iter()
+            .map(|u| {
+                (
+                    u.unit.as_str(),
+                    u.entries,
+                    u.errors,
+                    u.warnings,
+                    u.message_bytes,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![("a.service", 3, 1, 1, 15), ("b.service", 1, 1, 0, 5)]
+        );
+        assert!(units.units.iter().all(|u| u.last_timestamp.is_some()));
     }
 
     #[tokio::test]
-    async fn test_api_filters_endpoint() {
+    async fn test_api_hosts_lists_each_host() {
         let temp_dir = tempfile::tempdir().unwrap();
+        {
+            let mut buffer = DuckDBBuffer::new(temp_dir.path()).unwrap();
+            for (i, (source, hostname)) in [
+                (LOCAL_SOURCE, "alpha"),
+                (LOCAL_SOURCE, "alpha"),
+                ("syslog", "beta"),
+            ]
+            .into_iter()
+            .enumerate()
+            {
+                let mut fields = std::collections::HashMap::new();
+                fields.insert("MESSAGE".to_string(), format!("line {}", i));
+                fields.insert("_HOSTNAME".to_string(), hostname.to_string());
+                let mut entry = crate::log_entry::LogEntry::new(
+                    Utc::now() - Duration::minutes(10) + Duration::seconds(i as i64),
+                    fields,
+                );
+                entry.source = source.to_string();
+                buffer.add_entry(&entry).unwrap();
+            }
+        }
         let app = create_test_app(temp_dir.path().to_str().unwrap());
 
         let response = app
             .oneshot(
                 Request::builder()
-                    .uri("/api/filters")
+                    .uri("/api/hosts?start=-1h")
                     .body(Body::empty())
                     .unwrap(),
             )
             .await
             .unwrap();
+        assert_eq!(response.status(), AxumStatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let hosts: HostsResponse = serde_json::from_slice(&body).unwrap();
+        let summary: Vec<(&str, &str, i64)> = hosts
+            .hosts
+            .iter()
+            .map(|h| (h.source.as_str(), h.hostname.as_str(), h.entries))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![(LOCAL_SOURCE, "alpha", 2), ("syslog", "beta", 1)]
+        );
+        let alpha = &hosts.hosts[0];
+        assert!(alpha.first_seen < alpha.last_seen);
+        assert!((alpha.entries_per_minute - 2.0 / 60.0).abs() < 1e-3);
+    }
+
+    #[tokio::test]
+    async fn test_api_stats_groups_by_columns() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        {
+            let mut buffer = DuckDBBuffer::new(temp_dir.path()).unwrap();
+            for (i, (unit, priority)) in [
+                ("a.service", "3"),
+                ("a.service", "3"),
+                ("a.service", "6"),
+                ("b.service", "6"),
+            ]
+            .into_iter()
+            .enumerate()
+            {
+                let mut fields = std::collections::HashMap::new();
+                fields.insert("MESSAGE".to_string(), format!("line {}", i));
+                fields.insert("_SYSTEMD_UNIT".to_string(), unit.to_string());
+                fields.insert("PRIORITY".to_string(), priority.to_string());
+                let entry = crate::log_entry::LogEntry::new(
+                    Utc::now() - Duration::minutes(10) + Duration::seconds(i as i64),
+                    fields,
+                );
+                buffer.add_entry(&entry).unwrap();
+            }
+        }
+        let app = create_test_app(temp_dir.path().to_str().unwrap());
+        let get = |uri: &str| {
+            app.clone()
+                .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+        };
 
+        let response = get("/api/stats?group_by=unit").await.unwrap();
         assert_eq!(response.status(), AxumStatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let stats: StatsResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(stats.group_by, vec!["unit"]);
+        let counts: Vec<(&str, i64)> = stats
+            .groups
+            .iter()
+            .map(|g| (g["unit"].as_str().unwrap(), g["count"].as_i64().unwrap()))
+            .collect();
+        assert_eq!(counts, vec![("a.service", 3), ("b.service", 1)]);
+        assert!(
+            stats.groups[0]["first_timestamp"].as_str().unwrap()
+                < stats.groups[0]["last_timestamp"].as_str().unwrap()
+        );
 
+        // Errors by unit
+        let response = get("/api/stats?group_by=unit,priority&priority=3&start=-1h")
+            .await
+            .unwrap();
         let body = response.into_body().collect().await.unwrap().to_bytes();
-        let filters: FilterValues = serde_json::from_slice(&body).unwrap();
-        // Empty dir should have empty filter lists
-        assert!(filters.hostnames.is_empty());
-        assert!(filters.units.is_empty());
+        let stats: StatsResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(stats.group_by, vec!["unit", "priority"]);
+        assert_eq!(stats.groups.len(), 1);
+        assert_eq!(stats.groups[0]["unit"], "a.service");
+        assert_eq!(stats.groups[0]["count"], 2);
+
+        for uri in ["/api/stats", "/api/stats?group_by=unit,nope"] {
+            assert_eq!(
+                get(uri).await.unwrap().status(),
+                AxumStatusCode::BAD_REQUEST,
+                "{}",
+                uri
+            );
+        }
     }
 
     #[tokio::test]
-    async fn test_search_ui_returns_html() {
+    async fn test_api_search_stream_sends_events() {
         let temp_dir = tempfile::tempdir().unwrap();
+        {
+            let mut buffer = DuckDBBuffer::new(temp_dir.path()).unwrap();
+            for i in 0..3 {
+                let mut fields = std::collections::HashMap::new();
+                fields.insert("MESSAGE".to_string(), format!("line {}", i));
+                let entry = crate::log_entry::LogEntry::new(
+                    Utc::now() - Duration::minutes(1) + Duration::milliseconds(i),
+                    fields,
+                );
+                buffer.add_entry(&entry).unwrap();
+            }
+        }
         let app = create_test_app(temp_dir.path().to_str().unwrap());
 
+        let request = |uri: &str| {
+            Request::builder()
+                .uri(uri)
+                .header("accept", "text/event-stream")
+                .body(Body::empty())
+                .unwrap()
+        };
         let response = app
-            .oneshot(Request::builder().uri("/").body(Body::empty()).unwrap())
+            .clone()
+            .oneshot(request("/api/search/stream?columns=message&interval=60"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), AxumStatusCode::OK);
+        assert_eq!(
+            response.headers().get("content-type").unwrap(),
+            "text/event-stream"
+        );
+        let frame = response.into_body().frame().await.unwrap().unwrap();
+        let text = String::from_utf8(frame.into_data().unwrap().to_vec()).unwrap();
+        let mut lines = text.lines();
+        assert_eq!(lines.next(), Some("event: results"));
+        let data: serde_json::Value =
+            serde_json::from_str(lines.next().unwrap().strip_prefix("data: ").unwrap()).unwrap();
+        assert_eq!(data["columns"], serde_json::json!(["message"]));
+        let messages: Vec<&str> = data["results"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|row| row["message"].as_str().unwrap())
+            .collect();
+        assert_eq!(messages, vec!["line 2", "line 1", "line 0"]);
+        // Page keys stay internal
+        assert!(data["results"][0].get(PAGE_TS_KEY).is_none());
+
+        let response = app
+            .oneshot(request("/api/search/stream?sort=priority"))
             .await
             .unwrap();
+        assert_eq!(response.status(), AxumStatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_api_export_formats() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        {
+            let mut buffer = DuckDBBuffer::new(temp_dir.path()).unwrap();
+            for (i, message) in ["plain", "with, comma", "with \"quotes\""]
+                .iter()
+                .enumerate()
+            {
+                let mut fields = std::collections::HashMap::new();
+                fields.insert("MESSAGE".to_string(), message.to_string());
+                fields.insert("_SYSTEMD_UNIT".to_string(), "app.service".to_string());
+                let entry = crate::log_entry::LogEntry::new(
+                    Utc::now() - Duration::minutes(1) + Duration::milliseconds(i as i64),
+                    fields,
+                );
+                buffer.add_entry(&entry).unwrap();
+            }
+        }
+        let app = create_test_app(temp_dir.path().to_str().unwrap());
+        let export = |query: &str| {
+            app.clone().oneshot(
+                Request::builder()
+                    .uri(format!(
+                        "/api/export?start=-1h&end=now&sort_dir=asc&columns=_systemd_unit,message&{}",
+                        query
+                    ))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+        };
 
+        let response = export("format=csv").await.unwrap();
         assert_eq!(response.status(), AxumStatusCode::OK);
+        assert_eq!(response.headers().get("content-type").unwrap(), "text/csv");
+        let disposition = response.headers().get("content-disposition").unwrap();
+        let disposition = disposition.to_str().unwrap();
+        assert!(disposition.starts_with("attachment; filename=\"livedata-export-"));
+        assert!(disposition.ends_with(".csv\""));
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(
+            std::str::from_utf8(&body).unwrap(),
+            "unit,message\r\n\
+             app.service,plain\r\n\
+             app.service,\"with, comma\"\r\n\
+             app.service,\"with \"\"quotes\"\"\"\r\n"
+        );
 
+        // The row cap applies to every format
+        let response = export("limit=2").await.unwrap();
+        assert_eq!(
+            response.headers().get("content-type").unwrap(),
+            "application/x-ndjson"
+        );
         let body = response.into_body().collect().await.unwrap().to_bytes();
-        let html = String::from_utf8(body.to_vec()).unwrap();
-        assert!(html.contains("<!DOCTYPE html>"));
-        assert!(html.contains("Livedata"));
+        let lines: Vec<serde_json::Value> = std::str::from_utf8(&body)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1]["message"], "with, comma");
+
+        let response = export("format=parquet&unit=app.service").await.unwrap();
+        assert_eq!(response.status(), AxumStatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert!(body.starts_with(b"PAR1") && body.ends_with(b"PAR1"));
+        let path = temp_dir.path().join("export.parquet");
+        std::fs::write(&path, &body).unwrap();
+        let conn = duckdb::Connection::open_in_memory().unwrap();
+        let rows: i64 = conn
+            .query_row(
+                &format!(
+                    "SELECT COUNT(*) FROM read_parquet('{}') WHERE unit = 'app.service'",
+                    path.display()
+                ),
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(rows, 3);
+
+        let response = export("format=xml").await.unwrap();
+        assert_eq!(response.status(), AxumStatusCode::BAD_REQUEST);
     }
 
     #[tokio::test]
-    async fn test_api_search_with_priority_filter() {
+    async fn test_api_export_job_writes_downloadable_file() {
         let temp_dir = tempfile::tempdir().unwrap();
+        {
+            let mut buffer = DuckDBBuffer::new(temp_dir.path()).unwrap();
+            for i in 0..(STREAM_CHUNK_ROWS + 5) {
+                let mut fields = std::collections::HashMap::new();
+                fields.insert("MESSAGE".to_string(), format!("line {}", i));
+                let entry = crate::log_entry::LogEntry::new(
+                    Utc::now() - Duration::minutes(1) + Duration::milliseconds(i as i64),
+                    fields,
+                );
+                buffer.add_entry(&entry).unwrap();
+            }
+        }
         let app = create_test_app(temp_dir.path().to_str().unwrap());
-
-        let response = app
-            .oneshot(
+        let request = |method: &str, uri: String| {
+            app.clone().oneshot(
                 Request::builder()
-                    .uri("/api/search?start=-1h&end=now&priority=3")
+                    .method(method)
+                    .uri(uri)
                     .body(Body::empty())
                     .unwrap(),
             )
+        };
+
+        let response = request(
+            "POST",
+            "/api/export/jobs?start=-1h&end=now&sort_dir=asc&columns=message&format=ndjson".into(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), AxumStatusCode::ACCEPTED);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let job: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let id = job["id"].as_str().unwrap().to_string();
+        assert_eq!(job["state"], "running");
+
+        let job = loop {
+            let response = request("GET", format!("/api/export/jobs/{}", id))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), AxumStatusCode::OK);
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            let job: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            if job["state"] != "running" {
+                break job;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        };
+        assert_eq!(job["state"], "completed");
+        assert_eq!(job["total_rows"], STREAM_CHUNK_ROWS + 5);
+        assert_eq!(job["rows_written"], STREAM_CHUNK_ROWS + 5);
+        assert_eq!(
+            temp_dir.path().join("exports").read_dir().unwrap().count(),
+            1
+        );
+
+        let response = request("GET", format!("/api/export/jobs/{}/download", id))
             .await
             .unwrap();
-
         assert_eq!(response.status(), AxumStatusCode::OK);
+        assert_eq!(
+            response.headers().get("content-type").unwrap(),
+            "application/x-ndjson"
+        );
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let lines: Vec<serde_json::Value> = std::str::from_utf8(&body)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), STREAM_CHUNK_ROWS + 5);
+        assert_eq!(lines[0]["message"], "line 0");
+
+        let response = request("GET", "/api/export/jobs/missing".into())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), AxumStatusCode::NOT_FOUND);
     }
 
     #[tokio::test]
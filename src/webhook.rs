@@ -0,0 +1,276 @@
+//! Webhook notification channel: POSTs each alert as JSON to a URL from a background
+//! thread, retrying failed deliveries with exponential backoff.
+
+use crate::notifications::{AlertEvent, NotificationChannel};
+use anyhow::{Result, bail};
+use log::{error, warn};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// Longest wait between two delivery or batch attempts
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A webhook alerts are POSTed to (`[[webhooks]]` table)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WebhookSettings {
+    pub url: String,
+
+    /// Extra request headers, e.g. an `Authorization` token
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+
+    /// Attempts after the first before an alert is given up on
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+
+    /// Wait before the first retry, doubling with each further one
+    #[serde(default = "default_retry_backoff_ms")]
+    pub retry_backoff_ms: u64,
+}
+
+impl WebhookSettings {
+    /// Settings for `url` with the default retries
+    pub fn for_url(url: String) -> Self {
+        Self {
+            url,
+            headers: BTreeMap::new(),
+            max_retries: default_max_retries(),
+            retry_backoff_ms: default_retry_backoff_ms(),
+        }
+    }
+}
+
+fn default_max_retries() -> u32 {
+    3
+}
+
+fn default_retry_backoff_ms() -> u64 {
+    1_000
+}
+
+/// Wait before retry number `attempt` (0 for the first retry), shared by the webhook
+/// channels and the log outputs
+pub(crate) fn retry_delay(backoff_ms: u64, attempt: u32) -> Duration {
+    Duration::from_millis(backoff_ms.saturating_mul(1 << attempt.min(16))).min(MAX_RETRY_DELAY)
+}
+
+/// A JSON document to POST, with the rule it is about for logging
+pub(crate) struct Delivery {
+    pub rule: String,
+    pub body: serde_json::Value,
+}
+
+/// Background thread POSTing deliveries to one URL in order, so senders never wait
+/// on the network; it ends once the queue is dropped
+pub(crate) struct Poster {
+    /// Name for logs; only the host is included, as the path often holds a token
+    pub name: String,
+    queue: mpsc::Sender<Delivery>,
+}
+
+impl Poster {
+    pub fn start(kind: &str, settings: WebhookSettings) -> Result<Self> {
+        let url = Url::parse(&settings.url)?;
+        let name = format!("{} {}", kind, url.host_str().unwrap_or_default());
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()?;
+        let (queue, deliveries) = mpsc::channel::<Delivery>();
+        let thread_name = name.clone();
+        std::thread::spawn(move || {
+            let rt = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .expect("Failed to create tokio runtime");
+            for delivery in deliveries {
+                if let Err(e) = rt.block_on(deliver(&client, &url, &settings, &delivery)) {
+                    error!(
+                        "Giving up on sending alert {} to {}: {}",
+                        delivery.rule, thread_name, e
+                    );
+                }
+            }
+        });
+        Ok(Self { name, queue })
+    }
+
+    pub fn post(&self, delivery: Delivery) -> Result<()> {
+        self.queue.send(delivery)?;
+        Ok(())
+    }
+}
+
+/// Posts each alert as it is serialized
+pub struct WebhookChannel {
+    poster: Poster,
+}
+
+impl WebhookChannel {
+    pub fn start(settings: WebhookSettings) -> Result<Self> {
+        Ok(Self {
+            poster: Poster::start("webhook", settings)?,
+        })
+    }
+}
+
+impl NotificationChannel for WebhookChannel {
+    fn name(&self) -> &str {
+        &self.poster.name
+    }
+
+    fn send(&self, alert: &AlertEvent) -> Result<()> {
+        self.poster.post(Delivery {
+            rule: alert.rule.clone(),
+            body: serde_json::to_value(alert)?,
+        })
+    }
+}
+
+/// POST `delivery`, retrying on connection errors, 429s and server errors
+async fn deliver(
+    client: &reqwest::Client,
+    url: &Url,
+    settings: &WebhookSettings,
+    delivery: &Delivery,
+) -> Result<()> {
+    let mut attempt = 0;
+    loop {
+        let mut request = client.post(url.clone()).json(&delivery.body);
+        for (name, value) in &settings.headers {
+            request = request.header(name, value);
+        }
+        let error = match request.send().await {
+            Ok(response) if response.status().is_success() => return Ok(()),
+            Ok(response)
+                if !(response.status().is_server_error()
+                    || response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS) =>
+            {
+                bail!("{} (not retried)", response.status())
+            }
+            Ok(response) => response.status().to_string(),
+            // The URL can hold the webhook's secret or a bot token
+            Err(e) => e.without_url().to_string(),
+        };
+        if attempt >= settings.max_retries {
+            bail!("{} after {} attempts", error, attempt + 1);
+        }
+        let delay = retry_delay(settings.retry_backoff_ms, attempt);
+        warn!(
+            "Alert {} delivery failed ({}), retrying in {:?}",
+            delivery.rule, error, delay
+        );
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notifications::AlertState;
+    use axum::{Json, Router, http::StatusCode, routing::post};
+    use chrono::Utc;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_retry_delay_doubles_up_to_a_cap() {
+        assert_eq!(retry_delay(500, 0), Duration::from_millis(500));
+        assert_eq!(retry_delay(500, 2), Duration::from_secs(2));
+        assert_eq!(retry_delay(500, 40), MAX_RETRY_DELAY);
+    }
+
+    #[tokio::test]
+    async fn test_delivery_errors_leave_out_the_url() {
+        // Nothing listens on the port once the listener is dropped
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let settings = WebhookSettings {
+            url: format!("http://{}/bot123:SECRET/sendMessage", addr),
+            headers: BTreeMap::new(),
+            max_retries: 0,
+            retry_backoff_ms: 10,
+        };
+        let error = deliver(
+            &reqwest::Client::new(),
+            &Url::parse(&settings.url).unwrap(),
+            &settings,
+            &Delivery {
+                rule: "disk".to_string(),
+                body: serde_json::json!({}),
+            },
+        )
+        .await
+        .unwrap_err();
+        assert!(!format!("{:#}", error).contains("SECRET"), "{:#}", error);
+    }
+
+    #[tokio::test]
+    async fn test_webhook_retries_until_delivered() {
+        let received: Arc<Mutex<Vec<serde_json::Value>>> = Arc::default();
+        let app = {
+            let received = received.clone();
+            Router::new().route(
+                "/hook",
+                post(move |Json(body): Json<serde_json::Value>| {
+                    let received = received.clone();
+                    async move {
+                        let mut received = received.lock().unwrap();
+                        received.push(body);
+                        // Fail the first attempt
+                        if received.len() == 1 {
+                            StatusCode::SERVICE_UNAVAILABLE
+                        } else {
+                            StatusCode::OK
+                        }
+                    }
+                }),
+            )
+        };
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let channel = WebhookChannel::start(WebhookSettings {
+            url: format!("http://{}/hook", addr),
+            headers: BTreeMap::new(),
+            max_retries: 2,
+            retry_backoff_ms: 10,
+        })
+        .unwrap();
+        assert_eq!(channel.name(), "webhook 127.0.0.1");
+        channel
+            .send(&AlertEvent {
+                timestamp: Utc::now(),
+                rule: "nginx errors".to_string(),
+                source: "log".to_string(),
+                state: AlertState::Firing,
+                value: 51.0,
+                threshold: 50.0,
+                message: "51 entries matched in the last 5m (threshold 50)".to_string(),
+                samples: vec!["upstream timed out".to_string()],
+                link: Some("http://localhost:3000/?q=unit%3Anginx".to_string()),
+                tags: vec!["web".to_string()],
+                details: BTreeMap::new(),
+            })
+            .unwrap();
+
+        for _ in 0..200 {
+            if received.lock().unwrap().len() >= 2 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 2);
+        assert_eq!(received[1]["rule"], "nginx errors");
+        assert_eq!(received[1]["state"], "firing");
+        assert_eq!(received[1]["value"], 51.0);
+        assert_eq!(received[1]["samples"][0], "upstream timed out");
+    }
+}
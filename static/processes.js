@@ -8,6 +8,11 @@ const DEBOUNCE_MS = 300;
 let autoRefreshInterval;
 let Tabulator;
 
+// Set when opened from a unit in the log search: only that unit's processes, with
+// their CPU and memory over the log search's time range
+const pageParams = new URLSearchParams(window.location.search);
+const unitFilter = pageParams.get('unit');
+
 async function loadTabulator() {
     const sources = [
         "https://unpkg.com/tabulator-tables@6.3.1/dist/js/tabulator_esm.min.js",
@@ -58,6 +63,7 @@ document.addEventListener('DOMContentLoaded', async () => {
         initializeTable();
         initializeControls();
         fetchProcesses();
+        fetchUnitHistory();
         console.log('[ProcessMonitor] Initialization complete');
     } catch (error) {
         console.error('[ProcessMonitor] Initialization failed:', error);
@@ -157,6 +163,13 @@ function initializeTable() {
                 field: "runtime_display", 
                 sorter: "string",
                 width: 150
+            },
+            {
+                title: "Unit",
+                field: "unit",
+                sorter: "string",
+                minWidth: 150,
+                formatter: (cell) => cell.getValue() || "-"
             }
         ]
     });
@@ -214,7 +227,10 @@ async function fetchProcesses() {
     console.log('[ProcessMonitor] Fetching process data...');
     
     try {
-        const response = await fetch('/api/processes');
+        const url = unitFilter
+            ? '/api/processes?unit=' + encodeURIComponent(unitFilter)
+            : '/api/processes';
+        const response = await fetch(url);
         
         if (!response.ok) {
             throw new Error(`HTTP error! status: ${response.status}`);
@@ -255,6 +271,74 @@ async function fetchProcesses() {
     }
 }
 
+/**
+ * Chart the CPU and memory of the unit's processes over the requested window
+ */
+async function fetchUnitHistory() {
+    const container = document.getElementById('unit-history');
+    if (!unitFilter || !container) return;
+
+    const query = new URLSearchParams({
+        unit: unitFilter,
+        start: pageParams.get('start') || '-1h',
+        end: pageParams.get('end') || 'now',
+    });
+    try {
+        const response = await fetch('/api/processes/history?' + query);
+        if (!response.ok) {
+            throw new Error(`HTTP error! status: ${response.status}`);
+        }
+        const data = await response.json();
+        const cpu = data.points.map(p => p.avg_cpu_usage);
+        const mem = data.points.map(p => p.avg_mem_usage);
+
+        container.hidden = false;
+        container.textContent = '';
+        const title = document.createElement('h2');
+        title.textContent = `Processes of ${unitFilter} (${query.get('start')} to ${query.get('end')})`;
+        container.appendChild(title);
+        if (data.points.length === 0) {
+            container.appendChild(document.createTextNode('No process samples in this window.'));
+            return;
+        }
+        const maxCpu = Math.max(...data.points.map(p => p.max_cpu_usage));
+        const maxMem = Math.max(...data.points.map(p => p.max_mem_usage));
+        container.appendChild(sparkline('CPU', cpu, `peak ${maxCpu.toFixed(1)}%`));
+        container.appendChild(sparkline('Memory', mem, `peak ${formatBytes(maxMem)}`));
+    } catch (error) {
+        console.error('[ProcessMonitor] Failed to fetch unit history:', error);
+        showError('Failed to fetch unit history: ' + error.message);
+    }
+}
+
+/**
+ * Labelled SVG line of the values, scaled to their maximum
+ */
+function sparkline(label, values, summary) {
+    const width = 600;
+    const height = 60;
+    const max = Math.max(...values, 1e-9);
+    const step = values.length > 1 ? width / (values.length - 1) : 0;
+    const points = values
+        .map((v, i) => `${(i * step).toFixed(1)},${(height - (v / max) * height).toFixed(1)}`)
+        .join(' ');
+
+    const row = document.createElement('div');
+    row.className = 'sparkline';
+    const caption = document.createElement('span');
+    caption.textContent = `${label} (${summary})`;
+    row.appendChild(caption);
+    const svgNs = 'http://www.w3.org/2000/svg';
+    const svg = document.createElementNS(svgNs, 'svg');
+    svg.setAttribute('viewBox', `0 0 ${width} ${height}`);
+    svg.setAttribute('preserveAspectRatio', 'none');
+    const line = document.createElementNS(svgNs, 'polyline');
+    line.setAttribute('points', points);
+    svg.appendChild(line);
+    row.appendChild(svg);
+    return row;
+}
+
 /**
  * Calculate memory percentage from bytes
  * Assumes 16GB total memory (to be replaced with actual system memory API)